@@ -0,0 +1,170 @@
+// Kernel benchmarks against the std equivalents. Dependency-free: each case
+// runs until it has TARGET of samples and reports the median time per
+// element, so results are comparable across sizes.
+//
+//   cargo bench --bench kernels                 # everything, sizes 10^2..10^6
+//   cargo bench --bench kernels -- sort         # cases whose name contains "sort"
+//   TOVA_BENCH_MAX_EXP=8 cargo bench --bench kernels -- sort/random
+//
+// `cutoff` times the radix sort against the std sort around
+// kernels::RADIX_SORT_MIN; rerun it when touching either path.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use tova_native::kernels;
+
+const TARGET: Duration = Duration::from_millis(200);
+const MAX_SAMPLES: usize = 50;
+
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Shape {
+    Random,
+    Sorted,
+    Reverse,
+    FewUniques,
+    NarrowRange,
+}
+
+const SHAPES: [(Shape, &str); 5] = [
+    (Shape::Random, "random"),
+    (Shape::Sorted, "sorted"),
+    (Shape::Reverse, "reverse"),
+    (Shape::FewUniques, "few-uniques"),
+    (Shape::NarrowRange, "narrow-range"),
+];
+
+fn f64_data(shape: Shape, n: usize) -> Vec<f64> {
+    let mut rng = Rng(n as u64);
+    let mut data: Vec<f64> = match shape {
+        Shape::FewUniques => (0..n).map(|_| (rng.next() % 16) as f64).collect(),
+        Shape::NarrowRange => (0..n).map(|_| 1000.0 + rng.unit()).collect(),
+        _ => (0..n).map(|_| (rng.unit() - 0.5) * 1e9).collect(),
+    };
+    match shape {
+        Shape::Sorted => data.sort_unstable_by(f64::total_cmp),
+        Shape::Reverse => data.sort_unstable_by(|a, b| b.total_cmp(a)),
+        _ => {}
+    }
+    data
+}
+
+fn i64_data(shape: Shape, n: usize) -> Vec<i64> {
+    f64_data(shape, n).into_iter().map(|v| v as i64).collect()
+}
+
+struct Bench {
+    filter: Option<String>,
+    max_exp: u32,
+}
+
+impl Bench {
+    fn from_env() -> Self {
+        // cargo passes `--bench`; anything else is a name filter
+        let filter = std::env::args().skip(1).find(|a| !a.starts_with("--"));
+        let max_exp = std::env::var("TOVA_BENCH_MAX_EXP").ok().and_then(|v| v.parse().ok()).unwrap_or(6);
+        Bench { filter, max_exp }
+    }
+
+    fn sizes(&self) -> Vec<usize> {
+        (2..=self.max_exp).map(|e| 10usize.pow(e)).collect()
+    }
+
+    fn enabled(&self, name: &str) -> bool {
+        self.filter.as_deref().is_none_or(|f| name.contains(f))
+    }
+
+    /// Time `run` on fresh copies of `input`; the copy is not timed.
+    fn run<T: Clone, R>(&self, name: &str, n: usize, input: &T, mut run: impl FnMut(&mut T) -> R) {
+        if !self.enabled(name) {
+            return;
+        }
+        let mut samples = Vec::new();
+        let started = Instant::now();
+        while samples.len() < MAX_SAMPLES && (samples.len() < 3 || started.elapsed() < TARGET) {
+            let mut data = input.clone();
+            let t = Instant::now();
+            black_box(run(black_box(&mut data)));
+            samples.push(t.elapsed());
+        }
+        samples.sort();
+        let median = samples[samples.len() / 2];
+        println!("{:<40} n={:<10} {:>10.2} ns/elem", name, n, median.as_nanos() as f64 / n.max(1) as f64);
+    }
+}
+
+fn bench_sort(b: &Bench) {
+    for n in b.sizes() {
+        for (shape, shape_name) in SHAPES {
+            let floats = f64_data(shape, n);
+            b.run(&format!("sort_f64/{}/kernel", shape_name), n, &floats, |d| kernels::sort_f64(d));
+            b.run(&format!("sort_f64/{}/std", shape_name), n, &floats, |d| d.sort_unstable_by(f64::total_cmp));
+            // <[f64]>::sort_floats is nightly-only; compare against it there by hand
+            let ints = i64_data(shape, n);
+            b.run(&format!("sort_i64/{}/kernel", shape_name), n, &ints, |d| kernels::sort_i64(d));
+            b.run(&format!("sort_i64/{}/std", shape_name), n, &ints, |d| d.sort_unstable());
+        }
+    }
+}
+
+fn bench_cutoff(b: &Bench) {
+    for n in [1_000, 2_000, 5_000, 10_000, 20_000, 50_000, 100_000] {
+        let floats = f64_data(Shape::Random, n);
+        b.run("cutoff/f64/radix", n, &floats, |d| kernels::bench::radix_sort_f64(d));
+        b.run("cutoff/f64/std", n, &floats, |d| d.sort_unstable_by(f64::total_cmp));
+        for bits in [8, 11] {
+            b.run(&format!("cutoff/f64/radix{}", bits), n, &floats, |d| kernels::bench::radix_sort_f64_bits(d, bits));
+        }
+        let ints = i64_data(Shape::Random, n);
+        b.run("cutoff/i64/radix", n, &ints, |d| kernels::bench::radix_sort_i64(d));
+        b.run("cutoff/i64/std", n, &ints, |d| d.sort_unstable());
+    }
+}
+
+fn bench_aggregates(b: &Bench) {
+    for n in b.sizes() {
+        let data = f64_data(Shape::Random, n);
+        b.run("sum_f64/kernel", n, &data, |d| kernels::sum_f64(d));
+        b.run("sum_f64/iter", n, &data, |d| d.iter().sum::<f64>());
+        b.run("sum_f64/parallel", n, &data, |d| kernels::sum_f64_parallel(d, 0));
+        b.run("min_f64/kernel", n, &data, |d| kernels::min_f64(d));
+        b.run("min_f64/iter", n, &data, |d| d.iter().copied().fold(f64::INFINITY, f64::min));
+        b.run("max_f64/kernel", n, &data, |d| kernels::max_f64(d));
+        b.run("max_f64/iter", n, &data, |d| d.iter().copied().fold(f64::NEG_INFINITY, f64::max));
+    }
+}
+
+fn bench_unique(b: &Bench) {
+    for n in b.sizes() {
+        let mut data = i64_data(Shape::FewUniques, n);
+        data.sort_unstable();
+        b.run("unique_sorted_i64/kernel", n, &data, |d| kernels::unique_sorted(d));
+        b.run("unique_sorted_i64/dedup", n, &data, |d| {
+            d.dedup();
+            d.len()
+        });
+    }
+}
+
+fn main() {
+    let b = Bench::from_env();
+    bench_cutoff(&b);
+    bench_sort(&b);
+    bench_aggregates(&b);
+    bench_unique(&b);
+}
@@ -0,0 +1,28 @@
+#![no_main]
+
+// Kernels that consume untrusted bytes: none may panic, read out of bounds
+// or report more output than they wrote.
+
+use libfuzzer_sys::fuzz_target;
+use tova_native::{kernels, NpyInfo};
+
+fuzz_target!(|data: &[u8]| {
+    let mut numbers = [0.0f64; 64];
+    match kernels::parse_json_numbers(data, &mut numbers) {
+        Ok(n) => assert!(n <= data.len()),
+        Err(at) => assert!(at <= data.len()),
+    }
+
+    let mut info = NpyInfo::default();
+    unsafe {
+        if tova_native::tova_npy_parse_header(data.as_ptr(), data.len(), &mut info) == 0 {
+            let mut out = [0.0f64; 256];
+            let n = tova_native::tova_npy_read_f64(data.as_ptr(), data.len(), out.as_mut_ptr(), out.len());
+            assert!(n < 0 || (n as usize <= out.len() && n as u64 == info.element_count));
+        }
+
+        let mut out = vec![0u8; 1 << 16];
+        let n = tova_native::tova_gzip_decompress(data.as_ptr(), data.len(), out.as_mut_ptr(), out.len(), 1 << 20);
+        assert!(n <= 1 << 20);
+    }
+});
@@ -0,0 +1,18 @@
+#![no_main]
+
+// Input bytes are read as native-endian f64 bit patterns, so the fuzzer
+// reaches every NaN payload, subnormal and signed zero directly.
+
+use libfuzzer_sys::fuzz_target;
+use tova_native::kernels;
+
+fuzz_target!(|data: &[u8]| {
+    let mut values: Vec<f64> = data.chunks_exact(8).map(|c| f64::from_ne_bytes(c.try_into().unwrap())).collect();
+    let mut expected = values.clone();
+    expected.sort_unstable_by(f64::total_cmp);
+    kernels::sort_f64(&mut values);
+    assert!(values.iter().zip(&expected).all(|(a, b)| a.to_bits() == b.to_bits()));
+
+    let len = kernels::unique_sorted(&mut values);
+    assert!(values[..len].windows(2).all(|w| w[0] != w[1]));
+});
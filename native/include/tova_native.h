@@ -0,0 +1,1462 @@
+/*
+ * tova_native.h: C interface to the tova_native library.
+ *
+ * Generated from native/src/lib.rs by `cargo run --bin gen-header`; do not
+ * edit. Check `tova_abi_version()` against TOVA_ABI_VERSION before use.
+ * Define TOVA_NATIVE_ZSTD when linking a build with the zstd feature
+ * (`tova_features() & TOVA_FEATURE_ZSTD`) to declare its exports.
+ */
+
+#ifndef TOVA_NATIVE_H
+#define TOVA_NATIVE_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Opaque handles, created and freed by the library */
+
+typedef struct DecompressStream DecompressStream;
+typedef struct GroupAgg GroupAgg;
+typedef struct HashJoin HashJoin;
+typedef struct OrderSet OrderSet;
+typedef struct SortF64Handle SortF64Handle;
+
+/* Numeric Sort — Radix sort for f64 (IEEE 754 trick) */
+
+/**
+ * Sort an array of f64 values in-place, radix sorting large inputs.
+ * Radix sort on floats: reinterpret as u64, flip sign bit for correct ordering.
+ * Time: O(n), Space: O(n). Inputs below the small cutoff use a comparison
+ * sort and inputs past the parallel threshold sort on every thread (see
+ * `tova_sort_set_tuning`).
+ * Orders like `f64::total_cmp`: -0.0 before +0.0, NaNs at the end
+ * (negative-signed NaNs at the front).
+ */
+void tova_sort_f64(double *ptr, size_t len);
+
+/**
+ * Sort an array of f64 values in-place without allocating: an in-place
+ * comparison sort at every size, O(1) auxiliary memory beyond the stack.
+ * Same order as `tova_sort_f64`; slower than it on large inputs.
+ */
+void tova_sort_f64_unstable(double *ptr, size_t len);
+
+/**
+ * Write to `out_idx` the indices that would sort `ptr` as `tova_sort_f64`
+ * does, leaving the values untouched: out_idx[i] is the position of the
+ * i-th smallest value, ready for `tova_gather_f64` or
+ * `tova_apply_permutation_inplace_f64` on every column of the table. Stable,
+ * so tied values keep their input order; O(n) radix sort from the small
+ * cutoff up. Returns 0, TOVA_INDEX_ERR_TOO_LONG past 2^32 values, which u32
+ * indices cannot address, or TOVA_ERR_OVERLAP when `out_idx` overlaps the
+ * values; nothing is written on failure.
+ */
+int32_t tova_argsort_f64(const double *ptr, size_t len, uint32_t *out_idx);
+
+/**
+ * Sort an array of i64 values in-place.
+ */
+void tova_sort_i64(int64_t *ptr, size_t len);
+
+/**
+ * `tova_sort_f64` in descending order: the exact reverse of its output,
+ * so +NaN first, +0.0 before -0.0 and negative-signed NaNs at the end.
+ * Same cutoff, radix and parallel paths.
+ */
+void tova_sort_f64_desc(double *ptr, size_t len);
+
+/**
+ * `tova_sort_i64` in descending order.
+ */
+void tova_sort_i64_desc(int64_t *ptr, size_t len);
+
+/**
+ * Bits of `tova_sort_caps`: the contract each sorter keeps. STABLE means the
+ * output equals a stable sort's; TOTAL_ORDER means NaNs and signed zeros
+ * follow `f64::total_cmp` (-NaN first, -0.0 before +0.0, +NaN last);
+ * NO_ALLOC means no auxiliary buffer at any size.
+ */
+#define TOVA_SORT_F64_STABLE (UINT64_C(1) << 0)
+#define TOVA_SORT_F64_TOTAL_ORDER (UINT64_C(1) << 1)
+#define TOVA_SORT_I64_STABLE (UINT64_C(1) << 2)
+#define TOVA_SORT_F64_UNSTABLE_TOTAL_ORDER (UINT64_C(1) << 3)
+#define TOVA_SORT_F64_UNSTABLE_NO_ALLOC (UINT64_C(1) << 4)
+
+/**
+ * TOVA_SORT_* bits for the sorters in this build. `tova_sort_f64_unstable`
+ * makes no stability promise, so there is no bit for it.
+ */
+uint64_t tova_sort_caps(void);
+
+#define TOVA_SORT_ERR_RADIX_BITS (-1)
+#define TOVA_SORT_ERR_PARALLEL_THRESHOLD (-2)
+#define TOVA_SORT_ERR_HANDLE (-3)
+
+/**
+ * Tune `tova_sort_f64` and `tova_sort_i64` for this process.
+ * `small_cutoff`: f64 inputs shorter than this use a comparison sort
+ * (default 10000). `radix_bits`: radix digit width, 8, 11 or 16 (default 16;
+ * narrower digits take more passes over smaller, cache-resident histograms).
+ * `parallel_threshold`: inputs at least this long sort on every available
+ * thread (at least 2; default usize::MAX, never). Returns 0, or
+ * TOVA_SORT_ERR_* leaving every setting unchanged. Sort output is identical
+ * under all settings.
+ */
+int32_t tova_sort_set_tuning(size_t small_cutoff, uint32_t radix_bits, size_t parallel_threshold);
+
+/**
+ * Current `tova_sort_set_tuning` values. Null outputs are skipped.
+ */
+void tova_sort_get_tuning(size_t *out_small_cutoff, uint32_t *out_radix_bits, size_t *out_parallel_threshold);
+
+/**
+ * Start sorting `ptr[..len]` like `tova_sort_f64`, a step at a time with
+ * `tova_sort_f64_step`, so a long sort can be interleaved with other work.
+ * The array must stay alive and untouched until `tova_sort_f64_finish` or
+ * `tova_sort_f64_abort` releases the handle; until then it holds the
+ * values in an unspecified state. Uses the tuning current at this call.
+ */
+SortF64Handle *tova_sort_f64_begin(double *ptr, size_t len);
+
+/**
+ * Do about `budget` elements' worth of work: part of a key rewrite,
+ * counting or scatter pass, with the few inputs that take a comparison
+ * sort done in one step. Returns 1 once the array is sorted (further steps
+ * do nothing), 0 while work remains, or TOVA_SORT_ERR_HANDLE for a null handle.
+ */
+int32_t tova_sort_f64_step(SortF64Handle *handle, size_t budget);
+
+/**
+ * Run whatever work remains, leave the array sorted and free the handle.
+ */
+void tova_sort_f64_finish(SortF64Handle *handle);
+
+/**
+ * Stop a sort early and free the handle. The array holds every original
+ * value, in an unspecified order.
+ */
+void tova_sort_f64_abort(SortF64Handle *handle);
+
+#define TOVA_EXTERNAL_SORT_ERR_ARGS (-1)
+#define TOVA_EXTERNAL_SORT_ERR_CREATE (-2)
+#define TOVA_EXTERNAL_SORT_ERR_IO (-3)
+#define TOVA_EXTERNAL_SORT_ERR_DISK_FULL (-4)
+
+/**
+ * Sort `ptr[..len]` like `tova_sort_f64` while touching at most
+ * `mem_budget_bytes` of it at a time, for arrays that do not fit in memory
+ * (a mapped file, say): budget-sized runs are sorted in place, spilled to
+ * temporary files in the NUL-terminated `scratch_dir` and merged back into
+ * the array, in several passes when there are more runs than one merge
+ * opens at once. The merge's buffers stay within the budget. An array
+ * within the budget is sorted in place with no files. Returns 0, or
+ * TOVA_EXTERNAL_SORT_ERR_* with the failing path in the last error message:
+ * ARGS for a null pointer, a budget under 8 bytes or an array misaligned for
+ * f64 (an aligned copy of it would not fit either), CREATE when a run file
+ * cannot be created, DISK_FULL when the scratch device fills up, IO for any
+ * other read or write failure. Run files are removed on every return. After an error while spilling or in an
+ * intermediate merge pass the array holds the same values in some order;
+ * after one in the final merge its contents are unspecified.
+ */
+int32_t tova_sort_f64_external(double *ptr, size_t len, const char *scratch_dir, size_t mem_budget_bytes);
+
+/* Array utilities */
+
+/**
+ * Remove duplicates from a sorted i64 array. Returns new length.
+ */
+size_t tova_unique_sorted_i64(int64_t *ptr, size_t len);
+
+/**
+ * Remove duplicates from a sorted f64 array. Returns new length.
+ */
+size_t tova_unique_sorted_f64(double *ptr, size_t len);
+
+/**
+ * Remove duplicates from a sorted i64 array like `tova_unique_sorted_i64`,
+ * writing how many times each distinct value occurred to `out_counts`
+ * (capacity `len`). Returns the number of distinct values.
+ */
+size_t tova_unique_counts_sorted_i64(int64_t *ptr, size_t len, uint64_t *out_counts);
+
+/**
+ * `tova_unique_counts_sorted_i64` for an array sorted by `tova_sort_f64`.
+ * Values are equal when their bits are: -0.0 and +0.0 are counted apart.
+ */
+size_t tova_unique_counts_sorted_f64(double *ptr, size_t len, uint64_t *out_counts);
+
+/**
+ * Sum an array of f64 values using Kahan summation (compensated, more accurate).
+ */
+double tova_sum_f64(const double *ptr, size_t len);
+
+/**
+ * Minimum of an f64 array, skipping NaN (NaN when there is no other value).
+ * -0.0 is smaller than +0.0.
+ */
+double tova_min_f64(const double *ptr, size_t len);
+
+/**
+ * Maximum of an f64 array, skipping NaN (NaN when there is no other value).
+ * +0.0 is larger than -0.0.
+ */
+double tova_max_f64(const double *ptr, size_t len);
+
+/**
+ * Compensated sum on up to `threads` threads (0 = available parallelism).
+ * The result is bit-identical for every thread count: the array is summed
+ * in fixed-size blocks merged in order, so it can differ from
+ * `tova_sum_f64` in the last bits. Small arrays are summed on the caller's
+ * thread.
+ */
+double tova_sum_f64_parallel(const double *ptr, size_t len, size_t threads);
+
+/**
+ * `tova_min_f64` on up to `threads` threads (0 = available parallelism).
+ */
+double tova_min_f64_parallel(const double *ptr, size_t len, size_t threads);
+
+/**
+ * `tova_max_f64` on up to `threads` threads (0 = available parallelism).
+ */
+double tova_max_f64_parallel(const double *ptr, size_t len, size_t threads);
+
+/**
+ * Minimum and maximum in one pass on up to `threads` threads, written to
+ * `out_min` and `out_max` (NaN when there is no non-NaN value).
+ */
+void tova_minmax_f64_parallel(const double *ptr, size_t len, size_t threads, double *out_min, double *out_max);
+
+/* i64 aggregates */
+
+#define TOVA_AGG_OK 0
+
+/**
+ * No value was counted
+ */
+#define TOVA_AGG_EMPTY 1
+
+/**
+ * Segment offsets decrease or run past the values
+ */
+#define TOVA_AGG_ERR_OFFSETS (-1)
+
+/**
+ * Smallest value of an i64 array (0 and TOVA_AGG_EMPTY when empty).
+ */
+int64_t tova_min_i64(const int64_t *ptr, size_t len, int32_t *out_status);
+
+/**
+ * Largest value of an i64 array (0 and TOVA_AGG_EMPTY when empty).
+ */
+int64_t tova_max_i64(const int64_t *ptr, size_t len, int32_t *out_status);
+
+/**
+ * Smallest and largest value in one pass, written to `out_min` and
+ * `out_max` (0 when empty). Returns TOVA_AGG_OK or TOVA_AGG_EMPTY.
+ */
+int32_t tova_minmax_i64(const int64_t *ptr, size_t len, int64_t *out_min, int64_t *out_max);
+
+/**
+ * Position of the first smallest value (SIZE_MAX and TOVA_AGG_EMPTY when
+ * empty).
+ */
+size_t tova_argmin_i64(const int64_t *ptr, size_t len, int32_t *out_status);
+
+/**
+ * Position of the first largest value (SIZE_MAX and TOVA_AGG_EMPTY when
+ * empty).
+ */
+size_t tova_argmax_i64(const int64_t *ptr, size_t len, int32_t *out_status);
+
+/**
+ * Mean of an i64 array, summed exactly in i128 (NaN and TOVA_AGG_EMPTY
+ * when empty). Within an ulp of the true mean whatever the magnitudes.
+ */
+double tova_mean_i64(const int64_t *ptr, size_t len, int32_t *out_status);
+
+/**
+ * `tova_min_i64` over the valid values.
+ */
+int64_t tova_min_i64_masked(const int64_t *ptr, const uint8_t *validity, size_t len, int32_t *out_status);
+
+/**
+ * `tova_max_i64` over the valid values.
+ */
+int64_t tova_max_i64_masked(const int64_t *ptr, const uint8_t *validity, size_t len, int32_t *out_status);
+
+/**
+ * `tova_minmax_i64` over the valid values.
+ */
+int32_t tova_minmax_i64_masked(const int64_t *ptr, const uint8_t *validity, size_t len, int64_t *out_min, int64_t *out_max);
+
+/**
+ * `tova_argmin_i64` over the valid values; the position counts the
+ * invalid ones too.
+ */
+size_t tova_argmin_i64_masked(const int64_t *ptr, const uint8_t *validity, size_t len, int32_t *out_status);
+
+/**
+ * `tova_argmax_i64` over the valid values; the position counts the
+ * invalid ones too.
+ */
+size_t tova_argmax_i64_masked(const int64_t *ptr, const uint8_t *validity, size_t len, int32_t *out_status);
+
+/**
+ * `tova_mean_i64` over the valid values.
+ */
+double tova_mean_i64_masked(const int64_t *ptr, const uint8_t *validity, size_t len, int32_t *out_status);
+
+/**
+ * Smallest value of each segment to `out` (0 for an empty one). Returns
+ * the number of empty segments, or TOVA_AGG_ERR_OFFSETS with nothing
+ * written.
+ */
+intptr_t tova_min_i64_segmented(const int64_t *values, size_t len, const uint64_t *offsets, size_t segments, int64_t *out);
+
+/**
+ * Largest value of each segment; as `tova_min_i64_segmented`.
+ */
+intptr_t tova_max_i64_segmented(const int64_t *values, size_t len, const uint64_t *offsets, size_t segments, int64_t *out);
+
+/**
+ * Position in `values` of each segment's first smallest value (u64::MAX
+ * for an empty one); as `tova_min_i64_segmented`.
+ */
+intptr_t tova_argmin_i64_segmented(const int64_t *values, size_t len, const uint64_t *offsets, size_t segments, uint64_t *out);
+
+/**
+ * Position in `values` of each segment's first largest value (u64::MAX
+ * for an empty one); as `tova_min_i64_segmented`.
+ */
+intptr_t tova_argmax_i64_segmented(const int64_t *values, size_t len, const uint64_t *offsets, size_t segments, uint64_t *out);
+
+/**
+ * Mean of each segment as `tova_mean_i64` computes it (NaN for an empty
+ * one); as `tova_min_i64_segmented`.
+ */
+intptr_t tova_mean_i64_segmented(const int64_t *values, size_t len, const uint64_t *offsets, size_t segments, double *out);
+
+/* Arrow IPC ingestion */
+
+#define TOVA_ARROW_ERR_MALFORMED (-1)
+#define TOVA_ARROW_ERR_UNSUPPORTED_TYPE (-2)
+#define TOVA_ARROW_ERR_COMPRESSED (-3)
+#define TOVA_ARROW_ERR_NO_COLUMN (-4)
+#define TOVA_ARROW_ERR_BIG_ENDIAN (-5)
+
+/**
+ * Sum the non-null values of Float64 column `column_index` across every
+ * record batch of an Arrow IPC stream or file. Returns 0 and writes `out`,
+ * or a negative TOVA_ARROW_ERR_* code.
+ */
+int32_t tova_arrow_sum_f64(const uint8_t *ipc_ptr, size_t ipc_len, uint32_t column_index, double *out);
+
+/**
+ * Minimum non-null, non-NaN value of a Float64 IPC column (NaN when there
+ * is none), with the same semantics as `tova_min_f64`.
+ */
+int32_t tova_arrow_min_f64(const uint8_t *ipc_ptr, size_t ipc_len, uint32_t column_index, double *out);
+
+/**
+ * Maximum non-null, non-NaN value of a Float64 IPC column (NaN when there
+ * is none), with the same semantics as `tova_max_f64`.
+ */
+int32_t tova_arrow_max_f64(const uint8_t *ipc_ptr, size_t ipc_len, uint32_t column_index, double *out);
+
+/**
+ * Number of non-null values in a Float64 IPC column.
+ */
+int32_t tova_arrow_count_f64(const uint8_t *ipc_ptr, size_t ipc_len, uint32_t column_index, uint64_t *out);
+
+/* NPY ingestion */
+
+#define TOVA_NPY_ERR_MALFORMED (-1)
+#define TOVA_NPY_ERR_VERSION (-2)
+#define TOVA_NPY_ERR_UNSUPPORTED_DTYPE (-3)
+#define TOVA_NPY_ERR_BIG_ENDIAN (-4)
+#define TOVA_NPY_ERR_FORTRAN_ORDER (-5)
+#define TOVA_NPY_ERR_MULTI_DIM (-6)
+#define TOVA_NPY_ERR_TRUNCATED (-7)
+#define TOVA_NPY_ERR_CAPACITY (-8)
+#define TOVA_NPY_DTYPE_F8 1
+#define TOVA_NPY_DTYPE_I8 2
+#define TOVA_NPY_DTYPE_F4 3
+#define TOVA_NPY_DTYPE_I4 4
+#define TOVA_NPY_LITTLE_ENDIAN 0
+#define TOVA_NPY_BIG_ENDIAN 1
+
+/**
+ * Header summary filled by `tova_npy_parse_header`.
+ */
+typedef struct NpyInfo {
+    uint32_t dtype;
+    uint32_t byte_order;
+    uint64_t item_size;
+    uint64_t element_count;
+    uint64_t data_offset;
+} NpyInfo;
+
+/**
+ * Parse and validate a .npy header. Returns 0 and fills `out_info`, or a
+ * negative TOVA_NPY_ERR_* code. Big-endian payloads are reported, not rejected.
+ */
+int32_t tova_npy_parse_header(const uint8_t *bytes, size_t len, NpyInfo *out_info);
+
+/**
+ * Copy a little-endian f8 or i8 .npy payload into `out` as f64 (i8 values are
+ * converted). Returns the element count or a negative TOVA_NPY_ERR_* code.
+ */
+intptr_t tova_npy_read_f64(const uint8_t *bytes, size_t len, double *out, size_t out_cap);
+
+/* String distance */
+
+/**
+ * Levenshtein (byte-wise edit) distance between two strings. Returns u32::MAX
+ * once the distance is known to exceed `max_dist`; pass u32::MAX for no bound.
+ */
+uint32_t tova_levenshtein(const uint8_t *a, size_t alen, const uint8_t *b, size_t blen, uint32_t max_dist);
+
+/**
+ * Distance from `query` to each of `count` strings in an offsets+bytes column
+ * (`offsets` holds count + 1 entries; string i is bytes[offsets[i]..offsets[i+1]]).
+ * Writes u32::MAX for candidates beyond `max_dist` or with invalid offsets.
+ * Inputs aliasing `out` are copied first (recorded as TOVA_ERR_OVERLAP).
+ */
+void tova_levenshtein_batch(const uint8_t *query, size_t qlen, const uint8_t *bytes, const uint64_t *offsets, size_t count, uint32_t max_dist, uint32_t *out);
+
+/**
+ * Hamming distance between two bit fingerprints of `len` u64 words.
+ */
+uint64_t tova_hamming_u64(const uint64_t *a, const uint64_t *b, size_t len);
+
+/* Substring search */
+
+/**
+ * Report overlapping matches ("aa" occurs 4 times in "aaaaa" instead of 2).
+ */
+#define TOVA_FIND_OVERLAPPING 1
+
+/**
+ * Compare ASCII letters case-insensitively; other bytes must match exactly.
+ */
+#define TOVA_FIND_IGNORE_ASCII_CASE 2
+
+/**
+ * Write the offsets of every match of `needle` in `haystack` to `out_offsets`.
+ * Returns the match count, or the negated count when it exceeds `out_cap`
+ * (the first `out_cap` offsets are still written).
+ */
+intptr_t tova_find_all(const uint8_t *haystack, size_t hlen, const uint8_t *needle, size_t nlen, uint32_t flags, uint64_t *out_offsets, size_t out_cap);
+
+/**
+ * Number of matches of `needle` in `haystack` (see TOVA_FIND_* flags).
+ */
+uint64_t tova_count_occurrences(const uint8_t *haystack, size_t hlen, const uint8_t *needle, size_t nlen, uint32_t flags);
+
+/**
+ * Offset of the first match of `needle` in `haystack`, or -1.
+ */
+int64_t tova_find_first(const uint8_t *haystack, size_t hlen, const uint8_t *needle, size_t nlen, uint32_t flags);
+
+/* JSON number arrays */
+
+/**
+ * Syntax errors return `TOVA_JSON_ERR_BASE - offset`, far below any negated
+ * capacity, so a result `r <= TOVA_JSON_ERR_BASE` means "invalid at byte
+ * `TOVA_JSON_ERR_BASE - r`".
+ */
+#define TOVA_JSON_ERR_BASE (INTPTR_MIN / 2)
+
+/**
+ * Parse a flat JSON array of numbers into `out`. Returns the element count,
+ * the negated count when it exceeds `out_cap` (the first `out_cap` values are
+ * still written), or `TOVA_JSON_ERR_BASE - offset` for invalid input. Numbers
+ * beyond f64 range become ±Infinity, as with JSON.parse.
+ */
+intptr_t tova_parse_json_numbers(const uint8_t *bytes, size_t len, double *out, size_t out_cap);
+
+/* Decompression */
+
+#define TOVA_DECOMPRESS_ERR_CORRUPT INTPTR_MIN
+#define TOVA_DECOMPRESS_ERR_TRUNCATED (INTPTR_MIN + 1)
+#define TOVA_DECOMPRESS_ERR_TOO_LARGE (INTPTR_MIN + 2)
+#define TOVA_DECOMPRESS_ERR_CHECKSUM (INTPTR_MIN + 3)
+#define TOVA_DECOMPRESS_ERR_UNSUPPORTED (INTPTR_MIN + 4)
+#define TOVA_FORMAT_GZIP 1
+#define TOVA_FORMAT_ZSTD 2
+
+/**
+ * Decompress a gzip buffer (one or more members) into `out`. Returns bytes
+ * written, the negated required size if `out_cap` is too small (taken from
+ * the ISIZE footer when possible), or a TOVA_DECOMPRESS_ERR_* code. Output
+ * beyond `max_output` bytes fails with TOO_LARGE instead of being produced.
+ */
+intptr_t tova_gzip_decompress(const uint8_t *src, size_t len, uint8_t *out, size_t out_cap, size_t max_output);
+
+#if defined(TOVA_NATIVE_ZSTD)
+/**
+ * Zstandard counterpart of `tova_gzip_decompress`, sized from the frame
+ * content-size header when present. Requires the `zstd` feature.
+ */
+intptr_t tova_zstd_decompress(const uint8_t *src, size_t len, uint8_t *out, size_t out_cap, size_t max_output);
+#endif
+
+/**
+ * Start a streaming decoder for TOVA_FORMAT_GZIP or TOVA_FORMAT_ZSTD that
+ * produces at most `max_output` bytes in total. Null for unknown or
+ * compiled-out formats. Free with `tova_decompress_free`.
+ */
+DecompressStream *tova_decompress_create(uint32_t format, uint64_t max_output);
+
+/**
+ * Feed the next compressed chunk. Decoding advances a whole block at a time,
+ * so tiny chunks are buffered until a block completes. Returns the number of
+ * decoded bytes ready for `tova_decompress_read`, or an error code (after
+ * which the stream stays failed).
+ */
+intptr_t tova_decompress_feed(DecompressStream *stream, const uint8_t *src, size_t len);
+
+/**
+ * Move up to `out_cap` decoded bytes into `out`; returns how many were copied.
+ */
+size_t tova_decompress_read(DecompressStream *stream, uint8_t *out, size_t out_cap);
+
+/**
+ * Check that everything fed so far was a complete stream (trailers and
+ * checksums verified). Returns 0, TRUNCATED, or the error that failed the stream.
+ */
+intptr_t tova_decompress_finish(const DecompressStream *stream);
+
+/**
+ * Release a stream from `tova_decompress_create`.
+ */
+void tova_decompress_free(DecompressStream *stream);
+
+/* Filter + gather */
+
+/**
+ * Write the indices of the nonzero bytes of `mask` (len <= 2^32) to
+ * `out_indices`. Returns the count, or the negated count when it exceeds
+ * `out_cap` (the first `out_cap` indices are still written).
+ */
+intptr_t tova_nonzero_u8(const uint8_t *mask, size_t len, uint32_t *out_indices, size_t out_cap);
+
+/**
+ * out[i] = values[indices[i]] for i in 0..n — pairs with `tova_nonzero_u8`
+ * for filtering and with argsort output for permuting. Returns n, or
+ * `-(i + 1)` for the first i whose index is >= `values_len` (out[..i] is written).
+ * `out` may alias `values` or `indices`; the inputs are read as they were on entry.
+ */
+intptr_t tova_gather_f64(const double *values, size_t values_len, const uint32_t *indices, size_t n, double *out);
+
+/**
+ * i64 variant of `tova_gather_f64`.
+ */
+intptr_t tova_gather_i64(const int64_t *values, size_t values_len, const uint32_t *indices, size_t n, int64_t *out);
+
+/* Dictionary columns */
+
+/**
+ * out[i] = dict[codes[i]]. `out` may alias `codes` or `dict`; the inputs
+ * are read as they were on entry.
+ */
+intptr_t tova_dict_decode_f64(const uint32_t *codes, size_t len, const double *dict, size_t dict_len, double *out);
+
+/**
+ * i64 variant of `tova_dict_decode_f64`.
+ */
+intptr_t tova_dict_decode_i64(const uint32_t *codes, size_t len, const int64_t *dict, size_t dict_len, int64_t *out);
+
+/**
+ * `tova_dict_decode_f64` writing NaN where the bit of `validity` (an Arrow
+ * bitmap of `len` bits; null: all valid) is clear. Codes under null slots
+ * are not checked.
+ */
+intptr_t tova_dict_decode_f64_masked(const uint32_t *codes, const uint8_t *validity, size_t len, const double *dict, size_t dict_len, double *out);
+
+/**
+ * `tova_dict_decode_f64_masked` for i64, writing `null_value` for nulls.
+ */
+intptr_t tova_dict_decode_i64_masked(const uint32_t *codes, const uint8_t *validity, size_t len, const int64_t *dict, size_t dict_len, int64_t null_value, int64_t *out);
+
+/**
+ * out_counts[c] = how many of the `len` codes are c, for each of the
+ * `dict_len` dictionary values: the group-by count of the decoded column.
+ * On error out_counts holds the counts of codes[..i].
+ */
+intptr_t tova_dict_take_counts(const uint32_t *codes, size_t len, size_t dict_len, uint64_t *out_counts);
+
+/* Packed bitmaps */
+
+/**
+ * out_mask[i] = 1 if lo <= values[i] <= hi, else 0 (NaN is never in range).
+ */
+void tova_mask_from_range_f64(const double *values, size_t len, double lo, double hi, uint8_t *out_mask);
+
+/**
+ * `tova_mask_from_range_f64` into a packed bitmap of `len` bits.
+ */
+void tova_mask_from_range_f64_packed(const double *values, size_t len, double lo, double hi, uint8_t *out_bitmap);
+
+/**
+ * out = a & b over `len_bits` bits.
+ */
+void tova_bitmap_and(const uint8_t *a, const uint8_t *b, uint8_t *out, size_t len_bits);
+
+/**
+ * out = a | b over `len_bits` bits.
+ */
+void tova_bitmap_or(const uint8_t *a, const uint8_t *b, uint8_t *out, size_t len_bits);
+
+/**
+ * out = !a over `len_bits` bits.
+ */
+void tova_bitmap_not(const uint8_t *a, uint8_t *out, size_t len_bits);
+
+/**
+ * Number of set bits among the first `len_bits`.
+ */
+size_t tova_bitmap_count(const uint8_t *bitmap, size_t len_bits);
+
+/**
+ * Compact `values` in place to the elements whose bit is set in `bitmap`
+ * (`len` bits), keeping their order. Returns how many were kept; the rest
+ * of `values` is left as it was.
+ */
+size_t tova_filter_bitmap_f64(double *values, const uint8_t *bitmap, size_t len);
+
+/* Scatter + permutation */
+
+#define TOVA_INDEX_ERR_OUT_OF_RANGE (-1)
+#define TOVA_INDEX_ERR_DUPLICATE (-2)
+#define TOVA_INDEX_ERR_TOO_LONG (-3)
+
+/**
+ * Duplicate target indices: later values overwrite earlier ones.
+ */
+#define TOVA_SCATTER_LAST_WINS 0
+
+/**
+ * Duplicate target indices fail with TOVA_INDEX_ERR_DUPLICATE.
+ */
+#define TOVA_SCATTER_REJECT_DUPLICATES 1
+
+/**
+ * out[indices[i]] = values[i] for i in 0..n, the inverse of gather. Returns
+ * 0, or TOVA_INDEX_ERR_OUT_OF_RANGE / TOVA_INDEX_ERR_DUPLICATE (per `policy`,
+ * a TOVA_SCATTER_* constant) without writing anything. TOVA_ERR_OVERLAP if
+ * `out` aliases `values` or `indices`.
+ */
+int32_t tova_scatter_f64(const double *values, const uint32_t *indices, size_t n, double *out, size_t out_len, uint32_t policy);
+
+/**
+ * i64 variant of `tova_scatter_f64`.
+ */
+int32_t tova_scatter_i64(const int64_t *values, const uint32_t *indices, size_t n, int64_t *out, size_t out_len, uint32_t policy);
+
+/**
+ * Reorder `values` in place so values'[i] = values[perm[i]] (the argsort
+ * convention), using cycle-following instead of a second value buffer. The
+ * high bit of each `perm` entry is borrowed as a visited flag during the
+ * walk and cleared again, so `perm` is unchanged on return. Returns 0,
+ * TOVA_INDEX_ERR_OUT_OF_RANGE / DUPLICATE if `perm` is not a permutation of
+ * 0..len (values untouched), TOVA_INDEX_ERR_TOO_LONG if len > 2^31, or
+ * TOVA_ERR_OVERLAP if `values` and `perm` share memory.
+ */
+int32_t tova_apply_permutation_inplace_f64(double *values, uint32_t *perm, size_t len);
+
+/**
+ * i64 variant of `tova_apply_permutation_inplace_f64`.
+ */
+int32_t tova_apply_permutation_inplace_i64(int64_t *values, uint32_t *perm, size_t len);
+
+/* Byte order + record layout */
+
+#define TOVA_LAYOUT_ERR_FIELD (-1)
+#define TOVA_LAYOUT_ERR_WIDTH (-2)
+
+/**
+ * Reverse the byte order of each u64 in place (big-endian i64/f64 columns).
+ */
+void tova_bswap_u64(uint64_t *ptr, size_t len);
+
+/**
+ * Reverse the byte order of each u32 in place.
+ */
+void tova_bswap_u32(uint32_t *ptr, size_t len);
+
+/**
+ * Reverse the byte order of each u16 in place.
+ */
+void tova_bswap_u16(uint16_t *ptr, size_t len);
+
+/**
+ * Extract one fixed-width field from each of `count` records of
+ * `record_size` bytes into a contiguous `count * field_size` output (one
+ * column of an array-of-structs → struct-of-arrays transpose). Returns 0,
+ * TOVA_LAYOUT_ERR_FIELD if the field does not fit inside the record, or
+ * TOVA_ERR_OVERLAP if `src` and `out` share memory.
+ */
+int32_t tova_deinterleave(const uint8_t *src, size_t record_size, size_t count, size_t field_offset, size_t field_size, uint8_t *out);
+
+/**
+ * Reverse of `tova_deinterleave`: write `count` contiguous field values
+ * from `src` into the field at `field_offset` of each record in `out`,
+ * leaving the other bytes of each record untouched.
+ */
+int32_t tova_interleave(const uint8_t *src, size_t record_size, size_t count, size_t field_offset, size_t field_size, uint8_t *out);
+
+/**
+ * `tova_deinterleave` for a field stored little-endian in the records: the
+ * output column is in host byte order. TOVA_LAYOUT_ERR_WIDTH unless
+ * `field_size` is 1, 2, 4 or 8.
+ */
+int32_t tova_deinterleave_le(const uint8_t *src, size_t record_size, size_t count, size_t field_offset, size_t field_size, uint8_t *out);
+
+/**
+ * `tova_deinterleave_le` for big-endian fields (network order, most binary
+ * file formats).
+ */
+int32_t tova_deinterleave_be(const uint8_t *src, size_t record_size, size_t count, size_t field_offset, size_t field_size, uint8_t *out);
+
+/**
+ * `tova_interleave` from a host-order column into little-endian record fields.
+ */
+int32_t tova_interleave_le(const uint8_t *src, size_t record_size, size_t count, size_t field_offset, size_t field_size, uint8_t *out);
+
+/**
+ * `tova_interleave` from a host-order column into big-endian record fields.
+ */
+int32_t tova_interleave_be(const uint8_t *src, size_t record_size, size_t count, size_t field_offset, size_t field_size, uint8_t *out);
+
+/* Library info + last error */
+
+/**
+ * Bits of `tova_features`. PARALLEL: the multi-threaded reductions and the
+ * parallel sort path are available. SIMD is reserved for kernels with
+ * explicit vector paths; none has one, so it reads as clear.
+ */
+#define TOVA_FEATURE_SIMD (UINT64_C(1) << 0)
+#define TOVA_FEATURE_PARALLEL (UINT64_C(1) << 1)
+#define TOVA_FEATURE_GZIP (UINT64_C(1) << 2)
+#define TOVA_FEATURE_ZSTD (UINT64_C(1) << 3)
+
+/**
+ * Last-error code for invalid input whose return value has no i32 form
+ * (JSON offsets, decompression codes); the message carries the detail.
+ */
+#define TOVA_ERR_INVALID_INPUT (-101)
+
+/**
+ * Crate version as a static NUL-terminated semver string.
+ */
+const char *tova_version(void);
+
+/**
+ * TOVA_FEATURE_* bits for the optional kernels compiled into this build.
+ */
+uint64_t tova_features(void);
+
+/**
+ * Version of the FFI surface: bumped whenever an export is added, removed
+ * or changes signature, so a loader can refuse a library built from a
+ * different source than it expects.
+ */
+#define TOVA_ABI_VERSION 23
+
+uint32_t tova_abi_version(void);
+
+/**
+ * Names of every entry point in this build, each NUL-terminated, with an
+ * empty name (a second NUL) ending the list. Static; do not free.
+ */
+const char *tova_symbols(void);
+
+/**
+ * A check failed; the report says which.
+ */
+#define TOVA_SELFCHECK_ERR_FAILED (-1)
+
+/**
+ * `cap` is too small for the report; nothing was written.
+ */
+#define TOVA_SELFCHECK_ERR_BUFFER (-2)
+
+/**
+ * Run a few kernels on fixed inputs (sort 8 values, sum, an XXH64 test
+ * vector) and write a JSON report into `out` as a NUL-terminated string:
+ * `{"abi":1,"version":"..","features":N,"checks":{"sort":true,..},"ok":true}`.
+ * Meant for loaders to call once at startup, so a library built for the
+ * wrong target or from other sources fails there rather than mid-request.
+ * Returns 0 when every check passed.
+ */
+int32_t tova_selfcheck(uint8_t *out, size_t cap);
+
+/**
+ * Code recorded by the most recent checked call on this thread, 0 if it
+ * completed without detecting a problem.
+ */
+int32_t tova_last_error(void);
+
+/**
+ * Copy the last error's message into `buf` as a NUL-terminated string,
+ * truncated to `cap - 1` bytes. Returns the full message length, so a
+ * result >= `cap` means the copy was cut short. Empty when there is no error.
+ */
+size_t tova_last_error_message(uint8_t *buf, size_t cap);
+
+/* Aliasing checks */
+
+#define TOVA_ERR_OVERLAP (-100)
+
+/* Rank + percentile */
+
+/**
+ * Number of elements of ascending `sorted` that are <= `value` (0 for NaN).
+ * NaNs in `sorted` must sit at the end, as `tova_sort_f64` leaves them.
+ */
+size_t tova_rank_f64(const double *sorted, size_t len, double value);
+
+/**
+ * Empirical percentile in [0, 100] of each query against ascending `sorted`:
+ * 100 * (count below + half the count equal) / len, so a value tied with the
+ * whole reference scores 50. NaN queries (and an empty reference) give NaN.
+ * Ascending runs of queries advance a cursor instead of searching from scratch.
+ */
+void tova_percentile_of_f64(const double *sorted, size_t len, const double *queries, size_t qlen, double *out);
+
+/**
+ * Compute quantile edges and filter thresholds from all the values even past
+ * QUANTILE_SAMPLE_MIN.
+ */
+#define TOVA_QUANTILE_EXACT 1
+
+/**
+ * Write the k - 1 interior edges splitting the non-NaN values into k
+ * equal-frequency buckets to `out_edges`: edge i is the value of rank
+ * floor(i * n / k), so bucket i holds [edge i-1, edge i). Exact edges use
+ * partition-based selection, not a full sort; inputs over QUANTILE_SAMPLE_MIN
+ * values use a sorted random sample of QUANTILE_SAMPLE_LEN unless `flags`
+ * has TOVA_QUANTILE_EXACT. Heavy ties can leave fewer distinct edges than
+ * asked for: those come first and the remaining slots hold the maximum.
+ * Returns the distinct edge count (0, with NaN edges, when all values are
+ * NaN). `out_edges` may alias `values`; the values are read as they were on entry.
+ */
+size_t tova_quantile_edges_f64(const double *values, size_t len, size_t k, uint32_t flags, double *out_edges);
+
+/**
+ * Compact `values` in place to those greater than their own q-quantile,
+ * keeping their order, and the nullable `companion` ids (`len` of them) in
+ * lockstep. The quantile is the value of rank floor(q * n) among the n
+ * non-NaN values (clamped to the largest), found by selection, or from a
+ * sample past QUANTILE_SAMPLE_MIN values unless `flags` has
+ * TOVA_QUANTILE_EXACT; it goes to the nullable `out_threshold` (NaN when
+ * every value is NaN). NaNs are dropped. Returns the new length, or SIZE_MAX
+ * with nothing changed and the last error TOVA_ERR_INVALID_INPUT for q
+ * outside [0, 1], or TOVA_ERR_OVERLAP when the buffers overlap.
+ */
+size_t tova_filter_above_quantile_f64(double *values, size_t len, double q, uint32_t flags, int64_t *companion, double *out_threshold);
+
+/**
+ * `tova_filter_above_quantile_f64`, keeping the values less than the
+ * q-quantile instead.
+ */
+size_t tova_filter_below_quantile_f64(double *values, size_t len, double q, uint32_t flags, int64_t *companion, double *out_threshold);
+
+/**
+ * `tova_filter_above_quantile_f64`, keeping the values from the
+ * q_lo-quantile up to but not including the q_hi-quantile, which go to
+ * `out_thresholds[0]` and `[1]`. Needs q_lo <= q_hi.
+ */
+size_t tova_filter_between_quantiles_f64(double *values, size_t len, double q_lo, double q_hi, uint32_t flags, int64_t *companion, double *out_thresholds);
+
+/* Order-statistics multiset */
+
+#define TOVA_OSET_ERR_HANDLE (-1)
+#define TOVA_OSET_ERR_NAN (-2)
+#define TOVA_OSET_ERR_ABSENT (-3)
+
+/**
+ * An empty multiset of f64 for rank and quantile queries over a changing
+ * window of values; every operation is O(log n). Free with `tova_oset_free`.
+ */
+OrderSet *tova_oset_create(void);
+
+/**
+ * Add one copy of `value` (-0.0 counts as 0.0). Returns 0,
+ * TOVA_OSET_ERR_NAN for a NaN, which is not added, or TOVA_OSET_ERR_HANDLE.
+ */
+int32_t tova_oset_insert_f64(OrderSet *handle, double value);
+
+/**
+ * Remove one copy of `value`. Returns 0, TOVA_OSET_ERR_ABSENT if the set
+ * holds none, or TOVA_OSET_ERR_HANDLE.
+ */
+int32_t tova_oset_remove_f64(OrderSet *handle, double value);
+
+/**
+ * The value of rank floor(q * (len - 1)), the convention of
+ * `tova_profile_f64`'s quartiles: q = 0.99 gives the p99. NaN for an empty
+ * set, a `q` outside [0, 1] or a null handle.
+ */
+double tova_oset_quantile(const OrderSet *handle, double q);
+
+/**
+ * Number of values <= `value`, as `tova_rank_f64` counts them (0 for NaN
+ * or a null handle).
+ */
+size_t tova_oset_rank(const OrderSet *handle, double value);
+
+/**
+ * Values in the set, copies included; 0 for a null handle.
+ */
+size_t tova_oset_len(const OrderSet *handle);
+
+/**
+ * Release a set from `tova_oset_create`.
+ */
+void tova_oset_free(OrderSet *handle);
+
+/* Column profile */
+
+/**
+ * Slots `tova_profile_f64` / `tova_profile_i64` write, at these indices.
+ * Statistics cover the counted values: valid and not NaN. Missing
+ * statistics (min of no values, stddev of one, quartiles without
+ * TOVA_PROFILE_QUARTILES, unused top slots) are NaN.
+ */
+#define TOVA_PROFILE_LEN 22
+
+/**
+ * Counted values
+ */
+#define TOVA_PROFILE_COUNT 0
+
+/**
+ * Values whose validity bit is clear
+ */
+#define TOVA_PROFILE_NULLS 1
+
+/**
+ * Valid NaNs
+ */
+#define TOVA_PROFILE_NANS 2
+
+/**
+ * HyperLogLog estimate of the distinct values, within about 2%
+ */
+#define TOVA_PROFILE_DISTINCT 3
+#define TOVA_PROFILE_MIN 4
+#define TOVA_PROFILE_MAX 5
+#define TOVA_PROFILE_MEAN 6
+
+/**
+ * Sample standard deviation (n - 1 denominator)
+ */
+#define TOVA_PROFILE_STDDEV 7
+
+/**
+ * 25th, 50th and 75th percentiles: the value of rank floor(q * (n - 1))
+ */
+#define TOVA_PROFILE_P25 8
+#define TOVA_PROFILE_P50 9
+#define TOVA_PROFILE_P75 10
+
+/**
+ * How many of the top slots below are filled (at most 5)
+ */
+#define TOVA_PROFILE_TOP_COUNT 11
+
+/**
+ * The most frequent values, most frequent first and ties smallest first.
+ * Values under 1/64 of the count may be missed in favour of rarer ones.
+ */
+#define TOVA_PROFILE_TOP_VALUES 12
+
+/**
+ * Their counts: exact with TOVA_PROFILE_QUARTILES, else upper bounds
+ */
+#define TOVA_PROFILE_TOP_COUNTS 17
+
+/**
+ * Make a second pass for the quartiles and exact top counts.
+ */
+#define TOVA_PROFILE_QUARTILES 1
+#define TOVA_PROFILE_ERR_NULL (-1)
+
+/**
+ * Profile a column in one pass (two with TOVA_PROFILE_QUARTILES), writing
+ * TOVA_PROFILE_LEN f64s to `out` at the TOVA_PROFILE_* indices. `validity`
+ * is an Arrow bitmap of `len` bits, or null when every value is valid.
+ * Quartiles of more than QUANTILE_SAMPLE_MIN values come from an evenly
+ * strided sample. Returns 0, or TOVA_PROFILE_ERR_NULL for a null `out`
+ * (or null `values` with len > 0).
+ */
+int32_t tova_profile_f64(const double *values, const uint8_t *validity, size_t len, uint32_t flags, double *out);
+
+/**
+ * `tova_profile_f64` for i64 columns. Statistics are computed in f64:
+ * values beyond 2^53 in magnitude are reported rounded, and NANS is 0.
+ */
+int32_t tova_profile_i64(const int64_t *values, const uint8_t *validity, size_t len, uint32_t flags, double *out);
+
+/* Pair packing */
+
+/**
+ * out[i] = pack(hi[i], lo[i]): (hi << 32) | lo as raw u32 halves, or with
+ * `order_preserving` the form whose signed i64 order equals lexicographic
+ * (hi, lo) order (see `kernels::pack_i32_pair`). Unpack with the same flag.
+ */
+void tova_pack_i32_pairs(const int32_t *hi, const int32_t *lo, size_t len, int64_t *out, bool order_preserving);
+
+/**
+ * Inverse of `tova_pack_i32_pairs` for keys packed with the same flag.
+ */
+void tova_unpack_i32_pairs(const int64_t *src, size_t len, int32_t *out_hi, int32_t *out_lo, bool order_preserving);
+
+/* Weighted aggregates */
+
+/**
+ * Σ values[i] * weights[i], compensated for both product and sum rounding.
+ */
+double tova_weighted_sum_f64(const double *values, const double *weights, size_t len);
+
+/**
+ * Σ w·x / Σ w with both sums compensated; NaN when the total weight is 0.
+ */
+double tova_weighted_mean_f64(const double *values, const double *weights, size_t len);
+
+/**
+ * `tova_weighted_sum_f64` skipping pairs where either side is NaN.
+ */
+double tova_weighted_sum_masked_f64(const double *values, const double *weights, size_t len);
+
+/**
+ * `tova_weighted_mean_f64` skipping pairs where either side is NaN.
+ */
+double tova_weighted_mean_masked_f64(const double *values, const double *weights, size_t len);
+
+/**
+ * Σ x², compensated the same way (RMS = sqrt(sum_squares / len)).
+ */
+double tova_sum_squares_f64(const double *ptr, size_t len);
+
+/* Grouped partials */
+
+/**
+ * Combine two sets of group-by partials, each with strictly ascending keys
+ * and a sum and row count per key (what each worker of a parallel group-by
+ * produces). Matching keys have their sums and counts added; the rest are
+ * copied, and the merged keys stay ascending, so results can be merged
+ * again in any grouping. Returns the merged group count, or its negation
+ * when it exceeds `out_cap` (the first `out_cap` groups are still written).
+ * The outputs may alias the inputs; the inputs are read as they were on entry.
+ */
+intptr_t tova_merge_groups_f64(const int64_t *keys_a, const double *vals_a, const uint64_t *counts_a, size_t len_a, const int64_t *keys_b, const double *vals_b, const uint64_t *counts_b, size_t len_b, int64_t *out_keys, double *out_vals, uint64_t *out_counts, size_t out_cap);
+
+/* Streaming group-by */
+
+/**
+ * Compensated sum per group (NaN once any of its values is)
+ */
+#define TOVA_GROUP_AGG_SUM 1
+
+/**
+ * Smallest non-NaN value per group (NaN when there is none)
+ */
+#define TOVA_GROUP_AGG_MIN 2
+
+/**
+ * Largest non-NaN value per group (NaN when there is none)
+ */
+#define TOVA_GROUP_AGG_MAX 3
+
+/**
+ * Rows per group; `values` may be null
+ */
+#define TOVA_GROUP_AGG_COUNT 4
+
+/**
+ * Sum / rows per group
+ */
+#define TOVA_GROUP_AGG_MEAN 5
+#define TOVA_GROUP_AGG_ERR_HANDLE (-1)
+
+/**
+ * The handle was already finalized
+ */
+#define TOVA_GROUP_AGG_ERR_FINALIZED (-2)
+
+/**
+ * An unknown op, or merging handles with different ops
+ */
+#define TOVA_GROUP_AGG_ERR_OP (-3)
+
+/**
+ * What `tova_group_agg_finalize` returns on error; `tova_last_error` says
+ * which TOVA_GROUP_AGG_ERR_* it was
+ */
+#define TOVA_GROUP_AGG_ERR_FINALIZE INTPTR_MIN
+
+/**
+ * An empty group-by computing `op` (a TOVA_GROUP_AGG_* op) per key. Null,
+ * with TOVA_GROUP_AGG_ERR_OP recorded, for an unknown op. Free with
+ * `tova_group_agg_free`.
+ */
+GroupAgg *tova_group_agg_create(int32_t op);
+
+/**
+ * Fold `len` rows, `keys[i]` with `values[i]`, into the groups. The table
+ * doubles as the group count does, so a handle fed thousands of chunks
+ * rehashes only as often as that; `tova_group_agg_reserve` avoids even
+ * that. Returns 0 or a TOVA_GROUP_AGG_ERR_* code.
+ */
+int32_t tova_group_agg_update(GroupAgg *handle, const int64_t *keys, const double *values, size_t len);
+
+/**
+ * Make room for `groups` groups in all, so that updates up to that many
+ * never rehash. Returns 0, a TOVA_GROUP_AGG_ERR_* code, or
+ * TOVA_ERR_INVALID_INPUT when no table could hold that many.
+ */
+int32_t tova_group_agg_reserve(GroupAgg *handle, size_t groups);
+
+/**
+ * Fold every group of `src` into `dst`, as if `dst` had been given the
+ * rows `src` was; `src` is left as it was. Both must compute the same op
+ * (else TOVA_GROUP_AGG_ERR_OP) and be distinct, unfinalized handles.
+ * Returns 0 or a TOVA_GROUP_AGG_ERR_* code.
+ */
+int32_t tova_group_agg_merge(GroupAgg *dst, const GroupAgg *src);
+
+/**
+ * Groups so far; 0 for a null or finalized handle.
+ */
+size_t tova_group_agg_len(const GroupAgg *handle);
+
+/**
+ * Write each group's key, result and row count (`out_counts` may be null)
+ * in ascending key order and release the state; the handle then refuses
+ * everything with TOVA_GROUP_AGG_ERR_FINALIZED but `tova_group_agg_free`.
+ * Returns the group count; when that exceeds `out_cap` it returns the
+ * negated count, writes the first `out_cap` groups and keeps the state, so
+ * the call can be repeated with room for them all. TOVA_GROUP_AGG_ERR_FINALIZE
+ * for a null or finalized handle.
+ */
+intptr_t tova_group_agg_finalize(GroupAgg *handle, int64_t *out_keys, double *out_vals, uint64_t *out_counts, size_t out_cap);
+
+/**
+ * Release a handle from `tova_group_agg_create`, finalized or not.
+ */
+void tova_group_agg_free(GroupAgg *handle);
+
+/* Time buckets */
+
+/**
+ * `flags` bit: also emit the empty buckets between the first and last one,
+ * with count 0 and the op's value for no rows (0 for SUM and COUNT, NaN for
+ * the rest)
+ */
+#define TOVA_TIME_BUCKET_FILL_GAPS 1
+
+/**
+ * What `tova_time_bucket_agg` returns on error; `tova_last_error` says
+ * which error it was
+ */
+#define TOVA_TIME_BUCKET_ERR INTPTR_MIN
+
+/**
+ * Aggregate `values` (null for TOVA_GROUP_AGG_COUNT) with `op`, a
+ * TOVA_GROUP_AGG_* op, per bucket of `ts`: rows fall in the `bucket`-wide
+ * bucket starting at origin + floor((ts - origin) / bucket) * bucket, so
+ * timestamps before `origin`, negative ones included, round down. Writes
+ * each bucket's start, result and row count (`out_counts` may be null) in
+ * ascending order and returns the bucket count; when that exceeds `out_cap`
+ * it returns the negated count and writes the first `out_cap` buckets.
+ * Everything is read before anything is written, so the outputs may
+ * overlap the inputs. TOVA_TIME_BUCKET_ERR for an unknown op
+ * (TOVA_GROUP_AGG_ERR_OP), or for a `bucket` that is not positive or a
+ * bucket start or gap-filled count out of range (TOVA_ERR_INVALID_INPUT).
+ */
+intptr_t tova_time_bucket_agg(const int64_t *ts, const double *values, size_t len, int64_t bucket, int64_t origin, int32_t op, uint32_t flags, int64_t *out_bucket_starts, double *out_values, uint64_t *out_counts, size_t out_cap);
+
+/* String + row hashing */
+
+#define TOVA_HASH_ERR_OFFSETS (-1)
+#define TOVA_HASH_ERR_KIND (-2)
+#define TOVA_COLUMN_I64 1
+#define TOVA_COLUMN_F64 2
+#define TOVA_COLUMN_STRING 3
+
+/**
+ * A packed validity bitmap, one bit per row (`tova_gather_columns` only).
+ */
+#define TOVA_COLUMN_BITMAP 4
+
+/**
+ * One key column for `tova_hash_columns`. `data` points at `count` i64/f64
+ * values, or at the string bytes with `offsets` holding count + 1 entries.
+ * For a bitmap column `data` holds ceil(count / 8) bytes.
+ */
+typedef struct ColumnDesc {
+    uint32_t kind;
+    const uint8_t *data;
+    const uint64_t *offsets;
+} ColumnDesc;
+
+/**
+ * XXH64 of each string in an offsets+bytes column. Returns 0, or
+ * TOVA_HASH_ERR_OFFSETS (nothing written) if the offsets decrease.
+ */
+int32_t tova_hash_strings(const uint8_t *bytes, const uint64_t *offsets, size_t count, uint64_t seed, uint64_t *out);
+
+/**
+ * One hash per row over `ncols` key columns of `count` rows each. Columns are
+ * folded in order with an XXH64 round, so swapping columns changes the hash,
+ * then avalanched together with the column count. Returns 0,
+ * TOVA_HASH_ERR_KIND / TOVA_HASH_ERR_OFFSETS for a bad descriptor, or
+ * TOVA_ERR_OVERLAP; nothing is written on error.
+ */
+int32_t tova_hash_columns(const ColumnDesc *columns, size_t ncols, size_t count, uint64_t seed, uint64_t *out);
+
+/* Table gather + scatter */
+
+#define TOVA_INDEX_ERR_KIND (-4)
+
+/**
+ * An output column for `tova_gather_columns`: `data` has room for `nrows`
+ * values (or bits) of `kind`, which must match its input column's.
+ */
+typedef struct ColumnDescMut {
+    uint32_t kind;
+    uint8_t *data;
+} ColumnDescMut;
+
+/**
+ * Materialize a table in permuted order: out_columns[c][i] =
+ * columns[c][perm[i]] for every i64, f64 and bitmap column, each `nrows`
+ * long (offsets are ignored). Every index is checked before any column is
+ * written. Columns move on all threads when `nrows` reaches the sort's
+ * parallel threshold (`tova_sort_set_tuning`). Returns 0,
+ * TOVA_INDEX_ERR_OUT_OF_RANGE, TOVA_INDEX_ERR_KIND (an unsupported kind, or
+ * an output kind that differs from its input's), or TOVA_ERR_OVERLAP when an
+ * output overlaps any input or another output; nothing is written on error.
+ */
+int32_t tova_gather_columns(const uint32_t *perm, size_t nrows, const ColumnDesc *columns, size_t ncols, const ColumnDescMut *out_columns);
+
+/**
+ * The inverse of `tova_gather_columns`: out_columns[c][perm[i]] =
+ * columns[c][i]. `perm` must be a permutation of 0..nrows, else
+ * TOVA_INDEX_ERR_OUT_OF_RANGE / TOVA_INDEX_ERR_DUPLICATE with nothing
+ * written; otherwise as `tova_gather_columns`.
+ */
+int32_t tova_scatter_columns(const uint32_t *perm, size_t nrows, const ColumnDesc *columns, size_t ncols, const ColumnDescMut *out_columns);
+
+/* As-of join */
+
+/**
+ * Match each left timestamp to the last right timestamp at or before it.
+ */
+#define TOVA_ASOF_BACKWARD 0
+
+/**
+ * ... to the first right timestamp at or after it.
+ */
+#define TOVA_ASOF_FORWARD 1
+
+/**
+ * ... to the closer of those two, the backward one on a tie.
+ */
+#define TOVA_ASOF_NEAREST 2
+#define TOVA_ASOF_ERR_LEFT_UNSORTED (-1)
+#define TOVA_ASOF_ERR_RIGHT_UNSORTED (-2)
+#define TOVA_ASOF_ERR_TOLERANCE (-3)
+#define TOVA_ASOF_ERR_DIRECTION (-4)
+
+/**
+ * As-of join of two ascending timestamp columns: out_right_idx[i] is the
+ * index of the right row matching left_ts[i] in `direction`
+ * (TOVA_ASOF_*), or -1 when there is none within `tolerance` (0 for exact
+ * matches only, i64::MAX for no limit). Of equal right timestamps, backward
+ * matches take the last and forward matches the first. Returns 0, or
+ * TOVA_ASOF_ERR_* with nothing written: both inputs are checked sorted
+ * first, and the tolerance must not be negative. `out_right_idx` may alias
+ * `left_ts`.
+ */
+int32_t tova_asof_join_i64(const int64_t *left_ts, size_t llen, const int64_t *right_ts, size_t rlen, int64_t tolerance, uint32_t direction, int64_t *out_right_idx);
+
+/* Hash join */
+
+#define TOVA_HASHJOIN_ERR_HANDLE INTPTR_MIN
+
+/**
+ * Index the build side of an inner equi-join, `keys`, for
+ * `tova_hashjoin_probe_i64`; duplicate keys keep all their rows. Sorting
+ * neither side, it suits a small unsorted build side against a large probe
+ * side. Null past 2^32 - 1 rows. Free with `tova_hashjoin_free`.
+ */
+HashJoin *tova_hashjoin_build_i64(const int64_t *keys, size_t len);
+
+/**
+ * Every (probe row, build row) pair whose keys are equal, probe rows in
+ * order and each one's build rows ascending, written to `out_probe_idx`
+ * and `out_build_idx` (plen <= 2^32). Returns the count, or the negated
+ * count when it exceeds `out_cap` (the first `out_cap` pairs are still
+ * written), or TOVA_HASHJOIN_ERR_HANDLE for a null handle. Either output
+ * may alias `probe_keys`.
+ */
+intptr_t tova_hashjoin_probe_i64(const HashJoin *handle, const int64_t *probe_keys, size_t plen, uint32_t *out_probe_idx, uint32_t *out_build_idx, size_t out_cap);
+
+/**
+ * Release a table from `tova_hashjoin_build_i64`.
+ */
+void tova_hashjoin_free(HashJoin *handle);
+
+/* Sampling */
+
+/**
+ * Weighted sample without replacement of up to `k` row indices (A-ExpJ
+ * reservoir sampling): each draw takes a row with probability proportional
+ * to its weight among the rows left. Rows with a weight that is not finite
+ * and positive, or a NaN in `values`, are skipped; `values` may be null to
+ * sample on the weights alone. The same inputs and `seed` give the same
+ * sample. Writes the indices (len <= 2^32) to `out_indices` in ascending
+ * order and returns how many: k, or every eligible row when there are fewer.
+ */
+size_t tova_weighted_sample_f64(const double *values, const double *weights, size_t len, size_t k, uint64_t seed, uint32_t *out_indices);
+
+/**
+ * Uniform sample of up to `per_stratum_k` row indices for each distinct
+ * value of `strata`, in one pass. The same inputs and `seed` give the same
+ * sample. Writes the indices (len <= 2^32) to `out_indices` in ascending
+ * order and returns the count, or the negated count when it exceeds
+ * `out_cap` (the first `out_cap` indices are still written).
+ */
+intptr_t tova_stratified_sample_i64(const int64_t *strata, size_t len, size_t per_stratum_k, uint64_t seed, uint32_t *out_indices, size_t out_cap);
+
+/* Fingerprints */
+
+/**
+ * MinHash signature of the set of token `hashes`, `num_perm` slots written
+ * to `out_signature`. Compare signatures made with the same `num_perm` and
+ * `seed` with `tova_minhash_similarity`; they are the same on every
+ * platform for the same inputs.
+ */
+void tova_minhash_u64(const uint64_t *hashes, size_t len, uint32_t num_perm, uint64_t seed, uint64_t *out_signature);
+
+/**
+ * Fraction of the `num_perm` slots of two MinHash signatures that match,
+ * an estimate of the Jaccard similarity of the two token sets. NaN when
+ * `num_perm` is 0.
+ */
+double tova_minhash_similarity(const uint64_t *sig_a, const uint64_t *sig_b, uint32_t num_perm);
+
+/**
+ * 64-bit SimHash of the token `hashes`, each weighted by `weights` (null
+ * for equal weights; tokens with a weight that is not finite are left
+ * out). Near-duplicates differ in few bits: see `tova_hamming_distance_u64`.
+ */
+uint64_t tova_simhash_u64(const uint64_t *hashes, const double *weights, size_t len);
+
+/**
+ * Number of bits in which two fingerprints differ.
+ */
+uint32_t tova_hamming_distance_u64(uint64_t a, uint64_t b);
+
+/* Decimal fixed-point */
+
+/**
+ * Round halfway cases to the even unit (banker's rounding).
+ */
+#define TOVA_ROUND_HALF_EVEN 0
+
+/**
+ * Round halfway cases away from zero.
+ */
+#define TOVA_ROUND_HALF_UP 1
+
+/**
+ * Or'ed into a conversion's rounding mode: write TOVA_DECIMAL_NULL for
+ * inexact values instead of rounding them.
+ */
+#define TOVA_ROUND_STRICT (1 << 8)
+
+/**
+ * No value: an empty mean, a result past i64, or a value that did not convert.
+ */
+#define TOVA_DECIMAL_NULL (INT64_C(-9223372036854775807) - INT64_C(1))
+#define TOVA_DECIMAL_ERR_ARGS (-1)
+
+/**
+ * Exact sum of scaled values as an i128, split into its high word
+ * (`out_hi`, signed) and low word (`out_lo`): sum = hi * 2^64 + lo. No i64
+ * input can overflow it. Returns 0, or TOVA_DECIMAL_ERR_ARGS when an output
+ * is null.
+ */
+int32_t tova_sum_scaled_i64(const int64_t *ptr, size_t len, int64_t *out_hi, uint64_t *out_lo);
+
+/**
+ * Mean of scaled values with `scale` more decimals than they have (0 keeps
+ * their units), from the exact sum and rounded once by `rounding`.
+ * TOVA_DECIMAL_NULL, with the last error set, for no values, a scale past
+ * 18, an unknown rounding mode or a mean past i64.
+ */
+int64_t tova_mean_scaled_i64(const int64_t *ptr, size_t len, uint32_t scale, int32_t rounding);
+
+/**
+ * Convert f64 values to units of 10^-`scale`, rounding each from its exact
+ * binary value by `rounding` (0.125 is a tie, but 1.005 is stored just
+ * under 1.005 and rounds down to 100 at scale 2 in either mode). A
+ * value converts exactly when it is the double nearest its decimal, as
+ * 0.1 is for 10 at scale 2 but 0.1 + 0.2 is not for 30. Non-finite values
+ * and results past i64 are written as TOVA_DECIMAL_NULL, and so are inexact
+ * values when `rounding` has TOVA_ROUND_STRICT. Returns how many values did
+ * not convert exactly; an unknown rounding mode or a scale past 18 writes
+ * nothing and returns SIZE_MAX. `out` may alias `src`.
+ */
+size_t tova_convert_f64_scaled_i64(const double *src, size_t len, uint32_t scale, int64_t *out, int32_t rounding);
+
+/* Sparse vectors */
+
+#define TOVA_SPARSE_ERR_UNSORTED (-1)
+#define TOVA_SPARSE_ERR_OUT_OF_RANGE (-2)
+#define TOVA_SPARSE_ERR_INDPTR (-3)
+
+/**
+ * Dot product of two sparse vectors (Σ over shared indices), compensated
+ * like `tova_weighted_sum_f64`. NaN, with the last error
+ * TOVA_SPARSE_ERR_UNSORTED, if either index array is not strictly
+ * ascending.
+ */
+double tova_sparse_dot(const uint32_t *ia, const double *va, size_t na, const uint32_t *ib, const double *vb, size_t nb);
+
+/**
+ * Dot product of a sparse vector (indices in any order) with a dense one
+ * of `dense_len` values. NaN, with the last error
+ * TOVA_SPARSE_ERR_OUT_OF_RANGE, if an index is not below `dense_len`.
+ */
+double tova_sparse_dense_dot(const uint32_t *idx, const double *vals, size_t n, const double *dense, size_t dense_len);
+
+/**
+ * Score a sparse query against every row of a CSR batch by dot product
+ * and write the `k` best rows to `out_rows`, highest score first and ties
+ * to the lower row, with their scores in `out_scores`. Rows scoring NaN
+ * are skipped. Returns how many rows were written (min(k, rows scored)),
+ * or SIZE_MAX with nothing written and the last error set:
+ * TOVA_SPARSE_ERR_UNSORTED for a query or row whose indices are not
+ * strictly ascending, TOVA_SPARSE_ERR_INDPTR for bad offsets or more than
+ * 2^32 - 1 rows, TOVA_ERR_OVERLAP when an output overlaps an input or the
+ * other output.
+ */
+size_t tova_sparse_topk_dot(const uint32_t *query_idx, const double *query_vals, size_t qn, const uint64_t *batch_csr_indptr, const uint32_t *batch_idx, const double *batch_vals, size_t nrows, size_t k, uint32_t *out_rows, double *out_scores);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* TOVA_NATIVE_H */
@@ -0,0 +1,299 @@
+// Prints include/tova_native.h, the C header for the cdylib's exports:
+//
+//     cargo run --quiet --bin gen-header > include/tova_native.h
+//
+// It reads src/lib.rs, where every export lives, and declares each
+// `#[no_mangle]` function, `#[repr(C)]` struct and `pub const TOVA_*` in
+// source order with its doc comment. Other types the exports point at are
+// declared opaque. tests/abi.rs fails when the committed header differs
+// from this output, so regenerate it with any change to the C surface.
+//
+// The parser only knows the forms lib.rs uses (items start in column 0,
+// signatures end at their `{`); anything else stops it with a message
+// rather than producing a wrong header.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+const SOURCE: &str = include_str!("../lib.rs");
+
+/// The `cfg` exports may carry, and the macro C callers define to see them.
+const FEATURE_GATES: &[(&str, &str)] = &[("#[cfg(feature = \"zstd\")]", "TOVA_NATIVE_ZSTD")];
+
+const PREAMBLE: &str = "\
+/*
+ * tova_native.h: C interface to the tova_native library.
+ *
+ * Generated from native/src/lib.rs by `cargo run --bin gen-header`; do not
+ * edit. Check `tova_abi_version()` against TOVA_ABI_VERSION before use.
+ * Define TOVA_NATIVE_ZSTD when linking a build with the zstd feature
+ * (`tova_features() & TOVA_FEATURE_ZSTD`) to declare its exports.
+ */
+
+#ifndef TOVA_NATIVE_H
+#define TOVA_NATIVE_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern \"C\" {
+#endif
+";
+
+const POSTAMBLE: &str = "
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* TOVA_NATIVE_H */
+";
+
+fn main() {
+    print!("{}", header(SOURCE));
+}
+
+/// What the attributes and doc comment above an item said.
+#[derive(Default)]
+struct Pending {
+    doc: Vec<String>,
+    no_mangle: bool,
+    repr_c: bool,
+    gate: Option<&'static str>,
+}
+
+fn header(source: &str) -> String {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut body = String::new();
+    let mut structs = BTreeSet::new();
+    let mut pointees = BTreeSet::new();
+    let mut section: Option<&str> = None;
+    let mut pending = Pending::default();
+    // Runs of undocumented constants stay together, as in the source
+    let mut after_define = false;
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        i += 1;
+        if line.starts_with(' ') || line.starts_with('}') || line.is_empty() {
+            continue;
+        }
+        if line.starts_with("// ====") {
+            // Banner: `// ====`, `// Title`, `// ====`
+            if let Some(title) = lines.get(i).and_then(|l| l.strip_prefix("// ")) {
+                section = Some(title);
+                i += 2;
+            }
+            continue;
+        }
+        if let Some(doc) = line.strip_prefix("///") {
+            pending.doc.push(doc.strip_prefix(' ').unwrap_or(doc).to_string());
+            continue;
+        }
+        if line.starts_with("#[") {
+            match line {
+                "#[no_mangle]" => pending.no_mangle = true,
+                "#[repr(C)]" => pending.repr_c = true,
+                _ => {
+                    if let Some(&(_, gate)) = FEATURE_GATES.iter().find(|(cfg, _)| *cfg == line) {
+                        pending.gate = Some(gate);
+                    }
+                }
+            }
+            continue;
+        }
+
+        let item = std::mem::take(&mut pending);
+        let out = if let Some(rest) = line.strip_prefix("pub const TOVA_") {
+            constant(&format!("TOVA_{}", rest))
+        } else if item.no_mangle {
+            let start = i - 1;
+            while !lines[i - 1].trim_end().ends_with('{') {
+                i += 1;
+            }
+            function(&lines[start..i].join(" "), &mut pointees)
+        } else if let (true, Some(rest)) = (item.repr_c, line.strip_prefix("pub struct ")) {
+            let name = rest.trim_end_matches(" {").to_string();
+            let start = i;
+            while lines[i] != "}" {
+                i += 1;
+            }
+            let fields = record(&name, &lines[start..i], &mut pointees);
+            structs.insert(name);
+            fields
+        } else {
+            continue;
+        };
+
+        let define = out.starts_with("#define");
+        if let Some(title) = section.take() {
+            writeln!(body, "\n/* {} */", title).unwrap();
+            after_define = false;
+        }
+        if !(define && after_define && item.doc.is_empty() && item.gate.is_none()) {
+            body.push('\n');
+        }
+        after_define = define;
+        if let Some(gate) = item.gate {
+            writeln!(body, "#if defined({})", gate).unwrap();
+        }
+        body.push_str(&doc_comment(&item.doc, ""));
+        body.push_str(&out);
+        if item.gate.is_some() {
+            body.push_str("#endif\n");
+        }
+    }
+
+    let mut out = String::from(PREAMBLE);
+    let opaque: Vec<&String> = pointees.difference(&structs).collect();
+    if !opaque.is_empty() {
+        out.push_str("\n/* Opaque handles, created and freed by the library */\n\n");
+        for name in opaque {
+            writeln!(out, "typedef struct {0} {0};", name).unwrap();
+        }
+    }
+    out.push_str(&body);
+    out.push_str(POSTAMBLE);
+    out
+}
+
+fn doc_comment(doc: &[String], indent: &str) -> String {
+    if doc.is_empty() {
+        return String::new();
+    }
+    let mut out = format!("{}/**\n", indent);
+    for line in doc {
+        let line = line.replace("*/", "*\\/");
+        if line.is_empty() {
+            writeln!(out, "{} *", indent).unwrap();
+        } else {
+            writeln!(out, "{} * {}", indent, line).unwrap();
+        }
+    }
+    writeln!(out, "{} */", indent).unwrap();
+    out
+}
+
+/// `TOVA_NAME: type = expr;` as a #define.
+fn constant(decl: &str) -> String {
+    let (name, rest) = decl.split_once(": ").unwrap_or_else(|| panic!("unparsed constant: {}", decl));
+    let (ty, expr) = rest.split_once(" = ").unwrap_or_else(|| panic!("unparsed constant: {}", decl));
+    let expr = expr.trim_end_matches(';');
+    let mut parts = Vec::new();
+    let mut after_shift = false;
+    for token in expr.split(' ') {
+        let digits = token.trim_start_matches('-');
+        let part = match token {
+            "isize::MIN" => "INTPTR_MIN".to_string(),
+            "isize::MAX" => "INTPTR_MAX".to_string(),
+            "<<" | "+" | "-" | "/" => token.to_string(),
+            _ if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit() || b == b'_') => {
+                let literal = token.replace('_', "");
+                match ty {
+                    // A shift's width keeps its plain int type
+                    _ if after_shift => literal,
+                    "u64" => format!("UINT64_C({})", literal),
+                    "i64" => format!("INT64_C({})", literal),
+                    "i32" | "u32" | "usize" | "isize" => literal,
+                    _ => panic!("unsupported constant type {} for {}", ty, name),
+                }
+            }
+            _ => panic!("unsupported constant expression for {}: {}", name, expr),
+        };
+        after_shift = token == "<<";
+        parts.push(part);
+    }
+    let c = parts.join(" ");
+    if c.contains(' ') || c.starts_with('-') {
+        format!("#define {} ({})\n", name, c)
+    } else {
+        format!("#define {} {}\n", name, c)
+    }
+}
+
+/// A `pub (unsafe) extern "C" fn` signature, up to its `{`, as a prototype.
+fn function(signature: &str, pointees: &mut BTreeSet<String>) -> String {
+    let signature = signature.split_whitespace().collect::<Vec<_>>().join(" ");
+    let rest = signature
+        .strip_prefix("pub unsafe extern \"C\" fn ")
+        .unwrap_or_else(|| panic!("exports are `pub unsafe extern \"C\" fn`: {}", signature));
+    let open = rest.find('(').unwrap();
+    let close = rest.rfind(')').unwrap();
+    let name = &rest[..open];
+    let params: Vec<String> = rest[open + 1..close]
+        .split(',')
+        .map(str::trim)
+        .filter(|param| !param.is_empty())
+        .map(|param| {
+            let (pname, ty) = param.split_once(": ").unwrap_or_else(|| panic!("unparsed parameter of {}: {}", name, param));
+            declare(&c_type(ty, pointees), pname)
+        })
+        .collect();
+    let ret = rest[close + 1..].trim_end_matches('{').trim();
+    let ret = match ret.strip_prefix("-> ") {
+        Some(ty) => c_type(ty, pointees),
+        None => "void".to_string(),
+    };
+    let params = if params.is_empty() { "void".to_string() } else { params.join(", ") };
+    format!("{};\n", declare(&ret, &format!("{}({})", name, params)))
+}
+
+/// A `#[repr(C)]` struct's field lines as a typedef.
+fn record(name: &str, lines: &[&str], pointees: &mut BTreeSet<String>) -> String {
+    let mut out = format!("typedef struct {} {{\n", name);
+    let mut doc = Vec::new();
+    for line in lines {
+        let line = line.trim();
+        if let Some(text) = line.strip_prefix("///") {
+            doc.push(text.strip_prefix(' ').unwrap_or(text).to_string());
+            continue;
+        }
+        let field = line
+            .strip_prefix("pub ")
+            .and_then(|f| f.strip_suffix(','))
+            .unwrap_or_else(|| panic!("unparsed field of {}: {}", name, line));
+        let (fname, ty) = field.split_once(": ").unwrap();
+        out.push_str(&doc_comment(&doc, "    "));
+        doc.clear();
+        writeln!(out, "    {};", declare(&c_type(ty, pointees), fname)).unwrap();
+    }
+    writeln!(out, "}} {};", name).unwrap();
+    out
+}
+
+fn c_type(ty: &str, pointees: &mut BTreeSet<String>) -> String {
+    if let Some(inner) = ty.strip_prefix("*const ") {
+        return format!("const {} *", c_type(inner, pointees));
+    }
+    if let Some(inner) = ty.strip_prefix("*mut ") {
+        return format!("{} *", c_type(inner, pointees));
+    }
+    let c = match ty {
+        "u8" => "uint8_t",
+        "u16" => "uint16_t",
+        "u32" => "uint32_t",
+        "u64" => "uint64_t",
+        "i8" => "int8_t",
+        "i16" => "int16_t",
+        "i32" => "int32_t",
+        "i64" => "int64_t",
+        "usize" => "size_t",
+        "isize" => "intptr_t",
+        "f32" => "float",
+        "f64" => "double",
+        "bool" => "bool",
+        "c_char" => "char",
+        _ if ty.starts_with(|c: char| c.is_ascii_uppercase()) && ty.chars().all(|c| c.is_ascii_alphanumeric()) => {
+            pointees.insert(ty.to_string());
+            ty
+        }
+        _ => panic!("no C type for {}", ty),
+    };
+    c.to_string()
+}
+
+/// `ty name`, without a space between a pointer's `*` and the name.
+fn declare(ty: &str, name: &str) -> String {
+    if ty.ends_with('*') { format!("{}{}", ty, name) } else { format!("{} {}", ty, name) }
+}
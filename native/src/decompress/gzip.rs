@@ -0,0 +1,354 @@
+// gzip (RFC 1952) framing around a DEFLATE (RFC 1951) decoder.
+
+use super::{BitReader, DecodeError, DecodeResult, Sink};
+use std::sync::OnceLock;
+
+/// DEFLATE back-references reach at most 32 KiB
+pub(crate) const WINDOW: usize = 1 << 15;
+
+const FLAGS_RESERVED: u8 = 0xE0;
+const FHCRC: u8 = 0x02;
+const FEXTRA: u8 = 0x04;
+const FNAME: u8 = 0x08;
+const FCOMMENT: u8 = 0x10;
+
+const LEN_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LEN_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+};
+
+pub(crate) fn crc32_update(crc: u32, bytes: &[u8]) -> u32 {
+    let mut c = !crc;
+    for &b in bytes {
+        c = CRC_TABLE[((c ^ b as u32) & 0xFF) as usize] ^ (c >> 8);
+    }
+    !c
+}
+
+/// Canonical Huffman decoder: a 10-bit lookup table for short codes and a
+/// bit-at-a-time walk (as in zlib's puff) for the rare longer ones.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+    /// (symbol << 4) | length, 0 when the code is longer than FAST_BITS
+    fast: Vec<u16>,
+}
+
+const FAST_BITS: u32 = 10;
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> DecodeResult<Self> {
+        let mut counts = [0u16; 16];
+        for &l in lengths {
+            counts[l as usize] += 1;
+        }
+        counts[0] = 0;
+        // Over-subscribed code sets are invalid; incomplete ones are tolerated
+        let mut left = 1i32;
+        for &c in &counts[1..] {
+            left = (left << 1) - c as i32;
+            if left < 0 {
+                return Err(DecodeError::Corrupt);
+            }
+        }
+
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        let mut next_code = [0u32; 16];
+        let mut code = 0u32;
+        for len in 1..16 {
+            code = (code + counts[len - 1] as u32) << 1;
+            next_code[len] = code;
+        }
+        let mut fast = vec![0u16; 1 << FAST_BITS];
+        for (sym, &len) in lengths.iter().enumerate() {
+            if len == 0 {
+                continue;
+            }
+            let len = len as usize;
+            symbols[offsets[len] as usize] = sym as u16;
+            offsets[len] += 1;
+            let code = next_code[len];
+            next_code[len] += 1;
+            if len as u32 <= FAST_BITS {
+                // Stream bits arrive LSB-first, so index by the reversed code
+                let rev = (code.reverse_bits() >> (32 - len)) as usize;
+                let entry = ((sym as u16) << 4) | len as u16;
+                for slot in (rev..1 << FAST_BITS).step_by(1 << len) {
+                    fast[slot] = entry;
+                }
+            }
+        }
+        Ok(Huffman { counts, symbols, fast })
+    }
+
+    fn decode(&self, br: &mut BitReader) -> DecodeResult<u16> {
+        let entry = self.fast[br.peek(FAST_BITS) as usize];
+        if entry != 0 {
+            let len = (entry & 15) as u32;
+            br.need(len)?;
+            br.pos += len as usize;
+            return Ok(entry >> 4);
+        }
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= br.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(DecodeError::Corrupt)
+    }
+}
+
+fn fixed_tables() -> &'static (Huffman, Huffman) {
+    static FIXED: OnceLock<(Huffman, Huffman)> = OnceLock::new();
+    FIXED.get_or_init(|| {
+        let mut lit = [8u8; 288];
+        lit[144..256].fill(9);
+        lit[256..280].fill(7);
+        (Huffman::new(&lit).unwrap(), Huffman::new(&[5u8; 30]).unwrap())
+    })
+}
+
+fn dynamic_tables(br: &mut BitReader) -> DecodeResult<(Huffman, Huffman)> {
+    let hlit = br.bits(5)? as usize + 257;
+    let hdist = br.bits(5)? as usize + 1;
+    let hclen = br.bits(4)? as usize + 4;
+    let mut cl_lengths = [0u8; 19];
+    for &i in &CODE_LENGTH_ORDER[..hclen] {
+        cl_lengths[i] = br.bits(3)? as u8;
+    }
+    let cl = Huffman::new(&cl_lengths)?;
+
+    let mut lengths = vec![0u8; hlit + hdist];
+    let mut i = 0;
+    while i < lengths.len() {
+        let (value, repeat) = match cl.decode(br)? {
+            sym @ 0..=15 => (sym as u8, 1),
+            16 => {
+                let prev = *lengths[..i].last().ok_or(DecodeError::Corrupt)?;
+                (prev, 3 + br.bits(2)? as usize)
+            }
+            17 => (0, 3 + br.bits(3)? as usize),
+            _ => (0, 11 + br.bits(7)? as usize),
+        };
+        if i + repeat > lengths.len() {
+            return Err(DecodeError::Corrupt);
+        }
+        lengths[i..i + repeat].fill(value);
+        i += repeat;
+    }
+    if lengths[256] == 0 {
+        return Err(DecodeError::Corrupt);
+    }
+    Ok((Huffman::new(&lengths[..hlit])?, Huffman::new(&lengths[hlit..])?))
+}
+
+fn inflate_codes<S: Sink>(br: &mut BitReader, sink: &mut S, lit: &Huffman, dist: &Huffman) -> DecodeResult<()> {
+    loop {
+        let sym = lit.decode(br)? as usize;
+        if sym < 256 {
+            sink.push(sym as u8)?;
+        } else if sym == 256 {
+            return Ok(());
+        } else {
+            let i = sym - 257;
+            if i >= LEN_BASE.len() {
+                return Err(DecodeError::Corrupt);
+            }
+            let len = LEN_BASE[i] as usize + br.bits(LEN_EXTRA[i] as u32)? as usize;
+            let d = dist.decode(br)? as usize;
+            if d >= DIST_BASE.len() {
+                return Err(DecodeError::Corrupt);
+            }
+            let distance = DIST_BASE[d] as usize + br.bits(DIST_EXTRA[d] as u32)? as usize;
+            sink.copy_match(distance, len)?;
+        }
+    }
+}
+
+/// Decode one DEFLATE block; returns whether it was the final one.
+fn inflate_block<S: Sink>(br: &mut BitReader, sink: &mut S) -> DecodeResult<bool> {
+    let last = br.bits(1)? == 1;
+    match br.bits(2)? {
+        0 => {
+            br.align();
+            let len = br.bits(16)?;
+            let nlen = br.bits(16)?;
+            if len != !nlen & 0xFFFF {
+                return Err(DecodeError::Corrupt);
+            }
+            br.need(len * 8)?;
+            let start = br.pos / 8;
+            sink.extend(&br.data[start..start + len as usize])?;
+            br.pos += len as usize * 8;
+        }
+        1 => {
+            let (lit, dist) = fixed_tables();
+            inflate_codes(br, sink, lit, dist)?;
+        }
+        2 => {
+            let (lit, dist) = dynamic_tables(br)?;
+            inflate_codes(br, sink, &lit, &dist)?;
+        }
+        _ => return Err(DecodeError::Corrupt),
+    }
+    Ok(last)
+}
+
+/// Length of the member header at the start of `input`, or `NeedInput`.
+fn header_len(input: &[u8]) -> DecodeResult<usize> {
+    let need = |n: usize| if input.len() < n { Err(DecodeError::NeedInput) } else { Ok(()) };
+    need(10)?;
+    if input[0] != 0x1F || input[1] != 0x8B || input[3] & FLAGS_RESERVED != 0 {
+        return Err(DecodeError::Corrupt);
+    }
+    // 8 (deflate) is the only compression method ever defined
+    if input[2] != 8 {
+        return Err(DecodeError::Unsupported);
+    }
+    let flags = input[3];
+    let mut pos = 10;
+    if flags & FEXTRA != 0 {
+        need(pos + 2)?;
+        pos += 2 + u16::from_le_bytes([input[pos], input[pos + 1]]) as usize;
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            need(pos + 1)?;
+            match input[pos..].iter().position(|&b| b == 0) {
+                Some(nul) => pos += nul + 1,
+                None => return Err(DecodeError::NeedInput),
+            }
+        }
+    }
+    if flags & FHCRC != 0 {
+        pos += 2;
+    }
+    need(pos)?;
+    Ok(pos)
+}
+
+/// ISIZE of a single-member gzip buffer: the uncompressed length mod 2^32.
+/// Claims beyond DEFLATE's best possible ratio (~1032:1) can only come from a
+/// truncated or forged footer and are ignored.
+pub(crate) fn size_hint(src: &[u8]) -> Option<u64> {
+    if src.len() < 18 || src[0] != 0x1F || src[1] != 0x8B {
+        return None;
+    }
+    let size = u32::from_le_bytes(src[src.len() - 4..].try_into().unwrap()) as u64;
+    if size > src.len() as u64 * 1032 {
+        return None;
+    }
+    Some(size)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Header,
+    Blocks,
+    Trailer,
+}
+
+pub(crate) struct GzipDecoder {
+    state: State,
+    /// Bits of the first unconsumed input byte already used by the last block
+    bit_offset: usize,
+    crc: u32,
+    member_start: usize,
+    members: u64,
+}
+
+impl GzipDecoder {
+    pub(crate) fn new() -> Self {
+        GzipDecoder { state: State::Header, bit_offset: 0, crc: 0, member_start: 0, members: 0 }
+    }
+
+    pub(crate) fn at_boundary(&self) -> bool {
+        self.state == State::Header && self.members > 0
+    }
+
+    pub(crate) fn decode<S: Sink>(&mut self, input: &[u8], sink: &mut S) -> DecodeResult<usize> {
+        let mut pos = 0;
+        loop {
+            match self.state {
+                State::Header => {
+                    if pos == input.len() {
+                        return Ok(pos);
+                    }
+                    match header_len(&input[pos..]) {
+                        Ok(n) => pos += n,
+                        Err(DecodeError::NeedInput) => return Ok(pos),
+                        Err(e) => return Err(e),
+                    }
+                    self.crc = 0;
+                    self.member_start = sink.len();
+                    self.state = State::Blocks;
+                }
+                State::Blocks => {
+                    let mut br = BitReader::new(input, pos * 8 + self.bit_offset);
+                    let start = sink.len();
+                    match inflate_block(&mut br, sink) {
+                        Ok(last) => {
+                            self.crc = crc32_update(self.crc, sink.tail(start));
+                            if last {
+                                br.align();
+                                self.state = State::Trailer;
+                            }
+                            pos = br.pos / 8;
+                            self.bit_offset = br.pos % 8;
+                        }
+                        Err(DecodeError::NeedInput) => {
+                            sink.truncate(start);
+                            return Ok(pos);
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+                State::Trailer => {
+                    let Some(trailer) = input.get(pos..pos + 8) else {
+                        return Ok(pos);
+                    };
+                    let crc = u32::from_le_bytes(trailer[..4].try_into().unwrap());
+                    let size = u32::from_le_bytes(trailer[4..].try_into().unwrap());
+                    if crc != self.crc || size != (sink.len() - self.member_start) as u32 {
+                        return Err(DecodeError::Checksum);
+                    }
+                    pos += 8;
+                    self.members += 1;
+                    self.state = State::Header;
+                }
+            }
+        }
+    }
+}
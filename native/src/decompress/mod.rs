@@ -0,0 +1,382 @@
+// Decompression for the ingestion kernels: gzip always, zstd behind the
+// `zstd` cargo feature. Both decoders are std-only and write straight into the
+// caller's output buffer for one-shot calls, or into a sliding window for
+// streaming handles.
+
+pub(crate) mod gzip;
+#[cfg(feature = "zstd")]
+pub(crate) mod zstd;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum DecodeError {
+    /// Input ended inside a header, block or trailer
+    NeedInput,
+    Corrupt,
+    /// Output would exceed the sink's capacity or the caller's hard cap
+    Overflow,
+    Unsupported,
+    Checksum,
+}
+
+pub(crate) type DecodeResult<T> = Result<T, DecodeError>;
+
+/// Output target shared by both decoders. Positions are logical byte offsets
+/// from the start of the stream, so a windowed sink can drop consumed output.
+pub(crate) trait Sink {
+    fn len(&self) -> usize;
+    fn push(&mut self, byte: u8) -> DecodeResult<()>;
+    fn extend(&mut self, bytes: &[u8]) -> DecodeResult<()>;
+    #[cfg_attr(not(feature = "zstd"), allow(dead_code))]
+    fn fill(&mut self, byte: u8, n: usize) -> DecodeResult<()>;
+    fn copy_match(&mut self, dist: usize, n: usize) -> DecodeResult<()>;
+    fn truncate(&mut self, len: usize);
+    /// Output written since logical position `from`
+    fn tail(&self, from: usize) -> &[u8];
+}
+
+/// LZ77 copy within `buf`: `n` bytes from `dist` back, written at `at`.
+/// Overlapping copies (dist < n) repeat the pattern as the format requires.
+fn lz_copy(buf: &mut [u8], at: usize, dist: usize, n: usize) {
+    let src = at - dist;
+    if dist >= n {
+        buf.copy_within(src..src + n, at);
+    } else {
+        for i in 0..n {
+            buf[at + i] = buf[src + i];
+        }
+    }
+}
+
+/// Fixed-capacity sink over the caller's buffer.
+pub(crate) struct SliceSink<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> SliceSink<'a> {
+    pub(crate) fn new(buf: &'a mut [u8]) -> Self {
+        SliceSink { buf, len: 0 }
+    }
+
+    fn reserve(&self, n: usize) -> DecodeResult<usize> {
+        match self.len.checked_add(n) {
+            Some(end) if end <= self.buf.len() => Ok(end),
+            _ => Err(DecodeError::Overflow),
+        }
+    }
+}
+
+impl Sink for SliceSink<'_> {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn push(&mut self, byte: u8) -> DecodeResult<()> {
+        let end = self.reserve(1)?;
+        self.buf[self.len] = byte;
+        self.len = end;
+        Ok(())
+    }
+
+    fn extend(&mut self, bytes: &[u8]) -> DecodeResult<()> {
+        let end = self.reserve(bytes.len())?;
+        self.buf[self.len..end].copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+
+    fn fill(&mut self, byte: u8, n: usize) -> DecodeResult<()> {
+        let end = self.reserve(n)?;
+        self.buf[self.len..end].fill(byte);
+        self.len = end;
+        Ok(())
+    }
+
+    fn copy_match(&mut self, dist: usize, n: usize) -> DecodeResult<()> {
+        if dist == 0 || dist > self.len {
+            return Err(DecodeError::Corrupt);
+        }
+        let end = self.reserve(n)?;
+        lz_copy(self.buf, self.len, dist, n);
+        self.len = end;
+        Ok(())
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+
+    fn tail(&self, from: usize) -> &[u8] {
+        &self.buf[from..self.len]
+    }
+}
+
+/// Growable sink for streaming and for sizing a one-shot result. Keeps
+/// unread output plus enough history for the decoder's window.
+pub(crate) struct WindowSink {
+    buf: Vec<u8>,
+    /// Logical offset of buf[0]
+    base: usize,
+    /// Logical offset of the next byte handed to the reader
+    read: usize,
+    limit: usize,
+}
+
+impl WindowSink {
+    pub(crate) fn new(limit: usize) -> Self {
+        WindowSink { buf: Vec::new(), base: 0, read: 0, limit }
+    }
+
+    fn reserve(&mut self, n: usize) -> DecodeResult<()> {
+        match self.len().checked_add(n) {
+            Some(end) if end <= self.limit => Ok(()),
+            _ => Err(DecodeError::Overflow),
+        }
+    }
+
+    pub(crate) fn available(&self) -> usize {
+        self.len() - self.read
+    }
+
+    /// Copy unread output into `out`, then drop whatever is neither unread
+    /// nor within `window` bytes of the end.
+    pub(crate) fn read_into(&mut self, out: &mut [u8], window: usize) -> usize {
+        let start = self.read - self.base;
+        let n = out.len().min(self.available());
+        out[..n].copy_from_slice(&self.buf[start..start + n]);
+        self.read += n;
+
+        let keep_from = self.read.min(self.len().saturating_sub(window));
+        // Compact only once the dead prefix is worth a memmove
+        if keep_from - self.base > window.max(1 << 16) {
+            self.buf.drain(..keep_from - self.base);
+            self.base = keep_from;
+        }
+        n
+    }
+}
+
+impl Sink for WindowSink {
+    fn len(&self) -> usize {
+        self.base + self.buf.len()
+    }
+
+    fn push(&mut self, byte: u8) -> DecodeResult<()> {
+        self.reserve(1)?;
+        self.buf.push(byte);
+        Ok(())
+    }
+
+    fn extend(&mut self, bytes: &[u8]) -> DecodeResult<()> {
+        self.reserve(bytes.len())?;
+        self.buf.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn fill(&mut self, byte: u8, n: usize) -> DecodeResult<()> {
+        self.reserve(n)?;
+        self.buf.resize(self.buf.len() + n, byte);
+        Ok(())
+    }
+
+    fn copy_match(&mut self, dist: usize, n: usize) -> DecodeResult<()> {
+        if dist == 0 || dist > self.buf.len() {
+            return Err(DecodeError::Corrupt);
+        }
+        self.reserve(n)?;
+        let at = self.buf.len();
+        self.buf.resize(at + n, 0);
+        lz_copy(&mut self.buf, at, dist, n);
+        Ok(())
+    }
+
+    fn truncate(&mut self, len: usize) {
+        if len < self.len() {
+            self.buf.truncate(len.max(self.base) - self.base);
+        }
+    }
+
+    fn tail(&self, from: usize) -> &[u8] {
+        &self.buf[from - self.base..]
+    }
+}
+
+/// LSB-first bit reader over a byte slice (DEFLATE order, and the forward
+/// streams in zstd table headers). Reads past the end report `NeedInput`.
+pub(crate) struct BitReader<'a> {
+    data: &'a [u8],
+    /// Bit position from the start of `data`
+    pub(crate) pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub(crate) fn new(data: &'a [u8], pos: usize) -> Self {
+        BitReader { data, pos }
+    }
+
+    /// Next `n` (<= 25) bits without consuming them, zero-padded past the end
+    pub(crate) fn peek(&self, n: u32) -> u32 {
+        let byte = self.pos >> 3;
+        let word = match self.data.get(byte..byte + 4) {
+            Some(b) => u32::from_le_bytes(b.try_into().unwrap()),
+            None => {
+                let mut w = 0u32;
+                for (i, &b) in self.data.get(byte..).unwrap_or(&[]).iter().enumerate() {
+                    w |= (b as u32) << (8 * i);
+                }
+                w
+            }
+        };
+        (word >> (self.pos & 7)) & ((1u32 << n) - 1)
+    }
+
+    pub(crate) fn need(&self, n: u32) -> DecodeResult<()> {
+        if self.pos + n as usize > self.data.len() * 8 {
+            Err(DecodeError::NeedInput)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub(crate) fn bits(&mut self, n: u32) -> DecodeResult<u32> {
+        self.need(n)?;
+        let v = self.peek(n);
+        self.pos += n as usize;
+        Ok(v)
+    }
+
+    pub(crate) fn align(&mut self) {
+        self.pos = (self.pos + 7) & !7;
+    }
+}
+
+pub(crate) enum Decoder {
+    Gzip(gzip::GzipDecoder),
+    #[cfg(feature = "zstd")]
+    Zstd(Box<zstd::ZstdDecoder>),
+}
+
+impl Decoder {
+    /// Decode whole units (headers, blocks, trailers) from `input` into `sink`.
+    /// Returns the input bytes consumed; stops early when the next unit is
+    /// incomplete, leaving `sink` at the end of the last complete unit.
+    pub(crate) fn decode<S: Sink>(&mut self, input: &[u8], sink: &mut S) -> DecodeResult<usize> {
+        match self {
+            Decoder::Gzip(d) => d.decode(input, sink),
+            #[cfg(feature = "zstd")]
+            Decoder::Zstd(d) => d.decode(input, sink),
+        }
+    }
+
+    /// Whether the input so far forms one or more complete members/frames.
+    pub(crate) fn at_boundary(&self) -> bool {
+        match self {
+            Decoder::Gzip(d) => d.at_boundary(),
+            #[cfg(feature = "zstd")]
+            Decoder::Zstd(d) => d.at_boundary(),
+        }
+    }
+
+    /// History the decoder may reference; a windowed sink must retain this much.
+    pub(crate) fn window(&self) -> usize {
+        match self {
+            Decoder::Gzip(_) => gzip::WINDOW,
+            #[cfg(feature = "zstd")]
+            Decoder::Zstd(d) => d.window(),
+        }
+    }
+}
+
+/// Decode a complete buffer into `out`. `size_hint` is what the container
+/// claims the output size is (gzip ISIZE, zstd content size); it lets an
+/// undersized call return the needed size without decoding. Returns the bytes
+/// written, or `Err(Ok(needed))` when `out` is too small.
+pub(crate) fn decompress_all(
+    make: fn() -> Decoder,
+    src: &[u8],
+    size_hint: Option<u64>,
+    out: &mut [u8],
+    max_output: usize,
+) -> Result<usize, Result<usize, DecodeError>> {
+    let cap = out.len().min(max_output);
+    if let Some(hint) = size_hint {
+        if hint > max_output as u64 {
+            return Err(Err(DecodeError::Overflow));
+        }
+        if hint > cap as u64 {
+            return Err(Ok(hint as usize));
+        }
+    }
+
+    let mut sink = SliceSink::new(&mut out[..cap]);
+    match finish_all(&mut make(), src, &mut sink) {
+        Ok(()) => Ok(sink.len()),
+        // No usable hint (or a lying one) and `out` too small: measure the real
+        // size, bounded by the hard cap
+        Err(DecodeError::Overflow) if cap < max_output => {
+            let mut sink = WindowSink::new(max_output);
+            match finish_all(&mut make(), src, &mut sink) {
+                Ok(()) => Err(Ok(sink.len())),
+                Err(e) => Err(Err(e)),
+            }
+        }
+        Err(e) => Err(Err(e)),
+    }
+}
+
+fn finish_all<S: Sink>(decoder: &mut Decoder, src: &[u8], sink: &mut S) -> DecodeResult<()> {
+    let consumed = decoder.decode(src, sink)?;
+    if consumed != src.len() || !decoder.at_boundary() {
+        return Err(DecodeError::NeedInput);
+    }
+    Ok(())
+}
+
+/// Incremental decoder behind the create/feed/read/finish FFI handle.
+pub struct DecompressStream {
+    decoder: Decoder,
+    input: Vec<u8>,
+    sink: WindowSink,
+    failed: Option<DecodeError>,
+}
+
+impl DecompressStream {
+    pub(crate) fn new(decoder: Decoder, max_output: usize) -> Self {
+        DecompressStream { decoder, input: Vec::new(), sink: WindowSink::new(max_output), failed: None }
+    }
+
+    /// Buffer `chunk` and decode every unit it completes. Returns the number
+    /// of decoded bytes waiting to be read.
+    pub(crate) fn feed(&mut self, chunk: &[u8]) -> DecodeResult<usize> {
+        if let Some(e) = self.failed {
+            return Err(e);
+        }
+        self.input.extend_from_slice(chunk);
+        match self.decoder.decode(&self.input, &mut self.sink) {
+            Ok(consumed) => {
+                self.input.drain(..consumed);
+                Ok(self.sink.available())
+            }
+            Err(e) => {
+                self.failed = Some(e);
+                Err(e)
+            }
+        }
+    }
+
+    pub(crate) fn read(&mut self, out: &mut [u8]) -> usize {
+        let window = self.decoder.window();
+        self.sink.read_into(out, window)
+    }
+
+    /// Ok when everything fed so far forms complete members/frames.
+    pub(crate) fn finish(&self) -> DecodeResult<()> {
+        if let Some(e) = self.failed {
+            return Err(e);
+        }
+        if !self.input.is_empty() || !self.decoder.at_boundary() {
+            return Err(DecodeError::NeedInput);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,742 @@
+// Zstandard (RFC 8878) frame decoder. No dictionary support.
+
+use super::{BitReader, DecodeError, DecodeResult, Sink};
+use crate::xxh64::Xxh64;
+
+const FRAME_MAGIC: u32 = 0xFD2F_B528;
+const SKIPPABLE_MAGIC: u32 = 0x184D_2A50;
+const SKIPPABLE_MASK: u32 = 0xFFFF_FFF0;
+const MAX_BLOCK: usize = 128 << 10;
+/// Same ceiling as the reference decoder's default (windowLog 27)
+const MAX_WINDOW: u64 = 1 << 27;
+
+const LL_BASE: [u32; 36] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 18, 20, 22, 24, 28, 32, 40, 48, 64, 128, 256, 512, 1024,
+    2048, 4096, 8192, 16384, 32768, 65536,
+];
+const LL_BITS: [u8; 36] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 3, 3, 4, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16,
+];
+const ML_BASE: [u32; 53] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33,
+    34, 35, 37, 39, 41, 43, 47, 51, 59, 67, 83, 99, 131, 259, 515, 1027, 2051, 4099, 8195, 16387, 32771, 65539,
+];
+const ML_BITS: [u8; 53] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 3,
+    3, 4, 4, 5, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16,
+];
+
+const LL_DEFAULT: [i16; 36] = [
+    4, 3, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 3, 2, 1, 1, 1, 1, 1, -1, -1, -1, -1,
+];
+const ML_DEFAULT: [i16; 53] = [
+    1, 4, 3, 2, 2, 2, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+    1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1, -1, -1, -1,
+];
+const OF_DEFAULT: [i16; 29] = [
+    1, 1, 1, 1, 1, 1, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1,
+];
+
+// --- Bitstreams ---
+
+/// Backward bitstream: starts after the highest set bit of the last byte and
+/// reads toward the first byte. Reads past the start yield zero bits and drive
+/// `pos` negative, which callers treat as overflow.
+struct BackBits<'a> {
+    data: &'a [u8],
+    pos: isize,
+}
+
+impl<'a> BackBits<'a> {
+    fn new(data: &'a [u8]) -> DecodeResult<Self> {
+        match data.last() {
+            Some(&last) if last != 0 => Ok(BackBits {
+                data,
+                pos: (data.len() * 8) as isize - last.leading_zeros() as isize - 1,
+            }),
+            _ => Err(DecodeError::Corrupt),
+        }
+    }
+
+    /// Bits [start, start + n) as an integer, zero outside the stream
+    fn bits_at(&self, start: isize, n: u32) -> u64 {
+        if n == 0 {
+            return 0;
+        }
+        if start < 0 {
+            let avail = start + n as isize;
+            return if avail <= 0 { 0 } else { self.bits_at(0, avail as u32) << -start };
+        }
+        let byte = (start >> 3) as usize;
+        let mut word = 0u64;
+        for (i, &b) in self.data[byte..].iter().take(8).enumerate() {
+            word |= (b as u64) << (8 * i);
+        }
+        (word >> (start & 7)) & ((1u64 << n) - 1)
+    }
+
+    fn peek(&self, n: u32) -> u64 {
+        self.bits_at(self.pos - n as isize, n)
+    }
+
+    fn read(&mut self, n: u32) -> u64 {
+        self.pos -= n as isize;
+        self.bits_at(self.pos, n)
+    }
+}
+
+// --- FSE tables ---
+
+#[derive(Clone, Copy, Default)]
+struct FseEntry {
+    symbol: u8,
+    bits: u8,
+    base: u16,
+}
+
+#[derive(Clone)]
+struct Fse {
+    log: u32,
+    entries: Vec<FseEntry>,
+}
+
+impl Fse {
+    fn rle(symbol: u8) -> Self {
+        Fse { log: 0, entries: vec![FseEntry { symbol, bits: 0, base: 0 }] }
+    }
+
+    fn from_counts(norm: &[i16], log: u32) -> DecodeResult<Self> {
+        let size = 1usize << log;
+        let mut entries = vec![FseEntry::default(); size];
+        let mut next = vec![0u32; norm.len()];
+        let mut high = size;
+        for (s, &c) in norm.iter().enumerate() {
+            if c == -1 {
+                high = high.checked_sub(1).ok_or(DecodeError::Corrupt)?;
+                entries[high].symbol = s as u8;
+                next[s] = 1;
+            } else {
+                next[s] = c.max(0) as u32;
+            }
+        }
+        let step = (size >> 1) + (size >> 3) + 3;
+        let mask = size - 1;
+        let mut pos = 0;
+        for (s, &c) in norm.iter().enumerate() {
+            for _ in 0..c.max(0) {
+                entries[pos].symbol = s as u8;
+                pos = (pos + step) & mask;
+                while pos >= high {
+                    pos = (pos + step) & mask;
+                }
+            }
+        }
+        if pos != 0 {
+            return Err(DecodeError::Corrupt);
+        }
+        for e in entries.iter_mut() {
+            let state = next[e.symbol as usize];
+            next[e.symbol as usize] += 1;
+            let bits = log - (31 - state.leading_zeros());
+            e.bits = bits as u8;
+            e.base = ((state << bits) as usize - size) as u16;
+        }
+        Ok(Fse { log, entries })
+    }
+
+    /// Parse a normalized-count table header; returns the table and the bytes used.
+    fn read(data: &[u8], max_symbol: usize, max_log: u32) -> DecodeResult<(Self, usize)> {
+        let mut br = BitReader::new(data, 0);
+        let fail = |_| DecodeError::Corrupt;
+        let log = br.bits(4).map_err(fail)? + 5;
+        if log > max_log {
+            return Err(DecodeError::Corrupt);
+        }
+        let mut remaining = (1i32 << log) + 1;
+        let mut threshold = 1i32 << log;
+        let mut nbits = log + 1;
+        let mut norm: Vec<i16> = Vec::new();
+        while remaining > 1 {
+            if norm.len() > max_symbol {
+                return Err(DecodeError::Corrupt);
+            }
+            // Small values take one bit less than large ones
+            let max = 2 * threshold - 1 - remaining;
+            let low = br.peek(nbits - 1) as i32;
+            let value = if low < max {
+                br.bits(nbits - 1).map_err(fail)?;
+                low
+            } else {
+                let mut v = br.bits(nbits).map_err(fail)? as i32;
+                if v >= threshold {
+                    v -= max;
+                }
+                v
+            };
+            let count = value - 1;
+            remaining -= count.abs();
+            norm.push(count as i16);
+            if count == 0 {
+                // 2-bit repeat flags; 3 means "three more zeros, keep reading"
+                loop {
+                    let repeat = br.bits(2).map_err(fail)?;
+                    norm.extend(std::iter::repeat_n(0, repeat as usize));
+                    if repeat != 3 {
+                        break;
+                    }
+                }
+            }
+            while remaining < threshold {
+                nbits -= 1;
+                threshold >>= 1;
+            }
+        }
+        if remaining != 1 || norm.len() > max_symbol + 1 {
+            return Err(DecodeError::Corrupt);
+        }
+        Ok((Fse::from_counts(&norm, log)?, br.pos.div_ceil(8)))
+    }
+}
+
+struct FseState {
+    state: usize,
+}
+
+impl FseState {
+    fn new(table: &Fse, br: &mut BackBits) -> Self {
+        FseState { state: br.read(table.log) as usize }
+    }
+
+    fn symbol(&self, table: &Fse) -> u8 {
+        table.entries[self.state].symbol
+    }
+
+    fn update(&mut self, table: &Fse, br: &mut BackBits) {
+        let e = table.entries[self.state];
+        self.state = e.base as usize + br.read(e.bits as u32) as usize;
+    }
+}
+
+// --- Huffman literals ---
+
+#[derive(Clone)]
+struct HufTable {
+    max_bits: u32,
+    /// (symbol, code length) indexed by the next max_bits stream bits
+    entries: Vec<(u8, u8)>,
+}
+
+impl HufTable {
+    /// Parse a Huffman tree description; returns the table and bytes used.
+    fn read(data: &[u8]) -> DecodeResult<(Self, usize)> {
+        let header = *data.first().ok_or(DecodeError::Corrupt)? as usize;
+        let (mut weights, used) = if header >= 128 {
+            // Direct: 4-bit weights, two per byte, high nibble first
+            let n = header - 127;
+            let bytes = data.get(1..1 + n.div_ceil(2)).ok_or(DecodeError::Corrupt)?;
+            let weights = (0..n).map(|i| if i % 2 == 0 { bytes[i / 2] >> 4 } else { bytes[i / 2] & 15 }).collect();
+            (weights, 1 + bytes.len())
+        } else {
+            let body = data.get(1..1 + header).ok_or(DecodeError::Corrupt)?;
+            (Self::fse_weights(body)?, 1 + header)
+        };
+
+        // The last weight is implied by completing the sum to a power of two
+        let sum: u32 = weights.iter().filter(|&&w| w > 0).map(|&w| 1u32 << (w - 1)).sum();
+        if sum == 0 {
+            return Err(DecodeError::Corrupt);
+        }
+        let max_bits = 32 - sum.leading_zeros();
+        let rest = (1u32 << max_bits) - sum;
+        if max_bits > 11 || !rest.is_power_of_two() {
+            return Err(DecodeError::Corrupt);
+        }
+        weights.push(rest.trailing_zeros() as u8 + 1);
+
+        let mut rank_start = [0usize; 13];
+        let mut next = 0;
+        for (w, start) in rank_start.iter_mut().enumerate().take(max_bits as usize + 1).skip(1) {
+            *start = next;
+            next += weights.iter().filter(|&&x| x as usize == w).count() << (w - 1);
+        }
+        let mut entries = vec![(0u8, 0u8); 1 << max_bits];
+        for (sym, &w) in weights.iter().enumerate() {
+            if w == 0 {
+                continue;
+            }
+            let w = w as usize;
+            if w > max_bits as usize {
+                return Err(DecodeError::Corrupt);
+            }
+            let len = 1 << (w - 1);
+            let code_len = (max_bits as usize + 1 - w) as u8;
+            entries[rank_start[w]..rank_start[w] + len].fill((sym as u8, code_len));
+            rank_start[w] += len;
+        }
+        Ok((HufTable { max_bits, entries }, used))
+    }
+
+    /// Weights compressed with an FSE table of accuracy <= 6, decoded with
+    /// two interleaved states until the bitstream runs out.
+    fn fse_weights(body: &[u8]) -> DecodeResult<Vec<u8>> {
+        let (table, used) = Fse::read(body, 255, 6)?;
+        let mut br = BackBits::new(&body[used..])?;
+        let mut states = [FseState::new(&table, &mut br), FseState::new(&table, &mut br)];
+        let mut weights = Vec::new();
+        let mut turn = 0;
+        loop {
+            weights.push(states[turn].symbol(&table));
+            states[turn].update(&table, &mut br);
+            if br.pos < 0 {
+                weights.push(states[turn ^ 1].symbol(&table));
+                break;
+            }
+            if weights.len() > 255 {
+                return Err(DecodeError::Corrupt);
+            }
+            turn ^= 1;
+        }
+        if weights.len() > 255 {
+            return Err(DecodeError::Corrupt);
+        }
+        Ok(weights)
+    }
+
+    fn decode_stream(&self, data: &[u8], count: usize, out: &mut Vec<u8>) -> DecodeResult<()> {
+        let mut br = BackBits::new(data)?;
+        for _ in 0..count {
+            let (symbol, len) = self.entries[br.peek(self.max_bits) as usize];
+            br.pos -= len as isize;
+            if br.pos < 0 {
+                return Err(DecodeError::Corrupt);
+            }
+            out.push(symbol);
+        }
+        if br.pos != 0 {
+            return Err(DecodeError::Corrupt);
+        }
+        Ok(())
+    }
+}
+
+// --- Frames and blocks ---
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    FrameHeader,
+    Blocks,
+    Checksum,
+    Skip(u64),
+}
+
+pub(crate) struct ZstdDecoder {
+    state: State,
+    frames: u64,
+    window: usize,
+    frame_start: usize,
+    content_size: Option<u64>,
+    checksum: bool,
+    hasher: Xxh64,
+    reps: [usize; 3],
+    huffman: Option<HufTable>,
+    ll: Option<Fse>,
+    of: Option<Fse>,
+    ml: Option<Fse>,
+    literals: Vec<u8>,
+}
+
+struct FrameHeader {
+    len: usize,
+    window: u64,
+    content_size: Option<u64>,
+    checksum: bool,
+}
+
+fn le(bytes: &[u8]) -> u64 {
+    bytes.iter().rev().fold(0, |acc, &b| (acc << 8) | b as u64)
+}
+
+/// Parse the frame header after the magic number, or `NeedInput`.
+fn frame_header(data: &[u8]) -> DecodeResult<FrameHeader> {
+    let desc = *data.first().ok_or(DecodeError::NeedInput)?;
+    let fcs_flag = desc >> 6;
+    let single_segment = desc & 0x20 != 0;
+    if desc & 0x08 != 0 {
+        return Err(DecodeError::Corrupt);
+    }
+    let did_size = [0, 1, 2, 4][(desc & 3) as usize];
+    let fcs_size = match fcs_flag {
+        0 => single_segment as usize,
+        1 => 2,
+        2 => 4,
+        _ => 8,
+    };
+    let len = 1 + !single_segment as usize + did_size + fcs_size;
+    if data.len() < len {
+        return Err(DecodeError::NeedInput);
+    }
+    let mut pos = 1;
+    let mut window = 0;
+    if !single_segment {
+        let b = data[pos];
+        let base = 1u64 << (10 + (b >> 3));
+        window = base + (base / 8) * (b & 7) as u64;
+        pos += 1;
+    }
+    if le(&data[pos..pos + did_size]) != 0 {
+        return Err(DecodeError::Unsupported);
+    }
+    pos += did_size;
+    let content_size = match fcs_size {
+        0 => None,
+        2 => Some(le(&data[pos..pos + 2]) + 256),
+        n => Some(le(&data[pos..pos + n])),
+    };
+    if single_segment {
+        window = content_size.unwrap_or(0);
+    }
+    if window > MAX_WINDOW {
+        return Err(DecodeError::Unsupported);
+    }
+    Ok(FrameHeader { len, window, content_size, checksum: desc & 0x04 != 0 })
+}
+
+/// Content size declared by the first frame (later frames, if any, add to it).
+pub(crate) fn size_hint(src: &[u8]) -> Option<u64> {
+    if src.len() < 4 || le(&src[..4]) != FRAME_MAGIC as u64 {
+        return None;
+    }
+    let header = frame_header(&src[4..]).ok()?;
+    header.content_size
+}
+
+impl ZstdDecoder {
+    pub(crate) fn new() -> Self {
+        ZstdDecoder {
+            state: State::FrameHeader,
+            frames: 0,
+            window: 0,
+            frame_start: 0,
+            content_size: None,
+            checksum: false,
+            hasher: Xxh64::new(),
+            reps: [1, 4, 8],
+            huffman: None,
+            ll: None,
+            of: None,
+            ml: None,
+            literals: Vec::new(),
+        }
+    }
+
+    pub(crate) fn at_boundary(&self) -> bool {
+        self.state == State::FrameHeader && self.frames > 0
+    }
+
+    pub(crate) fn window(&self) -> usize {
+        self.window.max(MAX_BLOCK)
+    }
+
+    pub(crate) fn decode<S: Sink>(&mut self, input: &[u8], sink: &mut S) -> DecodeResult<usize> {
+        let mut pos = 0;
+        loop {
+            let rest = &input[pos..];
+            match self.state {
+                State::FrameHeader => {
+                    if rest.len() < 4 {
+                        return Ok(pos);
+                    }
+                    let magic = le(&rest[..4]) as u32;
+                    if magic & SKIPPABLE_MASK == SKIPPABLE_MAGIC {
+                        if rest.len() < 8 {
+                            return Ok(pos);
+                        }
+                        self.state = State::Skip(le(&rest[4..8]));
+                        pos += 8;
+                        continue;
+                    }
+                    if magic != FRAME_MAGIC {
+                        return Err(DecodeError::Corrupt);
+                    }
+                    let header = match frame_header(&rest[4..]) {
+                        Ok(h) => h,
+                        Err(DecodeError::NeedInput) => return Ok(pos),
+                        Err(e) => return Err(e),
+                    };
+                    pos += 4 + header.len;
+                    self.window = header.window as usize;
+                    self.content_size = header.content_size;
+                    self.checksum = header.checksum;
+                    self.frame_start = sink.len();
+                    self.hasher = Xxh64::new();
+                    self.reps = [1, 4, 8];
+                    self.huffman = None;
+                    self.ll = None;
+                    self.of = None;
+                    self.ml = None;
+                    self.state = State::Blocks;
+                }
+                State::Blocks => {
+                    if rest.len() < 3 {
+                        return Ok(pos);
+                    }
+                    let header = le(&rest[..3]) as usize;
+                    let last = header & 1 != 0;
+                    let size = header >> 3;
+                    let block_max = self.window.clamp(1, MAX_BLOCK);
+                    let body_len = match (header >> 1) & 3 {
+                        0 | 2 => size,
+                        1 => 1,
+                        _ => return Err(DecodeError::Corrupt),
+                    };
+                    if size > block_max {
+                        return Err(DecodeError::Corrupt);
+                    }
+                    let Some(body) = rest.get(3..3 + body_len) else {
+                        return Ok(pos);
+                    };
+                    let start = sink.len();
+                    match (header >> 1) & 3 {
+                        0 => sink.extend(body)?,
+                        1 => sink.fill(body[0], size)?,
+                        _ => self.decode_compressed(body, sink)?,
+                    }
+                    if sink.len() - start > block_max {
+                        return Err(DecodeError::Corrupt);
+                    }
+                    if self.checksum {
+                        self.hasher.update(sink.tail(start));
+                    }
+                    pos += 3 + body_len;
+                    if last {
+                        if let Some(expected) = self.content_size {
+                            if (sink.len() - self.frame_start) as u64 != expected {
+                                return Err(DecodeError::Corrupt);
+                            }
+                        }
+                        if self.checksum {
+                            self.state = State::Checksum;
+                        } else {
+                            self.end_frame();
+                        }
+                    }
+                }
+                State::Checksum => {
+                    if rest.len() < 4 {
+                        return Ok(pos);
+                    }
+                    if le(&rest[..4]) as u32 != self.hasher.digest() as u32 {
+                        return Err(DecodeError::Checksum);
+                    }
+                    pos += 4;
+                    self.end_frame();
+                }
+                State::Skip(remaining) => {
+                    let n = remaining.min(rest.len() as u64);
+                    pos += n as usize;
+                    if n < remaining {
+                        self.state = State::Skip(remaining - n);
+                        return Ok(pos);
+                    }
+                    self.end_frame();
+                }
+            }
+        }
+    }
+
+    fn end_frame(&mut self) {
+        self.frames += 1;
+        self.state = State::FrameHeader;
+    }
+
+    fn decode_compressed<S: Sink>(&mut self, block: &[u8], sink: &mut S) -> DecodeResult<()> {
+        let used = self.decode_literals(block)?;
+        self.decode_sequences(&block[used..], sink)
+    }
+
+    /// Fill `self.literals`; returns the bytes of `block` the section used.
+    fn decode_literals(&mut self, block: &[u8]) -> DecodeResult<usize> {
+        let corrupt = DecodeError::Corrupt;
+        let b0 = *block.first().ok_or(corrupt)?;
+        let size_format = (b0 >> 2) & 3;
+        self.literals.clear();
+        match b0 & 3 {
+            kind @ (0 | 1) => {
+                let (regen, header) = match size_format {
+                    0 | 2 => ((b0 >> 3) as usize, 1),
+                    1 => (le(block.get(..2).ok_or(corrupt)?) as usize >> 4, 2),
+                    _ => (le(block.get(..3).ok_or(corrupt)?) as usize >> 4, 3),
+                };
+                if regen > MAX_BLOCK {
+                    return Err(corrupt);
+                }
+                if kind == 0 {
+                    self.literals.extend_from_slice(block.get(header..header + regen).ok_or(corrupt)?);
+                    Ok(header + regen)
+                } else {
+                    let byte = *block.get(header).ok_or(corrupt)?;
+                    self.literals.resize(regen, byte);
+                    Ok(header + 1)
+                }
+            }
+            kind => {
+                let (header, streams, field_bits) = match size_format {
+                    0 => (3, 1, 10),
+                    1 => (3, 4, 10),
+                    2 => (4, 4, 14),
+                    _ => (5, 4, 18),
+                };
+                let h = le(block.get(..header).ok_or(corrupt)?) >> 4;
+                let mask = (1u64 << field_bits) - 1;
+                let regen = (h & mask) as usize;
+                let compressed = ((h >> field_bits) & mask) as usize;
+                if regen > MAX_BLOCK {
+                    return Err(corrupt);
+                }
+                let mut body = block.get(header..header + compressed).ok_or(corrupt)?;
+                if kind == 2 {
+                    let (table, used) = HufTable::read(body)?;
+                    self.huffman = Some(table);
+                    body = &body[used..];
+                }
+                let table = self.huffman.as_ref().ok_or(corrupt)?;
+                if streams == 1 {
+                    table.decode_stream(body, regen, &mut self.literals)?;
+                } else {
+                    if body.len() < 6 {
+                        return Err(corrupt);
+                    }
+                    let sizes = [le(&body[0..2]) as usize, le(&body[2..4]) as usize, le(&body[4..6]) as usize];
+                    let segment = regen.div_ceil(4);
+                    let last = regen.checked_sub(3 * segment).ok_or(corrupt)?;
+                    let mut data = &body[6..];
+                    for (i, count) in [segment, segment, segment, last].into_iter().enumerate() {
+                        let len = if i < 3 { sizes[i] } else { data.len() };
+                        let stream = data.get(..len).ok_or(corrupt)?;
+                        table.decode_stream(stream, count, &mut self.literals)?;
+                        data = &data[len..];
+                    }
+                }
+                Ok(header + compressed)
+            }
+        }
+    }
+
+    fn decode_sequences<S: Sink>(&mut self, data: &[u8], sink: &mut S) -> DecodeResult<()> {
+        let corrupt = DecodeError::Corrupt;
+        let b0 = *data.first().ok_or(corrupt)? as usize;
+        let (count, mut pos) = match b0 {
+            0..=127 => (b0, 1),
+            128..=254 => (((b0 - 128) << 8) + *data.get(1).ok_or(corrupt)? as usize, 2),
+            _ => (le(data.get(1..3).ok_or(corrupt)?) as usize + 0x7F00, 3),
+        };
+        let mut lit_pos = 0;
+        if count > 0 {
+            let modes = *data.get(pos).ok_or(corrupt)?;
+            if modes & 3 != 0 {
+                return Err(corrupt);
+            }
+            pos += 1;
+            let ll = Self::table(data, &mut pos, modes >> 6, &mut self.ll, &LL_DEFAULT, 6, 35, 9)?;
+            let of = Self::table(data, &mut pos, (modes >> 4) & 3, &mut self.of, &OF_DEFAULT, 5, 31, 8)?;
+            let ml = Self::table(data, &mut pos, (modes >> 2) & 3, &mut self.ml, &ML_DEFAULT, 6, 52, 9)?;
+
+            let mut br = BackBits::new(&data[pos..])?;
+            let mut ll_state = FseState::new(&ll, &mut br);
+            let mut of_state = FseState::new(&of, &mut br);
+            let mut ml_state = FseState::new(&ml, &mut br);
+            for i in 0..count {
+                let of_code = of_state.symbol(&of) as u32;
+                let ml_code = ml_state.symbol(&ml) as usize;
+                let ll_code = ll_state.symbol(&ll) as usize;
+                if of_code > 31 || ml_code >= ML_BASE.len() || ll_code >= LL_BASE.len() {
+                    return Err(corrupt);
+                }
+                let of_value = (1u64 << of_code) + br.read(of_code);
+                let match_len = ML_BASE[ml_code] as usize + br.read(ML_BITS[ml_code] as u32) as usize;
+                let lit_len = LL_BASE[ll_code] as usize + br.read(LL_BITS[ll_code] as u32) as usize;
+                let offset = self.resolve_offset(of_value, lit_len)?;
+
+                let lits = self.literals.get(lit_pos..lit_pos + lit_len).ok_or(corrupt)?;
+                sink.extend(lits)?;
+                lit_pos += lit_len;
+                if offset > sink.len() - self.frame_start {
+                    return Err(corrupt);
+                }
+                sink.copy_match(offset, match_len)?;
+
+                if i + 1 < count {
+                    ll_state.update(&ll, &mut br);
+                    ml_state.update(&ml, &mut br);
+                    of_state.update(&of, &mut br);
+                }
+            }
+            if br.pos != 0 {
+                return Err(corrupt);
+            }
+        } else if pos != data.len() {
+            return Err(corrupt);
+        }
+        sink.extend(&self.literals[lit_pos..])
+    }
+
+    /// Resolve one of the four table modes: predefined, RLE, FSE-described or
+    /// repeat-previous. The chosen table becomes the "previous" one.
+    #[allow(clippy::too_many_arguments)]
+    fn table(
+        data: &[u8],
+        pos: &mut usize,
+        mode: u8,
+        previous: &mut Option<Fse>,
+        default: &[i16],
+        default_log: u32,
+        max_symbol: usize,
+        max_log: u32,
+    ) -> DecodeResult<Fse> {
+        let table = match mode {
+            0 => Fse::from_counts(default, default_log)?,
+            1 => {
+                let symbol = *data.get(*pos).ok_or(DecodeError::Corrupt)?;
+                if symbol as usize > max_symbol {
+                    return Err(DecodeError::Corrupt);
+                }
+                *pos += 1;
+                Fse::rle(symbol)
+            }
+            2 => {
+                let (table, used) = Fse::read(data.get(*pos..).ok_or(DecodeError::Corrupt)?, max_symbol, max_log)?;
+                *pos += used;
+                table
+            }
+            _ => return previous.clone().ok_or(DecodeError::Corrupt),
+        };
+        *previous = Some(table.clone());
+        Ok(table)
+    }
+
+    fn resolve_offset(&mut self, of_value: u64, lit_len: usize) -> DecodeResult<usize> {
+        let reps = self.reps;
+        if of_value > 3 {
+            let offset = (of_value - 3) as usize;
+            self.reps = [offset, reps[0], reps[1]];
+            return Ok(offset);
+        }
+        // With no literals the repeat codes shift by one
+        let idx = of_value as usize - 1 + (lit_len == 0) as usize;
+        let offset = match idx {
+            0 => reps[0],
+            1 => reps[1],
+            2 => reps[2],
+            _ => reps[0].wrapping_sub(1),
+        };
+        if offset == 0 {
+            return Err(DecodeError::Corrupt);
+        }
+        match idx {
+            0 => {}
+            1 => self.reps = [offset, reps[0], reps[2]],
+            _ => self.reps = [offset, reps[0], reps[1]],
+        }
+        Ok(offset)
+    }
+}
@@ -0,0 +1,195 @@
+// External sort for f64 columns bigger than the memory a sort may take, such
+// as a file mapped into memory. Runs of `mem_budget` bytes are sorted where
+// they lie with the in-place comparison sort (the radix sort's scratch copy
+// is what would not fit), each written to a temporary file in the scratch
+// directory, and a k-way merge streams them back over the caller's buffer.
+// A merge opens at most `fan_in` runs, so more runs than that are first
+// merged in passes into fewer, longer run files. Every merge's buffers share
+// the budget. Run files are removed on every exit path.
+
+use crate::kernels;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Read buffer a merge aims to give each run; smaller budgets merge fewer
+/// runs at once rather than shrink it, down to two
+const READ_BUFFER: usize = 64 << 10;
+
+/// Most runs one merge opens, well under the usual limit of 1024 open files
+const MAX_FAN_IN: usize = 256;
+
+/// Values converted per write
+const WRITE_CHUNK: usize = 8 << 10;
+
+#[derive(Debug)]
+pub(crate) enum SpillError {
+    /// Fewer than 8 bytes: not even one value per run
+    Budget,
+    /// A run file could not be created in the scratch directory
+    Create(PathBuf, io::Error),
+    /// Writing or reading a run file failed; `StorageFull` when the disk is
+    Io(PathBuf, io::Error),
+}
+
+/// Distinguishes the run files of concurrent sorts in one process
+static NEXT_SORT: AtomicU64 = AtomicU64::new(0);
+
+/// Run files of one sort, removed when dropped.
+struct RunFiles(Vec<PathBuf>);
+
+impl Drop for RunFiles {
+    fn drop(&mut self) {
+        for path in &self.0 {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Sort `data` in `f64::total_cmp` order, the order `sort_f64` gives,
+/// touching at most `mem_budget` bytes of it at a time. Data within the
+/// budget is sorted in place with no files. An error while spilling or in an
+/// intermediate merge pass leaves every value in `data`, reordered; one
+/// during the final merge leaves its contents unspecified.
+pub(crate) fn sort_f64_external(data: &mut [f64], scratch_dir: &Path, mem_budget: usize) -> Result<(), SpillError> {
+    let run_len = mem_budget / 8;
+    if run_len == 0 {
+        return Err(SpillError::Budget);
+    }
+    if data.len() <= run_len {
+        kernels::sort_f64_unstable(data);
+        return Ok(());
+    }
+    let sort = NEXT_SORT.fetch_add(1, Ordering::Relaxed);
+    let mut files = RunFiles(Vec::new());
+    let new_run = |files: &mut RunFiles| {
+        let path = scratch_dir.join(format!("tova-sort-{}-{}-{}.run", std::process::id(), sort, files.0.len()));
+        let file = File::create(&path).map_err(|e| SpillError::Create(path.clone(), e))?;
+        files.0.push(path.clone());
+        Ok((path, file))
+    };
+    let mut runs = Vec::new();
+    for run in data.chunks_mut(run_len) {
+        kernels::sort_f64_unstable(run);
+        let (path, file) = new_run(&mut files)?;
+        write_run(file, run).map_err(|e| SpillError::Io(path.clone(), e))?;
+        runs.push(path);
+    }
+    let fan_in = fan_in(mem_budget);
+    while runs.len() > fan_in {
+        let mut merged = Vec::with_capacity(runs.len().div_ceil(fan_in));
+        for group in runs.chunks(fan_in) {
+            let (path, file) = new_run(&mut files)?;
+            // The output's write buffer takes a share of the budget too
+            merge_to_file(group, &path, file, mem_budget / (group.len() + 1))?;
+            // Frees the disk space as it goes; `files` still removes them on error
+            for done in group {
+                let _ = fs::remove_file(done);
+            }
+            merged.push(path);
+        }
+        runs = merged;
+    }
+    merge_into(&runs, data, mem_budget / runs.len())
+}
+
+/// Runs one merge reads at once: as many as get a `READ_BUFFER` each within
+/// the budget, between two and `MAX_FAN_IN`.
+fn fan_in(mem_budget: usize) -> usize {
+    (mem_budget / READ_BUFFER).clamp(2, MAX_FAN_IN)
+}
+
+pub(crate) fn write_run(file: File, run: &[f64]) -> io::Result<()> {
+    let mut out = BufWriter::new(file);
+    let mut bytes = Vec::with_capacity(WRITE_CHUNK * 8);
+    for chunk in run.chunks(WRITE_CHUNK) {
+        bytes.clear();
+        bytes.extend(chunk.iter().flat_map(|v| v.to_ne_bytes()));
+        out.write_all(&bytes)?;
+    }
+    // Surfaces a full disk here rather than when the file is dropped
+    out.into_inner().map_err(|e| e.into_error())?.sync_all()
+}
+
+/// One sorted run file, read a value at a time.
+struct Run {
+    path: PathBuf,
+    reader: BufReader<File>,
+}
+
+impl Run {
+    fn next(&mut self) -> Result<Option<f64>, SpillError> {
+        let mut bytes = [0u8; 8];
+        match self.reader.read_exact(&mut bytes) {
+            Ok(()) => Ok(Some(f64::from_ne_bytes(bytes))),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(SpillError::Io(self.path.clone(), e)),
+        }
+    }
+}
+
+/// K-way merge over open run files, yielding values in `total_cmp` order.
+struct Merge {
+    runs: Vec<Run>,
+    // Keyed by the radix sort's order key, whose unsigned order is total_cmp's
+    heap: BinaryHeap<Reverse<(u64, usize)>>,
+}
+
+impl Merge {
+    /// Open every run with a `buffer`-byte read buffer.
+    fn open(paths: &[PathBuf], buffer: usize) -> Result<Self, SpillError> {
+        let mut runs = Vec::with_capacity(paths.len());
+        for path in paths {
+            let file = File::open(path).map_err(|e| SpillError::Io(path.clone(), e))?;
+            runs.push(Run { path: path.clone(), reader: BufReader::with_capacity(buffer, file) });
+        }
+        let mut heap = BinaryHeap::with_capacity(runs.len());
+        for (i, run) in runs.iter_mut().enumerate() {
+            if let Some(v) = run.next()? {
+                heap.push(Reverse((kernels::f64_order_key(v), i)));
+            }
+        }
+        Ok(Merge { runs, heap })
+    }
+
+    /// The next value, with the index of the run it came from
+    fn next(&mut self) -> Result<Option<(f64, usize)>, SpillError> {
+        let Some(Reverse((key, i))) = self.heap.pop() else {
+            return Ok(None);
+        };
+        if let Some(v) = self.runs[i].next()? {
+            self.heap.push(Reverse((kernels::f64_order_key(v), i)));
+        }
+        Ok(Some((f64::from_bits(kernels::order_key_bits(key)), i)))
+    }
+}
+
+/// Merge the sorted run files into one longer run in `file`.
+fn merge_to_file(paths: &[PathBuf], path: &Path, file: File, buffer: usize) -> Result<(), SpillError> {
+    let mut merge = Merge::open(paths, buffer)?;
+    let mut out = BufWriter::with_capacity(buffer, file);
+    let io_err = |e| SpillError::Io(path.to_path_buf(), e);
+    while let Some((v, _)) = merge.next()? {
+        out.write_all(&v.to_ne_bytes()).map_err(io_err)?;
+    }
+    out.into_inner().map_err(|e| io_err(e.into_error()))?.sync_all().map_err(io_err)
+}
+
+/// Merge the sorted run files into `out`, which has room for all of them.
+fn merge_into(paths: &[PathBuf], out: &mut [f64], buffer: usize) -> Result<(), SpillError> {
+    let mut merge = Merge::open(paths, buffer)?;
+    for slot in out.iter_mut() {
+        let Some((v, _)) = merge.next()? else {
+            return Err(SpillError::Io(paths[0].clone(), io::Error::new(io::ErrorKind::UnexpectedEof, "run files ended early")));
+        };
+        *slot = v;
+    }
+    // Only a run file changed behind our back can hold more values
+    if let Some((_, i)) = merge.next()? {
+        return Err(SpillError::Io(paths[i].clone(), io::Error::new(io::ErrorKind::InvalidData, "run file grew")));
+    }
+    Ok(())
+}
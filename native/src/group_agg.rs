@@ -0,0 +1,236 @@
+// Group-by aggregation carried across calls, for data that arrives in row
+// groups: each chunk updates the handle's running state per key, handles fed
+// by different threads merge into one, and finalizing emits the groups in
+// ascending key order. Merging combines whole group states, so splitting the
+// rows between handles changes nothing but the order sums are added in.
+//
+// Open addressing with linear probing over a power-of-two table, as in
+// hash_join, with each group's state in parallel columns. The table doubles
+// when it passes half full, so it rehashes once per doubling of the group
+// count however many updates feed it, and never shrinks in between; a caller
+// that knows roughly how many groups to expect can reserve them up front.
+
+use crate::kernels;
+use crate::xxh64;
+
+/// Slots in the smallest table
+const MIN_SLOTS: usize = 16;
+
+/// What each group reports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GroupOp {
+    /// Compensated sum of the values; NaN once any is NaN
+    Sum,
+    /// Smallest non-NaN value, NaN when there is none
+    Min,
+    /// Largest non-NaN value, NaN when there is none
+    Max,
+    /// Rows, as an f64
+    Count,
+    /// Sum / rows
+    Mean,
+}
+
+pub struct GroupAgg {
+    op: GroupOp,
+    /// The key of each occupied slot
+    keys: Vec<i64>,
+    /// Rows seen per slot, 0 for a free slot
+    counts: Vec<u64>,
+    /// Running sum, or minimum or maximum (NaN until a non-NaN value)
+    acc: Vec<f64>,
+    /// Kahan compensation of a running sum
+    comp: Vec<f64>,
+    groups: usize,
+    mask: usize,
+    /// Results were taken; the state is gone
+    finished: bool,
+}
+
+impl GroupAgg {
+    pub fn new(op: GroupOp) -> GroupAgg {
+        GroupAgg::with_slots(op, MIN_SLOTS)
+    }
+
+    fn with_slots(op: GroupOp, slots: usize) -> GroupAgg {
+        GroupAgg {
+            op,
+            keys: vec![0; slots],
+            counts: vec![0; slots],
+            acc: vec![empty_acc(op); slots],
+            comp: vec![0.0; slots],
+            groups: 0,
+            mask: slots - 1,
+            finished: false,
+        }
+    }
+
+    pub fn op(&self) -> GroupOp {
+        self.op
+    }
+
+    /// Distinct keys seen.
+    pub fn len(&self) -> usize {
+        self.groups
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups == 0
+    }
+
+    /// Fold `keys[i]` / `values[i]` into the groups. `values` is only read
+    /// for ops other than Count and then has the length of `keys`.
+    pub fn update(&mut self, keys: &[i64], values: &[f64]) {
+        for (i, &key) in keys.iter().enumerate() {
+            let slot = self.entry(key);
+            self.counts[slot] += 1;
+            if self.op != GroupOp::Count {
+                fold(self.op, &mut self.acc[slot], &mut self.comp[slot], values[i]);
+            }
+        }
+    }
+
+    /// Fold every group of `other` (which has the same op) into this one.
+    pub fn merge(&mut self, other: &GroupAgg) {
+        for slot in (0..other.slots()).filter(|&s| other.counts[s] > 0) {
+            let into = self.entry(other.keys[slot]);
+            self.counts[into] += other.counts[slot];
+            match self.op {
+                GroupOp::Count => {}
+                GroupOp::Min => self.acc[into] = kernels::fold_min_f64(self.acc[into], other.acc[slot]),
+                GroupOp::Max => self.acc[into] = kernels::fold_max_f64(self.acc[into], other.acc[slot]),
+                GroupOp::Sum | GroupOp::Mean => {
+                    add(&mut self.acc[into], &mut self.comp[into], other.acc[slot]);
+                    add(&mut self.acc[into], &mut self.comp[into], -other.comp[slot]);
+                }
+            }
+        }
+    }
+
+    /// Each group's key, result and row count, ascending by key.
+    pub fn results(&self) -> Vec<(i64, f64, u64)> {
+        let mut groups: Vec<(i64, f64, u64)> = (0..self.slots())
+            .filter(|&s| self.counts[s] > 0)
+            .map(|s| (self.keys[s], result(self.op, self.acc[s], self.comp[s], self.counts[s]), self.counts[s]))
+            .collect();
+        groups.sort_unstable_by_key(|g| g.0);
+        groups
+    }
+
+    /// Drop the state once its results are out; the handle only answers
+    /// `is_finished` after this.
+    pub fn finish(&mut self) {
+        *self = GroupAgg { finished: true, ..GroupAgg::new(self.op) };
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn slots(&self) -> usize {
+        self.mask + 1
+    }
+
+    /// The slot holding `key`, claiming a free one (and growing the table
+    /// past half full) if there is none.
+    fn entry(&mut self, key: i64) -> usize {
+        let mut slot = hash(key) as usize & self.mask;
+        while self.counts[slot] != 0 {
+            if self.keys[slot] == key {
+                return slot;
+            }
+            slot = (slot + 1) & self.mask;
+        }
+        if (self.groups + 1) * 2 > self.slots() {
+            self.rehash(self.slots() * 2);
+            return self.entry(key);
+        }
+        self.keys[slot] = key;
+        self.groups += 1;
+        slot
+    }
+
+    /// Make room for `groups` groups in all without growing again; false
+    /// if no table could be that large.
+    pub fn reserve(&mut self, groups: usize) -> bool {
+        let Some(slots) = groups.checked_mul(2).and_then(usize::checked_next_power_of_two) else {
+            return false;
+        };
+        if slots > self.slots() {
+            self.rehash(slots);
+        }
+        true
+    }
+
+    fn rehash(&mut self, slots: usize) {
+        let old = std::mem::replace(self, GroupAgg::with_slots(self.op, slots));
+        for s in (0..old.slots()).filter(|&s| old.counts[s] > 0) {
+            let mut slot = hash(old.keys[s]) as usize & self.mask;
+            while self.counts[slot] != 0 {
+                slot = (slot + 1) & self.mask;
+            }
+            self.keys[slot] = old.keys[s];
+            self.counts[slot] = old.counts[s];
+            self.acc[slot] = old.acc[s];
+            self.comp[slot] = old.comp[s];
+        }
+        self.groups = old.groups;
+    }
+}
+
+/// `GroupAgg::results` for rows whose keys arrive in ascending order, as
+/// time-sorted data does: each run of equal keys is one group, folded in
+/// place with no table and no sort. Same results as a GroupAgg fed the rows.
+pub fn sorted_results(op: GroupOp, keys: &[i64], values: &[f64]) -> Vec<(i64, f64, u64)> {
+    let mut groups = Vec::new();
+    let mut start = 0;
+    while start < keys.len() {
+        let key = keys[start];
+        let end = start + keys[start..].iter().take_while(|&&k| k == key).count();
+        let (mut acc, mut comp) = (empty_acc(op), 0.0);
+        if op != GroupOp::Count {
+            values[start..end].iter().for_each(|&v| fold(op, &mut acc, &mut comp, v));
+        }
+        let count = (end - start) as u64;
+        groups.push((key, result(op, acc, comp, count), count));
+        start = end;
+    }
+    groups
+}
+
+/// What `results` reports for a group of `count` rows; for no rows, 0 for
+/// Sum and Count and NaN for the rest.
+pub fn result(op: GroupOp, acc: f64, comp: f64, count: u64) -> f64 {
+    match op {
+        GroupOp::Count => count as f64,
+        GroupOp::Min | GroupOp::Max => acc,
+        GroupOp::Sum => acc - comp,
+        GroupOp::Mean => (acc - comp) / count as f64,
+    }
+}
+
+/// A group's accumulator before its first row.
+pub fn empty_acc(op: GroupOp) -> f64 {
+    if matches!(op, GroupOp::Min | GroupOp::Max) { f64::NAN } else { 0.0 }
+}
+
+/// Fold one value into a group's accumulator (any op but Count).
+fn fold(op: GroupOp, acc: &mut f64, comp: &mut f64, value: f64) {
+    match op {
+        GroupOp::Min => *acc = kernels::fold_min_f64(*acc, value),
+        GroupOp::Max => *acc = kernels::fold_max_f64(*acc, value),
+        _ => add(acc, comp, value),
+    }
+}
+
+/// Kahan step, as `kernels::sum_f64` takes.
+fn add(acc: &mut f64, comp: &mut f64, value: f64) {
+    let y = value - *comp;
+    let t = *acc + y;
+    *comp = (t - *acc) - y;
+    *acc = t;
+}
+
+fn hash(key: i64) -> u64 {
+    xxh64::avalanche(xxh64::round(0, key as u64))
+}
@@ -0,0 +1,87 @@
+// Hash table over the build side of an equi-join on i64 keys, probed with
+// the other side in whatever order it comes: a merge join needs both sides
+// sorted, and sorting a large probe side costs far more than hashing a small
+// build side.
+//
+// Open addressing with linear probing over a power-of-two table of distinct
+// keys, each slot holding its key and the first build row with it. Further
+// rows with the same key chain from that row through `next`, which has one
+// entry per build row, so duplicates never take a slot or lengthen a probe.
+// Keys hash with the XXH64 round and avalanche, as the profile sketches do.
+
+use crate::xxh64;
+
+/// A free slot's first row, and the end of a chain
+const EMPTY: u32 = u32::MAX;
+
+/// Slots in the smallest table
+const MIN_SLOTS: usize = 16;
+
+pub struct HashJoin {
+    /// The key of each occupied slot
+    keys: Vec<i64>,
+    /// Lowest build row with the slot's key, EMPTY for a free slot
+    first: Vec<u32>,
+    /// The next build row with the same key as this one, ascending
+    next: Vec<u32>,
+    mask: usize,
+}
+
+impl HashJoin {
+    /// Index `keys`, the build side. None past 2^32 - 1 rows, which u32 row
+    /// indices cannot name alongside EMPTY.
+    pub fn build(keys: &[i64]) -> Option<HashJoin> {
+        if keys.len() > EMPTY as usize {
+            return None;
+        }
+        // At most half full even if every key is distinct
+        let slots = (keys.len() * 2).next_power_of_two().max(MIN_SLOTS);
+        let mut join = HashJoin { keys: vec![0; slots], first: vec![EMPTY; slots], next: vec![EMPTY; keys.len()], mask: slots - 1 };
+        // Backwards, so that each chain lists its rows in ascending order
+        for (row, &key) in keys.iter().enumerate().rev() {
+            let slot = join.slot(key);
+            join.next[row] = join.first[slot];
+            join.keys[slot] = key;
+            join.first[slot] = row as u32;
+        }
+        Some(join)
+    }
+
+    /// Build rows indexed.
+    pub fn len(&self) -> usize {
+        self.next.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.next.is_empty()
+    }
+
+    /// Call `emit(probe_row, build_row)` for every pair of rows with equal
+    /// keys: probe rows in order, and each one's build rows ascending.
+    /// Returns how many pairs there were. `keys` has fewer than 2^32 rows.
+    pub fn probe(&self, keys: &[i64], mut emit: impl FnMut(u32, u32)) -> usize {
+        let mut pairs = 0;
+        for (probe_row, &key) in keys.iter().enumerate() {
+            let mut row = self.first[self.slot(key)];
+            while row != EMPTY {
+                emit(probe_row as u32, row);
+                pairs += 1;
+                row = self.next[row as usize];
+            }
+        }
+        pairs
+    }
+
+    /// The slot holding `key`, or the free slot where it would go.
+    fn slot(&self, key: i64) -> usize {
+        let mut slot = hash(key) as usize & self.mask;
+        while self.first[slot] != EMPTY && self.keys[slot] != key {
+            slot = (slot + 1) & self.mask;
+        }
+        slot
+    }
+}
+
+fn hash(key: i64) -> u64 {
+    xxh64::avalanche(xxh64::round(0, key as u64))
+}
@@ -0,0 +1,2932 @@
+// Safe kernels over slices. The `tova_*` exports in lib.rs validate and
+// convert their raw pointers, then call these; Rust callers (tova_runtime,
+// benches, property tests) use them directly.
+//
+// Functions writing a variable number of results into a caller-sized `out`
+// return the full count, writing only the first `out.len()` results, so the
+// exports can report "needed more room" without a second pass.
+
+use crate::xxh64;
+use std::cmp;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use crate::{TOVA_FIND_IGNORE_ASCII_CASE, TOVA_FIND_OVERLAPPING};
+
+// ============================================================
+// Sorting
+// ============================================================
+
+/// Default smallest input the f64 sorts hand to the radix sort; below it they
+/// use a std comparison sort. Each 16-bit radix pass clears and prefix-sums a
+/// 65536-entry histogram, a fixed ~100µs that only amortises in the tens of
+/// thousands of elements. Chosen from `cargo bench --bench kernels -- cutoff`,
+/// which times both around this size; rerun it when touching either path.
+pub const RADIX_SORT_MIN: usize = 10_000;
+
+/// Digit widths the radix sort implements.
+pub const RADIX_BITS: [u32; 3] = [8, 11, 16];
+
+/// Sort settings, changeable at runtime (machines and workloads disagree on
+/// the best values). The sorters read them on every call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SortTuning {
+    /// Inputs shorter than this use a comparison sort instead of the radix sort
+    pub small_cutoff: usize,
+    /// Radix digit width: 8 (8 passes, 256 counters), 11 (6 passes, 2048) or
+    /// 16 (4 passes, 65536)
+    pub radix_bits: u32,
+    /// Inputs at least this long are sorted in chunks on every available
+    /// thread, then merged; usize::MAX (the default) never does
+    pub parallel_threshold: usize,
+}
+
+pub const DEFAULT_SORT_TUNING: SortTuning =
+    SortTuning { small_cutoff: RADIX_SORT_MIN, radix_bits: 16, parallel_threshold: usize::MAX };
+
+static SMALL_CUTOFF: AtomicUsize = AtomicUsize::new(DEFAULT_SORT_TUNING.small_cutoff);
+static DIGIT_BITS: AtomicU32 = AtomicU32::new(DEFAULT_SORT_TUNING.radix_bits);
+static PARALLEL_THRESHOLD: AtomicUsize = AtomicUsize::new(DEFAULT_SORT_TUNING.parallel_threshold);
+
+pub fn sort_tuning() -> SortTuning {
+    SortTuning {
+        small_cutoff: SMALL_CUTOFF.load(Ordering::Relaxed),
+        radix_bits: DIGIT_BITS.load(Ordering::Relaxed),
+        parallel_threshold: PARALLEL_THRESHOLD.load(Ordering::Relaxed),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortTuningError {
+    /// `radix_bits` is not one of RADIX_BITS
+    RadixBits,
+    /// `parallel_threshold` is below 2, too short to split
+    ParallelThreshold,
+}
+
+/// Validate and apply `tuning`; on error nothing changes. The fields are
+/// stored separately, so a sort running concurrently may see a mix of old
+/// and new values, any of which sorts correctly.
+pub fn set_sort_tuning(tuning: SortTuning) -> Result<(), SortTuningError> {
+    if !RADIX_BITS.contains(&tuning.radix_bits) {
+        return Err(SortTuningError::RadixBits);
+    }
+    if tuning.parallel_threshold < 2 {
+        return Err(SortTuningError::ParallelThreshold);
+    }
+    SMALL_CUTOFF.store(tuning.small_cutoff, Ordering::Relaxed);
+    DIGIT_BITS.store(tuning.radix_bits, Ordering::Relaxed);
+    PARALLEL_THRESHOLD.store(tuning.parallel_threshold, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Sort ascending: comparison sort below the small cutoff, an LSD radix sort
+/// on order-preserving u64 keys from there, and chunks sorted on every thread
+/// then merged past the parallel threshold (see `SortTuning`). Every path
+/// orders by `f64::total_cmp`: -NaN < -inf < ... < -0.0 < +0.0 < ... < +inf < +NaN.
+///
+/// Equal values under that order are identical bit patterns, so the output
+/// is the same as a stable sort's; `sort_f64_by_key` is the stable sort for
+/// tagged values.
+pub fn sort_f64(data: &mut [f64]) {
+    if data.len() >= PARALLEL_THRESHOLD.load(Ordering::Relaxed) {
+        parallel_sort(data, resolve_threads(0), sort_f64_serial, |a, b| a.total_cmp(b).is_lt());
+    } else {
+        sort_f64_serial(data);
+    }
+}
+
+fn sort_f64_serial(data: &mut [f64]) {
+    if data.len() < SMALL_CUTOFF.load(Ordering::Relaxed) {
+        data.sort_unstable_by(f64::total_cmp);
+    } else {
+        radix_sort_f64(data);
+    }
+}
+
+/// `sort_f64` in descending order, the exact reverse of its output
+/// (+NaN first, +0.0 before -0.0, -NaN last). Same paths and tuning; the
+/// radix sort runs on the complemented order key rather than reversing after.
+pub fn sort_f64_desc(data: &mut [f64]) {
+    if data.len() >= PARALLEL_THRESHOLD.load(Ordering::Relaxed) {
+        parallel_sort(data, resolve_threads(0), sort_f64_desc_serial, |a, b| b.total_cmp(a).is_lt());
+    } else {
+        sort_f64_desc_serial(data);
+    }
+}
+
+fn sort_f64_desc_serial(data: &mut [f64]) {
+    if data.len() < SMALL_CUTOFF.load(Ordering::Relaxed)
+        || !radix_sort_by_key(data, |&val| !f64_order_key(val))
+    {
+        data.sort_unstable_by(|a, b| b.total_cmp(a));
+    }
+}
+
+/// `sort_f64` without the radix sort's O(n) scratch buffer: an in-place
+/// comparison sort at every size that never allocates. Same order, slower on
+/// large inputs.
+pub fn sort_f64_unstable(data: &mut [f64]) {
+    data.sort_unstable_by(f64::total_cmp);
+}
+
+/// Stable sort of `items` by an f64 key in `f64::total_cmp` order: items with
+/// equal keys keep their relative order on both the comparison and the radix path.
+pub fn sort_f64_by_key<T: Copy>(items: &mut [T], key: impl Fn(&T) -> f64) {
+    if items.len() < SMALL_CUTOFF.load(Ordering::Relaxed) {
+        items.sort_by(|a, b| key(a).total_cmp(&key(b)));
+    } else {
+        if !radix_sort_by_key(items, |item| f64_order_key(key(item))) {
+            items.sort_by(|a, b| key(a).total_cmp(&key(b)));
+        }
+    }
+}
+
+/// out[i] = the index of the i-th smallest value, in `sort_f64`'s order,
+/// leaving `values` as it is; `out` has the length of `values` (at most
+/// 2^32). Stable: equal values keep their input order. From the small cutoff
+/// up, radix sorts (order key, index) pairs, so the keys are computed once
+/// rather than fetched through the indices on every pass.
+pub fn argsort_f64(values: &[f64], out: &mut [u32]) {
+    let mut pairs = Vec::new();
+    if values.len() < SMALL_CUTOFF.load(Ordering::Relaxed) || pairs.try_reserve_exact(values.len()).is_err() {
+        out.iter_mut().enumerate().for_each(|(i, idx)| *idx = i as u32);
+        out.sort_by(|&a, &b| values[a as usize].total_cmp(&values[b as usize]));
+        return;
+    }
+    pairs.extend(values.iter().enumerate().map(|(i, &val)| (f64_order_key(val), i as u32)));
+    if !radix_sort_by_key(&mut pairs, |pair| pair.0) {
+        pairs.sort_by_key(|pair| pair.0);
+    }
+    out.iter_mut().zip(&pairs).for_each(|(idx, pair)| *idx = pair.1);
+}
+
+/// Sort ascending. Integer comparisons are cheap enough that the std sort
+/// beat the radix sort at every size `cargo bench --bench kernels -- cutoff`
+/// measured (10^3..10^7), so there is no radix path here; past the parallel
+/// threshold the std sort runs per chunk. Equal values are
+/// indistinguishable, so the output is the same as a stable sort's.
+pub fn sort_i64(data: &mut [i64]) {
+    if data.len() >= PARALLEL_THRESHOLD.load(Ordering::Relaxed) {
+        parallel_sort(data, resolve_threads(0), |run| run.sort_unstable(), |a, b| a < b);
+    } else {
+        data.sort_unstable();
+    }
+}
+
+/// `sort_i64` in descending order, on the same paths.
+pub fn sort_i64_desc(data: &mut [i64]) {
+    if data.len() >= PARALLEL_THRESHOLD.load(Ordering::Relaxed) {
+        parallel_sort(data, resolve_threads(0), |run| run.sort_unstable_by(|a, b| b.cmp(a)), |a, b| a > b);
+    } else {
+        data.sort_unstable_by(|a, b| b.cmp(a));
+    }
+}
+
+/// `sort_f64` that reports to `progress` and stops early when it returns
+/// false. `progress` hears how much of the sort is done, out of
+/// PROGRESS_DONE: 0 before any work, then after every PROGRESS_INTERVAL
+/// elements of a radix pass, and PROGRESS_DONE once sorted (its answer to
+/// that last call is ignored). It runs on this thread whatever the parallel
+/// threshold, and below the small cutoff sorts in one uninterruptible step.
+///
+/// On `Cancelled`, `data` holds a permutation of its input.
+pub fn sort_f64_with_progress(data: &mut [f64], progress: &dyn Fn(u64) -> bool) -> Result<(), Cancelled> {
+    let mut ticker = Ticker::start(progress)?;
+    if data.len() < SMALL_CUTOFF.load(Ordering::Relaxed)
+        || !radix_sort_tracked(data, &|&val: &f64| f64_order_key(val), &mut ticker)?
+    {
+        data.sort_unstable_by(f64::total_cmp);
+    }
+    progress(PROGRESS_DONE);
+    Ok(())
+}
+
+/// `sort_i64` with progress and cancellation, as `sort_f64_with_progress`.
+/// The std sort cannot be interrupted, so from the small cutoff up this
+/// takes the radix sort `sort_i64` skips, trading some speed for the checks.
+pub fn sort_i64_with_progress(data: &mut [i64], progress: &dyn Fn(u64) -> bool) -> Result<(), Cancelled> {
+    let mut ticker = Ticker::start(progress)?;
+    if data.len() < SMALL_CUTOFF.load(Ordering::Relaxed)
+        || !radix_sort_tracked(data, &|&val: &i64| (val as u64) ^ (1u64 << 63), &mut ticker)?
+    {
+        data.sort_unstable();
+    }
+    progress(PROGRESS_DONE);
+    Ok(())
+}
+
+/// The radix and parallel sorts with explicit parameters, for the cutoff
+/// benchmark and the property tests.
+#[doc(hidden)]
+pub mod bench {
+    pub fn radix_sort_f64(data: &mut [f64]) {
+        super::radix_sort_f64(data);
+    }
+
+    pub fn radix_sort_i64(data: &mut [i64]) {
+        super::radix_sort_i64(data);
+    }
+
+    /// `radix_sort_f64` with `bits`-wide digits (one of RADIX_BITS).
+    pub fn radix_sort_f64_bits(data: &mut [f64], bits: u32) {
+        assert!(super::radix_sort_bits(data, |&val| super::f64_order_key(val), bits));
+    }
+
+    /// `radix_sort_f64_bits` counting in usize, as inputs of 2^32 elements
+    /// or more do, at any length.
+    pub fn radix_sort_f64_wide(data: &mut [f64], bits: u32) {
+        let key = |val: &f64| super::f64_order_key(*val);
+        let untracked = &mut super::Untracked;
+        assert!(match bits {
+            8 => super::radix_sort_counted::<f64, usize, 8>(data, &key, untracked),
+            11 => super::radix_sort_counted::<f64, usize, 11>(data, &key, untracked),
+            _ => super::radix_sort_counted::<f64, usize, 16>(data, &key, untracked),
+        } == Ok(true));
+    }
+
+    pub fn parallel_sort_f64(data: &mut [f64], threads: usize) {
+        super::parallel_sort(data, threads, super::sort_f64_serial, |a, b| a.total_cmp(b).is_lt());
+    }
+}
+
+/// IEEE 754 radix sort trick:
+/// - Positive floats: bit pattern is already in correct order
+/// - Negative floats: bit pattern is in reverse order, and all bits are flipped
+///
+/// Transform: if sign bit is set, flip all bits; else flip only sign bit
+/// This gives a monotonically increasing u64 mapping for all f64 values.
+pub(crate) fn f64_order_key(val: f64) -> u64 {
+    bits_order_key(val.to_bits())
+}
+
+pub(crate) fn bits_order_key(bits: u64) -> u64 {
+    if bits >> 63 == 1 {
+        !bits // negative: flip all bits
+    } else {
+        bits ^ (1u64 << 63) // positive: flip sign bit
+    }
+}
+
+/// Inverse of `bits_order_key`.
+pub(crate) fn order_key_bits(key: u64) -> u64 {
+    if key >> 63 == 1 {
+        key ^ (1u64 << 63)
+    } else {
+        !key
+    }
+}
+
+/// Stable LSD radix sort on the digits of `key`, at the tuned digit width.
+/// Each pass scatters in input order, which is what keeps equal keys in
+/// place; a pass-skipping or in-place (American flag) variant would have to
+/// preserve that. False, with `items` untouched, if the scratch buffer could
+/// not be allocated; callers fall back to a comparison sort.
+fn radix_sort_by_key<T: Copy>(items: &mut [T], key: impl Fn(&T) -> u64) -> bool {
+    radix_sort_bits(items, key, DIGIT_BITS.load(Ordering::Relaxed))
+}
+
+fn radix_sort_bits<T: Copy>(items: &mut [T], key: impl Fn(&T) -> u64, bits: u32) -> bool {
+    radix_sort_bits_tracked(items, &key, bits, &mut Untracked) == Ok(true)
+}
+
+/// `radix_sort_by_key` reporting to `progress`; on `Cancelled`, `items`
+/// holds a permutation of its input.
+fn radix_sort_tracked<T: Copy>(items: &mut [T], key: &impl Fn(&T) -> u64, progress: &mut impl Progress) -> Result<bool, Cancelled> {
+    radix_sort_bits_tracked(items, key, DIGIT_BITS.load(Ordering::Relaxed), progress)
+}
+
+fn radix_sort_bits_tracked<T: Copy>(
+    items: &mut [T],
+    key: &impl Fn(&T) -> u64,
+    bits: u32,
+    progress: &mut impl Progress,
+) -> Result<bool, Cancelled> {
+    match bits {
+        8 => radix_sort_digits::<T, 8>(items, key, progress),
+        11 => radix_sort_digits::<T, 11>(items, key, progress),
+        _ => radix_sort_digits::<T, 16>(items, key, progress),
+    }
+}
+
+/// 64 / BITS passes rounded up: 8 of 8 bits, 6 of 11 (the last digit has 9)
+/// or 4 of 16. Every count is even, so passes go items -> buf -> items in
+/// pairs and the result lands back in `items` without a copy.
+fn radix_sort_digits<T: Copy, const BITS: u32>(
+    items: &mut [T],
+    key: &impl Fn(&T) -> u64,
+    progress: &mut impl Progress,
+) -> Result<bool, Cancelled> {
+    if u32::try_from(items.len()).is_ok() {
+        radix_sort_counted::<T, u32, BITS>(items, key, progress)
+    } else {
+        radix_sort_counted::<T, usize, BITS>(items, key, progress)
+    }
+}
+
+/// A digit histogram entry. u32 keeps the 16-bit digit's table at 256KB
+/// and counts every input below 2^32 elements, which on 32-bit targets is
+/// every input; only longer ones need usize.
+trait RadixCount: Copy + Default + std::ops::AddAssign {
+    const ONE: Self;
+    fn index(self) -> usize;
+}
+
+impl RadixCount for u32 {
+    const ONE: u32 = 1;
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl RadixCount for usize {
+    const ONE: usize = 1;
+    fn index(self) -> usize {
+        self
+    }
+}
+
+/// Elements a radix pass or a tracked sum works through between reports to
+/// its progress callback.
+pub const PROGRESS_INTERVAL: usize = 1 << 16;
+
+/// Progress a kernel reports once finished; reports before then are
+/// proportionally smaller.
+pub const PROGRESS_DONE: u64 = 1_000_000;
+
+/// A kernel stopped early because its progress callback returned false.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cancelled;
+
+/// Where a long kernel reports the elements it has worked through. `Untracked`
+/// compiles the checks away for the plain kernels.
+trait Progress {
+    /// Set how many elements of work lie ahead.
+    fn begin(&mut self, total: u64);
+    fn advance(&mut self, elements: usize) -> Result<(), Cancelled>;
+}
+
+struct Untracked;
+
+impl Progress for Untracked {
+    #[inline(always)]
+    fn begin(&mut self, _total: u64) {}
+
+    #[inline(always)]
+    fn advance(&mut self, _elements: usize) -> Result<(), Cancelled> {
+        Ok(())
+    }
+}
+
+/// Reports to a `*_with_progress` kernel's callback, scaled to PROGRESS_DONE.
+struct Ticker<'a> {
+    report: &'a dyn Fn(u64) -> bool,
+    total: u64,
+    done: u64,
+}
+
+impl<'a> Ticker<'a> {
+    /// Report 0 done before any work, so a callback that already wants to
+    /// stop stops the kernel at once.
+    fn start(report: &'a dyn Fn(u64) -> bool) -> Result<Self, Cancelled> {
+        if report(0) { Ok(Ticker { report, total: 0, done: 0 }) } else { Err(Cancelled) }
+    }
+}
+
+impl Progress for Ticker<'_> {
+    fn begin(&mut self, total: u64) {
+        self.total = total;
+        self.done = 0;
+    }
+
+    fn advance(&mut self, elements: usize) -> Result<(), Cancelled> {
+        self.done = (self.done + elements as u64).min(self.total);
+        // Below PROGRESS_DONE until the kernel itself reports it finished
+        let scaled = (self.done as u128 * PROGRESS_DONE as u128 / self.total.max(1) as u128) as u64;
+        if (self.report)(scaled.min(PROGRESS_DONE - 1)) { Ok(()) } else { Err(Cancelled) }
+    }
+}
+
+/// Each pass counts then scatters every item: 2 * passes * len elements of
+/// progress. A pass into `items` cancelled part way leaves it mixed, so the
+/// complete previous pass in `buf` is copied back over it.
+fn radix_sort_counted<T: Copy, C: RadixCount, const BITS: u32>(
+    items: &mut [T],
+    key: &impl Fn(&T) -> u64,
+    progress: &mut impl Progress,
+) -> Result<bool, Cancelled> {
+    let passes = 64u32.div_ceil(BITS);
+    debug_assert!(passes.is_multiple_of(2));
+    let mut buf = Vec::new();
+    if buf.try_reserve_exact(items.len()).is_err() {
+        return Ok(false);
+    }
+    buf.extend_from_slice(items);
+    progress.begin(2 * passes as u64 * items.len() as u64);
+    let mut counts = vec![C::default(); 1 << BITS];
+    for pass in (0..passes).step_by(2) {
+        radix_pass::<T, C, BITS>(items, &mut buf, pass * BITS, key, &mut counts, progress)?;
+        if let Err(cancelled) = radix_pass::<T, C, BITS>(&buf, items, (pass + 1) * BITS, key, &mut counts, progress) {
+            items.copy_from_slice(&buf);
+            return Err(cancelled);
+        }
+    }
+    Ok(true)
+}
+
+fn radix_pass<T: Copy, C: RadixCount, const BITS: u32>(
+    src: &[T],
+    dst: &mut [T],
+    shift: u32,
+    key: &impl Fn(&T) -> u64,
+    counts: &mut [C],
+    progress: &mut impl Progress,
+) -> Result<(), Cancelled> {
+    let digit = |item: &T| ((key(item) >> shift) & ((1 << BITS) - 1)) as usize;
+    counts.fill(C::default());
+
+    // Count
+    for chunk in src.chunks(PROGRESS_INTERVAL) {
+        for item in chunk {
+            counts[digit(item)] += C::ONE;
+        }
+        progress.advance(chunk.len())?;
+    }
+
+    // Prefix sum
+    let mut total = C::default();
+    for count in counts.iter_mut() {
+        let c = *count;
+        *count = total;
+        total += c;
+    }
+
+    // Scatter
+    for chunk in src.chunks(PROGRESS_INTERVAL) {
+        for item in chunk {
+            let d = digit(item);
+            dst[counts[d].index()] = *item;
+            counts[d] += C::ONE;
+        }
+        progress.advance(chunk.len())?;
+    }
+    Ok(())
+}
+
+/// Sort `threads` chunks concurrently with `sort_run`, then merge adjacent
+/// runs pairwise, each round's merges in parallel. Merges prefer the left run
+/// on ties, so stable chunk sorts give a stable result. If the merge buffer
+/// cannot be allocated the whole slice goes to `sort_run` on this thread.
+fn parallel_sort<T: Copy + Send + Sync>(
+    data: &mut [T],
+    threads: usize,
+    sort_run: impl Fn(&mut [T]) + Sync,
+    less: impl Fn(&T, &T) -> bool + Sync,
+) {
+    let threads = resolve_threads(threads.max(1));
+    let mut buf = Vec::new();
+    if threads == 1 || data.len() < 2 || buf.try_reserve_exact(data.len()).is_err() {
+        sort_run(data);
+        return;
+    }
+    let mut width = data.len().div_ceil(threads);
+    std::thread::scope(|s| {
+        for run in data.chunks_mut(width) {
+            let sort_run = &sort_run;
+            s.spawn(move || sort_run(run));
+        }
+    });
+
+    buf.extend_from_slice(data);
+    let mut in_data = true;
+    while width < data.len() {
+        if in_data {
+            merge_round(data, &mut buf, width, &less);
+        } else {
+            merge_round(&buf, data, width, &less);
+        }
+        in_data = !in_data;
+        width *= 2;
+    }
+    if !in_data {
+        data.copy_from_slice(&buf);
+    }
+}
+
+fn merge_round<T: Copy + Send + Sync>(src: &[T], dst: &mut [T], width: usize, less: &(impl Fn(&T, &T) -> bool + Sync)) {
+    std::thread::scope(|s| {
+        for (pair, out) in src.chunks(2 * width).zip(dst.chunks_mut(2 * width)) {
+            s.spawn(move || {
+                let (left, right) = pair.split_at(width.min(pair.len()));
+                merge(left, right, out, less);
+            });
+        }
+    });
+}
+
+fn merge<T: Copy>(left: &[T], right: &[T], out: &mut [T], less: &impl Fn(&T, &T) -> bool) {
+    let (mut i, mut j) = (0, 0);
+    for slot in out.iter_mut() {
+        if j < right.len() && (i == left.len() || less(&right[j], &left[i])) {
+            *slot = right[j];
+            j += 1;
+        } else {
+            *slot = left[i];
+            i += 1;
+        }
+    }
+}
+
+fn radix_sort_f64(data: &mut [f64]) {
+    if !radix_sort_by_key(data, |&val| f64_order_key(val)) {
+        data.sort_unstable_by(f64::total_cmp);
+    }
+}
+
+fn radix_sort_i64(data: &mut [i64]) {
+    // Signed to unsigned order by flipping the sign bit
+    if !radix_sort_by_key(data, |&val| (val as u64) ^ (1u64 << 63)) {
+        data.sort_unstable();
+    }
+}
+
+/// `sort_f64` split into steps of bounded work, for callers that cannot
+/// block for the whole sort. It works on the values' bit patterns (`data`
+/// stays `&mut [u64]` so NaN payloads survive every step) and is resumable
+/// between any two steps:
+///
+/// 1. rewrite each value as its order key, in place;
+/// 2. per radix pass, count digits, then scatter into the other buffer;
+/// 3. rewrite the keys back into values.
+///
+/// Until it finishes, `data` holds every input value or key exactly once in
+/// no particular order (or partly in the scratch buffer; `abort` puts it back).
+/// The result is identical to `sort_f64`'s. Inputs below the small cutoff, or
+/// whose scratch buffer cannot be allocated, sort in one comparison step.
+pub struct IncrementalSortF64 {
+    phase: SortPhase,
+    bits: u32,
+    buf: Vec<u64>,
+    counts: Vec<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SortPhase {
+    /// The whole sort as one comparison sort
+    Comparison,
+    /// Values in `data[..next]` are keys now
+    Keys { next: usize },
+    /// Counting the digits of `pass` in `data` (even passes) or `buf` (odd)
+    Count { pass: u32, next: usize },
+    /// Scattering the source of `pass` into the other buffer
+    Scatter { pass: u32, next: usize },
+    /// Keys in `data[..next]` are values again
+    Values { next: usize },
+    Done,
+}
+
+impl IncrementalSortF64 {
+    /// State for sorting `len` values at the current tuning's cutoff and
+    /// digit width. Allocates the scratch buffer up front.
+    pub fn new(len: usize) -> Self {
+        let bits = DIGIT_BITS.load(Ordering::Relaxed);
+        let mut sort = IncrementalSortF64 { phase: SortPhase::Done, bits, buf: Vec::new(), counts: Vec::new() };
+        if len < 2 {
+            return sort;
+        }
+        if len < SMALL_CUTOFF.load(Ordering::Relaxed) || sort.buf.try_reserve_exact(len).is_err() {
+            sort.phase = SortPhase::Comparison;
+            return sort;
+        }
+        sort.buf.resize(len, 0);
+        sort.counts = vec![0; 1 << bits];
+        sort.phase = SortPhase::Keys { next: 0 };
+        sort
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.phase == SortPhase::Done
+    }
+
+    /// Advance over about `budget` elements (at least one), possibly across
+    /// phases; a comparison sort always runs whole. `data` must be the same
+    /// slice on every call. Returns true once `data` is sorted.
+    pub fn step(&mut self, data: &mut [u64], budget: usize) -> bool {
+        let len = data.len();
+        let passes = 64u32.div_ceil(self.bits);
+        let mask = (1u64 << self.bits) - 1;
+        let mut budget = budget.max(1);
+        while budget > 0 {
+            let phase = match self.phase {
+                SortPhase::Done => break,
+                SortPhase::Comparison => {
+                    data.sort_unstable_by_key(|&bits| bits_order_key(bits));
+                    SortPhase::Done
+                }
+                SortPhase::Keys { next } => {
+                    let end = next.saturating_add(budget).min(len);
+                    for bits in &mut data[next..end] {
+                        *bits = bits_order_key(*bits);
+                    }
+                    budget -= end - next;
+                    if end == len {
+                        self.counts.fill(0);
+                        SortPhase::Count { pass: 0, next: 0 }
+                    } else {
+                        SortPhase::Keys { next: end }
+                    }
+                }
+                SortPhase::Count { pass, next } => {
+                    let src: &[u64] = if pass.is_multiple_of(2) { data } else { &self.buf };
+                    let end = next.saturating_add(budget).min(len);
+                    let shift = pass * self.bits;
+                    for &key in &src[next..end] {
+                        self.counts[((key >> shift) & mask) as usize] += 1;
+                    }
+                    budget -= end - next;
+                    if end < len {
+                        SortPhase::Count { pass, next: end }
+                    } else {
+                        let mut total = 0;
+                        for count in self.counts.iter_mut() {
+                            let c = *count;
+                            *count = total;
+                            total += c;
+                        }
+                        budget = budget.saturating_sub(self.counts.len());
+                        SortPhase::Scatter { pass, next: 0 }
+                    }
+                }
+                SortPhase::Scatter { pass, next } => {
+                    let (src, dst): (&[u64], &mut [u64]) =
+                        if pass.is_multiple_of(2) { (data, &mut self.buf) } else { (&self.buf, data) };
+                    let end = next.saturating_add(budget).min(len);
+                    let shift = pass * self.bits;
+                    for &key in &src[next..end] {
+                        let d = ((key >> shift) & mask) as usize;
+                        dst[self.counts[d]] = key;
+                        self.counts[d] += 1;
+                    }
+                    budget -= end - next;
+                    if end < len {
+                        SortPhase::Scatter { pass, next: end }
+                    } else if pass + 1 < passes {
+                        self.counts.fill(0);
+                        SortPhase::Count { pass: pass + 1, next: 0 }
+                    } else {
+                        SortPhase::Values { next: 0 }
+                    }
+                }
+                SortPhase::Values { next } => {
+                    let end = next.saturating_add(budget).min(len);
+                    for key in &mut data[next..end] {
+                        *key = order_key_bits(*key);
+                    }
+                    budget -= end - next;
+                    if end == len {
+                        self.buf = Vec::new();
+                        self.counts = Vec::new();
+                        SortPhase::Done
+                    } else {
+                        SortPhase::Values { next: end }
+                    }
+                }
+            };
+            self.phase = phase;
+        }
+        self.is_done()
+    }
+
+    /// Stop early, leaving every input value in `data` in some order. The
+    /// sort counts as done afterwards.
+    pub fn abort(&mut self, data: &mut [u64]) {
+        let keys = match self.phase {
+            SortPhase::Comparison | SortPhase::Done => 0..0,
+            SortPhase::Keys { next } => 0..next,
+            SortPhase::Count { .. } | SortPhase::Scatter { .. } => 0..data.len(),
+            SortPhase::Values { next } => next..data.len(),
+        };
+        // An odd pass scatters back into `data`, so mid-way only `buf` holds
+        // every key
+        if let SortPhase::Scatter { pass, next } = self.phase {
+            if !pass.is_multiple_of(2) && next > 0 {
+                data.copy_from_slice(&self.buf);
+            }
+        }
+        for key in &mut data[keys] {
+            *key = order_key_bits(*key);
+        }
+        *self = IncrementalSortF64::new(0);
+    }
+}
+
+// ============================================================
+// Array utilities
+// ============================================================
+
+/// Compact runs of equal values in sorted `data` to their first element.
+/// Returns the new length; `data[..len]` holds the distinct values.
+pub fn unique_sorted<T: Copy + PartialEq>(data: &mut [T]) -> usize {
+    if data.len() <= 1 {
+        return data.len();
+    }
+    let mut write = 1usize;
+    for read in 1..data.len() {
+        if data[read] != data[write - 1] {
+            data[write] = data[read];
+            write += 1;
+        }
+    }
+    write
+}
+
+/// `unique_sorted` that also writes the run length of each distinct value to
+/// `counts[i]`. `counts` must hold at least one slot per distinct value.
+pub fn unique_counts_sorted_i64(data: &mut [i64], counts: &mut [u64]) -> usize {
+    unique_counts_by(data, counts, |a, b| a == b)
+}
+
+/// `unique_counts_sorted_i64` for f64 under total-order equality: values are
+/// equal when their bits are, so -0.0 and +0.0 count separately and NaNs
+/// group by payload, matching the runs `sort_f64` produces.
+pub fn unique_counts_sorted_f64(data: &mut [f64], counts: &mut [u64]) -> usize {
+    unique_counts_by(data, counts, |a, b| a.to_bits() == b.to_bits())
+}
+
+fn unique_counts_by<T: Copy>(data: &mut [T], counts: &mut [u64], eq: impl Fn(&T, &T) -> bool) -> usize {
+    if data.is_empty() {
+        return 0;
+    }
+    let mut write = 0usize;
+    counts[0] = 1;
+    for read in 1..data.len() {
+        if eq(&data[read], &data[write]) {
+            counts[write] += 1;
+        } else {
+            write += 1;
+            data[write] = data[read];
+            counts[write] = 1;
+        }
+    }
+    write + 1
+}
+
+/// Kahan-compensated sum (0.0 when empty).
+pub fn sum_f64(data: &[f64]) -> f64 {
+    let mut sum = 0.0f64;
+    let mut comp = 0.0f64; // compensation for lost low-order bits
+    for &val in data {
+        let y = val - comp;
+        let t = sum + y;
+        comp = (t - sum) - y;
+        sum = t;
+    }
+    sum
+}
+
+/// `sum_f64` reporting to `progress` as `sort_f64_with_progress` does, after
+/// every PROGRESS_INTERVAL values, and stopping early when it returns false.
+/// The sum is identical to `sum_f64`'s.
+pub fn sum_f64_with_progress(data: &[f64], progress: &dyn Fn(u64) -> bool) -> Result<f64, Cancelled> {
+    let mut ticker = Ticker::start(progress)?;
+    ticker.begin(data.len() as u64);
+    let mut sum = 0.0f64;
+    let mut comp = 0.0f64;
+    for chunk in data.chunks(PROGRESS_INTERVAL) {
+        for &val in chunk {
+            let y = val - comp;
+            let t = sum + y;
+            comp = (t - sum) - y;
+            sum = t;
+        }
+        ticker.advance(chunk.len())?;
+    }
+    progress(PROGRESS_DONE);
+    Ok(sum)
+}
+
+// Min and max skip NaN, so they return NaN only for empty or all-NaN input,
+// and order signed zeros totally: min prefers -0.0 and max +0.0 wherever they
+// appear. Everything that reports a minimum or maximum, for f64 and i64
+// alike, decides with `improves`, which keeps the first of equal values.
+
+/// Whether `val` replaces `best` (None: nothing seen yet) as the minimum
+/// (`want` Less) or maximum (Greater). Only a strictly better non-NaN
+/// value does.
+fn improves<T: Scalar>(best: Option<T>, val: T, want: cmp::Ordering) -> bool {
+    val.key().is_some() && best.is_none_or(|b| val.order(&b) == want)
+}
+
+/// Fold step for `min_f64`. A NaN accumulator means "nothing seen yet".
+pub fn fold_min_f64(acc: f64, val: f64) -> f64 {
+    if improves((!acc.is_nan()).then_some(acc), val, cmp::Ordering::Less) {
+        val
+    } else {
+        acc
+    }
+}
+
+/// Fold step for `max_f64`. A NaN accumulator means "nothing seen yet".
+pub fn fold_max_f64(acc: f64, val: f64) -> f64 {
+    if improves((!acc.is_nan()).then_some(acc), val, cmp::Ordering::Greater) {
+        val
+    } else {
+        acc
+    }
+}
+
+/// Smallest non-NaN value (NaN when there is none).
+pub fn min_f64(data: &[f64]) -> f64 {
+    data.iter().fold(f64::NAN, |m, &val| fold_min_f64(m, val))
+}
+
+/// Largest non-NaN value (NaN when there is none).
+pub fn max_f64(data: &[f64]) -> f64 {
+    data.iter().fold(f64::NAN, |m, &val| fold_max_f64(m, val))
+}
+
+/// Position and value of the first smallest counted value: one whose bit is
+/// set in `validity` (None: all valid) and that is not NaN. None when no
+/// value is counted.
+pub fn argmin<T: Scalar>(values: &[T], validity: Option<&[u8]>) -> Option<(usize, T)> {
+    arg_extreme(values, validity, cmp::Ordering::Less)
+}
+
+/// `argmin` for the first largest counted value.
+pub fn argmax<T: Scalar>(values: &[T], validity: Option<&[u8]>) -> Option<(usize, T)> {
+    arg_extreme(values, validity, cmp::Ordering::Greater)
+}
+
+fn arg_extreme<T: Scalar>(values: &[T], validity: Option<&[u8]>, want: cmp::Ordering) -> Option<(usize, T)> {
+    let mut best = None::<(usize, T)>;
+    each_valid_at(values, validity, |i, v| {
+        if improves(best.map(|(_, b)| b), v, want) {
+            best = Some((i, v));
+        }
+    });
+    best
+}
+
+/// Smallest and largest counted value (as for `argmin`) in one pass.
+pub fn minmax<T: Scalar>(values: &[T], validity: Option<&[u8]>) -> Option<(T, T)> {
+    let (mut min, mut max) = (None::<T>, None::<T>);
+    each_valid(values, validity, |v| {
+        if improves(min, v, cmp::Ordering::Less) {
+            min = Some(v);
+        }
+        if improves(max, v, cmp::Ordering::Greater) {
+            max = Some(v);
+        }
+    });
+    min.zip(max)
+}
+
+/// Mean of the valid values (as for `argmin`), None when there are none.
+/// The sum is exact in i128 and the division rounds the whole part and the
+/// fraction separately, so the mean of values beyond 2^53 is within an ulp.
+pub fn mean_i64(values: &[i64], validity: Option<&[u8]>) -> Option<f64> {
+    let (mut sum, mut count) = (0i128, 0i128);
+    each_valid(values, validity, |v| {
+        sum += v as i128;
+        count += 1;
+    });
+    (count > 0).then(|| sum.div_euclid(count) as f64 + sum.rem_euclid(count) as f64 / count as f64)
+}
+
+/// `reduce` over each segment `values[offsets[i]..offsets[i + 1]]`, given
+/// the segment's start, writing its result to `out[i]` (`empty` for None)
+/// and returning how many segments were empty. `offsets` holds out.len() + 1
+/// non-decreasing entries, the last at most values.len().
+pub fn segmented<T, R: Copy>(
+    values: &[T],
+    offsets: &[u64],
+    out: &mut [R],
+    empty: R,
+    reduce: impl Fn(usize, &[T]) -> Option<R>,
+) -> usize {
+    let mut empties = 0;
+    for (slot, bounds) in out.iter_mut().zip(offsets.windows(2)) {
+        let (start, end) = (bounds[0] as usize, bounds[1] as usize);
+        *slot = reduce(start, &values[start..end]).unwrap_or_else(|| {
+            empties += 1;
+            empty
+        });
+    }
+    empties
+}
+
+// ============================================================
+// Parallel reductions
+// ============================================================
+
+// The parallel sum cuts its input into fixed PARALLEL_SUM_BLOCK-element
+// blocks, whatever the thread count. Each block gets its own compensated sum,
+// and the block results are merged in block order with an error-free two-sum,
+// so threads only decide who sums which block: the result is bit-identical
+// for every `threads` value. It can differ from `sum_f64` (one compensated
+// pass over everything) in the last bits. Min and max need no such care;
+// their fold is associative and commutative.
+
+/// Elements per block of `sum_f64_parallel`.
+pub const PARALLEL_SUM_BLOCK: usize = 1 << 16;
+
+/// Inputs shorter than this are reduced on the calling thread; spawning
+/// threads costs more than it saves. Same semantics either way.
+pub const PARALLEL_MIN_LEN: usize = 1 << 20;
+
+/// Error-free transformation: `a + b == s + err` exactly.
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    let bb = s - a;
+    (s, (a - (s - bb)) + (b - bb))
+}
+
+/// Kahan sum of one block as (sum, compensation); the block's value is
+/// sum - compensation.
+fn kahan_block(block: &[f64]) -> (f64, f64) {
+    let mut sum = 0.0f64;
+    let mut comp = 0.0f64;
+    for &val in block {
+        let y = val - comp;
+        let t = sum + y;
+        comp = (t - sum) - y;
+        sum = t;
+    }
+    (sum, comp)
+}
+
+/// Whether this target can spawn threads: wasm without the atomics feature
+/// cannot, so every parallel path there runs on the calling thread.
+const THREADS_AVAILABLE: bool = !cfg!(all(target_family = "wasm", not(target_feature = "atomics")));
+
+fn resolve_threads(threads: usize) -> usize {
+    if !THREADS_AVAILABLE {
+        1
+    } else if threads == 0 {
+        std::thread::available_parallelism().map_or(1, |n| n.get())
+    } else {
+        threads
+    }
+}
+
+/// Run `f` over `threads` contiguous runs of whole blocks, returning the
+/// results in data order.
+fn per_thread_blocks<R: Send>(data: &[f64], threads: usize, f: impl Fn(&[f64]) -> R + Sync) -> Vec<R> {
+    let blocks = data.len().div_ceil(PARALLEL_SUM_BLOCK);
+    let per_thread = blocks.div_ceil(resolve_threads(threads).min(blocks).max(1)) * PARALLEL_SUM_BLOCK;
+    if per_thread >= data.len() {
+        return vec![f(data)];
+    }
+    std::thread::scope(|s| {
+        let handles: Vec<_> = data.chunks(per_thread).map(|run| s.spawn(|| f(run))).collect();
+        handles.into_iter().map(|h| h.join().expect("reduction thread panicked")).collect()
+    })
+}
+
+/// Compensated sum on up to `threads` threads (0 = available parallelism),
+/// identical for every thread count.
+pub fn sum_f64_parallel(data: &[f64], threads: usize) -> f64 {
+    let partials: Vec<(f64, f64)> = if data.len() < PARALLEL_MIN_LEN {
+        data.chunks(PARALLEL_SUM_BLOCK).map(kahan_block).collect()
+    } else {
+        per_thread_blocks(data, threads, |run| run.chunks(PARALLEL_SUM_BLOCK).map(kahan_block).collect::<Vec<_>>())
+            .into_iter()
+            .flatten()
+            .collect()
+    };
+    let (mut hi, mut lo) = (0.0f64, 0.0f64);
+    for (sum, comp) in partials {
+        let (s, err) = two_sum(hi, sum);
+        hi = s;
+        lo += err - comp;
+    }
+    hi + lo
+}
+
+/// `min_f64` on up to `threads` threads (0 = available parallelism).
+pub fn min_f64_parallel(data: &[f64], threads: usize) -> f64 {
+    if data.len() < PARALLEL_MIN_LEN {
+        return min_f64(data);
+    }
+    per_thread_blocks(data, threads, min_f64).into_iter().fold(f64::NAN, fold_min_f64)
+}
+
+/// `max_f64` on up to `threads` threads (0 = available parallelism).
+pub fn max_f64_parallel(data: &[f64], threads: usize) -> f64 {
+    if data.len() < PARALLEL_MIN_LEN {
+        return max_f64(data);
+    }
+    per_thread_blocks(data, threads, max_f64).into_iter().fold(f64::NAN, fold_max_f64)
+}
+
+/// `(min_f64, max_f64)` in one pass on up to `threads` threads.
+pub fn minmax_f64_parallel(data: &[f64], threads: usize) -> (f64, f64) {
+    let minmax = |run: &[f64]| {
+        run.iter().fold((f64::NAN, f64::NAN), |(lo, hi), &val| (fold_min_f64(lo, val), fold_max_f64(hi, val)))
+    };
+    if data.len() < PARALLEL_MIN_LEN {
+        return minmax(data);
+    }
+    per_thread_blocks(data, threads, minmax)
+        .into_iter()
+        .fold((f64::NAN, f64::NAN), |(lo, hi), (min, max)| (fold_min_f64(lo, min), fold_max_f64(hi, max)))
+}
+
+// ============================================================
+// String distance
+// ============================================================
+
+/// Byte-wise edit distance, or u32::MAX once it is known to exceed `max_dist`
+/// (pass u32::MAX for no bound).
+pub fn levenshtein(a: &[u8], b: &[u8], max_dist: u32) -> u32 {
+    levenshtein_bounded(a, b, max_dist, &mut Vec::new())
+}
+
+/// Two-row Levenshtein DP over bytes, giving up with u32::MAX as soon as every
+/// cell in a row exceeds `max_dist`. `row` is scratch reused across calls.
+fn levenshtein_bounded(a: &[u8], b: &[u8], max_dist: u32, row: &mut Vec<u32>) -> u32 {
+    // Shared prefix and suffix never contribute edits
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    let suffix = a.iter().rev().zip(b.iter().rev()).take_while(|(x, y)| x == y).count();
+    let (a, b) = (&a[..a.len() - suffix], &b[..b.len() - suffix]);
+    // Keep the row over the shorter string
+    let (a, b) = if a.len() < b.len() { (b, a) } else { (a, b) };
+
+    if (a.len() - b.len()) as u64 > max_dist as u64 {
+        return u32::MAX;
+    }
+    if b.is_empty() {
+        return a.len() as u32;
+    }
+
+    row.clear();
+    row.extend(0..=b.len() as u32);
+    for (i, &ca) in a.iter().enumerate() {
+        let mut diag = row[0];
+        row[0] = i as u32 + 1;
+        let mut row_min = row[0];
+        for (j, &cb) in b.iter().enumerate() {
+            let cost = if ca == cb { diag } else { diag + 1 };
+            diag = row[j + 1];
+            let cell = cost.min(row[j] + 1).min(diag + 1);
+            row[j + 1] = cell;
+            row_min = row_min.min(cell);
+        }
+        if row_min > max_dist {
+            return u32::MAX;
+        }
+    }
+    let dist = row[b.len()];
+    if dist > max_dist { u32::MAX } else { dist }
+}
+
+/// Distance from `query` to each string of an offsets+bytes column (string i
+/// is `bytes[offsets[i]..offsets[i + 1]]`); u32::MAX beyond `max_dist` or for
+/// offsets outside `bytes`.
+pub fn levenshtein_batch(query: &[u8], bytes: &[u8], offsets: &[u64], max_dist: u32, out: &mut [u32]) {
+    let mut row = Vec::with_capacity(query.len() + 1);
+    for (dst, w) in out.iter_mut().zip(offsets.windows(2)) {
+        *dst = match bytes.get(w[0] as usize..w[1] as usize) {
+            Some(s) => levenshtein_bounded(query, s, max_dist, &mut row),
+            None => u32::MAX,
+        };
+    }
+}
+
+/// Differing bits between two fingerprints of equal word count.
+pub fn hamming_u64(a: &[u64], b: &[u64]) -> u64 {
+    a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones() as u64).sum()
+}
+
+// ============================================================
+// Substring search
+// ============================================================
+
+/// Crochemore-Perrin two-way searcher: linear time, constant space, with a
+/// 64-bit byte-set skip so bytes absent from the needle jump a whole needle.
+struct TwoWaySearcher {
+    needle: Vec<u8>,
+    crit_pos: usize,
+    period: usize,
+    /// Needle has no short period; the left half is never remembered
+    long_period: bool,
+    byteset: u64,
+}
+
+/// Critical factorization half: start and period of the maximal suffix of
+/// `arr` under the normal (`order_greater` false) or reversed byte order.
+fn maximal_suffix(arr: &[u8], order_greater: bool) -> (usize, usize) {
+    let mut left = 0;
+    let mut right = 1;
+    let mut offset = 0;
+    let mut period = 1;
+    while let Some(&a) = arr.get(right + offset) {
+        let b = arr[left + offset];
+        if (a < b && !order_greater) || (a > b && order_greater) {
+            right += offset + 1;
+            offset = 0;
+            period = right - left;
+        } else if a == b {
+            if offset + 1 == period {
+                right += offset + 1;
+                offset = 0;
+            } else {
+                offset += 1;
+            }
+        } else {
+            left = right;
+            right += 1;
+            offset = 0;
+            period = 1;
+        }
+    }
+    (left, period)
+}
+
+impl TwoWaySearcher {
+    fn new(needle: &[u8], fold: bool) -> Self {
+        let needle: Vec<u8> = if fold { needle.to_ascii_lowercase() } else { needle.to_vec() };
+        let (crit_a, period_a) = maximal_suffix(&needle, false);
+        let (crit_b, period_b) = maximal_suffix(&needle, true);
+        let (crit_pos, period) = if crit_a > crit_b { (crit_a, period_a) } else { (crit_b, period_b) };
+        let byteset = needle.iter().fold(0u64, |set, &b| set | 1 << (b & 63));
+        let periodic = period + crit_pos <= needle.len() && needle[..crit_pos] == needle[period..period + crit_pos];
+        if periodic {
+            TwoWaySearcher { needle, crit_pos, period, long_period: false, byteset }
+        } else {
+            // The true period exceeds max(left, right), so that is a safe shift
+            let period = crit_pos.max(needle.len() - crit_pos) + 1;
+            TwoWaySearcher { needle, crit_pos, period, long_period: true, byteset }
+        }
+    }
+
+    /// Call `on_match` with each match start in order until it returns false.
+    fn search<const FOLD: bool>(&self, hay: &[u8], overlapping: bool, mut on_match: impl FnMut(usize) -> bool) {
+        let n = self.needle.len();
+        let at = |i: usize| if FOLD { hay[i].to_ascii_lowercase() } else { hay[i] };
+        let mut position = 0;
+        let mut memory = 0;
+        'outer: while position + n <= hay.len() {
+            if (self.byteset >> (at(position + n - 1) & 63)) & 1 == 0 {
+                position += n;
+                memory = 0;
+                continue;
+            }
+            // Right half first; a mismatch there shifts past the compared prefix
+            let start = if self.long_period { self.crit_pos } else { self.crit_pos.max(memory) };
+            for i in start..n {
+                if self.needle[i] != at(position + i) {
+                    position += i - self.crit_pos + 1;
+                    memory = 0;
+                    continue 'outer;
+                }
+            }
+            // Then the left half, skipping what the period says already matched
+            let stop = if self.long_period { 0 } else { memory };
+            for i in (stop..self.crit_pos).rev() {
+                if self.needle[i] != at(position + i) {
+                    position += self.period;
+                    if !self.long_period {
+                        memory = n - self.period;
+                    }
+                    continue 'outer;
+                }
+            }
+            if !on_match(position) {
+                return;
+            }
+            if overlapping {
+                position += self.period;
+                if !self.long_period {
+                    memory = n - self.period;
+                }
+            } else {
+                position += n;
+                memory = 0;
+            }
+        }
+    }
+}
+
+/// Call `on_match` with the start of each match of `needle` in `hay` (see the
+/// TOVA_FIND_* flags) until it returns false. The empty needle matches at
+/// every offset 0..=hay.len(), like JS `indexOf("")` at each start position.
+pub fn find_each(hay: &[u8], needle: &[u8], flags: u32, mut on_match: impl FnMut(usize) -> bool) {
+    if needle.is_empty() {
+        for pos in 0..=hay.len() {
+            if !on_match(pos) {
+                return;
+            }
+        }
+        return;
+    }
+    let overlapping = flags & TOVA_FIND_OVERLAPPING != 0;
+    if flags & TOVA_FIND_IGNORE_ASCII_CASE != 0 {
+        TwoWaySearcher::new(needle, true).search::<true>(hay, overlapping, on_match);
+    } else {
+        TwoWaySearcher::new(needle, false).search::<false>(hay, overlapping, on_match);
+    }
+}
+
+/// Match offsets into `out`; returns the total match count.
+pub fn find_all(hay: &[u8], needle: &[u8], flags: u32, out: &mut [u64]) -> usize {
+    let mut count = 0usize;
+    find_each(hay, needle, flags, |pos| {
+        if let Some(dst) = out.get_mut(count) {
+            *dst = pos as u64;
+        }
+        count += 1;
+        true
+    });
+    count
+}
+
+pub fn count_occurrences(hay: &[u8], needle: &[u8], flags: u32) -> u64 {
+    let mut count = 0u64;
+    find_each(hay, needle, flags, |_| {
+        count += 1;
+        true
+    });
+    count
+}
+
+pub fn find_first(hay: &[u8], needle: &[u8], flags: u32) -> Option<usize> {
+    let mut first = None;
+    find_each(hay, needle, flags, |pos| {
+        first = Some(pos);
+        false
+    });
+    first
+}
+
+// ============================================================
+// JSON number arrays
+// ============================================================
+
+fn json_skip_ws(bytes: &[u8], mut pos: usize) -> usize {
+    while pos < bytes.len() && matches!(bytes[pos], b' ' | b'\t' | b'\n' | b'\r') {
+        pos += 1;
+    }
+    pos
+}
+
+fn json_digits(bytes: &[u8], mut pos: usize) -> usize {
+    while pos < bytes.len() && bytes[pos].is_ascii_digit() {
+        pos += 1;
+    }
+    pos
+}
+
+/// Scan one RFC 8259 number starting at `start`; returns its end or the
+/// offset of the first offending byte.
+fn json_scan_number(bytes: &[u8], start: usize) -> Result<usize, usize> {
+    let mut pos = start;
+    if bytes.get(pos) == Some(&b'-') {
+        pos += 1;
+    }
+    match bytes.get(pos) {
+        Some(b'0') => pos += 1,
+        Some(b'1'..=b'9') => pos = json_digits(bytes, pos + 1),
+        _ => return Err(pos),
+    }
+    if bytes.get(pos) == Some(&b'.') {
+        let frac = json_digits(bytes, pos + 1);
+        if frac == pos + 1 {
+            return Err(frac);
+        }
+        pos = frac;
+    }
+    if matches!(bytes.get(pos), Some(b'e' | b'E')) {
+        pos += 1;
+        if matches!(bytes.get(pos), Some(b'+' | b'-')) {
+            pos += 1;
+        }
+        let exp = json_digits(bytes, pos);
+        if exp == pos {
+            return Err(exp);
+        }
+        pos = exp;
+    }
+    Ok(pos)
+}
+
+/// Parse a flat JSON array of numbers into `out`, returning the element count
+/// or the byte offset of the first invalid input. Numbers beyond f64 range
+/// become ±Infinity, as with JSON.parse.
+pub fn parse_json_numbers(bytes: &[u8], out: &mut [f64]) -> Result<usize, usize> {
+    let mut pos = json_skip_ws(bytes, 0);
+    if bytes.get(pos) != Some(&b'[') {
+        return Err(pos);
+    }
+    pos = json_skip_ws(bytes, pos + 1);
+    let mut count = 0usize;
+    if bytes.get(pos) == Some(&b']') {
+        pos += 1;
+    } else {
+        loop {
+            let end = json_scan_number(bytes, pos)?;
+            if let Some(dst) = out.get_mut(count) {
+                // The scan above admits only ASCII digits, signs, '.', 'e' and 'E'
+                let text = std::str::from_utf8(&bytes[pos..end]).unwrap_or("");
+                *dst = text.parse().unwrap_or(f64::NAN);
+            }
+            count += 1;
+            pos = json_skip_ws(bytes, end);
+            match bytes.get(pos) {
+                Some(b',') => pos = json_skip_ws(bytes, pos + 1),
+                Some(b']') => {
+                    pos += 1;
+                    break;
+                }
+                _ => return Err(pos),
+            }
+        }
+    }
+    pos = json_skip_ws(bytes, pos);
+    if pos != bytes.len() {
+        return Err(pos);
+    }
+    Ok(count)
+}
+
+// ============================================================
+// Filter, gather, scatter, permutation
+// ============================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexError {
+    /// The index at this position of the index array is out of range
+    OutOfRange { position: usize },
+    /// A target index appears twice
+    Duplicate,
+    /// Longer than the 2^31 entries a permutation can hold
+    TooLong,
+}
+
+/// Indices of the nonzero bytes of `mask` into `out`; returns the total count.
+pub fn nonzero_u8(mask: &[u8], out: &mut [u32]) -> usize {
+    let mut count = 0usize;
+    let mut emit = |i: usize| {
+        if let Some(dst) = out.get_mut(count) {
+            *dst = i as u32;
+        }
+        count += 1;
+    };
+    let mut words = mask.chunks_exact(8);
+    let mut base = 0;
+    for word in &mut words {
+        // Sparse masks are mostly zero words; skip them eight bytes at a time
+        if u64::from_ne_bytes(word.try_into().unwrap()) != 0 {
+            for (j, &b) in word.iter().enumerate() {
+                if b != 0 {
+                    emit(base + j);
+                }
+            }
+        }
+        base += 8;
+    }
+    for (j, &b) in words.remainder().iter().enumerate() {
+        if b != 0 {
+            emit(base + j);
+        }
+    }
+    count
+}
+
+/// out[i] = values[indices[i]]. On an out-of-range index, `out` is written up
+/// to that position.
+pub fn gather<T: Copy>(values: &[T], indices: &[u32], out: &mut [T]) -> Result<(), IndexError> {
+    for (position, (dst, &idx)) in out.iter_mut().zip(indices).enumerate() {
+        *dst = *values.get(idx as usize).ok_or(IndexError::OutOfRange { position })?;
+    }
+    Ok(())
+}
+
+/// `gather` for dictionary-encoded columns: out[i] = dict[codes[i]] where
+/// bit i of `validity` is set, `null` elsewhere. Codes under null slots are
+/// never read as indices, since Arrow leaves them unspecified.
+pub fn dict_decode<T: Copy>(codes: &[u32], validity: Option<&[u8]>, dict: &[T], null: T, out: &mut [T]) -> Result<(), IndexError> {
+    let Some(bitmap) = validity else {
+        return gather(dict, codes, out);
+    };
+    for (position, (dst, &code)) in out.iter_mut().zip(codes).enumerate() {
+        *dst = if bit(bitmap, position) == 0 {
+            null
+        } else {
+            *dict.get(code as usize).ok_or(IndexError::OutOfRange { position })?
+        };
+    }
+    Ok(())
+}
+
+/// counts[c] = occurrences of code c, for `counts.len()` dictionary values.
+/// On error `counts` holds the counts of the codes before `position`.
+pub fn dict_counts(codes: &[u32], counts: &mut [u64]) -> Result<(), IndexError> {
+    counts.fill(0);
+    for (position, &code) in codes.iter().enumerate() {
+        *counts.get_mut(code as usize).ok_or(IndexError::OutOfRange { position })? += 1;
+    }
+    Ok(())
+}
+
+/// out[indices[i]] = values[i], the inverse of `gather`. Duplicate targets
+/// overwrite in order unless `reject_duplicates`. Nothing is written on error.
+pub fn scatter<T: Copy>(values: &[T], indices: &[u32], out: &mut [T], reject_duplicates: bool) -> Result<(), IndexError> {
+    // Validate everything first so a rejected call leaves `out` untouched
+    if let Some(position) = indices.iter().position(|&i| i as usize >= out.len()) {
+        return Err(IndexError::OutOfRange { position });
+    }
+    if reject_duplicates {
+        let mut seen = vec![0u64; out.len().div_ceil(64)];
+        for &i in indices {
+            let (word, bit) = (i as usize / 64, 1u64 << (i % 64));
+            if seen[word] & bit != 0 {
+                return Err(IndexError::Duplicate);
+            }
+            seen[word] |= bit;
+        }
+    }
+    for (&v, &i) in values.iter().zip(indices) {
+        out[i as usize] = v;
+    }
+    Ok(())
+}
+
+/// Marks visited perm entries during validation and the cycle walk
+const PERM_MARK: u32 = 1 << 31;
+
+/// Reorder `values` so values'[i] = values[perm[i]] by following cycles. The
+/// high bit of each `perm` entry is borrowed as a visited flag and cleared
+/// again, so `perm` is unchanged on return. Values are untouched on error.
+pub fn apply_permutation<T: Copy>(values: &mut [T], perm: &mut [u32]) -> Result<(), IndexError> {
+    let len = values.len().min(perm.len());
+    if len > PERM_MARK as usize {
+        return Err(IndexError::TooLong);
+    }
+
+    // Entries with the high bit set are out of range anyway, and would be
+    // misread once it is in use as a flag
+    if let Some(position) = perm[..len].iter().position(|&p| p & PERM_MARK != 0) {
+        return Err(IndexError::OutOfRange { position });
+    }
+
+    // Validate with the high bit as a "seen as a target" flag, then clear it
+    let mut status = Ok(());
+    for i in 0..len {
+        let target = (perm[i] & !PERM_MARK) as usize;
+        if target >= len {
+            status = Err(IndexError::OutOfRange { position: i });
+            break;
+        }
+        if perm[target] & PERM_MARK != 0 {
+            status = Err(IndexError::Duplicate);
+            break;
+        }
+        perm[target] |= PERM_MARK;
+    }
+    for p in perm[..len].iter_mut() {
+        *p &= !PERM_MARK;
+    }
+    status?;
+
+    // Follow each cycle once, holding a single value aside; the high bit now
+    // marks positions already written
+    for start in 0..len {
+        if perm[start] & PERM_MARK != 0 {
+            continue;
+        }
+        let held = values[start];
+        let mut j = start;
+        loop {
+            let k = perm[j] as usize;
+            perm[j] |= PERM_MARK;
+            if k == start {
+                values[j] = held;
+                break;
+            }
+            values[j] = values[k];
+            j = k;
+        }
+    }
+    for p in perm[..len].iter_mut() {
+        *p &= !PERM_MARK;
+    }
+    Ok(())
+}
+
+// ============================================================
+// Packed bitmaps
+// ============================================================
+
+// Arrow's layout: element i is bit i % 8 of byte i / 8, so element 0 is the
+// least significant bit of byte 0. A bitmap of `len_bits` bits spans
+// `bitmap_bytes(len_bits)` bytes. Bits past `len_bits` in the last byte are
+// ignored on input and written as zero on output.
+
+pub fn bitmap_bytes(len_bits: usize) -> usize {
+    len_bits.div_ceil(8)
+}
+
+fn clear_tail(bitmap: &mut [u8], len_bits: usize) {
+    let tail = len_bits % 8;
+    if tail != 0 {
+        bitmap[len_bits / 8] &= (1u8 << tail) - 1;
+    }
+}
+
+fn in_range(v: f64, lo: f64, hi: f64) -> bool {
+    lo <= v && v <= hi
+}
+
+/// out[i] = 1 if lo <= values[i] <= hi, else 0; NaN is never in range.
+pub fn mask_from_range_f64(values: &[f64], lo: f64, hi: f64, out: &mut [u8]) {
+    for (dst, &v) in out.iter_mut().zip(values) {
+        *dst = in_range(v, lo, hi) as u8;
+    }
+}
+
+/// `mask_from_range_f64` as a packed bitmap of `values.len()` bits.
+pub fn mask_from_range_f64_packed(values: &[f64], lo: f64, hi: f64, out: &mut [u8]) {
+    for (dst, chunk) in out.iter_mut().zip(values.chunks(8)) {
+        *dst = chunk
+            .iter()
+            .enumerate()
+            .fold(0u8, |byte, (j, &v)| byte | (in_range(v, lo, hi) as u8) << j);
+    }
+}
+
+fn bitmap_zip(a: &[u8], b: &[u8], out: &mut [u8], len_bits: usize, op: impl Fn(u64, u64) -> u64) {
+    let n = bitmap_bytes(len_bits);
+    let (a, b, out) = (&a[..n], &b[..n], &mut out[..n]);
+    let whole = n / 8 * 8;
+    for ((dst, x), y) in out[..whole].chunks_exact_mut(8).zip(a.chunks_exact(8)).zip(b.chunks_exact(8)) {
+        let word = op(u64::from_le_bytes(x.try_into().unwrap()), u64::from_le_bytes(y.try_into().unwrap()));
+        dst.copy_from_slice(&word.to_le_bytes());
+    }
+    for i in whole..n {
+        out[i] = op(a[i] as u64, b[i] as u64) as u8;
+    }
+    clear_tail(out, len_bits);
+}
+
+pub fn bitmap_and(a: &[u8], b: &[u8], out: &mut [u8], len_bits: usize) {
+    bitmap_zip(a, b, out, len_bits, |x, y| x & y);
+}
+
+pub fn bitmap_or(a: &[u8], b: &[u8], out: &mut [u8], len_bits: usize) {
+    bitmap_zip(a, b, out, len_bits, |x, y| x | y);
+}
+
+pub fn bitmap_not(a: &[u8], out: &mut [u8], len_bits: usize) {
+    bitmap_zip(a, a, out, len_bits, |x, _| !x);
+}
+
+/// Set bits among the first `len_bits`.
+pub fn bitmap_count(bitmap: &[u8], len_bits: usize) -> usize {
+    let full = len_bits / 8;
+    let mut words = bitmap[..full].chunks_exact(8);
+    let mut count: usize = (&mut words)
+        .map(|w| u64::from_le_bytes(w.try_into().unwrap()).count_ones() as usize)
+        .sum();
+    count += words.remainder().iter().map(|b| b.count_ones() as usize).sum::<usize>();
+    let tail = len_bits % 8;
+    if tail != 0 {
+        count += (bitmap[full] & ((1u8 << tail) - 1)).count_ones() as usize;
+    }
+    count
+}
+
+/// Move the values whose bit is set to the front, in order; returns how many.
+/// `bitmap` holds at least `values.len()` bits.
+pub fn filter_bitmap_f64(values: &mut [f64], bitmap: &[u8]) -> usize {
+    let len = values.len();
+    let mut kept = 0;
+    for (base, &byte) in (0..len).step_by(8).zip(bitmap) {
+        let mut bits = byte;
+        while bits != 0 {
+            let i = base + bits.trailing_zeros() as usize;
+            if i >= len {
+                break;
+            }
+            values[kept] = values[i];
+            kept += 1;
+            bits &= bits - 1;
+        }
+    }
+    kept
+}
+
+// ============================================================
+// Rank + percentile
+// ============================================================
+
+/// First index >= `start` where `pred` turns false, assuming it holds on a
+/// prefix of `sorted`. Gallops from `start` so nearby answers cost O(log gap).
+fn gallop(sorted: &[f64], start: usize, pred: impl Fn(f64) -> bool) -> usize {
+    let mut lo = start;
+    let mut step = 1;
+    while lo + step <= sorted.len() && pred(sorted[lo + step - 1]) {
+        lo += step;
+        step *= 2;
+    }
+    let hi = (lo + step).min(sorted.len());
+    lo + sorted[lo..hi].partition_point(|&x| pred(x))
+}
+
+/// Number of elements of ascending `sorted` that are <= `value` (0 for NaN).
+/// NaNs in `sorted` must sit at the end, as `sort_f64` leaves them.
+pub fn rank_f64(sorted: &[f64], value: f64) -> usize {
+    sorted.partition_point(|&x| x <= value)
+}
+
+/// Empirical percentile in [0, 100] of each query against ascending `sorted`:
+/// 100 * (count below + half the count equal) / len. NaN queries (and an
+/// empty reference) give NaN. Ascending runs of queries advance a cursor
+/// instead of searching from scratch.
+pub fn percentile_of_f64(sorted: &[f64], queries: &[f64], out: &mut [f64]) {
+    let len = sorted.len();
+    // Counts of elements < prev and <= prev for the previous query
+    let (mut prev, mut below, mut through) = (f64::NEG_INFINITY, 0, 0);
+    for (dst, &q) in out.iter_mut().zip(queries) {
+        if q.is_nan() || len == 0 {
+            *dst = f64::NAN;
+            continue;
+        }
+        if q < prev {
+            below = 0;
+            through = 0;
+        }
+        below = gallop(sorted, below, |x| x < q);
+        through = gallop(sorted, through.max(below), |x| x <= q);
+        prev = q;
+        *dst = 100.0 * (below as f64 + (through - below) as f64 * 0.5) / len as f64;
+    }
+}
+
+/// Inputs with more non-NaN values than this have their quantile edges read
+/// from a sorted random sample unless exact edges are asked for.
+pub const QUANTILE_SAMPLE_MIN: usize = 1 << 20;
+
+/// Values drawn (with replacement, from a fixed seed) for sampled edges: the
+/// bucket shares it gives are off by about 1/sqrt(QUANTILE_SAMPLE_LEN) of a
+/// bucket's own share at worst.
+pub const QUANTILE_SAMPLE_LEN: usize = 1 << 16;
+
+/// The non-NaN `values` quantiles are read from: all of them, or past
+/// QUANTILE_SAMPLE_MIN (and without `exact`) a random sample.
+fn quantile_data(values: &[f64], exact: bool) -> Vec<f64> {
+    let data: Vec<f64> = values.iter().copied().filter(|x| !x.is_nan()).collect();
+    if exact || data.len() <= QUANTILE_SAMPLE_MIN {
+        return data;
+    }
+    let mut state = 0x9E37_79B9_7F4A_7C15u64;
+    let mut sample = Vec::with_capacity(QUANTILE_SAMPLE_LEN);
+    for _ in 0..QUANTILE_SAMPLE_LEN {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        sample.push(data[(state % data.len() as u64) as usize]);
+    }
+    sample
+}
+
+/// Fill `out` with the k - 1 = `out.len()` interior edges splitting the
+/// non-NaN `values` into k equal-frequency buckets: edge i is the value of
+/// rank floor(i * n / k), so bucket i holds the values in [edge i-1, edge i).
+/// Exact edges come from successive partition-based selections rather than a
+/// full sort; past QUANTILE_SAMPLE_MIN values (and without `exact`) they come
+/// from a sample. Ties can make neighbouring edges equal: the distinct edges
+/// are written first and the rest padded with the largest value. Returns the
+/// distinct edge count (0 with `out` all NaN when every value is NaN).
+pub fn quantile_edges_f64(values: &[f64], out: &mut [f64], exact: bool) -> usize {
+    if out.is_empty() {
+        return 0;
+    }
+    let mut data = quantile_data(values, exact);
+    if data.is_empty() {
+        out.fill(f64::NAN);
+        return 0;
+    }
+    let (n, k) = (data.len(), out.len() + 1);
+    let max = data.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    // Each selection leaves everything after its position >= it, so the next
+    // one only has to search that suffix
+    let mut from = 0;
+    for (i, edge) in out.iter_mut().enumerate() {
+        let rank = ((i as u128 + 1) * n as u128 / k as u128) as usize;
+        let rank = rank.min(n - 1);
+        let (_, &mut nth, _) = data[from..].select_nth_unstable_by(rank - from, f64::total_cmp);
+        *edge = nth;
+        from = rank;
+    }
+    let mut distinct = 0;
+    for i in 0..out.len() {
+        if distinct == 0 || out[i] != out[distinct - 1] {
+            out[distinct] = out[i];
+            distinct += 1;
+        }
+    }
+    out[distinct..].fill(max);
+    distinct
+}
+
+/// The values `filter_quantile_f64` keeps, by where they fall against
+/// quantiles of the column, each q in [0, 1].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QuantileFilter {
+    /// Values greater than the q-quantile
+    Above(f64),
+    /// Values less than the q-quantile
+    Below(f64),
+    /// Values in [q_lo-quantile, q_hi-quantile), q_lo <= q_hi
+    Between(f64, f64),
+}
+
+/// Keep the `values` that pass `filter`, moved to the front in order with
+/// `companion` (when given, the same length) moved in lockstep; returns how
+/// many were kept and the (lo, hi) thresholds, equal unless Between. The
+/// q-quantile is the value of rank floor(q * n) among the n non-NaN values,
+/// clamped to the largest, placed as `quantile_edges_f64` places an edge and
+/// sampled past the same size unless `exact`. NaNs never pass; the
+/// thresholds are NaN when every value is NaN.
+pub fn filter_quantile_f64(
+    values: &mut [f64],
+    mut companion: Option<&mut [i64]>,
+    filter: QuantileFilter,
+    exact: bool,
+) -> (usize, (f64, f64)) {
+    let mut data = quantile_data(values, exact);
+    if data.is_empty() {
+        return (0, (f64::NAN, f64::NAN));
+    }
+    let n = data.len();
+    let rank = |q: f64| ((q * n as f64) as usize).min(n - 1);
+    let (q_lo, q_hi) = match filter {
+        QuantileFilter::Above(q) | QuantileFilter::Below(q) => (q, q),
+        QuantileFilter::Between(lo, hi) => (lo, hi),
+    };
+    let (lo_rank, hi_rank) = (rank(q_lo), rank(q_hi).max(rank(q_lo)));
+    let (_, &mut lo, _) = data.select_nth_unstable_by(lo_rank, f64::total_cmp);
+    // Everything after lo_rank is now >= lo, so hi is in that suffix
+    let (_, &mut hi, _) = data[lo_rank..].select_nth_unstable_by(hi_rank - lo_rank, f64::total_cmp);
+    let keep = |x: f64| match filter {
+        QuantileFilter::Above(_) => x > lo,
+        QuantileFilter::Below(_) => x < lo,
+        QuantileFilter::Between(..) => lo <= x && x < hi,
+    };
+    let mut kept = 0;
+    for i in 0..values.len() {
+        if keep(values[i]) {
+            values[kept] = values[i];
+            if let Some(companion) = companion.as_deref_mut() {
+                companion[kept] = companion[i];
+            }
+            kept += 1;
+        }
+    }
+    (kept, (lo, hi))
+}
+
+// ============================================================
+// Column profile
+// ============================================================
+
+/// Most frequent values a profile reports.
+pub const PROFILE_TOP_K: usize = 5;
+
+/// Counters in the SpaceSaving sketch behind the top values: every value
+/// making up more than 1/PROFILE_SKETCH_SLOTS of the counted values has one.
+const PROFILE_SKETCH_SLOTS: usize = 64;
+
+/// HyperLogLog register index bits: 4096 registers, for a distinct estimate
+/// within about 1.04 / sqrt(4096) = 1.6% (one standard error).
+const HLL_BITS: u32 = 12;
+
+/// Summary of one column. Statistics cover the counted values: those whose
+/// validity bit is set and that are not NaN.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+    pub count: u64,
+    /// Values whose validity bit is clear
+    pub nulls: u64,
+    /// Valid NaNs (always 0 for i64)
+    pub nans: u64,
+    /// HyperLogLog estimate of the distinct counted values (-0.0 equals 0.0)
+    pub distinct: f64,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    /// Sample standard deviation (n - 1 denominator); NaN below two values
+    pub stddev: f64,
+    /// 25th, 50th and 75th percentiles, each the value at rank
+    /// floor(q * (n - 1)) in sorted order; NaN unless `exact_pass` was asked for
+    pub quartiles: [f64; 3],
+    /// Up to PROFILE_TOP_K most frequent values with their counts, most
+    /// frequent first and ties smallest first. Only values making up more
+    /// than 1/PROFILE_SKETCH_SLOTS of the count are sure to be found. Counts
+    /// are upper bounds from the sketch, or exact with the second pass.
+    pub top: Vec<(f64, u64)>,
+}
+
+/// What profiling and the min/max family need of an element type.
+pub trait Scalar: Copy {
+    /// Equal values share a key; None for NaN
+    fn key(self) -> Option<u64>;
+    fn to_f64(self) -> f64;
+    fn order(&self, other: &Self) -> cmp::Ordering;
+}
+
+impl Scalar for f64 {
+    fn key(self) -> Option<u64> {
+        (!self.is_nan()).then(|| (self + 0.0).to_bits())
+    }
+    fn to_f64(self) -> f64 {
+        self
+    }
+    fn order(&self, other: &Self) -> cmp::Ordering {
+        self.total_cmp(other)
+    }
+}
+
+impl Scalar for i64 {
+    fn key(self) -> Option<u64> {
+        Some(self as u64)
+    }
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+    fn order(&self, other: &Self) -> cmp::Ordering {
+        self.cmp(other)
+    }
+}
+
+/// One pass over `values[i]` whose bit is set in `validity` (every value
+/// without one); returns how many were skipped.
+fn each_valid<T: Copy>(values: &[T], validity: Option<&[u8]>, mut f: impl FnMut(T)) -> u64 {
+    each_valid_at(values, validity, |_, v| f(v))
+}
+
+/// `each_valid` passing each value's position too.
+fn each_valid_at<T: Copy>(values: &[T], validity: Option<&[u8]>, mut f: impl FnMut(usize, T)) -> u64 {
+    let Some(bitmap) = validity else {
+        values.iter().enumerate().for_each(|(i, &v)| f(i, v));
+        return 0;
+    };
+    let mut skipped = 0;
+    for (i, &v) in values.iter().enumerate() {
+        if bitmap[i / 8] & (1 << (i % 8)) != 0 {
+            f(i, v);
+        } else {
+            skipped += 1;
+        }
+    }
+    skipped
+}
+
+/// SpaceSaving heavy hitters: a full sketch hands the least counted slot
+/// to a new value, which inherits that count, so counts only overestimate.
+struct SpaceSaving<T> {
+    slots: Vec<(u64, T, u64)>,
+    index: std::collections::HashMap<u64, usize>,
+}
+
+impl<T: Scalar> SpaceSaving<T> {
+    fn new() -> Self {
+        SpaceSaving { slots: Vec::with_capacity(PROFILE_SKETCH_SLOTS), index: Default::default() }
+    }
+
+    fn add(&mut self, key: u64, value: T) {
+        if let Some(&i) = self.index.get(&key) {
+            self.slots[i].2 += 1;
+        } else if self.slots.len() < PROFILE_SKETCH_SLOTS {
+            self.index.insert(key, self.slots.len());
+            self.slots.push((key, value, 1));
+        } else {
+            let i = (0..self.slots.len()).min_by_key(|&i| self.slots[i].2).unwrap_or(0);
+            self.index.remove(&self.slots[i].0);
+            self.index.insert(key, i);
+            self.slots[i] = (key, value, self.slots[i].2 + 1);
+        }
+    }
+
+    fn top(mut self) -> Vec<(f64, u64)> {
+        self.slots.sort_by(|a, b| b.2.cmp(&a.2).then(a.1.order(&b.1)));
+        self.slots.iter().take(PROFILE_TOP_K).map(|&(_, value, count)| (value.to_f64(), count)).collect()
+    }
+}
+
+fn hll_add(registers: &mut [u8], key: u64) {
+    let hash = xxh64::avalanche(xxh64::round(0, key));
+    let rank = (hash << HLL_BITS).leading_zeros().min(64 - HLL_BITS) + 1;
+    let register = &mut registers[(hash >> (64 - HLL_BITS)) as usize];
+    *register = (*register).max(rank as u8);
+}
+
+/// The HyperLogLog estimate, switching to linear counting over empty
+/// registers at small cardinalities where that is more accurate.
+fn hll_estimate(registers: &[u8]) -> f64 {
+    let m = registers.len() as f64;
+    let alpha = 0.7213 / (1.0 + 1.079 / m);
+    let raw = alpha * m * m / registers.iter().map(|&r| (-(r as f64)).exp2()).sum::<f64>();
+    let empty = registers.iter().filter(|&&r| r == 0).count();
+    if raw <= 2.5 * m && empty > 0 {
+        m * (m / empty as f64).ln()
+    } else {
+        raw
+    }
+}
+
+/// Profile `values`, where `validity` is an Arrow bitmap of at least
+/// `values.len()` bits (None: all valid). One pass gathers everything but
+/// the quartiles; `exact_pass` adds a second that reads the quartiles off a
+/// sample (every counted value up to QUANTILE_SAMPLE_MIN of them, else every
+/// k-th, keeping about QUANTILE_SAMPLE_LEN) and recounts the sketch's
+/// candidates exactly.
+pub fn profile_f64(values: &[f64], validity: Option<&[u8]>, exact_pass: bool) -> Profile {
+    profile(values, validity, exact_pass)
+}
+
+/// `profile_f64` for i64 columns. Statistics are computed in f64, so values
+/// beyond 2^53 in magnitude are reported rounded.
+pub fn profile_i64(values: &[i64], validity: Option<&[u8]>, exact_pass: bool) -> Profile {
+    profile(values, validity, exact_pass)
+}
+
+fn profile<T: Scalar>(values: &[T], validity: Option<&[u8]>, exact_pass: bool) -> Profile {
+    let (mut count, mut nans) = (0u64, 0u64);
+    let (mut mean, mut m2) = (0.0, 0.0);
+    let (mut min, mut max) = (None::<T>, None::<T>);
+    let mut registers = vec![0u8; 1 << HLL_BITS];
+    let mut sketch = SpaceSaving::new();
+    let nulls = each_valid(values, validity, |v| {
+        let Some(key) = v.key() else {
+            nans += 1;
+            return;
+        };
+        count += 1;
+        // Welford's update
+        let x = v.to_f64();
+        let delta = x - mean;
+        mean += delta / count as f64;
+        m2 += delta * (x - mean);
+        if improves(min, v, cmp::Ordering::Less) {
+            min = Some(v);
+        }
+        if improves(max, v, cmp::Ordering::Greater) {
+            max = Some(v);
+        }
+        hll_add(&mut registers, key);
+        sketch.add(key, v);
+    });
+
+    let mut quartiles = [f64::NAN; 3];
+    if exact_pass && count > 0 {
+        let stride = if count as usize <= QUANTILE_SAMPLE_MIN { 1 } else { (count as usize).div_ceil(QUANTILE_SAMPLE_LEN) };
+        let mut sample = Vec::with_capacity((count as usize).div_ceil(stride));
+        for slot in sketch.slots.iter_mut() {
+            slot.2 = 0;
+        }
+        let mut seen = 0usize;
+        each_valid(values, validity, |v| {
+            let Some(key) = v.key() else { return };
+            if let Some(&i) = sketch.index.get(&key) {
+                sketch.slots[i].2 += 1;
+            }
+            if seen.is_multiple_of(stride) {
+                sample.push(v);
+            }
+            seen += 1;
+        });
+        // Ascending ranks, so each selection only searches the suffix the
+        // previous one left
+        let n = sample.len();
+        let mut from = 0;
+        for (q, out) in [0.25, 0.5, 0.75].into_iter().zip(&mut quartiles) {
+            let rank = ((q * (n - 1) as f64) as usize).max(from);
+            let (_, &mut nth, _) = sample[from..].select_nth_unstable_by(rank - from, T::order);
+            *out = nth.to_f64();
+            from = rank;
+        }
+    }
+
+    Profile {
+        count,
+        nulls,
+        nans,
+        distinct: if count == 0 { 0.0 } else { hll_estimate(&registers).round().clamp(1.0, count as f64) },
+        min: min.map_or(f64::NAN, T::to_f64),
+        max: max.map_or(f64::NAN, T::to_f64),
+        mean: if count == 0 { f64::NAN } else { mean },
+        stddev: if count < 2 { f64::NAN } else { (m2 / (count - 1) as f64).sqrt() },
+        quartiles,
+        top: sketch.top(),
+    }
+}
+
+// ============================================================
+// Pair packing
+// ============================================================
+
+// Plain packing stores both halves as raw u32 bits: (hi << 32) | lo. The
+// order-preserving form additionally flips the sign bit of `lo`, so signed
+// i64 order of the packed keys (sort_i64) equals lexicographic signed
+// (hi, lo) order: the high word already compares as a signed i32, and
+// lo ^ 0x8000_0000 maps i32::MIN..=i32::MAX onto 0..=u32::MAX.
+
+const PAIR_LO_FLIP: u32 = 0x8000_0000;
+
+pub fn pack_i32_pair(hi: i32, lo: i32, order_preserving: bool) -> i64 {
+    let flip = if order_preserving { PAIR_LO_FLIP } else { 0 };
+    (((hi as u32 as u64) << 32) | (lo as u32 ^ flip) as u64) as i64
+}
+
+pub fn unpack_i32_pair(key: i64, order_preserving: bool) -> (i32, i32) {
+    let flip = if order_preserving { PAIR_LO_FLIP } else { 0 };
+    ((key >> 32) as i32, (key as u32 ^ flip) as i32)
+}
+
+pub fn pack_i32_pairs(hi: &[i32], lo: &[i32], out: &mut [i64], order_preserving: bool) {
+    for ((dst, &h), &l) in out.iter_mut().zip(hi).zip(lo) {
+        *dst = pack_i32_pair(h, l, order_preserving);
+    }
+}
+
+pub fn unpack_i32_pairs(src: &[i64], out_hi: &mut [i32], out_lo: &mut [i32], order_preserving: bool) {
+    for ((&v, h), l) in src.iter().zip(out_hi).zip(out_lo) {
+        (*h, *l) = unpack_i32_pair(v, order_preserving);
+    }
+}
+
+// ============================================================
+// Weighted aggregates
+// ============================================================
+
+/// Neumaier's variant of Kahan summation: unlike `sum_f64`'s loop it also
+/// keeps the low bits when an addend is larger than the running sum, which
+/// happens constantly once products of mixed magnitudes are involved.
+#[derive(Default)]
+struct CompensatedSum {
+    sum: f64,
+    comp: f64,
+}
+
+impl CompensatedSum {
+    fn add(&mut self, x: f64) {
+        let t = self.sum + x;
+        self.comp += if self.sum.abs() >= x.abs() { (self.sum - t) + x } else { (x - t) + self.sum };
+        self.sum = t;
+    }
+
+    /// Add a * b exactly up to final rounding: the fused multiply-add recovers
+    /// the rounding error of the product, which is summed separately.
+    fn add_product(&mut self, a: f64, b: f64) {
+        let p = a * b;
+        self.add(p);
+        if p.is_finite() {
+            self.comp += a.mul_add(b, -p);
+        }
+    }
+
+    fn value(&self) -> f64 {
+        self.sum + self.comp
+    }
+}
+
+fn weighted(values: &[f64], weights: &[f64], skip_nan: bool) -> (f64, f64) {
+    let (mut num, mut den) = (CompensatedSum::default(), CompensatedSum::default());
+    for (&x, &w) in values.iter().zip(weights) {
+        if skip_nan && (x.is_nan() || w.is_nan()) {
+            continue;
+        }
+        num.add_product(x, w);
+        den.add(w);
+    }
+    (num.value(), den.value())
+}
+
+/// Σ values[i] * weights[i], compensated for both product and sum rounding.
+pub fn weighted_sum_f64(values: &[f64], weights: &[f64]) -> f64 {
+    weighted(values, weights, false).0
+}
+
+/// Σ w·x / Σ w with both sums compensated; NaN when the total weight is 0.
+pub fn weighted_mean_f64(values: &[f64], weights: &[f64]) -> f64 {
+    let (num, den) = weighted(values, weights, false);
+    if den == 0.0 { f64::NAN } else { num / den }
+}
+
+/// `weighted_sum_f64` skipping pairs where either side is NaN.
+pub fn weighted_sum_masked_f64(values: &[f64], weights: &[f64]) -> f64 {
+    weighted(values, weights, true).0
+}
+
+/// `weighted_mean_f64` skipping pairs where either side is NaN.
+pub fn weighted_mean_masked_f64(values: &[f64], weights: &[f64]) -> f64 {
+    let (num, den) = weighted(values, weights, true);
+    if den == 0.0 { f64::NAN } else { num / den }
+}
+
+/// Σ x², compensated the same way (RMS = sqrt(sum_squares / len)).
+pub fn sum_squares_f64(data: &[f64]) -> f64 {
+    let mut acc = CompensatedSum::default();
+    for &x in data {
+        acc.add_product(x, x);
+    }
+    acc.value()
+}
+
+// ============================================================
+// Grouped partials
+// ============================================================
+
+/// One side's group-by partials: strictly ascending `keys`, with the sum and
+/// row count of each group at the same index.
+#[derive(Clone, Copy)]
+pub struct GroupPartials<'a> {
+    pub keys: &'a [i64],
+    pub sums: &'a [f64],
+    pub counts: &'a [u64],
+}
+
+/// Output columns for `merge_groups_f64`, all of the same length.
+pub struct GroupPartialsMut<'a> {
+    pub keys: &'a mut [i64],
+    pub sums: &'a mut [f64],
+    pub counts: &'a mut [u64],
+}
+
+impl GroupPartialsMut<'_> {
+    fn put(&mut self, at: usize, key: i64, sum: f64, count: u64) {
+        if at < self.keys.len() {
+            self.keys[at] = key;
+            self.sums[at] = sum;
+            self.counts[at] = count;
+        }
+    }
+}
+
+/// Combine two sets of partials into `out`: keys present on both sides get
+/// their sums and counts added, the rest are copied, and the result stays
+/// ascending so it can be merged again. Returns the merged group count.
+/// Unsorted input gives a merge of runs rather than of groups.
+pub fn merge_groups_f64(a: GroupPartials, b: GroupPartials, mut out: GroupPartialsMut) -> usize {
+    let (mut i, mut j, mut n) = (0, 0, 0);
+    while i < a.keys.len() && j < b.keys.len() {
+        match a.keys[i].cmp(&b.keys[j]) {
+            cmp::Ordering::Less => {
+                out.put(n, a.keys[i], a.sums[i], a.counts[i]);
+                i += 1;
+            }
+            cmp::Ordering::Greater => {
+                out.put(n, b.keys[j], b.sums[j], b.counts[j]);
+                j += 1;
+            }
+            cmp::Ordering::Equal => {
+                out.put(n, a.keys[i], a.sums[i] + b.sums[j], a.counts[i].saturating_add(b.counts[j]));
+                i += 1;
+                j += 1;
+            }
+        }
+        n += 1;
+    }
+    for (side, from) in [(a, i), (b, j)] {
+        for k in from..side.keys.len() {
+            out.put(n, side.keys[k], side.sums[k], side.counts[k]);
+            n += 1;
+        }
+    }
+    n
+}
+
+// ============================================================
+// Time buckets
+// ============================================================
+
+/// Start of the `width`-long bucket holding `ts`, buckets being aligned to
+/// `origin`: origin + floor((ts - origin) / width) * width. Plain integer
+/// arithmetic on epoch units, so time zones and DST never enter into it,
+/// and floor (not truncation) keeps timestamps before `origin` in the bucket
+/// below. None when `width` is not positive or the start is not an i64.
+pub fn time_bucket(ts: i64, width: i64, origin: i64) -> Option<i64> {
+    if width <= 0 {
+        return None;
+    }
+    let (ts, width, origin) = (ts as i128, width as i128, origin as i128);
+    i64::try_from(origin + (ts - origin).div_euclid(width) * width).ok()
+}
+
+// ============================================================
+// String + row hashing
+// ============================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnError {
+    /// String offsets decrease or point past the string bytes
+    Offsets,
+    /// A column has fewer rows than the output
+    TooShort,
+}
+
+/// One key column of `hash_columns`; all columns have the same row count.
+#[derive(Clone, Copy)]
+pub enum Column<'a> {
+    I64(&'a [i64]),
+    F64(&'a [f64]),
+    /// String i is `bytes[offsets[i]..offsets[i + 1]]`
+    Str { bytes: &'a [u8], offsets: &'a [u64] },
+}
+
+fn check_offsets(bytes: &[u8], offsets: &[u64]) -> Result<(), ColumnError> {
+    let monotonic = offsets.windows(2).all(|w| w[0] <= w[1]);
+    let in_bounds = offsets.last().is_none_or(|&end| end <= bytes.len() as u64);
+    if monotonic && in_bounds { Ok(()) } else { Err(ColumnError::Offsets) }
+}
+
+/// XXH64 of `data`, the hash the column hashes build on.
+pub fn hash_xxh64(data: &[u8], seed: u64) -> u64 {
+    xxh64::xxh64(data, seed)
+}
+
+/// XXH64 of each string of an offsets+bytes column (`offsets` holds
+/// `out.len() + 1` entries). Nothing is written on error.
+pub fn hash_strings(bytes: &[u8], offsets: &[u64], seed: u64, out: &mut [u64]) -> Result<(), ColumnError> {
+    check_offsets(bytes, offsets)?;
+    for (dst, w) in out.iter_mut().zip(offsets.windows(2)) {
+        *dst = xxh64::xxh64(&bytes[w[0] as usize..w[1] as usize], seed);
+    }
+    Ok(())
+}
+
+/// Per-column value feeding the row mix. Floats are canonicalized so keys that
+/// compare equal (0.0 / -0.0, any NaN payload) hash equal.
+fn column_value(col: &Column, row: usize, seed: u64) -> u64 {
+    match *col {
+        Column::I64(values) => values[row] as u64,
+        Column::F64(values) => {
+            let v = values[row];
+            if v.is_nan() {
+                f64::NAN.to_bits()
+            } else {
+                (v + 0.0).to_bits()
+            }
+        }
+        Column::Str { bytes, offsets } => {
+            xxh64::xxh64(&bytes[offsets[row] as usize..offsets[row + 1] as usize], seed)
+        }
+    }
+}
+
+/// One hash per row over the key columns. Columns are folded in order with
+/// an XXH64 round, so swapping columns changes the hash, then avalanched
+/// together with the column count. Nothing is written on error.
+pub fn hash_columns(columns: &[Column], seed: u64, out: &mut [u64]) -> Result<(), ColumnError> {
+    let rows = out.len();
+    for col in columns {
+        let fits = match *col {
+            Column::I64(values) => values.len() >= rows,
+            Column::F64(values) => values.len() >= rows,
+            Column::Str { bytes, offsets } => {
+                check_offsets(bytes, offsets)?;
+                offsets.len() > rows
+            }
+        };
+        if !fits {
+            return Err(ColumnError::TooShort);
+        }
+    }
+    for (row, dst) in out.iter_mut().enumerate() {
+        let mut h = seed;
+        for col in columns {
+            h = xxh64::round(h, column_value(col, row, seed)).rotate_left(27);
+        }
+        *dst = xxh64::avalanche(h ^ columns.len() as u64);
+    }
+    Ok(())
+}
+
+// ============================================================
+// Table gather + scatter
+// ============================================================
+
+// Permute every column of a table in one call: the index array is checked
+// once, before any column is written, and columns are independent, so they
+// can move on several threads.
+
+/// One column of a table and where it moves to; both sides hold one entry
+/// (or one bit) per index.
+pub enum ColumnMove<'a> {
+    I64(&'a [i64], &'a mut [i64]),
+    F64(&'a [f64], &'a mut [f64]),
+    /// Packed bitmaps in the layout of `bitmap_bytes`
+    Bitmap(&'a [u8], &'a mut [u8]),
+}
+
+fn bit(bitmap: &[u8], i: usize) -> u8 {
+    (bitmap[i / 8] >> (i % 8)) & 1
+}
+
+impl ColumnMove<'_> {
+    /// out[i] = in[indices[i]], indices in range.
+    fn gather(&mut self, indices: &[u32]) {
+        match self {
+            ColumnMove::I64(values, out) => gather_unchecked(values, indices, out),
+            ColumnMove::F64(values, out) => gather_unchecked(values, indices, out),
+            ColumnMove::Bitmap(bits, out) => {
+                for (byte, rows) in out.iter_mut().zip(indices.chunks(8)) {
+                    *byte = rows.iter().enumerate().fold(0, |acc, (j, &row)| acc | bit(bits, row as usize) << j);
+                }
+            }
+        }
+    }
+
+    /// out[indices[i]] = in[i], indices in range and distinct.
+    fn scatter(&mut self, indices: &[u32]) {
+        match self {
+            ColumnMove::I64(values, out) => scatter_unchecked(values, indices, out),
+            ColumnMove::F64(values, out) => scatter_unchecked(values, indices, out),
+            ColumnMove::Bitmap(bits, out) => {
+                out.fill(0);
+                for (i, &row) in indices.iter().enumerate() {
+                    out[row as usize / 8] |= bit(bits, i) << (row % 8);
+                }
+            }
+        }
+    }
+}
+
+fn gather_unchecked<T: Copy>(values: &[T], indices: &[u32], out: &mut [T]) {
+    for (dst, &i) in out.iter_mut().zip(indices) {
+        *dst = values[i as usize];
+    }
+}
+
+fn scatter_unchecked<T: Copy>(values: &[T], indices: &[u32], out: &mut [T]) {
+    for (&v, &i) in values.iter().zip(indices) {
+        out[i as usize] = v;
+    }
+}
+
+/// Run `f` on every column, split over up to `threads` threads (0 = available
+/// parallelism).
+fn each_column(columns: &mut [ColumnMove], threads: usize, f: impl Fn(&mut ColumnMove) + Sync) {
+    let threads = resolve_threads(threads).min(columns.len());
+    if threads <= 1 {
+        columns.iter_mut().for_each(f);
+        return;
+    }
+    let per_thread = columns.len().div_ceil(threads);
+    std::thread::scope(|s| {
+        for group in columns.chunks_mut(per_thread) {
+            s.spawn(|| group.iter_mut().for_each(&f));
+        }
+    });
+}
+
+/// Move every column so out[i] = in[indices[i]] (the argsort convention),
+/// on up to `threads` threads. Nothing is written when an index is not below
+/// `indices.len()`.
+pub fn gather_columns(indices: &[u32], columns: &mut [ColumnMove], threads: usize) -> Result<(), IndexError> {
+    if let Some(position) = indices.iter().position(|&i| i as usize >= indices.len()) {
+        return Err(IndexError::OutOfRange { position });
+    }
+    each_column(columns, threads, |column| column.gather(indices));
+    Ok(())
+}
+
+/// Move every column so out[indices[i]] = in[i], the inverse of
+/// `gather_columns`, on up to `threads` threads. `indices` must be a
+/// permutation, so every output row is written once; nothing is written
+/// when it is not.
+pub fn scatter_columns(indices: &[u32], columns: &mut [ColumnMove], threads: usize) -> Result<(), IndexError> {
+    let len = indices.len();
+    let mut seen = vec![0u64; len.div_ceil(64)];
+    for (position, &i) in indices.iter().enumerate() {
+        if i as usize >= len {
+            return Err(IndexError::OutOfRange { position });
+        }
+        let (word, bit) = (i as usize / 64, 1u64 << (i % 64));
+        if seen[word] & bit != 0 {
+            return Err(IndexError::Duplicate);
+        }
+        seen[word] |= bit;
+    }
+    each_column(columns, threads, |column| column.scatter(indices));
+    Ok(())
+}
+
+// ============================================================
+// As-of join
+// ============================================================
+
+/// Which right row an as-of join matches to a left timestamp t.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsofDirection {
+    /// The last row at or before t
+    Backward,
+    /// The first row at or after t
+    Forward,
+    /// Whichever of those two is closer, backward on a tie
+    Nearest,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsofError {
+    LeftUnsorted,
+    RightUnsorted,
+}
+
+/// For each of the ascending `left` timestamps, the index into ascending
+/// `right` of its match in `direction` that lies within `tolerance` of it,
+/// or -1. Of equal right timestamps, backward matches take the last and
+/// forward matches the first. Two cursors only move forward, so this is
+/// O(left + right). Nothing is written when either side is unsorted.
+pub fn asof_join_i64(left: &[i64], right: &[i64], tolerance: u64, direction: AsofDirection, out: &mut [i64]) -> Result<(), AsofError> {
+    if !left.is_sorted() {
+        return Err(AsofError::LeftUnsorted);
+    }
+    if !right.is_sorted() {
+        return Err(AsofError::RightUnsorted);
+    }
+    // right[..below] < t and right[..upto] <= t
+    let (mut below, mut upto) = (0, 0);
+    for (dst, &t) in out.iter_mut().zip(left) {
+        while below < right.len() && right[below] < t {
+            below += 1;
+        }
+        upto = upto.max(below);
+        while upto < right.len() && right[upto] <= t {
+            upto += 1;
+        }
+        let within = |i: usize| (right[i].abs_diff(t) <= tolerance).then_some(i);
+        let backward = upto.checked_sub(1).and_then(within);
+        let forward = (below < right.len()).then_some(below).and_then(within);
+        let matched = match direction {
+            AsofDirection::Backward => backward,
+            AsofDirection::Forward => forward,
+            AsofDirection::Nearest => match (backward, forward) {
+                (Some(b), Some(f)) => Some(if t.abs_diff(right[b]) <= right[f].abs_diff(t) { b } else { f }),
+                (b, f) => b.or(f),
+            },
+        };
+        *dst = matched.map_or(-1, |i| i as i64);
+    }
+    Ok(())
+}
+
+// ============================================================
+// Sampling
+// ============================================================
+
+// Samples are drawn from a SplitMix64 stream seeded by the caller, so equal
+// inputs and seeds give equal samples on every platform. Sampled indices are
+// written in ascending order, ready for `gather`.
+
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in the open interval (0, 1), so its log is finite and negative.
+    fn next_open01(&mut self) -> f64 {
+        ((self.next_u64() >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+    }
+
+    /// Uniform in 0..n, n > 0.
+    fn below(&mut self, n: u64) -> u64 {
+        ((self.next_u64() as u128 * n as u128) >> 64) as u64
+    }
+}
+
+/// A reservoir entry keyed by ln(u) / weight; the sample keeps the largest keys.
+#[derive(Clone, Copy)]
+struct Keyed {
+    key: f64,
+    index: u32,
+}
+
+impl PartialEq for Keyed {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Keyed {}
+
+impl PartialOrd for Keyed {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Keyed {
+    /// Reversed, so a BinaryHeap of them has the smallest key on top
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        other.key.total_cmp(&self.key).then(other.index.cmp(&self.index))
+    }
+}
+
+/// Weighted sample without replacement of up to `out.len()` indices of
+/// `weights` (len <= 2^32), each draw taking a row with probability
+/// proportional to its weight among those left. Rows whose weight is not
+/// finite and positive, or whose value in `values` (if given, as long as
+/// `weights`) is NaN, are never sampled. Returns how many indices were
+/// written: `out.len()`, or every eligible row when there are fewer.
+///
+/// Efraimidis and Spirakis' A-ExpJ: one pass keeping the rows with the
+/// largest keys u^(1/w), held as ln(u) / w, and drawing how much weight to
+/// skip before the next row that enters the reservoir, so only O(k log(n/k))
+/// random numbers are drawn.
+pub fn weighted_sample_f64(values: Option<&[f64]>, weights: &[f64], seed: u64, out: &mut [u32]) -> usize {
+    let k = out.len();
+    if k == 0 {
+        return 0;
+    }
+    let mut rng = SplitMix64(seed);
+    let mut reservoir = std::collections::BinaryHeap::with_capacity(k);
+    // Weight left to skip before the next row enters, once the reservoir is full
+    let mut skip = 0.0;
+    for (i, &w) in weights.iter().enumerate() {
+        if !(w.is_finite() && w > 0.0) || values.is_some_and(|v| v[i].is_nan()) {
+            continue;
+        }
+        if reservoir.len() < k {
+            reservoir.push(Keyed { key: rng.next_open01().ln() / w, index: i as u32 });
+            if reservoir.len() == k {
+                skip = rng.next_open01().ln() / reservoir.peek().unwrap().key;
+            }
+            continue;
+        }
+        skip -= w;
+        if skip > 0.0 {
+            continue;
+        }
+        // The entering row's key is uniform above the threshold's: u in (T^w, 1)
+        let threshold = reservoir.peek().unwrap().key;
+        let floor = (threshold * w).exp();
+        let u = floor + rng.next_open01() * (1.0 - floor);
+        let key = (u.ln() / w).max(threshold);
+        reservoir.pop();
+        reservoir.push(Keyed { key, index: i as u32 });
+        skip = rng.next_open01().ln() / reservoir.peek().unwrap().key;
+    }
+    let count = reservoir.len();
+    for (dst, entry) in out.iter_mut().zip(reservoir) {
+        *dst = entry.index;
+    }
+    out[..count].sort_unstable();
+    count
+}
+
+/// Uniform sample of up to `k` indices of `strata` (len <= 2^32) for every
+/// distinct stratum value, in one pass with a reservoir per stratum.
+/// Writes the first `out.len()` sampled indices and returns the full count.
+pub fn stratified_sample_i64(strata: &[i64], k: usize, seed: u64, out: &mut [u32]) -> usize {
+    if k == 0 {
+        return 0;
+    }
+    let mut rng = SplitMix64(seed);
+    // Per stratum: rows seen so far, and the sample of them
+    let mut reservoirs: std::collections::HashMap<i64, (u64, Vec<u32>)> = std::collections::HashMap::new();
+    for (i, &stratum) in strata.iter().enumerate() {
+        let (seen, sample) = reservoirs.entry(stratum).or_default();
+        if sample.len() < k {
+            sample.push(i as u32);
+        } else {
+            let slot = rng.below(*seen + 1) as usize;
+            if slot < k {
+                sample[slot] = i as u32;
+            }
+        }
+        *seen += 1;
+    }
+    let mut sampled: Vec<u32> = reservoirs.into_values().flat_map(|(_, sample)| sample).collect();
+    sampled.sort_unstable();
+    for (dst, &index) in out.iter_mut().zip(&sampled) {
+        *dst = index;
+    }
+    sampled.len()
+}
+
+// ============================================================
+// Fingerprints
+// ============================================================
+
+// Locality-sensitive fingerprints of documents given as token hashes, for
+// near-duplicate detection. Token hashes go through the XXH64 avalanche
+// before use, so small or sequential ids work as well as real hashes, and
+// everything is plain 64-bit integer arithmetic: equal inputs and seeds give
+// equal fingerprints on every platform.
+
+/// MinHash signature of the set of `hashes`, one slot per element of `out`:
+/// slot i holds the smallest image of any hash under the i-th permutation,
+/// an affine map x -> a*x + b (a odd, drawn from `seed`) followed by the
+/// avalanche, both bijections of u64. Duplicate hashes do not change it. An
+/// empty set leaves every slot at u64::MAX.
+pub fn minhash_u64(hashes: &[u64], seed: u64, out: &mut [u64]) {
+    let mut rng = SplitMix64(seed);
+    let permutations: Vec<(u64, u64)> = out.iter().map(|_| (rng.next_u64() | 1, rng.next_u64())).collect();
+    out.fill(u64::MAX);
+    for &hash in hashes {
+        let x = xxh64::avalanche(hash);
+        for (slot, &(a, b)) in out.iter_mut().zip(&permutations) {
+            *slot = (*slot).min(xxh64::avalanche(x.wrapping_mul(a).wrapping_add(b)));
+        }
+    }
+}
+
+/// Fraction of the slots of two equally long MinHash signatures that are
+/// equal, an estimate of the Jaccard similarity of their sets. NaN for empty
+/// signatures.
+pub fn minhash_similarity(a: &[u64], b: &[u64]) -> f64 {
+    let equal = a.iter().zip(b).filter(|(x, y)| x == y).count();
+    equal as f64 / a.len() as f64
+}
+
+/// 64-bit SimHash of `hashes`: each token votes for every bit of its hash
+/// that is set and against every one that is clear, with its weight in
+/// `weights` (if given, as long as `hashes`) or 1, and the fingerprint keeps
+/// the bits with a positive total. Tokens whose weight is not finite do not
+/// vote.
+pub fn simhash_u64(hashes: &[u64], weights: Option<&[f64]>) -> u64 {
+    let mut votes = [0.0f64; 64];
+    for (i, &hash) in hashes.iter().enumerate() {
+        let weight = weights.map_or(1.0, |w| w[i]);
+        if !weight.is_finite() {
+            continue;
+        }
+        let bits = xxh64::avalanche(hash);
+        for (bit, vote) in votes.iter_mut().enumerate() {
+            if bits >> bit & 1 == 1 {
+                *vote += weight;
+            } else {
+                *vote -= weight;
+            }
+        }
+    }
+    votes.iter().enumerate().fold(0, |fingerprint, (bit, &vote)| if vote > 0.0 { fingerprint | 1 << bit } else { fingerprint })
+}
+
+// ============================================================
+// Decimal fixed-point
+// ============================================================
+
+// Money and other exact decimals held as i64 counts of 10^-scale units
+// (cents at scale 2). Sums and means are exact up to the final rounding,
+// which is always explicit.
+
+/// Largest scale: 10^18 is the largest power of ten an i64 holds.
+pub const MAX_DECIMAL_SCALE: u32 = 18;
+
+/// Powers of ten up to 10^MAX_DECIMAL_SCALE, all exact in an f64.
+const POW10_F64: [f64; 19] = [1e0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10, 1e11, 1e12, 1e13, 1e14, 1e15, 1e16, 1e17, 1e18];
+
+/// How a quotient or conversion halfway between two units rounds; anything
+/// nearer one of them rounds to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    /// Banker's rounding: ties go to the even unit
+    HalfEven,
+    /// Ties go away from zero
+    HalfUp,
+}
+
+/// n / d rounded to an integer, d > 0.
+fn round_div(n: i128, d: i128, rounding: Rounding) -> i128 {
+    let (q, r) = (n / d, n % d);
+    let twice = 2 * r.unsigned_abs();
+    let d = d as u128;
+    let away = twice > d || (twice == d && (rounding == Rounding::HalfUp || q % 2 != 0));
+    if !away {
+        q
+    } else if n < 0 {
+        q - 1
+    } else {
+        q + 1
+    }
+}
+
+/// Exact sum of the scaled values; no i64 input can overflow it.
+pub fn sum_scaled_i64(values: &[i64]) -> i128 {
+    values.iter().map(|&v| v as i128).sum()
+}
+
+/// Mean of the scaled values with `extra_digits` more decimals than they
+/// have, i.e. mean * 10^extra_digits, rounded once. None for no values or a
+/// result past i64.
+pub fn mean_scaled_i64(values: &[i64], extra_digits: u32, rounding: Rounding) -> Option<i64> {
+    if values.is_empty() || extra_digits > MAX_DECIMAL_SCALE {
+        return None;
+    }
+    // Overflowing i128 means the mean itself is far past i64
+    let total = sum_scaled_i64(values).checked_mul(10i128.pow(extra_digits))?;
+    i64::try_from(round_div(total, values.len() as i128, rounding)).ok()
+}
+
+/// `x` in units of 10^-scale, rounded from its exact binary value, and
+/// whether that is exact: whether `x` is the double nearest the decimal, as
+/// parsing the decimal would give. None when `x` is not finite or the result
+/// is past i64 (i64::MIN included, as exports use it for "no value").
+pub fn scale_f64(x: f64, scale: u32, rounding: Rounding) -> Option<(i64, bool)> {
+    if !x.is_finite() || scale > MAX_DECIMAL_SCALE {
+        return None;
+    }
+    let bits = x.to_bits();
+    let biased = ((bits >> 52) & 0x7ff) as i32;
+    let fraction = bits & ((1 << 52) - 1);
+    let (mantissa, exponent) = if biased == 0 { (fraction, -1074) } else { (fraction | 1 << 52, biased - 1075) };
+    // Below 2^113, so shifts and the sign fit an i128
+    let scaled = mantissa as i128 * 10i128.pow(scale);
+    let magnitude = if exponent >= 0 {
+        if exponent >= 64 || scaled > i64::MAX as i128 >> exponent {
+            return None;
+        }
+        scaled << exponent
+    } else if exponent > -120 {
+        round_div(scaled, 1 << -exponent, rounding)
+    } else {
+        // Under half a unit whatever the mantissa
+        0
+    };
+    let units = i64::try_from(magnitude).ok()?;
+    let units = if bits >> 63 == 1 { -units } else { units };
+    // Division of exact operands rounds correctly, giving the nearest double;
+    // from 2^53 units up doubles are at least a unit apart, so each is the
+    // nearest to the decimal it rounds to
+    let exact = units.unsigned_abs() >= 1 << 53 || units as f64 / POW10_F64[scale as usize] == x;
+    Some((units, exact))
+}
+
+/// Convert each of `values` with `scale_f64`, writing i64::MIN for values it
+/// cannot convert and, when `strict`, for inexact ones too. Returns how many
+/// values were not converted exactly.
+pub fn f64_to_scaled_i64(values: &[f64], scale: u32, rounding: Rounding, strict: bool, out: &mut [i64]) -> usize {
+    let mut inexact = 0;
+    for (dst, &x) in out.iter_mut().zip(values) {
+        *dst = match scale_f64(x, scale, rounding) {
+            Some((units, true)) => units,
+            Some((units, false)) if !strict => {
+                inexact += 1;
+                units
+            }
+            _ => {
+                inexact += 1;
+                i64::MIN
+            }
+        };
+    }
+    inexact
+}
+
+// ============================================================
+// Sparse vectors
+// ============================================================
+
+// Sparse vectors as parallel index and value arrays, indices strictly
+// ascending, so two of them intersect in one merge pass. Products are summed
+// with the weighted aggregates' compensation, so a sparse dot equals the
+// dense one over the same values.
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SparseError {
+    /// Indices not strictly ascending at `position`
+    Unsorted { position: usize },
+    /// An index at `position` past the end of the dense vector
+    OutOfRange { position: usize },
+    /// CSR row offsets that decrease, or run past the entries, at `row`
+    BadIndptr { row: usize },
+}
+
+/// Ok if `indices` are strictly ascending.
+pub fn check_sparse(indices: &[u32]) -> Result<(), SparseError> {
+    match indices.windows(2).position(|w| w[0] >= w[1]) {
+        Some(i) => Err(SparseError::Unsorted { position: i + 1 }),
+        None => Ok(()),
+    }
+}
+
+/// Σ va[i] * vb[j] over indices present in both; both index arrays strictly
+/// ascending.
+pub fn sparse_dot(ia: &[u32], va: &[f64], ib: &[u32], vb: &[f64]) -> f64 {
+    let mut acc = CompensatedSum::default();
+    let (mut i, mut j) = (0, 0);
+    while i < ia.len() && j < ib.len() {
+        match ia[i].cmp(&ib[j]) {
+            cmp::Ordering::Less => i += 1,
+            cmp::Ordering::Greater => j += 1,
+            cmp::Ordering::Equal => {
+                acc.add_product(va[i], vb[j]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    acc.value()
+}
+
+/// Σ vals[i] * dense[idx[i]], in any index order; OutOfRange at the first
+/// index past `dense`.
+pub fn sparse_dense_dot(idx: &[u32], vals: &[f64], dense: &[f64]) -> Result<f64, SparseError> {
+    let mut acc = CompensatedSum::default();
+    for (position, (&i, &v)) in idx.iter().zip(vals).enumerate() {
+        let Some(&d) = dense.get(i as usize) else {
+            return Err(SparseError::OutOfRange { position });
+        };
+        acc.add_product(v, d);
+    }
+    Ok(acc.value())
+}
+
+/// Rows of a sparse matrix in compressed sparse row form: row r holds the
+/// entries indptr[r]..indptr[r + 1] of `indices` and `values`.
+pub struct Csr<'a> {
+    pub indptr: &'a [u64],
+    pub indices: &'a [u32],
+    pub values: &'a [f64],
+}
+
+impl Csr<'_> {
+    pub fn rows(&self) -> usize {
+        self.indptr.len().saturating_sub(1)
+    }
+
+    fn row(&self, r: usize) -> (&[u32], &[f64]) {
+        let span = self.indptr[r] as usize..self.indptr[r + 1] as usize;
+        (&self.indices[span.clone()], &self.values[span])
+    }
+
+    /// Ok if the offsets are non-decreasing, within the entries, and each
+    /// row's indices strictly ascending.
+    pub fn check(&self) -> Result<(), SparseError> {
+        let entries = self.indices.len().min(self.values.len()) as u64;
+        if let Some(row) = self.indptr.windows(2).position(|w| w[0] > w[1] || w[1] > entries) {
+            return Err(SparseError::BadIndptr { row });
+        }
+        for r in 0..self.rows() {
+            if let Err(SparseError::Unsorted { position }) = check_sparse(self.row(r).0) {
+                return Err(SparseError::Unsorted { position: self.indptr[r] as usize + position });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A row's score, ordered so a BinaryHeap of them has the worst on top:
+/// lowest score, then highest row.
+struct Scored {
+    score: f64,
+    row: u32,
+}
+
+impl PartialEq for Scored {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == cmp::Ordering::Equal
+    }
+}
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        other.score.total_cmp(&self.score).then(self.row.cmp(&other.row))
+    }
+}
+
+/// Score `query` against every row of `csr` (checked) by sparse dot product
+/// and write the `out_rows.len()` best rows, highest score first and ties to
+/// the lower row, with their scores. Rows scoring NaN are never picked.
+/// Returns how many were written: every scored row when there are fewer.
+pub fn sparse_topk_dot(query_idx: &[u32], query_vals: &[f64], csr: &Csr, out_rows: &mut [u32], out_scores: &mut [f64]) -> usize {
+    let k = out_rows.len().min(out_scores.len());
+    if k == 0 {
+        return 0;
+    }
+    let mut best = std::collections::BinaryHeap::with_capacity(k + 1);
+    for r in 0..csr.rows() {
+        let (idx, vals) = csr.row(r);
+        let score = sparse_dot(query_idx, query_vals, idx, vals);
+        if score.is_nan() {
+            continue;
+        }
+        best.push(Scored { score, row: r as u32 });
+        if best.len() > k {
+            best.pop();
+        }
+    }
+    let best = best.into_sorted_vec();
+    for ((row, score), s) in out_rows.iter_mut().zip(out_scores.iter_mut()).zip(&best) {
+        *row = s.row;
+        *score = s.score;
+    }
+    best.len()
+}
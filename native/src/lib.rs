@@ -2,174 +2,303 @@
 // Provides high-performance sort, hash, and data processing operations
 // Called from Bun via FFI (bun:ffi)
 
+// Every export shares one contract: pointers come from a live TypedArray/Buffer
+// and `len` matches its element count. Spelling that out per function adds nothing.
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::RefCell;
+use std::ffi::c_char;
 use std::slice;
 
+mod decompress;
+mod external_sort;
+mod group_agg;
+mod hash_join;
+pub mod kernels;
+mod order_set;
+mod xxh64;
+
+pub use decompress::DecompressStream;
+pub use group_agg::GroupAgg;
+pub use hash_join::HashJoin;
+pub use order_set::OrderSet;
+use group_agg::GroupOp;
+
 // ============================================================
 // Numeric Sort — Radix sort for f64 (IEEE 754 trick)
 // ============================================================
 
-/// Sort an array of f64 values in-place using radix sort.
+/// Sort an array of f64 values in-place, radix sorting large inputs.
 /// Radix sort on floats: reinterpret as u64, flip sign bit for correct ordering.
-/// Time: O(n), Space: O(n). Beats comparison sort for n > ~256.
+/// Time: O(n), Space: O(n). Inputs below the small cutoff use a comparison
+/// sort and inputs past the parallel threshold sort on every thread (see
+/// `tova_sort_set_tuning`).
+/// Orders like `f64::total_cmp`: -0.0 before +0.0, NaNs at the end
+/// (negative-signed NaNs at the front).
 #[no_mangle]
 pub unsafe extern "C" fn tova_sort_f64(ptr: *mut f64, len: usize) {
     if len <= 1 {
         return;
     }
-    let data = slice::from_raw_parts_mut(ptr, len);
+    with_view_mut(ptr, len, kernels::sort_f64);
+}
 
-    // For small arrays, use insertion sort (cache-friendly, low overhead)
-    if len <= 64 {
-        insertion_sort_f64(data);
+/// Sort an array of f64 values in-place without allocating: an in-place
+/// comparison sort at every size, O(1) auxiliary memory beyond the stack.
+/// Same order as `tova_sort_f64`; slower than it on large inputs.
+#[no_mangle]
+pub unsafe extern "C" fn tova_sort_f64_unstable(ptr: *mut f64, len: usize) {
+    if len <= 1 {
         return;
     }
+    with_view_mut(ptr, len, kernels::sort_f64_unstable);
+}
 
-    radix_sort_f64(data);
+/// Write to `out_idx` the indices that would sort `ptr` as `tova_sort_f64`
+/// does, leaving the values untouched: out_idx[i] is the position of the
+/// i-th smallest value, ready for `tova_gather_f64` or
+/// `tova_apply_permutation_inplace_f64` on every column of the table. Stable,
+/// so tied values keep their input order; O(n) radix sort from the small
+/// cutoff up. Returns 0, TOVA_INDEX_ERR_TOO_LONG past 2^32 values, which u32
+/// indices cannot address, or TOVA_ERR_OVERLAP when `out_idx` overlaps the
+/// values; nothing is written on failure.
+#[no_mangle]
+pub unsafe extern "C" fn tova_argsort_f64(ptr: *const f64, len: usize, out_idx: *mut u32) -> i32 {
+    clear_last_error();
+    if len as u64 > 1 << 32 {
+        return fail(TOVA_INDEX_ERR_TOO_LONG, "argsort: longer than 2^32 values");
+    }
+    if overlaps(ptr, len, out_idx, len) {
+        return fail(TOVA_ERR_OVERLAP, "output range overlaps an input");
+    }
+    let mut copy = Vec::new();
+    let values = view(ptr, len, &mut copy);
+    with_view_mut(out_idx, len, |out| kernels::argsort_f64(values, out));
+    0
 }
 
-fn insertion_sort_f64(data: &mut [f64]) {
-    for i in 1..data.len() {
-        let key = data[i];
-        let mut j = i;
-        while j > 0 && data[j - 1] > key {
-            data[j] = data[j - 1];
-            j -= 1;
-        }
-        data[j] = key;
+/// Sort an array of i64 values in-place.
+#[no_mangle]
+pub unsafe extern "C" fn tova_sort_i64(ptr: *mut i64, len: usize) {
+    if len <= 1 {
+        return;
     }
+    with_view_mut(ptr, len, kernels::sort_i64);
 }
 
-/// IEEE 754 radix sort trick:
-/// - Positive floats: bit pattern is already in correct order
-/// - Negative floats: bit pattern is in reverse order, and all bits are flipped
-/// Transform: if sign bit is set, flip all bits; else flip only sign bit
-/// This gives a monotonically increasing u64 mapping for all f64 values.
-fn radix_sort_f64(data: &mut [f64]) {
-    let len = data.len();
-    let mut keys: Vec<u64> = Vec::with_capacity(len);
-    let mut buf: Vec<u64> = vec![0u64; len];
+/// `tova_sort_f64` in descending order: the exact reverse of its output,
+/// so +NaN first, +0.0 before -0.0 and negative-signed NaNs at the end.
+/// Same cutoff, radix and parallel paths.
+#[no_mangle]
+pub unsafe extern "C" fn tova_sort_f64_desc(ptr: *mut f64, len: usize) {
+    if len <= 1 {
+        return;
+    }
+    with_view_mut(ptr, len, kernels::sort_f64_desc);
+}
 
-    // Convert f64 to sortable u64
-    for &val in data.iter() {
-        let bits = val.to_bits();
-        let key = if bits >> 63 == 1 {
-            !bits // negative: flip all bits
-        } else {
-            bits ^ (1u64 << 63) // positive: flip sign bit
-        };
-        keys.push(key);
+/// `tova_sort_i64` in descending order.
+#[no_mangle]
+pub unsafe extern "C" fn tova_sort_i64_desc(ptr: *mut i64, len: usize) {
+    if len <= 1 {
+        return;
     }
+    with_view_mut(ptr, len, kernels::sort_i64_desc);
+}
 
-    // 4-pass radix sort on 16-bit chunks (64 bits / 4 passes = 16 bits per pass)
-    for pass in 0..4u32 {
-        let shift = pass * 16;
-        let mut counts = [0u32; 65536];
+/// Bits of `tova_sort_caps`: the contract each sorter keeps. STABLE means the
+/// output equals a stable sort's; TOTAL_ORDER means NaNs and signed zeros
+/// follow `f64::total_cmp` (-NaN first, -0.0 before +0.0, +NaN last);
+/// NO_ALLOC means no auxiliary buffer at any size.
+pub const TOVA_SORT_F64_STABLE: u64 = 1 << 0;
+pub const TOVA_SORT_F64_TOTAL_ORDER: u64 = 1 << 1;
+pub const TOVA_SORT_I64_STABLE: u64 = 1 << 2;
+pub const TOVA_SORT_F64_UNSTABLE_TOTAL_ORDER: u64 = 1 << 3;
+pub const TOVA_SORT_F64_UNSTABLE_NO_ALLOC: u64 = 1 << 4;
 
-        // Count
-        for &key in keys.iter() {
-            let digit = ((key >> shift) & 0xFFFF) as usize;
-            counts[digit] += 1;
-        }
+/// TOVA_SORT_* bits for the sorters in this build. `tova_sort_f64_unstable`
+/// makes no stability promise, so there is no bit for it.
+#[no_mangle]
+pub unsafe extern "C" fn tova_sort_caps() -> u64 {
+    TOVA_SORT_F64_STABLE
+        | TOVA_SORT_F64_TOTAL_ORDER
+        | TOVA_SORT_I64_STABLE
+        | TOVA_SORT_F64_UNSTABLE_TOTAL_ORDER
+        | TOVA_SORT_F64_UNSTABLE_NO_ALLOC
+}
 
-        // Prefix sum
-        let mut total = 0u32;
-        for count in counts.iter_mut() {
-            let c = *count;
-            *count = total;
-            total += c;
-        }
+pub const TOVA_SORT_ERR_RADIX_BITS: i32 = -1;
+pub const TOVA_SORT_ERR_PARALLEL_THRESHOLD: i32 = -2;
+pub const TOVA_SORT_ERR_HANDLE: i32 = -3;
 
-        // Scatter
-        for &key in keys.iter() {
-            let digit = ((key >> shift) & 0xFFFF) as usize;
-            let pos = counts[digit] as usize;
-            buf[pos] = key;
-            counts[digit] += 1;
+/// Tune `tova_sort_f64` and `tova_sort_i64` for this process.
+/// `small_cutoff`: f64 inputs shorter than this use a comparison sort
+/// (default 10000). `radix_bits`: radix digit width, 8, 11 or 16 (default 16;
+/// narrower digits take more passes over smaller, cache-resident histograms).
+/// `parallel_threshold`: inputs at least this long sort on every available
+/// thread (at least 2; default usize::MAX, never). Returns 0, or
+/// TOVA_SORT_ERR_* leaving every setting unchanged. Sort output is identical
+/// under all settings.
+#[no_mangle]
+pub unsafe extern "C" fn tova_sort_set_tuning(small_cutoff: usize, radix_bits: u32, parallel_threshold: usize) -> i32 {
+    clear_last_error();
+    let tuning = kernels::SortTuning { small_cutoff, radix_bits, parallel_threshold };
+    match kernels::set_sort_tuning(tuning) {
+        Ok(()) => 0,
+        Err(kernels::SortTuningError::RadixBits) => fail(TOVA_SORT_ERR_RADIX_BITS, "sort tuning: radix_bits must be 8, 11 or 16"),
+        Err(kernels::SortTuningError::ParallelThreshold) => {
+            fail(TOVA_SORT_ERR_PARALLEL_THRESHOLD, "sort tuning: parallel_threshold must be at least 2")
         }
+    }
+}
 
-        // Swap
-        std::mem::swap(&mut keys, &mut buf);
+/// Current `tova_sort_set_tuning` values. Null outputs are skipped.
+#[no_mangle]
+pub unsafe extern "C" fn tova_sort_get_tuning(out_small_cutoff: *mut usize, out_radix_bits: *mut u32, out_parallel_threshold: *mut usize) {
+    let tuning = kernels::sort_tuning();
+    if !out_small_cutoff.is_null() {
+        out_small_cutoff.write_unaligned(tuning.small_cutoff);
+    }
+    if !out_radix_bits.is_null() {
+        out_radix_bits.write_unaligned(tuning.radix_bits);
+    }
+    if !out_parallel_threshold.is_null() {
+        out_parallel_threshold.write_unaligned(tuning.parallel_threshold);
     }
+}
 
-    // Convert sortable u64 back to f64
-    for (i, &key) in keys.iter().enumerate() {
-        let bits = if key >> 63 == 0 {
-            !key // was negative
-        } else {
-            key ^ (1u64 << 63) // was positive
+/// A `tova_sort_f64` in progress: the caller's array, or an aligned copy of
+/// a misaligned one that is written back when the sort ends.
+pub struct SortF64Handle {
+    ptr: *mut f64,
+    len: usize,
+    copy: Option<Vec<u64>>,
+    sort: kernels::IncrementalSortF64,
+}
+
+impl SortF64Handle {
+    /// The sort and the bit patterns it works on.
+    unsafe fn parts(&mut self) -> (&mut kernels::IncrementalSortF64, &mut [u64]) {
+        let data: &mut [u64] = match &mut self.copy {
+            Some(copy) => copy,
+            None if self.len == 0 => &mut [],
+            None => slice::from_raw_parts_mut(self.ptr as *mut u64, self.len),
         };
-        data[i] = f64::from_bits(bits);
+        (&mut self.sort, data)
+    }
+
+    unsafe fn write_back(&self) {
+        if let Some(copy) = &self.copy {
+            std::ptr::copy_nonoverlapping(copy.as_ptr() as *const u8, self.ptr as *mut u8, std::mem::size_of_val(copy.as_slice()));
+        }
     }
 }
 
-/// Sort an array of i64 values in-place using radix sort (signed).
+/// Start sorting `ptr[..len]` like `tova_sort_f64`, a step at a time with
+/// `tova_sort_f64_step`, so a long sort can be interleaved with other work.
+/// The array must stay alive and untouched until `tova_sort_f64_finish` or
+/// `tova_sort_f64_abort` releases the handle; until then it holds the
+/// values in an unspecified state. Uses the tuning current at this call.
 #[no_mangle]
-pub unsafe extern "C" fn tova_sort_i64(ptr: *mut i64, len: usize) {
-    if len <= 1 {
-        return;
-    }
-    let data = slice::from_raw_parts_mut(ptr, len);
+pub unsafe extern "C" fn tova_sort_f64_begin(ptr: *mut f64, len: usize) -> *mut SortF64Handle {
+    clear_last_error();
+    let len = if ptr.is_null() { 0 } else { len };
+    let copy = (len > 0 && !ptr.is_aligned()).then(|| (0..len).map(|i| (ptr as *const u64).add(i).read_unaligned()).collect());
+    Box::into_raw(Box::new(SortF64Handle { ptr, len, copy, sort: kernels::IncrementalSortF64::new(len) }))
+}
 
-    if len <= 64 {
-        insertion_sort_i64(data);
+/// Do about `budget` elements' worth of work: part of a key rewrite,
+/// counting or scatter pass, with the few inputs that take a comparison
+/// sort done in one step. Returns 1 once the array is sorted (further steps
+/// do nothing), 0 while work remains, or TOVA_SORT_ERR_HANDLE for a null handle.
+#[no_mangle]
+pub unsafe extern "C" fn tova_sort_f64_step(handle: *mut SortF64Handle, budget: usize) -> i32 {
+    clear_last_error();
+    let Some(handle) = handle.as_mut() else {
+        return fail(TOVA_SORT_ERR_HANDLE, "sort step: null handle");
+    };
+    let (sort, data) = handle.parts();
+    sort.step(data, budget) as i32
+}
+
+/// Run whatever work remains, leave the array sorted and free the handle.
+#[no_mangle]
+pub unsafe extern "C" fn tova_sort_f64_finish(handle: *mut SortF64Handle) {
+    if handle.is_null() {
         return;
     }
-
-    radix_sort_i64(data);
+    let mut handle = Box::from_raw(handle);
+    let (sort, data) = handle.parts();
+    sort.step(data, usize::MAX);
+    handle.write_back();
 }
 
-fn insertion_sort_i64(data: &mut [i64]) {
-    for i in 1..data.len() {
-        let key = data[i];
-        let mut j = i;
-        while j > 0 && data[j - 1] > key {
-            data[j] = data[j - 1];
-            j -= 1;
-        }
-        data[j] = key;
+/// Stop a sort early and free the handle. The array holds every original
+/// value, in an unspecified order.
+#[no_mangle]
+pub unsafe extern "C" fn tova_sort_f64_abort(handle: *mut SortF64Handle) {
+    if handle.is_null() {
+        return;
     }
+    let mut handle = Box::from_raw(handle);
+    let (sort, data) = handle.parts();
+    sort.abort(data);
+    handle.write_back();
 }
 
-fn radix_sort_i64(data: &mut [i64]) {
-    let len = data.len();
-    let mut keys: Vec<u64> = Vec::with_capacity(len);
-    let mut buf: Vec<u64> = vec![0u64; len];
+pub const TOVA_EXTERNAL_SORT_ERR_ARGS: i32 = -1;
+pub const TOVA_EXTERNAL_SORT_ERR_CREATE: i32 = -2;
+pub const TOVA_EXTERNAL_SORT_ERR_IO: i32 = -3;
+pub const TOVA_EXTERNAL_SORT_ERR_DISK_FULL: i32 = -4;
 
-    // Convert signed to unsigned by flipping the sign bit
-    for &val in data.iter() {
-        keys.push((val as u64) ^ (1u64 << 63));
+/// Sort `ptr[..len]` like `tova_sort_f64` while touching at most
+/// `mem_budget_bytes` of it at a time, for arrays that do not fit in memory
+/// (a mapped file, say): budget-sized runs are sorted in place, spilled to
+/// temporary files in the NUL-terminated `scratch_dir` and merged back into
+/// the array, in several passes when there are more runs than one merge
+/// opens at once. The merge's buffers stay within the budget. An array
+/// within the budget is sorted in place with no files. Returns 0, or
+/// TOVA_EXTERNAL_SORT_ERR_* with the failing path in the last error message:
+/// ARGS for a null pointer, a budget under 8 bytes or an array misaligned for
+/// f64 (an aligned copy of it would not fit either), CREATE when a run file
+/// cannot be created, DISK_FULL when the scratch device fills up, IO for any
+/// other read or write failure. Run files are removed on every return. After an error while spilling or in an
+/// intermediate merge pass the array holds the same values in some order;
+/// after one in the final merge its contents are unspecified.
+#[no_mangle]
+pub unsafe extern "C" fn tova_sort_f64_external(ptr: *mut f64, len: usize, scratch_dir: *const c_char, mem_budget_bytes: usize) -> i32 {
+    clear_last_error();
+    if scratch_dir.is_null() || (ptr.is_null() && len > 0) {
+        return fail(TOVA_EXTERNAL_SORT_ERR_ARGS, "external sort: null pointer");
     }
+    if len > 0 && !ptr.is_aligned() {
+        return fail(TOVA_EXTERNAL_SORT_ERR_ARGS, "external sort: array is not 8-byte aligned");
+    }
+    let bytes = std::ffi::CStr::from_ptr(scratch_dir).to_bytes();
+    #[cfg(unix)]
+    let dir = std::path::Path::new(<std::ffi::OsStr as std::os::unix::ffi::OsStrExt>::from_bytes(bytes));
+    #[cfg(not(unix))]
+    let dir = match std::str::from_utf8(bytes) {
+        Ok(dir) => std::path::Path::new(dir),
+        Err(_) => return fail(TOVA_EXTERNAL_SORT_ERR_ARGS, "external sort: scratch_dir is not UTF-8"),
+    };
+    let data = if len == 0 { &mut [][..] } else { slice::from_raw_parts_mut(ptr, len) };
+    match external_sort::sort_f64_external(data, dir, mem_budget_bytes) {
+        Ok(()) => 0,
+        Err(e) => spill_failure(e),
+    }
+}
 
-    // 4-pass radix sort on 16-bit chunks
-    for pass in 0..4u32 {
-        let shift = pass * 16;
-        let mut counts = [0u32; 65536];
-
-        for &key in keys.iter() {
-            let digit = ((key >> shift) & 0xFFFF) as usize;
-            counts[digit] += 1;
-        }
-
-        let mut total = 0u32;
-        for count in counts.iter_mut() {
-            let c = *count;
-            *count = total;
-            total += c;
+fn spill_failure(err: external_sort::SpillError) -> i32 {
+    match err {
+        external_sort::SpillError::Budget => fail(TOVA_EXTERNAL_SORT_ERR_ARGS, "external sort: mem_budget_bytes is under 8"),
+        external_sort::SpillError::Create(path, e) => {
+            fail(TOVA_EXTERNAL_SORT_ERR_CREATE, &format!("external sort: cannot create {}: {}", path.display(), e))
         }
-
-        for &key in keys.iter() {
-            let digit = ((key >> shift) & 0xFFFF) as usize;
-            let pos = counts[digit] as usize;
-            buf[pos] = key;
-            counts[digit] += 1;
+        external_sort::SpillError::Io(path, e) => {
+            let code = if e.kind() == std::io::ErrorKind::StorageFull { TOVA_EXTERNAL_SORT_ERR_DISK_FULL } else { TOVA_EXTERNAL_SORT_ERR_IO };
+            fail(code, &format!("external sort: {}: {}", path.display(), e))
         }
-
-        std::mem::swap(&mut keys, &mut buf);
-    }
-
-    // Convert back to signed
-    for (i, &key) in keys.iter().enumerate() {
-        data[i] = (key ^ (1u64 << 63)) as i64;
     }
 }
 
@@ -183,15 +312,7 @@ pub unsafe extern "C" fn tova_unique_sorted_i64(ptr: *mut i64, len: usize) -> us
     if len <= 1 {
         return len;
     }
-    let data = slice::from_raw_parts_mut(ptr, len);
-    let mut write = 1usize;
-    for read in 1..len {
-        if data[read] != data[write - 1] {
-            data[write] = data[read];
-            write += 1;
-        }
-    }
-    write
+    with_view_mut(ptr, len, kernels::unique_sorted)
 }
 
 /// Remove duplicates from a sorted f64 array. Returns new length.
@@ -200,15 +321,28 @@ pub unsafe extern "C" fn tova_unique_sorted_f64(ptr: *mut f64, len: usize) -> us
     if len <= 1 {
         return len;
     }
-    let data = slice::from_raw_parts_mut(ptr, len);
-    let mut write = 1usize;
-    for read in 1..len {
-        if data[read] != data[write - 1] {
-            data[write] = data[read];
-            write += 1;
-        }
+    with_view_mut(ptr, len, kernels::unique_sorted)
+}
+
+/// Remove duplicates from a sorted i64 array like `tova_unique_sorted_i64`,
+/// writing how many times each distinct value occurred to `out_counts`
+/// (capacity `len`). Returns the number of distinct values.
+#[no_mangle]
+pub unsafe extern "C" fn tova_unique_counts_sorted_i64(ptr: *mut i64, len: usize, out_counts: *mut u64) -> usize {
+    if len == 0 {
+        return 0;
+    }
+    with_view_mut(ptr, len, |data| with_view_mut(out_counts, len, |counts| kernels::unique_counts_sorted_i64(data, counts)))
+}
+
+/// `tova_unique_counts_sorted_i64` for an array sorted by `tova_sort_f64`.
+/// Values are equal when their bits are: -0.0 and +0.0 are counted apart.
+#[no_mangle]
+pub unsafe extern "C" fn tova_unique_counts_sorted_f64(ptr: *mut f64, len: usize, out_counts: *mut u64) -> usize {
+    if len == 0 {
+        return 0;
     }
-    write
+    with_view_mut(ptr, len, |data| with_view_mut(out_counts, len, |counts| kernels::unique_counts_sorted_f64(data, counts)))
 }
 
 /// Sum an array of f64 values using Kahan summation (compensated, more accurate).
@@ -217,116 +351,7243 @@ pub unsafe extern "C" fn tova_sum_f64(ptr: *const f64, len: usize) -> f64 {
     if len == 0 {
         return 0.0;
     }
-    let data = slice::from_raw_parts(ptr, len);
-    let mut sum = 0.0f64;
-    let mut comp = 0.0f64; // compensation for lost low-order bits
-    for &val in data.iter() {
-        let y = val - comp;
-        let t = sum + y;
-        comp = (t - sum) - y;
-        sum = t;
-    }
-    sum
+    kernels::sum_f64(view(ptr, len, &mut Vec::new()))
 }
 
-/// Find the minimum value in an f64 array.
+/// Minimum of an f64 array, skipping NaN (NaN when there is no other value).
+/// -0.0 is smaller than +0.0.
 #[no_mangle]
 pub unsafe extern "C" fn tova_min_f64(ptr: *const f64, len: usize) -> f64 {
     if len == 0 {
         return f64::NAN;
     }
-    let data = slice::from_raw_parts(ptr, len);
-    let mut m = data[0];
-    for &val in data.iter().skip(1) {
-        if val < m {
-            m = val;
-        }
-    }
-    m
+    kernels::min_f64(view(ptr, len, &mut Vec::new()))
 }
 
-/// Find the maximum value in an f64 array.
+/// Maximum of an f64 array, skipping NaN (NaN when there is no other value).
+/// +0.0 is larger than -0.0.
 #[no_mangle]
 pub unsafe extern "C" fn tova_max_f64(ptr: *const f64, len: usize) -> f64 {
     if len == 0 {
         return f64::NAN;
     }
-    let data = slice::from_raw_parts(ptr, len);
-    let mut m = data[0];
-    for &val in data.iter().skip(1) {
-        if val > m {
-            m = val;
-        }
+    kernels::max_f64(view(ptr, len, &mut Vec::new()))
+}
+
+/// Compensated sum on up to `threads` threads (0 = available parallelism).
+/// The result is bit-identical for every thread count: the array is summed
+/// in fixed-size blocks merged in order, so it can differ from
+/// `tova_sum_f64` in the last bits. Small arrays are summed on the caller's
+/// thread.
+#[no_mangle]
+pub unsafe extern "C" fn tova_sum_f64_parallel(ptr: *const f64, len: usize, threads: usize) -> f64 {
+    if len == 0 {
+        return 0.0;
+    }
+    kernels::sum_f64_parallel(view(ptr, len, &mut Vec::new()), threads)
+}
+
+/// `tova_min_f64` on up to `threads` threads (0 = available parallelism).
+#[no_mangle]
+pub unsafe extern "C" fn tova_min_f64_parallel(ptr: *const f64, len: usize, threads: usize) -> f64 {
+    if len == 0 {
+        return f64::NAN;
+    }
+    kernels::min_f64_parallel(view(ptr, len, &mut Vec::new()), threads)
+}
+
+/// `tova_max_f64` on up to `threads` threads (0 = available parallelism).
+#[no_mangle]
+pub unsafe extern "C" fn tova_max_f64_parallel(ptr: *const f64, len: usize, threads: usize) -> f64 {
+    if len == 0 {
+        return f64::NAN;
     }
-    m
+    kernels::max_f64_parallel(view(ptr, len, &mut Vec::new()), threads)
+}
+
+/// Minimum and maximum in one pass on up to `threads` threads, written to
+/// `out_min` and `out_max` (NaN when there is no non-NaN value).
+#[no_mangle]
+pub unsafe extern "C" fn tova_minmax_f64_parallel(ptr: *const f64, len: usize, threads: usize, out_min: *mut f64, out_max: *mut f64) {
+    let (min, max) = if len == 0 {
+        (f64::NAN, f64::NAN)
+    } else {
+        kernels::minmax_f64_parallel(view(ptr, len, &mut Vec::new()), threads)
+    };
+    out_min.write_unaligned(min);
+    out_max.write_unaligned(max);
 }
 
 // ============================================================
-// Tests
+// i64 aggregates
 // ============================================================
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+// Ids, counts and timestamps aggregated as i64, exact where a trip through
+// f64 is not (beyond 2^53). Minima and maxima are decided by the same fold as
+// the f64 functions above, so ties go to the first value in both. With no NaN
+// to mark an empty input, each writes TOVA_AGG_OK or TOVA_AGG_EMPTY to
+// `out_status` (which may be null) and returns 0 when empty, SIZE_MAX for a
+// position and NaN for a mean. The _masked variants count only the values
+// whose bit is set in `validity`, an Arrow bitmap of `len` bits (null: all
+// valid). The _segmented variants reduce each run
+// values[offsets[i]..offsets[i + 1]] of `segments` runs (`offsets` holds
+// segments + 1 non-decreasing entries), writing the same empty values for
+// empty runs, and return how many runs were empty.
 
-    #[test]
-    fn test_sort_f64() {
-        let mut data = vec![3.14, -1.0, 2.71, 0.0, -0.5, 100.0, -100.0, 1.0];
-        unsafe { tova_sort_f64(data.as_mut_ptr(), data.len()); }
-        assert_eq!(data, vec![-100.0, -1.0, -0.5, 0.0, 1.0, 2.71, 3.14, 100.0]);
+pub const TOVA_AGG_OK: i32 = 0;
+/// No value was counted
+pub const TOVA_AGG_EMPTY: i32 = 1;
+/// Segment offsets decrease or run past the values
+pub const TOVA_AGG_ERR_OFFSETS: i32 = -1;
+
+unsafe fn agg_status<T>(found: Option<T>, out_status: *mut i32) -> Option<T> {
+    if !out_status.is_null() {
+        out_status.write_unaligned(if found.is_some() { TOVA_AGG_OK } else { TOVA_AGG_EMPTY });
     }
+    found
+}
 
-    #[test]
-    fn test_sort_f64_large() {
-        let mut data: Vec<f64> = (0..10000).map(|i| (10000 - i) as f64).collect();
-        unsafe { tova_sort_f64(data.as_mut_ptr(), data.len()); }
-        let expected: Vec<f64> = (1..=10000).map(|i| i as f64).collect();
-        assert_eq!(data, expected);
+unsafe fn agg_arg_i64(ptr: *const i64, validity: *const u8, len: usize, out_status: *mut i32, want_max: bool) -> Option<(usize, i64)> {
+    let mut copy = Vec::new();
+    let (values, validity) = (view(ptr, len, &mut copy), validity_bits(validity, len));
+    let found = if want_max { kernels::argmax(values, validity) } else { kernels::argmin(values, validity) };
+    agg_status(found, out_status)
+}
+
+unsafe fn agg_minmax_i64(ptr: *const i64, validity: *const u8, len: usize, out_min: *mut i64, out_max: *mut i64) -> i32 {
+    let found = kernels::minmax(view(ptr, len, &mut Vec::new()), validity_bits(validity, len));
+    let (min, max) = found.unwrap_or((0, 0));
+    out_min.write_unaligned(min);
+    out_max.write_unaligned(max);
+    if found.is_some() { TOVA_AGG_OK } else { TOVA_AGG_EMPTY }
+}
+
+unsafe fn agg_mean_i64(ptr: *const i64, validity: *const u8, len: usize, out_status: *mut i32) -> f64 {
+    let mean = kernels::mean_i64(view(ptr, len, &mut Vec::new()), validity_bits(validity, len));
+    agg_status(mean, out_status).unwrap_or(f64::NAN)
+}
+
+/// Smallest value of an i64 array (0 and TOVA_AGG_EMPTY when empty).
+#[no_mangle]
+pub unsafe extern "C" fn tova_min_i64(ptr: *const i64, len: usize, out_status: *mut i32) -> i64 {
+    agg_arg_i64(ptr, std::ptr::null(), len, out_status, false).map_or(0, |(_, v)| v)
+}
+
+/// Largest value of an i64 array (0 and TOVA_AGG_EMPTY when empty).
+#[no_mangle]
+pub unsafe extern "C" fn tova_max_i64(ptr: *const i64, len: usize, out_status: *mut i32) -> i64 {
+    agg_arg_i64(ptr, std::ptr::null(), len, out_status, true).map_or(0, |(_, v)| v)
+}
+
+/// Smallest and largest value in one pass, written to `out_min` and
+/// `out_max` (0 when empty). Returns TOVA_AGG_OK or TOVA_AGG_EMPTY.
+#[no_mangle]
+pub unsafe extern "C" fn tova_minmax_i64(ptr: *const i64, len: usize, out_min: *mut i64, out_max: *mut i64) -> i32 {
+    agg_minmax_i64(ptr, std::ptr::null(), len, out_min, out_max)
+}
+
+/// Position of the first smallest value (SIZE_MAX and TOVA_AGG_EMPTY when
+/// empty).
+#[no_mangle]
+pub unsafe extern "C" fn tova_argmin_i64(ptr: *const i64, len: usize, out_status: *mut i32) -> usize {
+    agg_arg_i64(ptr, std::ptr::null(), len, out_status, false).map_or(usize::MAX, |(i, _)| i)
+}
+
+/// Position of the first largest value (SIZE_MAX and TOVA_AGG_EMPTY when
+/// empty).
+#[no_mangle]
+pub unsafe extern "C" fn tova_argmax_i64(ptr: *const i64, len: usize, out_status: *mut i32) -> usize {
+    agg_arg_i64(ptr, std::ptr::null(), len, out_status, true).map_or(usize::MAX, |(i, _)| i)
+}
+
+/// Mean of an i64 array, summed exactly in i128 (NaN and TOVA_AGG_EMPTY
+/// when empty). Within an ulp of the true mean whatever the magnitudes.
+#[no_mangle]
+pub unsafe extern "C" fn tova_mean_i64(ptr: *const i64, len: usize, out_status: *mut i32) -> f64 {
+    agg_mean_i64(ptr, std::ptr::null(), len, out_status)
+}
+
+/// `tova_min_i64` over the valid values.
+#[no_mangle]
+pub unsafe extern "C" fn tova_min_i64_masked(ptr: *const i64, validity: *const u8, len: usize, out_status: *mut i32) -> i64 {
+    agg_arg_i64(ptr, validity, len, out_status, false).map_or(0, |(_, v)| v)
+}
+
+/// `tova_max_i64` over the valid values.
+#[no_mangle]
+pub unsafe extern "C" fn tova_max_i64_masked(ptr: *const i64, validity: *const u8, len: usize, out_status: *mut i32) -> i64 {
+    agg_arg_i64(ptr, validity, len, out_status, true).map_or(0, |(_, v)| v)
+}
+
+/// `tova_minmax_i64` over the valid values.
+#[no_mangle]
+pub unsafe extern "C" fn tova_minmax_i64_masked(
+    ptr: *const i64,
+    validity: *const u8,
+    len: usize,
+    out_min: *mut i64,
+    out_max: *mut i64,
+) -> i32 {
+    agg_minmax_i64(ptr, validity, len, out_min, out_max)
+}
+
+/// `tova_argmin_i64` over the valid values; the position counts the
+/// invalid ones too.
+#[no_mangle]
+pub unsafe extern "C" fn tova_argmin_i64_masked(ptr: *const i64, validity: *const u8, len: usize, out_status: *mut i32) -> usize {
+    agg_arg_i64(ptr, validity, len, out_status, false).map_or(usize::MAX, |(i, _)| i)
+}
+
+/// `tova_argmax_i64` over the valid values; the position counts the
+/// invalid ones too.
+#[no_mangle]
+pub unsafe extern "C" fn tova_argmax_i64_masked(ptr: *const i64, validity: *const u8, len: usize, out_status: *mut i32) -> usize {
+    agg_arg_i64(ptr, validity, len, out_status, true).map_or(usize::MAX, |(i, _)| i)
+}
+
+/// `tova_mean_i64` over the valid values.
+#[no_mangle]
+pub unsafe extern "C" fn tova_mean_i64_masked(ptr: *const i64, validity: *const u8, len: usize, out_status: *mut i32) -> f64 {
+    agg_mean_i64(ptr, validity, len, out_status)
+}
+
+/// Checks the offsets, then writes `reduce` of each segment (`empty` for an
+/// empty one) to `out`. Inputs aliasing `out` are copied first.
+unsafe fn agg_segments<R: Copy>(
+    values: *const i64,
+    len: usize,
+    offsets: *const u64,
+    segments: usize,
+    out: *mut R,
+    empty: R,
+    reduce: impl Fn(usize, &[i64]) -> Option<R>,
+) -> isize {
+    clear_last_error();
+    if segments == 0 {
+        return 0;
     }
+    let (mut values_copy, mut offsets_copy) = (Vec::new(), Vec::new());
+    let offsets = unaliased(offsets, segments + 1, out, segments, &mut offsets_copy);
+    if offsets.windows(2).any(|w| w[0] > w[1]) || offsets[segments] > len as u64 {
+        return fail(TOVA_AGG_ERR_OFFSETS, "segmented aggregate: offsets decrease or run past the values") as isize;
+    }
+    let values = unaliased(values, len, out, segments, &mut values_copy);
+    with_view_mut(out, segments, |out| kernels::segmented(values, offsets, out, empty, reduce)) as isize
+}
 
-    #[test]
-    fn test_sort_f64_negative() {
-        let mut data = vec![-3.0, -1.0, -2.0];
-        unsafe { tova_sort_f64(data.as_mut_ptr(), data.len()); }
-        assert_eq!(data, vec![-3.0, -2.0, -1.0]);
+/// Smallest value of each segment to `out` (0 for an empty one). Returns
+/// the number of empty segments, or TOVA_AGG_ERR_OFFSETS with nothing
+/// written.
+#[no_mangle]
+pub unsafe extern "C" fn tova_min_i64_segmented(values: *const i64, len: usize, offsets: *const u64, segments: usize, out: *mut i64) -> isize {
+    agg_segments(values, len, offsets, segments, out, 0, |_, run| kernels::argmin(run, None).map(|(_, v)| v))
+}
+
+/// Largest value of each segment; as `tova_min_i64_segmented`.
+#[no_mangle]
+pub unsafe extern "C" fn tova_max_i64_segmented(values: *const i64, len: usize, offsets: *const u64, segments: usize, out: *mut i64) -> isize {
+    agg_segments(values, len, offsets, segments, out, 0, |_, run| kernels::argmax(run, None).map(|(_, v)| v))
+}
+
+/// Position in `values` of each segment's first smallest value (u64::MAX
+/// for an empty one); as `tova_min_i64_segmented`.
+#[no_mangle]
+pub unsafe extern "C" fn tova_argmin_i64_segmented(values: *const i64, len: usize, offsets: *const u64, segments: usize, out: *mut u64) -> isize {
+    agg_segments(values, len, offsets, segments, out, u64::MAX, |start, run| kernels::argmin(run, None).map(|(i, _)| (start + i) as u64))
+}
+
+/// Position in `values` of each segment's first largest value (u64::MAX
+/// for an empty one); as `tova_min_i64_segmented`.
+#[no_mangle]
+pub unsafe extern "C" fn tova_argmax_i64_segmented(values: *const i64, len: usize, offsets: *const u64, segments: usize, out: *mut u64) -> isize {
+    agg_segments(values, len, offsets, segments, out, u64::MAX, |start, run| kernels::argmax(run, None).map(|(i, _)| (start + i) as u64))
+}
+
+/// Mean of each segment as `tova_mean_i64` computes it (NaN for an empty
+/// one); as `tova_min_i64_segmented`.
+#[no_mangle]
+pub unsafe extern "C" fn tova_mean_i64_segmented(values: *const i64, len: usize, offsets: *const u64, segments: usize, out: *mut f64) -> isize {
+    agg_segments(values, len, offsets, segments, out, f64::NAN, |_, run| kernels::mean_i64(run, None))
+}
+
+// ============================================================
+// Arrow IPC ingestion
+// ============================================================
+
+// Minimal reader for Arrow IPC streams and files: walks the flatbuffer
+// metadata just far enough to find one Float64 column's validity bitmap and
+// data buffer in each record batch, then aggregates them where they lie.
+
+pub const TOVA_ARROW_ERR_MALFORMED: i32 = -1;
+pub const TOVA_ARROW_ERR_UNSUPPORTED_TYPE: i32 = -2;
+pub const TOVA_ARROW_ERR_COMPRESSED: i32 = -3;
+pub const TOVA_ARROW_ERR_NO_COLUMN: i32 = -4;
+pub const TOVA_ARROW_ERR_BIG_ENDIAN: i32 = -5;
+
+// Schema.fbs / Message.fbs constants
+const ARROW_HEADER_SCHEMA: u8 = 1;
+const ARROW_HEADER_RECORD_BATCH: u8 = 3;
+const ARROW_TYPE_FLOATING_POINT: u8 = 3;
+const ARROW_PRECISION_DOUBLE: i16 = 2;
+const ARROW_MAX_NESTING: usize = 64;
+
+type Fb<T> = Result<T, i32>;
+
+fn fb_bytes<const N: usize>(buf: &[u8], at: usize) -> Fb<[u8; N]> {
+    at.checked_add(N)
+        .and_then(|end| buf.get(at..end))
+        .map(|b| b.try_into().unwrap())
+        .ok_or(TOVA_ARROW_ERR_MALFORMED)
+}
+
+fn fb_u32(buf: &[u8], at: usize) -> Fb<u32> {
+    fb_bytes::<4>(buf, at).map(u32::from_le_bytes)
+}
+
+fn fb_i32(buf: &[u8], at: usize) -> Fb<i32> {
+    fb_bytes::<4>(buf, at).map(i32::from_le_bytes)
+}
+
+fn fb_u16(buf: &[u8], at: usize) -> Fb<u16> {
+    fb_bytes::<2>(buf, at).map(u16::from_le_bytes)
+}
+
+/// A flatbuffer table; every read is bounds-checked against the buffer.
+#[derive(Clone, Copy)]
+struct FbTable<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    vtable: usize,
+    vtable_len: usize,
+}
+
+/// A flatbuffer vector: `len` elements starting at `start`.
+#[derive(Clone, Copy)]
+struct FbVector<'a> {
+    buf: &'a [u8],
+    start: usize,
+    len: usize,
+}
+
+impl<'a> FbTable<'a> {
+    fn root(buf: &'a [u8]) -> Fb<Self> {
+        Self::at(buf, fb_u32(buf, 0)? as usize)
     }
 
-    #[test]
-    fn test_sort_i64() {
-        let mut data = vec![5i64, -3, 0, 10, -1, 7, 2];
-        unsafe { tova_sort_i64(data.as_mut_ptr(), data.len()); }
-        assert_eq!(data, vec![-3, -1, 0, 2, 5, 7, 10]);
+    fn at(buf: &'a [u8], pos: usize) -> Fb<Self> {
+        let vtable = (pos as i64) - (fb_i32(buf, pos)? as i64);
+        if vtable < 0 {
+            return Err(TOVA_ARROW_ERR_MALFORMED);
+        }
+        let vtable = vtable as usize;
+        let vtable_len = fb_u16(buf, vtable)? as usize;
+        if vtable_len < 4 || vtable + vtable_len > buf.len() {
+            return Err(TOVA_ARROW_ERR_MALFORMED);
+        }
+        Ok(FbTable { buf, pos, vtable, vtable_len })
     }
 
-    #[test]
-    fn test_sort_i64_large() {
-        let mut data: Vec<i64> = (0..10000).map(|i| 5000 - i).collect();
-        unsafe { tova_sort_i64(data.as_mut_ptr(), data.len()); }
-        let expected: Vec<i64> = (-4999..=5000).collect();
-        assert_eq!(data, expected);
+    fn field_pos(&self, id: usize) -> Fb<Option<usize>> {
+        let slot = 4 + 2 * id;
+        if slot + 2 > self.vtable_len {
+            return Ok(None);
+        }
+        match fb_u16(self.buf, self.vtable + slot)? {
+            0 => Ok(None),
+            off => Ok(Some(self.pos + off as usize)),
+        }
     }
 
-    #[test]
-    fn test_unique_sorted() {
-        let mut data = vec![1i64, 1, 2, 2, 3, 3, 3, 4];
-        let new_len = unsafe { tova_unique_sorted_i64(data.as_mut_ptr(), data.len()) };
-        assert_eq!(new_len, 4);
-        assert_eq!(&data[..new_len], &[1, 2, 3, 4]);
+    fn u8_field(&self, id: usize, default: u8) -> Fb<u8> {
+        match self.field_pos(id)? {
+            Some(p) => fb_bytes::<1>(self.buf, p).map(|b| b[0]),
+            None => Ok(default),
+        }
     }
 
-    #[test]
-    fn test_sum_f64() {
-        let data = vec![1.0, 2.0, 3.0, 4.0, 5.0];
-        let sum = unsafe { tova_sum_f64(data.as_ptr(), data.len()) };
-        assert_eq!(sum, 15.0);
+    fn i16_field(&self, id: usize, default: i16) -> Fb<i16> {
+        match self.field_pos(id)? {
+            Some(p) => fb_bytes::<2>(self.buf, p).map(i16::from_le_bytes),
+            None => Ok(default),
+        }
     }
 
-    #[test]
-    fn test_min_max_f64() {
-        let data = vec![3.0, 1.0, 4.0, 1.5, 9.0, 2.6];
-        let min = unsafe { tova_min_f64(data.as_ptr(), data.len()) };
-        let max = unsafe { tova_max_f64(data.as_ptr(), data.len()) };
-        assert_eq!(min, 1.0);
-        assert_eq!(max, 9.0);
+    fn i64_field(&self, id: usize, default: i64) -> Fb<i64> {
+        match self.field_pos(id)? {
+            Some(p) => fb_bytes::<8>(self.buf, p).map(i64::from_le_bytes),
+            None => Ok(default),
+        }
+    }
+
+    fn offset_target(&self, id: usize) -> Fb<Option<usize>> {
+        match self.field_pos(id)? {
+            Some(p) => Ok(Some(p + fb_u32(self.buf, p)? as usize)),
+            None => Ok(None),
+        }
+    }
+
+    fn table_field(&self, id: usize) -> Fb<Option<FbTable<'a>>> {
+        match self.offset_target(id)? {
+            Some(t) => FbTable::at(self.buf, t).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn vector_field(&self, id: usize) -> Fb<Option<FbVector<'a>>> {
+        match self.offset_target(id)? {
+            Some(t) => Ok(Some(FbVector {
+                buf: self.buf,
+                start: t + 4,
+                len: fb_u32(self.buf, t)? as usize,
+            })),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<'a> FbVector<'a> {
+    fn table(&self, i: usize) -> Fb<FbTable<'a>> {
+        if i >= self.len {
+            return Err(TOVA_ARROW_ERR_MALFORMED);
+        }
+        let p = self.start + 4 * i;
+        FbTable::at(self.buf, p + fb_u32(self.buf, p)? as usize)
+    }
+
+    /// Element `i` of a vector of `size`-byte structs
+    fn struct_bytes(&self, i: usize, size: usize) -> Fb<&'a [u8]> {
+        if i >= self.len {
+            return Err(TOVA_ARROW_ERR_MALFORMED);
+        }
+        let p = self.start + size * i;
+        self.buf.get(p..p + size).ok_or(TOVA_ARROW_ERR_MALFORMED)
+    }
+}
+
+/// Position of a column's field node and first buffer within a record batch.
+#[derive(Clone, Copy)]
+struct ArrowColumnSlot {
+    node: usize,
+    buffer: usize,
+}
+
+/// Nodes and buffers a field (with its children) occupies in a record batch,
+/// following the IPC depth-first flattening.
+fn arrow_field_layout(field: FbTable, depth: usize) -> Fb<(usize, usize)> {
+    if depth > ARROW_MAX_NESTING {
+        return Err(TOVA_ARROW_ERR_MALFORMED);
+    }
+    // Dictionary-encoded fields carry validity + indices; values live in dictionary batches
+    if field.table_field(4)?.is_some() {
+        return Ok((1, 2));
+    }
+    let own_buffers = match field.u8_field(2, 0)? {
+        1 => 0, // Null
+        // Fixed width: validity + values
+        2 | 3 | 6 | 7 | 8 | 9 | 10 | 11 | 15 | 18 => 2,
+        // (Large)Binary/Utf8: validity + offsets + data
+        4 | 5 | 19 | 20 => 3,
+        // (Large)List, Map: validity + offsets
+        12 | 17 | 21 => 2,
+        // Struct, FixedSizeList: validity only
+        13 | 16 => 1,
+        // Unions, views, run-end encoded
+        _ => return Err(TOVA_ARROW_ERR_UNSUPPORTED_TYPE),
+    };
+    let mut nodes = 1;
+    let mut buffers = own_buffers;
+    if let Some(children) = field.vector_field(5)? {
+        for i in 0..children.len {
+            let (n, b) = arrow_field_layout(children.table(i)?, depth + 1)?;
+            nodes += n;
+            buffers += b;
+        }
+    }
+    Ok((nodes, buffers))
+}
+
+fn arrow_locate_f64_column(schema: FbTable, column: usize) -> Fb<ArrowColumnSlot> {
+    if schema.i16_field(0, 0)? != 0 {
+        return Err(TOVA_ARROW_ERR_BIG_ENDIAN);
+    }
+    let fields = schema.vector_field(1)?.ok_or(TOVA_ARROW_ERR_MALFORMED)?;
+    if column >= fields.len {
+        return Err(TOVA_ARROW_ERR_NO_COLUMN);
+    }
+    let mut slot = ArrowColumnSlot { node: 0, buffer: 0 };
+    for i in 0..column {
+        let (n, b) = arrow_field_layout(fields.table(i)?, 0)?;
+        slot.node += n;
+        slot.buffer += b;
+    }
+    let field = fields.table(column)?;
+    let is_f64 = field.table_field(4)?.is_none()
+        && field.u8_field(2, 0)? == ARROW_TYPE_FLOATING_POINT
+        && match field.table_field(3)? {
+            Some(ty) => ty.i16_field(0, 0)? == ARROW_PRECISION_DOUBLE,
+            None => false,
+        };
+    if !is_f64 {
+        return Err(TOVA_ARROW_ERR_UNSUPPORTED_TYPE);
+    }
+    Ok(slot)
+}
+
+/// Running aggregate over the valid values of one column across batches.
+struct ArrowF64Agg {
+    sum: f64,
+    comp: f64,
+    min: f64,
+    max: f64,
+    count: u64,
+}
+
+impl ArrowF64Agg {
+    fn new() -> Self {
+        ArrowF64Agg { sum: 0.0, comp: 0.0, min: f64::NAN, max: f64::NAN, count: 0 }
+    }
+
+    /// `values` holds n little-endian f64s; read bytewise since an IPC buffer
+    /// handed over from JS has no alignment guarantee.
+    fn update(&mut self, values: &[u8], validity: Option<&[u8]>, n: usize) {
+        for (i, chunk) in values.chunks_exact(8).take(n).enumerate() {
+            if let Some(bits) = validity {
+                if (bits[i >> 3] >> (i & 7)) & 1 == 0 {
+                    continue;
+                }
+            }
+            let val = f64::from_le_bytes(chunk.try_into().unwrap());
+            let y = val - self.comp;
+            let t = self.sum + y;
+            self.comp = (t - self.sum) - y;
+            self.sum = t;
+            self.min = kernels::fold_min_f64(self.min, val);
+            self.max = kernels::fold_max_f64(self.max, val);
+            self.count += 1;
+        }
+    }
+}
+
+fn arrow_body_slice<'a>(body: &'a [u8], buffers: FbVector, index: usize) -> Fb<&'a [u8]> {
+    let desc = buffers.struct_bytes(index, 16)?;
+    let offset = i64::from_le_bytes(desc[0..8].try_into().unwrap());
+    let length = i64::from_le_bytes(desc[8..16].try_into().unwrap());
+    // Negative, or past the address space on a 32-bit target
+    let start = usize::try_from(offset).map_err(|_| TOVA_ARROW_ERR_MALFORMED)?;
+    let length = usize::try_from(length).map_err(|_| TOVA_ARROW_ERR_MALFORMED)?;
+    let end = start.checked_add(length).ok_or(TOVA_ARROW_ERR_MALFORMED)?;
+    body.get(start..end).ok_or(TOVA_ARROW_ERR_MALFORMED)
+}
+
+fn arrow_fold_batch(batch: FbTable, body: &[u8], slot: ArrowColumnSlot, agg: &mut ArrowF64Agg) -> Fb<()> {
+    if batch.table_field(3)?.is_some() {
+        return Err(TOVA_ARROW_ERR_COMPRESSED);
+    }
+    let nodes = batch.vector_field(1)?.ok_or(TOVA_ARROW_ERR_MALFORMED)?;
+    let buffers = batch.vector_field(2)?.ok_or(TOVA_ARROW_ERR_MALFORMED)?;
+    let node = nodes.struct_bytes(slot.node, 16)?;
+    let length = i64::from_le_bytes(node[0..8].try_into().unwrap());
+    let null_count = i64::from_le_bytes(node[8..16].try_into().unwrap());
+    if null_count < 0 {
+        return Err(TOVA_ARROW_ERR_MALFORMED);
+    }
+    let n = usize::try_from(length).map_err(|_| TOVA_ARROW_ERR_MALFORMED)?;
+
+    let validity = arrow_body_slice(body, buffers, slot.buffer)?;
+    let values = arrow_body_slice(body, buffers, slot.buffer + 1)?;
+    if values.len() / 8 < n {
+        return Err(TOVA_ARROW_ERR_MALFORMED);
+    }
+    // Writers may omit the bitmap when nothing is null
+    let validity = if null_count == 0 || validity.is_empty() {
+        None
+    } else if validity.len() < n.div_ceil(8) {
+        return Err(TOVA_ARROW_ERR_MALFORMED);
+    } else {
+        Some(validity)
+    };
+    agg.update(values, validity, n);
+    Ok(())
+}
+
+/// Encapsulated message at `pos`: [0xFFFFFFFF] <i32 metadata size> <Message> <body>;
+/// pre-1.0 writers omit the continuation marker. Returns the message, its
+/// body and where the next message starts, or None at the end-of-stream marker.
+fn arrow_read_message(ipc: &[u8], mut pos: usize) -> Fb<Option<(FbTable<'_>, &[u8], usize)>> {
+    let end = ipc.len();
+    let mut meta_len = fb_i32(ipc, pos)?;
+    pos += 4;
+    if meta_len == -1 {
+        meta_len = fb_i32(ipc, pos)?;
+        pos += 4;
+    }
+    if meta_len == 0 {
+        return Ok(None);
+    }
+    let meta_end = match usize::try_from(meta_len).ok().and_then(|m| pos.checked_add(m)) {
+        Some(meta_end) if meta_end <= end => meta_end,
+        _ => return Err(TOVA_ARROW_ERR_MALFORMED),
+    };
+    let message = FbTable::root(&ipc[pos..meta_end])?;
+    let body_len = message.i64_field(3, 0)?;
+    let body_end = match usize::try_from(body_len) {
+        Ok(body_len) if body_len <= end - meta_end => meta_end + body_len,
+        _ => return Err(TOVA_ARROW_ERR_MALFORMED),
+    };
+    Ok(Some((message, &ipc[meta_end..body_end], body_end)))
+}
+
+fn arrow_fold_message(message: FbTable, body: &[u8], slot: ArrowColumnSlot, agg: &mut ArrowF64Agg) -> Fb<()> {
+    let batch = message.table_field(2)?.ok_or(TOVA_ARROW_ERR_MALFORMED)?;
+    arrow_fold_batch(batch, body, slot, agg)
+}
+
+/// File format: magic + padding, stream, footer, footer length, magic. The
+/// padding after the magic depends on the writer's alignment (arrow-rs pads
+/// to 64 bytes), so batches are found through the footer's blocks rather
+/// than by walking the stream.
+fn arrow_aggregate_file(ipc: &[u8], column: usize) -> Fb<ArrowF64Agg> {
+    if ipc.len() < 18 || &ipc[ipc.len() - 6..] != b"ARROW1" {
+        return Err(TOVA_ARROW_ERR_MALFORMED);
+    }
+    let footer_end = ipc.len() - 10;
+    let footer_len = fb_i32(ipc, footer_end)?;
+    if footer_len < 0 || footer_len as usize > footer_end - 8 {
+        return Err(TOVA_ARROW_ERR_MALFORMED);
+    }
+    let footer_start = footer_end - footer_len as usize;
+    let footer = FbTable::root(&ipc[footer_start..footer_end])?;
+    let schema = footer.table_field(1)?.ok_or(TOVA_ARROW_ERR_MALFORMED)?;
+    let slot = arrow_locate_f64_column(schema, column)?;
+    let mut agg = ArrowF64Agg::new();
+    if let Some(blocks) = footer.vector_field(3)? {
+        for i in 0..blocks.len {
+            // Block: i64 offset, i32 metadata length, padding, i64 body length
+            let block = blocks.struct_bytes(i, 24)?;
+            let offset = i64::from_le_bytes(block[0..8].try_into().unwrap());
+            let offset = usize::try_from(offset).map_err(|_| TOVA_ARROW_ERR_MALFORMED)?;
+            if offset < 8 || offset >= footer_start {
+                return Err(TOVA_ARROW_ERR_MALFORMED);
+            }
+            match arrow_read_message(&ipc[..footer_start], offset)? {
+                Some((message, body, _)) if message.u8_field(1, 0)? == ARROW_HEADER_RECORD_BATCH => {
+                    arrow_fold_message(message, body, slot, &mut agg)?
+                }
+                _ => return Err(TOVA_ARROW_ERR_MALFORMED),
+            }
+        }
+    }
+    Ok(agg)
+}
+
+fn arrow_aggregate_f64(ipc: &[u8], column: usize) -> Fb<ArrowF64Agg> {
+    if ipc.len() >= 8 && &ipc[..6] == b"ARROW1" {
+        return arrow_aggregate_file(ipc, column);
+    }
+    let mut pos = 0;
+    let mut slot = None;
+    let mut agg = ArrowF64Agg::new();
+    while pos + 4 <= ipc.len() {
+        let Some((message, body, next)) = arrow_read_message(ipc, pos)? else {
+            break; // end-of-stream marker
+        };
+        match message.u8_field(1, 0)? {
+            ARROW_HEADER_SCHEMA => {
+                let schema = message.table_field(2)?.ok_or(TOVA_ARROW_ERR_MALFORMED)?;
+                slot = Some(arrow_locate_f64_column(schema, column)?);
+            }
+            ARROW_HEADER_RECORD_BATCH => {
+                let slot = slot.ok_or(TOVA_ARROW_ERR_MALFORMED)?;
+                arrow_fold_message(message, body, slot, &mut agg)?;
+            }
+            // Dictionary batches and tensors never hold a plain Float64 column
+            _ => {}
+        }
+        pos = next;
+    }
+    if slot.is_none() {
+        return Err(TOVA_ARROW_ERR_MALFORMED);
+    }
+    Ok(agg)
+}
+
+fn arrow_error_message(code: i32) -> &'static str {
+    match code {
+        TOVA_ARROW_ERR_UNSUPPORTED_TYPE => "arrow: column is not Float64",
+        TOVA_ARROW_ERR_COMPRESSED => "arrow: compressed record batches are not supported",
+        TOVA_ARROW_ERR_NO_COLUMN => "arrow: column index out of range",
+        TOVA_ARROW_ERR_BIG_ENDIAN => "arrow: big-endian IPC data is not supported",
+        _ => "arrow: malformed IPC data",
+    }
+}
+
+unsafe fn arrow_run(
+    ipc_ptr: *const u8,
+    ipc_len: usize,
+    column_index: u32,
+    write: impl FnOnce(&ArrowF64Agg),
+) -> i32 {
+    clear_last_error();
+    if ipc_ptr.is_null() {
+        return fail(TOVA_ARROW_ERR_MALFORMED, arrow_error_message(TOVA_ARROW_ERR_MALFORMED));
+    }
+    let ipc = slice::from_raw_parts(ipc_ptr, ipc_len);
+    match arrow_aggregate_f64(ipc, column_index as usize) {
+        Ok(agg) => {
+            write(&agg);
+            0
+        }
+        Err(code) => fail(code, arrow_error_message(code)),
+    }
+}
+
+/// Sum the non-null values of Float64 column `column_index` across every
+/// record batch of an Arrow IPC stream or file. Returns 0 and writes `out`,
+/// or a negative TOVA_ARROW_ERR_* code.
+#[no_mangle]
+pub unsafe extern "C" fn tova_arrow_sum_f64(ipc_ptr: *const u8, ipc_len: usize, column_index: u32, out: *mut f64) -> i32 {
+    arrow_run(ipc_ptr, ipc_len, column_index, |agg| out.write_unaligned(agg.sum))
+}
+
+/// Minimum non-null, non-NaN value of a Float64 IPC column (NaN when there
+/// is none), with the same semantics as `tova_min_f64`.
+#[no_mangle]
+pub unsafe extern "C" fn tova_arrow_min_f64(ipc_ptr: *const u8, ipc_len: usize, column_index: u32, out: *mut f64) -> i32 {
+    arrow_run(ipc_ptr, ipc_len, column_index, |agg| out.write_unaligned(agg.min))
+}
+
+/// Maximum non-null, non-NaN value of a Float64 IPC column (NaN when there
+/// is none), with the same semantics as `tova_max_f64`.
+#[no_mangle]
+pub unsafe extern "C" fn tova_arrow_max_f64(ipc_ptr: *const u8, ipc_len: usize, column_index: u32, out: *mut f64) -> i32 {
+    arrow_run(ipc_ptr, ipc_len, column_index, |agg| out.write_unaligned(agg.max))
+}
+
+/// Number of non-null values in a Float64 IPC column.
+#[no_mangle]
+pub unsafe extern "C" fn tova_arrow_count_f64(ipc_ptr: *const u8, ipc_len: usize, column_index: u32, out: *mut u64) -> i32 {
+    arrow_run(ipc_ptr, ipc_len, column_index, |agg| out.write_unaligned(agg.count))
+}
+
+// ============================================================
+// NPY ingestion
+// ============================================================
+
+pub const TOVA_NPY_ERR_MALFORMED: i32 = -1;
+pub const TOVA_NPY_ERR_VERSION: i32 = -2;
+pub const TOVA_NPY_ERR_UNSUPPORTED_DTYPE: i32 = -3;
+pub const TOVA_NPY_ERR_BIG_ENDIAN: i32 = -4;
+pub const TOVA_NPY_ERR_FORTRAN_ORDER: i32 = -5;
+pub const TOVA_NPY_ERR_MULTI_DIM: i32 = -6;
+pub const TOVA_NPY_ERR_TRUNCATED: i32 = -7;
+pub const TOVA_NPY_ERR_CAPACITY: i32 = -8;
+
+pub const TOVA_NPY_DTYPE_F8: u32 = 1;
+pub const TOVA_NPY_DTYPE_I8: u32 = 2;
+pub const TOVA_NPY_DTYPE_F4: u32 = 3;
+pub const TOVA_NPY_DTYPE_I4: u32 = 4;
+
+pub const TOVA_NPY_LITTLE_ENDIAN: u32 = 0;
+pub const TOVA_NPY_BIG_ENDIAN: u32 = 1;
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+
+/// Header summary filled by `tova_npy_parse_header`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NpyInfo {
+    pub dtype: u32,
+    pub byte_order: u32,
+    pub item_size: u64,
+    pub element_count: u64,
+    pub data_offset: u64,
+}
+
+/// Cursor over the Python dict literal numpy writes as the header, e.g.
+/// `{'descr': '<f8', 'fortran_order': False, 'shape': (3,), }`.
+struct NpyHeaderLexer<'a> {
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> NpyHeaderLexer<'a> {
+    fn skip_ws(&mut self) {
+        while self.pos < self.s.len() && self.s[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        self.skip_ws();
+        if self.s.get(self.pos) == Some(&c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn string(&mut self) -> Option<&'a [u8]> {
+        self.skip_ws();
+        let quote = *self.s.get(self.pos)?;
+        if quote != b'\'' && quote != b'"' {
+            return None;
+        }
+        let start = self.pos + 1;
+        let len = self.s[start..].iter().position(|&c| c == quote)?;
+        self.pos = start + len + 1;
+        Some(&self.s[start..start + len])
+    }
+
+    fn word(&mut self) -> &'a [u8] {
+        self.skip_ws();
+        let start = self.pos;
+        while self.pos < self.s.len() && self.s[self.pos].is_ascii_alphanumeric() {
+            self.pos += 1;
+        }
+        &self.s[start..self.pos]
+    }
+
+    /// `(d0, d1, ...)` with an optional trailing comma
+    fn shape(&mut self) -> Option<Vec<u64>> {
+        if !self.eat(b'(') {
+            return None;
+        }
+        let mut dims = Vec::new();
+        loop {
+            if self.eat(b')') {
+                return Some(dims);
+            }
+            let digits = std::str::from_utf8(self.word()).ok()?;
+            dims.push(digits.parse().ok()?);
+            if !self.eat(b',') {
+                return if self.eat(b')') { Some(dims) } else { None };
+            }
+        }
+    }
+}
+
+fn npy_parse(bytes: &[u8]) -> Result<NpyInfo, i32> {
+    if bytes.len() < 10 || &bytes[..6] != NPY_MAGIC {
+        return Err(TOVA_NPY_ERR_MALFORMED);
+    }
+    // v1 has a u16 header length, v2/v3 a u32 (v3 only changes the header encoding to utf-8)
+    let (header_start, header_len) = match bytes[6] {
+        1 => (10, u16::from_le_bytes([bytes[8], bytes[9]]) as usize),
+        2 | 3 => {
+            if bytes.len() < 12 {
+                return Err(TOVA_NPY_ERR_MALFORMED);
+            }
+            (12, u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize)
+        }
+        _ => return Err(TOVA_NPY_ERR_VERSION),
+    };
+    let header = bytes
+        .get(header_start..header_start + header_len)
+        .ok_or(TOVA_NPY_ERR_MALFORMED)?;
+
+    let mut lex = NpyHeaderLexer { s: header, pos: 0 };
+    let mut descr = None;
+    let mut fortran = None;
+    let mut shape = None;
+    if !lex.eat(b'{') {
+        return Err(TOVA_NPY_ERR_MALFORMED);
+    }
+    loop {
+        if lex.eat(b'}') {
+            break;
+        }
+        let key = lex.string().ok_or(TOVA_NPY_ERR_MALFORMED)?;
+        if !lex.eat(b':') {
+            return Err(TOVA_NPY_ERR_MALFORMED);
+        }
+        match key {
+            b"descr" => descr = Some(lex.string().ok_or(TOVA_NPY_ERR_UNSUPPORTED_DTYPE)?),
+            b"fortran_order" => {
+                fortran = match lex.word() {
+                    b"True" => Some(true),
+                    b"False" => Some(false),
+                    _ => return Err(TOVA_NPY_ERR_MALFORMED),
+                }
+            }
+            b"shape" => shape = Some(lex.shape().ok_or(TOVA_NPY_ERR_MALFORMED)?),
+            _ => return Err(TOVA_NPY_ERR_MALFORMED),
+        }
+        if !lex.eat(b',') {
+            if lex.eat(b'}') {
+                break;
+            }
+            return Err(TOVA_NPY_ERR_MALFORMED);
+        }
+    }
+    let (descr, fortran, shape) = match (descr, fortran, shape) {
+        (Some(d), Some(f), Some(s)) => (d, f, s),
+        _ => return Err(TOVA_NPY_ERR_MALFORMED),
+    };
+
+    let byte_order = match descr.first() {
+        Some(b'<') => TOVA_NPY_LITTLE_ENDIAN,
+        Some(b'>') => TOVA_NPY_BIG_ENDIAN,
+        _ => return Err(TOVA_NPY_ERR_UNSUPPORTED_DTYPE),
+    };
+    let (dtype, item_size) = match &descr[1..] {
+        b"f8" => (TOVA_NPY_DTYPE_F8, 8),
+        b"i8" => (TOVA_NPY_DTYPE_I8, 8),
+        b"f4" => (TOVA_NPY_DTYPE_F4, 4),
+        b"i4" => (TOVA_NPY_DTYPE_I4, 4),
+        _ => return Err(TOVA_NPY_ERR_UNSUPPORTED_DTYPE),
+    };
+    // A 2-D array with one non-unit axis is still multi-dimensional to callers
+    // indexing it, so only 0-D and 1-D shapes are accepted
+    if shape.len() > 1 {
+        return Err(TOVA_NPY_ERR_MULTI_DIM);
+    }
+    if fortran {
+        return Err(TOVA_NPY_ERR_FORTRAN_ORDER);
+    }
+    Ok(NpyInfo {
+        dtype,
+        byte_order,
+        item_size,
+        element_count: shape.first().copied().unwrap_or(1),
+        data_offset: (header_start + header_len) as u64,
+    })
+}
+
+/// Parse and validate a .npy header. Returns 0 and fills `out_info`, or a
+/// negative TOVA_NPY_ERR_* code. Big-endian payloads are reported, not rejected.
+#[no_mangle]
+pub unsafe extern "C" fn tova_npy_parse_header(bytes: *const u8, len: usize, out_info: *mut NpyInfo) -> i32 {
+    clear_last_error();
+    if bytes.is_null() || out_info.is_null() {
+        return npy_fail(TOVA_NPY_ERR_MALFORMED);
+    }
+    match npy_parse(slice::from_raw_parts(bytes, len)) {
+        Ok(info) => {
+            out_info.write_unaligned(info);
+            0
+        }
+        Err(code) => npy_fail(code),
+    }
+}
+
+fn npy_fail(code: i32) -> i32 {
+    let message = match code {
+        TOVA_NPY_ERR_VERSION => "npy: unsupported format version",
+        TOVA_NPY_ERR_UNSUPPORTED_DTYPE => "npy: unsupported dtype",
+        TOVA_NPY_ERR_BIG_ENDIAN => "npy: big-endian payload",
+        TOVA_NPY_ERR_FORTRAN_ORDER => "npy: Fortran-ordered array",
+        TOVA_NPY_ERR_MULTI_DIM => "npy: array is not one-dimensional",
+        TOVA_NPY_ERR_TRUNCATED => "npy: payload shorter than the header declares",
+        TOVA_NPY_ERR_CAPACITY => "npy: output buffer too small",
+        _ => "npy: malformed header",
+    };
+    fail(code, message)
+}
+
+/// Copy a little-endian f8 or i8 .npy payload into `out` as f64 (i8 values are
+/// converted). Returns the element count or a negative TOVA_NPY_ERR_* code.
+#[no_mangle]
+pub unsafe extern "C" fn tova_npy_read_f64(bytes: *const u8, len: usize, out: *mut f64, out_cap: usize) -> isize {
+    clear_last_error();
+    if bytes.is_null() {
+        return npy_fail(TOVA_NPY_ERR_MALFORMED) as isize;
+    }
+    let mut bytes_copy = Vec::new();
+    let bytes = unaliased(bytes, len, out, out_cap, &mut bytes_copy);
+    let info = match npy_parse(bytes) {
+        Ok(info) => info,
+        Err(code) => return npy_fail(code) as isize,
+    };
+    if info.byte_order != TOVA_NPY_LITTLE_ENDIAN {
+        return npy_fail(TOVA_NPY_ERR_BIG_ENDIAN) as isize;
+    }
+    if info.dtype != TOVA_NPY_DTYPE_F8 && info.dtype != TOVA_NPY_DTYPE_I8 {
+        return npy_fail(TOVA_NPY_ERR_UNSUPPORTED_DTYPE) as isize;
+    }
+    // A count or offset past the address space cannot fit in `bytes` either
+    let (n, start) = match (usize::try_from(info.element_count), usize::try_from(info.data_offset)) {
+        (Ok(n), Ok(start)) => (n, start),
+        _ => return TOVA_NPY_ERR_TRUNCATED as isize,
+    };
+    let payload = match n.checked_mul(8).and_then(|b| bytes.get(start..start.checked_add(b)?)) {
+        Some(p) => p,
+        None => return TOVA_NPY_ERR_TRUNCATED as isize,
+    };
+    if n > out_cap || out.is_null() {
+        return if n == 0 { 0 } else { npy_fail(TOVA_NPY_ERR_CAPACITY) as isize };
+    }
+    // The payload offset is 64-byte aligned in the file, not necessarily in memory
+    with_view_mut(out, n, |out| {
+        for (dst, chunk) in out.iter_mut().zip(payload.chunks_exact(8)) {
+            let raw: [u8; 8] = chunk.try_into().unwrap();
+            *dst = if info.dtype == TOVA_NPY_DTYPE_F8 {
+                f64::from_le_bytes(raw)
+            } else {
+                i64::from_le_bytes(raw) as f64
+            };
+        }
+    });
+    n as isize
+}
+
+// ============================================================
+// String distance
+// ============================================================
+
+/// Levenshtein (byte-wise edit) distance between two strings. Returns u32::MAX
+/// once the distance is known to exceed `max_dist`; pass u32::MAX for no bound.
+#[no_mangle]
+pub unsafe extern "C" fn tova_levenshtein(a: *const u8, alen: usize, b: *const u8, blen: usize, max_dist: u32) -> u32 {
+    let a = if alen == 0 { &[][..] } else { slice::from_raw_parts(a, alen) };
+    let b = if blen == 0 { &[][..] } else { slice::from_raw_parts(b, blen) };
+    kernels::levenshtein(a, b, max_dist)
+}
+
+/// Distance from `query` to each of `count` strings in an offsets+bytes column
+/// (`offsets` holds count + 1 entries; string i is bytes[offsets[i]..offsets[i+1]]).
+/// Writes u32::MAX for candidates beyond `max_dist` or with invalid offsets.
+/// Inputs aliasing `out` are copied first (recorded as TOVA_ERR_OVERLAP).
+#[no_mangle]
+pub unsafe extern "C" fn tova_levenshtein_batch(
+    query: *const u8,
+    qlen: usize,
+    bytes: *const u8,
+    offsets: *const u64,
+    count: usize,
+    max_dist: u32,
+    out: *mut u32,
+) {
+    clear_last_error();
+    if count == 0 {
+        return;
+    }
+    let (mut query_copy, mut offsets_copy, mut bytes_copy) = (Vec::new(), Vec::new(), Vec::new());
+    let query = unaliased(query, qlen, out, count, &mut query_copy);
+    let offsets = unaliased(offsets, count + 1, out, count, &mut offsets_copy);
+    let bytes = unaliased(bytes, offsets[count] as usize, out, count, &mut bytes_copy);
+    with_view_mut(out, count, |out| kernels::levenshtein_batch(query, bytes, offsets, max_dist, out));
+}
+
+/// Hamming distance between two bit fingerprints of `len` u64 words.
+#[no_mangle]
+pub unsafe extern "C" fn tova_hamming_u64(a: *const u64, b: *const u64, len: usize) -> u64 {
+    if len == 0 {
+        return 0;
+    }
+    kernels::hamming_u64(view(a, len, &mut Vec::new()), view(b, len, &mut Vec::new()))
+}
+
+// ============================================================
+// Substring search
+// ============================================================
+
+/// Report overlapping matches ("aa" occurs 4 times in "aaaaa" instead of 2).
+pub const TOVA_FIND_OVERLAPPING: u32 = 1;
+/// Compare ASCII letters case-insensitively; other bytes must match exactly.
+pub const TOVA_FIND_IGNORE_ASCII_CASE: u32 = 2;
+
+/// Borrow the haystack and needle of a search export.
+unsafe fn search_args<'a>(haystack: *const u8, hlen: usize, needle: *const u8, nlen: usize) -> (&'a [u8], &'a [u8]) {
+    let hay = if hlen == 0 { &[][..] } else { slice::from_raw_parts(haystack, hlen) };
+    let needle = if nlen == 0 { &[][..] } else { slice::from_raw_parts(needle, nlen) };
+    (hay, needle)
+}
+
+/// Write the offsets of every match of `needle` in `haystack` to `out_offsets`.
+/// Returns the match count, or the negated count when it exceeds `out_cap`
+/// (the first `out_cap` offsets are still written).
+#[no_mangle]
+pub unsafe extern "C" fn tova_find_all(
+    haystack: *const u8,
+    hlen: usize,
+    needle: *const u8,
+    nlen: usize,
+    flags: u32,
+    out_offsets: *mut u64,
+    out_cap: usize,
+) -> isize {
+    clear_last_error();
+    let (mut hay_copy, mut needle_copy) = (Vec::new(), Vec::new());
+    let hay = unaliased(haystack, hlen, out_offsets, out_cap, &mut hay_copy);
+    let needle = unaliased(needle, nlen, out_offsets, out_cap, &mut needle_copy);
+    let count = with_view_mut(out_offsets, out_cap, |out| kernels::find_all(hay, needle, flags, out));
+    if count > out_cap { -(count as isize) } else { count as isize }
+}
+
+/// Number of matches of `needle` in `haystack` (see TOVA_FIND_* flags).
+#[no_mangle]
+pub unsafe extern "C" fn tova_count_occurrences(haystack: *const u8, hlen: usize, needle: *const u8, nlen: usize, flags: u32) -> u64 {
+    let (hay, needle) = search_args(haystack, hlen, needle, nlen);
+    kernels::count_occurrences(hay, needle, flags)
+}
+
+/// Offset of the first match of `needle` in `haystack`, or -1.
+#[no_mangle]
+pub unsafe extern "C" fn tova_find_first(haystack: *const u8, hlen: usize, needle: *const u8, nlen: usize, flags: u32) -> i64 {
+    let (hay, needle) = search_args(haystack, hlen, needle, nlen);
+    kernels::find_first(hay, needle, flags).map_or(-1, |pos| pos as i64)
+}
+
+// ============================================================
+// JSON number arrays
+// ============================================================
+
+/// Syntax errors return `TOVA_JSON_ERR_BASE - offset`, far below any negated
+/// capacity, so a result `r <= TOVA_JSON_ERR_BASE` means "invalid at byte
+/// `TOVA_JSON_ERR_BASE - r`".
+pub const TOVA_JSON_ERR_BASE: isize = isize::MIN / 2;
+
+/// Parse a flat JSON array of numbers into `out`. Returns the element count,
+/// the negated count when it exceeds `out_cap` (the first `out_cap` values are
+/// still written), or `TOVA_JSON_ERR_BASE - offset` for invalid input. Numbers
+/// beyond f64 range become ±Infinity, as with JSON.parse.
+#[no_mangle]
+pub unsafe extern "C" fn tova_parse_json_numbers(bytes: *const u8, len: usize, out: *mut f64, out_cap: usize) -> isize {
+    clear_last_error();
+    let mut bytes_copy = Vec::new();
+    let bytes = unaliased(bytes, len, out, out_cap, &mut bytes_copy);
+    match with_view_mut(out, out_cap, |out| kernels::parse_json_numbers(bytes, out)) {
+        Ok(count) if count > out_cap => -(count as isize),
+        Ok(count) => count as isize,
+        Err(offset) => {
+            set_last_error(TOVA_ERR_INVALID_INPUT, &format!("json: unexpected input at byte {offset}"));
+            TOVA_JSON_ERR_BASE - offset as isize
+        }
+    }
+}
+
+// ============================================================
+// Decompression
+// ============================================================
+
+// Results share one isize: bytes written (>= 0), the negated required size
+// when `out_cap` is too small, or one of these codes at the bottom of the range.
+pub const TOVA_DECOMPRESS_ERR_CORRUPT: isize = isize::MIN;
+pub const TOVA_DECOMPRESS_ERR_TRUNCATED: isize = isize::MIN + 1;
+pub const TOVA_DECOMPRESS_ERR_TOO_LARGE: isize = isize::MIN + 2;
+pub const TOVA_DECOMPRESS_ERR_CHECKSUM: isize = isize::MIN + 3;
+pub const TOVA_DECOMPRESS_ERR_UNSUPPORTED: isize = isize::MIN + 4;
+
+pub const TOVA_FORMAT_GZIP: u32 = 1;
+pub const TOVA_FORMAT_ZSTD: u32 = 2;
+
+fn decompress_error_code(e: decompress::DecodeError) -> isize {
+    use decompress::DecodeError::*;
+    let (code, message) = match e {
+        NeedInput => (TOVA_DECOMPRESS_ERR_TRUNCATED, "decompress: input ends mid-stream"),
+        Corrupt => (TOVA_DECOMPRESS_ERR_CORRUPT, "decompress: corrupt input"),
+        Overflow => (TOVA_DECOMPRESS_ERR_TOO_LARGE, "decompress: output exceeds max_output"),
+        Checksum => (TOVA_DECOMPRESS_ERR_CHECKSUM, "decompress: checksum mismatch"),
+        Unsupported => (TOVA_DECOMPRESS_ERR_UNSUPPORTED, "decompress: unsupported stream feature"),
+    };
+    set_last_error(TOVA_ERR_INVALID_INPUT, message);
+    code
+}
+
+unsafe fn decompress_one_shot(
+    make: fn() -> decompress::Decoder,
+    hint: fn(&[u8]) -> Option<u64>,
+    src: *const u8,
+    len: usize,
+    out: *mut u8,
+    out_cap: usize,
+    max_output: usize,
+) -> isize {
+    clear_last_error();
+    if src.is_null() {
+        return TOVA_DECOMPRESS_ERR_TRUNCATED;
+    }
+    let mut src_copy = Vec::new();
+    let src = unaliased(src, len, out, out_cap, &mut src_copy);
+    let out: &mut [u8] = if out.is_null() { &mut [] } else { slice::from_raw_parts_mut(out, out_cap) };
+    match decompress::decompress_all(make, src, hint(src), out, max_output) {
+        Ok(n) => n as isize,
+        Err(Ok(needed)) => -(needed as isize),
+        Err(Err(e)) => decompress_error_code(e),
+    }
+}
+
+fn gzip_decoder() -> decompress::Decoder {
+    decompress::Decoder::Gzip(decompress::gzip::GzipDecoder::new())
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_decoder() -> decompress::Decoder {
+    decompress::Decoder::Zstd(Box::new(decompress::zstd::ZstdDecoder::new()))
+}
+
+/// Decompress a gzip buffer (one or more members) into `out`. Returns bytes
+/// written, the negated required size if `out_cap` is too small (taken from
+/// the ISIZE footer when possible), or a TOVA_DECOMPRESS_ERR_* code. Output
+/// beyond `max_output` bytes fails with TOO_LARGE instead of being produced.
+#[no_mangle]
+pub unsafe extern "C" fn tova_gzip_decompress(src: *const u8, len: usize, out: *mut u8, out_cap: usize, max_output: usize) -> isize {
+    decompress_one_shot(gzip_decoder, decompress::gzip::size_hint, src, len, out, out_cap, max_output)
+}
+
+/// Zstandard counterpart of `tova_gzip_decompress`, sized from the frame
+/// content-size header when present. Requires the `zstd` feature.
+#[cfg(feature = "zstd")]
+#[no_mangle]
+pub unsafe extern "C" fn tova_zstd_decompress(src: *const u8, len: usize, out: *mut u8, out_cap: usize, max_output: usize) -> isize {
+    decompress_one_shot(zstd_decoder, decompress::zstd::size_hint, src, len, out, out_cap, max_output)
+}
+
+/// Start a streaming decoder for TOVA_FORMAT_GZIP or TOVA_FORMAT_ZSTD that
+/// produces at most `max_output` bytes in total. Null for unknown or
+/// compiled-out formats. Free with `tova_decompress_free`.
+#[no_mangle]
+pub unsafe extern "C" fn tova_decompress_create(format: u32, max_output: u64) -> *mut DecompressStream {
+    let decoder = match format {
+        TOVA_FORMAT_GZIP => gzip_decoder(),
+        #[cfg(feature = "zstd")]
+        TOVA_FORMAT_ZSTD => zstd_decoder(),
+        _ => return std::ptr::null_mut(),
+    };
+    let max_output = usize::try_from(max_output).unwrap_or(usize::MAX);
+    Box::into_raw(Box::new(DecompressStream::new(decoder, max_output)))
+}
+
+/// Feed the next compressed chunk. Decoding advances a whole block at a time,
+/// so tiny chunks are buffered until a block completes. Returns the number of
+/// decoded bytes ready for `tova_decompress_read`, or an error code (after
+/// which the stream stays failed).
+#[no_mangle]
+pub unsafe extern "C" fn tova_decompress_feed(stream: *mut DecompressStream, src: *const u8, len: usize) -> isize {
+    clear_last_error();
+    if stream.is_null() {
+        return TOVA_DECOMPRESS_ERR_CORRUPT;
+    }
+    let chunk = if len == 0 { &[][..] } else { slice::from_raw_parts(src, len) };
+    match (*stream).feed(chunk) {
+        Ok(available) => available as isize,
+        Err(e) => decompress_error_code(e),
+    }
+}
+
+/// Move up to `out_cap` decoded bytes into `out`; returns how many were copied.
+#[no_mangle]
+pub unsafe extern "C" fn tova_decompress_read(stream: *mut DecompressStream, out: *mut u8, out_cap: usize) -> usize {
+    if stream.is_null() || out.is_null() {
+        return 0;
+    }
+    (*stream).read(slice::from_raw_parts_mut(out, out_cap))
+}
+
+/// Check that everything fed so far was a complete stream (trailers and
+/// checksums verified). Returns 0, TRUNCATED, or the error that failed the stream.
+#[no_mangle]
+pub unsafe extern "C" fn tova_decompress_finish(stream: *const DecompressStream) -> isize {
+    clear_last_error();
+    if stream.is_null() {
+        return TOVA_DECOMPRESS_ERR_CORRUPT;
+    }
+    match (*stream).finish() {
+        Ok(()) => 0,
+        Err(e) => decompress_error_code(e),
+    }
+}
+
+/// Release a stream from `tova_decompress_create`.
+#[no_mangle]
+pub unsafe extern "C" fn tova_decompress_free(stream: *mut DecompressStream) {
+    if !stream.is_null() {
+        drop(Box::from_raw(stream));
+    }
+}
+
+// ============================================================
+// Filter + gather
+// ============================================================
+
+/// Write the indices of the nonzero bytes of `mask` (len <= 2^32) to
+/// `out_indices`. Returns the count, or the negated count when it exceeds
+/// `out_cap` (the first `out_cap` indices are still written).
+#[no_mangle]
+pub unsafe extern "C" fn tova_nonzero_u8(mask: *const u8, len: usize, out_indices: *mut u32, out_cap: usize) -> isize {
+    clear_last_error();
+    if len == 0 {
+        return 0;
+    }
+    let mut mask_copy = Vec::new();
+    let mask = unaliased(mask, len, out_indices, out_cap, &mut mask_copy);
+    let count = with_view_mut(out_indices, out_cap, |out| kernels::nonzero_u8(mask, out));
+    if count > out_cap { -(count as isize) } else { count as isize }
+}
+
+unsafe fn gather<T: Copy>(values: *const T, values_len: usize, indices: *const u32, n: usize, out: *mut T) -> isize {
+    clear_last_error();
+    if n == 0 {
+        return 0;
+    }
+    let (mut values_copy, mut indices_copy) = (Vec::new(), Vec::new());
+    let values = unaliased(values, values_len, out, n, &mut values_copy);
+    let indices = unaliased(indices, n, out, n, &mut indices_copy);
+    match with_view_mut(out, n, |out| kernels::gather(values, indices, out)) {
+        Ok(()) => n as isize,
+        Err(kernels::IndexError::OutOfRange { position }) => {
+            let idx = indices[position];
+            let message = format!("gather: index {idx} at position {position} is out of range for {} values", values.len());
+            set_last_error(TOVA_INDEX_ERR_OUT_OF_RANGE, &message);
+            -(position as isize) - 1
+        }
+        Err(e) => unreachable!("gather only fails on range: {e:?}"),
+    }
+}
+
+/// out[i] = values[indices[i]] for i in 0..n — pairs with `tova_nonzero_u8`
+/// for filtering and with argsort output for permuting. Returns n, or
+/// `-(i + 1)` for the first i whose index is >= `values_len` (out[..i] is written).
+/// `out` may alias `values` or `indices`; the inputs are read as they were on entry.
+#[no_mangle]
+pub unsafe extern "C" fn tova_gather_f64(values: *const f64, values_len: usize, indices: *const u32, n: usize, out: *mut f64) -> isize {
+    gather(values, values_len, indices, n, out)
+}
+
+/// i64 variant of `tova_gather_f64`.
+#[no_mangle]
+pub unsafe extern "C" fn tova_gather_i64(values: *const i64, values_len: usize, indices: *const u32, n: usize, out: *mut i64) -> isize {
+    gather(values, values_len, indices, n, out)
+}
+
+// ============================================================
+// Dictionary columns
+// ============================================================
+
+// Arrow dictionary-encoded columns arrive as u32 codes into a values
+// dictionary. Decoding materializes the column for the other kernels;
+// counting by code skips that for group-by counts. Both check every code
+// against `dict_len` and return like `tova_gather_f64`: `len`, or `-(i + 1)`
+// for the first position i whose code is out of range, with
+// TOVA_INDEX_ERR_OUT_OF_RANGE and the code in the last error.
+
+unsafe fn dict_decode<T: Copy>(
+    codes: *const u32,
+    validity: *const u8,
+    len: usize,
+    dict: *const T,
+    dict_len: usize,
+    null: T,
+    out: *mut T,
+) -> isize {
+    clear_last_error();
+    if len == 0 {
+        return 0;
+    }
+    let (mut codes_copy, mut dict_copy) = (Vec::new(), Vec::new());
+    let codes = unaliased(codes, len, out, len, &mut codes_copy);
+    let dict = unaliased(dict, dict_len, out, len, &mut dict_copy);
+    let validity = validity_bits(validity, len);
+    dict_result("dict decode", codes, dict_len, with_view_mut(out, len, |out| kernels::dict_decode(codes, validity, dict, null, out)))
+}
+
+fn dict_result(name: &str, codes: &[u32], dict_len: usize, result: Result<(), kernels::IndexError>) -> isize {
+    match result {
+        Ok(()) => codes.len() as isize,
+        Err(kernels::IndexError::OutOfRange { position }) => {
+            let code = codes[position];
+            let message = format!("{name}: code {code} at position {position} is out of range for {dict_len} dictionary values");
+            set_last_error(TOVA_INDEX_ERR_OUT_OF_RANGE, &message);
+            -(position as isize) - 1
+        }
+        Err(e) => unreachable!("{name} only fails on range: {e:?}"),
+    }
+}
+
+/// out[i] = dict[codes[i]]. `out` may alias `codes` or `dict`; the inputs
+/// are read as they were on entry.
+#[no_mangle]
+pub unsafe extern "C" fn tova_dict_decode_f64(codes: *const u32, len: usize, dict: *const f64, dict_len: usize, out: *mut f64) -> isize {
+    dict_decode(codes, std::ptr::null(), len, dict, dict_len, 0.0, out)
+}
+
+/// i64 variant of `tova_dict_decode_f64`.
+#[no_mangle]
+pub unsafe extern "C" fn tova_dict_decode_i64(codes: *const u32, len: usize, dict: *const i64, dict_len: usize, out: *mut i64) -> isize {
+    dict_decode(codes, std::ptr::null(), len, dict, dict_len, 0, out)
+}
+
+/// `tova_dict_decode_f64` writing NaN where the bit of `validity` (an Arrow
+/// bitmap of `len` bits; null: all valid) is clear. Codes under null slots
+/// are not checked.
+#[no_mangle]
+pub unsafe extern "C" fn tova_dict_decode_f64_masked(
+    codes: *const u32,
+    validity: *const u8,
+    len: usize,
+    dict: *const f64,
+    dict_len: usize,
+    out: *mut f64,
+) -> isize {
+    dict_decode(codes, validity, len, dict, dict_len, f64::NAN, out)
+}
+
+/// `tova_dict_decode_f64_masked` for i64, writing `null_value` for nulls.
+#[no_mangle]
+pub unsafe extern "C" fn tova_dict_decode_i64_masked(
+    codes: *const u32,
+    validity: *const u8,
+    len: usize,
+    dict: *const i64,
+    dict_len: usize,
+    null_value: i64,
+    out: *mut i64,
+) -> isize {
+    dict_decode(codes, validity, len, dict, dict_len, null_value, out)
+}
+
+/// out_counts[c] = how many of the `len` codes are c, for each of the
+/// `dict_len` dictionary values: the group-by count of the decoded column.
+/// On error out_counts holds the counts of codes[..i].
+#[no_mangle]
+pub unsafe extern "C" fn tova_dict_take_counts(codes: *const u32, len: usize, dict_len: usize, out_counts: *mut u64) -> isize {
+    clear_last_error();
+    let mut codes_copy = Vec::new();
+    let codes = unaliased(codes, len, out_counts, dict_len, &mut codes_copy);
+    dict_result("dict counts", codes, dict_len, with_view_mut(out_counts, dict_len, |counts| kernels::dict_counts(codes, counts)))
+}
+
+// ============================================================
+// Packed bitmaps
+// ============================================================
+
+// Bitmaps use Arrow's bit order: element i is bit i % 8 of byte i / 8 (LSB
+// first) and span ceil(len_bits / 8) bytes. Unused bits of the last byte are
+// ignored on input and written as zero. Outputs may alias inputs.
+
+/// out_mask[i] = 1 if lo <= values[i] <= hi, else 0 (NaN is never in range).
+#[no_mangle]
+pub unsafe extern "C" fn tova_mask_from_range_f64(values: *const f64, len: usize, lo: f64, hi: f64, out_mask: *mut u8) {
+    clear_last_error();
+    if len == 0 {
+        return;
+    }
+    let mut values_copy = Vec::new();
+    let values = unaliased(values, len, out_mask, len, &mut values_copy);
+    kernels::mask_from_range_f64(values, lo, hi, slice::from_raw_parts_mut(out_mask, len));
+}
+
+/// `tova_mask_from_range_f64` into a packed bitmap of `len` bits.
+#[no_mangle]
+pub unsafe extern "C" fn tova_mask_from_range_f64_packed(values: *const f64, len: usize, lo: f64, hi: f64, out_bitmap: *mut u8) {
+    clear_last_error();
+    if len == 0 {
+        return;
+    }
+    let bytes = kernels::bitmap_bytes(len);
+    let mut values_copy = Vec::new();
+    let values = unaliased(values, len, out_bitmap, bytes, &mut values_copy);
+    kernels::mask_from_range_f64_packed(values, lo, hi, slice::from_raw_parts_mut(out_bitmap, bytes));
+}
+
+unsafe fn bitmap_zip(a: *const u8, b: *const u8, out: *mut u8, len_bits: usize, op: fn(&[u8], &[u8], &mut [u8], usize)) {
+    clear_last_error();
+    if len_bits == 0 {
+        return;
+    }
+    let bytes = kernels::bitmap_bytes(len_bits);
+    let (mut a_copy, mut b_copy) = (Vec::new(), Vec::new());
+    let a = unaliased(a, bytes, out, bytes, &mut a_copy);
+    let b = unaliased(b, bytes, out, bytes, &mut b_copy);
+    op(a, b, slice::from_raw_parts_mut(out, bytes), len_bits);
+}
+
+/// out = a & b over `len_bits` bits.
+#[no_mangle]
+pub unsafe extern "C" fn tova_bitmap_and(a: *const u8, b: *const u8, out: *mut u8, len_bits: usize) {
+    bitmap_zip(a, b, out, len_bits, kernels::bitmap_and)
+}
+
+/// out = a | b over `len_bits` bits.
+#[no_mangle]
+pub unsafe extern "C" fn tova_bitmap_or(a: *const u8, b: *const u8, out: *mut u8, len_bits: usize) {
+    bitmap_zip(a, b, out, len_bits, kernels::bitmap_or)
+}
+
+/// out = !a over `len_bits` bits.
+#[no_mangle]
+pub unsafe extern "C" fn tova_bitmap_not(a: *const u8, out: *mut u8, len_bits: usize) {
+    bitmap_zip(a, a, out, len_bits, |a, _, out, len_bits| kernels::bitmap_not(a, out, len_bits))
+}
+
+/// Number of set bits among the first `len_bits`.
+#[no_mangle]
+pub unsafe extern "C" fn tova_bitmap_count(bitmap: *const u8, len_bits: usize) -> usize {
+    if len_bits == 0 {
+        return 0;
+    }
+    kernels::bitmap_count(slice::from_raw_parts(bitmap, kernels::bitmap_bytes(len_bits)), len_bits)
+}
+
+/// Compact `values` in place to the elements whose bit is set in `bitmap`
+/// (`len` bits), keeping their order. Returns how many were kept; the rest
+/// of `values` is left as it was.
+#[no_mangle]
+pub unsafe extern "C" fn tova_filter_bitmap_f64(values: *mut f64, bitmap: *const u8, len: usize) -> usize {
+    clear_last_error();
+    if len == 0 {
+        return 0;
+    }
+    let mut bitmap_copy = Vec::new();
+    let bitmap = unaliased(bitmap, kernels::bitmap_bytes(len), values, len, &mut bitmap_copy);
+    with_view_mut(values, len, |values| kernels::filter_bitmap_f64(values, bitmap))
+}
+
+// ============================================================
+// Scatter + permutation
+// ============================================================
+
+pub const TOVA_INDEX_ERR_OUT_OF_RANGE: i32 = -1;
+pub const TOVA_INDEX_ERR_DUPLICATE: i32 = -2;
+pub const TOVA_INDEX_ERR_TOO_LONG: i32 = -3;
+
+/// Duplicate target indices: later values overwrite earlier ones.
+pub const TOVA_SCATTER_LAST_WINS: u32 = 0;
+/// Duplicate target indices fail with TOVA_INDEX_ERR_DUPLICATE.
+pub const TOVA_SCATTER_REJECT_DUPLICATES: u32 = 1;
+
+/// Longest permutation: the kernel borrows each entry's high bit
+const PERM_MAX_LEN: usize = 1 << 31;
+
+unsafe fn scatter<T: Copy>(values: *const T, indices: *const u32, n: usize, out: *mut T, out_len: usize, policy: u32) -> i32 {
+    clear_last_error();
+    if n == 0 {
+        return 0;
+    }
+    if overlaps(out, out_len, values, n) || overlaps(out, out_len, indices, n) {
+        return fail(TOVA_ERR_OVERLAP, "output range overlaps an input");
+    }
+    let (mut values_copy, mut indices_copy) = (Vec::new(), Vec::new());
+    let values = view(values, n, &mut values_copy);
+    let indices = view(indices, n, &mut indices_copy);
+    let reject_duplicates = policy == TOVA_SCATTER_REJECT_DUPLICATES;
+    match with_view_mut(out, out_len, |out| kernels::scatter(values, indices, out, reject_duplicates)) {
+        Ok(()) => 0,
+        Err(kernels::IndexError::Duplicate) => fail(TOVA_INDEX_ERR_DUPLICATE, "scatter: duplicate target index"),
+        Err(_) => fail(TOVA_INDEX_ERR_OUT_OF_RANGE, "scatter: target index out of range"),
+    }
+}
+
+/// out[indices[i]] = values[i] for i in 0..n, the inverse of gather. Returns
+/// 0, or TOVA_INDEX_ERR_OUT_OF_RANGE / TOVA_INDEX_ERR_DUPLICATE (per `policy`,
+/// a TOVA_SCATTER_* constant) without writing anything. TOVA_ERR_OVERLAP if
+/// `out` aliases `values` or `indices`.
+#[no_mangle]
+pub unsafe extern "C" fn tova_scatter_f64(values: *const f64, indices: *const u32, n: usize, out: *mut f64, out_len: usize, policy: u32) -> i32 {
+    scatter(values, indices, n, out, out_len, policy)
+}
+
+/// i64 variant of `tova_scatter_f64`.
+#[no_mangle]
+pub unsafe extern "C" fn tova_scatter_i64(values: *const i64, indices: *const u32, n: usize, out: *mut i64, out_len: usize, policy: u32) -> i32 {
+    scatter(values, indices, n, out, out_len, policy)
+}
+
+unsafe fn apply_permutation<T: Copy>(values: *mut T, perm: *mut u32, len: usize) -> i32 {
+    clear_last_error();
+    if len == 0 {
+        return 0;
+    }
+    if len > PERM_MAX_LEN {
+        return fail(TOVA_INDEX_ERR_TOO_LONG, "permutation: longer than 2^31 entries");
+    }
+    if overlaps(values, len, perm, len) {
+        return fail(TOVA_ERR_OVERLAP, "output range overlaps an input");
+    }
+    match with_view_mut(values, len, |values| with_view_mut(perm, len, |perm| kernels::apply_permutation(values, perm))) {
+        Ok(()) => 0,
+        Err(kernels::IndexError::Duplicate) => fail(TOVA_INDEX_ERR_DUPLICATE, "permutation: not a permutation of 0..len"),
+        Err(kernels::IndexError::TooLong) => fail(TOVA_INDEX_ERR_TOO_LONG, "permutation: longer than 2^31 entries"),
+        Err(kernels::IndexError::OutOfRange { .. }) => fail(TOVA_INDEX_ERR_OUT_OF_RANGE, "permutation: not a permutation of 0..len"),
+    }
+}
+
+/// Reorder `values` in place so values'[i] = values[perm[i]] (the argsort
+/// convention), using cycle-following instead of a second value buffer. The
+/// high bit of each `perm` entry is borrowed as a visited flag during the
+/// walk and cleared again, so `perm` is unchanged on return. Returns 0,
+/// TOVA_INDEX_ERR_OUT_OF_RANGE / DUPLICATE if `perm` is not a permutation of
+/// 0..len (values untouched), TOVA_INDEX_ERR_TOO_LONG if len > 2^31, or
+/// TOVA_ERR_OVERLAP if `values` and `perm` share memory.
+#[no_mangle]
+pub unsafe extern "C" fn tova_apply_permutation_inplace_f64(values: *mut f64, perm: *mut u32, len: usize) -> i32 {
+    apply_permutation(values, perm, len)
+}
+
+/// i64 variant of `tova_apply_permutation_inplace_f64`.
+#[no_mangle]
+pub unsafe extern "C" fn tova_apply_permutation_inplace_i64(values: *mut i64, perm: *mut u32, len: usize) -> i32 {
+    apply_permutation(values, perm, len)
+}
+
+// ============================================================
+// Byte order + record layout
+// ============================================================
+
+pub const TOVA_LAYOUT_ERR_FIELD: i32 = -1;
+pub const TOVA_LAYOUT_ERR_WIDTH: i32 = -2;
+
+/// Reverse the byte order of each u64 in place (big-endian i64/f64 columns).
+#[no_mangle]
+pub unsafe extern "C" fn tova_bswap_u64(ptr: *mut u64, len: usize) {
+    if len == 0 {
+        return;
+    }
+    with_view_mut(ptr, len, |values| {
+        for v in values {
+            *v = v.swap_bytes();
+        }
+    });
+}
+
+/// Reverse the byte order of each u32 in place.
+#[no_mangle]
+pub unsafe extern "C" fn tova_bswap_u32(ptr: *mut u32, len: usize) {
+    if len == 0 {
+        return;
+    }
+    with_view_mut(ptr, len, |values| {
+        for v in values {
+            *v = v.swap_bytes();
+        }
+    });
+}
+
+/// Reverse the byte order of each u16 in place.
+#[no_mangle]
+pub unsafe extern "C" fn tova_bswap_u16(ptr: *mut u16, len: usize) {
+    if len == 0 {
+        return;
+    }
+    with_view_mut(ptr, len, |values| {
+        for v in values {
+            *v = v.swap_bytes();
+        }
+    });
+}
+
+fn field_fits(record_size: usize, field_offset: usize, field_size: usize) -> bool {
+    field_size > 0 && field_offset.checked_add(field_size).is_some_and(|end| end <= record_size)
+}
+
+/// Fixed-width copy loop; N is known at compile time so each step is one move.
+unsafe fn strided_copy<const N: usize>(src: *const u8, src_stride: usize, dst: *mut u8, dst_stride: usize, count: usize) {
+    for i in 0..count {
+        let v = (src.add(i * src_stride) as *const [u8; N]).read_unaligned();
+        (dst.add(i * dst_stride) as *mut [u8; N]).write_unaligned(v);
+    }
+}
+
+unsafe fn strided_copy_any(src: *const u8, src_stride: usize, dst: *mut u8, dst_stride: usize, count: usize, size: usize) {
+    match size {
+        1 => strided_copy::<1>(src, src_stride, dst, dst_stride, count),
+        2 => strided_copy::<2>(src, src_stride, dst, dst_stride, count),
+        4 => strided_copy::<4>(src, src_stride, dst, dst_stride, count),
+        8 => strided_copy::<8>(src, src_stride, dst, dst_stride, count),
+        _ => {
+            for i in 0..count {
+                std::ptr::copy_nonoverlapping(src.add(i * src_stride), dst.add(i * dst_stride), size);
+            }
+        }
+    }
+}
+
+/// Extract one fixed-width field from each of `count` records of
+/// `record_size` bytes into a contiguous `count * field_size` output (one
+/// column of an array-of-structs → struct-of-arrays transpose). Returns 0,
+/// TOVA_LAYOUT_ERR_FIELD if the field does not fit inside the record, or
+/// TOVA_ERR_OVERLAP if `src` and `out` share memory.
+#[no_mangle]
+pub unsafe extern "C" fn tova_deinterleave(
+    src: *const u8,
+    record_size: usize,
+    count: usize,
+    field_offset: usize,
+    field_size: usize,
+    out: *mut u8,
+) -> i32 {
+    clear_last_error();
+    if !field_fits(record_size, field_offset, field_size) {
+        return fail(TOVA_LAYOUT_ERR_FIELD, "layout: field does not fit inside the record");
+    }
+    if overlaps(src, record_size.saturating_mul(count), out, field_size.saturating_mul(count)) {
+        return fail(TOVA_ERR_OVERLAP, "output range overlaps an input");
+    }
+    if count > 0 {
+        strided_copy_any(src.add(field_offset), record_size, out, field_size, count, field_size);
+    }
+    0
+}
+
+/// Reverse of `tova_deinterleave`: write `count` contiguous field values
+/// from `src` into the field at `field_offset` of each record in `out`,
+/// leaving the other bytes of each record untouched.
+#[no_mangle]
+pub unsafe extern "C" fn tova_interleave(
+    src: *const u8,
+    record_size: usize,
+    count: usize,
+    field_offset: usize,
+    field_size: usize,
+    out: *mut u8,
+) -> i32 {
+    clear_last_error();
+    if !field_fits(record_size, field_offset, field_size) {
+        return fail(TOVA_LAYOUT_ERR_FIELD, "layout: field does not fit inside the record");
+    }
+    if overlaps(src, field_size.saturating_mul(count), out, record_size.saturating_mul(count)) {
+        return fail(TOVA_ERR_OVERLAP, "output range overlaps an input");
+    }
+    if count > 0 {
+        strided_copy_any(src, field_size, out.add(field_offset), record_size, count, field_size);
+    }
+    0
+}
+
+/// Reverse the bytes of each `size`-byte field at `ptr`, `stride` bytes apart.
+unsafe fn swap_fields(ptr: *mut u8, stride: usize, count: usize, size: usize) {
+    for i in 0..count {
+        slice::from_raw_parts_mut(ptr.add(i * stride), size).reverse();
+    }
+}
+
+/// Whether a field stored in the given byte order needs swapping on this host.
+fn foreign_order(big_endian: bool) -> bool {
+    big_endian != cfg!(target_endian = "big")
+}
+
+fn check_width(field_size: usize) -> Result<(), i32> {
+    match field_size {
+        1 | 2 | 4 | 8 => Ok(()),
+        _ => Err(fail(TOVA_LAYOUT_ERR_WIDTH, "layout: byte-ordered fields must be 1, 2, 4 or 8 bytes")),
+    }
+}
+
+unsafe fn deinterleave_ordered(
+    src: *const u8,
+    record_size: usize,
+    count: usize,
+    field_offset: usize,
+    field_size: usize,
+    out: *mut u8,
+    big_endian: bool,
+) -> i32 {
+    if let Err(code) = check_width(field_size) {
+        return code;
+    }
+    let status = tova_deinterleave(src, record_size, count, field_offset, field_size, out);
+    if status == 0 && foreign_order(big_endian) {
+        swap_fields(out, field_size, count, field_size);
+    }
+    status
+}
+
+unsafe fn interleave_ordered(
+    src: *const u8,
+    record_size: usize,
+    count: usize,
+    field_offset: usize,
+    field_size: usize,
+    out: *mut u8,
+    big_endian: bool,
+) -> i32 {
+    if let Err(code) = check_width(field_size) {
+        return code;
+    }
+    let status = tova_interleave(src, record_size, count, field_offset, field_size, out);
+    if status == 0 && foreign_order(big_endian) {
+        swap_fields(out.add(field_offset), record_size, count, field_size);
+    }
+    status
+}
+
+/// `tova_deinterleave` for a field stored little-endian in the records: the
+/// output column is in host byte order. TOVA_LAYOUT_ERR_WIDTH unless
+/// `field_size` is 1, 2, 4 or 8.
+#[no_mangle]
+pub unsafe extern "C" fn tova_deinterleave_le(
+    src: *const u8,
+    record_size: usize,
+    count: usize,
+    field_offset: usize,
+    field_size: usize,
+    out: *mut u8,
+) -> i32 {
+    deinterleave_ordered(src, record_size, count, field_offset, field_size, out, false)
+}
+
+/// `tova_deinterleave_le` for big-endian fields (network order, most binary
+/// file formats).
+#[no_mangle]
+pub unsafe extern "C" fn tova_deinterleave_be(
+    src: *const u8,
+    record_size: usize,
+    count: usize,
+    field_offset: usize,
+    field_size: usize,
+    out: *mut u8,
+) -> i32 {
+    deinterleave_ordered(src, record_size, count, field_offset, field_size, out, true)
+}
+
+/// `tova_interleave` from a host-order column into little-endian record fields.
+#[no_mangle]
+pub unsafe extern "C" fn tova_interleave_le(
+    src: *const u8,
+    record_size: usize,
+    count: usize,
+    field_offset: usize,
+    field_size: usize,
+    out: *mut u8,
+) -> i32 {
+    interleave_ordered(src, record_size, count, field_offset, field_size, out, false)
+}
+
+/// `tova_interleave` from a host-order column into big-endian record fields.
+#[no_mangle]
+pub unsafe extern "C" fn tova_interleave_be(
+    src: *const u8,
+    record_size: usize,
+    count: usize,
+    field_offset: usize,
+    field_size: usize,
+    out: *mut u8,
+) -> i32 {
+    interleave_ordered(src, record_size, count, field_offset, field_size, out, true)
+}
+
+// ============================================================
+// Library info + last error
+// ============================================================
+
+/// Bits of `tova_features`. PARALLEL: the multi-threaded reductions and the
+/// parallel sort path are available. SIMD is reserved for kernels with
+/// explicit vector paths; none has one, so it reads as clear.
+pub const TOVA_FEATURE_SIMD: u64 = 1 << 0;
+pub const TOVA_FEATURE_PARALLEL: u64 = 1 << 1;
+pub const TOVA_FEATURE_GZIP: u64 = 1 << 2;
+pub const TOVA_FEATURE_ZSTD: u64 = 1 << 3;
+
+/// Last-error code for invalid input whose return value has no i32 form
+/// (JSON offsets, decompression codes); the message carries the detail.
+pub const TOVA_ERR_INVALID_INPUT: i32 = -101;
+
+static VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "\0");
+
+/// Crate version as a static NUL-terminated semver string.
+#[no_mangle]
+pub unsafe extern "C" fn tova_version() -> *const c_char {
+    VERSION.as_ptr() as *const c_char
+}
+
+/// TOVA_FEATURE_* bits for the optional kernels compiled into this build.
+#[no_mangle]
+pub unsafe extern "C" fn tova_features() -> u64 {
+    let mut bits = TOVA_FEATURE_GZIP | TOVA_FEATURE_PARALLEL;
+    if cfg!(feature = "zstd") {
+        bits |= TOVA_FEATURE_ZSTD;
+    }
+    bits
+}
+
+/// Version of the FFI surface: bumped whenever an export is added, removed
+/// or changes signature, so a loader can refuse a library built from a
+/// different source than it expects.
+pub const TOVA_ABI_VERSION: u32 = 23;
+
+#[no_mangle]
+pub unsafe extern "C" fn tova_abi_version() -> u32 {
+    TOVA_ABI_VERSION
+}
+
+// `tova_symbols` lists every export of this build. tests/abi.rs checks the
+// list against the exports the cdylib actually has.
+macro_rules! exported_symbols {
+    ($($name:literal),* $(,)?) => {
+        #[cfg(feature = "zstd")]
+        static SYMBOLS: &str = concat!($($name, "\0",)* "tova_zstd_decompress\0", "\0");
+        #[cfg(not(feature = "zstd"))]
+        static SYMBOLS: &str = concat!($($name, "\0",)* "\0");
+    };
+}
+
+exported_symbols!(
+    "tova_sort_f64", "tova_sort_f64_unstable", "tova_sort_i64", "tova_sort_caps",
+    "tova_sort_set_tuning", "tova_sort_get_tuning", "tova_sort_f64_begin", "tova_sort_f64_step",
+    "tova_sort_f64_finish", "tova_sort_f64_abort", "tova_sort_f64_external", "tova_unique_sorted_i64",
+    "tova_unique_sorted_f64", "tova_unique_counts_sorted_i64", "tova_unique_counts_sorted_f64",
+    "tova_sum_f64", "tova_min_f64", "tova_max_f64", "tova_sum_f64_parallel",
+    "tova_min_f64_parallel", "tova_max_f64_parallel", "tova_minmax_f64_parallel",
+    "tova_arrow_sum_f64", "tova_arrow_min_f64", "tova_arrow_max_f64", "tova_arrow_count_f64",
+    "tova_npy_parse_header", "tova_npy_read_f64", "tova_levenshtein", "tova_levenshtein_batch",
+    "tova_hamming_u64", "tova_find_all", "tova_count_occurrences", "tova_find_first",
+    "tova_parse_json_numbers", "tova_gzip_decompress", "tova_decompress_create",
+    "tova_decompress_feed", "tova_decompress_read", "tova_decompress_finish",
+    "tova_decompress_free", "tova_nonzero_u8", "tova_gather_f64", "tova_gather_i64",
+    "tova_scatter_f64", "tova_scatter_i64", "tova_apply_permutation_inplace_f64",
+    "tova_apply_permutation_inplace_i64", "tova_bswap_u64", "tova_bswap_u32", "tova_bswap_u16",
+    "tova_deinterleave", "tova_interleave", "tova_deinterleave_le", "tova_deinterleave_be",
+    "tova_interleave_le", "tova_interleave_be", "tova_version", "tova_features", "tova_abi_version",
+    "tova_selfcheck", "tova_symbols", "tova_last_error",
+    "tova_last_error_message", "tova_rank_f64", "tova_percentile_of_f64", "tova_quantile_edges_f64", "tova_pack_i32_pairs",
+    "tova_unpack_i32_pairs", "tova_weighted_sum_f64", "tova_weighted_mean_f64",
+    "tova_weighted_sum_masked_f64", "tova_weighted_mean_masked_f64", "tova_sum_squares_f64",
+    "tova_merge_groups_f64", "tova_hash_strings", "tova_hash_columns", "tova_mask_from_range_f64",
+    "tova_mask_from_range_f64_packed", "tova_bitmap_and", "tova_bitmap_or", "tova_bitmap_not",
+    "tova_bitmap_count", "tova_filter_bitmap_f64", "tova_profile_f64", "tova_profile_i64",
+    "tova_asof_join_i64", "tova_oset_create", "tova_oset_insert_f64", "tova_oset_remove_f64",
+    "tova_oset_quantile", "tova_oset_rank", "tova_oset_len", "tova_oset_free",
+    "tova_hashjoin_build_i64", "tova_hashjoin_probe_i64", "tova_hashjoin_free",
+    "tova_weighted_sample_f64", "tova_stratified_sample_i64",
+    "tova_minhash_u64", "tova_minhash_similarity", "tova_simhash_u64", "tova_hamming_distance_u64",
+    "tova_sum_scaled_i64", "tova_mean_scaled_i64", "tova_convert_f64_scaled_i64",
+    "tova_gather_columns", "tova_scatter_columns", "tova_sparse_dot", "tova_sparse_dense_dot",
+    "tova_sparse_topk_dot", "tova_filter_above_quantile_f64", "tova_filter_below_quantile_f64",
+    "tova_filter_between_quantiles_f64", "tova_min_i64", "tova_max_i64", "tova_minmax_i64",
+    "tova_argmin_i64", "tova_argmax_i64", "tova_mean_i64", "tova_min_i64_masked", "tova_max_i64_masked",
+    "tova_minmax_i64_masked", "tova_argmin_i64_masked", "tova_argmax_i64_masked",
+    "tova_mean_i64_masked", "tova_min_i64_segmented", "tova_max_i64_segmented",
+    "tova_argmin_i64_segmented", "tova_argmax_i64_segmented", "tova_mean_i64_segmented",
+    "tova_group_agg_create", "tova_group_agg_update", "tova_group_agg_reserve", "tova_group_agg_merge",
+    "tova_group_agg_len", "tova_group_agg_finalize", "tova_group_agg_free",
+    "tova_sort_f64_desc", "tova_sort_i64_desc",
+    "tova_dict_decode_f64", "tova_dict_decode_i64", "tova_dict_decode_f64_masked", "tova_dict_decode_i64_masked",
+    "tova_dict_take_counts",
+    "tova_time_bucket_agg",
+    "tova_argsort_f64",
+);
+
+/// Names of every entry point in this build, each NUL-terminated, with an
+/// empty name (a second NUL) ending the list. Static; do not free.
+#[no_mangle]
+pub unsafe extern "C" fn tova_symbols() -> *const c_char {
+    SYMBOLS.as_ptr() as *const c_char
+}
+
+/// A check failed; the report says which.
+pub const TOVA_SELFCHECK_ERR_FAILED: i32 = -1;
+/// `cap` is too small for the report; nothing was written.
+pub const TOVA_SELFCHECK_ERR_BUFFER: i32 = -2;
+
+/// Run a few kernels on fixed inputs (sort 8 values, sum, an XXH64 test
+/// vector) and write a JSON report into `out` as a NUL-terminated string:
+/// `{"abi":1,"version":"..","features":N,"checks":{"sort":true,..},"ok":true}`.
+/// Meant for loaders to call once at startup, so a library built for the
+/// wrong target or from other sources fails there rather than mid-request.
+/// Returns 0 when every check passed.
+#[no_mangle]
+pub unsafe extern "C" fn tova_selfcheck(out: *mut u8, cap: usize) -> i32 {
+    clear_last_error();
+    let checks = selfcheck();
+    let ok = checks.iter().all(|&(_, passed)| passed);
+    let checks: Vec<String> = checks.iter().map(|(name, passed)| format!("\"{}\":{}", name, passed)).collect();
+    let report = format!(
+        "{{\"abi\":{},\"version\":\"{}\",\"features\":{},\"checks\":{{{}}},\"ok\":{}}}",
+        TOVA_ABI_VERSION,
+        env!("CARGO_PKG_VERSION"),
+        tova_features(),
+        checks.join(","),
+        ok
+    );
+    if out.is_null() || cap <= report.len() {
+        return fail(TOVA_SELFCHECK_ERR_BUFFER, &format!("selfcheck: report needs {} bytes", report.len() + 1));
+    }
+    std::ptr::copy_nonoverlapping(report.as_ptr(), out, report.len());
+    *out.add(report.len()) = 0;
+    if ok {
+        0
+    } else {
+        fail(TOVA_SELFCHECK_ERR_FAILED, "selfcheck: a kernel returned a wrong result")
+    }
+}
+
+fn selfcheck() -> [(&'static str, bool); 3] {
+    let mut floats = [5.0, -0.5, 3.25, -2.0, 8.0, 1.0, 0.0, -7.75];
+    kernels::sort_f64(&mut floats);
+    let sort = floats == [-7.75, -2.0, -0.5, 0.0, 1.0, 3.25, 5.0, 8.0];
+    let sum = kernels::sum_f64(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0]) == 36.0;
+    let hash = xxh64::xxh64(b"abc", 0) == 0x44BC_2CF5_AD77_0999;
+    [("sort", sort), ("sum", sum), ("hash", hash)]
+}
+
+// Kernels that validate their input clear this on entry and fill it when they
+// reject something (or recover from misuse, as with overlapping ranges). It is
+// per thread, so Workers calling into one library never see each other's errors.
+struct LastError {
+    code: i32,
+    message: String,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<LastError> = const { RefCell::new(LastError { code: 0, message: String::new() }) };
+}
+
+fn clear_last_error() {
+    LAST_ERROR.with(|e| {
+        let mut e = e.borrow_mut();
+        e.code = 0;
+        e.message.clear();
+    });
+}
+
+fn set_last_error(code: i32, message: &str) {
+    LAST_ERROR.with(|e| {
+        let mut e = e.borrow_mut();
+        e.code = code;
+        e.message.clear();
+        e.message.push_str(message);
+    });
+}
+
+fn fail(code: i32, message: &str) -> i32 {
+    set_last_error(code, message);
+    code
+}
+
+/// Code recorded by the most recent checked call on this thread, 0 if it
+/// completed without detecting a problem.
+#[no_mangle]
+pub unsafe extern "C" fn tova_last_error() -> i32 {
+    LAST_ERROR.with(|e| e.borrow().code)
+}
+
+/// Copy the last error's message into `buf` as a NUL-terminated string,
+/// truncated to `cap - 1` bytes. Returns the full message length, so a
+/// result >= `cap` means the copy was cut short. Empty when there is no error.
+#[no_mangle]
+pub unsafe extern "C" fn tova_last_error_message(buf: *mut u8, cap: usize) -> usize {
+    LAST_ERROR.with(|e| {
+        let e = e.borrow();
+        let message = e.message.as_bytes();
+        if !buf.is_null() && cap > 0 {
+            let n = message.len().min(cap - 1);
+            std::ptr::copy_nonoverlapping(message.as_ptr(), buf, n);
+            *buf.add(n) = 0;
+        }
+        message.len()
+    })
+}
+
+// ============================================================
+// Aliasing checks
+// ============================================================
+
+// Exports taking several pointers check them against each other, since two
+// views of one ArrayBuffer are easy to create by accident. Status-returning
+// functions (i32) fail with TOVA_ERR_OVERLAP before writing anything; count-
+// returning and void functions copy the aliased input aside first, so the
+// result matches a call on separate buffers. Both record the code for
+// `tova_last_error`.
+
+pub const TOVA_ERR_OVERLAP: i32 = -100;
+
+/// Whether `a[..a_len]` and `b[..b_len]` share any byte. Empty ranges never do.
+fn overlaps<A, B>(a: *const A, a_len: usize, b: *const B, b_len: usize) -> bool {
+    let a_bytes = a_len.saturating_mul(std::mem::size_of::<A>());
+    let b_bytes = b_len.saturating_mul(std::mem::size_of::<B>());
+    if a_bytes == 0 || b_bytes == 0 {
+        return false;
+    }
+    let (a, b) = (a as usize, b as usize);
+    a < b.saturating_add(b_bytes) && b < a.saturating_add(a_bytes)
+}
+
+/// View of an input that stays valid while `out[..out_len]` is written: the
+/// input itself, or a copy in `scratch` when the two overlap (or, as with
+/// `view`, when the input is misaligned).
+unsafe fn unaliased<T: Copy, O>(ptr: *const T, len: usize, out: *const O, out_len: usize, scratch: &mut Vec<T>) -> &[T] {
+    if len == 0 {
+        return &[];
+    }
+    if overlaps(ptr, len, out, out_len) {
+        set_last_error(TOVA_ERR_OVERLAP, "input overlaps the output range; copied before writing");
+        scratch.extend((0..len).map(|i| ptr.add(i).read_unaligned()));
+        scratch
+    } else {
+        view(ptr, len, scratch)
+    }
+}
+
+// ============================================================
+// Alignment
+// ============================================================
+
+// Bun hands over views at any byte offset into an ArrayBuffer (a column
+// starting 4 bytes into a packed file), and a slice built on a pointer that
+// is misaligned for its element type is undefined behaviour. Every typed
+// export borrows its buffers through `view` / `with_view_mut` instead: aligned
+// pointers become slices as before, misaligned ones are copied into an
+// aligned buffer with unaligned reads (and written back for in-place and
+// output buffers), so kernels only ever see aligned slices. Byte buffers need
+// none of this.
+
+/// `len` Ts at `ptr` as a slice, or an aligned copy in `scratch` when `ptr`
+/// is misaligned for T. Empty for len 0, whatever the pointer.
+unsafe fn view<T: Copy>(ptr: *const T, len: usize, scratch: &mut Vec<T>) -> &[T] {
+    if len == 0 {
+        return &[];
+    }
+    if ptr.is_aligned() {
+        return slice::from_raw_parts(ptr, len);
+    }
+    scratch.extend((0..len).map(|i| ptr.add(i).read_unaligned()));
+    scratch
+}
+
+/// Run `f` on the `len` Ts at `ptr` as a mutable slice. A misaligned buffer
+/// is copied into an aligned one for `f` and copied back afterwards.
+unsafe fn with_view_mut<T: Copy, R>(ptr: *mut T, len: usize, f: impl FnOnce(&mut [T]) -> R) -> R {
+    if len == 0 {
+        return f(&mut []);
+    }
+    if ptr.is_aligned() {
+        return f(slice::from_raw_parts_mut(ptr, len));
+    }
+    let mut aligned: Vec<T> = (0..len).map(|i| ptr.add(i).read_unaligned()).collect();
+    let result = f(&mut aligned);
+    // The byte count of a slice that exists, so it cannot overflow
+    std::ptr::copy_nonoverlapping(aligned.as_ptr() as *const u8, ptr as *mut u8, std::mem::size_of_val(aligned.as_slice()));
+    result
+}
+
+// ============================================================
+// Rank + percentile
+// ============================================================
+
+/// Number of elements of ascending `sorted` that are <= `value` (0 for NaN).
+/// NaNs in `sorted` must sit at the end, as `tova_sort_f64` leaves them.
+#[no_mangle]
+pub unsafe extern "C" fn tova_rank_f64(sorted: *const f64, len: usize, value: f64) -> usize {
+    if len == 0 {
+        return 0;
+    }
+    kernels::rank_f64(view(sorted, len, &mut Vec::new()), value)
+}
+
+/// Empirical percentile in [0, 100] of each query against ascending `sorted`:
+/// 100 * (count below + half the count equal) / len, so a value tied with the
+/// whole reference scores 50. NaN queries (and an empty reference) give NaN.
+/// Ascending runs of queries advance a cursor instead of searching from scratch.
+#[no_mangle]
+pub unsafe extern "C" fn tova_percentile_of_f64(sorted: *const f64, len: usize, queries: *const f64, qlen: usize, out: *mut f64) {
+    clear_last_error();
+    if qlen == 0 {
+        return;
+    }
+    let (mut sorted_copy, mut queries_copy) = (Vec::new(), Vec::new());
+    let sorted = unaliased(sorted, len, out, qlen, &mut sorted_copy);
+    let queries = unaliased(queries, qlen, out, qlen, &mut queries_copy);
+    with_view_mut(out, qlen, |out| kernels::percentile_of_f64(sorted, queries, out));
+}
+
+/// Compute quantile edges and filter thresholds from all the values even past
+/// QUANTILE_SAMPLE_MIN.
+pub const TOVA_QUANTILE_EXACT: u32 = 1;
+
+/// Write the k - 1 interior edges splitting the non-NaN values into k
+/// equal-frequency buckets to `out_edges`: edge i is the value of rank
+/// floor(i * n / k), so bucket i holds [edge i-1, edge i). Exact edges use
+/// partition-based selection, not a full sort; inputs over QUANTILE_SAMPLE_MIN
+/// values use a sorted random sample of QUANTILE_SAMPLE_LEN unless `flags`
+/// has TOVA_QUANTILE_EXACT. Heavy ties can leave fewer distinct edges than
+/// asked for: those come first and the remaining slots hold the maximum.
+/// Returns the distinct edge count (0, with NaN edges, when all values are
+/// NaN). `out_edges` may alias `values`; the values are read as they were on entry.
+#[no_mangle]
+pub unsafe extern "C" fn tova_quantile_edges_f64(values: *const f64, len: usize, k: usize, flags: u32, out_edges: *mut f64) -> usize {
+    clear_last_error();
+    if k < 2 {
+        return 0;
+    }
+    let mut values_copy = Vec::new();
+    let values = unaliased(values, len, out_edges, k - 1, &mut values_copy);
+    with_view_mut(out_edges, k - 1, |out| kernels::quantile_edges_f64(values, out, flags & TOVA_QUANTILE_EXACT != 0))
+}
+
+/// Shared body of the quantile filters; `out_thresholds` (nullable) takes
+/// `n_thresholds` values: lo, then hi.
+unsafe fn filter_quantile(
+    values: *mut f64,
+    len: usize,
+    filter: kernels::QuantileFilter,
+    flags: u32,
+    companion: *mut i64,
+    out_thresholds: *mut f64,
+    n_thresholds: usize,
+) -> usize {
+    clear_last_error();
+    let in_range = |q: f64| (0.0..=1.0).contains(&q);
+    let valid = match filter {
+        kernels::QuantileFilter::Above(q) | kernels::QuantileFilter::Below(q) => in_range(q),
+        kernels::QuantileFilter::Between(lo, hi) => in_range(lo) && in_range(hi) && lo <= hi,
+    };
+    if !valid {
+        set_last_error(TOVA_ERR_INVALID_INPUT, "quantile filter: q must be in [0, 1], and q_lo <= q_hi");
+        return usize::MAX;
+    }
+    let companion_len = if companion.is_null() { 0 } else { len };
+    let thresholds_len = if out_thresholds.is_null() { 0 } else { n_thresholds };
+    if overlaps(values, len, companion, companion_len)
+        || overlaps(out_thresholds, thresholds_len, values, len)
+        || overlaps(out_thresholds, thresholds_len, companion, companion_len)
+    {
+        set_last_error(TOVA_ERR_OVERLAP, "quantile filter: values, companion and thresholds overlap");
+        return usize::MAX;
+    }
+    let exact = flags & TOVA_QUANTILE_EXACT != 0;
+    let (kept, (lo, hi)) = with_view_mut(values, len, |values| {
+        if companion.is_null() {
+            kernels::filter_quantile_f64(values, None, filter, exact)
+        } else {
+            with_view_mut(companion, len, |companion| kernels::filter_quantile_f64(values, Some(companion), filter, exact))
+        }
+    });
+    if !out_thresholds.is_null() {
+        for (i, threshold) in [lo, hi].into_iter().take(n_thresholds).enumerate() {
+            out_thresholds.add(i).write_unaligned(threshold);
+        }
+    }
+    kept
+}
+
+/// Compact `values` in place to those greater than their own q-quantile,
+/// keeping their order, and the nullable `companion` ids (`len` of them) in
+/// lockstep. The quantile is the value of rank floor(q * n) among the n
+/// non-NaN values (clamped to the largest), found by selection, or from a
+/// sample past QUANTILE_SAMPLE_MIN values unless `flags` has
+/// TOVA_QUANTILE_EXACT; it goes to the nullable `out_threshold` (NaN when
+/// every value is NaN). NaNs are dropped. Returns the new length, or SIZE_MAX
+/// with nothing changed and the last error TOVA_ERR_INVALID_INPUT for q
+/// outside [0, 1], or TOVA_ERR_OVERLAP when the buffers overlap.
+#[no_mangle]
+pub unsafe extern "C" fn tova_filter_above_quantile_f64(
+    values: *mut f64,
+    len: usize,
+    q: f64,
+    flags: u32,
+    companion: *mut i64,
+    out_threshold: *mut f64,
+) -> usize {
+    filter_quantile(values, len, kernels::QuantileFilter::Above(q), flags, companion, out_threshold, 1)
+}
+
+/// `tova_filter_above_quantile_f64`, keeping the values less than the
+/// q-quantile instead.
+#[no_mangle]
+pub unsafe extern "C" fn tova_filter_below_quantile_f64(
+    values: *mut f64,
+    len: usize,
+    q: f64,
+    flags: u32,
+    companion: *mut i64,
+    out_threshold: *mut f64,
+) -> usize {
+    filter_quantile(values, len, kernels::QuantileFilter::Below(q), flags, companion, out_threshold, 1)
+}
+
+/// `tova_filter_above_quantile_f64`, keeping the values from the
+/// q_lo-quantile up to but not including the q_hi-quantile, which go to
+/// `out_thresholds[0]` and `[1]`. Needs q_lo <= q_hi.
+#[no_mangle]
+pub unsafe extern "C" fn tova_filter_between_quantiles_f64(
+    values: *mut f64,
+    len: usize,
+    q_lo: f64,
+    q_hi: f64,
+    flags: u32,
+    companion: *mut i64,
+    out_thresholds: *mut f64,
+) -> usize {
+    filter_quantile(values, len, kernels::QuantileFilter::Between(q_lo, q_hi), flags, companion, out_thresholds, 2)
+}
+
+// ============================================================
+// Order-statistics multiset
+// ============================================================
+
+pub const TOVA_OSET_ERR_HANDLE: i32 = -1;
+pub const TOVA_OSET_ERR_NAN: i32 = -2;
+pub const TOVA_OSET_ERR_ABSENT: i32 = -3;
+
+/// An empty multiset of f64 for rank and quantile queries over a changing
+/// window of values; every operation is O(log n). Free with `tova_oset_free`.
+#[no_mangle]
+pub unsafe extern "C" fn tova_oset_create() -> *mut OrderSet {
+    Box::into_raw(Box::new(OrderSet::new()))
+}
+
+/// Add one copy of `value` (-0.0 counts as 0.0). Returns 0,
+/// TOVA_OSET_ERR_NAN for a NaN, which is not added, or TOVA_OSET_ERR_HANDLE.
+#[no_mangle]
+pub unsafe extern "C" fn tova_oset_insert_f64(handle: *mut OrderSet, value: f64) -> i32 {
+    clear_last_error();
+    let Some(set) = handle.as_mut() else {
+        return fail(TOVA_OSET_ERR_HANDLE, "oset insert: null handle");
+    };
+    if !set.insert(value) {
+        return fail(TOVA_OSET_ERR_NAN, "oset insert: NaN");
+    }
+    0
+}
+
+/// Remove one copy of `value`. Returns 0, TOVA_OSET_ERR_ABSENT if the set
+/// holds none, or TOVA_OSET_ERR_HANDLE.
+#[no_mangle]
+pub unsafe extern "C" fn tova_oset_remove_f64(handle: *mut OrderSet, value: f64) -> i32 {
+    clear_last_error();
+    let Some(set) = handle.as_mut() else {
+        return fail(TOVA_OSET_ERR_HANDLE, "oset remove: null handle");
+    };
+    if !set.remove(value) {
+        return fail(TOVA_OSET_ERR_ABSENT, &format!("oset remove: {} is not in the set", value));
+    }
+    0
+}
+
+/// The value of rank floor(q * (len - 1)), the convention of
+/// `tova_profile_f64`'s quartiles: q = 0.99 gives the p99. NaN for an empty
+/// set, a `q` outside [0, 1] or a null handle.
+#[no_mangle]
+pub unsafe extern "C" fn tova_oset_quantile(handle: *const OrderSet, q: f64) -> f64 {
+    handle.as_ref().and_then(|set| set.quantile(q)).unwrap_or(f64::NAN)
+}
+
+/// Number of values <= `value`, as `tova_rank_f64` counts them (0 for NaN
+/// or a null handle).
+#[no_mangle]
+pub unsafe extern "C" fn tova_oset_rank(handle: *const OrderSet, value: f64) -> usize {
+    handle.as_ref().map_or(0, |set| set.rank(value))
+}
+
+/// Values in the set, copies included; 0 for a null handle.
+#[no_mangle]
+pub unsafe extern "C" fn tova_oset_len(handle: *const OrderSet) -> usize {
+    handle.as_ref().map_or(0, OrderSet::len)
+}
+
+/// Release a set from `tova_oset_create`.
+#[no_mangle]
+pub unsafe extern "C" fn tova_oset_free(handle: *mut OrderSet) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+// ============================================================
+// Column profile
+// ============================================================
+
+/// Slots `tova_profile_f64` / `tova_profile_i64` write, at these indices.
+/// Statistics cover the counted values: valid and not NaN. Missing
+/// statistics (min of no values, stddev of one, quartiles without
+/// TOVA_PROFILE_QUARTILES, unused top slots) are NaN.
+pub const TOVA_PROFILE_LEN: usize = 22;
+/// Counted values
+pub const TOVA_PROFILE_COUNT: usize = 0;
+/// Values whose validity bit is clear
+pub const TOVA_PROFILE_NULLS: usize = 1;
+/// Valid NaNs
+pub const TOVA_PROFILE_NANS: usize = 2;
+/// HyperLogLog estimate of the distinct values, within about 2%
+pub const TOVA_PROFILE_DISTINCT: usize = 3;
+pub const TOVA_PROFILE_MIN: usize = 4;
+pub const TOVA_PROFILE_MAX: usize = 5;
+pub const TOVA_PROFILE_MEAN: usize = 6;
+/// Sample standard deviation (n - 1 denominator)
+pub const TOVA_PROFILE_STDDEV: usize = 7;
+/// 25th, 50th and 75th percentiles: the value of rank floor(q * (n - 1))
+pub const TOVA_PROFILE_P25: usize = 8;
+pub const TOVA_PROFILE_P50: usize = 9;
+pub const TOVA_PROFILE_P75: usize = 10;
+/// How many of the top slots below are filled (at most 5)
+pub const TOVA_PROFILE_TOP_COUNT: usize = 11;
+/// The most frequent values, most frequent first and ties smallest first.
+/// Values under 1/64 of the count may be missed in favour of rarer ones.
+pub const TOVA_PROFILE_TOP_VALUES: usize = 12;
+/// Their counts: exact with TOVA_PROFILE_QUARTILES, else upper bounds
+pub const TOVA_PROFILE_TOP_COUNTS: usize = 17;
+
+/// Make a second pass for the quartiles and exact top counts.
+pub const TOVA_PROFILE_QUARTILES: u32 = 1;
+
+pub const TOVA_PROFILE_ERR_NULL: i32 = -1;
+
+/// Profile a column in one pass (two with TOVA_PROFILE_QUARTILES), writing
+/// TOVA_PROFILE_LEN f64s to `out` at the TOVA_PROFILE_* indices. `validity`
+/// is an Arrow bitmap of `len` bits, or null when every value is valid.
+/// Quartiles of more than QUANTILE_SAMPLE_MIN values come from an evenly
+/// strided sample. Returns 0, or TOVA_PROFILE_ERR_NULL for a null `out`
+/// (or null `values` with len > 0).
+#[no_mangle]
+pub unsafe extern "C" fn tova_profile_f64(values: *const f64, validity: *const u8, len: usize, flags: u32, out: *mut f64) -> i32 {
+    clear_last_error();
+    if out.is_null() || (values.is_null() && len > 0) {
+        return fail(TOVA_PROFILE_ERR_NULL, "profile: null pointer");
+    }
+    let mut copy = Vec::new();
+    let profile = kernels::profile_f64(view(values, len, &mut copy), validity_bits(validity, len), flags & TOVA_PROFILE_QUARTILES != 0);
+    write_profile(&profile, out);
+    0
+}
+
+/// `tova_profile_f64` for i64 columns. Statistics are computed in f64:
+/// values beyond 2^53 in magnitude are reported rounded, and NANS is 0.
+#[no_mangle]
+pub unsafe extern "C" fn tova_profile_i64(values: *const i64, validity: *const u8, len: usize, flags: u32, out: *mut f64) -> i32 {
+    clear_last_error();
+    if out.is_null() || (values.is_null() && len > 0) {
+        return fail(TOVA_PROFILE_ERR_NULL, "profile: null pointer");
+    }
+    let mut copy = Vec::new();
+    let profile = kernels::profile_i64(view(values, len, &mut copy), validity_bits(validity, len), flags & TOVA_PROFILE_QUARTILES != 0);
+    write_profile(&profile, out);
+    0
+}
+
+unsafe fn validity_bits<'a>(validity: *const u8, len: usize) -> Option<&'a [u8]> {
+    (!validity.is_null() && len > 0).then(|| slice::from_raw_parts(validity, kernels::bitmap_bytes(len)))
+}
+
+/// The whole profile is computed before this runs, so `out` may overlap
+/// the inputs.
+unsafe fn write_profile(profile: &kernels::Profile, out: *mut f64) {
+    with_view_mut(out, TOVA_PROFILE_LEN, |out| {
+        out.fill(f64::NAN);
+        out[TOVA_PROFILE_COUNT] = profile.count as f64;
+        out[TOVA_PROFILE_NULLS] = profile.nulls as f64;
+        out[TOVA_PROFILE_NANS] = profile.nans as f64;
+        out[TOVA_PROFILE_DISTINCT] = profile.distinct;
+        out[TOVA_PROFILE_MIN] = profile.min;
+        out[TOVA_PROFILE_MAX] = profile.max;
+        out[TOVA_PROFILE_MEAN] = profile.mean;
+        out[TOVA_PROFILE_STDDEV] = profile.stddev;
+        out[TOVA_PROFILE_P25..=TOVA_PROFILE_P75].copy_from_slice(&profile.quartiles);
+        out[TOVA_PROFILE_TOP_COUNT] = profile.top.len() as f64;
+        for (i, &(value, count)) in profile.top.iter().enumerate() {
+            out[TOVA_PROFILE_TOP_VALUES + i] = value;
+            out[TOVA_PROFILE_TOP_COUNTS + i] = count as f64;
+        }
+    });
+}
+
+// ============================================================
+// Pair packing
+// ============================================================
+
+/// out[i] = pack(hi[i], lo[i]): (hi << 32) | lo as raw u32 halves, or with
+/// `order_preserving` the form whose signed i64 order equals lexicographic
+/// (hi, lo) order (see `kernels::pack_i32_pair`). Unpack with the same flag.
+#[no_mangle]
+pub unsafe extern "C" fn tova_pack_i32_pairs(hi: *const i32, lo: *const i32, len: usize, out: *mut i64, order_preserving: bool) {
+    clear_last_error();
+    if len == 0 {
+        return;
+    }
+    let (mut hi_copy, mut lo_copy) = (Vec::new(), Vec::new());
+    let hi = unaliased(hi, len, out, len, &mut hi_copy);
+    let lo = unaliased(lo, len, out, len, &mut lo_copy);
+    with_view_mut(out, len, |out| kernels::pack_i32_pairs(hi, lo, out, order_preserving));
+}
+
+/// Inverse of `tova_pack_i32_pairs` for keys packed with the same flag.
+#[no_mangle]
+pub unsafe extern "C" fn tova_unpack_i32_pairs(src: *const i64, len: usize, out_hi: *mut i32, out_lo: *mut i32, order_preserving: bool) {
+    clear_last_error();
+    if len == 0 {
+        return;
+    }
+    // Either output may be the one sharing memory with `src`
+    let nearest = if overlaps(src, len, out_hi, len) { out_hi } else { out_lo };
+    let mut src_copy = Vec::new();
+    let src = unaliased(src, len, nearest, len, &mut src_copy);
+    // Raw writes: the two outputs may themselves overlap
+    for (i, &v) in src.iter().enumerate() {
+        let (hi, lo) = kernels::unpack_i32_pair(v, order_preserving);
+        out_hi.add(i).write_unaligned(hi);
+        out_lo.add(i).write_unaligned(lo);
+    }
+}
+
+// ============================================================
+// Weighted aggregates
+// ============================================================
+
+/// Borrow the value and weight columns of a weighted export.
+unsafe fn weighted_args(values: *const f64, weights: *const f64, len: usize, scratch: &mut (Vec<f64>, Vec<f64>)) -> (&[f64], &[f64]) {
+    (view(values, len, &mut scratch.0), view(weights, len, &mut scratch.1))
+}
+
+/// Σ values[i] * weights[i], compensated for both product and sum rounding.
+#[no_mangle]
+pub unsafe extern "C" fn tova_weighted_sum_f64(values: *const f64, weights: *const f64, len: usize) -> f64 {
+    let mut scratch = Default::default();
+    let (values, weights) = weighted_args(values, weights, len, &mut scratch);
+    kernels::weighted_sum_f64(values, weights)
+}
+
+/// Σ w·x / Σ w with both sums compensated; NaN when the total weight is 0.
+#[no_mangle]
+pub unsafe extern "C" fn tova_weighted_mean_f64(values: *const f64, weights: *const f64, len: usize) -> f64 {
+    let mut scratch = Default::default();
+    let (values, weights) = weighted_args(values, weights, len, &mut scratch);
+    kernels::weighted_mean_f64(values, weights)
+}
+
+/// `tova_weighted_sum_f64` skipping pairs where either side is NaN.
+#[no_mangle]
+pub unsafe extern "C" fn tova_weighted_sum_masked_f64(values: *const f64, weights: *const f64, len: usize) -> f64 {
+    let mut scratch = Default::default();
+    let (values, weights) = weighted_args(values, weights, len, &mut scratch);
+    kernels::weighted_sum_masked_f64(values, weights)
+}
+
+/// `tova_weighted_mean_f64` skipping pairs where either side is NaN.
+#[no_mangle]
+pub unsafe extern "C" fn tova_weighted_mean_masked_f64(values: *const f64, weights: *const f64, len: usize) -> f64 {
+    let mut scratch = Default::default();
+    let (values, weights) = weighted_args(values, weights, len, &mut scratch);
+    kernels::weighted_mean_masked_f64(values, weights)
+}
+
+/// Σ x², compensated the same way (RMS = sqrt(sum_squares / len)).
+#[no_mangle]
+pub unsafe extern "C" fn tova_sum_squares_f64(ptr: *const f64, len: usize) -> f64 {
+    if len == 0 {
+        return 0.0;
+    }
+    kernels::sum_squares_f64(view(ptr, len, &mut Vec::new()))
+}
+
+// ============================================================
+// Grouped partials
+// ============================================================
+
+/// Borrow one side of `tova_merge_groups_f64`, copied into `scratch` when any
+/// of its columns overlaps any output column.
+unsafe fn group_partials<'a>(
+    (keys, sums, counts, len): (*const i64, *const f64, *const u64, usize),
+    outs: [(usize, usize); 3],
+    scratch: &'a mut (Vec<i64>, Vec<f64>, Vec<u64>),
+) -> kernels::GroupPartials<'a> {
+    let bytes = len.saturating_mul(8);
+    let columns = [(keys as usize, bytes), (sums as usize, bytes), (counts as usize, bytes)];
+    let aliased = columns.iter().any(|&(ptr, bytes)| {
+        outs.iter().any(|&(out, out_bytes)| overlaps(ptr as *const u8, bytes, out as *const u8, out_bytes))
+    });
+    if aliased {
+        set_last_error(TOVA_ERR_OVERLAP, "input overlaps the output range; copied before writing");
+        scratch.0.extend((0..len).map(|i| keys.add(i).read_unaligned()));
+        scratch.1.extend((0..len).map(|i| sums.add(i).read_unaligned()));
+        scratch.2.extend((0..len).map(|i| counts.add(i).read_unaligned()));
+        return kernels::GroupPartials { keys: &scratch.0, sums: &scratch.1, counts: &scratch.2 };
+    }
+    kernels::GroupPartials {
+        keys: view(keys, len, &mut scratch.0),
+        sums: view(sums, len, &mut scratch.1),
+        counts: view(counts, len, &mut scratch.2),
+    }
+}
+
+/// Combine two sets of group-by partials, each with strictly ascending keys
+/// and a sum and row count per key (what each worker of a parallel group-by
+/// produces). Matching keys have their sums and counts added; the rest are
+/// copied, and the merged keys stay ascending, so results can be merged
+/// again in any grouping. Returns the merged group count, or its negation
+/// when it exceeds `out_cap` (the first `out_cap` groups are still written).
+/// The outputs may alias the inputs; the inputs are read as they were on entry.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn tova_merge_groups_f64(
+    keys_a: *const i64,
+    vals_a: *const f64,
+    counts_a: *const u64,
+    len_a: usize,
+    keys_b: *const i64,
+    vals_b: *const f64,
+    counts_b: *const u64,
+    len_b: usize,
+    out_keys: *mut i64,
+    out_vals: *mut f64,
+    out_counts: *mut u64,
+    out_cap: usize,
+) -> isize {
+    clear_last_error();
+    let out_bytes = out_cap.saturating_mul(8);
+    let outs = [(out_keys as usize, out_bytes), (out_vals as usize, out_bytes), (out_counts as usize, out_bytes)];
+    let (mut scratch_a, mut scratch_b) = Default::default();
+    let a = group_partials((keys_a, vals_a, counts_a, len_a), outs, &mut scratch_a);
+    let b = group_partials((keys_b, vals_b, counts_b, len_b), outs, &mut scratch_b);
+    let count = with_view_mut(out_keys, out_cap, |keys| {
+        with_view_mut(out_vals, out_cap, |sums| {
+            with_view_mut(out_counts, out_cap, |counts| kernels::merge_groups_f64(a, b, kernels::GroupPartialsMut { keys, sums, counts }))
+        })
+    });
+    if count > out_cap { -(count as isize) } else { count as isize }
+}
+
+// ============================================================
+// Streaming group-by
+// ============================================================
+
+// A group-by whose state outlives one call, for input read a row group at a
+// time: update a handle with each chunk, merge the handles of parallel
+// workers, then finalize once. Keys are i64 and values f64.
+
+/// Compensated sum per group (NaN once any of its values is)
+pub const TOVA_GROUP_AGG_SUM: i32 = 1;
+/// Smallest non-NaN value per group (NaN when there is none)
+pub const TOVA_GROUP_AGG_MIN: i32 = 2;
+/// Largest non-NaN value per group (NaN when there is none)
+pub const TOVA_GROUP_AGG_MAX: i32 = 3;
+/// Rows per group; `values` may be null
+pub const TOVA_GROUP_AGG_COUNT: i32 = 4;
+/// Sum / rows per group
+pub const TOVA_GROUP_AGG_MEAN: i32 = 5;
+
+pub const TOVA_GROUP_AGG_ERR_HANDLE: i32 = -1;
+/// The handle was already finalized
+pub const TOVA_GROUP_AGG_ERR_FINALIZED: i32 = -2;
+/// An unknown op, or merging handles with different ops
+pub const TOVA_GROUP_AGG_ERR_OP: i32 = -3;
+/// What `tova_group_agg_finalize` returns on error; `tova_last_error` says
+/// which TOVA_GROUP_AGG_ERR_* it was
+pub const TOVA_GROUP_AGG_ERR_FINALIZE: isize = isize::MIN;
+
+/// An empty group-by computing `op` (a TOVA_GROUP_AGG_* op) per key. Null,
+/// with TOVA_GROUP_AGG_ERR_OP recorded, for an unknown op. Free with
+/// `tova_group_agg_free`.
+#[no_mangle]
+pub unsafe extern "C" fn tova_group_agg_create(op: i32) -> *mut GroupAgg {
+    clear_last_error();
+    let Some(op) = group_op(op) else {
+        set_last_error(TOVA_GROUP_AGG_ERR_OP, &format!("group agg: unknown op {}", op));
+        return std::ptr::null_mut();
+    };
+    Box::into_raw(Box::new(GroupAgg::new(op)))
+}
+
+fn group_op(op: i32) -> Option<GroupOp> {
+    match op {
+        TOVA_GROUP_AGG_SUM => Some(GroupOp::Sum),
+        TOVA_GROUP_AGG_MIN => Some(GroupOp::Min),
+        TOVA_GROUP_AGG_MAX => Some(GroupOp::Max),
+        TOVA_GROUP_AGG_COUNT => Some(GroupOp::Count),
+        TOVA_GROUP_AGG_MEAN => Some(GroupOp::Mean),
+        _ => None,
+    }
+}
+
+/// The handle behind `handle`, or the error for a null or finalized one.
+unsafe fn group_agg<'a>(handle: *mut GroupAgg, what: &str) -> Result<&'a mut GroupAgg, i32> {
+    match handle.as_mut() {
+        None => Err(fail(TOVA_GROUP_AGG_ERR_HANDLE, &format!("group agg {}: null handle", what))),
+        Some(agg) if agg.is_finished() => Err(fail(TOVA_GROUP_AGG_ERR_FINALIZED, &format!("group agg {}: already finalized", what))),
+        Some(agg) => Ok(agg),
+    }
+}
+
+/// Fold `len` rows, `keys[i]` with `values[i]`, into the groups. The table
+/// doubles as the group count does, so a handle fed thousands of chunks
+/// rehashes only as often as that; `tova_group_agg_reserve` avoids even
+/// that. Returns 0 or a TOVA_GROUP_AGG_ERR_* code.
+#[no_mangle]
+pub unsafe extern "C" fn tova_group_agg_update(handle: *mut GroupAgg, keys: *const i64, values: *const f64, len: usize) -> i32 {
+    clear_last_error();
+    let agg = match group_agg(handle, "update") {
+        Ok(agg) => agg,
+        Err(code) => return code,
+    };
+    let (mut keys_copy, mut values_copy) = (Vec::new(), Vec::new());
+    let values = if agg.op() == GroupOp::Count { &[][..] } else { view(values, len, &mut values_copy) };
+    agg.update(view(keys, len, &mut keys_copy), values);
+    0
+}
+
+/// Make room for `groups` groups in all, so that updates up to that many
+/// never rehash. Returns 0, a TOVA_GROUP_AGG_ERR_* code, or
+/// TOVA_ERR_INVALID_INPUT when no table could hold that many.
+#[no_mangle]
+pub unsafe extern "C" fn tova_group_agg_reserve(handle: *mut GroupAgg, groups: usize) -> i32 {
+    clear_last_error();
+    let agg = match group_agg(handle, "reserve") {
+        Ok(agg) => agg,
+        Err(code) => return code,
+    };
+    if !agg.reserve(groups) {
+        return fail(TOVA_ERR_INVALID_INPUT, "group agg reserve: too many groups");
+    }
+    0
+}
+
+/// Fold every group of `src` into `dst`, as if `dst` had been given the
+/// rows `src` was; `src` is left as it was. Both must compute the same op
+/// (else TOVA_GROUP_AGG_ERR_OP) and be distinct, unfinalized handles.
+/// Returns 0 or a TOVA_GROUP_AGG_ERR_* code.
+#[no_mangle]
+pub unsafe extern "C" fn tova_group_agg_merge(dst: *mut GroupAgg, src: *const GroupAgg) -> i32 {
+    clear_last_error();
+    if std::ptr::eq(dst, src) {
+        return fail(TOVA_GROUP_AGG_ERR_HANDLE, "group agg merge: a handle into itself");
+    }
+    let into = match group_agg(dst, "merge") {
+        Ok(agg) => agg,
+        Err(code) => return code,
+    };
+    let from = match group_agg(src as *mut GroupAgg, "merge") {
+        Ok(agg) => agg,
+        Err(code) => return code,
+    };
+    if into.op() != from.op() {
+        return fail(TOVA_GROUP_AGG_ERR_OP, "group agg merge: handles compute different ops");
+    }
+    into.merge(from);
+    0
+}
+
+/// Groups so far; 0 for a null or finalized handle.
+#[no_mangle]
+pub unsafe extern "C" fn tova_group_agg_len(handle: *const GroupAgg) -> usize {
+    handle.as_ref().map_or(0, GroupAgg::len)
+}
+
+/// Write each group's key, result and row count (`out_counts` may be null)
+/// in ascending key order and release the state; the handle then refuses
+/// everything with TOVA_GROUP_AGG_ERR_FINALIZED but `tova_group_agg_free`.
+/// Returns the group count; when that exceeds `out_cap` it returns the
+/// negated count, writes the first `out_cap` groups and keeps the state, so
+/// the call can be repeated with room for them all. TOVA_GROUP_AGG_ERR_FINALIZE
+/// for a null or finalized handle.
+#[no_mangle]
+pub unsafe extern "C" fn tova_group_agg_finalize(
+    handle: *mut GroupAgg,
+    out_keys: *mut i64,
+    out_vals: *mut f64,
+    out_counts: *mut u64,
+    out_cap: usize,
+) -> isize {
+    clear_last_error();
+    let agg = match group_agg(handle, "finalize") {
+        Ok(agg) => agg,
+        Err(_) => return TOVA_GROUP_AGG_ERR_FINALIZE,
+    };
+    let groups = agg.results();
+    for (i, &(key, value, count)) in groups.iter().take(out_cap).enumerate() {
+        out_keys.add(i).write_unaligned(key);
+        out_vals.add(i).write_unaligned(value);
+        if !out_counts.is_null() {
+            out_counts.add(i).write_unaligned(count);
+        }
+    }
+    if groups.len() > out_cap {
+        return -(groups.len() as isize);
+    }
+    agg.finish();
+    groups.len() as isize
+}
+
+/// Release a handle from `tova_group_agg_create`, finalized or not.
+#[no_mangle]
+pub unsafe extern "C" fn tova_group_agg_free(handle: *mut GroupAgg) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+// ============================================================
+// Time buckets
+// ============================================================
+
+// Group-by on a timestamp truncated to fixed-width buckets ("sum per 5
+// minutes"), where the key is arithmetic on the timestamp. Any epoch unit
+// works as long as `ts`, `bucket` and `origin` share it. Rows in time order
+// skip the hash table; the results are the same either way.
+
+/// `flags` bit: also emit the empty buckets between the first and last one,
+/// with count 0 and the op's value for no rows (0 for SUM and COUNT, NaN for
+/// the rest)
+pub const TOVA_TIME_BUCKET_FILL_GAPS: u32 = 1;
+/// What `tova_time_bucket_agg` returns on error; `tova_last_error` says
+/// which error it was
+pub const TOVA_TIME_BUCKET_ERR: isize = isize::MIN;
+
+/// Aggregate `values` (null for TOVA_GROUP_AGG_COUNT) with `op`, a
+/// TOVA_GROUP_AGG_* op, per bucket of `ts`: rows fall in the `bucket`-wide
+/// bucket starting at origin + floor((ts - origin) / bucket) * bucket, so
+/// timestamps before `origin`, negative ones included, round down. Writes
+/// each bucket's start, result and row count (`out_counts` may be null) in
+/// ascending order and returns the bucket count; when that exceeds `out_cap`
+/// it returns the negated count and writes the first `out_cap` buckets.
+/// Everything is read before anything is written, so the outputs may
+/// overlap the inputs. TOVA_TIME_BUCKET_ERR for an unknown op
+/// (TOVA_GROUP_AGG_ERR_OP), or for a `bucket` that is not positive or a
+/// bucket start or gap-filled count out of range (TOVA_ERR_INVALID_INPUT).
+#[no_mangle]
+pub unsafe extern "C" fn tova_time_bucket_agg(
+    ts: *const i64,
+    values: *const f64,
+    len: usize,
+    bucket: i64,
+    origin: i64,
+    op: i32,
+    flags: u32,
+    out_bucket_starts: *mut i64,
+    out_values: *mut f64,
+    out_counts: *mut u64,
+    out_cap: usize,
+) -> isize {
+    clear_last_error();
+    let Some(op) = group_op(op) else {
+        set_last_error(TOVA_GROUP_AGG_ERR_OP, &format!("time bucket: unknown op {}", op));
+        return TOVA_TIME_BUCKET_ERR;
+    };
+    if bucket <= 0 {
+        set_last_error(TOVA_ERR_INVALID_INPUT, &format!("time bucket: width {} is not positive", bucket));
+        return TOVA_TIME_BUCKET_ERR;
+    }
+    let (mut ts_copy, mut values_copy) = (Vec::new(), Vec::new());
+    let ts = view(ts, len, &mut ts_copy);
+    let values = if op == GroupOp::Count { &[][..] } else { view(values, len, &mut values_copy) };
+    let mut starts = Vec::with_capacity(len);
+    for (i, &t) in ts.iter().enumerate() {
+        let Some(start) = kernels::time_bucket(t, bucket, origin) else {
+            set_last_error(TOVA_ERR_INVALID_INPUT, &format!("time bucket: the bucket of timestamp {} at {} is out of range", t, i));
+            return TOVA_TIME_BUCKET_ERR;
+        };
+        starts.push(start);
+    }
+    let groups = if ts.is_sorted() {
+        group_agg::sorted_results(op, &starts, values)
+    } else {
+        let mut agg = GroupAgg::new(op);
+        agg.update(&starts, values);
+        agg.results()
+    };
+
+    let write = |i: usize, (start, value, count): (i64, f64, u64)| {
+        if i < out_cap {
+            out_bucket_starts.add(i).write_unaligned(start);
+            out_values.add(i).write_unaligned(value);
+            if !out_counts.is_null() {
+                out_counts.add(i).write_unaligned(count);
+            }
+        }
+    };
+    let total = match (groups.first(), groups.last()) {
+        (Some(&(first, ..)), Some(&(last, ..))) if flags & TOVA_TIME_BUCKET_FILL_GAPS != 0 => {
+            // Every bucket start from first to last, each a multiple of
+            // `bucket` past the first
+            let total = (last as i128 - first as i128) / bucket as i128 + 1;
+            if total > isize::MAX as i128 {
+                set_last_error(TOVA_ERR_INVALID_INPUT, "time bucket: too many buckets between the first and last to fill");
+                return TOVA_TIME_BUCKET_ERR;
+            }
+            let empty = group_agg::result(op, group_agg::empty_acc(op), 0.0, 0);
+            let mut filled = groups.iter().peekable();
+            for i in 0..(total as usize).min(out_cap) {
+                let start = (first as i128 + i as i128 * bucket as i128) as i64;
+                match filled.next_if(|g| g.0 == start) {
+                    Some(&group) => write(i, group),
+                    None => write(i, (start, empty, 0)),
+                }
+            }
+            total as usize
+        }
+        _ => {
+            groups.iter().enumerate().for_each(|(i, &group)| write(i, group));
+            groups.len()
+        }
+    };
+    if total > out_cap { -(total as isize) } else { total as isize }
+}
+
+// ============================================================
+// String + row hashing
+// ============================================================
+
+pub const TOVA_HASH_ERR_OFFSETS: i32 = -1;
+pub const TOVA_HASH_ERR_KIND: i32 = -2;
+
+pub const TOVA_COLUMN_I64: u32 = 1;
+pub const TOVA_COLUMN_F64: u32 = 2;
+pub const TOVA_COLUMN_STRING: u32 = 3;
+/// A packed validity bitmap, one bit per row (`tova_gather_columns` only).
+pub const TOVA_COLUMN_BITMAP: u32 = 4;
+
+/// One key column for `tova_hash_columns`. `data` points at `count` i64/f64
+/// values, or at the string bytes with `offsets` holding count + 1 entries.
+/// For a bitmap column `data` holds ceil(count / 8) bytes.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ColumnDesc {
+    pub kind: u32,
+    pub data: *const u8,
+    pub offsets: *const u64,
+}
+
+/// Offsets for `count` strings, checked to be non-decreasing.
+unsafe fn string_offsets(offsets: *const u64, count: usize, scratch: &mut Vec<u64>) -> Option<&[u64]> {
+    let offsets = view(offsets, count + 1, scratch);
+    let monotonic = offsets.windows(2).all(|w| w[0] <= w[1]);
+    (monotonic && usize::try_from(offsets[count]).is_ok()).then_some(offsets)
+}
+
+/// XXH64 of each string in an offsets+bytes column. Returns 0, or
+/// TOVA_HASH_ERR_OFFSETS (nothing written) if the offsets decrease.
+#[no_mangle]
+pub unsafe extern "C" fn tova_hash_strings(bytes: *const u8, offsets: *const u64, count: usize, seed: u64, out: *mut u64) -> i32 {
+    clear_last_error();
+    if count == 0 {
+        return 0;
+    }
+    let offsets_ptr = offsets;
+    let mut offsets_copy = Vec::new();
+    let offsets = match string_offsets(offsets, count, &mut offsets_copy) {
+        Some(o) => o,
+        None => return fail(TOVA_HASH_ERR_OFFSETS, "hash: string offsets decrease"),
+    };
+    let bytes_len = offsets[count] as usize;
+    if overlaps(out, count, offsets_ptr, count + 1) || overlaps(out, count, bytes, bytes_len) {
+        return fail(TOVA_ERR_OVERLAP, "output range overlaps an input");
+    }
+    let bytes = if bytes_len == 0 { &[][..] } else { slice::from_raw_parts(bytes, bytes_len) };
+    match with_view_mut(out, count, |out| kernels::hash_strings(bytes, offsets, seed, out)) {
+        Ok(()) => 0,
+        Err(_) => fail(TOVA_HASH_ERR_OFFSETS, "hash: string offsets decrease"),
+    }
+}
+
+/// One hash per row over `ncols` key columns of `count` rows each. Columns are
+/// folded in order with an XXH64 round, so swapping columns changes the hash,
+/// then avalanched together with the column count. Returns 0,
+/// TOVA_HASH_ERR_KIND / TOVA_HASH_ERR_OFFSETS for a bad descriptor, or
+/// TOVA_ERR_OVERLAP; nothing is written on error.
+#[no_mangle]
+pub unsafe extern "C" fn tova_hash_columns(columns: *const ColumnDesc, ncols: usize, count: usize, seed: u64, out: *mut u64) -> i32 {
+    clear_last_error();
+    if count == 0 {
+        return 0;
+    }
+    let mut columns_copy = Vec::new();
+    let columns = view(columns, ncols, &mut columns_copy);
+    // Check every descriptor before building views, so nothing is borrowed
+    // from memory the output overlaps
+    let mut scratch_offsets: Vec<Vec<u64>> = (0..ncols).map(|_| Vec::new()).collect();
+    let mut col_offsets = Vec::with_capacity(ncols);
+    for (col, scratch) in columns.iter().zip(&mut scratch_offsets) {
+        let (offsets, span) = match col.kind {
+            TOVA_COLUMN_I64 | TOVA_COLUMN_F64 => (None, count.saturating_mul(8)),
+            TOVA_COLUMN_STRING => match string_offsets(col.offsets, count, scratch) {
+                Some(o) => {
+                    if overlaps(out, count, col.offsets, count + 1) {
+                        return fail(TOVA_ERR_OVERLAP, "output range overlaps an input");
+                    }
+                    (Some(o), o[count] as usize)
+                }
+                None => return fail(TOVA_HASH_ERR_OFFSETS, "hash: string offsets decrease"),
+            },
+            _ => return fail(TOVA_HASH_ERR_KIND, "hash: unknown column kind"),
+        };
+        if overlaps(out, count, col.data, span) {
+            return fail(TOVA_ERR_OVERLAP, "output range overlaps an input");
+        }
+        col_offsets.push(offsets);
+    }
+    let mut scratch_i64: Vec<Vec<i64>> = (0..ncols).map(|_| Vec::new()).collect();
+    let mut scratch_f64: Vec<Vec<f64>> = (0..ncols).map(|_| Vec::new()).collect();
+    let mut views = Vec::with_capacity(ncols);
+    for (((col, offsets), si), sf) in columns.iter().zip(&col_offsets).zip(&mut scratch_i64).zip(&mut scratch_f64) {
+        views.push(match (col.kind, offsets) {
+            (TOVA_COLUMN_I64, _) => kernels::Column::I64(view(col.data as *const i64, count, si)),
+            (TOVA_COLUMN_F64, _) => kernels::Column::F64(view(col.data as *const f64, count, sf)),
+            (_, Some(o)) => {
+                let len = o[count] as usize;
+                let bytes = if len == 0 { &[][..] } else { slice::from_raw_parts(col.data, len) };
+                kernels::Column::Str { bytes, offsets: o }
+            }
+            (_, None) => unreachable!("string columns always carry offsets"),
+        });
+    }
+    match with_view_mut(out, count, |out| kernels::hash_columns(&views, seed, out)) {
+        Ok(()) => 0,
+        Err(_) => fail(TOVA_HASH_ERR_OFFSETS, "hash: string offsets decrease"),
+    }
+}
+
+// ============================================================
+// Table gather + scatter
+// ============================================================
+
+pub const TOVA_INDEX_ERR_KIND: i32 = -4;
+
+/// An output column for `tova_gather_columns`: `data` has room for `nrows`
+/// values (or bits) of `kind`, which must match its input column's.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ColumnDescMut {
+    pub kind: u32,
+    pub data: *mut u8,
+}
+
+/// Bytes a column of `kind` spans over `rows` rows, None for kinds that
+/// cannot be moved.
+fn movable_span(kind: u32, rows: usize) -> Option<usize> {
+    match kind {
+        TOVA_COLUMN_I64 | TOVA_COLUMN_F64 => Some(rows.saturating_mul(8)),
+        TOVA_COLUMN_BITMAP => Some(kernels::bitmap_bytes(rows)),
+        _ => None,
+    }
+}
+
+/// `len` Ts at `ptr` to be written, or a zeroed aligned buffer in `scratch`
+/// when `ptr` is misaligned, for the caller to copy back.
+unsafe fn output<T: Copy + Default>(ptr: *mut T, len: usize, scratch: &mut Vec<T>) -> &mut [T] {
+    if ptr.is_aligned() {
+        return slice::from_raw_parts_mut(ptr, len);
+    }
+    scratch.resize(len, T::default());
+    scratch
+}
+
+unsafe fn move_columns(
+    perm: *const u32,
+    nrows: usize,
+    columns: *const ColumnDesc,
+    ncols: usize,
+    out_columns: *const ColumnDescMut,
+    scatter: bool,
+) -> i32 {
+    clear_last_error();
+    if nrows == 0 || ncols == 0 {
+        return 0;
+    }
+    if nrows > u32::MAX as usize {
+        return fail(TOVA_INDEX_ERR_TOO_LONG, "columns: more rows than u32 indices can name");
+    }
+    let (mut columns_copy, mut outs_copy, mut perm_copy) = (Vec::new(), Vec::new(), Vec::new());
+    let columns = view(columns, ncols, &mut columns_copy);
+    let outs = view(out_columns, ncols, &mut outs_copy);
+    // Check every descriptor and range before anything is borrowed or written
+    for (c, (col, out)) in columns.iter().zip(outs).enumerate() {
+        let span = match movable_span(col.kind, nrows) {
+            Some(span) if out.kind == col.kind => span,
+            _ => return fail(TOVA_INDEX_ERR_KIND, "columns: unsupported or mismatched column kind"),
+        };
+        let inputs_overlap = overlaps(out.data, span, perm, nrows)
+            || overlaps(out.data, span, columns.as_ptr(), ncols)
+            || overlaps(out.data, span, outs.as_ptr(), ncols)
+            || columns.iter().any(|other| overlaps(out.data, span, other.data, movable_span(other.kind, nrows).unwrap_or(0)))
+            || outs[..c].iter().any(|other| overlaps(out.data, span, other.data, movable_span(other.kind, nrows).unwrap_or(0)));
+        if inputs_overlap {
+            return fail(TOVA_ERR_OVERLAP, "output range overlaps an input or another output");
+        }
+    }
+    let perm = view(perm, nrows, &mut perm_copy);
+    let mut scratch_i64: Vec<Vec<i64>> = (0..ncols).map(|_| Vec::new()).collect();
+    let mut scratch_f64: Vec<Vec<f64>> = (0..ncols).map(|_| Vec::new()).collect();
+    // Misaligned outputs are moved into aligned buffers and copied back
+    let mut aligned_i64: Vec<Vec<i64>> = (0..ncols).map(|_| Vec::new()).collect();
+    let mut aligned_f64: Vec<Vec<f64>> = (0..ncols).map(|_| Vec::new()).collect();
+    let mut moves = Vec::with_capacity(ncols);
+    let scratch = scratch_i64.iter_mut().zip(&mut scratch_f64).zip(aligned_i64.iter_mut().zip(&mut aligned_f64));
+    for ((col, out), ((si, sf), (ai, af))) in columns.iter().zip(outs).zip(scratch) {
+        moves.push(match col.kind {
+            TOVA_COLUMN_I64 => kernels::ColumnMove::I64(view(col.data as *const i64, nrows, si), output(out.data as *mut i64, nrows, ai)),
+            TOVA_COLUMN_F64 => kernels::ColumnMove::F64(view(col.data as *const f64, nrows, sf), output(out.data as *mut f64, nrows, af)),
+            _ => {
+                let bytes = kernels::bitmap_bytes(nrows);
+                kernels::ColumnMove::Bitmap(slice::from_raw_parts(col.data, bytes), slice::from_raw_parts_mut(out.data, bytes))
+            }
+        });
+    }
+    // Columns move on every thread once the table is as long as a sort that
+    // would go parallel
+    let threads = if nrows >= kernels::sort_tuning().parallel_threshold { 0 } else { 1 };
+    let moved = if scatter { kernels::scatter_columns(perm, &mut moves, threads) } else { kernels::gather_columns(perm, &mut moves, threads) };
+    drop(moves);
+    match moved {
+        Ok(()) => {}
+        Err(kernels::IndexError::Duplicate) => return fail(TOVA_INDEX_ERR_DUPLICATE, "scatter columns: duplicate target index"),
+        Err(_) => return fail(TOVA_INDEX_ERR_OUT_OF_RANGE, "columns: row index out of range"),
+    }
+    for (out, (ai, af)) in outs.iter().zip(aligned_i64.iter().zip(&aligned_f64)) {
+        if !ai.is_empty() {
+            std::ptr::copy_nonoverlapping(ai.as_ptr() as *const u8, out.data, nrows * 8);
+        }
+        if !af.is_empty() {
+            std::ptr::copy_nonoverlapping(af.as_ptr() as *const u8, out.data, nrows * 8);
+        }
+    }
+    0
+}
+
+/// Materialize a table in permuted order: out_columns[c][i] =
+/// columns[c][perm[i]] for every i64, f64 and bitmap column, each `nrows`
+/// long (offsets are ignored). Every index is checked before any column is
+/// written. Columns move on all threads when `nrows` reaches the sort's
+/// parallel threshold (`tova_sort_set_tuning`). Returns 0,
+/// TOVA_INDEX_ERR_OUT_OF_RANGE, TOVA_INDEX_ERR_KIND (an unsupported kind, or
+/// an output kind that differs from its input's), or TOVA_ERR_OVERLAP when an
+/// output overlaps any input or another output; nothing is written on error.
+#[no_mangle]
+pub unsafe extern "C" fn tova_gather_columns(
+    perm: *const u32,
+    nrows: usize,
+    columns: *const ColumnDesc,
+    ncols: usize,
+    out_columns: *const ColumnDescMut,
+) -> i32 {
+    move_columns(perm, nrows, columns, ncols, out_columns, false)
+}
+
+/// The inverse of `tova_gather_columns`: out_columns[c][perm[i]] =
+/// columns[c][i]. `perm` must be a permutation of 0..nrows, else
+/// TOVA_INDEX_ERR_OUT_OF_RANGE / TOVA_INDEX_ERR_DUPLICATE with nothing
+/// written; otherwise as `tova_gather_columns`.
+#[no_mangle]
+pub unsafe extern "C" fn tova_scatter_columns(
+    perm: *const u32,
+    nrows: usize,
+    columns: *const ColumnDesc,
+    ncols: usize,
+    out_columns: *const ColumnDescMut,
+) -> i32 {
+    move_columns(perm, nrows, columns, ncols, out_columns, true)
+}
+
+// ============================================================
+// As-of join
+// ============================================================
+
+/// Match each left timestamp to the last right timestamp at or before it.
+pub const TOVA_ASOF_BACKWARD: u32 = 0;
+/// ... to the first right timestamp at or after it.
+pub const TOVA_ASOF_FORWARD: u32 = 1;
+/// ... to the closer of those two, the backward one on a tie.
+pub const TOVA_ASOF_NEAREST: u32 = 2;
+
+pub const TOVA_ASOF_ERR_LEFT_UNSORTED: i32 = -1;
+pub const TOVA_ASOF_ERR_RIGHT_UNSORTED: i32 = -2;
+pub const TOVA_ASOF_ERR_TOLERANCE: i32 = -3;
+pub const TOVA_ASOF_ERR_DIRECTION: i32 = -4;
+
+/// As-of join of two ascending timestamp columns: out_right_idx[i] is the
+/// index of the right row matching left_ts[i] in `direction`
+/// (TOVA_ASOF_*), or -1 when there is none within `tolerance` (0 for exact
+/// matches only, i64::MAX for no limit). Of equal right timestamps, backward
+/// matches take the last and forward matches the first. Returns 0, or
+/// TOVA_ASOF_ERR_* with nothing written: both inputs are checked sorted
+/// first, and the tolerance must not be negative. `out_right_idx` may alias
+/// `left_ts`.
+#[no_mangle]
+pub unsafe extern "C" fn tova_asof_join_i64(
+    left_ts: *const i64,
+    llen: usize,
+    right_ts: *const i64,
+    rlen: usize,
+    tolerance: i64,
+    direction: u32,
+    out_right_idx: *mut i64,
+) -> i32 {
+    clear_last_error();
+    let Ok(tolerance) = u64::try_from(tolerance) else {
+        return fail(TOVA_ASOF_ERR_TOLERANCE, "asof join: tolerance is negative");
+    };
+    let direction = match direction {
+        TOVA_ASOF_BACKWARD => kernels::AsofDirection::Backward,
+        TOVA_ASOF_FORWARD => kernels::AsofDirection::Forward,
+        TOVA_ASOF_NEAREST => kernels::AsofDirection::Nearest,
+        _ => return fail(TOVA_ASOF_ERR_DIRECTION, "asof join: unknown direction"),
+    };
+    if llen == 0 {
+        return 0;
+    }
+    let (mut left_copy, mut right_copy) = (Vec::new(), Vec::new());
+    let left = unaliased(left_ts, llen, out_right_idx, llen, &mut left_copy);
+    let right = unaliased(right_ts, rlen, out_right_idx, llen, &mut right_copy);
+    match with_view_mut(out_right_idx, llen, |out| kernels::asof_join_i64(left, right, tolerance, direction, out)) {
+        Ok(()) => 0,
+        Err(kernels::AsofError::LeftUnsorted) => fail(TOVA_ASOF_ERR_LEFT_UNSORTED, "asof join: left timestamps are not ascending"),
+        Err(kernels::AsofError::RightUnsorted) => fail(TOVA_ASOF_ERR_RIGHT_UNSORTED, "asof join: right timestamps are not ascending"),
+    }
+}
+
+// ============================================================
+// Hash join
+// ============================================================
+
+pub const TOVA_HASHJOIN_ERR_HANDLE: isize = isize::MIN;
+
+/// Index the build side of an inner equi-join, `keys`, for
+/// `tova_hashjoin_probe_i64`; duplicate keys keep all their rows. Sorting
+/// neither side, it suits a small unsorted build side against a large probe
+/// side. Null past 2^32 - 1 rows. Free with `tova_hashjoin_free`.
+#[no_mangle]
+pub unsafe extern "C" fn tova_hashjoin_build_i64(keys: *const i64, len: usize) -> *mut HashJoin {
+    let mut keys_copy = Vec::new();
+    match HashJoin::build(view(keys, len, &mut keys_copy)) {
+        Some(join) => Box::into_raw(Box::new(join)),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Every (probe row, build row) pair whose keys are equal, probe rows in
+/// order and each one's build rows ascending, written to `out_probe_idx`
+/// and `out_build_idx` (plen <= 2^32). Returns the count, or the negated
+/// count when it exceeds `out_cap` (the first `out_cap` pairs are still
+/// written), or TOVA_HASHJOIN_ERR_HANDLE for a null handle. Either output
+/// may alias `probe_keys`.
+#[no_mangle]
+pub unsafe extern "C" fn tova_hashjoin_probe_i64(
+    handle: *const HashJoin,
+    probe_keys: *const i64,
+    plen: usize,
+    out_probe_idx: *mut u32,
+    out_build_idx: *mut u32,
+    out_cap: usize,
+) -> isize {
+    clear_last_error();
+    let Some(join) = handle.as_ref() else {
+        set_last_error(TOVA_ERR_INVALID_INPUT, "hashjoin probe: null handle");
+        return TOVA_HASHJOIN_ERR_HANDLE;
+    };
+    if plen == 0 {
+        return 0;
+    }
+    // Either output may be the one sharing memory with the keys
+    let nearest = if overlaps(probe_keys, plen, out_probe_idx, out_cap) { out_probe_idx } else { out_build_idx };
+    let mut keys_copy = Vec::new();
+    let keys = unaliased(probe_keys, plen, nearest, out_cap, &mut keys_copy);
+    let mut written = 0;
+    // Raw writes: the two outputs may themselves overlap
+    let count = join.probe(keys, |probe_row, build_row| {
+        if written < out_cap {
+            out_probe_idx.add(written).write_unaligned(probe_row);
+            out_build_idx.add(written).write_unaligned(build_row);
+            written += 1;
+        }
+    });
+    if count > out_cap { -(count as isize) } else { count as isize }
+}
+
+/// Release a table from `tova_hashjoin_build_i64`.
+#[no_mangle]
+pub unsafe extern "C" fn tova_hashjoin_free(handle: *mut HashJoin) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+// ============================================================
+// Sampling
+// ============================================================
+
+/// Weighted sample without replacement of up to `k` row indices (A-ExpJ
+/// reservoir sampling): each draw takes a row with probability proportional
+/// to its weight among the rows left. Rows with a weight that is not finite
+/// and positive, or a NaN in `values`, are skipped; `values` may be null to
+/// sample on the weights alone. The same inputs and `seed` give the same
+/// sample. Writes the indices (len <= 2^32) to `out_indices` in ascending
+/// order and returns how many: k, or every eligible row when there are fewer.
+#[no_mangle]
+pub unsafe extern "C" fn tova_weighted_sample_f64(
+    values: *const f64,
+    weights: *const f64,
+    len: usize,
+    k: usize,
+    seed: u64,
+    out_indices: *mut u32,
+) -> usize {
+    clear_last_error();
+    if len == 0 || k == 0 {
+        return 0;
+    }
+    let (mut values_copy, mut weights_copy) = (Vec::new(), Vec::new());
+    let values = (!values.is_null()).then(|| unaliased(values, len, out_indices, k, &mut values_copy));
+    let weights = unaliased(weights, len, out_indices, k, &mut weights_copy);
+    with_view_mut(out_indices, k, |out| kernels::weighted_sample_f64(values, weights, seed, out))
+}
+
+/// Uniform sample of up to `per_stratum_k` row indices for each distinct
+/// value of `strata`, in one pass. The same inputs and `seed` give the same
+/// sample. Writes the indices (len <= 2^32) to `out_indices` in ascending
+/// order and returns the count, or the negated count when it exceeds
+/// `out_cap` (the first `out_cap` indices are still written).
+#[no_mangle]
+pub unsafe extern "C" fn tova_stratified_sample_i64(
+    strata: *const i64,
+    len: usize,
+    per_stratum_k: usize,
+    seed: u64,
+    out_indices: *mut u32,
+    out_cap: usize,
+) -> isize {
+    clear_last_error();
+    if len == 0 {
+        return 0;
+    }
+    let mut strata_copy = Vec::new();
+    let strata = unaliased(strata, len, out_indices, out_cap, &mut strata_copy);
+    let count = with_view_mut(out_indices, out_cap, |out| kernels::stratified_sample_i64(strata, per_stratum_k, seed, out));
+    if count > out_cap { -(count as isize) } else { count as isize }
+}
+
+// ============================================================
+// Fingerprints
+// ============================================================
+
+/// MinHash signature of the set of token `hashes`, `num_perm` slots written
+/// to `out_signature`. Compare signatures made with the same `num_perm` and
+/// `seed` with `tova_minhash_similarity`; they are the same on every
+/// platform for the same inputs.
+#[no_mangle]
+pub unsafe extern "C" fn tova_minhash_u64(hashes: *const u64, len: usize, num_perm: u32, seed: u64, out_signature: *mut u64) {
+    clear_last_error();
+    let num_perm = num_perm as usize;
+    let mut hashes_copy = Vec::new();
+    let hashes = unaliased(hashes, len, out_signature, num_perm, &mut hashes_copy);
+    with_view_mut(out_signature, num_perm, |out| kernels::minhash_u64(hashes, seed, out));
+}
+
+/// Fraction of the `num_perm` slots of two MinHash signatures that match,
+/// an estimate of the Jaccard similarity of the two token sets. NaN when
+/// `num_perm` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn tova_minhash_similarity(sig_a: *const u64, sig_b: *const u64, num_perm: u32) -> f64 {
+    let (mut a_copy, mut b_copy) = (Vec::new(), Vec::new());
+    let num_perm = num_perm as usize;
+    kernels::minhash_similarity(view(sig_a, num_perm, &mut a_copy), view(sig_b, num_perm, &mut b_copy))
+}
+
+/// 64-bit SimHash of the token `hashes`, each weighted by `weights` (null
+/// for equal weights; tokens with a weight that is not finite are left
+/// out). Near-duplicates differ in few bits: see `tova_hamming_distance_u64`.
+#[no_mangle]
+pub unsafe extern "C" fn tova_simhash_u64(hashes: *const u64, weights: *const f64, len: usize) -> u64 {
+    let (mut hashes_copy, mut weights_copy) = (Vec::new(), Vec::new());
+    let weights = (!weights.is_null()).then(|| view(weights, len, &mut weights_copy));
+    kernels::simhash_u64(view(hashes, len, &mut hashes_copy), weights)
+}
+
+/// Number of bits in which two fingerprints differ.
+#[no_mangle]
+pub unsafe extern "C" fn tova_hamming_distance_u64(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+// ============================================================
+// Decimal fixed-point
+// ============================================================
+
+// Exact decimals such as money as i64 counts of 10^-scale units, summed and
+// averaged without the rounding drift of f64.
+
+/// Round halfway cases to the even unit (banker's rounding).
+pub const TOVA_ROUND_HALF_EVEN: i32 = 0;
+/// Round halfway cases away from zero.
+pub const TOVA_ROUND_HALF_UP: i32 = 1;
+/// Or'ed into a conversion's rounding mode: write TOVA_DECIMAL_NULL for
+/// inexact values instead of rounding them.
+pub const TOVA_ROUND_STRICT: i32 = 1 << 8;
+
+/// No value: an empty mean, a result past i64, or a value that did not convert.
+pub const TOVA_DECIMAL_NULL: i64 = -9_223_372_036_854_775_807 - 1;
+
+pub const TOVA_DECIMAL_ERR_ARGS: i32 = -1;
+
+fn rounding_mode(mode: i32) -> Option<kernels::Rounding> {
+    match mode {
+        TOVA_ROUND_HALF_EVEN => Some(kernels::Rounding::HalfEven),
+        TOVA_ROUND_HALF_UP => Some(kernels::Rounding::HalfUp),
+        _ => None,
+    }
+}
+
+/// Exact sum of scaled values as an i128, split into its high word
+/// (`out_hi`, signed) and low word (`out_lo`): sum = hi * 2^64 + lo. No i64
+/// input can overflow it. Returns 0, or TOVA_DECIMAL_ERR_ARGS when an output
+/// is null.
+#[no_mangle]
+pub unsafe extern "C" fn tova_sum_scaled_i64(ptr: *const i64, len: usize, out_hi: *mut i64, out_lo: *mut u64) -> i32 {
+    clear_last_error();
+    if out_hi.is_null() || out_lo.is_null() {
+        return fail(TOVA_DECIMAL_ERR_ARGS, "sum_scaled: null output");
+    }
+    let sum = kernels::sum_scaled_i64(view(ptr, len, &mut Vec::new()));
+    out_hi.write_unaligned((sum >> 64) as i64);
+    out_lo.write_unaligned(sum as u64);
+    0
+}
+
+/// Mean of scaled values with `scale` more decimals than they have (0 keeps
+/// their units), from the exact sum and rounded once by `rounding`.
+/// TOVA_DECIMAL_NULL, with the last error set, for no values, a scale past
+/// 18, an unknown rounding mode or a mean past i64.
+#[no_mangle]
+pub unsafe extern "C" fn tova_mean_scaled_i64(ptr: *const i64, len: usize, scale: u32, rounding: i32) -> i64 {
+    clear_last_error();
+    let Some(mode) = rounding_mode(rounding) else {
+        set_last_error(TOVA_ERR_INVALID_INPUT, "mean_scaled: unknown rounding mode");
+        return TOVA_DECIMAL_NULL;
+    };
+    if len == 0 || scale > kernels::MAX_DECIMAL_SCALE {
+        set_last_error(TOVA_ERR_INVALID_INPUT, "mean_scaled: no values, or a scale past 18");
+        return TOVA_DECIMAL_NULL;
+    }
+    kernels::mean_scaled_i64(view(ptr, len, &mut Vec::new()), scale, mode).unwrap_or_else(|| {
+        set_last_error(TOVA_ERR_INVALID_INPUT, "mean_scaled: the mean does not fit an i64");
+        TOVA_DECIMAL_NULL
+    })
+}
+
+/// Convert f64 values to units of 10^-`scale`, rounding each from its exact
+/// binary value by `rounding` (0.125 is a tie, but 1.005 is stored just
+/// under 1.005 and rounds down to 100 at scale 2 in either mode). A
+/// value converts exactly when it is the double nearest its decimal, as
+/// 0.1 is for 10 at scale 2 but 0.1 + 0.2 is not for 30. Non-finite values
+/// and results past i64 are written as TOVA_DECIMAL_NULL, and so are inexact
+/// values when `rounding` has TOVA_ROUND_STRICT. Returns how many values did
+/// not convert exactly; an unknown rounding mode or a scale past 18 writes
+/// nothing and returns SIZE_MAX. `out` may alias `src`.
+#[no_mangle]
+pub unsafe extern "C" fn tova_convert_f64_scaled_i64(src: *const f64, len: usize, scale: u32, out: *mut i64, rounding: i32) -> usize {
+    clear_last_error();
+    let Some(mode) = rounding_mode(rounding & !TOVA_ROUND_STRICT) else {
+        set_last_error(TOVA_ERR_INVALID_INPUT, "convert_scaled: unknown rounding mode");
+        return usize::MAX;
+    };
+    if scale > kernels::MAX_DECIMAL_SCALE {
+        set_last_error(TOVA_ERR_INVALID_INPUT, "convert_scaled: scale past 18");
+        return usize::MAX;
+    }
+    let strict = rounding & TOVA_ROUND_STRICT != 0;
+    let mut src_copy = Vec::new();
+    let src = unaliased(src, len, out, len, &mut src_copy);
+    with_view_mut(out, len, |out| kernels::f64_to_scaled_i64(src, scale, mode, strict, out))
+}
+
+// ============================================================
+// Sparse vectors
+// ============================================================
+
+// A sparse vector is an index array and a value array of the same length,
+// indices strictly ascending. A batch of them is a CSR matrix of `nrows`
+// rows: `indptr` holds nrows + 1 non-decreasing u64 offsets, and row r is
+// the entries indptr[r]..indptr[r + 1] of `idx` and `vals`, which hold
+// indptr[nrows] entries each. indptr[0] is usually 0; entries before it are
+// ignored. An empty row has indptr[r] == indptr[r + 1].
+
+pub const TOVA_SPARSE_ERR_UNSORTED: i32 = -1;
+pub const TOVA_SPARSE_ERR_OUT_OF_RANGE: i32 = -2;
+pub const TOVA_SPARSE_ERR_INDPTR: i32 = -3;
+
+fn sparse_error(e: kernels::SparseError) -> (i32, String) {
+    match e {
+        kernels::SparseError::Unsorted { position } => {
+            (TOVA_SPARSE_ERR_UNSORTED, format!("sparse: indices not strictly ascending at entry {}", position))
+        }
+        kernels::SparseError::OutOfRange { position } => {
+            (TOVA_SPARSE_ERR_OUT_OF_RANGE, format!("sparse: index at entry {} is past the dense vector", position))
+        }
+        kernels::SparseError::BadIndptr { row } => {
+            (TOVA_SPARSE_ERR_INDPTR, format!("sparse: indptr decreases or runs past the entries at row {}", row))
+        }
+    }
+}
+
+/// Dot product of two sparse vectors (Σ over shared indices), compensated
+/// like `tova_weighted_sum_f64`. NaN, with the last error
+/// TOVA_SPARSE_ERR_UNSORTED, if either index array is not strictly
+/// ascending.
+#[no_mangle]
+pub unsafe extern "C" fn tova_sparse_dot(ia: *const u32, va: *const f64, na: usize, ib: *const u32, vb: *const f64, nb: usize) -> f64 {
+    clear_last_error();
+    let (mut ia_copy, mut va_copy, mut ib_copy, mut vb_copy) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    let (ia, va) = (view(ia, na, &mut ia_copy), view(va, na, &mut va_copy));
+    let (ib, vb) = (view(ib, nb, &mut ib_copy), view(vb, nb, &mut vb_copy));
+    if let Err(e) = kernels::check_sparse(ia).and(kernels::check_sparse(ib)) {
+        let (code, message) = sparse_error(e);
+        set_last_error(code, &message);
+        return f64::NAN;
+    }
+    kernels::sparse_dot(ia, va, ib, vb)
+}
+
+/// Dot product of a sparse vector (indices in any order) with a dense one
+/// of `dense_len` values. NaN, with the last error
+/// TOVA_SPARSE_ERR_OUT_OF_RANGE, if an index is not below `dense_len`.
+#[no_mangle]
+pub unsafe extern "C" fn tova_sparse_dense_dot(idx: *const u32, vals: *const f64, n: usize, dense: *const f64, dense_len: usize) -> f64 {
+    clear_last_error();
+    let (mut idx_copy, mut vals_copy, mut dense_copy) = (Vec::new(), Vec::new(), Vec::new());
+    let (idx, vals) = (view(idx, n, &mut idx_copy), view(vals, n, &mut vals_copy));
+    match kernels::sparse_dense_dot(idx, vals, view(dense, dense_len, &mut dense_copy)) {
+        Ok(dot) => dot,
+        Err(e) => {
+            let (code, message) = sparse_error(e);
+            set_last_error(code, &message);
+            f64::NAN
+        }
+    }
+}
+
+/// Score a sparse query against every row of a CSR batch by dot product
+/// and write the `k` best rows to `out_rows`, highest score first and ties
+/// to the lower row, with their scores in `out_scores`. Rows scoring NaN
+/// are skipped. Returns how many rows were written (min(k, rows scored)),
+/// or SIZE_MAX with nothing written and the last error set:
+/// TOVA_SPARSE_ERR_UNSORTED for a query or row whose indices are not
+/// strictly ascending, TOVA_SPARSE_ERR_INDPTR for bad offsets or more than
+/// 2^32 - 1 rows, TOVA_ERR_OVERLAP when an output overlaps an input or the
+/// other output.
+#[no_mangle]
+pub unsafe extern "C" fn tova_sparse_topk_dot(
+    query_idx: *const u32,
+    query_vals: *const f64,
+    qn: usize,
+    batch_csr_indptr: *const u64,
+    batch_idx: *const u32,
+    batch_vals: *const f64,
+    nrows: usize,
+    k: usize,
+    out_rows: *mut u32,
+    out_scores: *mut f64,
+) -> usize {
+    clear_last_error();
+    if nrows > u32::MAX as usize {
+        set_last_error(TOVA_SPARSE_ERR_INDPTR, "sparse: more than 2^32 - 1 rows");
+        return usize::MAX;
+    }
+    let mut indptr_copy = Vec::new();
+    let indptr = view(batch_csr_indptr, nrows + 1, &mut indptr_copy);
+    let nnz = match usize::try_from(indptr[nrows]) {
+        Ok(nnz) => nnz,
+        Err(_) => {
+            set_last_error(TOVA_SPARSE_ERR_INDPTR, "sparse: indptr runs past the address space");
+            return usize::MAX;
+        }
+    };
+    let k = k.min(nrows);
+    let inputs: [(*const u8, usize); 5] = [
+        (query_idx.cast(), qn.saturating_mul(4)),
+        (query_vals.cast(), qn.saturating_mul(8)),
+        (batch_csr_indptr.cast(), (nrows + 1) * 8),
+        (batch_idx.cast(), nnz.saturating_mul(4)),
+        (batch_vals.cast(), nnz.saturating_mul(8)),
+    ];
+    let any_input = |out: *const u8, bytes: usize| inputs.iter().any(|&(input, len)| overlaps(out, bytes, input, len));
+    if overlaps(out_rows, k, out_scores, k) || any_input(out_rows.cast(), k * 4) || any_input(out_scores.cast(), k * 8) {
+        set_last_error(TOVA_ERR_OVERLAP, "output range overlaps an input");
+        return usize::MAX;
+    }
+    let (mut qi_copy, mut qv_copy, mut bi_copy, mut bv_copy) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    let (query_idx, query_vals) = (view(query_idx, qn, &mut qi_copy), view(query_vals, qn, &mut qv_copy));
+    let csr = kernels::Csr { indptr, indices: view(batch_idx, nnz, &mut bi_copy), values: view(batch_vals, nnz, &mut bv_copy) };
+    if let Err(e) = kernels::check_sparse(query_idx).and(csr.check()) {
+        let (code, message) = sparse_error(e);
+        set_last_error(code, &message);
+        return usize::MAX;
+    }
+    with_view_mut(out_rows, k, |rows| {
+        with_view_mut(out_scores, k, |scores| kernels::sparse_topk_dot(query_idx, query_vals, &csr, rows, scores))
+    })
+}
+
+// ============================================================
+// Tests
+// ============================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Seeded xorshift64, so the randomized tests see the same inputs every run.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next_u64(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        /// Uniform in [0, 1)
+        fn unit(&mut self) -> f64 {
+            (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+        }
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)]
+    fn test_sort_f64() {
+        let mut data = vec![3.14, -1.0, 2.71, 0.0, -0.5, 100.0, -100.0, 1.0];
+        kernels::sort_f64(&mut data);
+        assert_eq!(data, vec![-100.0, -1.0, -0.5, 0.0, 1.0, 2.71, 3.14, 100.0]);
+    }
+
+    #[test]
+    fn test_sort_f64_large() {
+        let mut data: Vec<f64> = (0..10000).map(|i| (10000 - i) as f64).collect();
+        kernels::sort_f64(&mut data);
+        let expected: Vec<f64> = (1..=10000).map(|i| i as f64).collect();
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_sort_f64_negative() {
+        let mut data = vec![-3.0, -1.0, -2.0];
+        kernels::sort_f64(&mut data);
+        assert_eq!(data, vec![-3.0, -2.0, -1.0]);
+    }
+
+    #[test]
+    fn test_sort_i64() {
+        let mut data = vec![5i64, -3, 0, 10, -1, 7, 2];
+        kernels::sort_i64(&mut data);
+        assert_eq!(data, vec![-3, -1, 0, 2, 5, 7, 10]);
+    }
+
+    #[test]
+    fn test_sort_i64_large() {
+        let mut data: Vec<i64> = (0..10000).map(|i| 5000 - i).collect();
+        kernels::sort_i64(&mut data);
+        let expected: Vec<i64> = (-4999..=5000).collect();
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_sort_desc_reverses_large_inputs() {
+        // Ascending input, so the radix path has to turn all of it around
+        let mut data: Vec<f64> = (0..20_000).map(|i| (i - 10_000) as f64 * 0.5).collect();
+        unsafe { tova_sort_f64_desc(data.as_mut_ptr(), data.len()) };
+        let expected: Vec<f64> = (0..20_000).rev().map(|i| (i - 10_000) as f64 * 0.5).collect();
+        assert_eq!(data, expected);
+
+        let mut data: Vec<i64> = (-10_000..10_000).collect();
+        unsafe { tova_sort_i64_desc(data.as_mut_ptr(), data.len()) };
+        assert_eq!(data, (-10_000..10_000).rev().collect::<Vec<i64>>());
+    }
+
+    #[test]
+    fn test_sort_desc_is_the_reverse_of_ascending() {
+        let mut data = adversarial_f64(30_000);
+        data[3] = f64::NAN;
+        data[4] = -f64::NAN;
+        data[5] = -0.0;
+        data[6] = 0.0;
+        data[7] = f64::INFINITY;
+        // Below the small cutoff and past it
+        for len in [2, 9, 100, data.len()] {
+            let mut expected = data[..len].to_vec();
+            unsafe { tova_sort_f64(expected.as_mut_ptr(), len) };
+            expected.reverse();
+            let mut sorted = data[..len].to_vec();
+            unsafe { tova_sort_f64_desc(sorted.as_mut_ptr(), len) };
+            assert_eq!(bits_of(&sorted), bits_of(&expected), "len {}", len);
+
+            let ints: Vec<i64> = data[..len].iter().map(|v| v.to_bits() as i64 % 1000).collect();
+            let mut expected = ints.clone();
+            unsafe { tova_sort_i64(expected.as_mut_ptr(), len) };
+            expected.reverse();
+            let mut sorted = ints;
+            unsafe { tova_sort_i64_desc(sorted.as_mut_ptr(), len) };
+            assert_eq!(sorted, expected, "len {}", len);
+        }
+
+        let mut zeros = [-0.0, 0.0, f64::NAN, -1.0, -0.0, -f64::NAN, 0.0];
+        unsafe { tova_sort_f64_desc(zeros.as_mut_ptr(), zeros.len()) };
+        assert_eq!(bits_of(&zeros), bits_of(&[f64::NAN, 0.0, 0.0, -0.0, -0.0, -1.0, -f64::NAN]));
+    }
+
+    #[test]
+    fn test_sort_desc_of_all_equal_values() {
+        for len in [0, 1, 50, 15_000] {
+            let mut data = vec![-2.5f64; len];
+            unsafe { tova_sort_f64_desc(data.as_mut_ptr(), len) };
+            assert!(data.iter().all(|&v| v == -2.5));
+            let mut ints = vec![7i64; len];
+            unsafe { tova_sort_i64_desc(ints.as_mut_ptr(), len) };
+            assert!(ints.iter().all(|&v| v == 7));
+        }
+    }
+
+    fn argsort(values: &[f64]) -> Vec<u32> {
+        let mut idx = vec![u32::MAX; values.len()];
+        assert_eq!(unsafe { tova_argsort_f64(values.as_ptr(), values.len(), idx.as_mut_ptr()) }, 0);
+        idx
+    }
+
+    #[test]
+    fn test_argsort_f64_keeps_ties_in_input_order() {
+        let values = [2.5, -1.0, 2.5, -0.0, -7.25, 0.0, -1.0, f64::NAN, 2.5, -f64::NAN];
+        let before = bits_of(&values);
+        assert_eq!(argsort(&values), [9, 4, 1, 6, 3, 5, 0, 2, 8, 7]);
+        assert_eq!(bits_of(&values), before);
+        assert!(argsort(&[]).is_empty());
+        assert_eq!(argsort(&[3.0]), [0]);
+
+        // Past the small cutoff, on the radix path: runs of ties, negatives
+        let values: Vec<f64> = (0..30_000).map(|i| ((i * 7) % 11) as f64 - 5.0).collect();
+        let idx = argsort(&values);
+        for pair in idx.windows(2) {
+            let (a, b) = (values[pair[0] as usize], values[pair[1] as usize]);
+            assert!(a < b || (a == b && pair[0] < pair[1]), "{:?}", pair);
+        }
+    }
+
+    #[test]
+    fn test_argsort_f64_matches_a_comparison_argsort() {
+        let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+        // Few enough distinct values to leave plenty of ties
+        let values: Vec<f64> = (0..1_000_000).map(|_| ((rng.next_u64() >> 11) % 200_000) as f64 * 0.25 - 25_000.0).collect();
+        let mut expected: Vec<u32> = (0..values.len() as u32).collect();
+        expected.sort_by(|&a, &b| values[a as usize].total_cmp(&values[b as usize]));
+        assert_eq!(argsort(&values), expected);
+    }
+
+    #[test]
+    fn test_argsort_f64_refuses_overlapping_output() {
+        let mut buf = [3.0f64, 1.0, 2.0, 0.0];
+        let before = bits_of(&buf);
+        let p = buf.as_mut_ptr();
+        // Indices written over the values' back half
+        let r = unsafe { tova_argsort_f64(p, 4, p.add(2) as *mut u32) };
+        assert_eq!(r, TOVA_ERR_OVERLAP);
+        assert_eq!(unsafe { tova_last_error() }, TOVA_ERR_OVERLAP);
+        assert_eq!(bits_of(&buf), before);
+        // Adjacent ranges do not overlap
+        let mut buf = [3.0f64, 1.0, 2.0, 0.0, 0.0, 0.0];
+        let p = buf.as_mut_ptr();
+        assert_eq!(unsafe { tova_argsort_f64(p, 4, p.add(4) as *mut u32) }, 0);
+        let idx: Vec<u32> = (0..4).map(|i| unsafe { (p.add(4) as *const u32).add(i).read_unaligned() }).collect();
+        assert_eq!(idx, [3, 1, 2, 0]);
+    }
+
+    #[test]
+    fn test_unique_sorted() {
+        let mut data = vec![1i64, 1, 2, 2, 3, 3, 3, 4];
+        let new_len = kernels::unique_sorted(&mut data);
+        assert_eq!(new_len, 4);
+        assert_eq!(&data[..new_len], &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_unique_counts_sorted_runs() {
+        // Every run of length 1
+        let mut data = vec![1i64, 2, 3, 4];
+        let mut counts = vec![0u64; 4];
+        assert_eq!(unsafe { tova_unique_counts_sorted_i64(data.as_mut_ptr(), 4, counts.as_mut_ptr()) }, 4);
+        assert_eq!((&data[..], &counts[..]), (&[1, 2, 3, 4][..], &[1, 1, 1, 1][..]));
+
+        // One run of length len
+        let mut data = vec![7i64; 5];
+        let mut counts = vec![0u64; 5];
+        assert_eq!(kernels::unique_counts_sorted_i64(&mut data, &mut counts), 1);
+        assert_eq!((data[0], counts[0]), (7, 5));
+
+        let mut floats = vec![-0.0, -0.0, 0.0, 1.5, f64::NAN, f64::NAN];
+        let mut counts = vec![0u64; 6];
+        let n = unsafe { tova_unique_counts_sorted_f64(floats.as_mut_ptr(), 6, counts.as_mut_ptr()) };
+        assert_eq!(n, 4);
+        assert_eq!(&counts[..n], &[2, 1, 1, 2]);
+        assert_eq!(floats[0].to_bits(), (-0.0f64).to_bits());
+        assert!(floats[3].is_nan());
+        assert_eq!(unsafe { tova_unique_counts_sorted_i64(std::ptr::null_mut(), 0, std::ptr::null_mut()) }, 0);
+    }
+
+    #[test]
+    fn test_sum_f64() {
+        let data = vec![1.0, 2.0, 3.0, 4.0, 5.0];
+        let sum = kernels::sum_f64(&data);
+        assert_eq!(sum, 15.0);
+    }
+
+    #[test]
+    fn test_min_max_f64() {
+        let data = vec![3.0, 1.0, 4.0, 1.5, 9.0, 2.6];
+        let min = kernels::min_f64(&data);
+        let max = kernels::max_f64(&data);
+        assert_eq!(min, 1.0);
+        assert_eq!(max, 9.0);
+    }
+
+    #[test]
+    fn test_min_max_skip_nan_wherever_it_is() {
+        for data in [
+            [f64::NAN, 3.0, -1.0, 2.0],
+            [3.0, f64::NAN, -1.0, 2.0],
+            [3.0, -1.0, 2.0, f64::NAN],
+            [3.0, -f64::NAN, -1.0, 2.0],
+        ] {
+            assert_eq!(kernels::min_f64(&data), -1.0, "{:?}", data);
+            assert_eq!(kernels::max_f64(&data), 3.0, "{:?}", data);
+        }
+        assert!(kernels::min_f64(&[f64::NAN, f64::NAN]).is_nan());
+        assert!(kernels::max_f64(&[f64::NAN]).is_nan());
+        assert!(unsafe { tova_min_f64([0.0].as_ptr(), 0) }.is_nan());
+    }
+
+    /// Large magnitudes that cancel, with small values in between, so every
+    /// lost low-order bit shows up in the result.
+    fn adversarial_f64(n: usize) -> Vec<f64> {
+        let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+        (0..n)
+            .map(|i| {
+                let small = rng.unit();
+                match i % 4 {
+                    0 => 1e16,
+                    1 => small,
+                    2 => -1e16,
+                    _ => small * 1e-8 - 0.5,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parallel_sum_is_identical_for_any_thread_count() {
+        let data = adversarial_f64(kernels::PARALLEL_MIN_LEN + 3 * kernels::PARALLEL_SUM_BLOCK + 17);
+        let one = unsafe { tova_sum_f64_parallel(data.as_ptr(), data.len(), 1) };
+        for threads in [0, 2, 3, 8, 64] {
+            let many = unsafe { tova_sum_f64_parallel(data.as_ptr(), data.len(), threads) };
+            assert_eq!(many.to_bits(), one.to_bits(), "threads {}", threads);
+        }
+        // Below the threshold it stays on the caller's thread, same blocks
+        let small = &data[..kernels::PARALLEL_SUM_BLOCK * 2 + 5];
+        assert_eq!(kernels::sum_f64_parallel(small, 8).to_bits(), kernels::sum_f64_parallel(small, 1).to_bits());
+        assert_eq!(unsafe { tova_sum_f64_parallel(data.as_ptr(), 0, 8) }, 0.0);
+    }
+
+    #[test]
+    fn test_parallel_min_max_match_sequential() {
+        let mut data = adversarial_f64(kernels::PARALLEL_MIN_LEN + 1000);
+        data[0] = f64::NAN;
+        let last = data.len() - 1;
+        data[last] = -1e300;
+        data[12345] = 1e300;
+        for threads in [1, 8] {
+            let (mut min, mut max) = (0.0, 0.0);
+            unsafe { tova_minmax_f64_parallel(data.as_ptr(), data.len(), threads, &mut min, &mut max) };
+            assert_eq!((min, max), (-1e300, 1e300));
+            assert_eq!(unsafe { tova_min_f64_parallel(data.as_ptr(), data.len(), threads) }, kernels::min_f64(&data));
+            assert_eq!(unsafe { tova_max_f64_parallel(data.as_ptr(), data.len(), threads) }, kernels::max_f64(&data));
+        }
+        let (mut min, mut max) = (0.0, 0.0);
+        unsafe { tova_minmax_f64_parallel(data.as_ptr(), 0, 8, &mut min, &mut max) };
+        assert!(min.is_nan() && max.is_nan());
+    }
+
+    #[test]
+    fn test_min_max_order_signed_zeros() {
+        for data in [[0.0, -0.0, 0.0], [-0.0, 0.0, -0.0], [f64::NAN, 0.0, -0.0]] {
+            assert_eq!(kernels::min_f64(&data).to_bits(), (-0.0f64).to_bits(), "{:?}", data);
+            assert_eq!(kernels::max_f64(&data).to_bits(), 0.0f64.to_bits(), "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn test_i64_aggregates_are_exact_beyond_2_53() {
+        let big = 1i64 << 53;
+        // Adjacent values f64 cannot tell apart
+        let data = [big + 3, big + 1, i64::MAX, big + 2, i64::MIN, i64::MAX, i64::MIN + 1];
+        let (p, n) = (data.as_ptr(), data.len());
+        let mut status = -7;
+        unsafe {
+            assert_eq!(tova_min_i64(p, n, &mut status), i64::MIN);
+            assert_eq!(status, TOVA_AGG_OK);
+            assert_eq!(tova_max_i64(p, n, std::ptr::null_mut()), i64::MAX);
+            assert_eq!(tova_argmin_i64(p, n, &mut status), 4);
+            // The first of equal maxima
+            assert_eq!(tova_argmax_i64(p, n, &mut status), 2);
+            let (mut min, mut max) = (0, 0);
+            assert_eq!(tova_minmax_i64(p, n, &mut min, &mut max), TOVA_AGG_OK);
+            assert_eq!((min, max), (i64::MIN, i64::MAX));
+            assert_eq!(tova_min_i64(data[..4].as_ptr(), 4, &mut status), big + 1);
+            assert_eq!(tova_argmax_i64(data[..2].as_ptr(), 2, &mut status), 0);
+
+            // i128 accumulation: no overflow, and exact means stay exact
+            assert_eq!(tova_mean_i64([i64::MAX, i64::MAX].as_ptr(), 2, &mut status), i64::MAX as f64);
+            assert_eq!(tova_mean_i64([i64::MIN, i64::MIN].as_ptr(), 2, &mut status), i64::MIN as f64);
+            assert_eq!(tova_mean_i64([i64::MIN, i64::MAX].as_ptr(), 2, &mut status), -0.5);
+            assert_eq!(tova_mean_i64([big + 1, big + 3].as_ptr(), 2, &mut status), (big + 2) as f64);
+            assert_eq!(tova_mean_i64([-3, 4, 1].as_ptr(), 3, &mut status), 2.0 / 3.0);
+            assert_eq!(status, TOVA_AGG_OK);
+        }
+    }
+
+    #[test]
+    fn test_i64_aggregates_report_empty_input() {
+        let mut status = -7;
+        let p = std::ptr::null();
+        unsafe {
+            assert_eq!(tova_min_i64(p, 0, &mut status), 0);
+            assert_eq!(status, TOVA_AGG_EMPTY);
+            status = -7;
+            assert_eq!(tova_max_i64(p, 0, &mut status), 0);
+            assert_eq!(status, TOVA_AGG_EMPTY);
+            assert_eq!(tova_argmin_i64(p, 0, &mut status), usize::MAX);
+            assert_eq!(tova_argmax_i64(p, 0, std::ptr::null_mut()), usize::MAX);
+            assert!(tova_mean_i64(p, 0, &mut status).is_nan());
+            let (mut min, mut max) = (5, 5);
+            assert_eq!(tova_minmax_i64(p, 0, &mut min, &mut max), TOVA_AGG_EMPTY);
+            assert_eq!((min, max), (0, 0));
+
+            // Nothing valid is empty too, and a real 0 is not
+            let data = [4i64, 0, 9];
+            assert_eq!(tova_min_i64_masked(data.as_ptr(), [0u8].as_ptr(), 3, &mut status), 0);
+            assert_eq!(status, TOVA_AGG_EMPTY);
+            assert_eq!(tova_min_i64_masked(data.as_ptr(), [0b010u8].as_ptr(), 3, &mut status), 0);
+            assert_eq!(status, TOVA_AGG_OK);
+        }
+    }
+
+    #[test]
+    fn test_i64_masked_aggregates_skip_invalid_values() {
+        let data = [i64::MIN, 7, i64::MAX, -2, 7, 40, -2, i64::MIN, 3];
+        // Valid: 1, 3, 4, 6, 8 (the extremes at 0, 2 and 7 are null)
+        let validity = [0b0101_1010u8, 0b1];
+        let (p, v, n) = (data.as_ptr(), validity.as_ptr(), data.len());
+        let mut status = -7;
+        unsafe {
+            assert_eq!(tova_min_i64_masked(p, v, n, &mut status), -2);
+            assert_eq!(tova_max_i64_masked(p, v, n, &mut status), 7);
+            assert_eq!(tova_argmin_i64_masked(p, v, n, &mut status), 3);
+            assert_eq!(tova_argmax_i64_masked(p, v, n, &mut status), 1);
+            assert_eq!(tova_mean_i64_masked(p, v, n, &mut status), 13.0 / 5.0);
+            let (mut min, mut max) = (0, 0);
+            assert_eq!(tova_minmax_i64_masked(p, v, n, &mut min, &mut max), TOVA_AGG_OK);
+            assert_eq!((min, max), (-2, 7));
+            // A null bitmap counts everything
+            assert_eq!(tova_argmin_i64_masked(p, std::ptr::null(), n, &mut status), 0);
+            assert_eq!(tova_mean_i64_masked(p, std::ptr::null(), n, &mut status), kernels::mean_i64(&data, None).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_i64_segmented_aggregates() {
+        let data = [5i64, i64::MAX, -1, i64::MIN, 2, 2, (1 << 53) + 1, 1 << 53];
+        let offsets = [0u64, 3, 3, 4, 6, 8];
+        let (p, n, o) = (data.as_ptr(), data.len(), offsets.as_ptr());
+        let mut mins = [9i64; 5];
+        let mut maxes = [9i64; 5];
+        let mut argmins = [9u64; 5];
+        let mut argmaxes = [9u64; 5];
+        let mut means = [9.0f64; 5];
+        unsafe {
+            assert_eq!(tova_min_i64_segmented(p, n, o, 5, mins.as_mut_ptr()), 1);
+            assert_eq!(tova_max_i64_segmented(p, n, o, 5, maxes.as_mut_ptr()), 1);
+            assert_eq!(tova_argmin_i64_segmented(p, n, o, 5, argmins.as_mut_ptr()), 1);
+            assert_eq!(tova_argmax_i64_segmented(p, n, o, 5, argmaxes.as_mut_ptr()), 1);
+            assert_eq!(tova_mean_i64_segmented(p, n, o, 5, means.as_mut_ptr()), 1);
+        }
+        assert_eq!(mins, [-1, 0, i64::MIN, 2, 1 << 53]);
+        assert_eq!(maxes, [i64::MAX, 0, i64::MIN, 2, (1 << 53) + 1]);
+        // Positions in the whole array, the first of equal values
+        assert_eq!(argmins, [2, u64::MAX, 3, 4, 7]);
+        assert_eq!(argmaxes, [1, u64::MAX, 3, 4, 6]);
+        assert_eq!(means[0], kernels::mean_i64(&data[..3], None).unwrap());
+        assert!(means[1].is_nan());
+        assert_eq!(&means[2..], &[i64::MIN as f64, 2.0, (1u64 << 53) as f64]);
+
+        // Segments agree with the whole-array functions
+        let mut status = 0;
+        for (i, w) in offsets.windows(2).enumerate().filter(|(_, w)| w[0] < w[1]) {
+            let run = &data[w[0] as usize..w[1] as usize];
+            assert_eq!(unsafe { tova_min_i64(run.as_ptr(), run.len(), &mut status) }, mins[i]);
+            assert_eq!(unsafe { tova_argmax_i64(run.as_ptr(), run.len(), &mut status) } as u64 + w[0], argmaxes[i]);
+        }
+    }
+
+    #[test]
+    fn test_i64_segmented_aggregates_reject_bad_offsets() {
+        let data = [1i64, 2, 3];
+        let mut out = [9i64; 2];
+        for offsets in [[0u64, 2, 1], [0, 2, 4]] {
+            let code = unsafe { tova_min_i64_segmented(data.as_ptr(), 3, offsets.as_ptr(), 2, out.as_mut_ptr()) };
+            assert_eq!(code, TOVA_AGG_ERR_OFFSETS as isize);
+            assert_eq!(unsafe { tova_last_error() }, TOVA_AGG_ERR_OFFSETS);
+            assert_eq!(out, [9, 9]);
+        }
+        assert_eq!(unsafe { tova_min_i64_segmented(data.as_ptr(), 3, std::ptr::null(), 0, out.as_mut_ptr()) }, 0);
+        // Values aliasing the output are read before it is written
+        let mut both = [4i64, 1, 8, 2];
+        let offsets = [0u64, 2, 4];
+        let p = both.as_mut_ptr();
+        assert_eq!(unsafe { tova_max_i64_segmented(p, 4, offsets.as_ptr(), 2, p) }, 0);
+        assert_eq!(unsafe { tova_last_error() }, TOVA_ERR_OVERLAP);
+        assert_eq!(&both[..2], &[4, 8]);
+    }
+
+    #[test]
+    fn test_argmin_argmax_match_min_max_f64() {
+        let data = [3.0, f64::NAN, -0.0, 7.0, 0.0, -0.0, 7.0];
+        assert_eq!(kernels::argmin(&data, None), Some((2, -0.0)));
+        assert_eq!(kernels::argmax(&data, None), Some((3, 7.0)));
+        assert_eq!(kernels::argmin(&data, None).unwrap().1.to_bits(), kernels::min_f64(&data).to_bits());
+        assert_eq!(kernels::minmax(&data, None), Some((kernels::min_f64(&data), kernels::max_f64(&data))));
+        assert_eq!(kernels::argmin(&[f64::NAN], None), None);
+        // Invalid slots never win, even with the extremes in them
+        assert_eq!(kernels::argmax(&data, Some(&[0b0111_0111])), Some((6, 7.0)));
+    }
+
+    #[test]
+    fn test_sort_tuning_rejects_invalid_values() {
+        assert_eq!(unsafe { tova_sort_set_tuning(100, 12, 1 << 20) }, TOVA_SORT_ERR_RADIX_BITS);
+        assert_eq!(unsafe { tova_last_error() }, TOVA_SORT_ERR_RADIX_BITS);
+        assert_eq!(unsafe { tova_sort_set_tuning(100, 0, 1 << 20) }, TOVA_SORT_ERR_RADIX_BITS);
+        assert_eq!(unsafe { tova_sort_set_tuning(100, 11, 1) }, TOVA_SORT_ERR_PARALLEL_THRESHOLD);
+        assert_eq!(unsafe { tova_last_error() }, TOVA_SORT_ERR_PARALLEL_THRESHOLD);
+        unsafe { tova_sort_get_tuning(std::ptr::null_mut(), std::ptr::null_mut(), std::ptr::null_mut()) };
+    }
+
+    #[test]
+    fn test_sort_output_is_identical_under_every_tuning() {
+        let mut data = adversarial_f64(50_000);
+        data[7] = f64::NAN;
+        data[8] = -f64::NAN;
+        data[9] = -0.0;
+        let mut expected = data.clone();
+        expected.sort_by(f64::total_cmp);
+        let expected: Vec<u64> = expected.iter().map(|v| v.to_bits()).collect();
+        for bits in kernels::RADIX_BITS {
+            let mut sorted = data.clone();
+            kernels::bench::radix_sort_f64_bits(&mut sorted, bits);
+            assert_eq!(sorted.iter().map(|v| v.to_bits()).collect::<Vec<_>>(), expected, "{} bits", bits);
+            // The usize counters inputs past u32::MAX elements take
+            let mut sorted = data.clone();
+            kernels::bench::radix_sort_f64_wide(&mut sorted, bits);
+            assert_eq!(sorted.iter().map(|v| v.to_bits()).collect::<Vec<_>>(), expected, "{} bits, wide", bits);
+        }
+        for threads in [1, 2, 3, 8] {
+            let mut sorted = data.clone();
+            kernels::bench::parallel_sort_f64(&mut sorted, threads);
+            assert_eq!(sorted.iter().map(|v| v.to_bits()).collect::<Vec<_>>(), expected, "{} threads", threads);
+        }
+
+        // Through the exported sorter with every knob moved, then restored;
+        // other tests sorting meanwhile see valid settings throughout
+        let default = kernels::DEFAULT_SORT_TUNING;
+        assert_eq!(unsafe { tova_sort_set_tuning(64, 8, 1000) }, 0);
+        assert_eq!(unsafe { tova_sort_set_tuning(64, 9, 1000) }, TOVA_SORT_ERR_RADIX_BITS);
+        let (mut cutoff, mut bits, mut threshold) = (0, 0, 0);
+        unsafe { tova_sort_get_tuning(&mut cutoff, &mut bits, &mut threshold) };
+        assert_eq!((cutoff, bits, threshold), (64, 8, 1000));
+        let mut sorted = data.clone();
+        unsafe { tova_sort_f64(sorted.as_mut_ptr(), sorted.len()) };
+        let mut ints: Vec<i64> = data.iter().map(|v| v.to_bits() as i64).collect();
+        let mut ints_expected = ints.clone();
+        unsafe { tova_sort_i64(ints.as_mut_ptr(), ints.len()) };
+        assert_eq!(unsafe { tova_sort_set_tuning(default.small_cutoff, default.radix_bits, default.parallel_threshold) }, 0);
+        assert_eq!(sorted.iter().map(|v| v.to_bits()).collect::<Vec<_>>(), expected);
+        ints_expected.sort_unstable();
+        assert_eq!(ints, ints_expected);
+    }
+
+    fn bits_of(values: &[f64]) -> Vec<u64> {
+        values.iter().map(|v| v.to_bits()).collect()
+    }
+
+    #[test]
+    fn test_stepped_sort_matches_one_shot_for_any_budget() {
+        let mut data = adversarial_f64(30_000);
+        data[3] = f64::NAN;
+        data[4] = -f64::from_bits(f64::NAN.to_bits() | 1);
+        data[5] = -0.0;
+        for len in [0, 1, 5, 100, data.len()] {
+            let mut expected = data[..len].to_vec();
+            unsafe { tova_sort_f64(expected.as_mut_ptr(), len) };
+            for budget in [0, 1, 7, 4096, 65_539, usize::MAX] {
+                let mut sorted = data[..len].to_vec();
+                let handle = unsafe { tova_sort_f64_begin(sorted.as_mut_ptr(), len) };
+                let mut steps = 0;
+                while unsafe { tova_sort_f64_step(handle, budget) } == 0 {
+                    steps += 1;
+                }
+                assert_eq!(unsafe { tova_sort_f64_step(handle, budget) }, 1);
+                unsafe { tova_sort_f64_finish(handle) };
+                assert_eq!(bits_of(&sorted), bits_of(&expected), "len {} budget {}", len, budget);
+                if budget == usize::MAX {
+                    assert_eq!(steps, 0);
+                }
+            }
+        }
+
+        // Finishing early does the rest; a misaligned array is sorted in place
+        let (buf, ptr) = misaligned(&data, 3);
+        let handle = unsafe { tova_sort_f64_begin(ptr, data.len()) };
+        assert_eq!(unsafe { tova_sort_f64_step(handle, 50_000) }, 0);
+        unsafe { tova_sort_f64_finish(handle) };
+        let mut expected = data.clone();
+        unsafe { tova_sort_f64(expected.as_mut_ptr(), expected.len()) };
+        assert_eq!(bits_of(&read_back(ptr, data.len())), bits_of(&expected));
+        assert!(guards_intact(&buf, 3, data.len() * 8));
+
+        assert_eq!(unsafe { tova_sort_f64_step(std::ptr::null_mut(), 1) }, TOVA_SORT_ERR_HANDLE);
+        assert_eq!(unsafe { tova_last_error() }, TOVA_SORT_ERR_HANDLE);
+        unsafe { tova_sort_f64_finish(std::ptr::null_mut()) };
+        unsafe { tova_sort_f64_abort(std::ptr::null_mut()) };
+    }
+
+    #[test]
+    fn test_aborted_sort_keeps_every_value() {
+        let mut data = adversarial_f64(20_000);
+        data[10] = f64::from_bits(0x7FF0_0000_0000_0001); // signalling NaN payload
+        data[11] = -0.0;
+        let mut original = bits_of(&data);
+        original.sort_unstable();
+        // Stop in every phase: key rewrite, each count and scatter (both
+        // directions), the rewrite back, and after the end
+        for steps in [0, 1, 2, 3, 5, 8, 11, 17, 23, 30, 40] {
+            let mut values = data.clone();
+            let handle = unsafe { tova_sort_f64_begin(values.as_mut_ptr(), values.len()) };
+            for _ in 0..steps {
+                unsafe { tova_sort_f64_step(handle, 7_000) };
+            }
+            unsafe { tova_sort_f64_abort(handle) };
+            let mut kept = bits_of(&values);
+            kept.sort_unstable();
+            assert_eq!(kept, original, "aborted after {} steps", steps);
+        }
+    }
+
+    /// An empty directory of its own for a test's spill files.
+    fn scratch_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("tova-native-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn c_path(dir: &std::path::Path) -> std::ffi::CString {
+        std::ffi::CString::new(dir.to_str().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_external_sort_matches_in_memory_sort() {
+        let dir = scratch_dir("external-sort");
+        let scratch = c_path(&dir);
+        // 10 MB of values, sorted through ten 1 MB runs (and an odd tail)
+        let mut data = adversarial_f64(10 << 17);
+        data.extend([f64::NAN, -f64::NAN, -0.0, 0.0, f64::INFINITY, f64::from_bits(0x7FF0_0000_0000_0001)]);
+        let mut expected = data.clone();
+        unsafe { tova_sort_f64(expected.as_mut_ptr(), expected.len()) };
+        let code = unsafe { tova_sort_f64_external(data.as_mut_ptr(), data.len(), scratch.as_ptr(), 1 << 20) };
+        assert_eq!(code, 0);
+        assert_eq!(bits_of(&data), bits_of(&expected));
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0, "run files left behind");
+
+        // Within the budget: sorted in place, no files
+        let mut small = adversarial_f64(1000);
+        let mut expected = small.clone();
+        unsafe { tova_sort_f64(expected.as_mut_ptr(), expected.len()) };
+        assert_eq!(unsafe { tova_sort_f64_external(small.as_mut_ptr(), small.len(), scratch.as_ptr(), 8000) }, 0);
+        assert_eq!(bits_of(&small), bits_of(&expected));
+        // One value per run
+        let mut tiny = vec![3.0, -1.0, 2.0];
+        assert_eq!(unsafe { tova_sort_f64_external(tiny.as_mut_ptr(), 3, scratch.as_ptr(), 8) }, 0);
+        assert_eq!(tiny, [-1.0, 2.0, 3.0]);
+        assert_eq!(unsafe { tova_sort_f64_external(std::ptr::null_mut(), 0, scratch.as_ptr(), 8) }, 0);
+
+        // More runs than the usual 1024 open files, merged two at a time in passes
+        let mut many = adversarial_f64(1500);
+        let mut expected = many.clone();
+        unsafe { tova_sort_f64(expected.as_mut_ptr(), expected.len()) };
+        assert_eq!(unsafe { tova_sort_f64_external(many.as_mut_ptr(), many.len(), scratch.as_ptr(), 8) }, 0);
+        assert_eq!(bits_of(&many), bits_of(&expected));
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0, "run files left behind");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_external_sort_errors() {
+        let dir = scratch_dir("external-sort-errors");
+        let scratch = c_path(&dir);
+        let mut data = adversarial_f64(4096);
+        let mut original = bits_of(&data);
+        original.sort_unstable();
+
+        let missing = c_path(&dir.join("missing"));
+        let code = unsafe { tova_sort_f64_external(data.as_mut_ptr(), data.len(), missing.as_ptr(), 1024) };
+        assert_eq!(code, TOVA_EXTERNAL_SORT_ERR_CREATE);
+        assert_eq!(unsafe { tova_last_error() }, TOVA_EXTERNAL_SORT_ERR_CREATE);
+        let mut kept = bits_of(&data);
+        kept.sort_unstable();
+        assert_eq!(kept, original);
+
+        assert_eq!(unsafe { tova_sort_f64_external(data.as_mut_ptr(), data.len(), scratch.as_ptr(), 7) }, TOVA_EXTERNAL_SORT_ERR_ARGS);
+        assert_eq!(unsafe { tova_sort_f64_external(data.as_mut_ptr(), data.len(), std::ptr::null(), 1024) }, TOVA_EXTERNAL_SORT_ERR_ARGS);
+        let misaligned = unsafe { (data.as_mut_ptr() as *mut u8).add(1) as *mut f64 };
+        assert_eq!(unsafe { tova_sort_f64_external(misaligned, 8, scratch.as_ptr(), 1024) }, TOVA_EXTERNAL_SORT_ERR_ARGS);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // Writes to /dev/full fail the way a full scratch disk does
+        #[cfg(target_os = "linux")]
+        {
+            let full = std::fs::OpenOptions::new().write(true).open("/dev/full").unwrap();
+            let err = external_sort::write_run(full, &data).unwrap_err();
+            let code = spill_failure(external_sort::SpillError::Io("/dev/full".into(), err));
+            assert_eq!(code, TOVA_EXTERNAL_SORT_ERR_DISK_FULL);
+            assert!(last_message().contains("/dev/full"), "{}", last_message());
+        }
+    }
+
+    // --- Arrow IPC ---
+
+    // Forward-only flatbuffer builder: each table's vtable is written just
+    // before it and its children after it, so every offset points forward.
+    enum Fv {
+        U8(u8),
+        I16(i16),
+        I64(i64),
+        Bool(bool),
+        Str(&'static str),
+        Table(Vec<(usize, Fv)>),
+        Tables(Vec<Vec<(usize, Fv)>>),
+        Structs(usize, Vec<u8>),
+    }
+
+    fn fb_write_value(buf: &mut Vec<u8>, value: Fv) -> usize {
+        let pos = buf.len();
+        match value {
+            Fv::Str(s) => {
+                buf.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                buf.extend_from_slice(s.as_bytes());
+                buf.push(0);
+            }
+            Fv::Table(fields) => return fb_write_table(buf, fields),
+            Fv::Tables(tables) => {
+                buf.extend_from_slice(&(tables.len() as u32).to_le_bytes());
+                let slots = buf.len();
+                buf.resize(slots + 4 * tables.len(), 0);
+                for (i, t) in tables.into_iter().enumerate() {
+                    let child = fb_write_table(buf, t);
+                    let slot = slots + 4 * i;
+                    buf[slot..slot + 4].copy_from_slice(&((child - slot) as u32).to_le_bytes());
+                }
+            }
+            Fv::Structs(count, bytes) => {
+                buf.extend_from_slice(&(count as u32).to_le_bytes());
+                buf.extend_from_slice(&bytes);
+            }
+            _ => unreachable!("scalars are written inline"),
+        }
+        pos
+    }
+
+    fn fb_write_table(buf: &mut Vec<u8>, fields: Vec<(usize, Fv)>) -> usize {
+        let slots = fields.iter().map(|(id, _)| id + 1).max().unwrap_or(0);
+        let mut inline = vec![0u8; 4];
+        let mut vt = vec![0u16; slots];
+        let mut pending = Vec::new();
+        for (id, value) in fields {
+            vt[id] = inline.len() as u16;
+            match value {
+                Fv::U8(v) => inline.push(v),
+                Fv::Bool(v) => inline.push(v as u8),
+                Fv::I16(v) => inline.extend_from_slice(&v.to_le_bytes()),
+                Fv::I64(v) => inline.extend_from_slice(&v.to_le_bytes()),
+                child => {
+                    pending.push((inline.len(), child));
+                    inline.extend_from_slice(&[0; 4]);
+                }
+            }
+        }
+        let vt_pos = buf.len();
+        buf.extend_from_slice(&((4 + 2 * slots) as u16).to_le_bytes());
+        buf.extend_from_slice(&(inline.len() as u16).to_le_bytes());
+        for off in vt {
+            buf.extend_from_slice(&off.to_le_bytes());
+        }
+        let table_pos = buf.len();
+        inline[..4].copy_from_slice(&((table_pos - vt_pos) as i32).to_le_bytes());
+        buf.extend_from_slice(&inline);
+        for (off, child) in pending {
+            let child_pos = fb_write_value(buf, child);
+            let at = table_pos + off;
+            buf[at..at + 4].copy_from_slice(&((child_pos - at) as u32).to_le_bytes());
+        }
+        table_pos
+    }
+
+    fn fb_root(fields: Vec<(usize, Fv)>) -> Vec<u8> {
+        let mut buf = vec![0u8; 4];
+        let root = fb_write_table(&mut buf, fields);
+        buf[..4].copy_from_slice(&(root as u32).to_le_bytes());
+        buf
+    }
+
+    fn pad8(mut v: Vec<u8>) -> Vec<u8> {
+        v.resize(v.len().div_ceil(8) * 8, 0);
+        v
+    }
+
+    fn ipc_message(header_type: u8, header: Vec<(usize, Fv)>, body: Vec<u8>, legacy: bool) -> Vec<u8> {
+        let body = pad8(body);
+        let meta = pad8(fb_root(vec![
+            (0, Fv::I16(4)),
+            (1, Fv::U8(header_type)),
+            (2, Fv::Table(header)),
+            (3, Fv::I64(body.len() as i64)),
+        ]));
+        let mut out = Vec::new();
+        if !legacy {
+            out.extend_from_slice(&[0xFF; 4]);
+        }
+        out.extend_from_slice(&(meta.len() as i32).to_le_bytes());
+        out.extend_from_slice(&meta);
+        out.extend_from_slice(&body);
+        out
+    }
+
+    fn ipc_field(name: &'static str, type_type: u8, ty: Vec<(usize, Fv)>, children: Vec<Vec<(usize, Fv)>>) -> Vec<(usize, Fv)> {
+        vec![
+            (0, Fv::Str(name)),
+            (1, Fv::Bool(true)),
+            (2, Fv::U8(type_type)),
+            (3, Fv::Table(ty)),
+            (5, Fv::Tables(children)),
+        ]
+    }
+
+    /// Schema: id Int64, s Utf8, st Struct<child Int64>, x FloatingPoint(precision)
+    fn ipc_schema_table(precision: i16, big_endian: bool) -> Vec<(usize, Fv)> {
+        let fields = vec![
+            ipc_field("id", 2, vec![], vec![]),
+            ipc_field("s", 5, vec![], vec![]),
+            ipc_field("st", 13, vec![], vec![ipc_field("child", 2, vec![], vec![])]),
+            ipc_field("x", 3, vec![(0, Fv::I16(precision))], vec![]),
+        ];
+        let mut schema = vec![(1, Fv::Tables(fields))];
+        if big_endian {
+            schema.push((0, Fv::I16(1)));
+        }
+        schema
+    }
+
+    fn ipc_schema(precision: i16, big_endian: bool, legacy: bool) -> Vec<u8> {
+        ipc_message(1, ipc_schema_table(precision, big_endian), vec![], legacy)
+    }
+
+    /// Record batch where only column x (node 4, buffers 8 and 9) has data
+    fn ipc_batch(values: &[Option<f64>], compressed: bool, legacy: bool) -> Vec<u8> {
+        let n = values.len();
+        let nulls = values.iter().filter(|v| v.is_none()).count();
+        let mut validity = vec![0u8; n.div_ceil(8)];
+        let mut data = Vec::new();
+        for (i, v) in values.iter().enumerate() {
+            if v.is_some() {
+                validity[i >> 3] |= 1 << (i & 7);
+            }
+            data.extend_from_slice(&v.unwrap_or(0.0).to_le_bytes());
+        }
+        let validity = pad8(validity);
+        let mut nodes = Vec::new();
+        for i in 0..5 {
+            let (len, nc) = if i == 4 { (n, nulls) } else { (n, 0) };
+            nodes.extend_from_slice(&(len as i64).to_le_bytes());
+            nodes.extend_from_slice(&(nc as i64).to_le_bytes());
+        }
+        let mut buffers = Vec::new();
+        for i in 0..10 {
+            let (off, len) = match i {
+                8 => (0, validity.len()),
+                9 => (validity.len(), data.len()),
+                _ => (0, 0),
+            };
+            buffers.extend_from_slice(&(off as i64).to_le_bytes());
+            buffers.extend_from_slice(&(len as i64).to_le_bytes());
+        }
+        let mut batch = vec![
+            (0, Fv::I64(n as i64)),
+            (1, Fv::Structs(5, nodes)),
+            (2, Fv::Structs(10, buffers)),
+        ];
+        if compressed {
+            batch.push((3, Fv::Table(vec![(0, Fv::U8(1))])));
+        }
+        let mut body = validity;
+        body.extend_from_slice(&data);
+        ipc_message(3, batch, body, legacy)
+    }
+
+    fn ipc_stream(batches: &[&[Option<f64>]]) -> Vec<u8> {
+        let mut out = ipc_schema(2, false, false);
+        for b in batches {
+            out.extend(ipc_batch(b, false, false));
+        }
+        out.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0]);
+        out
+    }
+
+    fn arrow_sum(ipc: &[u8], column: u32) -> Result<f64, i32> {
+        let mut out = 0.0;
+        match unsafe { tova_arrow_sum_f64(ipc.as_ptr(), ipc.len(), column, &mut out) } {
+            0 => Ok(out),
+            code => Err(code),
+        }
+    }
+
+    #[test]
+    fn test_arrow_aggregates_across_batches_skipping_nulls() {
+        let ipc = ipc_stream(&[
+            &[Some(1.5), None, Some(-2.0), Some(4.0)],
+            &[None, Some(10.0), Some(0.25), None, None, Some(-3.5)],
+        ]);
+        let (mut min, mut max, mut count) = (0.0, 0.0, 0u64);
+        unsafe {
+            assert_eq!(tova_arrow_min_f64(ipc.as_ptr(), ipc.len(), 3, &mut min), 0);
+            assert_eq!(tova_arrow_max_f64(ipc.as_ptr(), ipc.len(), 3, &mut max), 0);
+            assert_eq!(tova_arrow_count_f64(ipc.as_ptr(), ipc.len(), 3, &mut count), 0);
+        }
+        assert_eq!(arrow_sum(&ipc, 3), Ok(10.25));
+        assert_eq!(min, -3.5);
+        assert_eq!(max, 10.0);
+        assert_eq!(count, 6);
+    }
+
+    #[test]
+    fn test_arrow_min_max_agree_with_the_slice_kernels() {
+        let ipc = ipc_stream(&[&[Some(f64::NAN), Some(0.0)], &[None, Some(-0.0), Some(f64::NAN)]]);
+        let (mut min, mut max) = (1.0, 1.0);
+        unsafe {
+            assert_eq!(tova_arrow_min_f64(ipc.as_ptr(), ipc.len(), 3, &mut min), 0);
+            assert_eq!(tova_arrow_max_f64(ipc.as_ptr(), ipc.len(), 3, &mut max), 0);
+        }
+        assert_eq!(min.to_bits(), (-0.0f64).to_bits());
+        assert_eq!(max.to_bits(), 0.0f64.to_bits());
+    }
+
+    #[test]
+    fn test_arrow_all_null_and_empty() {
+        let ipc = ipc_stream(&[&[None, None]]);
+        let (mut min, mut count) = (0.0, 1u64);
+        unsafe {
+            assert_eq!(tova_arrow_min_f64(ipc.as_ptr(), ipc.len(), 3, &mut min), 0);
+            assert_eq!(tova_arrow_count_f64(ipc.as_ptr(), ipc.len(), 3, &mut count), 0);
+        }
+        assert!(min.is_nan());
+        assert_eq!(count, 0);
+        assert_eq!(arrow_sum(&ipc_stream(&[]), 3), Ok(0.0));
+    }
+
+    #[test]
+    fn test_arrow_file_format_and_legacy_framing() {
+        // Padded past the first 8 bytes, as arrow-rs does; only the footer's
+        // blocks say where the batches are
+        let mut file = b"ARROW1".to_vec();
+        file.resize(64, 0);
+        file.extend(ipc_schema(2, false, false));
+        let mut blocks = Vec::new();
+        for values in [&[Some(1.0), Some(2.0)][..], &[Some(3.0)]] {
+            let batch = ipc_batch(values, false, false);
+            blocks.extend_from_slice(&(file.len() as i64).to_le_bytes());
+            // Metadata and body lengths; the reader takes them from the message
+            blocks.extend_from_slice(&[0; 16]);
+            file.extend(batch);
+        }
+        file.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0]);
+        let footer = fb_root(vec![
+            (0, Fv::I16(4)),
+            (1, Fv::Table(ipc_schema_table(2, false))),
+            (3, Fv::Structs(2, blocks)),
+        ]);
+        file.extend_from_slice(&footer);
+        file.extend_from_slice(&(footer.len() as i32).to_le_bytes());
+        file.extend_from_slice(b"ARROW1");
+        assert_eq!(arrow_sum(&file, 3), Ok(6.0));
+        assert_eq!(arrow_sum(&file, 0), Err(TOVA_ARROW_ERR_UNSUPPORTED_TYPE));
+
+        let mut legacy = ipc_schema(2, false, true);
+        legacy.extend(ipc_batch(&[Some(7.0), None, Some(1.0)], false, true));
+        legacy.extend_from_slice(&[0, 0, 0, 0]);
+        assert_eq!(arrow_sum(&legacy, 3), Ok(8.0));
+    }
+
+    #[test]
+    fn test_arrow_rejects_other_columns_and_types() {
+        let ipc = ipc_stream(&[&[Some(1.0)]]);
+        assert_eq!(arrow_sum(&ipc, 0), Err(TOVA_ARROW_ERR_UNSUPPORTED_TYPE));
+        assert_eq!(arrow_sum(&ipc, 1), Err(TOVA_ARROW_ERR_UNSUPPORTED_TYPE));
+        assert_eq!(arrow_sum(&ipc, 4), Err(TOVA_ARROW_ERR_NO_COLUMN));
+
+        let mut f32_ipc = ipc_schema(1, false, false);
+        f32_ipc.extend(ipc_batch(&[Some(1.0)], false, false));
+        assert_eq!(arrow_sum(&f32_ipc, 3), Err(TOVA_ARROW_ERR_UNSUPPORTED_TYPE));
+    }
+
+    #[test]
+    fn test_arrow_rejects_compressed_and_big_endian() {
+        let mut compressed = ipc_schema(2, false, false);
+        compressed.extend(ipc_batch(&[Some(1.0)], true, false));
+        assert_eq!(arrow_sum(&compressed, 3), Err(TOVA_ARROW_ERR_COMPRESSED));
+
+        let mut big = ipc_schema(2, true, false);
+        big.extend(ipc_batch(&[Some(1.0)], false, false));
+        assert_eq!(arrow_sum(&big, 3), Err(TOVA_ARROW_ERR_BIG_ENDIAN));
+    }
+
+    #[test]
+    fn test_arrow_malformed_input() {
+        let ipc = ipc_stream(&[&[Some(1.0), Some(2.0), None]]);
+        for cut in [0, 3, 7, 20, ipc.len() / 2, ipc.len() - 12] {
+            assert_eq!(arrow_sum(&ipc[..cut], 3), Err(TOVA_ARROW_ERR_MALFORMED), "cut at {}", cut);
+        }
+        let garbage: Vec<u8> = (0..512u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+        assert_eq!(arrow_sum(&garbage, 3), Err(TOVA_ARROW_ERR_MALFORMED));
+        // A batch before any schema has no column layout to follow
+        let orphan = ipc_batch(&[Some(1.0)], false, false);
+        assert_eq!(arrow_sum(&orphan, 3), Err(TOVA_ARROW_ERR_MALFORMED));
+        let mut out = 0.0;
+        assert_eq!(unsafe { tova_arrow_sum_f64(std::ptr::null(), 0, 3, &mut out) }, TOVA_ARROW_ERR_MALFORMED);
+    }
+
+    // Written by arrow-rs (testdata/arrow-gen): an Int64 id column, then a
+    // Float64 column split over two record batches.
+    #[test]
+    fn test_arrow_reads_arrow_rs_fixtures() {
+        let check = |ipc: &[u8], sum: f64, min: f64, max: f64, count: u64| {
+            let (mut lo, mut hi, mut n) = (0.0, 0.0, 0u64);
+            unsafe {
+                assert_eq!(tova_arrow_min_f64(ipc.as_ptr(), ipc.len(), 1, &mut lo), 0);
+                assert_eq!(tova_arrow_max_f64(ipc.as_ptr(), ipc.len(), 1, &mut hi), 0);
+                assert_eq!(tova_arrow_count_f64(ipc.as_ptr(), ipc.len(), 1, &mut n), 0);
+            }
+            assert_eq!((arrow_sum(ipc, 1), lo, hi, n), (Ok(sum), min, max, count));
+            assert_eq!(arrow_sum(ipc, 0), Err(TOVA_ARROW_ERR_UNSUPPORTED_TYPE));
+        };
+        check(include_bytes!("../testdata/arrow-f64.arrows"), 150502.5, -250.0, 999.5, 1003);
+        check(include_bytes!("../testdata/arrow-f64.arrow"), 150502.5, -250.0, 999.5, 1003);
+        // Every third value null, and a trailing all-null batch
+        check(include_bytes!("../testdata/arrow-f64-nulls.arrows"), 3267.0, 1.0, 98.0, 66);
+    }
+
+    // --- NPY ---
+
+    /// Byte-for-byte what `numpy.save` writes: magic, version, header length,
+    /// dict literal padded with spaces to a 64-byte boundary, newline, payload.
+    fn npy_fixture(major: u8, header: &str, payload: &[u8]) -> Vec<u8> {
+        let prefix = if major == 1 { 10 } else { 12 };
+        let mut dict = header.as_bytes().to_vec();
+        let total = (prefix + dict.len() + 1).div_ceil(64) * 64;
+        dict.resize(total - prefix - 1, b' ');
+        dict.push(b'\n');
+        let mut out = b"\x93NUMPY".to_vec();
+        out.extend_from_slice(&[major, 0]);
+        if major == 1 {
+            out.extend_from_slice(&(dict.len() as u16).to_le_bytes());
+        } else {
+            out.extend_from_slice(&(dict.len() as u32).to_le_bytes());
+        }
+        out.extend_from_slice(&dict);
+        out.extend_from_slice(payload);
+        out
+    }
+
+    fn npy_read(bytes: &[u8], cap: usize) -> (isize, Vec<f64>) {
+        let mut out = vec![0.0; cap];
+        let n = unsafe { tova_npy_read_f64(bytes.as_ptr(), bytes.len(), out.as_mut_ptr(), cap) };
+        (n, out)
+    }
+
+    #[test]
+    fn test_npy_f8_v1() {
+        let payload: Vec<u8> = [1.5f64, -2.0, 3.25].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let bytes = npy_fixture(1, "{'descr': '<f8', 'fortran_order': False, 'shape': (3,), }", &payload);
+        let mut info = NpyInfo::default();
+        assert_eq!(unsafe { tova_npy_parse_header(bytes.as_ptr(), bytes.len(), &mut info) }, 0);
+        assert_eq!(info, NpyInfo {
+            dtype: TOVA_NPY_DTYPE_F8,
+            byte_order: TOVA_NPY_LITTLE_ENDIAN,
+            item_size: 8,
+            element_count: 3,
+            data_offset: 128,
+        });
+        let (n, out) = npy_read(&bytes, 4);
+        assert_eq!(n, 3);
+        assert_eq!(&out[..3], &[1.5, -2.0, 3.25]);
+        assert_eq!(npy_read(&bytes, 2).0, TOVA_NPY_ERR_CAPACITY as isize);
+    }
+
+    #[test]
+    fn test_npy_i8_converts_and_v2_v3_headers() {
+        let payload: Vec<u8> = [-7i64, 0, 1 << 40].iter().flat_map(|v| v.to_le_bytes()).collect();
+        for major in [1, 2, 3] {
+            let bytes = npy_fixture(major, "{'descr': '<i8', 'fortran_order': False, 'shape': (3,), }", &payload);
+            let (n, out) = npy_read(&bytes, 3);
+            assert_eq!(n, 3, "version {}", major);
+            assert_eq!(out, vec![-7.0, 0.0, (1u64 << 40) as f64]);
+        }
+        let scalar = npy_fixture(1, "{'descr': '<i8', 'fortran_order': False, 'shape': (), }", &9i64.to_le_bytes());
+        assert_eq!(npy_read(&scalar, 1), (1, vec![9.0]));
+        let empty = npy_fixture(1, "{'descr': '<f8', 'fortran_order': False, 'shape': (0,), }", &[]);
+        assert_eq!(npy_read(&empty, 0).0, 0);
+    }
+
+    #[test]
+    fn test_npy_big_endian_reported_then_rejected() {
+        let payload: Vec<u8> = [1.0f64, 2.0].iter().flat_map(|v| v.to_be_bytes()).collect();
+        let bytes = npy_fixture(1, "{'descr': '>f8', 'fortran_order': False, 'shape': (2,), }", &payload);
+        let mut info = NpyInfo::default();
+        assert_eq!(unsafe { tova_npy_parse_header(bytes.as_ptr(), bytes.len(), &mut info) }, 0);
+        assert_eq!(info.byte_order, TOVA_NPY_BIG_ENDIAN);
+        assert_eq!(npy_read(&bytes, 2).0, TOVA_NPY_ERR_BIG_ENDIAN as isize);
+    }
+
+    #[test]
+    fn test_npy_rejections() {
+        let payload = [0u8; 32];
+        let cases = [
+            ("{'descr': '<f8', 'fortran_order': True, 'shape': (4,), }", TOVA_NPY_ERR_FORTRAN_ORDER),
+            ("{'descr': '<f8', 'fortran_order': False, 'shape': (2, 2), }", TOVA_NPY_ERR_MULTI_DIM),
+            ("{'descr': '<f4', 'fortran_order': False, 'shape': (8,), }", TOVA_NPY_ERR_UNSUPPORTED_DTYPE),
+            ("{'descr': '|u1', 'fortran_order': False, 'shape': (32,), }", TOVA_NPY_ERR_UNSUPPORTED_DTYPE),
+            ("{'descr': '<f8', 'shape': (4,), }", TOVA_NPY_ERR_MALFORMED),
+            ("{'descr': '<f8', 'fortran_order': False, 'shape': (4,)", TOVA_NPY_ERR_MALFORMED),
+            ("{'descr': '<f8', 'fortran_order': False, 'shape': (5,), }", TOVA_NPY_ERR_TRUNCATED),
+            // Past u32::MAX elements, and past the address space when usize is 32-bit
+            ("{'descr': '<f8', 'fortran_order': False, 'shape': (8589934592,), }", TOVA_NPY_ERR_TRUNCATED),
+            ("{'descr': '<f8', 'fortran_order': False, 'shape': (2305843009213693952,), }", TOVA_NPY_ERR_TRUNCATED),
+        ];
+        for (header, code) in cases {
+            assert_eq!(npy_read(&npy_fixture(1, header, &payload), 8).0, code as isize, "{}", header);
+        }
+        let mut bad_version = npy_fixture(1, "{'descr': '<f8', 'fortran_order': False, 'shape': (4,), }", &payload);
+        bad_version[6] = 4;
+        assert_eq!(npy_read(&bad_version, 8).0, TOVA_NPY_ERR_VERSION as isize);
+        assert_eq!(npy_read(b"\x93NUMPY\x01\x00\xff\x00{", 8).0, TOVA_NPY_ERR_MALFORMED as isize);
+        assert_eq!(npy_read(b"PK\x03\x04", 8).0, TOVA_NPY_ERR_MALFORMED as isize);
+    }
+
+    // --- String distance ---
+
+    fn levenshtein_reference(a: &[u8], b: &[u8]) -> u32 {
+        let mut d = vec![vec![0u32; b.len() + 1]; a.len() + 1];
+        for (i, row) in d.iter_mut().enumerate() {
+            row[0] = i as u32;
+        }
+        for (j, cell) in d[0].iter_mut().enumerate() {
+            *cell = j as u32;
+        }
+        for i in 1..=a.len() {
+            for j in 1..=b.len() {
+                let sub = d[i - 1][j - 1] + (a[i - 1] != b[j - 1]) as u32;
+                d[i][j] = sub.min(d[i - 1][j] + 1).min(d[i][j - 1] + 1);
+            }
+        }
+        d[a.len()][b.len()]
+    }
+
+    fn lev(a: &str, b: &str, max: u32) -> u32 {
+        unsafe { tova_levenshtein(a.as_ptr(), a.len(), b.as_ptr(), b.len(), max) }
+    }
+
+    #[test]
+    fn test_levenshtein_matches_reference() {
+        let words = ["", "a", "kitten", "sitting", "saturday", "sunday", "flaw", "lawn", "abcabc", "cbacba", "kitten"];
+        for a in words {
+            for b in words {
+                assert_eq!(lev(a, b, u32::MAX), levenshtein_reference(a.as_bytes(), b.as_bytes()), "{:?} {:?}", a, b);
+            }
+        }
+        assert_eq!(lev("", "", 0), 0);
+        assert_eq!(lev("same", "same", 0), 0);
+        assert_eq!(lev("", "abc", u32::MAX), 3);
+    }
+
+    #[test]
+    fn test_levenshtein_bound() {
+        assert_eq!(lev("kitten", "sitting", 3), 3);
+        assert_eq!(lev("kitten", "sitting", 2), u32::MAX);
+        assert_eq!(lev("a", "abcdefgh", 3), u32::MAX);
+        assert_eq!(lev("abcdefgh", "hgfedcba", 1), u32::MAX);
+    }
+
+    #[test]
+    fn test_levenshtein_batch() {
+        let cands = ["kitten", "", "sitting", "mitten", "kitchen sink"];
+        let mut bytes = Vec::new();
+        let mut offsets = vec![0u64];
+        for c in cands {
+            bytes.extend_from_slice(c.as_bytes());
+            offsets.push(bytes.len() as u64);
+        }
+        let q = "kitten";
+        let mut out = vec![0u32; cands.len()];
+        unsafe { tova_levenshtein_batch(q.as_ptr(), q.len(), bytes.as_ptr(), offsets.as_ptr(), cands.len(), 3, out.as_mut_ptr()) };
+        assert_eq!(out, vec![0, u32::MAX, 3, 1, u32::MAX]);
+    }
+
+    #[test]
+    fn test_hamming_u64() {
+        let a = [0u64, u64::MAX, 0b1011];
+        let b = [1u64, 0, 0b0110];
+        assert_eq!(unsafe { tova_hamming_u64(a.as_ptr(), b.as_ptr(), 3) }, 1 + 64 + 3);
+        assert_eq!(unsafe { tova_hamming_u64(a.as_ptr(), a.as_ptr(), 3) }, 0);
+    }
+
+    // --- Substring search ---
+
+    fn find_all(hay: &[u8], needle: &[u8], flags: u32) -> Vec<u64> {
+        let mut out = vec![0u64; hay.len() + 1];
+        let n = unsafe { tova_find_all(hay.as_ptr(), hay.len(), needle.as_ptr(), needle.len(), flags, out.as_mut_ptr(), out.len()) };
+        assert!(n >= 0);
+        out.truncate(n as usize);
+        out
+    }
+
+    fn find_all_reference(hay: &[u8], needle: &[u8], fold: bool, overlapping: bool) -> Vec<u64> {
+        let eq = |a: &[u8], b: &[u8]| if fold { a.eq_ignore_ascii_case(b) } else { a == b };
+        let mut out = Vec::new();
+        let mut pos = 0;
+        while pos + needle.len() <= hay.len() {
+            if eq(&hay[pos..pos + needle.len()], needle) {
+                out.push(pos as u64);
+                pos += if overlapping { 1 } else { needle.len().max(1) };
+            } else {
+                pos += 1;
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_find_all_overlapping() {
+        assert_eq!(find_all(b"aaaaa", b"aaa", TOVA_FIND_OVERLAPPING), vec![0, 1, 2]);
+        assert_eq!(find_all(b"aaaaa", b"aaa", 0), vec![0]);
+        assert_eq!(find_all(b"abababab", b"abab", TOVA_FIND_OVERLAPPING), vec![0, 2, 4]);
+    }
+
+    #[test]
+    fn test_find_edges() {
+        assert_eq!(find_all(b"abc", b"abcd", 0), Vec::<u64>::new());
+        assert_eq!(find_all(b"xx needle", b"needle", 0), vec![3]);
+        assert_eq!(find_all(b"needle", b"needle", 0), vec![0]);
+        // Empty needle matches at every offset, including the end
+        assert_eq!(find_all(b"abc", b"", 0), vec![0, 1, 2, 3]);
+        assert_eq!(unsafe { tova_find_first(b"abc".as_ptr(), 3, b"".as_ptr(), 0, 0) }, 0);
+
+        let hay = b"zz abc abc";
+        let mut out = [0u64; 1];
+        let n = unsafe { tova_find_all(hay.as_ptr(), hay.len(), b"abc".as_ptr(), 3, 0, out.as_mut_ptr(), 1) };
+        assert_eq!(n, -2);
+        assert_eq!(out, [3]);
+        assert_eq!(unsafe { tova_count_occurrences(hay.as_ptr(), hay.len(), b"abc".as_ptr(), 3, 0) }, 2);
+        assert_eq!(unsafe { tova_find_first(hay.as_ptr(), hay.len(), b"abc".as_ptr(), 3, 0) }, 3);
+        assert_eq!(unsafe { tova_find_first(hay.as_ptr(), hay.len(), b"abd".as_ptr(), 3, 0) }, -1);
+    }
+
+    #[test]
+    fn test_find_ignore_ascii_case() {
+        let log = b"ERROR disk; error net; Error: ErRoR";
+        assert_eq!(find_all(log, b"error", TOVA_FIND_IGNORE_ASCII_CASE), vec![0, 12, 23, 30]);
+        assert_eq!(find_all(log, b"error", 0), vec![12]);
+    }
+
+    #[test]
+    fn test_find_matches_reference() {
+        // Small alphabet exercises both periodic and long-period needles
+        let mut rng = Rng(0x2545F4914F6CDD1D);
+        for _ in 0..300 {
+            let hay: Vec<u8> = (0..(rng.next_u64() % 200)).map(|_| b"abAB"[(rng.next_u64() % 4) as usize]).collect();
+            let needle: Vec<u8> = (0..(1 + rng.next_u64() % 6)).map(|_| b"abAB"[(rng.next_u64() % 4) as usize]).collect();
+            for flags in 0..4 {
+                let fold = flags & TOVA_FIND_IGNORE_ASCII_CASE != 0;
+                let overlapping = flags & TOVA_FIND_OVERLAPPING != 0;
+                assert_eq!(
+                    find_all(&hay, &needle, flags),
+                    find_all_reference(&hay, &needle, fold, overlapping),
+                    "{:?} in {:?} flags {}",
+                    String::from_utf8_lossy(&needle),
+                    String::from_utf8_lossy(&hay),
+                    flags
+                );
+            }
+        }
+    }
+
+    // --- JSON number arrays ---
+
+    fn parse_json(text: &str) -> Result<Vec<f64>, usize> {
+        let mut out = vec![0.0; text.len()];
+        let r = unsafe { tova_parse_json_numbers(text.as_ptr(), text.len(), out.as_mut_ptr(), out.len()) };
+        if r <= TOVA_JSON_ERR_BASE {
+            return Err((TOVA_JSON_ERR_BASE - r) as usize);
+        }
+        assert!(r >= 0);
+        out.truncate(r as usize);
+        Ok(out)
+    }
+
+    #[test]
+    fn test_json_numbers_basic() {
+        assert_eq!(parse_json("[1.5,2,3e7, -4E-2 ,\n\t0.25e+1]"), Ok(vec![1.5, 2.0, 3e7, -0.04, 2.5]));
+        assert_eq!(parse_json("  [ ]  "), Ok(vec![]));
+        let neg_zero = parse_json("[-0, -0.0e0]").unwrap();
+        assert!(neg_zero.iter().all(|v| *v == 0.0 && v.is_sign_negative()));
+        assert_eq!(
+            parse_json("[1.7976931348623157e308, 5e-324, 2.2250738585072014e-308, 9007199254740993]"),
+            Ok(vec![f64::MAX, 5e-324, f64::MIN_POSITIVE, 9007199254740992.0])
+        );
+        assert_eq!(parse_json("[1e400, -1e400, 1e-400]"), Ok(vec![f64::INFINITY, f64::NEG_INFINITY, 0.0]));
+    }
+
+    #[test]
+    fn test_json_numbers_rejections() {
+        assert_eq!(parse_json("[1,2,]"), Err(5));
+        assert_eq!(parse_json("[1,[2]]"), Err(3));
+        assert_eq!(parse_json("[1,{\"a\":2}]"), Err(3));
+        assert_eq!(parse_json("[\"1\"]"), Err(1));
+        assert_eq!(parse_json("[01]"), Err(2));
+        assert_eq!(parse_json("[1.]"), Err(3));
+        assert_eq!(parse_json("[1e]"), Err(3));
+        assert_eq!(parse_json("[+1]"), Err(1));
+        assert_eq!(parse_json("[NaN]"), Err(1));
+        assert_eq!(parse_json("[1 2]"), Err(3));
+        assert_eq!(parse_json("[1]x"), Err(3));
+        assert_eq!(parse_json("[1"), Err(2));
+        assert_eq!(parse_json(""), Err(0));
+        assert_eq!(parse_json("{}"), Err(0));
+    }
+
+    #[test]
+    fn test_json_numbers_capacity() {
+        let text = "[1,2,3]";
+        let mut out = [0.0; 2];
+        let r = unsafe { tova_parse_json_numbers(text.as_ptr(), text.len(), out.as_mut_ptr(), out.len()) };
+        assert_eq!(r, -3);
+        assert_eq!(out, [1.0, 2.0]);
+    }
+
+    #[test]
+    fn test_json_numbers_round_trip_1m() {
+        // Rust's float formatting is shortest round-trip, the same contract as JSON.stringify
+        let mut rng = Rng(0x9E3779B97F4A7C15);
+        let values: Vec<f64> = (0..1_000_000)
+            .map(|_| f64::from_bits(rng.next_u64()))
+            .filter(|v| v.is_finite())
+            .collect();
+        let text = format!("[{}]", values.iter().map(|v| format!("{:e}", v)).collect::<Vec<_>>().join(","));
+        let parsed = parse_json(&text).unwrap();
+        assert_eq!(parsed.len(), values.len());
+        assert!(parsed.iter().zip(&values).all(|(a, b)| a.to_bits() == b.to_bits()));
+    }
+
+    // --- Decompression ---
+
+    /// Keep in sync with sample_data() in testdata/gen.py
+    fn sample_data() -> Vec<u8> {
+        let words = ["alpha", "beta", "gamma", "delta", "epsilon", "zeta", "eta"];
+        let mut out = Vec::new();
+        for i in 0..10000u64 {
+            out.extend_from_slice(format!("{},{},{}\n", i, (i * 7919) % 1000, words[(i % 7) as usize]).as_bytes());
+        }
+        let mut rng = Rng(0x2545F4914F6CDD1D);
+        for _ in 0..6000 {
+            out.push((rng.next_u64() >> 56) as u8);
+        }
+        out
+    }
+
+    type OneShot = unsafe extern "C" fn(*const u8, usize, *mut u8, usize, usize) -> isize;
+
+    fn one_shot(f: OneShot, src: &[u8], cap: usize, max_output: usize) -> Result<Vec<u8>, isize> {
+        let mut out = vec![0u8; cap];
+        let r = unsafe { f(src.as_ptr(), src.len(), out.as_mut_ptr(), cap, max_output) };
+        if r < 0 {
+            return Err(r);
+        }
+        out.truncate(r as usize);
+        Ok(out)
+    }
+
+    fn stream_all(format: u32, src: &[u8], chunk: usize) -> Result<Vec<u8>, isize> {
+        unsafe {
+            let stream = tova_decompress_create(format, u64::MAX);
+            assert!(!stream.is_null());
+            let mut out = Vec::new();
+            let mut buf = vec![0u8; 5000];
+            let mut result = Ok(());
+            for piece in src.chunks(chunk) {
+                let r = tova_decompress_feed(stream, piece.as_ptr(), piece.len());
+                if r < 0 {
+                    result = Err(r);
+                    break;
+                }
+                loop {
+                    let n = tova_decompress_read(stream, buf.as_mut_ptr(), buf.len());
+                    if n == 0 {
+                        break;
+                    }
+                    out.extend_from_slice(&buf[..n]);
+                }
+            }
+            if result.is_ok() {
+                match tova_decompress_finish(stream) {
+                    0 => {}
+                    code => result = Err(code),
+                }
+            }
+            tova_decompress_free(stream);
+            result.map(|_| out)
+        }
+    }
+
+    /// gzip member holding `data` in stored (uncompressed) DEFLATE blocks
+    fn gzip_stored(data: &[u8], block: usize) -> Vec<u8> {
+        let mut out = vec![0x1F, 0x8B, 8, 0, 0, 0, 0, 0, 0, 255];
+        let blocks: Vec<&[u8]> = if data.is_empty() { vec![&[]] } else { data.chunks(block).collect() };
+        for (i, b) in blocks.iter().enumerate() {
+            out.push((i + 1 == blocks.len()) as u8);
+            out.extend_from_slice(&(b.len() as u16).to_le_bytes());
+            out.extend_from_slice(&(!(b.len() as u16)).to_le_bytes());
+            out.extend_from_slice(b);
+        }
+        out.extend_from_slice(&decompress::gzip::crc32_update(0, data).to_le_bytes());
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out
+    }
+
+    #[test]
+    fn test_gzip_fixtures() {
+        let data = sample_data();
+        for fixture in [&include_bytes!("../testdata/sample.gz")[..], &include_bytes!("../testdata/sample-fixed.gz")[..]] {
+            assert_eq!(one_shot(tova_gzip_decompress, fixture, data.len(), usize::MAX).unwrap(), data);
+            // ISIZE answers the sizing call without decoding
+            assert_eq!(one_shot(tova_gzip_decompress, fixture, 0, usize::MAX), Err(-(data.len() as isize)));
+            assert_eq!(one_shot(tova_gzip_decompress, fixture, data.len(), data.len() - 1), Err(TOVA_DECOMPRESS_ERR_TOO_LARGE));
+            for chunk in [1021, 4096] {
+                assert_eq!(stream_all(TOVA_FORMAT_GZIP, fixture, chunk).unwrap(), data);
+            }
+        }
+    }
+
+    #[test]
+    fn test_gzip_stored_and_multi_member() {
+        assert_eq!(decompress::gzip::crc32_update(0, b"123456789"), 0xCBF4_3926);
+        let a = b"hello, stored blocks".repeat(10);
+        let stored = gzip_stored(&a, 64);
+        assert_eq!(one_shot(tova_gzip_decompress, &stored, 1000, usize::MAX).unwrap(), a);
+        assert_eq!(stream_all(TOVA_FORMAT_GZIP, &stored, 1).unwrap(), a);
+
+        let mut multi = gzip_stored(b"first,", 100);
+        multi.extend(gzip_stored(b"", 100));
+        multi.extend(gzip_stored(b"second", 100));
+        // ISIZE only describes the last member, so the sized call goes long
+        assert_eq!(one_shot(tova_gzip_decompress, &multi, 100, usize::MAX).unwrap(), b"first,second");
+        assert_eq!(one_shot(tova_gzip_decompress, &multi, 6, usize::MAX), Err(-12));
+    }
+
+    #[test]
+    fn test_gzip_lying_isize() {
+        let data = sample_data();
+        let real = include_bytes!("../testdata/sample.gz");
+        let n = real.len();
+        let with_isize = |size: u32| {
+            let mut v = real.to_vec();
+            v[n - 4..].copy_from_slice(&size.to_le_bytes());
+            v
+        };
+        // Claims less than the real output: decoding overruns the buffer, the
+        // real size is measured, and the footer mismatch is caught
+        let short = with_isize(1000);
+        assert_eq!(one_shot(tova_gzip_decompress, &short, 1000, usize::MAX), Err(TOVA_DECOMPRESS_ERR_CHECKSUM));
+        assert_eq!(one_shot(tova_gzip_decompress, &short, 1000, 5000), Err(TOVA_DECOMPRESS_ERR_TOO_LARGE));
+        // Claims more: everything fits, the footer still fails verification
+        let long = with_isize(data.len() as u32 + 10);
+        assert_eq!(one_shot(tova_gzip_decompress, &long, data.len() + 10, usize::MAX), Err(TOVA_DECOMPRESS_ERR_CHECKSUM));
+        // A claim over the hard cap is refused without decoding
+        let big = with_isize(20 << 20);
+        assert_eq!(one_shot(tova_gzip_decompress, &big, 0, 1 << 20), Err(TOVA_DECOMPRESS_ERR_TOO_LARGE));
+        // One no DEFLATE stream of this length could produce is ignored, and
+        // the output gets measured instead
+        let bomb = with_isize(u32::MAX);
+        assert_eq!(one_shot(tova_gzip_decompress, &bomb, 0, usize::MAX), Err(TOVA_DECOMPRESS_ERR_CHECKSUM));
+    }
+
+    #[test]
+    fn test_gzip_damage() {
+        let real = include_bytes!("../testdata/sample.gz");
+        let cap = sample_data().len();
+        let mut bad_crc = real.to_vec();
+        let n = bad_crc.len();
+        bad_crc[n - 8] ^= 1;
+        assert_eq!(one_shot(tova_gzip_decompress, &bad_crc, cap, usize::MAX), Err(TOVA_DECOMPRESS_ERR_CHECKSUM));
+        assert_eq!(one_shot(tova_gzip_decompress, &real[..n / 2], cap, usize::MAX), Err(TOVA_DECOMPRESS_ERR_TRUNCATED));
+        assert_eq!(stream_all(TOVA_FORMAT_GZIP, &real[..n - 3], 4096), Err(TOVA_DECOMPRESS_ERR_TRUNCATED));
+        assert_eq!(one_shot(tova_gzip_decompress, b"definitely not gzip data", cap, usize::MAX), Err(TOVA_DECOMPRESS_ERR_CORRUPT));
+        let mut bad_method = real.to_vec();
+        bad_method[2] = 9;
+        assert_eq!(one_shot(tova_gzip_decompress, &bad_method, cap, usize::MAX), Err(TOVA_DECOMPRESS_ERR_UNSUPPORTED));
+        let stream_cap = unsafe { tova_decompress_create(TOVA_FORMAT_GZIP, 100) };
+        assert_eq!(unsafe { tova_decompress_feed(stream_cap, real.as_ptr(), n) }, TOVA_DECOMPRESS_ERR_TOO_LARGE);
+        unsafe { tova_decompress_free(stream_cap) };
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_fixtures() {
+        let data = sample_data();
+        let fixtures: [&[u8]; 4] = [
+            include_bytes!("../testdata/sample.zst"),
+            include_bytes!("../testdata/sample-19.zst"),
+            include_bytes!("../testdata/sample-nosize.zst"),
+            include_bytes!("../testdata/sample-multi.zst"),
+        ];
+        for fixture in fixtures {
+            assert_eq!(one_shot(tova_zstd_decompress, fixture, data.len(), usize::MAX).unwrap(), data);
+            for chunk in [1021, 4096] {
+                assert_eq!(stream_all(TOVA_FORMAT_ZSTD, fixture, chunk).unwrap(), data);
+            }
+        }
+        // Content-size header answers the sizing call; without one the output is measured
+        assert_eq!(one_shot(tova_zstd_decompress, fixtures[0], 0, usize::MAX), Err(-(data.len() as isize)));
+        assert_eq!(one_shot(tova_zstd_decompress, fixtures[2], 10, usize::MAX), Err(-(data.len() as isize)));
+        assert_eq!(one_shot(tova_zstd_decompress, fixtures[2], 10, 1000), Err(TOVA_DECOMPRESS_ERR_TOO_LARGE));
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_damage() {
+        let real = include_bytes!("../testdata/sample.zst");
+        let cap = sample_data().len();
+        let mut bad_checksum = real.to_vec();
+        let n = bad_checksum.len();
+        bad_checksum[n - 1] ^= 0x40;
+        assert_eq!(one_shot(tova_zstd_decompress, &bad_checksum, cap, usize::MAX), Err(TOVA_DECOMPRESS_ERR_CHECKSUM));
+        assert_eq!(one_shot(tova_zstd_decompress, &real[..n - 2], cap, usize::MAX), Err(TOVA_DECOMPRESS_ERR_TRUNCATED));
+        assert_eq!(one_shot(tova_zstd_decompress, b"\x28\xb5\x2f\xfd\x24", cap, usize::MAX), Err(TOVA_DECOMPRESS_ERR_TRUNCATED));
+        assert_eq!(one_shot(tova_zstd_decompress, b"not zstd", cap, usize::MAX), Err(TOVA_DECOMPRESS_ERR_CORRUPT));
+        // Dictionary id 7 in the header
+        assert_eq!(one_shot(tova_zstd_decompress, b"\x28\xb5\x2f\xfd\x21\x07\x00\x01\x00\x00", cap, usize::MAX), Err(TOVA_DECOMPRESS_ERR_UNSUPPORTED));
+    }
+
+    // --- Filter + gather ---
+
+    fn nonzero(mask: &[u8]) -> Vec<u32> {
+        let mut out = vec![0u32; mask.len()];
+        let n = unsafe { tova_nonzero_u8(mask.as_ptr(), mask.len(), out.as_mut_ptr(), out.len()) };
+        out.truncate(n as usize);
+        out
+    }
+
+    #[test]
+    fn test_nonzero_gather_matches_reference() {
+        let mut rng = Rng(0x853C49E6748FEA9B);
+        let values: Vec<f64> = (0..1000).map(|i| i as f64 * 1.5).collect();
+        let ints: Vec<i64> = (0..1000).map(|i| -i).collect();
+        for density in [0u64, 1, 10, 50, 90, 100] {
+            for len in [0usize, 1, 7, 8, 9, 63, 1000] {
+                let mask: Vec<u8> = (0..len).map(|_| (rng.next_u64() % 100 < density) as u8 * (1 + (rng.next_u64() % 255) as u8)).collect();
+                let expected: Vec<u32> = (0..len as u32).filter(|&i| mask[i as usize] != 0).collect();
+                let idx = nonzero(&mask);
+                assert_eq!(idx, expected);
+                if density == 0 {
+                    assert!(idx.is_empty());
+                }
+                if density == 100 {
+                    assert_eq!(idx.len(), len);
+                }
+
+                let mut out = vec![0.0; idx.len()];
+                let mut out_i = vec![0i64; idx.len()];
+                unsafe {
+                    assert_eq!(tova_gather_f64(values.as_ptr(), len, idx.as_ptr(), idx.len(), out.as_mut_ptr()), idx.len() as isize);
+                    assert_eq!(tova_gather_i64(ints.as_ptr(), len, idx.as_ptr(), idx.len(), out_i.as_mut_ptr()), idx.len() as isize);
+                }
+                assert_eq!(out, expected.iter().map(|&i| values[i as usize]).collect::<Vec<_>>());
+                assert_eq!(out_i, expected.iter().map(|&i| ints[i as usize]).collect::<Vec<_>>());
+            }
+        }
+    }
+
+    // --- Dictionary columns ---
+
+    /// Dictionary encoding by hand: the sorted distinct values, with their
+    /// counts, and each value's position among them as its code.
+    fn dict_encode(values: &[i64]) -> (Vec<i64>, Vec<u64>, Vec<u32>) {
+        let mut dict = values.to_vec();
+        dict.sort_unstable();
+        let mut counts = vec![0u64; dict.len()];
+        let n = unsafe { tova_unique_counts_sorted_i64(dict.as_mut_ptr(), dict.len(), counts.as_mut_ptr()) };
+        dict.truncate(n);
+        counts.truncate(n);
+        let codes = values.iter().map(|v| dict.binary_search(v).unwrap() as u32).collect();
+        (dict, counts, codes)
+    }
+
+    #[test]
+    fn test_dict_decode_round_trips_and_counts_match() {
+        let values: Vec<i64> = (0..5000i64).map(|i| (i * 7919) % 37 - 18).collect();
+        let (dict, value_counts, codes) = dict_encode(&values);
+        assert_eq!(dict.len(), 37);
+        let mut out = vec![0i64; values.len()];
+        assert_eq!(unsafe { tova_dict_decode_i64(codes.as_ptr(), codes.len(), dict.as_ptr(), dict.len(), out.as_mut_ptr()) }, 5000);
+        assert_eq!(out, values);
+
+        let dict_f: Vec<f64> = dict.iter().map(|&v| v as f64 * 0.5).collect();
+        let mut out_f = vec![0.0; values.len()];
+        let n = unsafe { tova_dict_decode_f64(codes.as_ptr(), codes.len(), dict_f.as_ptr(), dict_f.len(), out_f.as_mut_ptr()) };
+        assert_eq!(n, 5000);
+        assert_eq!(out_f, values.iter().map(|&v| v as f64 * 0.5).collect::<Vec<_>>());
+
+        let mut counts = vec![u64::MAX; dict.len()];
+        assert_eq!(unsafe { tova_dict_take_counts(codes.as_ptr(), codes.len(), dict.len(), counts.as_mut_ptr()) }, 5000);
+        assert_eq!(counts, value_counts);
+        // Unused dictionary entries count zero
+        let mut counts = vec![u64::MAX; dict.len() + 3];
+        assert_eq!(unsafe { tova_dict_take_counts(codes.as_ptr(), codes.len(), counts.len(), counts.as_mut_ptr()) }, 5000);
+        assert_eq!(&counts[..dict.len()], &value_counts[..]);
+        assert_eq!(&counts[dict.len()..], &[0, 0, 0]);
+    }
+
+    #[test]
+    fn test_dict_masked_decode_fills_nulls() {
+        let dict = [10i64, 20, 30];
+        // Slot 2's code is garbage under a null, as Arrow allows
+        let codes = [2u32, 0, 99, 1, 2];
+        let validity = [0b1_1011u8];
+        let mut out = [0i64; 5];
+        let n = unsafe { tova_dict_decode_i64_masked(codes.as_ptr(), validity.as_ptr(), 5, dict.as_ptr(), 3, -1, out.as_mut_ptr()) };
+        assert_eq!(n, 5);
+        assert_eq!(out, [30, 10, -1, 20, 30]);
+
+        let dict_f = [0.5, 1.5, 2.5];
+        let mut out_f = [0.0; 5];
+        let n = unsafe { tova_dict_decode_f64_masked(codes.as_ptr(), validity.as_ptr(), 5, dict_f.as_ptr(), 3, out_f.as_mut_ptr()) };
+        assert_eq!(n, 5);
+        assert_eq!(bits_of(&out_f), bits_of(&[2.5, 0.5, f64::NAN, 1.5, 2.5]));
+        // A null bitmap means all valid, so the garbage code is caught
+        let n = unsafe { tova_dict_decode_f64_masked(codes.as_ptr(), std::ptr::null(), 5, dict_f.as_ptr(), 3, out_f.as_mut_ptr()) };
+        assert_eq!(n, -3);
+    }
+
+    #[test]
+    fn test_dict_rejects_codes_past_the_dictionary() {
+        let dict = [1.0, 2.0];
+        let codes = [0u32, 1, 1, 2, 5];
+        let mut out = [0.0; 5];
+        let n = unsafe { tova_dict_decode_f64(codes.as_ptr(), 5, dict.as_ptr(), 2, out.as_mut_ptr()) };
+        assert_eq!(n, -4);
+        assert_eq!(unsafe { tova_last_error() }, TOVA_INDEX_ERR_OUT_OF_RANGE);
+        assert_eq!(&out[..3], &[1.0, 2.0, 2.0]);
+        let mut ints = [0i64; 5];
+        assert_eq!(unsafe { tova_dict_decode_i64(codes.as_ptr(), 5, [7i64].as_ptr(), 1, ints.as_mut_ptr()) }, -2);
+        // An empty dictionary admits no code at all
+        assert_eq!(unsafe { tova_dict_decode_i64(codes.as_ptr(), 5, std::ptr::null(), 0, ints.as_mut_ptr()) }, -1);
+
+        let mut counts = [0u64; 2];
+        assert_eq!(unsafe { tova_dict_take_counts(codes.as_ptr(), 5, 2, counts.as_mut_ptr()) }, -4);
+        assert_eq!(counts, [1, 2]);
+        assert_eq!(unsafe { tova_dict_take_counts(codes.as_ptr(), 0, 2, counts.as_mut_ptr()) }, 0);
+        assert_eq!(counts, [0, 0]);
+    }
+
+    // --- Packed bitmaps ---
+
+    fn bit(bitmap: &[u8], i: usize) -> u8 {
+        (bitmap[i / 8] >> (i % 8)) & 1
+    }
+
+    #[test]
+    fn test_packed_bitmaps_match_byte_masks() {
+        let mut rng = Rng(0x9E3779B97F4A7C15);
+        for len in [1usize, 7, 8, 9, 63, 64, 65, 8191, 8192, 8193] {
+            let values: Vec<f64> = (0..len)
+                .map(|_| match rng.next_u64() % 20 {
+                    0 => f64::NAN,
+                    r => r as f64 - 10.0,
+                })
+                .collect();
+            let bytes = len.div_ceil(8);
+            let (mut mask_a, mut mask_b) = (vec![0u8; len], vec![0u8; len]);
+            // Start from set bits so the tail must be cleared, not left alone
+            let (mut a, mut b) = (vec![0xFFu8; bytes], vec![0xFFu8; bytes]);
+            unsafe {
+                tova_mask_from_range_f64(values.as_ptr(), len, -3.0, 4.0, mask_a.as_mut_ptr());
+                tova_mask_from_range_f64_packed(values.as_ptr(), len, -3.0, 4.0, a.as_mut_ptr());
+                tova_mask_from_range_f64(values.as_ptr(), len, 0.0, f64::INFINITY, mask_b.as_mut_ptr());
+                tova_mask_from_range_f64_packed(values.as_ptr(), len, 0.0, f64::INFINITY, b.as_mut_ptr());
+            }
+            for i in 0..len {
+                assert_eq!(mask_a[i], (-3.0..=4.0).contains(&values[i]) as u8);
+                assert_eq!(bit(&a, i), mask_a[i], "len {len} bit {i}");
+                assert_eq!(bit(&b, i), mask_b[i], "len {len} bit {i}");
+            }
+            assert_eq!(a[bytes - 1] >> 1 >> ((len - 1) % 8), 0, "len {len}: tail bits set");
+            let ones = |m: &[u8]| m.iter().filter(|&&x| x != 0).count();
+            assert_eq!(unsafe { tova_bitmap_count(a.as_ptr(), len) }, ones(&mask_a));
+
+            // Garbage in the inputs' unused bits must not leak into results
+            if len % 8 != 0 {
+                a[bytes - 1] |= !((1u8 << (len % 8)) - 1);
+                b[bytes - 1] |= !((1u8 << (len % 8)) - 1);
+            }
+            assert_eq!(unsafe { tova_bitmap_count(a.as_ptr(), len) }, ones(&mask_a));
+            let (mut and, mut or, mut not) = (vec![0xFFu8; bytes], vec![0xFFu8; bytes], vec![0xFFu8; bytes]);
+            unsafe {
+                tova_bitmap_and(a.as_ptr(), b.as_ptr(), and.as_mut_ptr(), len);
+                tova_bitmap_or(a.as_ptr(), b.as_ptr(), or.as_mut_ptr(), len);
+                tova_bitmap_not(a.as_ptr(), not.as_mut_ptr(), len);
+            }
+            for i in 0..len {
+                assert_eq!(bit(&and, i), mask_a[i] & mask_b[i]);
+                assert_eq!(bit(&or, i), mask_a[i] | mask_b[i]);
+                assert_eq!(bit(&not, i), 1 - mask_a[i]);
+            }
+            for out in [&and, &or, &not] {
+                assert_eq!(out[bytes - 1] >> 1 >> ((len - 1) % 8), 0, "len {len}: tail bits set");
+            }
+            assert_eq!(unsafe { tova_bitmap_count(not.as_ptr(), len) }, len - ones(&mask_a));
+
+            let expected: Vec<f64> = (0..len).filter(|&i| mask_a[i] != 0).map(|i| values[i]).collect();
+            let mut filtered = values.clone();
+            let kept = unsafe { tova_filter_bitmap_f64(filtered.as_mut_ptr(), a.as_ptr(), len) };
+            assert_eq!(kept, expected.len());
+            assert_eq!(filtered[..kept], expected[..]);
+        }
+    }
+
+    #[test]
+    fn test_bitmap_ops_in_place() {
+        // 10 bits: a = 0b10_1100_1010, b = 0b01_1010_0110
+        let mut a = [0b1100_1010u8, 0b10];
+        let b = [0b1010_0110u8, 0b01];
+        unsafe { tova_bitmap_and(a.as_ptr(), b.as_ptr(), a.as_mut_ptr(), 10) };
+        assert_eq!(a, [0b1000_0010, 0b00]);
+        unsafe { tova_bitmap_not(a.as_ptr(), a.as_mut_ptr(), 10) };
+        assert_eq!(a, [0b0111_1101, 0b11]);
+        unsafe { tova_bitmap_or(b.as_ptr(), a.as_ptr(), a.as_mut_ptr(), 10) };
+        assert_eq!(a, [0b1111_1111, 0b11]);
+        assert_eq!(unsafe { tova_bitmap_count(a.as_ptr(), 10) }, 10);
+        assert_eq!(unsafe { tova_bitmap_count(a.as_ptr(), 0) }, 0);
+    }
+
+    #[test]
+    fn test_nonzero_capacity_and_gather_bounds() {
+        let mask = [0u8, 1, 0, 2, 3];
+        let mut out = [0u32; 2];
+        assert_eq!(unsafe { tova_nonzero_u8(mask.as_ptr(), mask.len(), out.as_mut_ptr(), 2) }, -3);
+        assert_eq!(out, [1, 3]);
+
+        // Applying a permutation, then an out-of-range index at position 2
+        let values = [10.0, 20.0, 30.0];
+        let mut dst = [0.0; 3];
+        let perm = [2u32, 0, 1];
+        assert_eq!(unsafe { tova_gather_f64(values.as_ptr(), 3, perm.as_ptr(), 3, dst.as_mut_ptr()) }, 3);
+        assert_eq!(dst, [30.0, 10.0, 20.0]);
+        let bad = [1u32, 2, 3];
+        assert_eq!(unsafe { tova_gather_f64(values.as_ptr(), 3, bad.as_ptr(), 3, dst.as_mut_ptr()) }, -3);
+    }
+
+    // --- Scatter + permutation ---
+
+    #[test]
+    fn test_apply_argsort_permutation_equals_sort() {
+        let mut rng = Rng(0xDEADBEEFCAFEF00D);
+        let mut values: Vec<f64> = (0..5000).map(|_| (rng.next_u64() % 10_000) as f64 - 5000.5).collect();
+        let mut ids: Vec<i64> = (0..values.len() as i64).collect();
+        let mut perm: Vec<u32> = (0..values.len() as u32).collect();
+        perm.sort_by(|&a, &b| values[a as usize].total_cmp(&values[b as usize]));
+        let original_perm = perm.clone();
+        let mut sorted = values.clone();
+        sorted.sort_by(f64::total_cmp);
+
+        unsafe {
+            assert_eq!(tova_apply_permutation_inplace_f64(values.as_mut_ptr(), perm.as_mut_ptr(), values.len()), 0);
+            assert_eq!(tova_apply_permutation_inplace_i64(ids.as_mut_ptr(), perm.as_mut_ptr(), ids.len()), 0);
+        }
+        assert_eq!(values, sorted);
+        assert_eq!(perm, original_perm);
+        assert_eq!(ids, original_perm.iter().map(|&p| p as i64).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_apply_permutation_rejects_non_permutations() {
+        let mut values = [1.0, 2.0, 3.0];
+        let mut dup = [0u32, 0, 2];
+        let mut range = [0u32, 3, 1];
+        // 2 with the high bit set, which the walk borrows as its flag
+        let mut marked = [0u32, 0x8000_0002, 1];
+        unsafe {
+            assert_eq!(tova_apply_permutation_inplace_f64(values.as_mut_ptr(), dup.as_mut_ptr(), 3), TOVA_INDEX_ERR_DUPLICATE);
+            assert_eq!(tova_apply_permutation_inplace_f64(values.as_mut_ptr(), range.as_mut_ptr(), 3), TOVA_INDEX_ERR_OUT_OF_RANGE);
+            assert_eq!(tova_apply_permutation_inplace_f64(values.as_mut_ptr(), marked.as_mut_ptr(), 3), TOVA_INDEX_ERR_OUT_OF_RANGE);
+        }
+        assert_eq!(values, [1.0, 2.0, 3.0]);
+        assert_eq!(dup, [0, 0, 2]);
+        assert_eq!(range, [0, 3, 1]);
+        assert_eq!(marked, [0, 0x8000_0002, 1]);
+    }
+
+    #[test]
+    fn test_scatter_policies() {
+        let values = [1.0, 2.0, 3.0];
+        let mut out = [0.0; 4];
+        unsafe {
+            assert_eq!(tova_scatter_f64(values.as_ptr(), [3u32, 0, 2].as_ptr(), 3, out.as_mut_ptr(), 4, TOVA_SCATTER_REJECT_DUPLICATES), 0);
+            assert_eq!(out, [2.0, 0.0, 3.0, 1.0]);
+
+            let dup = [1u32, 1, 0];
+            assert_eq!(tova_scatter_f64(values.as_ptr(), dup.as_ptr(), 3, out.as_mut_ptr(), 4, TOVA_SCATTER_REJECT_DUPLICATES), TOVA_INDEX_ERR_DUPLICATE);
+            assert_eq!(out, [2.0, 0.0, 3.0, 1.0]);
+            assert_eq!(tova_scatter_f64(values.as_ptr(), dup.as_ptr(), 3, out.as_mut_ptr(), 4, TOVA_SCATTER_LAST_WINS), 0);
+            assert_eq!(out, [3.0, 2.0, 3.0, 1.0]);
+
+            assert_eq!(tova_scatter_f64(values.as_ptr(), [0u32, 4, 1].as_ptr(), 3, out.as_mut_ptr(), 4, TOVA_SCATTER_LAST_WINS), TOVA_INDEX_ERR_OUT_OF_RANGE);
+            assert_eq!(out, [3.0, 2.0, 3.0, 1.0]);
+
+            let ints = [7i64, 8];
+            let mut out_i = [0i64; 2];
+            assert_eq!(tova_scatter_i64(ints.as_ptr(), [1u32, 0].as_ptr(), 2, out_i.as_mut_ptr(), 2, TOVA_SCATTER_LAST_WINS), 0);
+            assert_eq!(out_i, [8, 7]);
+        }
+    }
+
+    // --- Byte order + record layout ---
+
+    #[test]
+    fn test_bswap_round_trips() {
+        let original: Vec<u64> = (0..100u64).map(|i| i.wrapping_mul(0x9E3779B97F4A7C15)).collect();
+        let mut v = original.clone();
+        unsafe { tova_bswap_u64(v.as_mut_ptr(), v.len()) };
+        assert_eq!(v[1], original[1].swap_bytes());
+        unsafe { tova_bswap_u64(v.as_mut_ptr(), v.len()) };
+        assert_eq!(v, original);
+
+        let mut w = [0x01020304u32, 0xAABBCCDD];
+        unsafe { tova_bswap_u32(w.as_mut_ptr(), 2) };
+        assert_eq!(w, [0x04030201, 0xDDCCBBAA]);
+        let mut h = [0x0102u16];
+        unsafe { tova_bswap_u16(h.as_mut_ptr(), 1) };
+        assert_eq!(h, [0x0201]);
+    }
+
+    #[test]
+    fn test_interleave_round_trip() {
+        let mut rng = Rng(0x1234_5678_9ABC_DEF1);
+        for record_size in [1usize, 3, 8, 13, 24] {
+            let count = 257;
+            let records: Vec<u8> = (0..record_size * count).map(|_| rng.next_u64() as u8).collect();
+            for field_size in [1usize, 2, 3, 4, 8] {
+                for field_offset in 0..=record_size.saturating_sub(field_size) {
+                    if field_size > record_size {
+                        continue;
+                    }
+                    let mut column = vec![0u8; count * field_size];
+                    let r = unsafe { tova_deinterleave(records.as_ptr(), record_size, count, field_offset, field_size, column.as_mut_ptr()) };
+                    assert_eq!(r, 0);
+                    for i in 0..count {
+                        let start = i * record_size + field_offset;
+                        assert_eq!(&column[i * field_size..(i + 1) * field_size], &records[start..start + field_size]);
+                    }
+                    let mut rebuilt = vec![0u8; records.len()];
+                    for offset in 0..record_size {
+                        let mut byte_col = vec![0u8; count];
+                        unsafe {
+                            tova_deinterleave(records.as_ptr(), record_size, count, offset, 1, byte_col.as_mut_ptr());
+                            tova_interleave(byte_col.as_ptr(), record_size, count, offset, 1, rebuilt.as_mut_ptr());
+                        }
+                    }
+                    unsafe { tova_interleave(column.as_ptr(), record_size, count, field_offset, field_size, rebuilt.as_mut_ptr()) };
+                    assert_eq!(rebuilt, records);
+                }
+            }
+        }
+        let mut out = [0u8; 8];
+        assert_eq!(unsafe { tova_deinterleave([0u8; 16].as_ptr(), 8, 2, 6, 4, out.as_mut_ptr()) }, TOVA_LAYOUT_ERR_FIELD);
+        assert_eq!(unsafe { tova_interleave([0u8; 16].as_ptr(), 8, 2, 0, 0, out.as_mut_ptr()) }, TOVA_LAYOUT_ERR_FIELD);
+    }
+
+    #[test]
+    fn test_parse_big_endian_24_byte_records() {
+        // Wire layout: u64 timestamp | f64 price | i32 qty | u32 flags, all big-endian
+        let rows = [(1_700_000_000_000u64, 101.25f64, -5i32, 0x1u32), (1_700_000_000_500, 99.5, 12, 0x80000000)];
+        let mut wire = Vec::new();
+        for (ts, price, qty, flags) in rows {
+            wire.extend_from_slice(&ts.to_be_bytes());
+            wire.extend_from_slice(&price.to_be_bytes());
+            wire.extend_from_slice(&qty.to_be_bytes());
+            wire.extend_from_slice(&flags.to_be_bytes());
+        }
+        let mut ts = [0u64; 2];
+        let mut price = [0u64; 2];
+        let mut qty = [0u32; 2];
+        unsafe {
+            assert_eq!(tova_deinterleave(wire.as_ptr(), 24, 2, 0, 8, ts.as_mut_ptr() as *mut u8), 0);
+            assert_eq!(tova_deinterleave(wire.as_ptr(), 24, 2, 8, 8, price.as_mut_ptr() as *mut u8), 0);
+            assert_eq!(tova_deinterleave(wire.as_ptr(), 24, 2, 16, 4, qty.as_mut_ptr() as *mut u8), 0);
+            tova_bswap_u64(ts.as_mut_ptr(), 2);
+            tova_bswap_u64(price.as_mut_ptr(), 2);
+            tova_bswap_u32(qty.as_mut_ptr(), 2);
+        }
+        assert_eq!(ts, [rows[0].0, rows[1].0]);
+        assert_eq!(price.map(f64::from_bits), [101.25, 99.5]);
+        assert_eq!(qty.map(|q| q as i32), [-5, 12]);
+    }
+
+    // --- Aliasing checks ---
+
+    #[test]
+    fn test_overlap_ranges() {
+        let buf = [0u64; 8];
+        let p = buf.as_ptr();
+        assert!(overlaps(p, 4, unsafe { p.add(3) }, 2));
+        assert!(!overlaps(p, 4, unsafe { p.add(4) }, 4));
+        assert!(!overlaps(p, 0, p, 4));
+        // Mixed element sizes compare byte ranges
+        assert!(overlaps(p, 1, unsafe { (p as *const u8).add(7) }, 1));
+        assert!(!overlaps(p as *const u32, 2, unsafe { p.add(1) }, 1));
+    }
+
+    #[test]
+    fn test_gather_in_place_reads_original_values() {
+        let mut values = vec![10.0, 20.0, 30.0, 40.0];
+        let indices = [3u32, 3, 0, 1];
+        let p = values.as_mut_ptr();
+        assert_eq!(unsafe { tova_gather_f64(p, 4, indices.as_ptr(), 4, p) }, 4);
+        assert_eq!(values, [40.0, 40.0, 10.0, 20.0]);
+        assert_eq!(unsafe { tova_last_error() }, TOVA_ERR_OVERLAP);
+
+        let mut out = [0.0; 4];
+        unsafe { tova_gather_f64(values.as_ptr(), 4, indices.as_ptr(), 4, out.as_mut_ptr()) };
+        assert_eq!(unsafe { tova_last_error() }, 0);
+    }
+
+    #[test]
+    fn test_overlapping_status_calls_fail_untouched() {
+        let mut buf = vec![1i64, 2, 3, 4, 5, 6];
+        let p = buf.as_mut_ptr();
+        let indices = [0u32, 1, 2];
+        let r = unsafe { tova_scatter_i64(p.add(2), indices.as_ptr(), 3, p, 4, TOVA_SCATTER_LAST_WINS) };
+        assert_eq!(r, TOVA_ERR_OVERLAP);
+        assert_eq!(buf, [1, 2, 3, 4, 5, 6]);
+        assert_eq!(unsafe { tova_last_error() }, TOVA_ERR_OVERLAP);
+
+        // perm carved out of the back half of the values range
+        let r = unsafe { tova_apply_permutation_inplace_i64(p, p.add(2) as *mut u32, 4) };
+        assert_eq!(r, TOVA_ERR_OVERLAP);
+        assert_eq!(buf, [1, 2, 3, 4, 5, 6]);
+
+        let mut records = vec![0u8; 64];
+        let r = unsafe { tova_deinterleave(records.as_ptr(), 8, 8, 0, 4, records.as_mut_ptr().add(16)) };
+        assert_eq!(r, TOVA_ERR_OVERLAP);
+        let r = unsafe { tova_interleave(records.as_ptr(), 8, 4, 0, 4, records.as_mut_ptr().add(32)) };
+        assert_eq!(r, 0);
+    }
+
+    #[test]
+    fn test_overlapping_parse_output_matches_separate_buffers() {
+        let text = b"[1.5, -2, 3e2, 4, 5, 6, 7, 8]";
+        let mut buf = vec![0f64; 8];
+        unsafe { std::ptr::copy_nonoverlapping(text.as_ptr(), buf.as_mut_ptr() as *mut u8, text.len()) };
+        let r = unsafe { tova_parse_json_numbers(buf.as_ptr() as *const u8, text.len(), buf.as_mut_ptr(), 8) };
+        assert_eq!(r, 8);
+        assert_eq!(buf, [1.5, -2.0, 300.0, 4.0, 5.0, 6.0, 7.0, 8.0]);
+        assert_eq!(unsafe { tova_last_error() }, TOVA_ERR_OVERLAP);
+
+        // Void entry point: output written over the candidate strings
+        let mut column = b"kitten sitting mitten".to_vec();
+        let offsets = [0u64, 6, 14, 21];
+        let mut expected = [0u32; 3];
+        unsafe { tova_levenshtein_batch(b"sitten".as_ptr(), 6, column.as_ptr(), offsets.as_ptr(), 3, 10, expected.as_mut_ptr()) };
+        assert_eq!(unsafe { tova_last_error() }, 0);
+        let out = column.as_mut_ptr() as *mut u32;
+        unsafe { tova_levenshtein_batch(b"sitten".as_ptr(), 6, column.as_ptr(), offsets.as_ptr(), 3, 10, out) };
+        assert_eq!(unsafe { tova_last_error() }, TOVA_ERR_OVERLAP);
+        let got: Vec<u32> = (0..3).map(|i| unsafe { out.add(i).read_unaligned() }).collect();
+        assert_eq!(got, expected);
+    }
+
+    #[test]
+    fn test_last_error_is_per_thread() {
+        let mut v = [1.0f64, 2.0];
+        let p = v.as_mut_ptr();
+        unsafe { tova_gather_f64(p, 2, [1u32, 0].as_ptr(), 2, p) };
+        assert_eq!(unsafe { tova_last_error() }, TOVA_ERR_OVERLAP);
+        assert_eq!(std::thread::spawn(|| unsafe { tova_last_error() }).join().unwrap(), 0);
+        assert_eq!(unsafe { tova_last_error() }, TOVA_ERR_OVERLAP);
+    }
+
+    // --- Library info + last error ---
+
+    #[test]
+    fn test_version_and_features() {
+        let version = unsafe { std::ffi::CStr::from_ptr(tova_version()) };
+        assert_eq!(version.to_str().unwrap(), env!("CARGO_PKG_VERSION"));
+        let features = unsafe { tova_features() };
+        assert_ne!(features & TOVA_FEATURE_GZIP, 0);
+        assert_eq!(features & TOVA_FEATURE_ZSTD != 0, cfg!(feature = "zstd"));
+    }
+
+    #[test]
+    fn test_selfcheck_report() {
+        assert_eq!(unsafe { tova_abi_version() }, TOVA_ABI_VERSION);
+        let mut buf = vec![0u8; 512];
+        assert_eq!(unsafe { tova_selfcheck(buf.as_mut_ptr(), buf.len()) }, 0);
+        assert_eq!(unsafe { tova_last_error() }, 0);
+        let report = std::ffi::CStr::from_bytes_until_nul(&buf).unwrap().to_str().unwrap().to_string();
+        assert_eq!(
+            report,
+            format!(
+                "{{\"abi\":{},\"version\":\"{}\",\"features\":{},\"checks\":{{\"sort\":true,\"sum\":true,\"hash\":true}},\"ok\":true}}",
+                TOVA_ABI_VERSION,
+                env!("CARGO_PKG_VERSION"),
+                unsafe { tova_features() }
+            )
+        );
+        // The report plus its NUL must fit
+        let mut small = vec![0xAAu8; report.len()];
+        assert_eq!(unsafe { tova_selfcheck(small.as_mut_ptr(), small.len()) }, TOVA_SELFCHECK_ERR_BUFFER);
+        assert!(small.iter().all(|&b| b == 0xAA));
+        assert!(last_message().contains(&format!("{} bytes", report.len() + 1)));
+    }
+
+    #[test]
+    fn test_symbols_list_is_double_nul_terminated() {
+        let mut names = Vec::new();
+        let mut p = unsafe { tova_symbols() };
+        loop {
+            let name = unsafe { std::ffi::CStr::from_ptr(p) };
+            if name.is_empty() {
+                break;
+            }
+            names.push(name.to_str().unwrap().to_string());
+            p = unsafe { p.add(name.to_bytes().len() + 1) };
+        }
+        assert!(names.iter().all(|n| n.starts_with("tova_")));
+        assert!(names.contains(&"tova_selfcheck".to_string()));
+        assert_eq!(names.contains(&"tova_zstd_decompress".to_string()), cfg!(feature = "zstd"));
+    }
+
+    fn last_message() -> String {
+        let len = unsafe { tova_last_error_message(std::ptr::null_mut(), 0) };
+        let mut buf = vec![0u8; len + 1];
+        assert_eq!(unsafe { tova_last_error_message(buf.as_mut_ptr(), buf.len()) }, len);
+        assert_eq!(buf[len], 0);
+        String::from_utf8(buf[..len].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_last_error_message_truncates() {
+        let text = b"[1, 2,, 3]";
+        let mut out = [0.0; 4];
+        let r = unsafe { tova_parse_json_numbers(text.as_ptr(), text.len(), out.as_mut_ptr(), 4) };
+        assert_eq!(r, TOVA_JSON_ERR_BASE - 6);
+        assert_eq!(unsafe { tova_last_error() }, TOVA_ERR_INVALID_INPUT);
+        let full = last_message();
+        assert_eq!(full, "json: unexpected input at byte 6");
+
+        let mut small = [0xFFu8; 5];
+        let len = unsafe { tova_last_error_message(small.as_mut_ptr(), small.len()) };
+        assert_eq!(len, full.len());
+        assert_eq!(&small, b"json\0");
+        let mut one = [0xFFu8; 1];
+        unsafe { tova_last_error_message(one.as_mut_ptr(), 1) };
+        assert_eq!(one, [0]);
+
+        // A successful call clears it
+        let r = unsafe { tova_parse_json_numbers(b"[1]".as_ptr(), 3, out.as_mut_ptr(), 4) };
+        assert_eq!(r, 1);
+        assert_eq!(unsafe { tova_last_error() }, 0);
+        assert_eq!(last_message(), "");
+    }
+
+    #[test]
+    fn test_parsing_kernels_record_errors() {
+        let mut info = NpyInfo { dtype: 0, byte_order: 0, item_size: 0, element_count: 0, data_offset: 0 };
+        let r = unsafe { tova_npy_parse_header(b"not npy".as_ptr(), 7, &mut info) };
+        assert_eq!(r, TOVA_NPY_ERR_MALFORMED);
+        assert_eq!(unsafe { tova_last_error() }, TOVA_NPY_ERR_MALFORMED);
+        assert!(last_message().starts_with("npy:"));
+
+        let mut out = [0u8; 16];
+        let r = unsafe { tova_gzip_decompress([0x1f, 0x8b].as_ptr(), 2, out.as_mut_ptr(), 16, 16) };
+        assert_eq!(r, TOVA_DECOMPRESS_ERR_TRUNCATED);
+        assert_eq!(unsafe { tova_last_error() }, TOVA_ERR_INVALID_INPUT);
+
+        let r = unsafe { tova_gather_f64([1.0].as_ptr(), 1, [0u32, 7].as_ptr(), 2, [0.0; 2].as_mut_ptr()) };
+        assert_eq!(r, -2);
+        assert_eq!(last_message(), "gather: index 7 at position 1 is out of range for 1 values");
+    }
+
+    #[test]
+    fn test_last_error_message_is_per_thread() {
+        unsafe { tova_parse_json_numbers(b"x".as_ptr(), 1, std::ptr::null_mut(), 0) };
+        let worker = std::thread::spawn(|| {
+            let before = (unsafe { tova_last_error() }, last_message());
+            unsafe { tova_parse_json_numbers(b"[".as_ptr(), 1, std::ptr::null_mut(), 0) };
+            (before, last_message())
+        });
+        let (before, worker_message) = worker.join().unwrap();
+        assert_eq!(before, (0, String::new()));
+        assert_eq!(worker_message, "json: unexpected input at byte 1");
+        assert_eq!(last_message(), "json: unexpected input at byte 0");
+    }
+
+    // --- Alignment ---
+
+    /// Copy `values` to byte `offset` of a fresh 8-aligned buffer, returning
+    /// the buffer (for the guard bytes around it) and the misaligned pointer.
+    fn misaligned<T: Copy>(values: &[T], offset: usize) -> (Vec<u64>, *mut T) {
+        let bytes = std::mem::size_of_val(values);
+        let mut buf = vec![u64::MAX; bytes.div_ceil(8) + 2];
+        let ptr = unsafe { (buf.as_mut_ptr() as *mut u8).add(offset) as *mut T };
+        for (i, &v) in values.iter().enumerate() {
+            unsafe { ptr.add(i).write_unaligned(v) };
+        }
+        (buf, ptr)
+    }
+
+    fn read_back<T: Copy>(ptr: *const T, len: usize) -> Vec<T> {
+        (0..len).map(|i| unsafe { ptr.add(i).read_unaligned() }).collect()
+    }
+
+    fn guards_intact(buf: &[u64], offset: usize, bytes: usize) -> bool {
+        let raw = unsafe { slice::from_raw_parts(buf.as_ptr() as *const u8, buf.len() * 8) };
+        raw[..offset].iter().chain(&raw[offset + bytes..]).all(|&b| b == 0xFF)
+    }
+
+    #[test]
+    fn test_misaligned_sorts_and_sums() {
+        let mut rng = Rng(0x0DDB_1A5E_5BAD_5EED);
+        // Past RADIX_SORT_MIN so the radix path sees misaligned input too
+        let sizes: &[usize] = if cfg!(miri) { &[1, 9, 64] } else { &[1, 9, 1000, 20_000] };
+        for &n in sizes {
+            let floats: Vec<f64> = (0..n).map(|_| (rng.next_u64() % 2001) as f64 / 8.0 - 125.0).collect();
+            let ints: Vec<i64> = (0..n).map(|_| rng.next_u64() as i64 >> 20).collect();
+            let mut sorted_floats = floats.clone();
+            sorted_floats.sort_by(f64::total_cmp);
+            let mut sorted_ints = ints.clone();
+            sorted_ints.sort();
+            for offset in 1..8 {
+                let (buf, ptr) = misaligned(&floats, offset);
+                unsafe {
+                    assert_eq!(tova_sum_f64(ptr, n), kernels::sum_f64(&floats));
+                    assert_eq!(tova_min_f64(ptr, n), kernels::min_f64(&floats));
+                    assert_eq!(tova_sum_f64_parallel(ptr, n, 3), kernels::sum_f64_parallel(&floats, 3));
+                    tova_sort_f64(ptr, n);
+                }
+                assert_eq!(read_back(ptr, n), sorted_floats, "n {n} offset {offset}");
+                assert!(guards_intact(&buf, offset, n * 8));
+
+                let (buf, ptr) = misaligned(&ints, offset);
+                unsafe { tova_sort_i64(ptr, n) };
+                assert_eq!(read_back(ptr, n), sorted_ints, "n {n} offset {offset}");
+                assert!(guards_intact(&buf, offset, n * 8));
+            }
+        }
+    }
+
+    #[test]
+    fn test_misaligned_outputs_and_scalars() {
+        let values = [4.0, -1.0, 2.5, 8.0, 0.0];
+        let indices = [3u32, 0, 0, 2];
+        for offset in 1..8 {
+            let (_values_buf, values_ptr) = misaligned(&values, offset);
+            let (_indices_buf, indices_ptr) = misaligned(&indices, 8 - offset);
+            let (out_buf, out) = misaligned(&[0.0f64; 4], offset);
+            assert_eq!(unsafe { tova_gather_f64(values_ptr, 5, indices_ptr, 4, out) }, 4);
+            assert_eq!(read_back(out, 4), [8.0, 4.0, 4.0, 2.5]);
+            assert!(guards_intact(&out_buf, offset, 32));
+
+            let (_min_buf, min) = misaligned(&[0.0f64], offset);
+            let (_max_buf, max) = misaligned(&[0.0f64], 8 - offset);
+            unsafe { tova_minmax_f64_parallel(values_ptr, 5, 2, min, max) };
+            assert_eq!((read_back(min, 1)[0], read_back(max, 1)[0]), (-1.0, 8.0));
+
+            let (_counts_buf, counts) = misaligned(&[0u64; 3], offset);
+            let (_keys_buf, keys) = misaligned(&[1i64, 1, 2, 5, 5], 8 - offset);
+            assert_eq!(unsafe { tova_unique_counts_sorted_i64(keys, 5, counts) }, 3);
+            assert_eq!(read_back(keys, 3), [1, 2, 5]);
+            assert_eq!(read_back(counts, 3), [2, 1, 2]);
+        }
+    }
+
+    #[test]
+    fn test_byte_ordered_fields() {
+        // Records: u16 tag | u32 value | f64 price, big-endian, 14 bytes each
+        let rows = [(7u16, 0xDEAD_BEEFu32, -2.75f64), (9, 42, 1e300)];
+        let mut wire = Vec::new();
+        for (tag, value, price) in rows {
+            wire.extend_from_slice(&tag.to_be_bytes());
+            wire.extend_from_slice(&value.to_be_bytes());
+            wire.extend_from_slice(&price.to_be_bytes());
+        }
+        let (mut tags, mut values, mut prices) = ([0u16; 2], [0u32; 2], [0f64; 2]);
+        unsafe {
+            assert_eq!(tova_deinterleave_be(wire.as_ptr(), 14, 2, 0, 2, tags.as_mut_ptr() as *mut u8), 0);
+            assert_eq!(tova_deinterleave_be(wire.as_ptr(), 14, 2, 2, 4, values.as_mut_ptr() as *mut u8), 0);
+            assert_eq!(tova_deinterleave_be(wire.as_ptr(), 14, 2, 6, 8, prices.as_mut_ptr() as *mut u8), 0);
+        }
+        assert_eq!(tags, [7, 9]);
+        assert_eq!(values, [0xDEAD_BEEF, 42]);
+        assert_eq!(prices, [-2.75, 1e300]);
+
+        // Re-encode little-endian and read it back both ways
+        let mut little = vec![0u8; wire.len()];
+        unsafe {
+            assert_eq!(tova_interleave_le(tags.as_ptr() as *const u8, 14, 2, 0, 2, little.as_mut_ptr()), 0);
+            assert_eq!(tova_interleave_le(values.as_ptr() as *const u8, 14, 2, 2, 4, little.as_mut_ptr()), 0);
+            assert_eq!(tova_interleave_le(prices.as_ptr() as *const u8, 14, 2, 6, 8, little.as_mut_ptr()), 0);
+        }
+        assert_eq!(&little[14..16], &9u16.to_le_bytes());
+        assert_eq!(&little[20..28], &1e300f64.to_le_bytes());
+        let mut round_trip = [0f64; 2];
+        unsafe { tova_deinterleave_le(little.as_ptr(), 14, 2, 6, 8, round_trip.as_mut_ptr() as *mut u8) };
+        assert_eq!(round_trip, prices);
+        let mut rebuilt = vec![0u8; wire.len()];
+        unsafe {
+            tova_interleave_be(tags.as_ptr() as *const u8, 14, 2, 0, 2, rebuilt.as_mut_ptr());
+            tova_interleave_be(values.as_ptr() as *const u8, 14, 2, 2, 4, rebuilt.as_mut_ptr());
+            tova_interleave_be(prices.as_ptr() as *const u8, 14, 2, 6, 8, rebuilt.as_mut_ptr());
+        }
+        assert_eq!(rebuilt, wire);
+
+        let mut out = [0u8; 6];
+        assert_eq!(unsafe { tova_deinterleave_be(wire.as_ptr(), 14, 2, 0, 3, out.as_mut_ptr()) }, TOVA_LAYOUT_ERR_WIDTH);
+        assert_eq!(unsafe { tova_interleave_le(out.as_ptr(), 14, 2, 12, 4, wire.as_mut_ptr()) }, TOVA_LAYOUT_ERR_FIELD);
+    }
+
+    // --- Rank + percentile ---
+
+    fn percentile_naive(sorted: &[f64], q: f64) -> f64 {
+        if q.is_nan() || sorted.is_empty() {
+            return f64::NAN;
+        }
+        let below = sorted.iter().filter(|&&x| x < q).count() as f64;
+        let equal = sorted.iter().filter(|&&x| x == q).count() as f64;
+        100.0 * (below + equal / 2.0) / sorted.len() as f64
+    }
+
+    fn percentiles(sorted: &[f64], queries: &[f64]) -> Vec<f64> {
+        let mut out = vec![0.0; queries.len()];
+        unsafe { tova_percentile_of_f64(sorted.as_ptr(), sorted.len(), queries.as_ptr(), queries.len(), out.as_mut_ptr()) };
+        out
+    }
+
+    #[test]
+    fn test_rank_f64() {
+        let sorted = [1.0, 2.0, 2.0, 2.0, 5.0];
+        let rank = |v: f64| unsafe { tova_rank_f64(sorted.as_ptr(), sorted.len(), v) };
+        assert_eq!(rank(0.5), 0);
+        assert_eq!(rank(1.0), 1);
+        assert_eq!(rank(2.0), 4);
+        assert_eq!(rank(4.9), 4);
+        assert_eq!(rank(5.0), 5);
+        assert_eq!(rank(f64::INFINITY), 5);
+        assert_eq!(rank(f64::NAN), 0);
+        assert_eq!(unsafe { tova_rank_f64(std::ptr::null(), 0, 1.0) }, 0);
+    }
+
+    #[test]
+    fn test_percentile_edges() {
+        let same = [3.0; 10];
+        assert_eq!(percentiles(&same, &[3.0, 2.0, 4.0]), [50.0, 0.0, 100.0]);
+        let sorted = [1.0, 2.0, 3.0, 4.0];
+        let out = percentiles(&sorted, &[f64::NEG_INFINITY, 0.0, 1.0, 2.5, 4.0, 9.0, f64::NAN]);
+        assert_eq!(out[..6], [0.0, 0.0, 12.5, 50.0, 87.5, 100.0]);
+        assert!(out[6].is_nan());
+        assert!(percentiles(&[], &[1.0])[0].is_nan());
+    }
+
+    #[test]
+    fn test_percentile_matches_naive_sorted_and_unsorted_queries() {
+        let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+        let mut next = || (rng.next_u64() % 200) as f64 / 4.0;
+        let mut sorted: Vec<f64> = (0..500).map(|_| next()).collect();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let mut queries: Vec<f64> = (0..300).map(|_| next() - 2.0).collect();
+        let expected: Vec<f64> = queries.iter().map(|&q| percentile_naive(&sorted, q)).collect();
+        assert_eq!(percentiles(&sorted, &queries), expected);
+
+        queries.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        queries.insert(150, f64::NAN);
+        let out = percentiles(&sorted, &queries);
+        for (&q, &p) in queries.iter().zip(&out) {
+            if q.is_nan() {
+                assert!(p.is_nan());
+            } else {
+                assert_eq!(p, percentile_naive(&sorted, q));
+            }
+        }
+    }
+
+    fn quantile_edges(values: &[f64], k: usize, flags: u32) -> (usize, Vec<f64>) {
+        let mut edges = vec![0.0; k - 1];
+        let distinct = unsafe { tova_quantile_edges_f64(values.as_ptr(), values.len(), k, flags, edges.as_mut_ptr()) };
+        (distinct, edges)
+    }
+
+    /// Bucket populations for edges, bucket i holding [edges[i-1], edges[i]).
+    fn populations(values: &[f64], edges: &[f64]) -> Vec<usize> {
+        let mut counts = vec![0; edges.len() + 1];
+        for &x in values.iter().filter(|x| !x.is_nan()) {
+            counts[edges.partition_point(|&e| e <= x)] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn test_quantile_edges_balance_buckets() {
+        let mut rng = Rng(0x2545F4914F6CDD1D);
+        let mut values: Vec<f64> = (0..100_000).map(|_| rng.unit() * 1000.0 - 500.0).collect();
+        values[17] = f64::NAN;
+        for k in [2, 7, 10, 64] {
+            let (distinct, edges) = quantile_edges(&values, k, 0);
+            assert_eq!(distinct, k - 1);
+            assert!(edges.windows(2).all(|w| w[0] < w[1]));
+            let share = (values.len() - 1) as f64 / k as f64;
+            for count in populations(&values, &edges) {
+                assert!((count as f64 - share).abs() <= 1.0, "k {k}: {count} vs {share}");
+            }
+        }
+
+        // Past the sampling threshold the edges are close; exact ones are exact
+        let big: Vec<f64> = (0..kernels::QUANTILE_SAMPLE_MIN + 1).map(|_| rng.unit()).collect();
+        for (flags, tolerance) in [(0, 0.1), (TOVA_QUANTILE_EXACT, 1.0 / 100_000.0)] {
+            let (distinct, edges) = quantile_edges(&big, 10, flags);
+            assert_eq!(distinct, 9);
+            let share = big.len() as f64 / 10.0;
+            for count in populations(&big, &edges) {
+                assert!((count as f64 - share).abs() <= share * tolerance, "flags {flags}: {count} vs {share}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_quantile_edges_with_ties() {
+        assert_eq!(quantile_edges(&[3.5; 100], 5, 0), (1, vec![3.5; 4]));
+        assert_eq!(populations(&[3.5; 100], &[3.5; 4]), [0, 0, 0, 0, 100]);
+
+        // Most values tied at 0: the collapsed edges come first, padded with the max
+        let mut values = vec![0.0; 80];
+        values.extend((1..=20).map(f64::from));
+        assert_eq!(quantile_edges(&values, 4, 0), (1, vec![0.0, 20.0, 20.0]));
+        assert_eq!(quantile_edges(&values, 10, 0), (3, vec![0.0, 1.0, 11.0, 20.0, 20.0, 20.0, 20.0, 20.0, 20.0]));
+
+        let (distinct, edges) = quantile_edges(&[f64::NAN; 3], 3, 0);
+        assert_eq!(distinct, 0);
+        assert!(edges.iter().all(|e| e.is_nan()));
+        assert_eq!(quantile_edges(&[], 2, 0).0, 0);
+        assert_eq!(unsafe { tova_quantile_edges_f64(values.as_ptr(), values.len(), 1, 0, std::ptr::null_mut()) }, 0);
+
+        // Edges written over the input they were computed from
+        let mut data: Vec<f64> = (0..10).rev().map(f64::from).collect();
+        let n = unsafe { tova_quantile_edges_f64(data.as_ptr(), 10, 5, 0, data.as_mut_ptr()) };
+        assert_eq!((n, &data[..4]), (4, &[2.0, 4.0, 6.0, 8.0][..]));
+    }
+
+    /// Run a quantile filter over a copy of `values` with ids 0.. alongside;
+    /// returns the kept values, their ids and the thresholds.
+    fn filter_quantile(values: &[f64], filter: kernels::QuantileFilter, flags: u32) -> (Vec<f64>, Vec<i64>, [f64; 2]) {
+        let (mut values, mut ids) = (values.to_vec(), (0..values.len() as i64).collect::<Vec<_>>());
+        let mut thresholds = [f64::NAN; 2];
+        let (v, c, t, len) = (values.as_mut_ptr(), ids.as_mut_ptr(), thresholds.as_mut_ptr(), values.len());
+        let kept = unsafe {
+            match filter {
+                kernels::QuantileFilter::Above(q) => tova_filter_above_quantile_f64(v, len, q, flags, c, t),
+                kernels::QuantileFilter::Below(q) => tova_filter_below_quantile_f64(v, len, q, flags, c, t),
+                kernels::QuantileFilter::Between(lo, hi) => tova_filter_between_quantiles_f64(v, len, lo, hi, flags, c, t),
+            }
+        };
+        assert_ne!(kept, usize::MAX, "{:?}", filter);
+        values.truncate(kept);
+        ids.truncate(kept);
+        (values, ids, thresholds)
+    }
+
+    #[test]
+    fn test_quantile_filters_keep_the_expected_fraction() {
+        let mut rng = Rng(0x9E3779B97F4A7C15);
+        let mut values: Vec<f64> = (0..100_003).map(|_| (rng.next_u64() >> 11) as f64).collect();
+        values[40] = f64::NAN;
+        let n = (values.len() - 1) as f64;
+        use kernels::QuantileFilter::{Above, Below, Between};
+        for (filter, share) in [
+            (Above(0.95), 0.05),
+            (Above(0.5), 0.5),
+            (Above(0.0), 1.0),
+            (Below(0.95), 0.95),
+            (Below(0.01), 0.01),
+            (Between(0.25, 0.75), 0.5),
+            (Between(0.1, 0.1), 0.0),
+        ] {
+            let (kept, ids, [lo, hi]) = filter_quantile(&values, filter, TOVA_QUANTILE_EXACT);
+            assert!((kept.len() as f64 - n * share).abs() <= 1.0, "{:?}: kept {} of {}", filter, kept.len(), n);
+            // In order, and each id still names its value
+            assert!(ids.windows(2).all(|w| w[0] < w[1]));
+            assert!(kept.iter().zip(&ids).all(|(&v, &id)| v.to_bits() == values[id as usize].to_bits()));
+            let passes = |x: f64| match filter {
+                Above(_) => x > lo,
+                Below(_) => x < lo,
+                Between(..) => lo <= x && x < hi,
+            };
+            assert_eq!(kept.len(), values.iter().filter(|&&x| passes(x)).count(), "{:?}", filter);
+        }
+
+        // Sampled past QUANTILE_SAMPLE_MIN, close to the asked-for share
+        let big: Vec<f64> = (0..kernels::QUANTILE_SAMPLE_MIN + 1).map(|i| i as f64).collect();
+        let (kept, _, [threshold, _]) = filter_quantile(&big, Above(0.9), 0);
+        let share = kept.len() as f64 / big.len() as f64;
+        assert!((share - 0.1).abs() < 0.01, "{} above {}", share, threshold);
+    }
+
+    #[test]
+    fn test_quantile_filter_edge_cases() {
+        use kernels::QuantileFilter::{Above, Below};
+        let (kept, _, [threshold, _]) = filter_quantile(&[f64::NAN; 4], Above(0.5), 0);
+        assert!(kept.is_empty() && threshold.is_nan());
+        // Ties sit at the threshold, neither above nor below it
+        assert_eq!(filter_quantile(&[2.0, 1.0, 2.0, 2.0, 3.0], Above(0.5), 0).0, vec![3.0]);
+        assert_eq!(filter_quantile(&[2.0, 1.0, 2.0, 2.0, 3.0], Below(0.5), 0).0, vec![1.0]);
+
+        let mut values = vec![5.0, 1.0, 4.0, 2.0, 3.0];
+        let mut threshold = 0.0;
+        unsafe {
+            let v = values.as_mut_ptr();
+            assert_eq!(tova_filter_above_quantile_f64(v, 5, 0.4, 0, std::ptr::null_mut(), &mut threshold), 2);
+            assert_eq!((&values[..2], threshold), (&[5.0, 4.0][..], 3.0));
+            assert_eq!(tova_filter_below_quantile_f64(v, 2, 1.0, 0, std::ptr::null_mut(), std::ptr::null_mut()), 1);
+            assert_eq!(values[0], 4.0);
+
+            for q in [-0.1, 1.5, f64::NAN] {
+                assert_eq!(tova_filter_above_quantile_f64(v, 5, q, 0, std::ptr::null_mut(), std::ptr::null_mut()), usize::MAX);
+                assert_eq!(tova_last_error(), TOVA_ERR_INVALID_INPUT);
+            }
+            let t = std::ptr::null_mut();
+            assert_eq!(tova_filter_between_quantiles_f64(v, 5, 0.8, 0.2, 0, std::ptr::null_mut(), t), usize::MAX);
+            assert_eq!(tova_last_error(), TOVA_ERR_INVALID_INPUT);
+            // A companion over the values themselves is refused untouched
+            let before = values.clone();
+            assert_eq!(tova_filter_above_quantile_f64(v, 4, 0.5, 0, v.add(1).cast(), t), usize::MAX);
+            assert_eq!(tova_last_error(), TOVA_ERR_OVERLAP);
+            assert_eq!(values, before);
+        }
+    }
+
+    // --- Column profile ---
+
+    fn profile_f64(values: &[f64], validity: Option<&[u8]>, flags: u32) -> [f64; TOVA_PROFILE_LEN] {
+        let mut out = [0.0; TOVA_PROFILE_LEN];
+        let bits = validity.map_or(std::ptr::null(), |v| v.as_ptr());
+        assert_eq!(unsafe { tova_profile_f64(values.as_ptr(), bits, values.len(), flags, out.as_mut_ptr()) }, 0);
+        out
+    }
+
+    /// Every statistic recomputed the slow way: a sort for min, max and
+    /// quartiles, two passes for stddev, a full count for the top values.
+    fn assert_profile_matches(out: &[f64; TOVA_PROFILE_LEN], counted: &[f64], nulls: usize, nans: usize) {
+        let mut sorted = counted.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let n = sorted.len();
+        assert_eq!(out[TOVA_PROFILE_COUNT], n as f64);
+        assert_eq!(out[TOVA_PROFILE_NULLS], nulls as f64);
+        assert_eq!(out[TOVA_PROFILE_NANS], nans as f64);
+        assert_eq!((out[TOVA_PROFILE_MIN], out[TOVA_PROFILE_MAX]), (sorted[0], sorted[n - 1]));
+        let mean = sorted.iter().sum::<f64>() / n as f64;
+        let var = sorted.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / (n - 1) as f64;
+        assert!((out[TOVA_PROFILE_MEAN] - mean).abs() <= 1e-9 * mean.abs().max(1.0));
+        assert!((out[TOVA_PROFILE_STDDEV] - var.sqrt()).abs() <= 1e-9 * var.sqrt());
+        for (slot, q) in [(TOVA_PROFILE_P25, 0.25), (TOVA_PROFILE_P50, 0.5), (TOVA_PROFILE_P75, 0.75)] {
+            assert_eq!(out[slot], sorted[(q * (n - 1) as f64) as usize], "q {q}");
+        }
+
+        let mut counts = std::collections::HashMap::new();
+        for v in &sorted {
+            *counts.entry((v + 0.0).to_bits()).or_insert(0u64) += 1;
+        }
+        let distinct = counts.len() as f64;
+        assert!((out[TOVA_PROFILE_DISTINCT] - distinct).abs() <= distinct * 0.05, "{} vs {distinct}", out[TOVA_PROFILE_DISTINCT]);
+        let mut top: Vec<(f64, u64)> = counts.into_iter().map(|(bits, count)| (f64::from_bits(bits), count)).collect();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.total_cmp(&b.0)));
+        top.truncate(5);
+        assert_eq!(out[TOVA_PROFILE_TOP_COUNT], top.len() as f64);
+        for (i, (value, count)) in top.into_iter().enumerate() {
+            assert_eq!((out[TOVA_PROFILE_TOP_VALUES + i], out[TOVA_PROFILE_TOP_COUNTS + i]), (value, count as f64), "top {i}");
+        }
+    }
+
+    #[test]
+    fn test_profile_matches_reference_with_nulls_and_ties() {
+        // Five heavy values (-0.0 and 0.0 count as one), a long tail of
+        // singletons, NaNs, and every seventh value null
+        let n = 20_000;
+        let mut values = Vec::with_capacity(n);
+        let mut validity = vec![0u8; kernels::bitmap_bytes(n)];
+        let (mut counted, mut nulls, mut nans) = (Vec::new(), 0, 0);
+        for i in 0..n {
+            let v = match i % 10 {
+                0..=2 => 7.0,
+                3..=4 => -2.5,
+                5 => if i % 20 == 5 { -0.0 } else { 0.0 },
+                6 => 100.0 + (i % 3) as f64,
+                7 if i % 70 == 7 => f64::NAN,
+                _ => i as f64 * 0.5,
+            };
+            values.push(v);
+            if i % 7 == 3 {
+                nulls += 1;
+            } else {
+                validity[i / 8] |= 1 << (i % 8);
+                if v.is_nan() {
+                    nans += 1;
+                } else {
+                    counted.push(v);
+                }
+            }
+        }
+        let out = profile_f64(&values, Some(&validity), TOVA_PROFILE_QUARTILES);
+        assert_profile_matches(&out, &counted, nulls, nans);
+        assert_eq!(out[TOVA_PROFILE_TOP_VALUES..TOVA_PROFILE_TOP_VALUES + 3], [7.0, -2.5, 0.0]);
+
+        // One pass: same statistics, no quartiles, top counts never under
+        let one = profile_f64(&values, Some(&validity), 0);
+        assert!(one[TOVA_PROFILE_P25..=TOVA_PROFILE_P75].iter().all(|q| q.is_nan()));
+        assert_eq!(one[..TOVA_PROFILE_P25], out[..TOVA_PROFILE_P25]);
+        assert_eq!(one[TOVA_PROFILE_TOP_VALUES..TOVA_PROFILE_TOP_VALUES + 3], [7.0, -2.5, 0.0]);
+        for i in 0..5 {
+            assert!(one[TOVA_PROFILE_TOP_COUNTS + i] >= out[TOVA_PROFILE_TOP_COUNTS + i]);
+        }
+
+        // No bitmap: everything valid
+        let all: Vec<f64> = values.iter().copied().filter(|v| !v.is_nan()).collect();
+        let out = profile_f64(&values, None, TOVA_PROFILE_QUARTILES);
+        assert_profile_matches(&out, &all, 0, values.len() - all.len());
+    }
+
+    #[test]
+    fn test_profile_i64_and_edge_cases() {
+        let values: Vec<i64> = (0..5000)
+            .map(|i| match i % 16 {
+                0..=3 => -3,
+                4..=6 => 40,
+                7 | 8 => 1 << 40,
+                9 => 12,
+                10 => -7,
+                _ => i,
+            })
+            .collect();
+        let mut out = [0.0; TOVA_PROFILE_LEN];
+        assert_eq!(unsafe { tova_profile_i64(values.as_ptr(), std::ptr::null(), values.len(), TOVA_PROFILE_QUARTILES, out.as_mut_ptr()) }, 0);
+        let as_f64: Vec<f64> = values.iter().map(|&v| v as f64).collect();
+        assert_profile_matches(&out, &as_f64, 0, 0);
+        // Same as profiling the values as f64, bar the distinct estimate:
+        // the two hash different bits
+        let mut expected = profile_f64(&as_f64, None, TOVA_PROFILE_QUARTILES);
+        expected[TOVA_PROFILE_DISTINCT] = out[TOVA_PROFILE_DISTINCT];
+        assert_eq!(out, expected);
+
+        // Nothing counted: zero counts, NaN statistics, no top values
+        let out = profile_f64(&[f64::NAN, 1.0], Some(&[0b01]), TOVA_PROFILE_QUARTILES);
+        assert_eq!(out[..=TOVA_PROFILE_DISTINCT], [0.0, 1.0, 1.0, 0.0]);
+        assert!(out[TOVA_PROFILE_MIN..TOVA_PROFILE_TOP_COUNT].iter().all(|v| v.is_nan()));
+        assert_eq!(out[TOVA_PROFILE_TOP_COUNT], 0.0);
+        let mut out = profile_f64(&[4.0], None, TOVA_PROFILE_QUARTILES);
+        assert_eq!((out[TOVA_PROFILE_MEAN], out[TOVA_PROFILE_P50]), (4.0, 4.0));
+        assert!(out[TOVA_PROFILE_STDDEV].is_nan());
+
+        assert_eq!(unsafe { tova_profile_f64(std::ptr::null(), std::ptr::null(), 0, 0, out.as_mut_ptr()) }, 0);
+        assert_eq!(unsafe { tova_profile_f64([1.0].as_ptr(), std::ptr::null(), 1, 0, std::ptr::null_mut()) }, TOVA_PROFILE_ERR_NULL);
+        assert_eq!(unsafe { tova_last_error() }, TOVA_PROFILE_ERR_NULL);
+        assert_eq!(unsafe { tova_profile_i64(std::ptr::null(), std::ptr::null(), 3, 0, out.as_mut_ptr()) }, TOVA_PROFILE_ERR_NULL);
+    }
+
+    // --- Pair packing ---
+
+    #[test]
+    fn test_pack_sort_unpack_orders_by_pair() {
+        let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+        // Small range so both halves repeat and cross zero
+        let mut next = || (rng.next_u64() % 7) as i32 - 3;
+        let mut hi: Vec<i32> = (0..400).map(|_| next()).collect();
+        let mut lo: Vec<i32> = (0..400).map(|_| next() * 1000).collect();
+        hi.extend([i32::MIN, i32::MAX, -1, 0]);
+        lo.extend([i32::MAX, i32::MIN, i32::MIN, -1]);
+        let n = hi.len();
+        let mut expected: Vec<(i32, i32)> = hi.iter().copied().zip(lo.iter().copied()).collect();
+        expected.sort();
+
+        let mut packed = vec![0i64; n];
+        let (mut out_hi, mut out_lo) = (vec![0i32; n], vec![0i32; n]);
+        unsafe {
+            tova_pack_i32_pairs(hi.as_ptr(), lo.as_ptr(), n, packed.as_mut_ptr(), true);
+            tova_sort_i64(packed.as_mut_ptr(), n);
+            tova_unpack_i32_pairs(packed.as_ptr(), n, out_hi.as_mut_ptr(), out_lo.as_mut_ptr(), true);
+        }
+        let got: Vec<(i32, i32)> = out_hi.into_iter().zip(out_lo).collect();
+        assert_eq!(got, expected);
+    }
+
+    #[test]
+    fn test_plain_pack_round_trips_raw_bits() {
+        let hi = [-1, 0, 7, i32::MIN];
+        let lo = [-1, -2, 0, i32::MAX];
+        let mut packed = [0i64; 4];
+        let (mut out_hi, mut out_lo) = ([0i32; 4], [0i32; 4]);
+        unsafe {
+            tova_pack_i32_pairs(hi.as_ptr(), lo.as_ptr(), 4, packed.as_mut_ptr(), false);
+            tova_unpack_i32_pairs(packed.as_ptr(), 4, out_hi.as_mut_ptr(), out_lo.as_mut_ptr(), false);
+        }
+        assert_eq!(packed[0], -1);
+        assert_eq!(packed[1], 0xFFFF_FFFE);
+        assert_eq!((out_hi, out_lo), (hi, lo));
+
+        // Unpacking over the source buffer still sees the original keys
+        let mut buf = packed;
+        let p = buf.as_mut_ptr() as *mut i32;
+        unsafe { tova_unpack_i32_pairs(buf.as_ptr(), 4, p, p.add(4), false) };
+        assert_eq!(unsafe { tova_last_error() }, TOVA_ERR_OVERLAP);
+        let halves: Vec<i32> = (0..8).map(|i| unsafe { *p.add(i) }).collect();
+        assert_eq!(halves, [-1, 0, 7, i32::MIN, -1, -2, 0, i32::MAX]);
+    }
+
+    // --- Weighted aggregates ---
+
+    /// Double-double reference: every product and sum carried as hi + lo.
+    fn dd_dot(values: &[f64], weights: &[f64]) -> f64 {
+        let two_sum = |a: f64, b: f64| {
+            let s = a + b;
+            let bb = s - a;
+            (s, (a - (s - bb)) + (b - bb))
+        };
+        let (mut hi, mut lo) = (0.0f64, 0.0f64);
+        for (&x, &w) in values.iter().zip(weights) {
+            let p = x * w;
+            let pe = x.mul_add(w, -p);
+            let (s, e) = two_sum(hi, p);
+            let (s2, e2) = two_sum(s, lo + e + pe);
+            hi = s2;
+            lo = e2;
+        }
+        hi + lo
+    }
+
+    #[test]
+    fn test_weighted_sum_adversarial_magnitudes() {
+        let values = [1e16, 1.0, -1e16, 3.0, 1e-8, 1e300, -1e300];
+        let weights = [1.0, 1.0, 1.0, 1.0 / 3.0, 1e8, 1e-290, 1e-290];
+        let got = unsafe { tova_weighted_sum_f64(values.as_ptr(), weights.as_ptr(), values.len()) };
+        assert_eq!(got, 3.0);
+
+        // Products with rounding error that a plain multiply drops
+        let mut rng = Rng(0xDEAD_BEEF_CAFE_F00D);
+        let values: Vec<f64> = (0..10_000)
+            .map(|i| {
+                let mag = 10f64.powi((rng.next_u64() % 24) as i32 - 12);
+                let v = (rng.next_u64() % 1_000_003) as f64 / 7.0 * mag;
+                if i % 2 == 0 { v } else { -v }
+            })
+            .collect();
+        let weights: Vec<f64> = (0..10_000).map(|_| (rng.next_u64() % 997) as f64 / 13.0 + 0.1).collect();
+        let reference = dd_dot(&values, &weights);
+        let got = unsafe { tova_weighted_sum_f64(values.as_ptr(), weights.as_ptr(), values.len()) };
+        let naive: f64 = values.iter().zip(&weights).map(|(x, w)| x * w).sum();
+        assert!((got - reference).abs() <= reference.abs() * 2.0 * f64::EPSILON, "{got} vs {reference}");
+        assert!((got - reference).abs() <= (naive - reference).abs());
+    }
+
+    #[test]
+    fn test_weighted_mean_and_masks() {
+        let values = [1.0, 2.0, f64::NAN, 4.0];
+        let weights = [1.0, 3.0, 5.0, f64::NAN];
+        let mean = unsafe { tova_weighted_mean_f64(values.as_ptr(), weights.as_ptr(), 2) };
+        assert_eq!(mean, 7.0 / 4.0);
+        assert!(unsafe { tova_weighted_sum_f64(values.as_ptr(), weights.as_ptr(), 4) }.is_nan());
+        assert_eq!(unsafe { tova_weighted_sum_masked_f64(values.as_ptr(), weights.as_ptr(), 4) }, 7.0);
+        assert_eq!(unsafe { tova_weighted_mean_masked_f64(values.as_ptr(), weights.as_ptr(), 4) }, 7.0 / 4.0);
+
+        let zero = [0.0, 0.0];
+        assert!(unsafe { tova_weighted_mean_f64(values.as_ptr(), zero.as_ptr(), 2) }.is_nan());
+        assert!(unsafe { tova_weighted_mean_masked_f64(values.as_ptr(), weights.as_ptr(), 0) }.is_nan());
+        let cancelling = [1.0, -1.0];
+        assert!(unsafe { tova_weighted_mean_f64(values.as_ptr(), cancelling.as_ptr(), 2) }.is_nan());
+    }
+
+    #[test]
+    fn test_sum_squares() {
+        let data = [1e8 + 1.0, 3.0, -4.0];
+        // (1e8 + 1)^2 = 1e16 + 2e8 + 1 needs the product's rounding error
+        let got = unsafe { tova_sum_squares_f64(data.as_ptr(), 3) };
+        assert_eq!(got, 1e16 + 2e8 + 26.0);
+        assert_eq!(unsafe { tova_sum_squares_f64(std::ptr::null(), 0) }, 0.0);
+    }
+
+    // --- Grouped partials ---
+
+    type Partials = (Vec<i64>, Vec<f64>, Vec<u64>);
+
+    fn partials(keys: &[i64]) -> Partials {
+        let sums = keys.iter().map(|&k| k as f64 * 3.0 + 1.0).collect();
+        let counts = keys.iter().map(|&k| k.unsigned_abs() % 5 + 1).collect();
+        (keys.to_vec(), sums, counts)
+    }
+
+    fn merge(a: &Partials, b: &Partials) -> Partials {
+        let cap = a.0.len() + b.0.len();
+        let mut out: Partials = (vec![0; cap], vec![0.0; cap], vec![0; cap]);
+        let n = unsafe {
+            tova_merge_groups_f64(
+                a.0.as_ptr(), a.1.as_ptr(), a.2.as_ptr(), a.0.len(),
+                b.0.as_ptr(), b.1.as_ptr(), b.2.as_ptr(), b.0.len(),
+                out.0.as_mut_ptr(), out.1.as_mut_ptr(), out.2.as_mut_ptr(), cap,
+            )
+        };
+        assert!(n >= 0);
+        out.0.truncate(n as usize);
+        out.1.truncate(n as usize);
+        out.2.truncate(n as usize);
+        out
+    }
+
+    #[test]
+    fn test_merge_groups_matches_a_hash_map() {
+        let cases: [(&[i64], &[i64]); 5] = [
+            (&[1, 2, 3], &[10, 20]),
+            (&[-5, 0, 7], &[-5, 0, 7]),
+            (&[1, 4, 6, 9], &[2, 4, 5, 9, 12]),
+            (&[], &[3, 8]),
+            (&[i64::MIN, 0], &[]),
+        ];
+        for (keys_a, keys_b) in cases {
+            let (a, b) = (partials(keys_a), partials(keys_b));
+            let mut reference = std::collections::HashMap::new();
+            for side in [&a, &b] {
+                for i in 0..side.0.len() {
+                    let entry = reference.entry(side.0[i]).or_insert((0.0, 0u64));
+                    entry.0 += side.1[i];
+                    entry.1 += side.2[i];
+                }
+            }
+            let merged = merge(&a, &b);
+            assert_eq!(merged.0.len(), reference.len(), "{keys_a:?} + {keys_b:?}");
+            assert!(merged.0.windows(2).all(|w| w[0] < w[1]));
+            for i in 0..merged.0.len() {
+                assert_eq!((merged.1[i], merged.2[i]), reference[&merged.0[i]]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_merge_groups_is_associative() {
+        let (a, b, c) = (partials(&[1, 3, 5, 7]), partials(&[2, 3, 6, 7, 9]), partials(&[0, 5, 6, 10]));
+        // Integral sums add exactly, so both groupings must agree bit for bit
+        assert_eq!(merge(&a, &merge(&b, &c)), merge(&merge(&a, &b), &c));
+        assert_eq!(merge(&a, &b), merge(&b, &a));
+    }
+
+    #[test]
+    fn test_merge_groups_capacity_and_aliasing() {
+        let (a, b) = (partials(&[1, 2, 4]), partials(&[2, 3]));
+        let (mut keys, mut sums, mut counts) = ([0i64; 2], [0.0f64; 2], [0u64; 2]);
+        let n = unsafe {
+            tova_merge_groups_f64(
+                a.0.as_ptr(), a.1.as_ptr(), a.2.as_ptr(), 3,
+                b.0.as_ptr(), b.1.as_ptr(), b.2.as_ptr(), 2,
+                keys.as_mut_ptr(), sums.as_mut_ptr(), counts.as_mut_ptr(), 2,
+            )
+        };
+        assert_eq!(n, -4);
+        assert_eq!(keys, [1, 2]);
+        assert_eq!(sums, [4.0, 14.0]);
+
+        // Merge b into a's own buffers, grown to fit
+        let expected = merge(&a, &b);
+        let mut dst = a.clone();
+        dst.0.resize(5, 0);
+        dst.1.resize(5, 0.0);
+        dst.2.resize(5, 0);
+        let n = unsafe {
+            tova_merge_groups_f64(
+                dst.0.as_ptr(), dst.1.as_ptr(), dst.2.as_ptr(), 3,
+                b.0.as_ptr(), b.1.as_ptr(), b.2.as_ptr(), 2,
+                dst.0.as_mut_ptr(), dst.1.as_mut_ptr(), dst.2.as_mut_ptr(), 5,
+            )
+        };
+        assert_eq!(n, 4);
+        assert_eq!(unsafe { tova_last_error() }, TOVA_ERR_OVERLAP);
+        assert_eq!(&dst.0[..4], &expected.0[..]);
+        assert_eq!(&dst.1[..4], &expected.1[..]);
+        assert_eq!(&dst.2[..4], &expected.2[..]);
+    }
+
+    // --- Streaming group-by ---
+
+    /// Rows with few distinct keys, some negative, and integral values so
+    /// that every summation order gives the same sums.
+    fn group_rows(n: usize) -> (Vec<i64>, Vec<f64>) {
+        let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+        (0..n)
+            .map(|_| {
+                let r = rng.next_u64();
+                ((r % 997) as i64 - 300, (r >> 40) as f64 - 8e6)
+            })
+            .unzip()
+    }
+
+    type Groups = (Vec<i64>, Vec<f64>, Vec<u64>);
+
+    fn finalize(handle: *mut GroupAgg) -> Groups {
+        let cap = unsafe { tova_group_agg_len(handle) };
+        let mut out: Groups = (vec![0; cap], vec![0.0; cap], vec![0; cap]);
+        let n = unsafe { tova_group_agg_finalize(handle, out.0.as_mut_ptr(), out.1.as_mut_ptr(), out.2.as_mut_ptr(), cap) };
+        assert_eq!(n, cap as isize);
+        unsafe { tova_group_agg_free(handle) };
+        out
+    }
+
+    const GROUP_OPS: [i32; 5] = [TOVA_GROUP_AGG_SUM, TOVA_GROUP_AGG_MIN, TOVA_GROUP_AGG_MAX, TOVA_GROUP_AGG_COUNT, TOVA_GROUP_AGG_MEAN];
+
+    #[test]
+    fn test_group_agg_chunked_updates_match_one_pass() {
+        let (keys, values) = group_rows(50_000);
+        for op in GROUP_OPS {
+            let whole = unsafe { tova_group_agg_create(op) };
+            assert_eq!(unsafe { tova_group_agg_update(whole, keys.as_ptr(), values.as_ptr(), keys.len()) }, 0);
+            let whole = finalize(whole);
+
+            // Thousands of small row groups, and a few empty ones
+            let chunked = unsafe { tova_group_agg_create(op) };
+            for (k, v) in keys.chunks(17).zip(values.chunks(17)) {
+                assert_eq!(unsafe { tova_group_agg_update(chunked, k.as_ptr(), v.as_ptr(), k.len()) }, 0);
+                assert_eq!(unsafe { tova_group_agg_update(chunked, std::ptr::null(), std::ptr::null(), 0) }, 0);
+            }
+            let chunked = finalize(chunked);
+            assert_eq!(chunked, whole, "op {}", op);
+
+            let mut reference = std::collections::BTreeMap::new();
+            for (&k, &v) in keys.iter().zip(&values) {
+                reference.entry(k).or_insert_with(Vec::new).push(v);
+            }
+            assert_eq!(whole.0, reference.keys().copied().collect::<Vec<_>>());
+            for (i, rows) in reference.values().enumerate() {
+                let expected = match op {
+                    TOVA_GROUP_AGG_SUM => rows.iter().sum(),
+                    TOVA_GROUP_AGG_MIN => kernels::min_f64(rows),
+                    TOVA_GROUP_AGG_MAX => kernels::max_f64(rows),
+                    TOVA_GROUP_AGG_COUNT => rows.len() as f64,
+                    _ => rows.iter().sum::<f64>() / rows.len() as f64,
+                };
+                assert_eq!((whole.1[i], whole.2[i]), (expected, rows.len() as u64), "op {} key {}", op, whole.0[i]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_group_agg_merged_worker_handles_match_the_global_result() {
+        let (keys, values) = group_rows(40_000);
+        for op in GROUP_OPS {
+            let global = unsafe { tova_group_agg_create(op) };
+            unsafe { tova_group_agg_update(global, keys.as_ptr(), values.as_ptr(), keys.len()) };
+            let global = finalize(global);
+
+            // One handle per worker thread over its share of the row groups
+            let workers: Vec<usize> = std::thread::scope(|s| {
+                let threads: Vec<_> = (0..4)
+                    .map(|w| {
+                        let (keys, values) = (&keys, &values);
+                        s.spawn(move || {
+                            let handle = unsafe { tova_group_agg_create(op) };
+                            for chunk in (w * 1000..keys.len()).step_by(4000) {
+                                let end = (chunk + 1000).min(keys.len());
+                                unsafe { tova_group_agg_update(handle, keys[chunk..end].as_ptr(), values[chunk..end].as_ptr(), end - chunk) };
+                            }
+                            handle as usize
+                        })
+                    })
+                    .collect();
+                threads.into_iter().map(|t| t.join().unwrap()).collect()
+            });
+            let merged = workers[0] as *mut GroupAgg;
+            for &other in &workers[1..] {
+                assert_eq!(unsafe { tova_group_agg_merge(merged, other as *const GroupAgg) }, 0);
+                unsafe { tova_group_agg_free(other as *mut GroupAgg) };
+            }
+            assert_eq!(finalize(merged), global, "op {}", op);
+        }
+    }
+
+    #[test]
+    fn test_group_agg_skips_nan_for_min_max() {
+        let keys = [1i64, 1, 2, 2, 3];
+        let values = [f64::NAN, 4.0, 5.0, -1.0, f64::NAN];
+        let min = unsafe { tova_group_agg_create(TOVA_GROUP_AGG_MIN) };
+        let sum = unsafe { tova_group_agg_create(TOVA_GROUP_AGG_SUM) };
+        let count = unsafe { tova_group_agg_create(TOVA_GROUP_AGG_COUNT) };
+        unsafe {
+            tova_group_agg_update(min, keys.as_ptr(), values.as_ptr(), 5);
+            tova_group_agg_update(sum, keys.as_ptr(), values.as_ptr(), 5);
+            // Counting reads no values
+            tova_group_agg_update(count, keys.as_ptr(), std::ptr::null(), 5);
+        }
+        let min = finalize(min);
+        assert_eq!(&min.1[..2], &[4.0, -1.0]);
+        assert!(min.1[2].is_nan());
+        assert_eq!(min.2, [2, 2, 1]);
+        let sum = finalize(sum);
+        assert!(sum.1[0].is_nan() && sum.1[2].is_nan());
+        assert_eq!(sum.1[1], 4.0);
+        assert_eq!(finalize(count).1, [2.0, 2.0, 1.0]);
+    }
+
+    #[test]
+    fn test_group_agg_finalizes_once() {
+        let handle = unsafe { tova_group_agg_create(TOVA_GROUP_AGG_SUM) };
+        let keys = [9i64, 3, 9, -4];
+        unsafe { tova_group_agg_update(handle, keys.as_ptr(), [1.0, 2.0, 3.0, 4.0].as_ptr(), 4) };
+        assert_eq!(unsafe { tova_group_agg_reserve(handle, 100_000) }, 0);
+        assert_eq!(unsafe { tova_group_agg_reserve(handle, usize::MAX) }, TOVA_ERR_INVALID_INPUT);
+
+        // Too little room writes what fits and keeps the state
+        let (mut out_keys, mut out_vals) = ([0i64; 3], [0.0f64; 3]);
+        let n = unsafe { tova_group_agg_finalize(handle, out_keys.as_mut_ptr(), out_vals.as_mut_ptr(), std::ptr::null_mut(), 2) };
+        assert_eq!(n, -3);
+        assert_eq!(&out_keys[..2], &[-4, 3]);
+        assert_eq!(unsafe { tova_group_agg_finalize(handle, out_keys.as_mut_ptr(), out_vals.as_mut_ptr(), std::ptr::null_mut(), 3) }, 3);
+        assert_eq!((out_keys, out_vals), ([-4, 3, 9], [4.0, 2.0, 4.0]));
+
+        // Everything after that is refused
+        let n = unsafe { tova_group_agg_finalize(handle, out_keys.as_mut_ptr(), out_vals.as_mut_ptr(), std::ptr::null_mut(), 3) };
+        assert_eq!(n, TOVA_GROUP_AGG_ERR_FINALIZE);
+        assert_eq!(unsafe { tova_last_error() }, TOVA_GROUP_AGG_ERR_FINALIZED);
+        assert_eq!(unsafe { tova_group_agg_update(handle, keys.as_ptr(), [0.0; 4].as_ptr(), 4) }, TOVA_GROUP_AGG_ERR_FINALIZED);
+        let other = unsafe { tova_group_agg_create(TOVA_GROUP_AGG_SUM) };
+        assert_eq!(unsafe { tova_group_agg_merge(other, handle) }, TOVA_GROUP_AGG_ERR_FINALIZED);
+        assert_eq!(unsafe { tova_group_agg_len(handle) }, 0);
+        unsafe { tova_group_agg_free(handle) };
+        unsafe { tova_group_agg_free(other) };
+    }
+
+    #[test]
+    fn test_group_agg_rejects_bad_handles_and_ops() {
+        assert!(unsafe { tova_group_agg_create(0) }.is_null());
+        assert_eq!(unsafe { tova_last_error() }, TOVA_GROUP_AGG_ERR_OP);
+        let null = std::ptr::null_mut();
+        assert_eq!(unsafe { tova_group_agg_update(null, [1i64].as_ptr(), [1.0].as_ptr(), 1) }, TOVA_GROUP_AGG_ERR_HANDLE);
+        assert_eq!(unsafe { tova_group_agg_finalize(null, null as *mut i64, null as *mut f64, null as *mut u64, 0) }, TOVA_GROUP_AGG_ERR_FINALIZE);
+        assert_eq!(unsafe { tova_last_error() }, TOVA_GROUP_AGG_ERR_HANDLE);
+
+        let (sum, max) = unsafe { (tova_group_agg_create(TOVA_GROUP_AGG_SUM), tova_group_agg_create(TOVA_GROUP_AGG_MAX)) };
+        assert_eq!(unsafe { tova_group_agg_merge(sum, max) }, TOVA_GROUP_AGG_ERR_OP);
+        assert_eq!(unsafe { tova_group_agg_merge(sum, sum) }, TOVA_GROUP_AGG_ERR_HANDLE);
+        assert_eq!(unsafe { tova_group_agg_merge(sum, null) }, TOVA_GROUP_AGG_ERR_HANDLE);
+        unsafe {
+            tova_group_agg_free(sum);
+            tova_group_agg_free(max);
+            tova_group_agg_free(null);
+        }
+    }
+
+    // --- Time buckets ---
+
+    fn time_buckets(ts: &[i64], values: &[f64], bucket: i64, origin: i64, op: i32, flags: u32) -> Groups {
+        let cap = ts.len().max(2048);
+        let mut out: Groups = (vec![0; cap], vec![0.0; cap], vec![0; cap]);
+        let n = unsafe {
+            let (starts, vals, counts) = (out.0.as_mut_ptr(), out.1.as_mut_ptr(), out.2.as_mut_ptr());
+            tova_time_bucket_agg(ts.as_ptr(), values.as_ptr(), ts.len(), bucket, origin, op, flags, starts, vals, counts, cap)
+        };
+        assert!(n >= 0 && n as usize <= cap, "{}", n);
+        out.0.truncate(n as usize);
+        out.1.truncate(n as usize);
+        out.2.truncate(n as usize);
+        out
+    }
+
+    #[test]
+    fn test_time_buckets_floor_negative_epochs() {
+        const FIVE_MIN: i64 = 300_000;
+        // Just before and after the epoch, and a bucket boundary below it
+        let ts = [-1, -FIVE_MIN, -FIVE_MIN - 1, 0, FIVE_MIN - 1, FIVE_MIN];
+        let values = [1.0, 2.0, 4.0, 8.0, 16.0, 32.0];
+        let (starts, sums, counts) = time_buckets(&ts, &values, FIVE_MIN, 0, TOVA_GROUP_AGG_SUM, 0);
+        assert_eq!(starts, [-2 * FIVE_MIN, -FIVE_MIN, 0, FIVE_MIN]);
+        assert_eq!(sums, [4.0, 3.0, 24.0, 32.0]);
+        assert_eq!(counts, [1, 2, 2, 1]);
+
+        // Buckets aligned to a minute past the hour, on both sides of it
+        let (starts, ..) = time_buckets(&ts, &values, FIVE_MIN, 60_000, TOVA_GROUP_AGG_COUNT, 0);
+        assert_eq!(starts, [-540_000, -240_000, 60_000]);
+        assert_eq!(kernels::time_bucket(-7, 5, 3), Some(-7));
+        assert_eq!(kernels::time_bucket(-8, 5, 3), Some(-12));
+        assert_eq!(kernels::time_bucket(i64::MIN, 8, 0), Some(i64::MIN));
+        assert_eq!(kernels::time_bucket(i64::MIN, 10, 0), None);
+
+        // A bucket starting below i64::MIN, a width of 0 and an unknown op
+        let mut out = ([0i64; 4], [0.0; 4]);
+        let call = |ts: &[i64], bucket: i64, origin: i64, op: i32, out: &mut ([i64; 4], [f64; 4])| unsafe {
+            let (starts, sums) = (out.0.as_mut_ptr(), out.1.as_mut_ptr());
+            tova_time_bucket_agg(ts.as_ptr(), values.as_ptr(), ts.len(), bucket, origin, op, 0, starts, sums, std::ptr::null_mut(), 4)
+        };
+        assert_eq!(call(&[i64::MIN], 10, 1, TOVA_GROUP_AGG_SUM, &mut out), TOVA_TIME_BUCKET_ERR);
+        assert_eq!(unsafe { tova_last_error() }, TOVA_ERR_INVALID_INPUT);
+        assert_eq!(call(&ts, 0, 0, TOVA_GROUP_AGG_SUM, &mut out), TOVA_TIME_BUCKET_ERR);
+        assert_eq!(unsafe { tova_last_error() }, TOVA_ERR_INVALID_INPUT);
+        assert_eq!(call(&ts, 10, 0, 9, &mut out), TOVA_TIME_BUCKET_ERR);
+        assert_eq!(unsafe { tova_last_error() }, TOVA_GROUP_AGG_ERR_OP);
+        assert_eq!(call(&[], 10, 0, TOVA_GROUP_AGG_SUM, &mut out), 0);
+    }
+
+    #[test]
+    fn test_time_buckets_fill_gaps_in_sparse_data() {
+        let ts = [130, 20, 25, 10_055, 90];
+        let values = [1.0, 2.0, 3.0, 4.0, 5.0];
+        let (starts, sums, counts) = time_buckets(&ts, &values, 10, 5, TOVA_GROUP_AGG_SUM, TOVA_TIME_BUCKET_FILL_GAPS);
+        assert_eq!(starts, (0..1005).map(|i| 15 + i * 10).collect::<Vec<i64>>());
+        let nonempty: Vec<(usize, f64, u64)> =
+            (0..starts.len()).filter(|&i| counts[i] > 0).map(|i| (i, sums[i], counts[i])).collect();
+        assert_eq!(nonempty, [(0, 2.0, 1), (1, 3.0, 1), (7, 5.0, 1), (11, 1.0, 1), (1004, 4.0, 1)]);
+        assert!(sums.iter().zip(&counts).all(|(&s, &c)| c > 0 || s == 0.0));
+
+        // Mean, min and max fill with NaN
+        for op in [TOVA_GROUP_AGG_MEAN, TOVA_GROUP_AGG_MIN, TOVA_GROUP_AGG_MAX] {
+            let (_, vals, counts) = time_buckets(&ts, &values, 10, 5, op, TOVA_TIME_BUCKET_FILL_GAPS);
+            assert!(vals.iter().zip(&counts).all(|(v, &c)| (c == 0) == v.is_nan()), "op {}", op);
+        }
+
+        // Too little room: the total, negated, and the first buckets
+        let (mut starts, mut sums) = ([0i64; 3], [0.0; 3]);
+        let n = unsafe {
+            let (op, flags) = (TOVA_GROUP_AGG_SUM, TOVA_TIME_BUCKET_FILL_GAPS);
+            let (out_starts, out_sums) = (starts.as_mut_ptr(), sums.as_mut_ptr());
+            tova_time_bucket_agg(ts.as_ptr(), values.as_ptr(), 5, 10, 5, op, flags, out_starts, out_sums, std::ptr::null_mut(), 3)
+        };
+        assert_eq!(n, -1005);
+        assert_eq!((starts, sums), ([15, 25, 35], [2.0, 3.0, 0.0]));
+    }
+
+    #[test]
+    fn test_time_buckets_match_group_agg_on_bucket_keys() {
+        let (keys, values) = group_rows(20_000);
+        let ts: Vec<i64> = keys.iter().map(|&k| k * 7_919 - 1_000_000).collect();
+        let mut sorted = ts.clone();
+        sorted.sort_unstable();
+        // Unsorted rows through the table, time-sorted ones through the runs
+        for ts in [&ts, &sorted] {
+            let starts: Vec<i64> = ts.iter().map(|&t| kernels::time_bucket(t, 60_000, 17).unwrap()).collect();
+            for op in GROUP_OPS {
+                let handle = unsafe { tova_group_agg_create(op) };
+                assert_eq!(unsafe { tova_group_agg_update(handle, starts.as_ptr(), values.as_ptr(), starts.len()) }, 0);
+                let expected = finalize(handle);
+                assert_eq!(time_buckets(ts, &values, 60_000, 17, op, 0), expected, "op {}", op);
+            }
+        }
+    }
+
+    // --- String + row hashing ---
+
+    fn string_column(strings: &[&[u8]]) -> (Vec<u8>, Vec<u64>) {
+        let mut bytes = Vec::new();
+        let mut offsets = vec![0u64];
+        for s in strings {
+            bytes.extend_from_slice(s);
+            offsets.push(bytes.len() as u64);
+        }
+        (bytes, offsets)
+    }
+
+    fn hash_strings(strings: &[&[u8]], seed: u64) -> Vec<u64> {
+        let (bytes, offsets) = string_column(strings);
+        let mut out = vec![0u64; strings.len()];
+        assert_eq!(unsafe { tova_hash_strings(bytes.as_ptr(), offsets.as_ptr(), strings.len(), seed, out.as_mut_ptr()) }, 0);
+        out
+    }
+
+    #[test]
+    fn test_hash_strings_xxh64() {
+        // Reference XXH64 values
+        assert_eq!(hash_strings(&[b"", b"a", b"abc"], 0), [0xEF46_DB37_51D8_E999, 0xD24E_C4F1_A98C_6E5B, 0x44BC_2CF5_AD77_0999]);
+        assert_eq!(kernels::hash_xxh64(b"abc", 0), 0x44BC_2CF5_AD77_0999);
+        let long: Vec<u8> = (0..100u8).collect();
+        let rows: [&[u8]; 4] = [b"tenant-1", &long, b"", b"tenant-1"];
+        let a = hash_strings(&rows, 7);
+        assert_eq!(a, hash_strings(&rows, 7));
+        assert_eq!(a[0], a[3]);
+        let b = hash_strings(&rows, 8);
+        assert!(a.iter().zip(&b).all(|(x, y)| x != y));
+
+        let offsets = [0u64, 4, 2];
+        let mut out = [0u64; 2];
+        let r = unsafe { tova_hash_strings(b"abcd".as_ptr(), offsets.as_ptr(), 2, 0, out.as_mut_ptr()) };
+        assert_eq!(r, TOVA_HASH_ERR_OFFSETS);
+        assert_eq!(out, [0, 0]);
+    }
+
+    #[test]
+    fn test_hash_columns_order_and_canonical_floats() {
+        let ids = [1i64, 2, 3];
+        let other = [3i64, 2, 1];
+        let prices = [0.0f64, -0.0, f64::NAN];
+        let prices_canon = [-0.0f64, 0.0, -f64::NAN];
+        let (bytes, offsets) = string_column(&[b"x", b"", b"yz"]);
+        let i64_col = |v: &[i64]| ColumnDesc { kind: TOVA_COLUMN_I64, data: v.as_ptr() as *const u8, offsets: std::ptr::null() };
+        let f64_col = |v: &[f64]| ColumnDesc { kind: TOVA_COLUMN_F64, data: v.as_ptr() as *const u8, offsets: std::ptr::null() };
+        let str_col = ColumnDesc { kind: TOVA_COLUMN_STRING, data: bytes.as_ptr(), offsets: offsets.as_ptr() };
+        let hash = |cols: &[ColumnDesc], seed: u64| {
+            let mut out = vec![0u64; 3];
+            assert_eq!(unsafe { tova_hash_columns(cols.as_ptr(), cols.len(), 3, seed, out.as_mut_ptr()) }, 0);
+            out
+        };
+
+        let ab = hash(&[i64_col(&ids), i64_col(&other)], 0);
+        let ba = hash(&[i64_col(&other), i64_col(&ids)], 0);
+        assert_ne!(ab[0], ba[0]);
+        assert_ne!(ab[2], ba[2]);
+        // Row 1 holds 2 in both columns, so the swap is a no-op there
+        assert_eq!(ab[1], ba[1]);
+        assert_eq!(ab, hash(&[i64_col(&ids), i64_col(&other)], 0));
+        assert!(ab.iter().zip(hash(&[i64_col(&ids), i64_col(&other)], 1)).all(|(x, y)| *x != y));
+
+        let full = hash(&[i64_col(&ids), f64_col(&prices), str_col], 5);
+        assert_eq!(full, hash(&[i64_col(&ids), f64_col(&prices_canon), str_col], 5));
+        assert_eq!(hash(&[], 5), vec![hash(&[], 5)[0]; 3]);
+
+        let bad = ColumnDesc { kind: 9, data: ids.as_ptr() as *const u8, offsets: std::ptr::null() };
+        let mut out = [0u64; 3];
+        assert_eq!(unsafe { tova_hash_columns(&bad, 1, 3, 0, out.as_mut_ptr()) }, TOVA_HASH_ERR_KIND);
+        assert_eq!(unsafe { tova_hash_columns(&bad, 1, 0, 0, out.as_mut_ptr()) }, 0);
+    }
+
+    #[test]
+    fn test_hash_columns_no_collisions_over_1m_rows() {
+        let n = 1_000_000;
+        let mut rng = Rng(0x0123_4567_89AB_CDEF);
+        let mut keys = Vec::with_capacity(n);
+        let mut bytes = Vec::new();
+        let mut offsets = vec![0u64];
+        for i in 0..n {
+            let r = rng.next_u64();
+            keys.push(i as i64);
+            bytes.extend_from_slice(&r.to_le_bytes()[..(r % 9) as usize]);
+            offsets.push(bytes.len() as u64);
+        }
+        let cols = [
+            ColumnDesc { kind: TOVA_COLUMN_I64, data: keys.as_ptr() as *const u8, offsets: std::ptr::null() },
+            ColumnDesc { kind: TOVA_COLUMN_STRING, data: bytes.as_ptr(), offsets: offsets.as_ptr() },
+        ];
+        let mut out = vec![0u64; n];
+        assert_eq!(unsafe { tova_hash_columns(cols.as_ptr(), 2, n, 42, out.as_mut_ptr()) }, 0);
+        out.sort_unstable();
+        out.dedup();
+        assert_eq!(out.len(), n);
+    }
+
+    // --- As-of join ---
+
+    fn asof(left: &[i64], right: &[i64], tolerance: i64, direction: u32) -> Vec<i64> {
+        let mut out = vec![0; left.len()];
+        let code = unsafe { tova_asof_join_i64(left.as_ptr(), left.len(), right.as_ptr(), right.len(), tolerance, direction, out.as_mut_ptr()) };
+        assert_eq!(code, 0);
+        out
+    }
+
+    #[test]
+    fn test_asof_join_directions_and_tolerance() {
+        let right = [10, 20, 20, 20, 35];
+        let left = [5, 10, 15, 20, 26, 34, 40, 100];
+        // Exact matches, the last of the tied 20s, nothing before 10
+        assert_eq!(asof(&left, &right, i64::MAX, TOVA_ASOF_BACKWARD), [-1, 0, 0, 3, 3, 3, 4, 4]);
+        assert_eq!(asof(&left, &right, i64::MAX, TOVA_ASOF_FORWARD), [0, 0, 1, 1, 4, 4, -1, -1]);
+        // 15 is equally far from 10 and 20 and takes the backward one
+        assert_eq!(asof(&left, &right, i64::MAX, TOVA_ASOF_NEAREST), [0, 0, 0, 3, 3, 4, 4, 4]);
+        // Gaps wider than the tolerance find nothing
+        assert_eq!(asof(&left, &right, 5, TOVA_ASOF_BACKWARD), [-1, 0, 0, 3, -1, -1, 4, -1]);
+        assert_eq!(asof(&left, &right, 0, TOVA_ASOF_FORWARD), [-1, 0, -1, 1, -1, -1, -1, -1]);
+        assert_eq!(asof(&left, &right, 4, TOVA_ASOF_NEAREST), [-1, 0, -1, 3, -1, 4, -1, -1]);
+
+        // Extreme timestamps do not overflow the distance
+        assert_eq!(asof(&[i64::MIN, i64::MAX], &[i64::MIN, i64::MAX], i64::MAX, TOVA_ASOF_NEAREST), [0, 1]);
+        assert_eq!(asof(&[1, 2], &[], i64::MAX, TOVA_ASOF_NEAREST), [-1, -1]);
+        assert_eq!(asof(&[], &right, 0, TOVA_ASOF_BACKWARD), Vec::<i64>::new());
+
+        // Written over the left column it was computed from
+        let mut data = left.to_vec();
+        let code = unsafe { tova_asof_join_i64(data.as_ptr(), 8, right.as_ptr(), 5, i64::MAX, TOVA_ASOF_BACKWARD, data.as_mut_ptr()) };
+        assert_eq!((code, data), (0, vec![-1, 0, 0, 3, 3, 3, 4, 4]));
+    }
+
+    #[test]
+    fn test_asof_join_rejections() {
+        let mut out = [7i64; 3];
+        let call = |left: &[i64], right: &[i64], tolerance, direction, out: &mut [i64]| unsafe {
+            tova_asof_join_i64(left.as_ptr(), left.len(), right.as_ptr(), right.len(), tolerance, direction, out.as_mut_ptr())
+        };
+        assert_eq!(call(&[1, 3, 2], &[1, 2], 0, TOVA_ASOF_BACKWARD, &mut out), TOVA_ASOF_ERR_LEFT_UNSORTED);
+        assert_eq!(unsafe { tova_last_error() }, TOVA_ASOF_ERR_LEFT_UNSORTED);
+        assert_eq!(call(&[1, 2, 3], &[2, 1], 0, TOVA_ASOF_BACKWARD, &mut out), TOVA_ASOF_ERR_RIGHT_UNSORTED);
+        assert_eq!(call(&[1, 2, 3], &[1], -1, TOVA_ASOF_BACKWARD, &mut out), TOVA_ASOF_ERR_TOLERANCE);
+        assert_eq!(call(&[1, 2, 3], &[1], 0, 3, &mut out), TOVA_ASOF_ERR_DIRECTION);
+        assert_eq!(out, [7; 3]);
+    }
+
+    #[test]
+    fn test_oset_counts_duplicates_and_rejects_bad_input() {
+        let set = unsafe { tova_oset_create() };
+        unsafe {
+            for v in [5.0, 1.0, 5.0, -0.0, 3.0, 5.0] {
+                assert_eq!(tova_oset_insert_f64(set, v), 0);
+            }
+            assert_eq!(tova_oset_len(set), 6);
+            assert_eq!(tova_oset_rank(set, 5.0), 6);
+            assert_eq!(tova_oset_rank(set, 4.9), 3);
+            assert_eq!(tova_oset_rank(set, f64::NAN), 0);
+            assert_eq!(tova_oset_quantile(set, 0.0).to_bits(), 0.0f64.to_bits());
+            assert_eq!(tova_oset_quantile(set, 0.5), 3.0);
+            assert_eq!(tova_oset_quantile(set, 1.0), 5.0);
+            assert!(tova_oset_quantile(set, 1.5).is_nan());
+
+            assert_eq!(tova_oset_insert_f64(set, f64::NAN), TOVA_OSET_ERR_NAN);
+            assert_eq!(tova_oset_remove_f64(set, 2.0), TOVA_OSET_ERR_ABSENT);
+            assert_eq!(tova_last_error(), TOVA_OSET_ERR_ABSENT);
+            // One copy at a time; 0.0 removes the -0.0
+            assert_eq!(tova_oset_remove_f64(set, 5.0), 0);
+            assert_eq!(tova_oset_remove_f64(set, 0.0), 0);
+            assert_eq!(tova_oset_len(set), 4);
+            assert_eq!(tova_oset_quantile(set, 0.0), 1.0);
+            assert_eq!(tova_oset_rank(set, 5.0), 4);
+            for v in [1.0, 3.0, 5.0, 5.0] {
+                assert_eq!(tova_oset_remove_f64(set, v), 0);
+            }
+            assert_eq!(tova_oset_len(set), 0);
+            assert!(tova_oset_quantile(set, 0.5).is_nan());
+            tova_oset_free(set);
+
+            let null = std::ptr::null_mut();
+            assert_eq!(tova_oset_insert_f64(null, 1.0), TOVA_OSET_ERR_HANDLE);
+            assert_eq!(tova_oset_remove_f64(null, 1.0), TOVA_OSET_ERR_HANDLE);
+            assert_eq!(tova_oset_len(null), 0);
+            assert!(tova_oset_quantile(null, 0.5).is_nan());
+            tova_oset_free(null);
+        }
+    }
+
+    #[test]
+    fn test_hashjoin_probe_pairs_and_capacity() {
+        let build = [5i64, 7, 5, -1, 5, 9];
+        let probe = [9i64, 5, 0, 7, 5];
+        unsafe {
+            let join = tova_hashjoin_build_i64(build.as_ptr(), build.len());
+            assert!(!join.is_null());
+            let (mut left, mut right) = ([0u32; 16], [0u32; 16]);
+            let n = tova_hashjoin_probe_i64(join, probe.as_ptr(), probe.len(), left.as_mut_ptr(), right.as_mut_ptr(), 16);
+            assert_eq!(n, 8);
+            assert_eq!(left[..8], [0, 1, 1, 1, 3, 4, 4, 4]);
+            assert_eq!(right[..8], [5, 0, 2, 4, 1, 0, 2, 4]);
+
+            // Too small an output still gets the first pairs
+            let (mut left, mut right) = ([u32::MAX; 3], [u32::MAX; 3]);
+            assert_eq!(tova_hashjoin_probe_i64(join, probe.as_ptr(), probe.len(), left.as_mut_ptr(), right.as_mut_ptr(), 3), -8);
+            assert_eq!((left, right), ([0, 1, 1], [5, 0, 2]));
+            assert_eq!(tova_hashjoin_probe_i64(join, probe.as_ptr(), 0, left.as_mut_ptr(), right.as_mut_ptr(), 3), 0);
+
+            // Probe keys in the buffer the probe indices are written to
+            let mut data = [5i64, 9, 1, 1];
+            let out = data.as_mut_ptr() as *mut u32;
+            let mut right = [0u32; 8];
+            assert_eq!(tova_hashjoin_probe_i64(join, data.as_ptr(), 2, out, right.as_mut_ptr(), 4), 4);
+            assert_eq!(tova_last_error(), TOVA_ERR_OVERLAP);
+            assert_eq!(std::slice::from_raw_parts(out, 4), [0, 0, 0, 1]);
+            assert_eq!(right[..4], [0, 2, 4, 5]);
+            tova_hashjoin_free(join);
+
+            let empty = tova_hashjoin_build_i64(std::ptr::null(), 0);
+            assert_eq!(tova_hashjoin_probe_i64(empty, probe.as_ptr(), probe.len(), left.as_mut_ptr(), right.as_mut_ptr(), 3), 0);
+            tova_hashjoin_free(empty);
+
+            let null = std::ptr::null();
+            assert_eq!(tova_hashjoin_probe_i64(null, probe.as_ptr(), probe.len(), left.as_mut_ptr(), right.as_mut_ptr(), 3), TOVA_HASHJOIN_ERR_HANDLE);
+            assert_eq!(tova_last_error(), TOVA_ERR_INVALID_INPUT);
+            tova_hashjoin_free(std::ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_weighted_samples_follow_the_weights() {
+        let weights = [1.0, 2.0, 3.0, 4.0, 0.0, -1.0, f64::NAN, f64::INFINITY];
+        let trials = 20_000;
+        let mut hits = [0usize; 8];
+        for seed in 0..trials {
+            let mut out = [u32::MAX; 1];
+            let n = unsafe { tova_weighted_sample_f64(std::ptr::null(), weights.as_ptr(), weights.len(), 1, seed, out.as_mut_ptr()) };
+            assert_eq!(n, 1);
+            hits[out[0] as usize] += 1;
+        }
+        for (i, &w) in weights[..4].iter().enumerate() {
+            let share = hits[i] as f64 / trials as f64;
+            assert!((share - w / 10.0).abs() < 0.015, "row {} drawn {} of {}", i, hits[i], trials);
+        }
+        assert_eq!(hits[4..], [0, 0, 0, 0]);
+
+        // Without replacement: a heavy row is taken first, then the rest by weight
+        let weights = [1.0, 1000.0, 1.0, 1.0];
+        let mut second = [0usize; 4];
+        let mut without_heavy = 0;
+        for seed in 0..trials {
+            let mut out = [0u32; 2];
+            assert_eq!(unsafe { tova_weighted_sample_f64(std::ptr::null(), weights.as_ptr(), 4, 2, seed, out.as_mut_ptr()) }, 2);
+            assert!(out[0] < out[1], "{:?}", out);
+            match out.iter().position(|&i| i == 1) {
+                Some(at) => second[out[1 - at] as usize] += 1,
+                // Left out with probability about 6 in a million
+                None => without_heavy += 1,
+            }
+        }
+        assert!(without_heavy <= 2, "row 1 left out of {} samples", without_heavy);
+        assert!(second.iter().enumerate().all(|(i, &n)| i == 1 || (n as f64 / trials as f64 - 1.0 / 3.0).abs() < 0.02), "{:?}", second);
+    }
+
+    #[test]
+    fn test_weighted_samples_are_seeded_and_skip_nan_values() {
+        let values: Vec<f64> = (0..1000).map(|i| if i % 3 == 0 { f64::NAN } else { i as f64 }).collect();
+        let weights: Vec<f64> = (0..1000).map(|i| 1.0 + (i % 7) as f64).collect();
+        let sample = |seed| {
+            let mut out = vec![0u32; 50];
+            let n = unsafe { tova_weighted_sample_f64(values.as_ptr(), weights.as_ptr(), 1000, 50, seed, out.as_mut_ptr()) };
+            assert_eq!(n, 50);
+            out
+        };
+        assert_eq!(sample(7), sample(7));
+        assert_ne!(sample(7), sample(8));
+        let out = sample(7);
+        assert!(out.windows(2).all(|w| w[0] < w[1]));
+        assert!(out.iter().all(|&i| i % 3 != 0));
+
+        // Fewer eligible rows than k: all of them
+        let weights = [0.0, 2.0, 0.0, 5.0];
+        let mut out = [u32::MAX; 3];
+        assert_eq!(unsafe { tova_weighted_sample_f64(std::ptr::null(), weights.as_ptr(), 4, 3, 1, out.as_mut_ptr()) }, 2);
+        assert_eq!(out, [1, 3, u32::MAX]);
+        assert_eq!(unsafe { tova_weighted_sample_f64(std::ptr::null(), weights.as_ptr(), 4, 0, 1, out.as_mut_ptr()) }, 0);
+    }
+
+    #[test]
+    fn test_stratified_samples_cap_and_cover_every_stratum() {
+        let strata: Vec<i64> = (0..5000).map(|i: i64| [-4, 0, 7, 7, 7, 9][(i * 31 % 6) as usize] + if i == 4321 { 100 } else { 0 }).collect();
+        let sample = |k: usize, seed: u64, cap: usize| {
+            let mut out = vec![u32::MAX; cap];
+            let n = unsafe { tova_stratified_sample_i64(strata.as_ptr(), strata.len(), k, seed, out.as_mut_ptr(), cap) };
+            (n, out)
+        };
+        let (n, out) = sample(100, 3, 1000);
+        // Four strata of at least 100 rows and one of a single row
+        assert_eq!(n, 401);
+        assert!(out[..401].windows(2).all(|w| w[0] < w[1]));
+        let mut per_stratum = std::collections::HashMap::new();
+        for &i in &out[..401] {
+            *per_stratum.entry(strata[i as usize]).or_insert(0) += 1;
+        }
+        assert_eq!(per_stratum.len(), 5);
+        assert!(per_stratum.iter().all(|(&s, &count)| count == if s > 50 { 1 } else { 100 }), "{:?}", per_stratum);
+        assert_eq!(sample(100, 3, 1000), (n, out));
+
+        // Too small an output still gets the first indices
+        let (n, out) = sample(100, 3, 10);
+        assert_eq!(n, -401);
+        assert!(out.iter().all(|&i| i != u32::MAX));
+        assert_eq!(sample(0, 3, 10).0, 0);
+
+        // Each row of a stratum is equally likely
+        let strata = [5i64; 10];
+        let mut hits = [0usize; 10];
+        for seed in 0..10_000 {
+            let mut out = [0u32; 1];
+            assert_eq!(unsafe { tova_stratified_sample_i64(strata.as_ptr(), 10, 1, seed, out.as_mut_ptr(), 1) }, 1);
+            hits[out[0] as usize] += 1;
+        }
+        assert!(hits.iter().all(|&h| (800..1200).contains(&h)), "{:?}", hits);
+    }
+
+    #[test]
+    fn test_minhash_estimates_jaccard_similarity() {
+        let signature = |tokens: &[u64], seed: u64| {
+            let mut out = vec![0u64; 256];
+            unsafe { tova_minhash_u64(tokens.as_ptr(), tokens.len(), 256, seed, out.as_mut_ptr()) };
+            out
+        };
+        let similarity = |a: &[u64], b: &[u64]| unsafe { tova_minhash_similarity(a.as_ptr(), b.as_ptr(), 256) };
+        // 400 of the 800 distinct tokens are shared: Jaccard 0.5
+        let a: Vec<u64> = (0..600).collect();
+        let b: Vec<u64> = (200..800).collect();
+        for seed in 0..8 {
+            let estimate = similarity(&signature(&a, seed), &signature(&b, seed));
+            assert!((estimate - 0.5).abs() < 0.1, "seed {}: {}", seed, estimate);
+        }
+        let disjoint: Vec<u64> = (1000..1600).collect();
+        assert!(similarity(&signature(&a, 1), &signature(&disjoint, 1)) < 0.05);
+
+        // Sets, not sequences: order and repeats do not matter
+        let mut shuffled: Vec<u64> = a.iter().rev().chain(&a[..100]).copied().collect();
+        shuffled.swap(3, 400);
+        assert_eq!(signature(&a, 5), signature(&shuffled, 5));
+        assert_eq!(similarity(&signature(&a, 5), &signature(&shuffled, 5)), 1.0);
+        assert_ne!(signature(&a, 5), signature(&a, 6));
+        // Pinned so a change to the permutations cannot go unnoticed
+        assert_eq!(signature(&[1, 2, 3], 0)[..2], [0x0fc4_192d_5b73_4842, 0x65e9_fe8d_8e4d_a7fd]);
+
+        assert!(signature(&[], 1).iter().all(|&slot| slot == u64::MAX));
+        assert!(unsafe { tova_minhash_similarity(std::ptr::null(), std::ptr::null(), 0) }.is_nan());
+    }
+
+    #[test]
+    fn test_simhash_distance_follows_overlap() {
+        let simhash = |tokens: &[u64]| unsafe { tova_simhash_u64(tokens.as_ptr(), std::ptr::null(), tokens.len()) };
+        let distance = |a: u64, b: u64| unsafe { tova_hamming_distance_u64(a, b) };
+        // Documents of 1000 tokens with the first `changed` replaced, averaged
+        // over several base documents
+        let mean_distance = |changed: u64| {
+            let total: u32 = (0..16u64)
+                .map(|doc| {
+                    let base: Vec<u64> = (0..1000).map(|t| doc << 32 | t).collect();
+                    let edited: Vec<u64> = base.iter().map(|&t| if t & 0xffff_ffff < changed { t | 1 << 63 } else { t }).collect();
+                    distance(simhash(&base), simhash(&edited))
+                })
+                .sum();
+            total as f64 / 16.0
+        };
+        let distances: Vec<f64> = [0, 20, 100, 400, 1000].into_iter().map(mean_distance).collect();
+        assert_eq!(distances[0], 0.0);
+        assert!(distances.windows(2).all(|w| w[0] < w[1]), "{:?}", distances);
+        // Unrelated documents differ in about half the bits
+        assert!((distances[4] - 32.0).abs() < 6.0, "{:?}", distances);
+
+        // Weights: tokens weighted 0 or NaN do not count
+        let a = [1u64, 2, 3, 4];
+        let b = [1u64, 2, 3, 99];
+        let weights = [1.0, 2.0, 0.5, 0.0];
+        let nan_weights = [1.0, 2.0, 0.5, f64::NAN];
+        unsafe {
+            let wa = tova_simhash_u64(a.as_ptr(), weights.as_ptr(), 4);
+            assert_eq!(wa, tova_simhash_u64(b.as_ptr(), nan_weights.as_ptr(), 4));
+            assert_eq!(wa, tova_simhash_u64(a.as_ptr(), weights.as_ptr(), 3));
+            assert_eq!(tova_simhash_u64(std::ptr::null(), std::ptr::null(), 0), 0);
+        }
+        assert_eq!(distance(0b1011, 0b0110), 3);
+        assert_eq!(distance(0, u64::MAX), 64);
+    }
+
+    #[test]
+    fn test_scaled_sums_are_exact_into_the_high_word() {
+        let sum = |values: &[i64]| {
+            let (mut hi, mut lo) = (0i64, 0u64);
+            assert_eq!(unsafe { tova_sum_scaled_i64(values.as_ptr(), values.len(), &mut hi, &mut lo) }, 0);
+            (hi, lo)
+        };
+        // 0.10 + 0.20 in cents
+        assert_eq!(sum(&[10, 20]), (0, 30));
+        assert_eq!(sum(&[i64::MAX, i64::MAX, 1]), (0, u64::MAX));
+        assert_eq!(sum(&[i64::MAX, i64::MAX, 2]), (1, 0));
+        assert_eq!(sum(&[i64::MIN, -1]), (-1, i64::MAX as u64));
+        assert_eq!(sum(&[i64::MIN; 4]), (-2, 0));
+        assert_eq!(sum(&[]), (0, 0));
+        let values = [1i64, 2];
+        assert_eq!(unsafe { tova_sum_scaled_i64(values.as_ptr(), 2, std::ptr::null_mut(), std::ptr::null_mut()) }, TOVA_DECIMAL_ERR_ARGS);
+    }
+
+    #[test]
+    fn test_scaled_means_round_ties_by_the_chosen_mode() {
+        let mean = |values: &[i64], scale: u32, rounding: i32| unsafe { tova_mean_scaled_i64(values.as_ptr(), values.len(), scale, rounding) };
+        let (even, up) = (TOVA_ROUND_HALF_EVEN, TOVA_ROUND_HALF_UP);
+        // Exactly .5: 1.5, 2.5 and -2.5
+        assert_eq!((mean(&[1, 2], 0, even), mean(&[1, 2], 0, up)), (2, 2));
+        assert_eq!((mean(&[1, 4], 0, even), mean(&[1, 4], 0, up)), (2, 3));
+        assert_eq!((mean(&[-1, -4], 0, even), mean(&[-1, -4], 0, up)), (-2, -3));
+        // Just either side of a tie rounds the same in both modes
+        assert_eq!((mean(&[1, 1, 2], 0, even), mean(&[1, 2, 2], 0, up)), (1, 2));
+        // More decimals than the inputs: 2.5 at one more digit
+        assert_eq!(mean(&[1, 4], 1, even), 25);
+        assert_eq!(mean(&[1, 0, 0], 4, up), 3333);
+        // The sum overflows i64 but the mean does not
+        assert_eq!(mean(&[i64::MAX, i64::MAX - 2], 0, even), i64::MAX - 1);
+
+        for (values, scale, rounding) in [(&[][..], 0, even), (&[1][..], 19, even), (&[1][..], 0, 7), (&[i64::MAX][..], 1, even)] {
+            assert_eq!(mean(values, scale, rounding), TOVA_DECIMAL_NULL);
+            assert_eq!(unsafe { tova_last_error() }, TOVA_ERR_INVALID_INPUT);
+        }
+    }
+
+    #[test]
+    fn test_f64_conversion_reports_inexact_values() {
+        let convert = |values: &[f64], scale: u32, rounding: i32| {
+            let mut out = vec![0i64; values.len()];
+            let inexact = unsafe { tova_convert_f64_scaled_i64(values.as_ptr(), values.len(), scale, out.as_mut_ptr(), rounding) };
+            (inexact, out)
+        };
+        let (even, up, strict) = (TOVA_ROUND_HALF_EVEN, TOVA_ROUND_HALF_UP, TOVA_ROUND_STRICT);
+        assert_eq!(convert(&[0.1, 0.2, 0.3, -19.99, 0.0, -0.0], 2, even), (0, vec![10, 20, 30, -1999, 0, 0]));
+        assert_eq!(convert(&[1234567.8901, 1e-4], 4, even), (0, vec![12_345_678_901, 1]));
+        // 0.1 + 0.2 is not the double nearest 0.30
+        assert_eq!(convert(&[0.1 + 0.2], 2, even), (1, vec![30]));
+        assert_eq!(convert(&[0.1 + 0.2], 2, even | strict), (1, vec![TOVA_DECIMAL_NULL]));
+        // 1.005 is stored as 1.00499999999999989..., exact only at three decimals
+        assert_eq!(convert(&[1.005], 2, up), (1, vec![100]));
+        assert_eq!(convert(&[1.005], 3, up | strict), (0, vec![1005]));
+
+        // Exact binary ties
+        assert_eq!(convert(&[0.125, -0.125, 0.375, 2.5], 2, even), (3, vec![12, -12, 38, 250]));
+        assert_eq!(convert(&[0.125, -0.125, 0.375, 0.5], 2, up), (3, vec![13, -13, 38, 50]));
+        assert_eq!(convert(&[2.5, 3.5, -2.5], 0, even), (3, vec![2, 4, -2]));
+        assert_eq!(convert(&[2.5, 3.5, -2.5], 0, up), (3, vec![3, 4, -3]));
+
+        // Not finite, past i64, or far below a unit
+        let edges = convert(&[f64::NAN, f64::INFINITY, 1e19, 9e18, 2f64.powi(60), 1e-300], 0, even);
+        assert_eq!(edges, (4, vec![TOVA_DECIMAL_NULL, TOVA_DECIMAL_NULL, TOVA_DECIMAL_NULL, 9_000_000_000_000_000_000, 1 << 60, 0]));
+        assert_eq!(convert(&[1e-300], 0, even | strict).1, [TOVA_DECIMAL_NULL]);
+        assert_eq!(convert(&[92_233_720_368.547_76], 8, even).1, [TOVA_DECIMAL_NULL]);
+
+        assert_eq!(convert(&[1.0], 19, even).0, usize::MAX);
+        assert_eq!(convert(&[1.0], 2, 5).0, usize::MAX);
+        assert_eq!(unsafe { tova_last_error() }, TOVA_ERR_INVALID_INPUT);
+    }
+
+    fn in_col(kind: u32, data: *const u8) -> ColumnDesc {
+        ColumnDesc { kind, data, offsets: std::ptr::null() }
+    }
+
+    fn out_col(kind: u32, data: *mut u8) -> ColumnDescMut {
+        ColumnDescMut { kind, data }
+    }
+
+    fn get_bit(bitmap: &[u8], i: usize) -> bool {
+        bitmap[i / 8] >> (i % 8) & 1 == 1
+    }
+
+    #[test]
+    fn test_gather_columns_materializes_a_sorted_table() {
+        let n = 1003;
+        let keys: Vec<f64> = (0..n).map(|i| ((i * 7919) % n) as f64 * 0.5).collect();
+        let ids: Vec<i64> = (0..n as i64).collect();
+        let scores: Vec<f64> = (0..n).map(|i| (i as f64).sin()).collect();
+        let counts: Vec<i64> = (0..n as i64).map(|i| i * i - 500).collect();
+        let mut valid = vec![0u8; kernels::bitmap_bytes(n)];
+        for i in (0..n).filter(|i| i % 3 != 1) {
+            valid[i / 8] |= 1 << (i % 8);
+        }
+        let mut perm: Vec<u32> = (0..n as u32).collect();
+        perm.sort_by(|&a, &b| keys[a as usize].total_cmp(&keys[b as usize]));
+
+        let (mut o_keys, mut o_scores) = (vec![0.0f64; n], vec![0.0f64; n]);
+        let (mut o_ids, mut o_counts) = (vec![0i64; n], vec![0i64; n]);
+        let mut o_valid = vec![0xFFu8; valid.len()];
+        let cols = [
+            in_col(TOVA_COLUMN_F64, keys.as_ptr() as *const u8),
+            in_col(TOVA_COLUMN_I64, ids.as_ptr() as *const u8),
+            in_col(TOVA_COLUMN_F64, scores.as_ptr() as *const u8),
+            in_col(TOVA_COLUMN_I64, counts.as_ptr() as *const u8),
+            in_col(TOVA_COLUMN_BITMAP, valid.as_ptr()),
+        ];
+        let outs = [
+            out_col(TOVA_COLUMN_F64, o_keys.as_mut_ptr() as *mut u8),
+            out_col(TOVA_COLUMN_I64, o_ids.as_mut_ptr() as *mut u8),
+            out_col(TOVA_COLUMN_F64, o_scores.as_mut_ptr() as *mut u8),
+            out_col(TOVA_COLUMN_I64, o_counts.as_mut_ptr() as *mut u8),
+            out_col(TOVA_COLUMN_BITMAP, o_valid.as_mut_ptr()),
+        ];
+        assert_eq!(unsafe { tova_gather_columns(perm.as_ptr(), n, cols.as_ptr(), 5, outs.as_ptr()) }, 0);
+        for (i, &p) in perm.iter().enumerate() {
+            let p = p as usize;
+            assert_eq!(
+                (o_keys[i], o_ids[i], o_scores[i].to_bits(), o_counts[i], get_bit(&o_valid, i)),
+                (keys[p], ids[p], scores[p].to_bits(), counts[p], get_bit(&valid, p)),
+                "row {}",
+                i
+            );
+        }
+        assert!(o_keys.windows(2).all(|w| w[0] <= w[1]));
+        // Bits past the last row are cleared
+        assert_eq!(o_valid[n / 8] >> (n % 8), 0);
+
+        // Split across threads, the columns come out the same
+        let (mut p_keys, mut p_ids, mut p_valid) = (vec![0.0f64; n], vec![0i64; n], vec![0u8; valid.len()]);
+        let mut moves = [
+            kernels::ColumnMove::F64(&keys, &mut p_keys),
+            kernels::ColumnMove::I64(&ids, &mut p_ids),
+            kernels::ColumnMove::Bitmap(&valid, &mut p_valid),
+        ];
+        kernels::gather_columns(&perm, &mut moves, 3).unwrap();
+        assert_eq!((p_keys, p_ids, p_valid), (o_keys, o_ids.clone(), o_valid.clone()));
+
+        // Scattering through the same permutation puts the table back
+        let (mut back_ids, mut back_valid) = (vec![0i64; n], vec![0xFFu8; valid.len()]);
+        let sorted = [in_col(TOVA_COLUMN_I64, o_ids.as_ptr() as *const u8), in_col(TOVA_COLUMN_BITMAP, o_valid.as_ptr())];
+        let back = [out_col(TOVA_COLUMN_I64, back_ids.as_mut_ptr() as *mut u8), out_col(TOVA_COLUMN_BITMAP, back_valid.as_mut_ptr())];
+        assert_eq!(unsafe { tova_scatter_columns(perm.as_ptr(), n, sorted.as_ptr(), 2, back.as_ptr()) }, 0);
+        assert_eq!((back_ids, back_valid), (ids, valid));
+    }
+
+    #[test]
+    fn test_gather_columns_permutes_bitmaps_bit_for_bit() {
+        // 11 rows, bits 0, 3, 4, 9 and 10 set
+        let valid = [0b0001_1001u8, 0b0000_0110];
+        let perm = [10u32, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0];
+        let mut out = [0u8; 2];
+        let cols = [in_col(TOVA_COLUMN_BITMAP, valid.as_ptr())];
+        let outs = [out_col(TOVA_COLUMN_BITMAP, out.as_mut_ptr())];
+        assert_eq!(unsafe { tova_gather_columns(perm.as_ptr(), 11, cols.as_ptr(), 1, outs.as_ptr()) }, 0);
+        // Reversed: bits 10, 7, 6, 1 and 0
+        assert_eq!(out, [0b1100_0011, 0b0000_0100]);
+
+        // A misaligned i64 output moves with it
+        let values: Vec<i64> = (0..11).collect();
+        let mut backing = [0i64; 12];
+        let misaligned = unsafe { (backing.as_mut_ptr() as *mut u8).add(1) };
+        let cols = [in_col(TOVA_COLUMN_I64, values.as_ptr() as *const u8), in_col(TOVA_COLUMN_BITMAP, valid.as_ptr())];
+        let outs = [out_col(TOVA_COLUMN_I64, misaligned), out_col(TOVA_COLUMN_BITMAP, out.as_mut_ptr())];
+        assert_eq!(unsafe { tova_scatter_columns(perm.as_ptr(), 11, cols.as_ptr(), 2, outs.as_ptr()) }, 0);
+        let moved: Vec<i64> = (0..11).map(|i| unsafe { (misaligned as *const i64).add(i).read_unaligned() }).collect();
+        assert_eq!(moved, (0..11).rev().collect::<Vec<i64>>());
+        assert_eq!(out, [0b1100_0011, 0b0000_0100]);
+    }
+
+    #[test]
+    fn test_gather_columns_rejects_bad_input_without_writing() {
+        let values = [1.0f64, 2.0, 3.0];
+        let ids = [7i64, 8, 9];
+        let valid = [0b101u8];
+        let (mut out_f, mut out_i, mut out_b) = ([-1.0f64; 3], [-1i64; 3], [0xAAu8]);
+        let cols = [
+            in_col(TOVA_COLUMN_F64, values.as_ptr() as *const u8),
+            in_col(TOVA_COLUMN_I64, ids.as_ptr() as *const u8),
+            in_col(TOVA_COLUMN_BITMAP, valid.as_ptr()),
+        ];
+        let outs = [
+            out_col(TOVA_COLUMN_F64, out_f.as_mut_ptr() as *mut u8),
+            out_col(TOVA_COLUMN_I64, out_i.as_mut_ptr() as *mut u8),
+            out_col(TOVA_COLUMN_BITMAP, out_b.as_mut_ptr()),
+        ];
+        let untouched = |f: &[f64; 3], i: &[i64; 3], b: &[u8; 1]| *f == [-1.0; 3] && *i == [-1; 3] && *b == [0xAA];
+
+        // The bad index is last, so a kernel writing as it checked would have
+        // filled the first rows
+        let gather = |perm: &[u32], outs: &[ColumnDescMut]| unsafe { tova_gather_columns(perm.as_ptr(), 3, cols.as_ptr(), 3, outs.as_ptr()) };
+        assert_eq!(gather(&[2, 0, 3], &outs), TOVA_INDEX_ERR_OUT_OF_RANGE);
+        assert_eq!(unsafe { tova_last_error() }, TOVA_INDEX_ERR_OUT_OF_RANGE);
+        let scatter = |perm: &[u32]| unsafe { tova_scatter_columns(perm.as_ptr(), 3, cols.as_ptr(), 3, outs.as_ptr()) };
+        assert_eq!(scatter(&[0, 2, 0]), TOVA_INDEX_ERR_DUPLICATE);
+        assert_eq!(scatter(&[0, 1, 5]), TOVA_INDEX_ERR_OUT_OF_RANGE);
+
+        let mismatched = [outs[0], out_col(TOVA_COLUMN_F64, out_i.as_mut_ptr() as *mut u8), outs[2]];
+        assert_eq!(gather(&[0, 1, 2], &mismatched), TOVA_INDEX_ERR_KIND);
+        // An output over another column's input
+        let aliased = [outs[0], out_col(TOVA_COLUMN_I64, values.as_ptr() as *mut u8), outs[2]];
+        assert_eq!(gather(&[0, 1, 2], &aliased), TOVA_ERR_OVERLAP);
+        assert!(untouched(&out_f, &out_i, &out_b));
+
+        let strings = [in_col(TOVA_COLUMN_STRING, valid.as_ptr())];
+        let string_out = [out_col(TOVA_COLUMN_STRING, out_b.as_mut_ptr())];
+        assert_eq!(unsafe { tova_gather_columns([0u32].as_ptr(), 1, strings.as_ptr(), 1, string_out.as_ptr()) }, TOVA_INDEX_ERR_KIND);
+        assert_eq!(gather(&[2, 1, 0], &outs), 0);
+        assert_eq!((out_f, out_i, out_b), ([3.0, 2.0, 1.0], [9, 8, 7], [0b101]));
+    }
+
+    /// A sparse vector over `dim` dimensions with about `density` of them
+    /// set, and the same vector dense.
+    fn random_sparse(rng: &mut Rng, dim: usize, density: f64) -> (Vec<u32>, Vec<f64>, Vec<f64>) {
+        let mut dense = vec![0.0; dim];
+        let (mut idx, mut vals) = (Vec::new(), Vec::new());
+        for (i, slot) in dense.iter_mut().enumerate() {
+            if rng.unit() >= density {
+                continue;
+            }
+            let v = (rng.next_u64() % 2001) as f64 / 100.0 - 10.0;
+            *slot = v;
+            idx.push(i as u32);
+            vals.push(v);
+        }
+        (idx, vals, dense)
+    }
+
+    #[test]
+    fn test_sparse_dots_match_dense_references() {
+        let mut rng = Rng(0x6A09_E667_F3BC_C908);
+        let dim = 300;
+        for density in [0.0, 0.02, 0.3, 1.0] {
+            let (ia, va, da) = random_sparse(&mut rng, dim, density);
+            let (ib, vb, db) = random_sparse(&mut rng, dim, 0.2);
+            let reference = kernels::weighted_sum_f64(&da, &db);
+            let sparse = unsafe { tova_sparse_dot(ia.as_ptr(), va.as_ptr(), ia.len(), ib.as_ptr(), vb.as_ptr(), ib.len()) };
+            assert_eq!(sparse, reference, "density {}", density);
+            let mixed = unsafe { tova_sparse_dense_dot(ia.as_ptr(), va.as_ptr(), ia.len(), db.as_ptr(), dim) };
+            assert_eq!(mixed, reference, "density {}", density);
+        }
+        // Empty vectors have a zero dot product
+        let none: [u32; 0] = [];
+        assert_eq!(unsafe { tova_sparse_dot(none.as_ptr(), [].as_ptr(), 0, [1u32].as_ptr(), [2.0].as_ptr(), 1) }, 0.0);
+        assert_eq!(unsafe { tova_sparse_dense_dot(none.as_ptr(), [].as_ptr(), 0, [].as_ptr(), 0) }, 0.0);
+
+        // Indices past the dense vector, and unsorted or repeated indices
+        let dense = [1.0, 2.0, 3.0];
+        assert!(unsafe { tova_sparse_dense_dot([0u32, 3].as_ptr(), [1.0, 1.0].as_ptr(), 2, dense.as_ptr(), 3) }.is_nan());
+        assert_eq!(unsafe { tova_last_error() }, TOVA_SPARSE_ERR_OUT_OF_RANGE);
+        assert_eq!(unsafe { tova_sparse_dense_dot([2u32, 0].as_ptr(), [1.0, 1.0].as_ptr(), 2, dense.as_ptr(), 3) }, 4.0);
+        assert_eq!(unsafe { tova_last_error() }, 0);
+        for bad in [[2u32, 1], [1, 1]] {
+            assert!(unsafe { tova_sparse_dot(bad.as_ptr(), [1.0, 1.0].as_ptr(), 2, [1u32].as_ptr(), [1.0].as_ptr(), 1) }.is_nan());
+            assert_eq!(unsafe { tova_last_error() }, TOVA_SPARSE_ERR_UNSORTED);
+        }
+    }
+
+    #[test]
+    fn test_sparse_topk_ranks_csr_rows_like_a_dense_scan() {
+        let mut rng = Rng(0xBB67_AE85_84CA_A73B);
+        let (dim, nrows) = (120, 200);
+        let (q_idx, q_vals, q_dense) = random_sparse(&mut rng, dim, 0.25);
+        let (mut indptr, mut idx, mut vals, mut dense_rows) = (vec![0u64], Vec::new(), Vec::new(), Vec::new());
+        for r in 0..nrows {
+            // Every tenth row is empty
+            let (ri, rv, rd) = random_sparse(&mut rng, dim, if r % 10 == 3 { 0.0 } else { 0.1 });
+            idx.extend(ri);
+            vals.extend(rv);
+            indptr.push(idx.len() as u64);
+            dense_rows.push(rd);
+        }
+        let mut expected: Vec<(f64, u32)> =
+            dense_rows.iter().enumerate().map(|(r, row)| (kernels::weighted_sum_f64(&q_dense, row), r as u32)).collect();
+        expected.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+
+        let topk = |k: usize, rows: usize| {
+            let (mut out_rows, mut out_scores) = (vec![u32::MAX; k], vec![f64::NAN; k]);
+            let n = unsafe {
+                tova_sparse_topk_dot(
+                    q_idx.as_ptr(),
+                    q_vals.as_ptr(),
+                    q_idx.len(),
+                    indptr.as_ptr(),
+                    idx.as_ptr(),
+                    vals.as_ptr(),
+                    rows,
+                    k,
+                    out_rows.as_mut_ptr(),
+                    out_scores.as_mut_ptr(),
+                )
+            };
+            (n, out_rows, out_scores)
+        };
+        for k in [1, 7, 50] {
+            let (n, rows, scores) = topk(k, nrows);
+            assert_eq!(n, k);
+            let want: Vec<(f64, u32)> = expected[..k].to_vec();
+            assert_eq!(scores.into_iter().zip(rows).collect::<Vec<_>>(), want, "k {}", k);
+        }
+        // More than there are rows: every row, ties (the empty rows' zeros)
+        // to the lower row
+        let (n, rows, _) = topk(nrows + 5, nrows);
+        assert_eq!(n, nrows);
+        assert_eq!(rows[..nrows], expected.iter().map(|e| e.1).collect::<Vec<_>>()[..]);
+        assert_eq!(topk(3, 0).0, 0);
+        assert_eq!(topk(0, nrows).0, 0);
+
+        // A row with repeated indices, and offsets that decrease
+        let (bad_idx, ones, query) = ([1u32, 1], [1.0, 1.0], [1u32]);
+        let run = |indptr: &[u64]| unsafe {
+            let (mut r, mut s) = ([0u32; 2], [0.0f64; 2]);
+            let (q, b) = (query.as_ptr(), bad_idx.as_ptr());
+            tova_sparse_topk_dot(q, ones.as_ptr(), 1, indptr.as_ptr(), b, ones.as_ptr(), 2, 2, r.as_mut_ptr(), s.as_mut_ptr())
+        };
+        assert_eq!(run(&[0, 1, 2]), 2);
+        assert_eq!(run(&[0, 2, 2]), usize::MAX);
+        assert_eq!(unsafe { tova_last_error() }, TOVA_SPARSE_ERR_UNSORTED);
+        assert_eq!(run(&[0, 2, 1]), usize::MAX);
+        assert_eq!(unsafe { tova_last_error() }, TOVA_SPARSE_ERR_INDPTR);
     }
 }
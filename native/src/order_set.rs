@@ -0,0 +1,238 @@
+// Order-statistics multiset of f64 for streaming percentiles over a sliding
+// window: insert, remove, rank and quantile in O(log n) expected time, where
+// re-sorting the window per update would be O(n log n).
+//
+// An indexed skip list over distinct values, each node carrying how many
+// copies of its value the set holds. Every link records its width: how many
+// values it skips over, counting the copies of the node it lands on. Nodes
+// live in a Vec and link by index; removed nodes are recycled.
+
+use crate::kernels;
+
+/// Levels a node may have; with a 1 in 4 chance of each extra level this
+/// covers sets far larger than memory allows
+const MAX_LEVEL: usize = 24;
+
+/// Index of the head node, which holds no value
+const HEAD: usize = 0;
+
+/// Link target past the last node
+const NIL: usize = usize::MAX;
+
+#[derive(Clone, Copy)]
+struct Link {
+    next: usize,
+    /// Values from just after this node up to and including `next`'s copies;
+    /// links to NIL count one past the end
+    width: usize,
+}
+
+struct Node {
+    /// `kernels::bits_order_key` of the value
+    key: u64,
+    count: usize,
+    links: Vec<Link>,
+}
+
+/// Multiset of non-NaN f64 values in ascending order. -0.0 is stored as 0.0.
+pub struct OrderSet {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    len: usize,
+    rng: u64,
+}
+
+impl Default for OrderSet {
+    fn default() -> Self {
+        OrderSet::new()
+    }
+}
+
+impl OrderSet {
+    pub fn new() -> Self {
+        let head = Node { key: 0, count: 0, links: vec![Link { next: NIL, width: 1 }; MAX_LEVEL] };
+        OrderSet { nodes: vec![head], free: Vec::new(), len: 0, rng: 0x2545_F491_4F6C_DD1D }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Add one copy of `value`; false, leaving the set unchanged, for NaN.
+    pub fn insert(&mut self, value: f64) -> bool {
+        let Some(key) = key_of(value) else {
+            return false;
+        };
+        let (update, before) = self.path_below(key);
+        let found = self.nodes[update[0]].links[0].next;
+        if found != NIL && self.nodes[found].key == key {
+            self.nodes[found].count += 1;
+            for (level, &node) in update.iter().enumerate() {
+                self.nodes[node].links[level].width += 1;
+            }
+        } else {
+            let height = self.random_height();
+            // The new node's position: one past everything below it
+            let position = before[0] + 1;
+            let mut links = Vec::with_capacity(height);
+            for (level, &node) in update.iter().enumerate() {
+                let link = &mut self.nodes[node].links[level];
+                if level < height {
+                    links.push(Link { next: link.next, width: link.width + before[level] + 1 - position });
+                    link.width = position - before[level];
+                } else {
+                    link.width += 1;
+                }
+            }
+            let index = self.alloc(Node { key, count: 1, links });
+            for (level, &node) in update.iter().take(height).enumerate() {
+                self.nodes[node].links[level].next = index;
+            }
+        }
+        self.len += 1;
+        true
+    }
+
+    /// Remove one copy of `value`; false if the set holds none.
+    pub fn remove(&mut self, value: f64) -> bool {
+        let Some(key) = key_of(value) else {
+            return false;
+        };
+        let (update, _) = self.path_below(key);
+        let found = self.nodes[update[0]].links[0].next;
+        if found == NIL || self.nodes[found].key != key {
+            return false;
+        }
+        if self.nodes[found].count > 1 {
+            self.nodes[found].count -= 1;
+            for (level, &node) in update.iter().enumerate() {
+                self.nodes[node].links[level].width -= 1;
+            }
+        } else {
+            let links = std::mem::take(&mut self.nodes[found].links);
+            for (level, &node) in update.iter().enumerate() {
+                let link = &mut self.nodes[node].links[level];
+                match links.get(level) {
+                    Some(skipped) => {
+                        link.next = skipped.next;
+                        link.width += skipped.width - 1;
+                    }
+                    None => link.width -= 1,
+                }
+            }
+            self.free.push(found);
+        }
+        self.len -= 1;
+        true
+    }
+
+    /// How many values are <= `value`; 0 for NaN.
+    pub fn rank(&self, value: f64) -> usize {
+        let Some(key) = key_of(value) else {
+            return 0;
+        };
+        let mut node = HEAD;
+        let mut position = 0;
+        for level in (0..MAX_LEVEL).rev() {
+            loop {
+                let link = self.nodes[node].links[level];
+                if link.next == NIL || self.nodes[link.next].key > key {
+                    break;
+                }
+                position += link.width;
+                node = link.next;
+            }
+        }
+        position
+    }
+
+    /// The value at `index` in ascending order, if the set is that long.
+    pub fn select(&self, index: usize) -> Option<f64> {
+        if index >= self.len {
+            return None;
+        }
+        // The node whose copies cover position index + 1
+        let target = index + 1;
+        let mut node = HEAD;
+        let mut position = 0;
+        for level in (0..MAX_LEVEL).rev() {
+            loop {
+                let link = self.nodes[node].links[level];
+                if link.next == NIL || position + link.width >= target {
+                    break;
+                }
+                position += link.width;
+                node = link.next;
+            }
+        }
+        let found = self.nodes[node].links[0].next;
+        Some(f64::from_bits(kernels::order_key_bits(self.nodes[found].key)))
+    }
+
+    /// The value of rank floor(q * (len - 1)), as `tova_profile_f64` takes
+    /// its quartiles; None when empty or `q` is outside [0, 1].
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.len == 0 || !(0.0..=1.0).contains(&q) {
+            return None;
+        }
+        let index = ((q * (self.len - 1) as f64).floor() as usize).min(self.len - 1);
+        self.select(index)
+    }
+
+    /// The last node with a key below `key` at each level, and how many
+    /// values precede or sit on each of them.
+    fn path_below(&self, key: u64) -> ([usize; MAX_LEVEL], [usize; MAX_LEVEL]) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut before = [0; MAX_LEVEL];
+        let mut node = HEAD;
+        let mut position = 0;
+        for level in (0..MAX_LEVEL).rev() {
+            loop {
+                let link = self.nodes[node].links[level];
+                if link.next == NIL || self.nodes[link.next].key >= key {
+                    break;
+                }
+                position += link.width;
+                node = link.next;
+            }
+            update[level] = node;
+            before[level] = position;
+        }
+        (update, before)
+    }
+
+    /// 1 plus one level per pair of trailing zero bits: each extra level
+    /// with probability 1/4.
+    fn random_height(&mut self) -> usize {
+        // xorshift64
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (1 + self.rng.trailing_zeros() as usize / 2).min(MAX_LEVEL)
+    }
+
+    fn alloc(&mut self, node: Node) -> usize {
+        match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+}
+
+/// The value's order key, with -0.0 folded into 0.0; None for NaN.
+fn key_of(value: f64) -> Option<u64> {
+    if value.is_nan() {
+        return None;
+    }
+    Some(kernels::bits_order_key((value + 0.0).to_bits()))
+}
@@ -0,0 +1,117 @@
+// XXH64: the zstd content checksum and the string/row hashing kernels.
+
+const P1: u64 = 0x9E37_79B1_85EB_CA87;
+const P2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+const P3: u64 = 0x1656_67B1_9E37_79F9;
+const P4: u64 = 0x85EB_CA77_C2B2_AE63;
+const P5: u64 = 0x27D4_EB2F_1656_67C5;
+
+pub(crate) fn round(acc: u64, lane: u64) -> u64 {
+    acc.wrapping_add(lane.wrapping_mul(P2)).rotate_left(31).wrapping_mul(P1)
+}
+
+fn merge(acc: u64, v: u64) -> u64 {
+    (acc ^ round(0, v)).wrapping_mul(P1).wrapping_add(P4)
+}
+
+/// Final avalanche shared by the digest and the row-hash mixer.
+pub(crate) fn avalanche(mut h: u64) -> u64 {
+    h ^= h >> 33;
+    h = h.wrapping_mul(P2);
+    h ^= h >> 29;
+    h = h.wrapping_mul(P3);
+    h ^ (h >> 32)
+}
+
+fn lanes(seed: u64) -> [u64; 4] {
+    [seed.wrapping_add(P1).wrapping_add(P2), seed.wrapping_add(P2), seed, seed.wrapping_sub(P1)]
+}
+
+fn stripe(v: &mut [u64; 4], block: &[u8]) {
+    for (i, lane) in block.chunks_exact(8).enumerate() {
+        v[i] = round(v[i], u64::from_le_bytes(lane.try_into().unwrap()));
+    }
+}
+
+/// Digest from the lane state, total length and the unstriped tail (< 32 bytes).
+fn finish(v: [u64; 4], seed: u64, total: u64, mut rest: &[u8]) -> u64 {
+    let mut h = if total >= 32 {
+        let [a, b, c, d] = v;
+        let mut h = a.rotate_left(1).wrapping_add(b.rotate_left(7)).wrapping_add(c.rotate_left(12)).wrapping_add(d.rotate_left(18));
+        for lane in v {
+            h = merge(h, lane);
+        }
+        h
+    } else {
+        seed.wrapping_add(P5)
+    };
+    h = h.wrapping_add(total);
+    while rest.len() >= 8 {
+        let k = round(0, u64::from_le_bytes(rest[..8].try_into().unwrap()));
+        h = (h ^ k).rotate_left(27).wrapping_mul(P1).wrapping_add(P4);
+        rest = &rest[8..];
+    }
+    if rest.len() >= 4 {
+        let k = u32::from_le_bytes(rest[..4].try_into().unwrap()) as u64;
+        h = (h ^ k.wrapping_mul(P1)).rotate_left(23).wrapping_mul(P2).wrapping_add(P3);
+        rest = &rest[4..];
+    }
+    for &b in rest {
+        h = (h ^ (b as u64).wrapping_mul(P5)).rotate_left(11).wrapping_mul(P1);
+    }
+    avalanche(h)
+}
+
+/// One-shot XXH64 of `data`.
+pub(crate) fn xxh64(data: &[u8], seed: u64) -> u64 {
+    let mut v = lanes(seed);
+    let mut stripes = data.chunks_exact(32);
+    for block in &mut stripes {
+        stripe(&mut v, block);
+    }
+    finish(v, seed, data.len() as u64, stripes.remainder())
+}
+
+/// Streaming XXH64 with seed 0.
+#[derive(Clone)]
+#[cfg_attr(not(feature = "zstd"), allow(dead_code))]
+pub(crate) struct Xxh64 {
+    v: [u64; 4],
+    total: u64,
+    buf: [u8; 32],
+    buf_len: usize,
+}
+
+#[cfg_attr(not(feature = "zstd"), allow(dead_code))]
+impl Xxh64 {
+    pub(crate) fn new() -> Self {
+        Xxh64 { v: lanes(0), total: 0, buf: [0; 32], buf_len: 0 }
+    }
+
+    pub(crate) fn update(&mut self, mut data: &[u8]) {
+        self.total += data.len() as u64;
+        if self.buf_len > 0 {
+            let take = (32 - self.buf_len).min(data.len());
+            self.buf[self.buf_len..self.buf_len + take].copy_from_slice(&data[..take]);
+            self.buf_len += take;
+            data = &data[take..];
+            if self.buf_len < 32 {
+                return;
+            }
+            let block = self.buf;
+            stripe(&mut self.v, &block);
+            self.buf_len = 0;
+        }
+        let mut stripes = data.chunks_exact(32);
+        for block in &mut stripes {
+            stripe(&mut self.v, block);
+        }
+        let rest = stripes.remainder();
+        self.buf[..rest.len()].copy_from_slice(rest);
+        self.buf_len = rest.len();
+    }
+
+    pub(crate) fn digest(&self) -> u64 {
+        finish(self.v, 0, self.total, &self.buf[..self.buf_len])
+    }
+}
@@ -0,0 +1,47 @@
+// Writes the Arrow IPC fixtures in native/testdata with arrow-rs, so the
+// reader in src/lib.rs is tested against a real writer and not only the
+// hand-rolled flatbuffers in its own tests.
+use std::fs::File;
+use std::sync::Arc;
+
+use arrow_array::{ArrayRef, Float64Array, Int64Array, RecordBatch};
+use arrow_ipc::writer::{FileWriter, StreamWriter};
+use arrow_schema::{DataType, Field, Schema};
+
+fn batch(schema: &Arc<Schema>, ids: Vec<i64>, values: Vec<Option<f64>>) -> RecordBatch {
+    let columns: Vec<ArrayRef> = vec![Arc::new(Int64Array::from(ids)), Arc::new(Float64Array::from(values))];
+    RecordBatch::try_new(schema.clone(), columns).unwrap()
+}
+
+fn main() {
+    // Column 0 is Int64 so the Float64 column sits at index 1.
+    let dense = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("x", DataType::Float64, false),
+    ]));
+    let batches = [
+        batch(&dense, (0..1000).collect(), (0..1000).map(|i| Some(i as f64 * 0.5 - 100.0)).collect()),
+        batch(&dense, vec![1000, 1001, 1002], vec![Some(-250.0), Some(3.0), Some(999.5)]),
+    ];
+    let mut w = StreamWriter::try_new(File::create("../arrow-f64.arrows").unwrap(), &dense).unwrap();
+    for b in &batches {
+        w.write(b).unwrap();
+    }
+    w.finish().unwrap();
+    let mut w = FileWriter::try_new(File::create("../arrow-f64.arrow").unwrap(), &dense).unwrap();
+    for b in &batches {
+        w.write(b).unwrap();
+    }
+    w.finish().unwrap();
+
+    // Every third value null; the nulls hide the extreme values.
+    let nullable = Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("x", DataType::Float64, true),
+    ]));
+    let values = (0..100).map(|i| if i % 3 == 0 { None } else { Some(i as f64) });
+    let mut w = StreamWriter::try_new(File::create("../arrow-f64-nulls.arrows").unwrap(), &nullable).unwrap();
+    w.write(&batch(&nullable, (0..100).collect(), values.collect())).unwrap();
+    w.write(&batch(&nullable, vec![100, 101], vec![None, None])).unwrap();
+    w.finish().unwrap();
+}
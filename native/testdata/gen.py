@@ -0,0 +1,45 @@
+#!/usr/bin/env python3
+# Regenerates the compressed fixtures used by the decompression tests in
+# src/lib.rs. sample_data() must stay in sync with the Rust helper of the
+# same name. Needs the gzip and zstd command-line tools.
+import subprocess
+import zlib
+
+WORDS = ["alpha", "beta", "gamma", "delta", "epsilon", "zeta", "eta"]
+MASK = (1 << 64) - 1
+
+
+def sample_data():
+    out = bytearray()
+    for i in range(10000):
+        out += f"{i},{(i * 7919) % 1000},{WORDS[i % 7]}\n".encode()
+    x = 0x2545F4914F6CDD1D
+    for _ in range(6000):
+        x ^= (x << 13) & MASK
+        x ^= x >> 7
+        x ^= (x << 17) & MASK
+        out.append(x >> 56)
+    return bytes(out)
+
+
+def run(args, data):
+    return subprocess.run(args, input=data, stdout=subprocess.PIPE, check=True).stdout
+
+
+def write(name, data):
+    with open(name, "wb") as f:
+        f.write(data)
+
+
+data = sample_data()
+write("sample.gz", run(["gzip", "-9", "-n", "-c"], data))
+fixed = zlib.compressobj(6, zlib.DEFLATED, 31, 8, zlib.Z_FIXED)
+write("sample-fixed.gz", fixed.compress(data) + fixed.flush())
+write("sample.zst", run(["zstd", "-3", "--check", "-c", "--content-size"], data))
+write("sample-19.zst", run(["zstd", "-19", "--check", "-c"], data))
+# Streamed from stdin without a size: no content-size field, no checksum
+write("sample-nosize.zst", run(["zstd", "-1", "--no-check", "-c", "--no-content-size"], data))
+# Two frames back to back with a skippable frame between them
+half = len(data) // 2
+skippable = (0x184D2A5A).to_bytes(4, "little") + (5).to_bytes(4, "little") + b"skip!"
+write("sample-multi.zst", run(["zstd", "-5", "-c"], data[:half]) + skippable + run(["zstd", "-5", "-c"], data[half:]))
@@ -0,0 +1,500 @@
+// ABI check for the C exports. The signature table fails to compile if any
+// export is renamed or its parameter/return types change; the symbol scan
+// builds the cdylib and checks it still exports every name unmangled; the
+// header checks keep include/tova_native.h in step with both.
+
+use std::ffi::c_char;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use tova_native::{ColumnDesc, ColumnDescMut, DecompressStream, GroupAgg, HashJoin, NpyInfo, OrderSet, SortF64Handle};
+
+const EXPORTS: &[&str] = &[
+    "tova_sort_f64",
+    "tova_sort_i64",
+    "tova_sort_f64_unstable",
+    "tova_sort_caps",
+    "tova_sort_set_tuning",
+    "tova_sort_get_tuning",
+    "tova_sort_f64_begin",
+    "tova_sort_f64_step",
+    "tova_sort_f64_finish",
+    "tova_sort_f64_abort",
+    "tova_sort_f64_external",
+    "tova_unique_sorted_i64",
+    "tova_unique_sorted_f64",
+    "tova_unique_counts_sorted_i64",
+    "tova_unique_counts_sorted_f64",
+    "tova_sum_f64",
+    "tova_min_f64",
+    "tova_max_f64",
+    "tova_sum_f64_parallel",
+    "tova_min_f64_parallel",
+    "tova_max_f64_parallel",
+    "tova_minmax_f64_parallel",
+    "tova_arrow_sum_f64",
+    "tova_arrow_min_f64",
+    "tova_arrow_max_f64",
+    "tova_arrow_count_f64",
+    "tova_npy_parse_header",
+    "tova_npy_read_f64",
+    "tova_levenshtein",
+    "tova_levenshtein_batch",
+    "tova_hamming_u64",
+    "tova_find_all",
+    "tova_count_occurrences",
+    "tova_find_first",
+    "tova_parse_json_numbers",
+    "tova_gzip_decompress",
+    "tova_decompress_create",
+    "tova_decompress_feed",
+    "tova_decompress_read",
+    "tova_decompress_finish",
+    "tova_decompress_free",
+    "tova_nonzero_u8",
+    "tova_gather_f64",
+    "tova_gather_i64",
+    "tova_scatter_f64",
+    "tova_scatter_i64",
+    "tova_apply_permutation_inplace_f64",
+    "tova_apply_permutation_inplace_i64",
+    "tova_bswap_u64",
+    "tova_bswap_u32",
+    "tova_bswap_u16",
+    "tova_deinterleave",
+    "tova_interleave",
+    "tova_deinterleave_le",
+    "tova_deinterleave_be",
+    "tova_interleave_le",
+    "tova_interleave_be",
+    "tova_version",
+    "tova_features",
+    "tova_abi_version",
+    "tova_selfcheck",
+    "tova_symbols",
+    "tova_last_error",
+    "tova_last_error_message",
+    "tova_rank_f64",
+    "tova_percentile_of_f64",
+    "tova_quantile_edges_f64",
+    "tova_pack_i32_pairs",
+    "tova_unpack_i32_pairs",
+    "tova_weighted_sum_f64",
+    "tova_weighted_mean_f64",
+    "tova_weighted_sum_masked_f64",
+    "tova_weighted_mean_masked_f64",
+    "tova_sum_squares_f64",
+    "tova_merge_groups_f64",
+    "tova_hash_strings",
+    "tova_hash_columns",
+    "tova_mask_from_range_f64",
+    "tova_mask_from_range_f64_packed",
+    "tova_bitmap_and",
+    "tova_bitmap_or",
+    "tova_bitmap_not",
+    "tova_bitmap_count",
+    "tova_filter_bitmap_f64",
+    "tova_profile_f64",
+    "tova_profile_i64",
+    "tova_asof_join_i64",
+    "tova_oset_create",
+    "tova_oset_insert_f64",
+    "tova_oset_remove_f64",
+    "tova_oset_quantile",
+    "tova_oset_rank",
+    "tova_oset_len",
+    "tova_oset_free",
+    "tova_hashjoin_build_i64",
+    "tova_hashjoin_probe_i64",
+    "tova_hashjoin_free",
+    "tova_weighted_sample_f64",
+    "tova_stratified_sample_i64",
+    "tova_minhash_u64",
+    "tova_minhash_similarity",
+    "tova_simhash_u64",
+    "tova_hamming_distance_u64",
+    "tova_sum_scaled_i64",
+    "tova_mean_scaled_i64",
+    "tova_convert_f64_scaled_i64",
+    "tova_gather_columns",
+    "tova_scatter_columns",
+    "tova_sparse_dot",
+    "tova_sparse_dense_dot",
+    "tova_sparse_topk_dot",
+    "tova_filter_above_quantile_f64",
+    "tova_filter_below_quantile_f64",
+    "tova_filter_between_quantiles_f64",
+    "tova_min_i64",
+    "tova_max_i64",
+    "tova_minmax_i64",
+    "tova_argmin_i64",
+    "tova_argmax_i64",
+    "tova_mean_i64",
+    "tova_min_i64_masked",
+    "tova_max_i64_masked",
+    "tova_minmax_i64_masked",
+    "tova_argmin_i64_masked",
+    "tova_argmax_i64_masked",
+    "tova_mean_i64_masked",
+    "tova_min_i64_segmented",
+    "tova_max_i64_segmented",
+    "tova_argmin_i64_segmented",
+    "tova_argmax_i64_segmented",
+    "tova_mean_i64_segmented",
+    "tova_group_agg_create",
+    "tova_group_agg_update",
+    "tova_group_agg_reserve",
+    "tova_group_agg_merge",
+    "tova_group_agg_len",
+    "tova_group_agg_finalize",
+    "tova_group_agg_free",
+    "tova_sort_f64_desc",
+    "tova_sort_i64_desc",
+    "tova_dict_decode_f64",
+    "tova_dict_decode_i64",
+    "tova_dict_decode_f64_masked",
+    "tova_dict_decode_i64_masked",
+    "tova_dict_take_counts",
+    "tova_time_bucket_agg",
+    "tova_argsort_f64",
+];
+
+#[test]
+fn test_export_signatures() {
+    let _: unsafe extern "C" fn(*mut f64, usize) = tova_native::tova_sort_f64;
+    let _: unsafe extern "C" fn(*mut i64, usize) = tova_native::tova_sort_i64;
+    let _: unsafe extern "C" fn(*mut f64, usize) = tova_native::tova_sort_f64_unstable;
+    let _: unsafe extern "C" fn() -> u64 = tova_native::tova_sort_caps;
+    let _: unsafe extern "C" fn(usize, u32, usize) -> i32 = tova_native::tova_sort_set_tuning;
+    let _: unsafe extern "C" fn(*mut usize, *mut u32, *mut usize) = tova_native::tova_sort_get_tuning;
+    let _: unsafe extern "C" fn(*mut f64, usize) -> *mut SortF64Handle = tova_native::tova_sort_f64_begin;
+    let _: unsafe extern "C" fn(*mut SortF64Handle, usize) -> i32 = tova_native::tova_sort_f64_step;
+    let _: unsafe extern "C" fn(*mut SortF64Handle) = tova_native::tova_sort_f64_finish;
+    let _: unsafe extern "C" fn(*mut SortF64Handle) = tova_native::tova_sort_f64_abort;
+    let _: unsafe extern "C" fn(*mut f64, usize, *const c_char, usize) -> i32 = tova_native::tova_sort_f64_external;
+    let _: unsafe extern "C" fn(*mut i64, usize) -> usize = tova_native::tova_unique_sorted_i64;
+    let _: unsafe extern "C" fn(*mut f64, usize) -> usize = tova_native::tova_unique_sorted_f64;
+    let _: unsafe extern "C" fn(*mut i64, usize, *mut u64) -> usize = tova_native::tova_unique_counts_sorted_i64;
+    let _: unsafe extern "C" fn(*mut f64, usize, *mut u64) -> usize = tova_native::tova_unique_counts_sorted_f64;
+    let _: unsafe extern "C" fn(*const f64, usize) -> f64 = tova_native::tova_sum_f64;
+    let _: unsafe extern "C" fn(*const f64, usize) -> f64 = tova_native::tova_min_f64;
+    let _: unsafe extern "C" fn(*const f64, usize) -> f64 = tova_native::tova_max_f64;
+    let _: unsafe extern "C" fn(*const f64, usize, usize) -> f64 = tova_native::tova_sum_f64_parallel;
+    let _: unsafe extern "C" fn(*const f64, usize, usize) -> f64 = tova_native::tova_min_f64_parallel;
+    let _: unsafe extern "C" fn(*const f64, usize, usize) -> f64 = tova_native::tova_max_f64_parallel;
+    let _: unsafe extern "C" fn(*const f64, usize, usize, *mut f64, *mut f64) = tova_native::tova_minmax_f64_parallel;
+    let _: unsafe extern "C" fn(*const u8, usize, u32, *mut f64) -> i32 = tova_native::tova_arrow_sum_f64;
+    let _: unsafe extern "C" fn(*const u8, usize, u32, *mut f64) -> i32 = tova_native::tova_arrow_min_f64;
+    let _: unsafe extern "C" fn(*const u8, usize, u32, *mut f64) -> i32 = tova_native::tova_arrow_max_f64;
+    let _: unsafe extern "C" fn(*const u8, usize, u32, *mut u64) -> i32 = tova_native::tova_arrow_count_f64;
+    let _: unsafe extern "C" fn(*const u8, usize, *mut NpyInfo) -> i32 = tova_native::tova_npy_parse_header;
+    let _: unsafe extern "C" fn(*const u8, usize, *mut f64, usize) -> isize = tova_native::tova_npy_read_f64;
+    let _: unsafe extern "C" fn(*const u8, usize, *const u8, usize, u32) -> u32 = tova_native::tova_levenshtein;
+    let _: unsafe extern "C" fn(*const u8, usize, *const u8, *const u64, usize, u32, *mut u32) = tova_native::tova_levenshtein_batch;
+    let _: unsafe extern "C" fn(*const u64, *const u64, usize) -> u64 = tova_native::tova_hamming_u64;
+    let _: unsafe extern "C" fn(*const u8, usize, *const u8, usize, u32, *mut u64, usize) -> isize = tova_native::tova_find_all;
+    let _: unsafe extern "C" fn(*const u8, usize, *const u8, usize, u32) -> u64 = tova_native::tova_count_occurrences;
+    let _: unsafe extern "C" fn(*const u8, usize, *const u8, usize, u32) -> i64 = tova_native::tova_find_first;
+    let _: unsafe extern "C" fn(*const u8, usize, *mut f64, usize) -> isize = tova_native::tova_parse_json_numbers;
+    let _: unsafe extern "C" fn(*const u8, usize, *mut u8, usize, usize) -> isize = tova_native::tova_gzip_decompress;
+    #[cfg(feature = "zstd")]
+    let _: unsafe extern "C" fn(*const u8, usize, *mut u8, usize, usize) -> isize = tova_native::tova_zstd_decompress;
+    let _: unsafe extern "C" fn(u32, u64) -> *mut DecompressStream = tova_native::tova_decompress_create;
+    let _: unsafe extern "C" fn(*mut DecompressStream, *const u8, usize) -> isize = tova_native::tova_decompress_feed;
+    let _: unsafe extern "C" fn(*mut DecompressStream, *mut u8, usize) -> usize = tova_native::tova_decompress_read;
+    let _: unsafe extern "C" fn(*const DecompressStream) -> isize = tova_native::tova_decompress_finish;
+    let _: unsafe extern "C" fn(*mut DecompressStream) = tova_native::tova_decompress_free;
+    let _: unsafe extern "C" fn(*const u8, usize, *mut u32, usize) -> isize = tova_native::tova_nonzero_u8;
+    let _: unsafe extern "C" fn(*const f64, usize, *const u32, usize, *mut f64) -> isize = tova_native::tova_gather_f64;
+    let _: unsafe extern "C" fn(*const i64, usize, *const u32, usize, *mut i64) -> isize = tova_native::tova_gather_i64;
+    let _: unsafe extern "C" fn(*const f64, *const u32, usize, *mut f64, usize, u32) -> i32 = tova_native::tova_scatter_f64;
+    let _: unsafe extern "C" fn(*const i64, *const u32, usize, *mut i64, usize, u32) -> i32 = tova_native::tova_scatter_i64;
+    let _: unsafe extern "C" fn(*mut f64, *mut u32, usize) -> i32 = tova_native::tova_apply_permutation_inplace_f64;
+    let _: unsafe extern "C" fn(*mut i64, *mut u32, usize) -> i32 = tova_native::tova_apply_permutation_inplace_i64;
+    let _: unsafe extern "C" fn(*mut u64, usize) = tova_native::tova_bswap_u64;
+    let _: unsafe extern "C" fn(*mut u32, usize) = tova_native::tova_bswap_u32;
+    let _: unsafe extern "C" fn(*mut u16, usize) = tova_native::tova_bswap_u16;
+    let _: unsafe extern "C" fn(*const u8, usize, usize, usize, usize, *mut u8) -> i32 = tova_native::tova_deinterleave;
+    let _: unsafe extern "C" fn(*const u8, usize, usize, usize, usize, *mut u8) -> i32 = tova_native::tova_interleave;
+    let _: unsafe extern "C" fn(*const u8, usize, usize, usize, usize, *mut u8) -> i32 = tova_native::tova_deinterleave_le;
+    let _: unsafe extern "C" fn(*const u8, usize, usize, usize, usize, *mut u8) -> i32 = tova_native::tova_deinterleave_be;
+    let _: unsafe extern "C" fn(*const u8, usize, usize, usize, usize, *mut u8) -> i32 = tova_native::tova_interleave_le;
+    let _: unsafe extern "C" fn(*const u8, usize, usize, usize, usize, *mut u8) -> i32 = tova_native::tova_interleave_be;
+    let _: unsafe extern "C" fn() -> *const c_char = tova_native::tova_version;
+    let _: unsafe extern "C" fn() -> u64 = tova_native::tova_features;
+    let _: unsafe extern "C" fn() -> u32 = tova_native::tova_abi_version;
+    let _: unsafe extern "C" fn(*mut u8, usize) -> i32 = tova_native::tova_selfcheck;
+    let _: unsafe extern "C" fn() -> *const c_char = tova_native::tova_symbols;
+    let _: unsafe extern "C" fn() -> i32 = tova_native::tova_last_error;
+    let _: unsafe extern "C" fn(*mut u8, usize) -> usize = tova_native::tova_last_error_message;
+    let _: unsafe extern "C" fn(*const f64, usize, f64) -> usize = tova_native::tova_rank_f64;
+    let _: unsafe extern "C" fn(*const f64, usize, *const f64, usize, *mut f64) = tova_native::tova_percentile_of_f64;
+    let _: unsafe extern "C" fn(*const f64, usize, usize, u32, *mut f64) -> usize = tova_native::tova_quantile_edges_f64;
+    let _: unsafe extern "C" fn(*const i32, *const i32, usize, *mut i64, bool) = tova_native::tova_pack_i32_pairs;
+    let _: unsafe extern "C" fn(*const i64, usize, *mut i32, *mut i32, bool) = tova_native::tova_unpack_i32_pairs;
+    let _: unsafe extern "C" fn(*const f64, *const f64, usize) -> f64 = tova_native::tova_weighted_sum_f64;
+    let _: unsafe extern "C" fn(*const f64, *const f64, usize) -> f64 = tova_native::tova_weighted_mean_f64;
+    let _: unsafe extern "C" fn(*const f64, *const f64, usize) -> f64 = tova_native::tova_weighted_sum_masked_f64;
+    let _: unsafe extern "C" fn(*const f64, *const f64, usize) -> f64 = tova_native::tova_weighted_mean_masked_f64;
+    let _: unsafe extern "C" fn(*const f64, usize) -> f64 = tova_native::tova_sum_squares_f64;
+    let _: unsafe extern "C" fn(
+        *const i64, *const f64, *const u64, usize,
+        *const i64, *const f64, *const u64, usize,
+        *mut i64, *mut f64, *mut u64, usize,
+    ) -> isize = tova_native::tova_merge_groups_f64;
+    let _: unsafe extern "C" fn(*const u8, *const u64, usize, u64, *mut u64) -> i32 = tova_native::tova_hash_strings;
+    let _: unsafe extern "C" fn(*const ColumnDesc, usize, usize, u64, *mut u64) -> i32 = tova_native::tova_hash_columns;
+    let _: unsafe extern "C" fn(*const f64, usize, f64, f64, *mut u8) = tova_native::tova_mask_from_range_f64;
+    let _: unsafe extern "C" fn(*const f64, usize, f64, f64, *mut u8) = tova_native::tova_mask_from_range_f64_packed;
+    let _: unsafe extern "C" fn(*const u8, *const u8, *mut u8, usize) = tova_native::tova_bitmap_and;
+    let _: unsafe extern "C" fn(*const u8, *const u8, *mut u8, usize) = tova_native::tova_bitmap_or;
+    let _: unsafe extern "C" fn(*const u8, *mut u8, usize) = tova_native::tova_bitmap_not;
+    let _: unsafe extern "C" fn(*const u8, usize) -> usize = tova_native::tova_bitmap_count;
+    let _: unsafe extern "C" fn(*mut f64, *const u8, usize) -> usize = tova_native::tova_filter_bitmap_f64;
+    let _: unsafe extern "C" fn(*const f64, *const u8, usize, u32, *mut f64) -> i32 = tova_native::tova_profile_f64;
+    let _: unsafe extern "C" fn(*const i64, *const u8, usize, u32, *mut f64) -> i32 = tova_native::tova_profile_i64;
+    let _: unsafe extern "C" fn(*const i64, usize, *const i64, usize, i64, u32, *mut i64) -> i32 = tova_native::tova_asof_join_i64;
+    let _: unsafe extern "C" fn() -> *mut OrderSet = tova_native::tova_oset_create;
+    let _: unsafe extern "C" fn(*mut OrderSet, f64) -> i32 = tova_native::tova_oset_insert_f64;
+    let _: unsafe extern "C" fn(*mut OrderSet, f64) -> i32 = tova_native::tova_oset_remove_f64;
+    let _: unsafe extern "C" fn(*const OrderSet, f64) -> f64 = tova_native::tova_oset_quantile;
+    let _: unsafe extern "C" fn(*const OrderSet, f64) -> usize = tova_native::tova_oset_rank;
+    let _: unsafe extern "C" fn(*const OrderSet) -> usize = tova_native::tova_oset_len;
+    let _: unsafe extern "C" fn(*mut OrderSet) = tova_native::tova_oset_free;
+    let _: unsafe extern "C" fn(*const i64, usize) -> *mut HashJoin = tova_native::tova_hashjoin_build_i64;
+    let _: unsafe extern "C" fn(*const HashJoin, *const i64, usize, *mut u32, *mut u32, usize) -> isize = tova_native::tova_hashjoin_probe_i64;
+    let _: unsafe extern "C" fn(*mut HashJoin) = tova_native::tova_hashjoin_free;
+    let _: unsafe extern "C" fn(*const f64, *const f64, usize, usize, u64, *mut u32) -> usize = tova_native::tova_weighted_sample_f64;
+    let _: unsafe extern "C" fn(*const i64, usize, usize, u64, *mut u32, usize) -> isize = tova_native::tova_stratified_sample_i64;
+    let _: unsafe extern "C" fn(*const u64, usize, u32, u64, *mut u64) = tova_native::tova_minhash_u64;
+    let _: unsafe extern "C" fn(*const u64, *const u64, u32) -> f64 = tova_native::tova_minhash_similarity;
+    let _: unsafe extern "C" fn(*const u64, *const f64, usize) -> u64 = tova_native::tova_simhash_u64;
+    let _: unsafe extern "C" fn(u64, u64) -> u32 = tova_native::tova_hamming_distance_u64;
+    let _: unsafe extern "C" fn(*const i64, usize, *mut i64, *mut u64) -> i32 = tova_native::tova_sum_scaled_i64;
+    let _: unsafe extern "C" fn(*const i64, usize, u32, i32) -> i64 = tova_native::tova_mean_scaled_i64;
+    let _: unsafe extern "C" fn(*const f64, usize, u32, *mut i64, i32) -> usize = tova_native::tova_convert_f64_scaled_i64;
+    let _: unsafe extern "C" fn(*const u32, usize, *const ColumnDesc, usize, *const ColumnDescMut) -> i32 = tova_native::tova_gather_columns;
+    let _: unsafe extern "C" fn(*const u32, usize, *const ColumnDesc, usize, *const ColumnDescMut) -> i32 = tova_native::tova_scatter_columns;
+    let _: unsafe extern "C" fn(*const u32, *const f64, usize, *const u32, *const f64, usize) -> f64 = tova_native::tova_sparse_dot;
+    let _: unsafe extern "C" fn(*const u32, *const f64, usize, *const f64, usize) -> f64 = tova_native::tova_sparse_dense_dot;
+    let _: unsafe extern "C" fn(*const u32, *const f64, usize, *const u64, *const u32, *const f64, usize, usize, *mut u32, *mut f64) -> usize =
+        tova_native::tova_sparse_topk_dot;
+    let _: unsafe extern "C" fn(*mut f64, usize, f64, u32, *mut i64, *mut f64) -> usize = tova_native::tova_filter_above_quantile_f64;
+    let _: unsafe extern "C" fn(*mut f64, usize, f64, u32, *mut i64, *mut f64) -> usize = tova_native::tova_filter_below_quantile_f64;
+    let _: unsafe extern "C" fn(*mut f64, usize, f64, f64, u32, *mut i64, *mut f64) -> usize =
+        tova_native::tova_filter_between_quantiles_f64;
+    let _: unsafe extern "C" fn(*const i64, usize, *mut i32) -> i64 = tova_native::tova_min_i64;
+    let _: unsafe extern "C" fn(*const i64, usize, *mut i32) -> i64 = tova_native::tova_max_i64;
+    let _: unsafe extern "C" fn(*const i64, usize, *mut i64, *mut i64) -> i32 = tova_native::tova_minmax_i64;
+    let _: unsafe extern "C" fn(*const i64, usize, *mut i32) -> usize = tova_native::tova_argmin_i64;
+    let _: unsafe extern "C" fn(*const i64, usize, *mut i32) -> usize = tova_native::tova_argmax_i64;
+    let _: unsafe extern "C" fn(*const i64, usize, *mut i32) -> f64 = tova_native::tova_mean_i64;
+    let _: unsafe extern "C" fn(*const i64, *const u8, usize, *mut i32) -> i64 = tova_native::tova_min_i64_masked;
+    let _: unsafe extern "C" fn(*const i64, *const u8, usize, *mut i32) -> i64 = tova_native::tova_max_i64_masked;
+    let _: unsafe extern "C" fn(*const i64, *const u8, usize, *mut i64, *mut i64) -> i32 =
+        tova_native::tova_minmax_i64_masked;
+    let _: unsafe extern "C" fn(*const i64, *const u8, usize, *mut i32) -> usize = tova_native::tova_argmin_i64_masked;
+    let _: unsafe extern "C" fn(*const i64, *const u8, usize, *mut i32) -> usize = tova_native::tova_argmax_i64_masked;
+    let _: unsafe extern "C" fn(*const i64, *const u8, usize, *mut i32) -> f64 = tova_native::tova_mean_i64_masked;
+    let _: unsafe extern "C" fn(*const i64, usize, *const u64, usize, *mut i64) -> isize =
+        tova_native::tova_min_i64_segmented;
+    let _: unsafe extern "C" fn(*const i64, usize, *const u64, usize, *mut i64) -> isize =
+        tova_native::tova_max_i64_segmented;
+    let _: unsafe extern "C" fn(*const i64, usize, *const u64, usize, *mut u64) -> isize =
+        tova_native::tova_argmin_i64_segmented;
+    let _: unsafe extern "C" fn(*const i64, usize, *const u64, usize, *mut u64) -> isize =
+        tova_native::tova_argmax_i64_segmented;
+    let _: unsafe extern "C" fn(*const i64, usize, *const u64, usize, *mut f64) -> isize =
+        tova_native::tova_mean_i64_segmented;
+    let _: unsafe extern "C" fn(i32) -> *mut GroupAgg = tova_native::tova_group_agg_create;
+    let _: unsafe extern "C" fn(*mut GroupAgg, *const i64, *const f64, usize) -> i32 = tova_native::tova_group_agg_update;
+    let _: unsafe extern "C" fn(*mut GroupAgg, usize) -> i32 = tova_native::tova_group_agg_reserve;
+    let _: unsafe extern "C" fn(*mut GroupAgg, *const GroupAgg) -> i32 = tova_native::tova_group_agg_merge;
+    let _: unsafe extern "C" fn(*const GroupAgg) -> usize = tova_native::tova_group_agg_len;
+    let _: unsafe extern "C" fn(*mut GroupAgg, *mut i64, *mut f64, *mut u64, usize) -> isize = tova_native::tova_group_agg_finalize;
+    let _: unsafe extern "C" fn(*mut GroupAgg) = tova_native::tova_group_agg_free;
+    let _: unsafe extern "C" fn(*mut f64, usize) = tova_native::tova_sort_f64_desc;
+    let _: unsafe extern "C" fn(*mut i64, usize) = tova_native::tova_sort_i64_desc;
+    let _: unsafe extern "C" fn(*const u32, usize, *const f64, usize, *mut f64) -> isize = tova_native::tova_dict_decode_f64;
+    let _: unsafe extern "C" fn(*const u32, usize, *const i64, usize, *mut i64) -> isize = tova_native::tova_dict_decode_i64;
+    let _: unsafe extern "C" fn(*const u32, *const u8, usize, *const f64, usize, *mut f64) -> isize =
+        tova_native::tova_dict_decode_f64_masked;
+    let _: unsafe extern "C" fn(*const u32, *const u8, usize, *const i64, usize, i64, *mut i64) -> isize =
+        tova_native::tova_dict_decode_i64_masked;
+    let _: unsafe extern "C" fn(*const u32, usize, usize, *mut u64) -> isize = tova_native::tova_dict_take_counts;
+    let _: unsafe extern "C" fn(*const i64, *const f64, usize, i64, i64, i32, u32, *mut i64, *mut f64, *mut u64, usize) -> isize =
+        tova_native::tova_time_bucket_agg;
+    let _: unsafe extern "C" fn(*const f64, usize, *mut u32) -> i32 = tova_native::tova_argsort_f64;
+}
+
+#[test]
+fn test_symbols_list_matches_exports() {
+    let mut listed = Vec::new();
+    let mut p = unsafe { tova_native::tova_symbols() };
+    loop {
+        let name = unsafe { std::ffi::CStr::from_ptr(p) };
+        if name.is_empty() {
+            break;
+        }
+        listed.push(name.to_str().unwrap());
+        p = unsafe { p.add(name.to_bytes().len() + 1) };
+    }
+    let mut expected: Vec<&str> = EXPORTS.to_vec();
+    if cfg!(feature = "zstd") {
+        expected.push("tova_zstd_decompress");
+    }
+    listed.sort_unstable();
+    expected.sort_unstable();
+    assert_eq!(listed, expected);
+}
+
+/// Build the cdylib into its own target dir: `cargo test` only builds the
+/// rlib, so the copy next to the test binary may be stale or missing.
+fn build_cdylib() -> PathBuf {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let target_dir = manifest_dir.join("target").join("abi-check");
+    let status = Command::new(env!("CARGO"))
+        .args(["build", "--lib", "--quiet", "--manifest-path"])
+        .arg(manifest_dir.join("Cargo.toml"))
+        .env("CARGO_TARGET_DIR", &target_dir)
+        .status()
+        .expect("failed to run cargo");
+    assert!(status.success(), "cargo build --lib failed");
+    let name = format!("{}tova_native{}", std::env::consts::DLL_PREFIX, std::env::consts::DLL_SUFFIX);
+    target_dir.join("debug").join(name)
+}
+
+/// Names an ELF shared object defines in its dynamic symbol table, or None
+/// for other formats. Handles the 32- and 64-bit little-endian layouts.
+fn elf_exports(image: &[u8]) -> Option<Vec<String>> {
+    if image.len() < 0x40 || &image[..4] != b"\x7fELF" || image[5] != 1 {
+        return None;
+    }
+    let wide = match image[4] {
+        1 => false,
+        2 => true,
+        _ => return None,
+    };
+    let u16_at = |at: usize| u16::from_le_bytes(image[at..at + 2].try_into().unwrap()) as usize;
+    let u32_at = |at: usize| u32::from_le_bytes(image[at..at + 4].try_into().unwrap()) as usize;
+    let word_at = |at: usize| if wide { u64::from_le_bytes(image[at..at + 8].try_into().unwrap()) as usize } else { u32_at(at) };
+    let (shoff, shentsize, shnum) =
+        if wide { (word_at(0x28), u16_at(0x3A), u16_at(0x3C)) } else { (word_at(0x20), u16_at(0x2E), u16_at(0x30)) };
+    // Offset, size, link and entry size of section `index`
+    let section = |index: usize| {
+        let at = shoff + index * shentsize;
+        if wide {
+            (word_at(at + 0x18), word_at(at + 0x20), u32_at(at + 0x28), word_at(at + 0x38))
+        } else {
+            (word_at(at + 0x10), word_at(at + 0x14), u32_at(at + 0x18), word_at(at + 0x24))
+        }
+    };
+    const SHT_DYNSYM: usize = 11;
+    let dynsym = (0..shnum).find(|&i| u32_at(shoff + i * shentsize + 4) == SHT_DYNSYM)?;
+    let (offset, size, link, entsize) = section(dynsym);
+    let (strings, _, _, _) = section(link);
+    let mut names = Vec::new();
+    for symbol in (offset..offset + size).step_by(entsize) {
+        let (info, shndx) = if wide { (image[symbol + 4], u16_at(symbol + 6)) } else { (image[symbol + 12], u16_at(symbol + 14)) };
+        // Defined (not SHN_UNDEF) and global or weak
+        if shndx == 0 || !matches!(info >> 4, 1 | 2) {
+            continue;
+        }
+        let start = strings + u32_at(symbol);
+        let len = image[start..].iter().position(|&b| b == 0)?;
+        names.push(String::from_utf8_lossy(&image[start..start + len]).into_owned());
+    }
+    Some(names)
+}
+
+#[test]
+fn test_cdylib_exports_every_symbol() {
+    let path = build_cdylib();
+    let image = fs::read(&path).unwrap();
+    if let Some(exports) = elf_exports(&image) {
+        // build_cdylib builds without the zstd feature
+        let mut exported: Vec<&str> = exports.iter().map(String::as_str).filter(|name| name.starts_with("tova_")).collect();
+        let mut expected = EXPORTS.to_vec();
+        exported.sort_unstable();
+        expected.sort_unstable();
+        assert_eq!(exported, expected, "{} exports differ from EXPORTS", path.display());
+        return;
+    }
+    let missing: Vec<&str> = EXPORTS
+        .iter()
+        .copied()
+        .filter(|name| {
+            let mut needle = name.as_bytes().to_vec();
+            needle.push(0);
+            !image.windows(needle.len()).any(|w| w == needle.as_slice())
+        })
+        .collect();
+    assert!(missing.is_empty(), "{} is missing exports: {:?}", path.display(), missing);
+}
+
+const HEADER: &str = include_str!("../include/tova_native.h");
+
+fn generate_header() -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_gen-header")).output().expect("failed to run gen-header");
+    assert!(output.status.success(), "gen-header failed: {}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Function names the header declares.
+fn declared_functions(header: &str) -> Vec<&str> {
+    header
+        .lines()
+        .filter(|line| line.ends_with(");") && !line.starts_with(' '))
+        .filter_map(|line| line.split('(').next()?.rsplit([' ', '*']).next())
+        .collect()
+}
+
+#[test]
+fn test_header_is_generated_from_the_source() {
+    let generated = generate_header();
+    assert_eq!(generated, generate_header(), "gen-header is not deterministic");
+    assert!(
+        generated == HEADER,
+        "include/tova_native.h is stale; run `cargo run --bin gen-header > include/tova_native.h` in native/"
+    );
+}
+
+#[test]
+fn test_header_declares_every_export() {
+    let mut declared = declared_functions(HEADER);
+    let mut expected = EXPORTS.to_vec();
+    expected.push("tova_zstd_decompress");
+    declared.sort_unstable();
+    expected.sort_unstable();
+    assert_eq!(declared, expected);
+    assert!(HEADER.contains(&format!("#define TOVA_ABI_VERSION {}\n", tova_native::TOVA_ABI_VERSION)));
+    for name in ["typedef struct NpyInfo {", "typedef struct ColumnDesc {", "typedef struct OrderSet OrderSet;", "#define TOVA_PROFILE_LEN"] {
+        assert!(HEADER.contains(name), "header lacks {}", name);
+    }
+}
+
+#[test]
+fn test_header_compiles_as_c_and_cpp() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target").join("abi-check");
+    fs::create_dir_all(&dir).unwrap();
+    let include = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("include");
+    for (compiler, file, std) in [("cc", "header.c", "-std=c99"), ("c++", "header.cpp", "-std=c++11")] {
+        let source = dir.join(file);
+        fs::write(&source, "#include \"tova_native.h\"\n").unwrap();
+        for zstd in [false, true] {
+            let mut command = Command::new(compiler);
+            command.args([std, "-Wall", "-Wextra", "-Werror", "-fsyntax-only", "-I"]).arg(&include).arg(&source);
+            if zstd {
+                command.arg("-DTOVA_NATIVE_ZSTD");
+            }
+            let output = match command.output() {
+                Ok(output) => output,
+                Err(err) => {
+                    eprintln!("skipping {}: {}", compiler, err);
+                    break;
+                }
+            };
+            assert!(output.status.success(), "{} rejects the header: {}", compiler, String::from_utf8_lossy(&output.stderr));
+        }
+    }
+}
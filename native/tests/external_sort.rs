@@ -0,0 +1,72 @@
+// `tova_sort_f64_external` on the input it exists for: a file-backed mapping
+// several times the memory budget. Writes 2 GB to the temp directory and
+// spills as much again, so it is ignored by default; run it with
+// `cargo test --release --test external_sort -- --ignored`. std has no mmap,
+// so the test declares libc's (64-bit Linux, where off_t is i64).
+#![cfg(all(target_os = "linux", target_pointer_width = "64"))]
+
+use std::ffi::{c_void, CString};
+use std::fs::{self, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::os::unix::io::AsRawFd;
+use tova_native::tova_sort_f64_external;
+
+extern "C" {
+    fn mmap(addr: *mut c_void, len: usize, prot: i32, flags: i32, fd: i32, offset: i64) -> *mut c_void;
+    fn munmap(addr: *mut c_void, len: usize) -> i32;
+}
+
+const PROT_READ: i32 = 1;
+const PROT_WRITE: i32 = 2;
+const MAP_SHARED: i32 = 1;
+
+/// Order-independent fingerprint of a multiset of bit patterns
+fn fingerprint(acc: (u64, u64), bits: u64) -> (u64, u64) {
+    let mixed = bits.wrapping_mul(0x9E37_79B9_7F4A_7C15).rotate_left(29);
+    (acc.0.wrapping_add(mixed), acc.1 ^ mixed.wrapping_mul(0xC2B2_AE3D_27D4_EB4F))
+}
+
+#[test]
+#[ignore = "expensive: sorts a 2 GB mapped file with a 256 MB budget"]
+fn test_external_sort_of_a_mapped_file_over_budget() {
+    let len = (2usize << 30) / 8;
+    let dir = std::env::temp_dir().join(format!("tova-external-sort-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("column.f64");
+
+    let mut state = 0x2545_F491_4F6C_DD1Du64;
+    let mut expected = (0, 0);
+    {
+        let mut out = BufWriter::new(fs::File::create(&path).unwrap());
+        for i in 0..len {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            // Mostly finite values, with runs of duplicates and some NaNs
+            let bits = match i % 64 {
+                0 => f64::NAN.to_bits() | (state & 0xFFFF),
+                1..=8 => 42.0f64.to_bits(),
+                _ => (((state >> 11) as f64 / (1u64 << 53) as f64 - 0.5) * 1e9).to_bits(),
+            };
+            expected = fingerprint(expected, bits);
+            out.write_all(&bits.to_ne_bytes()).unwrap();
+        }
+        out.flush().unwrap();
+    }
+
+    let file = OpenOptions::new().read(true).write(true).open(&path).unwrap();
+    let bytes = len * 8;
+    let map = unsafe { mmap(std::ptr::null_mut(), bytes, PROT_READ | PROT_WRITE, MAP_SHARED, file.as_raw_fd(), 0) };
+    assert_ne!(map as usize, usize::MAX, "mmap failed");
+    let scratch = CString::new(dir.to_str().unwrap()).unwrap();
+    let code = unsafe { tova_sort_f64_external(map as *mut f64, len, scratch.as_ptr(), 256 << 20) };
+    assert_eq!(code, 0);
+
+    let data = unsafe { std::slice::from_raw_parts(map as *const f64, len) };
+    assert!(data.windows(2).all(|w| w[0].total_cmp(&w[1]).is_le()), "output is not sorted");
+    assert_eq!(data.iter().fold((0, 0), |acc, v| fingerprint(acc, v.to_bits())), expected, "values changed");
+    unsafe { munmap(map, bytes) };
+    // Only the column itself: every run file was removed
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,478 @@
+// Differential property tests for the sorting and search kernels: random
+// inputs from a fixed seed, checked against the standard library. Set
+// TOVA_PROPTEST_SEED to explore other seeds; a failure prints the seed and
+// case so it can be replayed.
+
+use tova_native::{kernels, HashJoin, OrderSet};
+
+const DEFAULT_SEED: u64 = 0x7f4a_7c15_9e37_79b9;
+const CASES: usize = 300;
+
+/// SplitMix64: tiny, seedable and good enough to drive input generation.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    /// Lengths on both sides of the comparison/radix sort cutoff.
+    fn len(&mut self) -> usize {
+        let cutoff = kernels::RADIX_SORT_MIN;
+        match self.below(4) {
+            0 => self.below(8),
+            1 => self.below(200),
+            2 => cutoff - 5 + self.below(10),
+            _ => cutoff + self.below(4000),
+        }
+    }
+
+    /// Arbitrary bit patterns, weighted towards the classes the key
+    /// transform has to get right.
+    fn f64(&mut self) -> f64 {
+        let sign = self.next() << 63;
+        let bits = match self.below(8) {
+            0 => self.next(),
+            // NaN: quiet or signalling, arbitrary payload, either sign
+            1 => sign | 0x7FF0_0000_0000_0000 | (self.next() & 0x000F_FFFF_FFFF_FFFF).max(1),
+            2 => sign | 0x7FF0_0000_0000_0000,
+            3 => sign,
+            // Subnormal
+            4 => sign | (self.next() & 0x000F_FFFF_FFFF_FFFF),
+            5 => sign | [f64::MIN_POSITIVE, f64::MAX, f64::EPSILON, 1.0][self.below(4)].to_bits(),
+            _ => ((self.below(41) as f64) - 20.0).to_bits(),
+        };
+        f64::from_bits(bits)
+    }
+
+    fn i64(&mut self) -> i64 {
+        match self.below(4) {
+            0 => [i64::MIN, i64::MAX, 0, -1][self.below(4)],
+            1 => self.below(21) as i64 - 10,
+            _ => self.next() as i64,
+        }
+    }
+}
+
+fn seed() -> u64 {
+    std::env::var("TOVA_PROPTEST_SEED").ok().and_then(|s| s.parse().ok()).unwrap_or(DEFAULT_SEED)
+}
+
+/// Run `check` over CASES generated inputs, naming the seed and case on failure.
+fn forall(check: impl Fn(&mut Rng)) {
+    let seed = seed();
+    for case in 0..CASES {
+        let mut rng = Rng(seed ^ (case as u64).wrapping_mul(0xD6E8_FEB8_6659_FD93));
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| check(&mut rng)));
+        if let Err(panic) = result {
+            eprintln!("property failed: TOVA_PROPTEST_SEED={} case {}", seed, case);
+            std::panic::resume_unwind(panic);
+        }
+    }
+}
+
+fn bits(values: &[f64]) -> Vec<u64> {
+    values.iter().map(|v| v.to_bits()).collect()
+}
+
+#[test]
+fn prop_sort_f64_matches_total_cmp() {
+    forall(|rng| {
+        let n = rng.len();
+        let mut data: Vec<f64> = (0..n).map(|_| rng.f64()).collect();
+        let mut expected = data.clone();
+        expected.sort_unstable_by(f64::total_cmp);
+        kernels::sort_f64(&mut data);
+        assert_eq!(bits(&data), bits(&expected), "len {}", n);
+    });
+}
+
+#[test]
+fn prop_sort_i64_matches_std() {
+    forall(|rng| {
+        let n = rng.len();
+        let mut data: Vec<i64> = (0..n).map(|_| rng.i64()).collect();
+        let mut expected = data.clone();
+        expected.sort_unstable();
+        kernels::sort_i64(&mut data);
+        assert_eq!(data, expected, "len {}", n);
+    });
+}
+
+#[test]
+fn prop_unique_sorted_matches_dedup() {
+    forall(|rng| {
+        let n = rng.len();
+        let mut ints: Vec<i64> = (0..n).map(|_| rng.i64()).collect();
+        kernels::sort_i64(&mut ints);
+        let mut expected = ints.clone();
+        expected.dedup();
+        let len = kernels::unique_sorted(&mut ints);
+        assert_eq!(&ints[..len], &expected[..]);
+
+        let mut floats: Vec<f64> = (0..n).map(|_| rng.f64()).collect();
+        kernels::sort_f64(&mut floats);
+        let mut expected = floats.clone();
+        expected.dedup();
+        let len = kernels::unique_sorted(&mut floats);
+        assert_eq!(bits(&floats[..len]), bits(&expected));
+    });
+}
+
+#[test]
+fn prop_unique_counts_match_hashed_value_counts() {
+    use std::collections::HashMap;
+    forall(|rng| {
+        let n = rng.len();
+        let mut ints: Vec<i64> = (0..n).map(|_| rng.i64()).collect();
+        let mut expected: Vec<(i64, u64)> = ints
+            .iter()
+            .fold(HashMap::new(), |mut m, &v| {
+                *m.entry(v).or_insert(0u64) += 1;
+                m
+            })
+            .into_iter()
+            .collect();
+        expected.sort_unstable();
+        kernels::sort_i64(&mut ints);
+        let mut counts = vec![0u64; n];
+        let len = kernels::unique_counts_sorted_i64(&mut ints, &mut counts);
+        assert_eq!(ints[..len].iter().copied().zip(counts[..len].iter().copied()).collect::<Vec<_>>(), expected);
+
+        let mut floats: Vec<f64> = (0..n).map(|_| rng.f64()).collect();
+        let mut expected: Vec<(u64, u64)> = floats
+            .iter()
+            .fold(HashMap::new(), |mut m, &v| {
+                *m.entry(v.to_bits()).or_insert(0u64) += 1;
+                m
+            })
+            .into_iter()
+            .collect();
+        expected.sort_unstable_by(|a, b| f64::from_bits(a.0).total_cmp(&f64::from_bits(b.0)));
+        kernels::sort_f64(&mut floats);
+        let len = kernels::unique_counts_sorted_f64(&mut floats, &mut counts);
+        assert_eq!(bits(&floats[..len]).into_iter().zip(counts[..len].iter().copied()).collect::<Vec<_>>(), expected);
+    });
+}
+
+#[test]
+fn prop_rank_matches_partition_point() {
+    forall(|rng| {
+        let n = rng.len();
+        let mut sorted: Vec<f64> = (0..n).map(|_| rng.f64()).collect();
+        // rank_f64 expects NaNs at the end, as sort_f64 puts positive ones
+        for v in sorted.iter_mut().filter(|v| v.is_nan()) {
+            *v = f64::NAN;
+        }
+        kernels::sort_f64(&mut sorted);
+        let numbers = sorted.partition_point(|v| !v.is_nan());
+        for _ in 0..16 {
+            let query = if n > 0 && rng.below(2) == 0 { sorted[rng.below(n)] } else { rng.f64() };
+            let expected = if query.is_nan() { 0 } else { sorted[..numbers].partition_point(|&v| v <= query) };
+            assert_eq!(kernels::rank_f64(&sorted, query), expected, "query {:?}", query);
+        }
+    });
+}
+
+#[test]
+fn prop_sorting_is_idempotent_across_the_cutoff() {
+    forall(|rng| {
+        let n = rng.len();
+        let mut once: Vec<f64> = (0..n).map(|_| rng.f64()).collect();
+        kernels::sort_f64(&mut once);
+        let mut twice = once.clone();
+        kernels::sort_f64(&mut twice);
+        assert_eq!(bits(&once), bits(&twice));
+        assert!(once.windows(2).all(|w| w[0].total_cmp(&w[1]).is_le()));
+    });
+}
+
+#[test]
+fn prop_radix_sorts_match_std() {
+    // sort_i64 never takes its radix path, so check it directly
+    forall(|rng| {
+        let n = rng.len();
+        let mut ints: Vec<i64> = (0..n).map(|_| rng.i64()).collect();
+        let mut expected = ints.clone();
+        expected.sort_unstable();
+        kernels::bench::radix_sort_i64(&mut ints);
+        assert_eq!(ints, expected);
+
+        let mut floats: Vec<f64> = (0..n).map(|_| rng.f64()).collect();
+        let mut expected = floats.clone();
+        expected.sort_unstable_by(f64::total_cmp);
+        kernels::bench::radix_sort_f64(&mut floats);
+        assert_eq!(bits(&floats), bits(&expected));
+    });
+}
+
+#[test]
+fn prop_radix_widths_and_parallel_sort_match_std() {
+    forall(|rng| {
+        let n = rng.len();
+        let floats: Vec<f64> = (0..n).map(|_| rng.f64()).collect();
+        let mut expected = floats.clone();
+        expected.sort_unstable_by(f64::total_cmp);
+        for width in kernels::RADIX_BITS {
+            let mut sorted = floats.clone();
+            kernels::bench::radix_sort_f64_bits(&mut sorted, width);
+            assert_eq!(bits(&sorted), bits(&expected), "{} bits", width);
+        }
+        let mut sorted = floats.clone();
+        kernels::bench::parallel_sort_f64(&mut sorted, 1 + rng.below(8));
+        assert_eq!(bits(&sorted), bits(&expected));
+    });
+}
+
+#[test]
+fn prop_stepped_sort_matches_std() {
+    forall(|rng| {
+        let n = rng.len();
+        let floats: Vec<f64> = (0..n).map(|_| rng.f64()).collect();
+        let mut expected = floats.clone();
+        expected.sort_unstable_by(f64::total_cmp);
+        let mut keys = bits(&floats);
+        let mut sort = kernels::IncrementalSortF64::new(n);
+        let budget = 1 + rng.below(n + 1);
+        while !sort.step(&mut keys, budget) {}
+        assert_eq!(keys, bits(&expected), "budget {}", budget);
+    });
+}
+
+#[test]
+fn prop_cancelled_sorts_leave_a_permutation() {
+    forall(|rng| {
+        let n = rng.len();
+        let floats: Vec<f64> = (0..n).map(|_| rng.f64()).collect();
+        let mut expected = floats.clone();
+        expected.sort_unstable_by(f64::total_cmp);
+        // Stop at any report, including ones past the last
+        let stop_at = rng.below(12);
+        let reports = std::cell::RefCell::new(Vec::new());
+        let progress = |done: u64| {
+            reports.borrow_mut().push(done);
+            reports.borrow().len() <= stop_at
+        };
+        let mut sorted = floats.clone();
+        let result = kernels::sort_f64_with_progress(&mut sorted, &progress);
+        let reports = reports.into_inner();
+        assert!(reports.windows(2).all(|w| w[0] <= w[1]), "{:?}", reports);
+        match result {
+            Ok(()) => {
+                assert_eq!(bits(&sorted), bits(&expected), "stop at {}", stop_at);
+                assert_eq!(reports.last(), Some(&kernels::PROGRESS_DONE));
+                assert!(reports[..reports.len() - 1].iter().all(|&done| done < kernels::PROGRESS_DONE));
+            }
+            Err(kernels::Cancelled) => {
+                assert_eq!(reports.len(), stop_at + 1);
+                sorted.sort_unstable_by(f64::total_cmp);
+                assert_eq!(bits(&sorted), bits(&expected), "stop at {}", stop_at);
+            }
+        }
+
+        let ints: Vec<i64> = (0..n).map(|_| rng.i64()).collect();
+        let mut expected = ints.clone();
+        expected.sort_unstable();
+        let calls = std::cell::Cell::new(0);
+        let mut sorted = ints.clone();
+        let result = kernels::sort_i64_with_progress(&mut sorted, &|_| {
+            calls.set(calls.get() + 1);
+            calls.get() <= stop_at
+        });
+        if result.is_err() {
+            sorted.sort_unstable();
+        }
+        assert_eq!(sorted, expected, "stop at {}", stop_at);
+    });
+}
+
+#[test]
+fn prop_tracked_sum_matches_sum() {
+    forall(|rng| {
+        let n = rng.below(3 * kernels::PROGRESS_INTERVAL);
+        let floats: Vec<f64> = (0..n).map(|_| rng.f64()).collect();
+        let reports = std::cell::RefCell::new(Vec::new());
+        let sum = kernels::sum_f64_with_progress(&floats, &|done| {
+            reports.borrow_mut().push(done);
+            true
+        });
+        assert_eq!(sum.map(f64::to_bits), Ok(kernels::sum_f64(&floats).to_bits()));
+        let reports = reports.into_inner();
+        assert_eq!(reports.len(), n.div_ceil(kernels::PROGRESS_INTERVAL) + 2);
+        assert!(reports.windows(2).all(|w| w[0] <= w[1]) && reports.last() == Some(&kernels::PROGRESS_DONE));
+
+        let stopped = kernels::sum_f64_with_progress(&floats, &|done| done == 0);
+        assert_eq!(stopped.is_err(), n > 0);
+    });
+}
+
+#[test]
+fn prop_asof_join_matches_linear_scan() {
+    use kernels::AsofDirection::{Backward, Forward, Nearest};
+    forall(|rng| {
+        // Small timestamps so both sides share plenty of exact and duplicate values
+        let mut side = || {
+            let n = rng.below(48);
+            let mut ts: Vec<i64> = (0..n).map(|_| rng.below(64) as i64 - 32).collect();
+            ts.sort_unstable();
+            ts
+        };
+        let (left, right) = (side(), side());
+        let tolerance = rng.below(12) as u64;
+        let within = |t: i64, i: usize| (right[i].abs_diff(t) <= tolerance).then_some(i);
+        for direction in [Backward, Forward, Nearest] {
+            let mut out = vec![0; left.len()];
+            kernels::asof_join_i64(&left, &right, tolerance, direction, &mut out).unwrap();
+            for (&t, &got) in left.iter().zip(&out) {
+                let backward = right.iter().rposition(|&r| r <= t).and_then(|i| within(t, i));
+                let forward = right.iter().position(|&r| r >= t).and_then(|i| within(t, i));
+                let expected = match (direction, backward, forward) {
+                    (Backward, b, _) => b,
+                    (Forward, _, f) => f,
+                    (Nearest, Some(b), Some(f)) => Some(if t - right[b] <= right[f] - t { b } else { f }),
+                    (Nearest, b, f) => b.or(f),
+                };
+                assert_eq!(got, expected.map_or(-1, |i| i as i64), "{direction:?} t {t} tolerance {tolerance}");
+            }
+        }
+    });
+}
+
+/// Inner join by sorting both sides and merging runs of equal keys, as
+/// (probe row, build row) pairs in the order the hash join emits them.
+fn merge_join(build: &[i64], probe: &[i64]) -> Vec<(u32, u32)> {
+    let sorted = |keys: &[i64]| {
+        let mut rows: Vec<u32> = (0..keys.len() as u32).collect();
+        rows.sort_by_key(|&row| (keys[row as usize], row));
+        rows
+    };
+    let (build_rows, probe_rows) = (sorted(build), sorted(probe));
+    let mut pairs = Vec::new();
+    let (mut b, mut p) = (0, 0);
+    while b < build_rows.len() && p < probe_rows.len() {
+        let (bk, pk) = (build[build_rows[b] as usize], probe[probe_rows[p] as usize]);
+        if bk < pk {
+            b += 1;
+        } else if pk < bk {
+            p += 1;
+        } else {
+            let b_end = b + build_rows[b..].iter().take_while(|&&row| build[row as usize] == bk).count();
+            let p_end = p + probe_rows[p..].iter().take_while(|&&row| probe[row as usize] == pk).count();
+            for &probe_row in &probe_rows[p..p_end] {
+                pairs.extend(build_rows[b..b_end].iter().map(|&build_row| (probe_row, build_row)));
+            }
+            (b, p) = (b_end, p_end);
+        }
+    }
+    pairs.sort_unstable();
+    pairs
+}
+
+#[test]
+fn prop_hash_join_matches_merge_join() {
+    forall(|rng| {
+        // A few distinct keys make heavy duplicates; a disjoint probe side none
+        let distinct = 1 + rng.below(64);
+        let keys: Vec<i64> = (0..distinct).map(|_| rng.i64()).collect();
+        let build: Vec<i64> = (0..rng.below(300)).map(|_| keys[rng.below(distinct)]).collect();
+        let probe: Vec<i64> = match rng.below(4) {
+            0 => (0..rng.below(300)).map(|_| rng.i64()).filter(|k| !keys.contains(k)).collect(),
+            _ => (0..rng.below(300)).map(|_| if rng.below(4) == 0 { rng.i64() } else { keys[rng.below(distinct)] }).collect(),
+        };
+        let join = HashJoin::build(&build).unwrap();
+        assert_eq!(join.len(), build.len());
+        let mut pairs = Vec::new();
+        let count = join.probe(&probe, |p, b| pairs.push((p, b)));
+        assert_eq!(count, pairs.len());
+        assert_eq!(pairs, merge_join(&build, &probe), "build {build:?} probe {probe:?}");
+    });
+}
+
+#[test]
+fn prop_order_set_matches_sorted_vec() {
+    forall(|rng| {
+        // Few distinct values, so removals hit duplicates and empty the set
+        let distinct = 1 + rng.below(40);
+        let values: Vec<f64> = (0..distinct).map(|_| rng.f64()).filter(|v| !v.is_nan()).collect();
+        if values.is_empty() {
+            return;
+        }
+        let mut set = OrderSet::new();
+        let mut reference: Vec<f64> = Vec::new();
+        for step in 0..rng.below(400) {
+            let value = values[rng.below(values.len())];
+            // -0.0 is stored as 0.0
+            let value_or_zero = value + 0.0;
+            let at = reference.partition_point(|&v| v < value_or_zero);
+            if rng.below(5) < 3 {
+                assert!(set.insert(value));
+                reference.insert(at, value_or_zero);
+            } else {
+                let present = reference.get(at) == Some(&value_or_zero);
+                assert_eq!(set.remove(value), present, "step {step} remove {value}");
+                if present {
+                    reference.remove(at);
+                }
+            }
+            assert_eq!(set.len(), reference.len());
+            let probe = values[rng.below(values.len())];
+            assert_eq!(set.rank(probe), reference.partition_point(|&v| v <= probe), "step {step} rank {probe}");
+            for q in [0.0, 0.25, 0.5, 0.99, 1.0, rng.below(1001) as f64 / 1000.0] {
+                let expected = (!reference.is_empty()).then(|| reference[(q * (reference.len() - 1) as f64).floor() as usize]);
+                assert_eq!(set.quantile(q).map(f64::to_bits), expected.map(f64::to_bits), "step {step} q {q}");
+            }
+        }
+    });
+}
+
+#[test]
+fn prop_min_max_match_total_order_without_nan() {
+    forall(|rng| {
+        let n = rng.below(64);
+        let data: Vec<f64> = (0..n).map(|_| rng.f64()).collect();
+        let mut numbers: Vec<f64> = data.iter().copied().filter(|v| !v.is_nan()).collect();
+        numbers.sort_unstable_by(f64::total_cmp);
+        let (min, max) = (kernels::min_f64(&data), kernels::max_f64(&data));
+        match (numbers.first(), numbers.last()) {
+            (Some(lo), Some(hi)) => {
+                assert_eq!(min.to_bits(), lo.to_bits());
+                assert_eq!(max.to_bits(), hi.to_bits());
+            }
+            _ => assert!(min.is_nan() && max.is_nan()),
+        }
+    });
+}
+
+// --- Regressions found by the properties above ---
+
+// The small-input path used to compare with `>`, so NaNs stayed where they
+// were and -0.0/+0.0 kept input order, while the radix path ordered by bits.
+#[test]
+fn regression_small_sort_orders_negative_nan_first() {
+    let neg_nan = f64::from_bits(0xFFF8_0000_0000_0001);
+    let mut data = vec![1.0, neg_nan, f64::NEG_INFINITY, -2.0];
+    kernels::sort_f64(&mut data);
+    assert_eq!(bits(&data), bits(&[neg_nan, f64::NEG_INFINITY, -2.0, 1.0]));
+}
+
+#[test]
+fn regression_small_sort_moves_nan_past_numbers() {
+    let mut data = vec![3.0, f64::NAN, 1.0, f64::INFINITY, 2.0];
+    kernels::sort_f64(&mut data);
+    assert_eq!(bits(&data), bits(&[1.0, 2.0, 3.0, f64::INFINITY, f64::NAN]));
+}
+
+#[test]
+fn regression_small_sort_orders_signed_zeros() {
+    let mut data = vec![0.0, -0.0, 0.0, -0.0];
+    kernels::sort_f64(&mut data);
+    assert_eq!(bits(&data), bits(&[-0.0, -0.0, 0.0, 0.0]));
+}
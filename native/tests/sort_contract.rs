@@ -0,0 +1,113 @@
+// The promises `tova_sort_caps` makes, checked directly: stability with
+// tagged values on both sides of the radix cutoff, and no allocation in the
+// unstable sort. The counting allocator is why this is its own test binary.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use tova_native::kernels;
+use tova_native::*;
+
+struct CountingAlloc;
+
+thread_local! {
+    // Per thread, so tests running in parallel do not count each other
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn allocations_during(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(|n| n.get());
+    f();
+    ALLOCATIONS.with(|n| n.get()) - before
+}
+
+/// Values with heavy duplication, including the special classes, so every
+/// equal-key run is long.
+fn tagged(n: usize) -> Vec<(f64, u32)> {
+    let palette = [3.0, -1.0, 0.0, -0.0, f64::NAN, -f64::NAN, f64::INFINITY, 2.5];
+    let mut state = n as u64 | 1;
+    (0..n as u32)
+        .map(|i| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (palette[(state % palette.len() as u64) as usize], i)
+        })
+        .collect()
+}
+
+fn assert_stable(sorted: &[(f64, u32)]) {
+    for w in sorted.windows(2) {
+        let order = w[0].0.total_cmp(&w[1].0);
+        assert!(order.is_le(), "out of order: {:?}", w);
+        if order.is_eq() {
+            assert!(w[0].1 < w[1].1, "equal keys reordered: {:?}", w);
+        }
+    }
+}
+
+#[test]
+fn test_caps_declare_the_sort_contract() {
+    let caps = unsafe { tova_sort_caps() };
+    for bit in [
+        TOVA_SORT_F64_STABLE,
+        TOVA_SORT_F64_TOTAL_ORDER,
+        TOVA_SORT_I64_STABLE,
+        TOVA_SORT_F64_UNSTABLE_TOTAL_ORDER,
+        TOVA_SORT_F64_UNSTABLE_NO_ALLOC,
+    ] {
+        assert_ne!(caps & bit, 0, "missing cap {:#x}", bit);
+    }
+}
+
+#[test]
+fn test_sort_by_key_is_stable_on_both_paths() {
+    for n in [0, 1, 50, kernels::RADIX_SORT_MIN - 1, kernels::RADIX_SORT_MIN, 3 * kernels::RADIX_SORT_MIN] {
+        let mut items = tagged(n);
+        kernels::sort_f64_by_key(&mut items, |item| item.0);
+        assert_stable(&items);
+        assert_eq!(items.len(), n);
+    }
+}
+
+#[test]
+fn test_sort_f64_matches_the_stable_sort() {
+    // Keys that compare equal are the same bits, so a stable sort of the
+    // tagged values, untagged, is what sort_f64 must produce
+    let n = 2 * kernels::RADIX_SORT_MIN;
+    let mut items = tagged(n);
+    let mut values: Vec<f64> = items.iter().map(|item| item.0).collect();
+    kernels::sort_f64_by_key(&mut items, |item| item.0);
+    unsafe { tova_sort_f64(values.as_mut_ptr(), values.len()) };
+    let expected: Vec<u64> = items.iter().map(|item| item.0.to_bits()).collect();
+    assert_eq!(values.iter().map(|v| v.to_bits()).collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn test_unstable_sort_does_not_allocate() {
+    let n = 4 * kernels::RADIX_SORT_MIN;
+    let mut values: Vec<f64> = tagged(n).into_iter().map(|item| item.0).collect();
+    let mut expected = values.clone();
+    let mut radix = values.clone();
+
+    let allocated = allocations_during(|| unsafe { tova_sort_f64_unstable(values.as_mut_ptr(), values.len()) });
+    assert_eq!(allocated, 0);
+    expected.sort_by(f64::total_cmp);
+    assert_eq!(values.iter().map(|v| v.to_bits()).collect::<Vec<_>>(), expected.iter().map(|v| v.to_bits()).collect::<Vec<_>>());
+
+    // The counter does see the radix path's scratch buffer
+    assert!(allocations_during(|| kernels::sort_f64(&mut radix)) > 0);
+}
@@ -5,6 +5,10 @@
 let _lib = null;
 let _available = false;
 
+// Must match TOVA_ABI_VERSION in native/src/lib.rs. A library built from
+// other sources is ignored rather than failing at its first call.
+const EXPECTED_ABI_VERSION = 23;
+
 function _findLibrary() {
   const { existsSync } = require('fs');
   const { join, dirname } = require('path');
@@ -49,6 +53,10 @@ function _init() {
 
     const { dlopen, FFIType } = require('bun:ffi');
     _lib = dlopen(libPath, {
+      tova_abi_version: {
+        args: [],
+        returns: FFIType.u32,
+      },
       tova_sort_f64: {
         args: [FFIType.ptr, FFIType.u64],
         returns: FFIType.void,
@@ -78,6 +86,12 @@ function _init() {
         returns: FFIType.f64,
       },
     });
+    if (_lib.symbols.tova_abi_version() !== EXPECTED_ABI_VERSION) {
+      _lib.close();
+      _lib = false;
+      _available = false;
+      return false;
+    }
     _available = true;
     return true;
   } catch (e) {
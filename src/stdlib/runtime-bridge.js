@@ -74,9 +74,9 @@ function healthCheck() {
     return _runtime.healthCheck();
 }
 
-function channelCreate(capacity) {
+function channelCreate(capacity, options) {
     if (!_init()) throw new Error('tova_runtime not available');
-    return _runtime.channelCreate(capacity);
+    return _runtime.channelCreate(capacity, options);
 }
 
 function channelSend(id, value) {
@@ -219,4 +219,113 @@ function generateConsumerModule() {
     return new Uint8Array(bytes);
 }
 
-module.exports = { generateProducerModule, generateConsumerModule };
+/**
+ * Send-once: forwards one value and returns chan_send's status unchanged
+ * Exports: send_once(channel_id: i32, value: i64) -> i32
+ * Imports: tova.chan_send(ch: i32, val: i64) -> i32
+ */
+function generateSendOnceModule() {
+    const bytes = [];
+    bytes.push(0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00);
+
+    // Type section: type0 = (i32, i64) -> i32, shared by the import and the export
+    bytes.push(...encodeSection(1, [1, FUNC_TYPE, 2, I32, I64, 1, I32]));
+
+    const importBody = [
+        1,
+        ...encodeString("tova"),
+        ...encodeString("chan_send"),
+        0x00, 0,                            // kind=func, type index 0
+    ];
+    bytes.push(...encodeSection(2, importBody));
+
+    // Function section: func 1 uses type 0
+    bytes.push(...encodeSection(3, [1, 0]));
+
+    bytes.push(...encodeSection(7, [1, ...encodeString("send_once"), 0x00, 1]));
+
+    const funcBody = [
+        0,                              // no locals
+        0x20, 0x00,                     // local.get 0 (ch_id)
+        0x20, 0x01,                     // local.get 1 (value)
+        0x10, 0x00,                     // call func 0 (chan_send)
+        0x0B,                           // end function
+    ];
+    bytes.push(...encodeSection(10, [1, ...uleb128(funcBody.length), ...funcBody]));
+    return new Uint8Array(bytes);
+}
+
+/**
+ * Drain: receives until the channel ends, returns how many values it took
+ * (or chan_receive_status's status if that was not CLOSED)
+ * Exports: drain(channel_id: i32) -> i64, memory
+ * Imports: tova.chan_receive_status(ch: i32, out: i32) -> i32
+ */
+function generateDrainModule() {
+    const bytes = [];
+    bytes.push(0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00);
+
+    // Type section: type0 = chan_receive_status(i32, i32) -> i32, type1 = drain(i32) -> i64
+    bytes.push(...encodeSection(1, [2, FUNC_TYPE, 2, I32, I32, 1, I32, FUNC_TYPE, 1, I32, 1, I64]));
+
+    const importBody = [
+        1,
+        ...encodeString("tova"),
+        ...encodeString("chan_receive_status"),
+        0x00, 0,                            // kind=func, type index 0
+    ];
+    bytes.push(...encodeSection(2, importBody));
+
+    // Function section: func 1 uses type 1
+    bytes.push(...encodeSection(3, [1, 1]));
+
+    // Memory section: one memory, min 1 page; received values land at offset 0
+    bytes.push(...encodeSection(5, [1, 0x00, 1]));
+
+    const exportBody = [
+        2,
+        ...encodeString("drain"), 0x00, 1,  // kind=func, func index 1
+        ...encodeString("memory"), 0x02, 0, // kind=memory, memory index 0
+    ];
+    bytes.push(...encodeSection(7, exportBody));
+
+    // Params: local 0 = ch_id (i32)
+    // Locals: local 1 = status (i32), local 2 = count (i64)
+    const funcBody = [
+        2,                              // 2 local declarations
+        1, I32,                         // local 1 = status
+        1, I64,                         // local 2 = count
+
+        // loop: status = chan_receive_status(ch_id, 0); while status == 0, count++
+        0x03, 0x40,
+          0x20, 0x00,                   // local.get 0 (ch_id)
+          0x41, 0x00,                   // i32.const 0 (out)
+          0x10, 0x00,                   // call func 0 (chan_receive_status)
+          0x22, 0x01,                   // local.tee 1 (status)
+          0x45,                         // i32.eqz
+          0x04, 0x40,                   // if
+            0x20, 0x02,                 // local.get 2 (count)
+            0x42, 0x01,                 // i64.const 1
+            0x7C,                       // i64.add
+            0x21, 0x02,                 // local.set 2 (count)
+            0x0C, 0x01,                 // br 1 (loop)
+          0x0B,                         // end if
+        0x0B,                           // end loop
+
+        // status == -1 (closed) ? count : status
+        0x20, 0x01,                     // local.get 1 (status)
+        0x41, 0x7F,                     // i32.const -1
+        0x46,                           // i32.eq
+        0x04, I64,                      // if (result i64)
+          0x20, 0x02,                   // local.get 2 (count)
+        0x05,                           // else
+          0x20, 0x01,                   // local.get 1 (status)
+          0xAC,                         // i64.extend_i32_s
+        0x0B,                           // end if
+        0x0B,                           // end function
+    ];
+    bytes.push(...encodeSection(10, [1, ...uleb128(funcBody.length), ...funcBody]));
+    return new Uint8Array(bytes);
+}
+
+module.exports = { generateProducerModule, generateConsumerModule, generateSendOnceModule, generateDrainModule };
@@ -141,4 +141,333 @@ function generateFibModule() {
     return new Uint8Array(bytes);
 }
 
-module.exports = { generateAddModule, generateFibModule };
+/**
+ * Generate a WASM module exporting `name(i64) -> i64` whose body is `ops`
+ * (instructions operating on local 0, leaving one i64 on the stack).
+ */
+function generateUnaryModule(name, ops) {
+    const bytes = [];
+    // WASM magic number + version
+    bytes.push(0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00);
+    // Type section: 1 type = (i64) -> i64
+    bytes.push(...encodeSection(1, [1, FUNC_TYPE, 1, I64, 1, I64]));
+    // Function section: 1 function using type index 0
+    bytes.push(...encodeSection(3, [1, 0]));
+    // Export section: export `name` as function index 0
+    bytes.push(...encodeSection(7, [1, ...encodeString(name), 0x00, 0]));
+    // Code section: no locals, body, end
+    const funcBody = [0, ...ops, 0x0B];
+    bytes.push(...encodeSection(10, [1, ...uleb128(funcBody.length), ...funcBody]));
+    return new Uint8Array(bytes);
+}
+
+/**
+ * `double(x) = x * 2`
+ */
+function generateDoubleModule() {
+    // local.get 0, i64.const 2, i64.mul
+    return generateUnaryModule('double', [0x20, 0x00, 0x42, 0x02, 0x7E]);
+}
+
+/**
+ * `negate(x) = 0 - x`
+ */
+function generateNegateModule() {
+    // i64.const 0, local.get 0, i64.sub
+    return generateUnaryModule('negate', [0x42, 0x00, 0x20, 0x00, 0x7D]);
+}
+
+/**
+ * `spin(x)` loops forever; only running out of fuel or an interrupt stops it.
+ */
+function generateSpinModule() {
+    // loop, br 0, end, i64.const 0
+    return generateUnaryModule('spin', [0x03, 0x40, 0x0C, 0x00, 0x0B, 0x42, 0x00]);
+}
+
+/**
+ * `depth(n)` recurses n frames deep and returns n; a negative n recurses
+ * until the stack runs out.
+ */
+function generateDepthModule() {
+    // local.get 0, i64.eqz, if (result i64) i64.const 0
+    // else local.get 0, i64.const 1, i64.sub, call 0, i64.const 1, i64.add end
+    return generateUnaryModule('depth', [
+        0x20, 0x00, 0x50, 0x04, 0x7E, 0x42, 0x00,
+        0x05, 0x20, 0x00, 0x42, 0x01, 0x7D, 0x10, 0x00, 0x42, 0x01, 0x7C, 0x0B,
+    ]);
+}
+
+/**
+ * Generate a WASM module exporting `next() -> i64`, which increments a
+ * mutable global and returns it: 1, 2, 3, ... from one instance.
+ */
+function generateCounterModule() {
+    const bytes = [];
+    // WASM magic number + version
+    bytes.push(0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00);
+    // Type section: 1 type = () -> i64
+    bytes.push(...encodeSection(1, [1, FUNC_TYPE, 0, 1, I64]));
+    // Function section: 1 function using type index 0
+    bytes.push(...encodeSection(3, [1, 0]));
+    // Global section: (global (mut i64) (i64.const 0))
+    bytes.push(...encodeSection(6, [1, I64, 0x01, 0x42, 0x00, 0x0B]));
+    // Export section: export "next" as function index 0
+    bytes.push(...encodeSection(7, [1, ...encodeString('next'), 0x00, 0]));
+    // Code section: global.get 0, i64.const 1, i64.add, global.set 0, global.get 0
+    const funcBody = [0, 0x23, 0x00, 0x42, 0x01, 0x7C, 0x24, 0x00, 0x23, 0x00, 0x0B];
+    bytes.push(...encodeSection(10, [1, ...uleb128(funcBody.length), ...funcBody]));
+    return new Uint8Array(bytes);
+}
+
+/**
+ * Generate a WASM module exporting `run() -> i64` (returns 1) whose start
+ * function body is `startOps` — e.g. [0x00] traps with unreachable, [] is a no-op.
+ */
+function generateStartModule(startOps) {
+    const bytes = [];
+    // WASM magic number + version
+    bytes.push(0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00);
+    // Type section: type0 = () -> (), type1 = () -> i64
+    bytes.push(...encodeSection(1, [2, FUNC_TYPE, 0, 0, FUNC_TYPE, 0, 1, I64]));
+    // Function section: func 0 (start) uses type0, func 1 (run) uses type1
+    bytes.push(...encodeSection(3, [2, 0, 1]));
+    // Export section: export "run" as function index 1
+    bytes.push(...encodeSection(7, [1, ...encodeString('run'), 0x00, 1]));
+    // Start section: function index 0
+    bytes.push(...encodeSection(8, [0]));
+    // Code section: start body, then run = i64.const 1
+    const startBody = [0, ...startOps, 0x0B];
+    const runBody = [0, 0x42, 0x01, 0x0B];
+    bytes.push(...encodeSection(10, [
+        2,
+        ...uleb128(startBody.length), ...startBody,
+        ...uleb128(runBody.length), ...runBody,
+    ]));
+    return new Uint8Array(bytes);
+}
+
+/**
+ * Generate a WASM module with one function per typed batch signature:
+ *   add(i32, i32) = a + b, sub(i32, i32) = a - b
+ *   mad3_i32/mad3_i64(a, b, c) = a * b - c
+ *   mad4_i32/mad4_i64(a, b, c, d) = a * b - c * d
+ *   sqrt(f64), div(f64, f64) = a / b, mad3_f64(a, b, c) = a * b - c
+ */
+function generateTypedModule() {
+    const I32 = 0x7F, F64 = 0x7C;
+    const get = i => [0x20, i];
+    const funcs = [
+        ['add', [I32, I32], I32, [...get(0), ...get(1), 0x6A]],
+        ['sub', [I32, I32], I32, [...get(0), ...get(1), 0x6B]],
+        ['mad3_i32', [I32, I32, I32], I32, [...get(0), ...get(1), 0x6C, ...get(2), 0x6B]],
+        ['mad3_i64', [I64, I64, I64], I64, [...get(0), ...get(1), 0x7E, ...get(2), 0x7D]],
+        ['mad4_i32', [I32, I32, I32, I32], I32, [...get(0), ...get(1), 0x6C, ...get(2), ...get(3), 0x6C, 0x6B]],
+        ['mad4_i64', [I64, I64, I64, I64], I64, [...get(0), ...get(1), 0x7E, ...get(2), ...get(3), 0x7E, 0x7D]],
+        ['sqrt', [F64], F64, [...get(0), 0x9F]],
+        ['div', [F64, F64], F64, [...get(0), ...get(1), 0xA3]],
+        ['mad3_f64', [F64, F64, F64], F64, [...get(0), ...get(1), 0xA2, ...get(2), 0xA1]],
+    ];
+    const bytes = [];
+    // WASM magic number + version
+    bytes.push(0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00);
+    // Type section: one type per function
+    bytes.push(...encodeSection(1, [
+        ...uleb128(funcs.length),
+        ...funcs.flatMap(([, params, result]) => [FUNC_TYPE, params.length, ...params, 1, result]),
+    ]));
+    // Function section: function i uses type i
+    bytes.push(...encodeSection(3, [...uleb128(funcs.length), ...funcs.map((_, i) => i)]));
+    // Export section: every function under its name
+    bytes.push(...encodeSection(7, [
+        ...uleb128(funcs.length),
+        ...funcs.flatMap(([name], i) => [...encodeString(name), 0x00, i]),
+    ]));
+    // Code section: no locals, body, end
+    bytes.push(...encodeSection(10, [
+        ...uleb128(funcs.length),
+        ...funcs.flatMap(([, , , ops]) => {
+            const funcBody = [0, ...ops, 0x0B];
+            return [...uleb128(funcBody.length), ...funcBody];
+        }),
+    ]));
+    return new Uint8Array(bytes);
+}
+
+/**
+ * Generate a WASM module importing tova.fuel_remaining and tova.request_fuel:
+ *   run(rounds, amount) calls request_fuel(amount) before each round of about
+ *     80M fuel of busy work, then returns fuel_remaining(); 13 rounds outrun
+ *     the default fuel limit
+ *   remaining() returns fuel_remaining()
+ */
+function generateFuelModule() {
+    const bytes = [];
+    // WASM magic number + version
+    bytes.push(0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00);
+    // Type section: type0 = () -> i64, type1 = (i64) -> i64, type2 = (i64, i64) -> i64
+    bytes.push(...encodeSection(1, [3, FUNC_TYPE, 0, 1, I64, FUNC_TYPE, 1, I64, 1, I64, FUNC_TYPE, 2, I64, I64, 1, I64]));
+    // Import section: func 0 = fuel_remaining (type0), func 1 = request_fuel (type1)
+    bytes.push(...encodeSection(2, [
+        2,
+        ...encodeString('tova'), ...encodeString('fuel_remaining'), 0x00, 0,
+        ...encodeString('tova'), ...encodeString('request_fuel'), 0x00, 1,
+    ]));
+    // Function section: func 2 (run) uses type2, func 3 (remaining) uses type0
+    bytes.push(...encodeSection(3, [2, 2, 0]));
+    // Export section
+    bytes.push(...encodeSection(7, [
+        2,
+        ...encodeString('run'), 0x00, 2,
+        ...encodeString('remaining'), 0x00, 3,
+    ]));
+    // Params: local 0 = rounds, local 1 = amount; local 2 = i
+    const runBody = [
+        1, 1, I64,                          // 1 local declaration: local 2 (i64)
+        0x02, 0x40,                         // block
+          0x03, 0x40,                       // loop (round)
+            0x20, 0x00, 0x42, 0x00, 0x57,   // local.get 0, i64.const 0, i64.le_s
+            0x0D, 0x01,                     // br_if 1 (out of the block)
+            0x20, 0x01, 0x10, 0x01, 0x1A,   // local.get 1, call request_fuel, drop
+            0x42, 0x80, 0xAD, 0xE2, 0x04,   // i64.const 10000000
+            0x21, 0x02,                     // local.set 2
+            0x03, 0x40,                     // loop (burn)
+              0x20, 0x02, 0x42, 0x01, 0x7D, // local.get 2, i64.const 1, i64.sub
+              0x21, 0x02,                   // local.set 2
+              0x20, 0x02, 0x42, 0x00, 0x55, // local.get 2, i64.const 0, i64.gt_s
+              0x0D, 0x00,                   // br_if 0 (burn)
+            0x0B,                           // end burn
+            0x20, 0x00, 0x42, 0x01, 0x7D,   // local.get 0, i64.const 1, i64.sub
+            0x21, 0x00,                     // local.set 0
+            0x0C, 0x00,                     // br 0 (round)
+          0x0B,                             // end round
+        0x0B,                               // end block
+        0x10, 0x00,                         // call fuel_remaining
+        0x0B,                               // end function
+    ];
+    const remainingBody = [0, 0x10, 0x00, 0x0B];
+    bytes.push(...encodeSection(10, [
+        2,
+        ...uleb128(runBody.length), ...runBody,
+        ...uleb128(remainingBody.length), ...remainingBody,
+    ]));
+    return new Uint8Array(bytes);
+}
+
+/**
+ * Generate a guest of the shared data imports, with i64 params throughout
+ * (tova_runtime/src/testsupport.rs builds the same module from WAT):
+ *   search(key, spins) busy-waits `spins` iterations, then binary-searches
+ *     shared data 0 as a sorted table of little-endian i64s and returns the
+ *     key's index or -1
+ *   len(id) and read(id, src, dst, len) call the imports once
+ */
+function generateSharedDataModule() {
+    const I32 = 0x7F;
+    const bytes = [];
+    // WASM magic number + version
+    bytes.push(0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00);
+    // Type section: type0 = (i32) -> i64, type1 = (i32, i64, i32, i32) -> i32,
+    // type2 = (i64) -> i64, type3 = (i64, i64, i64, i64) -> i64, type4 = (i64, i64) -> i64
+    bytes.push(...encodeSection(1, [
+        5,
+        FUNC_TYPE, 1, I32, 1, I64,
+        FUNC_TYPE, 4, I32, I64, I32, I32, 1, I32,
+        FUNC_TYPE, 1, I64, 1, I64,
+        FUNC_TYPE, 4, I64, I64, I64, I64, 1, I64,
+        FUNC_TYPE, 2, I64, I64, 1, I64,
+    ]));
+    // Import section: func 0 = shared_data_len (type0), func 1 = shared_data_read (type1)
+    bytes.push(...encodeSection(2, [
+        2,
+        ...encodeString('tova'), ...encodeString('shared_data_len'), 0x00, 0,
+        ...encodeString('tova'), ...encodeString('shared_data_read'), 0x00, 1,
+    ]));
+    // Function section: func 2 (len), func 3 (read), func 4 (search)
+    bytes.push(...encodeSection(3, [3, 2, 3, 4]));
+    // Memory section: one page
+    bytes.push(...encodeSection(5, [1, 0x00, 1]));
+    // Export section
+    bytes.push(...encodeSection(7, [
+        4,
+        ...encodeString('len'), 0x00, 2,
+        ...encodeString('read'), 0x00, 3,
+        ...encodeString('search'), 0x00, 4,
+        ...encodeString('memory'), 0x02, 0,
+    ]));
+    const lenBody = [0, 0x20, 0x00, 0xA7, 0x10, 0x00, 0x0B];
+    const readBody = [
+        0,
+        0x20, 0x00, 0xA7,                   // id as i32
+        0x20, 0x01,                         // src
+        0x20, 0x02, 0xA7,                   // dst as i32
+        0x20, 0x03, 0xA7,                   // len as i32
+        0x10, 0x01, 0xAC,                   // call shared_data_read, i64.extend_i32_s
+        0x0B,
+    ];
+    // Params: local 0 = key, local 1 = spins; locals 2 = lo, 3 = hi, 4 = mid, 5 = v
+    const searchBody = [
+        1, 4, I64,                          // 1 local declaration: locals 2-5 (i64)
+        0x03, 0x40,                         // loop (spin)
+          0x20, 0x01, 0x42, 0x00, 0x55,     // local.get 1, i64.const 0, i64.gt_s
+          0x04, 0x40,                       // if
+            0x20, 0x01, 0x42, 0x01, 0x7D,   // local.get 1, i64.const 1, i64.sub
+            0x21, 0x01,                     // local.set 1
+            0x0C, 0x01,                     // br 1 (spin)
+          0x0B,                             // end if
+        0x0B,                               // end spin
+        0x41, 0x00, 0x10, 0x00,             // i32.const 0, call shared_data_len
+        0x42, 0x08, 0x7F, 0x21, 0x03,       // i64.const 8, i64.div_s, local.set 3
+        0x02, 0x40,                         // block (missing)
+          0x03, 0x40,                       // loop (probe)
+            0x20, 0x02, 0x20, 0x03, 0x59,   // local.get 2, local.get 3, i64.ge_s
+            0x0D, 0x01,                     // br_if 1 (missing)
+            0x20, 0x02, 0x20, 0x03, 0x7C,   // local.get 2, local.get 3, i64.add
+            0x42, 0x01, 0x88, 0x21, 0x04,   // i64.const 1, i64.shr_u, local.set 4
+            0x41, 0x00,                     // i32.const 0 (segment)
+            0x20, 0x04, 0x42, 0x08, 0x7E,   // local.get 4, i64.const 8, i64.mul (src)
+            0x41, 0x00, 0x41, 0x08,         // i32.const 0 (dst), i32.const 8 (len)
+            0x10, 0x01,                     // call shared_data_read
+            0x04, 0x40, 0x00, 0x0B,         // if: unreachable, end
+            0x41, 0x00, 0x29, 0x03, 0x00,   // i32.const 0, i64.load
+            0x21, 0x05,                     // local.set 5
+            0x20, 0x05, 0x20, 0x00, 0x51,   // local.get 5, local.get 0, i64.eq
+            0x04, 0x40,                     // if
+              0x20, 0x04, 0x0F,             // local.get 4, return
+            0x0B,                           // end if
+            0x20, 0x05, 0x20, 0x00, 0x53,   // local.get 5, local.get 0, i64.lt_s
+            0x04, 0x40,                     // if
+              0x20, 0x04, 0x42, 0x01, 0x7C, // local.get 4, i64.const 1, i64.add
+              0x21, 0x02,                   // local.set 2
+            0x05,                           // else
+              0x20, 0x04, 0x21, 0x03,       // local.get 4, local.set 3
+            0x0B,                           // end if
+            0x0C, 0x00,                     // br 0 (probe)
+          0x0B,                             // end probe
+        0x0B,                               // end missing
+        0x42, 0x7F,                         // i64.const -1
+        0x0B,                               // end function
+    ];
+    bytes.push(...encodeSection(10, [
+        3,
+        ...uleb128(lenBody.length), ...lenBody,
+        ...uleb128(readBody.length), ...readBody,
+        ...uleb128(searchBody.length), ...searchBody,
+    ]));
+    return new Uint8Array(bytes);
+}
+
+module.exports = {
+    generateStartModule,
+    generateAddModule,
+    generateFibModule,
+    generateUnaryModule,
+    generateDoubleModule,
+    generateNegateModule,
+    generateSpinModule,
+    generateDepthModule,
+    generateCounterModule,
+    generateTypedModule,
+    generateFuelModule,
+    generateSharedDataModule,
+};
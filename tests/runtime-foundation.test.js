@@ -22,10 +22,10 @@ const runtime = tryLoadRuntime();
 const hasRuntime = runtime !== null;
 
 // Lazy-load WASM generators only when runtime is available
-let generateAddModule, generateFibModule, generateProducerModule, generateConsumerModule;
+let generateAddModule, generateFibModule, generateStartModule, generateSpinModule, generateDepthModule, generateCounterModule, generateTypedModule, generateFuelModule, generateSharedDataModule, generateProducerModule, generateConsumerModule, generateSendOnceModule, generateDrainModule;
 if (hasRuntime) {
-    ({ generateAddModule, generateFibModule } = require('./fixtures/gen-test-wasm.js'));
-    ({ generateProducerModule, generateConsumerModule } = require('./fixtures/gen-channel-wasm.js'));
+    ({ generateAddModule, generateFibModule, generateStartModule, generateSpinModule, generateDepthModule, generateCounterModule, generateTypedModule, generateFuelModule, generateSharedDataModule } = require('./fixtures/gen-test-wasm.js'));
+    ({ generateProducerModule, generateConsumerModule, generateSendOnceModule, generateDrainModule } = require('./fixtures/gen-channel-wasm.js'));
 }
 
 describe.skipIf(!hasRuntime)('tova_runtime foundation', () => {
@@ -86,6 +86,41 @@ describe.skipIf(!hasRuntime)('channels', () => {
         expect(v2).toBeNull();
     });
 
+    test('closeAndDrain returns buffered values and rejects later sends', () => {
+        const chId = runtime.channelCreate(4);
+        runtime.channelSend(chId, 1);
+        runtime.channelSend(chId, 2);
+        expect(runtime.channelCloseAndDrain(chId)).toEqual([1, 2]);
+        expect(runtime.channelReceive(chId)).toBeNull();
+        expect(() => runtime.channelSend(chId, 3)).toThrow('closed channel');
+    });
+
+    test('buffer budget spans channels and frees on receive', () => {
+        const base = runtime.runtimeStats().channelBufferBytes;
+        runtime.configureRuntime({ channelBufferBudgetBytes: base + 4 * 8 });
+        const a = runtime.channelCreate(10);
+        const b = runtime.channelCreate(10);
+        try {
+            runtime.channelSend(a, 1);
+            runtime.channelSend(a, 2);
+            runtime.channelSend(b, 3);
+            runtime.channelSend(b, 4);
+            expect(() => runtime.channelSend(b, 5)).toThrow('ERR_CHANNEL_BUDGET');
+            const stats = runtime.runtimeStats();
+            expect(stats.channelBufferBytes).toBe(base + 32);
+            expect(stats.channelBufferHighWater).toBeGreaterThanOrEqual(base + 32);
+            expect(stats.channelBufferBudgetBytes).toBe(base + 32);
+
+            expect(runtime.channelReceive(a)).toBe(1);
+            expect(() => runtime.channelSend(b, 5)).not.toThrow();
+        } finally {
+            runtime.channelCloseAndDrain(a);
+            runtime.channelCloseAndDrain(b);
+            runtime.configureRuntime({ channelBufferBudgetBytes: 0 });
+        }
+        expect(runtime.runtimeStats().channelBufferBudgetBytes ?? null).toBeNull();
+    });
+
     test('channel 100 messages', () => {
         const chId = runtime.channelCreate(100);
         for (let i = 0; i < 100; i++) {
@@ -139,6 +174,68 @@ describe.skipIf(!hasRuntime)('wasmtime executor', () => {
         expect(results[0]).toBe(0);
         expect(results[999]).toBe(1998);
     });
+
+    test('precompiled module handle replaces the bytes', async () => {
+        const handle = await runtime.moduleCompile(Buffer.from(generateAddModule()));
+        expect(await runtime.execModule(handle, 'add', [3, 4])).toBe(7);
+
+        const tasks = Array.from({ length: 100 }, (_, i) => ({ module: handle, func: 'add', args: [i, 1] }));
+        const results = await runtime.concurrentWasm(tasks);
+        expect(results).toEqual(Array.from({ length: 100 }, (_, i) => i + 1));
+
+        expect(runtime.moduleRelease(handle)).toBe(true);
+        expect(runtime.moduleRelease(handle)).toBe(false);
+        await expect(runtime.execModule(handle, 'add', [1, 2])).rejects.toThrow('no such module handle');
+    });
+
+    test('task without wasm or module is rejected', async () => {
+        await expect(runtime.concurrentWasm([{ func: 'add', args: [1, 2] }])).rejects.toThrow('either `wasm` or `module`');
+    });
+});
+
+describe.skipIf(!hasRuntime)('compute pool', () => {
+    test('shared batch reports chunk placement', async () => {
+        const wasmBytes = Buffer.from(generateAddModule());
+        const tasks = Array.from({ length: 100 }, (_, i) => ({ wasm: wasmBytes, func: 'add', args: [i, i] }));
+        const report = await runtime.concurrentWasmSharedStats(tasks, { chunks: 4 });
+        expect(report.results).toEqual(Array.from({ length: 100 }, (_, i) => 2 * i));
+        expect(report.chunks.length).toBe(4);
+        expect(report.chunks.reduce((n, c) => n + c.tasks, 0)).toBe(100);
+        for (const c of report.chunks) {
+            expect(c.worker).toBeGreaterThanOrEqual(0);
+        }
+    });
+
+    test('chunk override does not change results', async () => {
+        const wasmBytes = Buffer.from(generateAddModule());
+        const tasks = Array.from({ length: 10 }, (_, i) => ({ wasm: wasmBytes, func: 'add', args: [i, 1] }));
+        expect(await runtime.concurrentWasmShared(tasks, { chunks: 3 })).toEqual(await runtime.concurrentWasmShared(tasks));
+    });
+
+    test('pool sizing cannot change once the pool is running', () => {
+        expect(() => runtime.configureRuntime({ computeThreads: 2 })).toThrow('before the compute pool starts');
+        expect(() => runtime.configureRuntime({ sharedChunks: 0 })).not.toThrow();
+    });
+});
+
+describe.skipIf(!hasRuntime)('start functions', () => {
+    test('a trapping start function reports a classified error', async () => {
+        const wasm = Buffer.from(generateStartModule([0x00]));
+        await expect(runtime.execWasm(wasm, 'run', [])).rejects.toThrow(/^ERR_TRAP_UNREACHABLE: in start function/);
+    });
+
+    test('runStart: false rejects modules that have a start function', async () => {
+        const wasm = Buffer.from(generateStartModule([]));
+        expect(await runtime.execWasm(wasm, 'run', [])).toBe(1);
+        await expect(runtime.execWasm(wasm, 'run', [], { runStart: false })).rejects.toThrow('ERR_START_FUNCTION');
+        expect(await runtime.execWasm(Buffer.from(generateAddModule()), 'add', [1, 2], { runStart: false })).toBe(3);
+    });
+
+    test('inspectWasm reports the start function', async () => {
+        const info = await runtime.inspectWasm(Buffer.from(generateStartModule([])));
+        expect(info).toEqual({ imports: [], exports: ['run'], hasStart: true });
+        expect((await runtime.inspectWasm(Buffer.from(generateAddModule()))).hasStart).toBe(false);
+    });
 });
 
 describe.skipIf(!hasRuntime)('WASM host imports — channels', () => {
@@ -171,6 +268,115 @@ describe.skipIf(!hasRuntime)('WASM host imports — channels', () => {
     });
 });
 
+describe.skipIf(!hasRuntime)('channel contexts', () => {
+    test('guests reach only channels granted to their context', async () => {
+        const ctx = runtime.contextCreate();
+        const owned = runtime.channelCreateIn(ctx, 4);
+        const guestId = runtime.contextGrant(ctx, owned);
+        const wasm = Buffer.from(generateSendOnceModule());
+        const options = { channelContext: ctx };
+
+        expect(await runtime.execWasmWithChannels(wasm, 'send_once', [guestId, 5], options)).toBe(0);
+        expect(runtime.channelReceive(owned)).toBe(5);
+        // -2: the id does not resolve in this context
+        expect(await runtime.execWasmWithChannels(wasm, 'send_once', [guestId + 1, 5], options)).toBe(-2);
+        runtime.contextDestroy(ctx);
+    });
+
+    test('destroying a context closes its channels but not others', () => {
+        const ctx = runtime.contextCreate();
+        const owned = runtime.channelCreateIn(ctx, 4);
+        const other = runtime.channelCreate(4);
+        runtime.contextGrant(ctx, other);
+
+        expect(runtime.contextDestroy(ctx)).toBe(true);
+        expect(() => runtime.channelSend(owned, 1)).toThrow('closed channel');
+        expect(runtime.channelSend(other, 1)).toBe(true);
+        expect(() => runtime.channelCreateIn(ctx, 4)).toThrow('no such channel context');
+    });
+
+    test('unknown context is rejected up front', async () => {
+        const wasm = Buffer.from(generateSendOnceModule());
+        await expect(runtime.execWasmWithChannels(wasm, 'send_once', [0, 1], { channelContext: 999999 }))
+            .rejects.toThrow('no such channel context');
+    });
+});
+
+describe.skipIf(!hasRuntime)('channel diagnostics', () => {
+    test('snapshot names both channels of a guest deadlock', async () => {
+        const a = runtime.channelCreate(1);
+        const b = runtime.channelCreate(1);
+        runtime.channelSend(b, 0);
+        // The consumer waits for a value on `a`; the sender waits for room in `b`
+        const receiving = runtime.execWasmWithChannels(Buffer.from(generateConsumerModule()), 'consumer', [a, 1]);
+        const sending = runtime.execWasmWithChannels(Buffer.from(generateSendOnceModule()), 'send_once', [b, 1]);
+        await new Promise(r => setTimeout(r, 100));
+
+        const stuck = runtime.diagnosticsBlocked().filter(op => op.channelId === a || op.channelId === b);
+        expect(stuck.map(op => [op.channelId, op.op, op.task]).sort()).toEqual([[a, 'receive', 'guest'], [b, 'send', 'guest']].sort());
+        for (const op of stuck) {
+            expect(op.waitedMs).toBeGreaterThan(50);
+            expect(op.waitedMs).toBeLessThan(10000);
+        }
+
+        runtime.channelClose(a);
+        runtime.channelClose(b);
+        await receiving;
+        expect(await sending).toBe(-1);
+        expect(runtime.diagnosticsBlocked().filter(op => op.channelId === a || op.channelId === b)).toEqual([]);
+    });
+
+    test('stall watchdog reports a stuck wait', async () => {
+        const ch = runtime.channelCreate(1);
+        const reported = new Promise(resolve => {
+            runtime.enableStallWatchdog(20, ops => {
+                if (ops.some(op => op.channelId === ch)) resolve(ops);
+            });
+        });
+        const receiving = runtime.execWasmWithChannels(Buffer.from(generateConsumerModule()), 'consumer', [ch, 1]);
+        try {
+            const ops = await reported;
+            expect(ops.find(op => op.channelId === ch).waitedMs).toBeGreaterThanOrEqual(20);
+        } finally {
+            expect(runtime.disableStallWatchdog()).toBe(true);
+            runtime.channelClose(ch);
+            await receiving;
+        }
+    });
+});
+
+describe.skipIf(!hasRuntime)('shared fuel budget', () => {
+    test('tasks past the budget fail fast and order is kept', async () => {
+        const wasm = Buffer.from(generateFibModule());
+        const probe = await runtime.concurrentWasmSettled([{ wasm, func: 'fib', args: [20] }]);
+        const perTask = probe.results[0].fuelUsed;
+        expect(perTask).toBeGreaterThan(0);
+        expect(probe.totalFuelUsed).toBe(perTask);
+
+        const tasks = Array.from({ length: 20 }, (_, i) => ({ wasm, func: 'fib', args: [20 - (i % 2)] }));
+        const settled = await runtime.concurrentWasmSettled(tasks, {
+            sharedFuelBudget: BigInt(perTask * 10 - 1),
+            maxConcurrency: 2,
+        });
+        const ok = settled.results.filter(r => r.ok).length;
+        // Half the budget-worth of tasks, plus at most one overshoot per concurrent slot
+        expect(ok).toBeGreaterThanOrEqual(9);
+        expect(ok).toBeLessThanOrEqual(12);
+        settled.results.forEach((r, i) => {
+            if (r.ok) {
+                expect(r.value).toBe(i % 2 === 0 ? 6765 : 4181);
+                expect(r.fuelUsed).toBeGreaterThan(0);
+            } else {
+                expect(r.error).toContain('ERR_FUEL_BUDGET');
+                expect(r.fuelUsed).toBe(0);
+            }
+        });
+        expect(settled.totalFuelUsed).toBe(settled.results.reduce((s, r) => s + r.fuelUsed, 0));
+
+        await expect(runtime.concurrentWasm(tasks, { sharedFuelBudget: 0n })).rejects.toThrow('ERR_FUEL_BUDGET');
+    });
+});
+
 describe.skipIf(!hasRuntime)('concurrent WASM modes', () => {
     test('concurrentWasmFirst returns first result', async () => {
         const wasmBytes = Buffer.from(generateAddModule());
@@ -241,3 +447,1088 @@ describe.skipIf(!hasRuntime)('runtime bridge', () => {
         expect(bridge.healthCheck()).toBe('tova_runtime ok');
     });
 });
+
+describe.skipIf(!hasRuntime)('runtime lifecycle', () => {
+    test('generation is set once the module loads', () => {
+        expect(runtime.runtimeGeneration()).toBeGreaterThanOrEqual(1);
+    });
+
+    test('reset starts a new generation and rejects stale ids', async () => {
+        const before = runtime.runtimeGeneration();
+        const ch = runtime.channelCreate(4);
+        runtime.channelSend(ch, 1);
+        const ctx = runtime.contextCreate();
+        const handle = await runtime.moduleCompile(Buffer.from(generateAddModule()));
+
+        expect(runtime.resetAllState()).toBe(before + 1);
+        expect(runtime.runtimeGeneration()).toBe(before + 1);
+
+        expect(() => runtime.channelSend(ch, 2)).toThrow('ERR_STALE_HANDLE');
+        expect(() => runtime.channelReceive(ch)).toThrow('ERR_STALE_HANDLE');
+        expect(() => runtime.contextDestroy(ctx)).toThrow('ERR_STALE_HANDLE');
+        await expect(runtime.execModule(handle, 'add', [1, 2])).rejects.toThrow('ERR_STALE_HANDLE');
+
+        // Fresh ids never alias the stale ones
+        const fresh = runtime.channelCreate(4);
+        expect(fresh).not.toBe(ch);
+        runtime.channelSend(fresh, 3);
+        expect(runtime.channelReceive(fresh)).toBe(3);
+    });
+
+    test('reset interrupts running guests and wakes blocked receivers', async () => {
+        const spinning = runtime.execWasm(Buffer.from(generateSpinModule()), 'spin', [0]);
+        const ch = runtime.channelCreate(1);
+        const receiving = runtime.execWasmWithChannels(Buffer.from(generateConsumerModule()), 'consumer', [ch, 1]);
+        await new Promise(r => setTimeout(r, 100));
+
+        runtime.resetAllState();
+        await expect(spinning).rejects.toThrow('ERR_CANCELLED');
+        // The consumer sees the channel end (or is interrupted) instead of hanging
+        await Promise.allSettled([receiving]);
+        expect(runtime.diagnosticsBlocked()).toEqual([]);
+    });
+});
+
+describe.skipIf(!hasRuntime)('input limits', () => {
+    test('runtime stats report the limits', () => {
+        const stats = runtime.runtimeStats();
+        expect(stats.maxModuleBytes).toBe(64 * 1024 * 1024);
+        expect(stats.maxArgsPerTask).toBe(64);
+        expect(stats.maxTasksPerBatch).toBe(100000);
+    });
+
+    test('an oversized module fails fast without copying it', async () => {
+        const big = Buffer.alloc(32 * 1024 * 1024);
+        runtime.configureRuntime({ maxModuleBytes: 1024 * 1024 });
+        try {
+            expect(runtime.runtimeStats().maxModuleBytes).toBe(1024 * 1024);
+            const before = process.memoryUsage().rss;
+            for (let i = 0; i < 20; i++) {
+                await expect(runtime.execWasm(big, 'add', [1, 2])).rejects.toThrow('ERR_INVALID_INPUT');
+            }
+            await expect(runtime.moduleCompile(big)).rejects.toThrow('maxModuleBytes');
+            // 20 copies would be 640 MB
+            expect(process.memoryUsage().rss - before).toBeLessThan(64 * 1024 * 1024);
+        } finally {
+            runtime.configureRuntime({ maxModuleBytes: 64 * 1024 * 1024 });
+        }
+    });
+
+    test('an oversized batch fails fast and names the offending task', async () => {
+        const wasm = Buffer.from(generateAddModule());
+        const tasks = Array.from({ length: 100001 }, () => ({ wasm, func: 'add', args: [1, 2] }));
+        await expect(runtime.concurrentWasm(tasks)).rejects.toThrow('maxTasksPerBatch');
+
+        const small = [{ wasm, func: 'add', args: [1, 2] }, { wasm, func: 'add', args: new Array(65).fill(0) }];
+        await expect(runtime.concurrentWasm(small)).rejects.toThrow('ERR_INVALID_INPUT: task 1');
+        await expect(runtime.execWasm(wasm, 'add', new Array(65).fill(0))).rejects.toThrow('maxArgsPerTask');
+    });
+});
+
+describe.skipIf(!hasRuntime)('instance pools', () => {
+    test('repeated exec of a pure module hits the pool', async () => {
+        const wasm = Buffer.from(generateFibModule());
+        const options = { instancePool: { size: 2 } };
+        const before = runtime.runtimeStats().instancePoolHits;
+        for (let i = 0; i < 20; i++) {
+            expect(await runtime.execWasm(wasm, 'fib', [10], options)).toBe(55);
+        }
+        expect(runtime.runtimeStats().instancePoolHits - before).toBeGreaterThanOrEqual(19);
+    });
+
+    test('resetEvery 1 never leaks guest state across calls', async () => {
+        const wasm = Buffer.from(generateCounterModule());
+        const options = { instancePool: { size: 2, resetEvery: 1 } };
+        const resets = runtime.runtimeStats().instancePoolResets;
+        const results = await Promise.all(Array.from({ length: 20 }, () => runtime.execWasm(wasm, 'next', [], options)));
+        expect(results).toEqual(new Array(20).fill(1));
+        expect(runtime.runtimeStats().instancePoolResets - resets).toBe(20);
+    });
+
+    test('pool size stays within the bound under concurrency', async () => {
+        const wasm = Buffer.from(generateAddModule());
+        const options = { instancePool: { size: 3 } };
+        const calls = Array.from({ length: 200 }, (_, i) => runtime.execWasm(wasm, 'add', [i, 1], options));
+        expect(await Promise.all(calls)).toEqual(Array.from({ length: 200 }, (_, i) => i + 1));
+        // Other modules' pools may hold instances too, but never more than their own bound each
+        expect(runtime.runtimeStats().instancePoolSize).toBeLessThanOrEqual(3 + 2 + 2);
+    });
+});
+
+describe.skipIf(!hasRuntime)('result channels', () => {
+    test('a guest streams intermediate results to a subscriber', async () => {
+        const ch = runtime.channelCreate(4);
+        const seen = [];
+        const subscription = runtime.channelSubscribe(ch, value => seen.push(value));
+        // producer(0, 10) sends 0..9 on guest id 0, then returns 10
+        const result = await runtime.execWasmWithChannels(
+            Buffer.from(generateProducerModule()), 'producer', [0, 10], { resultChannel: ch });
+        expect(result).toBe(10);
+        for (let i = 0; i < 50 && seen.length < 10; i++) await new Promise(r => setTimeout(r, 10));
+        expect(seen).toEqual([0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+
+        expect(runtime.channelUnsubscribe(subscription)).toBe(true);
+        expect(runtime.channelUnsubscribe(subscription)).toBe(false);
+        runtime.channelSend(ch, 99);
+        expect(runtime.channelReceive(ch)).toBe(99);
+    });
+
+    test('the result channel must be guest id 0 of a given context', async () => {
+        const ctx = runtime.contextCreate();
+        const other = runtime.channelCreate(1);
+        const ch = runtime.channelCreate(1);
+        runtime.contextGrant(ctx, other);
+        await expect(runtime.execWasmWithChannels(
+            Buffer.from(generateProducerModule()), 'producer', [0, 1], { channelContext: ctx, resultChannel: ch },
+        )).rejects.toThrow('already granted');
+        runtime.contextDestroy(ctx);
+    });
+});
+
+describe.skipIf(!hasRuntime)('host channel tasks', () => {
+    test('a producer feeds a collector', async () => {
+        const ch = runtime.channelCreate(2);
+        runtime.spawnChannelProducer(ch, [5, 6, 7, 8], 5);
+        expect(await runtime.spawnChannelCollector(ch, 4, 5000)).toEqual([5, 6, 7, 8]);
+        // Nothing more arrives: the timeout resolves an empty collection
+        expect(await runtime.spawnChannelCollector(ch, 1, 20)).toEqual([]);
+    });
+
+    test('a producer feeds a WASM consumer', async () => {
+        const ch = runtime.channelCreate(4);
+        runtime.spawnChannelProducer(ch, Array.from({ length: 100 }, (_, i) => i), 0);
+        const sum = await runtime.execWasmWithChannels(Buffer.from(generateConsumerModule()), 'consumer', [ch, 100]);
+        expect(sum).toBe(4950);
+    });
+
+    test('reducers fold until the channel closes', async () => {
+        for (const [op, expected] of [['sum', 10], ['min', -3], ['max', 7], ['count', 4]]) {
+            const ch = runtime.channelCreate(8);
+            const reducing = runtime.spawnChannelReducer(ch, op);
+            for (const v of [2, -3, 7, 4]) runtime.channelSend(ch, v);
+            runtime.channelClose(ch);
+            expect(await reducing).toBe(expected);
+        }
+        await expect(runtime.spawnChannelReducer(runtime.channelCreate(1), 'avg')).rejects.toThrow('ERR_INVALID_INPUT');
+    });
+
+    test('cancelling stops a producer blocked on a full channel', async () => {
+        const ch = runtime.channelCreate(1);
+        const producer = runtime.spawnChannelProducer(ch, [1, 2, 3], 0);
+        await new Promise(r => setTimeout(r, 30));
+        expect(runtime.channelProducerCancel(producer)).toBe(true);
+        expect(await runtime.spawnChannelCollector(ch, 3, 100)).toEqual([1]);
+        expect(runtime.channelProducerCancel(producer)).toBe(false);
+    });
+});
+
+describe.skipIf(!hasRuntime)('fair channels', () => {
+    test('channelStat reports contention on a full channel', async () => {
+        const ch = runtime.channelCreate(1);
+        runtime.channelSend(ch, 1);
+        const producer = runtime.spawnChannelProducer(ch, [2], 0);
+        await new Promise(r => setTimeout(r, 30));
+        expect(await runtime.spawnChannelCollector(ch, 2, 1000)).toEqual([1, 2]);
+        const stat = runtime.channelStat(ch);
+        expect(stat.fair).toBe(false);
+        expect(stat.capacity).toBe(1);
+        expect(stat.blockedSends).toBe(1);
+        expect(stat.maxSendWaitMs).toBeGreaterThan(10);
+        expect(runtime.channelProducerCancel(producer)).toBe(false);
+    });
+
+    test('WASM producers sharing a fair channel all deliver', async () => {
+        const ch = runtime.channelCreate(1, { fair: true });
+        expect(runtime.channelStat(ch).fair).toBe(true);
+        const wasm = Buffer.from(generateProducerModule());
+        const producing = runtime.concurrentWasmWithChannels([
+            { wasm, func: 'producer', args: [ch, 200] },
+            { wasm, func: 'producer', args: [ch, 200] },
+        ]);
+        const reducing = runtime.spawnChannelReducer(ch, 'sum', 10000);
+        expect(await producing).toEqual([200, 200]);
+        runtime.channelClose(ch);
+        expect(await reducing).toBe(2 * 19900);
+    });
+});
+
+describe.skipIf(!hasRuntime)('tags and quotas', () => {
+    const tagged = tag => runtime.metricsSnapshot().find(m => m.tag === tag);
+
+    test('metrics attribute fuel to each tag of an interleaved batch', async () => {
+        const wasm = Buffer.from(generateFibModule());
+        const tasks = Array.from({ length: 20 }, (_, i) => i % 2 === 0
+            ? { wasm, func: 'fib', args: [40], tag: 'tenant-a' }
+            : { wasm, func: 'fib', args: [5], tag: 'tenant-b' });
+        const batch = await runtime.concurrentWasmSettled(tasks);
+        const fuelOf = parity => batch.results.filter((_, i) => i % 2 === parity).reduce((s, r) => s + r.fuelUsed, 0);
+
+        const [a, b] = [tagged('tenant-a'), tagged('tenant-b')];
+        expect(a.executions).toBe(10);
+        expect(b.executions).toBe(10);
+        expect(a.fuelUsed).toBe(fuelOf(0));
+        expect(b.fuelUsed).toBe(fuelOf(1));
+        expect(a.fuelUsed).toBeGreaterThan(b.fuelUsed);
+        expect(a.maxMs).toBeGreaterThanOrEqual(a.p50Ms);
+    });
+
+    test('a maxConcurrent quota rejects promptly and recovers', async () => {
+        runtime.setTagQuota('tenant-c', { maxConcurrent: 1 });
+        const spinning = runtime.execWasm(Buffer.from(generateSpinModule()), 'spin', [0], { tag: 'tenant-c' });
+        await new Promise(r => setTimeout(r, 50));
+
+        const started = Date.now();
+        const fib = Buffer.from(generateFibModule());
+        await expect(runtime.execWasm(fib, 'fib', [10], { tag: 'tenant-c' })).rejects.toThrow('ERR_QUOTA');
+        expect(Date.now() - started).toBeLessThan(500);
+        // Other tags are unaffected
+        expect(await runtime.execWasm(fib, 'fib', [10], { tag: 'tenant-d' })).toBe(55);
+
+        await expect(spinning).rejects.toThrow('ERR_OUT_OF_FUEL');
+        expect(await runtime.execWasm(fib, 'fib', [10], { tag: 'tenant-c' })).toBe(55);
+        runtime.setTagQuota('tenant-c', {});
+    });
+});
+
+describe.skipIf(!hasRuntime)('allowed imports', () => {
+    test('a chan_send module runs with channels allowed and is rejected by name otherwise', async () => {
+        const wasm = Buffer.from(generateSendOnceModule());
+        const ch = runtime.channelCreate(2);
+        expect(await runtime.execWasmWithChannels(wasm, 'send_once', [ch, 5], { capabilities: { channels: true } })).toBe(0);
+        expect(await runtime.execWasmWithChannels(wasm, 'send_once', [ch, 6], { allowedImports: ['tova.chan_send'] })).toBe(0);
+        expect(runtime.channelReceive(ch)).toBe(5);
+        expect(runtime.channelReceive(ch)).toBe(6);
+
+        await expect(runtime.execWasmWithChannels(wasm, 'send_once', [ch, 7], { allowedImports: ['tova.chan_receive'] }))
+            .rejects.toThrow('ERR_FORBIDDEN_IMPORT: module imports tova.chan_send');
+        await expect(runtime.execWasmWithChannels(wasm, 'send_once', [ch, 7], { capabilities: { clock: true } }))
+            .rejects.toThrow('tova.chan_send');
+        await expect(runtime.execWasm(wasm, 'send_once', [ch, 7], { allowedImports: [] }))
+            .rejects.toThrow('ERR_FORBIDDEN_IMPORT');
+        expect(runtime.channelReceive(ch)).toBe(null);
+    });
+});
+
+describe.skipIf(!hasRuntime)('batch latency', () => {
+    test('the slow task is reported as maxIndex and logged once', async () => {
+        const wasm = Buffer.from(generateFibModule());
+        const tasks = Array.from({ length: 10 }, (_, i) => ({ wasm, func: 'fib', args: [i === 6 ? 20_000_000 : 20] }));
+        const before = runtime.runtimeStats().slowTasksLogged;
+
+        const settled = await runtime.concurrentWasmSettled(tasks, { latencySummary: true, slowTaskThresholdMs: 50 });
+        expect(settled.results.every(r => r.ok)).toBe(true);
+        expect(settled.latency.maxIndex).toBe(6);
+        expect(settled.latency.max).toBeGreaterThan(50);
+        expect(settled.latency.p50).toBeLessThanOrEqual(settled.latency.p90);
+        expect(settled.latency.p99).toBeLessThanOrEqual(settled.latency.max);
+        expect(runtime.runtimeStats().slowTasksLogged - before).toBe(1);
+
+        const plain = await runtime.concurrentWasmSettled(tasks.slice(0, 2));
+        expect(plain.latency).toBeUndefined();
+    });
+});
+
+describe.skipIf(!hasRuntime)('metering modes', () => {
+    test('fuel, epoch and none agree on a well-behaved module', async () => {
+        const wasm = Buffer.from(generateFibModule());
+        const results = await Promise.all(['fuel', 'epoch', 'none'].map(metering =>
+            runtime.execWasm(wasm, 'fib', [40], { metering })));
+        expect(results).toEqual([102334155, 102334155, 102334155]);
+
+        const settled = await runtime.concurrentWasmSettled(
+            [{ wasm, func: 'fib', args: [20] }, { wasm, func: 'fib', args: [21] }],
+            { metering: 'none' },
+        );
+        expect(settled.results.map(r => r.value)).toEqual([6765, 10946]);
+        expect(settled.totalFuelUsed).toBe(0);
+
+        const handle = await runtime.moduleCompile(wasm, { metering: 'none' });
+        expect(await runtime.execModule(handle, 'fib', [10])).toBe(55);
+        await expect(runtime.execModule(handle, 'fib', [10], { metering: 'fuel' })).rejects.toThrow('ERR_INVALID_INPUT');
+        runtime.moduleRelease(handle);
+
+        await expect(runtime.execWasm(wasm, 'fib', [10], { metering: 'gas' })).rejects.toThrow('ERR_INVALID_INPUT');
+        await expect(runtime.concurrentWasm([{ wasm, func: 'fib', args: [1] }], { metering: 'epoch', sharedFuelBudget: 10n }))
+            .rejects.toThrow('ERR_INVALID_INPUT');
+    });
+
+    test('epoch metering stops a spinning guest at the time limit', async () => {
+        runtime.configureRuntime({ epochTimeLimitMs: 100 });
+        try {
+            const started = Date.now();
+            await expect(runtime.execWasm(Buffer.from(generateSpinModule()), 'spin', [0], { metering: 'epoch' }))
+                .rejects.toThrow('ERR_TIME_LIMIT');
+            expect(Date.now() - started).toBeLessThan(2000);
+        } finally {
+            runtime.configureRuntime({ epochTimeLimitMs: 10000 });
+        }
+    });
+});
+
+describe.skipIf(!hasRuntime)('channel readers', () => {
+    test('batches deliver every value in order and end with one null', async () => {
+        const ch = runtime.channelCreate(64);
+        const values = Array.from({ length: 10000 }, (_, i) => i);
+        runtime.spawnChannelProducer(ch, values, 0);
+        const reader = runtime.channelReaderCreate(ch, 256);
+
+        const seen = [];
+        while (seen.length < values.length) {
+            const batch = await runtime.channelReaderNext(reader);
+            expect(batch.length).toBeGreaterThan(0);
+            expect(batch.length).toBeLessThanOrEqual(256);
+            seen.push(...batch);
+        }
+        expect(seen).toEqual(values);
+
+        runtime.channelClose(ch);
+        expect(await runtime.channelReaderNext(reader)).toBe(null);
+        await expect(runtime.channelReaderNext(reader)).rejects.toThrow('no such channel reader');
+    });
+
+    test('a second concurrent next() is rejected', async () => {
+        const ch = runtime.channelCreate(4);
+        const reader = runtime.channelReaderCreate(ch, 8);
+        const first = runtime.channelReaderNext(reader);
+        await expect(runtime.channelReaderNext(reader)).rejects.toThrow('already has a next() pending');
+        runtime.channelSend(ch, 3);
+        expect(await first).toEqual([3]);
+        expect(runtime.channelReaderClose(reader)).toBe(true);
+        runtime.channelClose(ch);
+    });
+});
+
+describe.skipIf(!hasRuntime)('stack overflow', () => {
+    test('unbounded recursion traps and bounded recursion still succeeds', async () => {
+        const wasm = Buffer.from(generateDepthModule());
+        await expect(runtime.execWasm(wasm, 'depth', [-1])).rejects.toThrow('ERR_STACK_OVERFLOW');
+        expect(await runtime.execWasm(wasm, 'depth', [5000])).toBe(5000);
+        const settled = await runtime.concurrentWasmSettled([
+            { wasm, func: 'depth', args: [-1] },
+            { wasm, func: 'depth', args: [100] },
+        ]);
+        expect(settled.results[0].error).toContain('ERR_STACK_OVERFLOW');
+        expect(settled.results[1].value).toBe(100);
+
+        // Engines already exist by now, so the limit is fixed
+        expect(() => runtime.configureEngineFeatures({ maxWasmStackBytes: 1 << 20 })).toThrow('before the first module');
+    });
+});
+
+describe.skipIf(!hasRuntime)('typed batch signatures', () => {
+    test('3- and 4-argument integer functions', async () => {
+        const wasm = Buffer.from(generateTypedModule());
+        for (const func of ['mad3_i32', 'mad3_i64']) {
+            const tasks = [[6, 7, 2], [-3, 5, 1]].map(args => ({ wasm, func, args }));
+            expect(await runtime.concurrentWasmShared(tasks)).toEqual([40, -16]);
+        }
+        for (const func of ['mad4_i32', 'mad4_i64']) {
+            const tasks = [[6, 7, 2, 3], [1, 1, 4, 4]].map(args => ({ wasm, func, args }));
+            expect(await runtime.concurrentWasmShared(tasks)).toEqual([36, -15]);
+        }
+    });
+
+    test('f64 functions take f64Args', async () => {
+        const wasm = Buffer.from(generateTypedModule());
+        const tasks = [
+            ...[2, 9].map(x => ({ wasm, func: 'sqrt', args: [], f64Args: [x] })),
+            { wasm, func: 'div', args: [], f64Args: [1, 4] },
+            { wasm, func: 'mad3_f64', args: [], f64Args: [1.5, 4, 0.25] },
+        ];
+        expect(await runtime.concurrentWasmSharedF64(tasks, { chunks: 2 })).toEqual([Math.SQRT2, 3, 0.25, 5.75]);
+        const report = await runtime.concurrentWasmSharedStats(tasks);
+        expect(report.f64Results).toEqual([Math.SQRT2, 3, 0.25, 5.75]);
+        expect(report.results).toEqual([]);
+
+        await expect(runtime.concurrentWasmShared(tasks)).rejects.toThrow('ERR_INVALID_INPUT');
+        await expect(runtime.concurrentWasmSharedF64([...tasks, { wasm, func: 'add', args: [1, 2] }]))
+            .rejects.toThrow('needs f64Args on every task');
+        await expect(runtime.concurrentWasm(tasks)).rejects.toThrow('only supported by concurrentWasmShared');
+    });
+
+    test('a batch mixing function names calls each task\'s own function', async () => {
+        const wasm = Buffer.from(generateTypedModule());
+        const tasks = [
+            { wasm, func: 'add', args: [5, 3] },
+            { wasm, func: 'sub', args: [5, 3] },
+            { wasm, func: 'mad3_i64', args: [2, 3, 4] },
+        ];
+        expect(await runtime.concurrentWasmShared(tasks, { chunks: 1 })).toEqual([8, 2, 2]);
+    });
+});
+
+describe.skipIf(!hasRuntime)('unordered completion', () => {
+    const slowFirst = () => {
+        const fib = Buffer.from(generateFibModule());
+        const add = Buffer.from(generateAddModule());
+        return [
+            { wasm: fib, func: 'fib', args: [30000000] },
+            ...Array.from({ length: 99 }, (_, i) => ({ wasm: add, func: 'add', args: [i, i] })),
+        ];
+    };
+
+    test('streams fast results before a slow first task', async () => {
+        const seen = [];
+        const batch = await runtime.concurrentWasmStream(slowFirst(), (r) => seen.push(r.index), { completion: 'unordered' });
+        await new Promise(r => setTimeout(r, 50));
+        expect(seen.length).toBe(100);
+        expect(seen[99]).toBe(0);
+
+        const ordered = await runtime.concurrentWasmSettled(slowFirst(), { completion: 'ordered' });
+        expect(batch.results.map(r => r.value)).toEqual(ordered.results.map(r => r.value));
+    });
+
+    test('the promise form places results by index', async () => {
+        const tasks = slowFirst();
+        const unordered = await runtime.concurrentWasm(tasks, { completion: 'unordered' });
+        expect(unordered).toEqual(await runtime.concurrentWasm(tasks, { completion: 'ordered' }));
+        expect(unordered[6]).toBe(10);
+        await expect(runtime.concurrentWasm(tasks, { completion: 'sorted' })).rejects.toThrow('ERR_INVALID_INPUT');
+    });
+});
+
+describe.skipIf(!hasRuntime)('channel waits', () => {
+    const sendTokens = (ctx, guestId, tokens) => {
+        const wasm = Buffer.from(generateSendOnceModule());
+        const tasks = tokens.map(t => ({ wasm, func: 'send_once', args: [guestId, t] }));
+        return runtime.concurrentWasmWithChannels(tasks, { channelContext: ctx });
+    };
+
+    test('waitCount resolves once every worker has sent its token', async () => {
+        const ctx = runtime.contextCreate();
+        const ch = runtime.channelCreateIn(ctx, 16);
+        const guestId = runtime.contextGrant(ctx, ch);
+        const waiting = runtime.channelWaitCount(ch, 8, 5000);
+        await sendTokens(ctx, guestId, [0, 1, 2, 3, 4, 5, 6, 7]);
+        expect((await waiting).sort((a, b) => a - b)).toEqual([0, 1, 2, 3, 4, 5, 6, 7]);
+        expect(runtime.channelReceive(ch)).toBe(null);
+        runtime.contextDestroy(ctx);
+    });
+
+    test('waitCount times out when a token is missing', async () => {
+        const ctx = runtime.contextCreate();
+        const ch = runtime.channelCreateIn(ctx, 16);
+        const guestId = runtime.contextGrant(ctx, ch);
+        await sendTokens(ctx, guestId, [0, 1, 2]);
+        await expect(runtime.channelWaitCount(ch, 4, 50)).rejects.toThrow('ERR_TIMEOUT');
+        runtime.contextDestroy(ctx);
+    });
+
+    test('waitValue discards values until the sentinel, false on close', async () => {
+        const ch = runtime.channelCreate(8);
+        for (const v of [1, 2, -1, 3]) runtime.channelSend(ch, v);
+        expect(await runtime.channelWaitValue(ch, -1, 1000)).toBe(true);
+        expect(runtime.channelReceive(ch)).toBe(3);
+        runtime.channelClose(ch);
+        expect(await runtime.channelWaitValue(ch, -1)).toBe(false);
+    });
+});
+
+describe.skipIf(!hasRuntime)('deterministic dispatch', () => {
+    // Sixteen guests each send their index to one channel; what the channel
+    // holds afterwards is the order they ran in.
+    const dispatchOrder = async (seed) => {
+        runtime.configureRuntime({ deterministicDispatch: true, dispatchSeed: seed });
+        try {
+            const ctx = runtime.contextCreate();
+            const ch = runtime.channelCreateIn(ctx, 16);
+            const guestId = runtime.contextGrant(ctx, ch);
+            const wasm = Buffer.from(generateSendOnceModule());
+            const tasks = Array.from({ length: 16 }, (_, i) => ({ wasm, func: 'send_once', args: [guestId, i] }));
+            await runtime.concurrentWasmWithChannels(tasks, { channelContext: ctx });
+            const order = runtime.channelCloseAndDrain(ch);
+            runtime.contextDestroy(ctx);
+            return order;
+        } finally {
+            runtime.configureRuntime({ deterministicDispatch: false });
+        }
+    };
+
+    test('the same seed replays the same receive sequence', async () => {
+        const first = await dispatchOrder(42);
+        expect(await dispatchOrder(42)).toEqual(first);
+        expect([...first].sort((a, b) => a - b)).toEqual(Array.from({ length: 16 }, (_, i) => i));
+
+        const other = await dispatchOrder(43);
+        expect(other).not.toEqual(first);
+        expect(await dispatchOrder(43)).toEqual(other);
+    });
+
+    test('runtimeStats reports the mode', () => {
+        runtime.configureRuntime({ deterministicDispatch: true, dispatchSeed: 9 });
+        try {
+            const stats = runtime.runtimeStats();
+            expect(stats.deterministicDispatch).toBe(true);
+            expect(stats.dispatchSeed).toBe(9);
+        } finally {
+            runtime.configureRuntime({ deterministicDispatch: false });
+        }
+        expect(runtime.runtimeStats().deterministicDispatch).toBe(false);
+        expect(runtime.runtimeStats().dispatchSeed ?? null).toBeNull();
+    });
+});
+
+describe.skipIf(!hasRuntime)('chunked batches', () => {
+    // `tasks` add(i, 2i) calls, flattened two arguments per task
+    const flatAddArgs = (tasks) => {
+        const args = new BigInt64Array(tasks * 2);
+        for (let i = 0; i < tasks; i++) {
+            args[2 * i] = BigInt(i);
+            args[2 * i + 1] = BigInt(2 * i);
+        }
+        return Buffer.from(args.buffer);
+    };
+
+    test('a million tasks complete with every result in place', async () => {
+        const wasm = Buffer.from(generateAddModule());
+        const progress = [];
+        const out = await runtime.concurrentWasmChunked(wasm, 'add', flatAddArgs(1_000_000), 2, { chunkSize: 65536 },
+            (p) => progress.push(p));
+        expect(out.length).toBe(1_000_000 * 8);
+        for (const i of [0, 1, 65535, 65536, 999_999]) {
+            expect(out.readBigInt64LE(i * 8)).toBe(BigInt(3 * i));
+        }
+
+        await new Promise(r => setTimeout(r, 50));
+        expect(progress.length).toBe(16);
+        progress.forEach((p, i) => expect(p).toEqual({ completedChunks: i + 1, totalChunks: 16 }));
+    });
+
+    test('cancelling after the first progress event stops before the last chunk', async () => {
+        const wasm = Buffer.from(generateAddModule());
+        const execId = runtime.execIdCreate();
+        const progress = [];
+        const run = runtime.concurrentWasmChunked(wasm, 'add', flatAddArgs(200_000), 2, { chunkSize: 1000, execId },
+            (p) => {
+                progress.push(p.completedChunks);
+                runtime.execCancel(execId);
+            });
+        await expect(run).rejects.toThrow('ERR_CANCELLED');
+        expect(progress.length).toBeGreaterThan(0);
+        expect(progress[progress.length - 1]).toBeLessThan(200);
+        expect(runtime.execCancel(execId)).toBe(false);
+    });
+
+    test('argsFlat must hold whole tasks', async () => {
+        const wasm = Buffer.from(generateAddModule());
+        await expect(runtime.concurrentWasmChunked(wasm, 'add', Buffer.alloc(24), 2)).rejects.toThrow('ERR_INVALID_INPUT');
+    });
+});
+
+describe.skipIf(!hasRuntime)('close propagation', () => {
+    const wasm = () => Buffer.from(generateDrainModule());
+    const within = (ms, promise) => Promise.race([
+        promise,
+        new Promise((_, reject) => setTimeout(() => reject(new Error(`not done within ${ms}ms`)), ms)),
+    ]);
+
+    test('closing every channel in a context ends its blocked consumers', async () => {
+        const ctx = runtime.contextCreate();
+        const input = runtime.channelCreateIn(ctx, 4);
+        const guestId = runtime.contextGrant(ctx, input);
+        runtime.channelSend(input, 1);
+        const consumers = [0, 1, 2].map(() =>
+            runtime.execWasmWithChannels(wasm(), 'drain', [guestId], { channelContext: ctx }));
+        await new Promise(r => setTimeout(r, 50));
+
+        expect(runtime.channelCloseAllInContext(ctx)).toBe(1);
+        const counts = await within(2000, Promise.all(consumers));
+        expect(counts.reduce((a, b) => a + b, 0)).toBe(1);
+        runtime.contextDestroy(ctx);
+    });
+
+    test('closing and draining the input ends consumers blocked on it', async () => {
+        const ctx = runtime.contextCreate();
+        const input = runtime.channelCreateIn(ctx, 4);
+        const guestId = runtime.contextGrant(ctx, input);
+        const consumers = [0, 1, 2].map(() =>
+            runtime.execWasmWithChannels(wasm(), 'drain', [guestId], { channelContext: ctx }));
+        await new Promise(r => setTimeout(r, 50));
+
+        expect(runtime.channelCloseAndDrain(input)).toEqual([]);
+        expect(await within(2000, Promise.all(consumers))).toEqual([0, 0, 0]);
+        runtime.contextDestroy(ctx);
+    });
+
+    test('an id that never named a channel is not mistaken for closed', async () => {
+        const ctx = runtime.contextCreate();
+        // -2: the id does not resolve in this context
+        expect(await runtime.execWasmWithChannels(wasm(), 'drain', [0], { channelContext: ctx })).toBe(-2);
+        runtime.contextDestroy(ctx);
+    });
+});
+
+describe.skipIf(!hasRuntime)('admission control', () => {
+    test('a full queue refuses new work at once, then drains and accepts it again', async () => {
+        runtime.configureRuntime({ maxQueuedExecutions: 8 });
+        try {
+            const fib = Buffer.from(generateFibModule());
+            const add = Buffer.from(generateAddModule());
+            const slow = Array.from({ length: 6 }, () => ({ wasm: fib, func: 'fib', args: [60_000_000], tag: 'admission-flood' }));
+            // One at a time: five tasks wait behind the first, and give up after 10ms
+            const flood = runtime.concurrentWasmSettled(slow, { maxConcurrency: 1, queueTimeoutMs: 10 });
+            while (runtime.runtimeStats().queuedExecutions < 5) {
+                await new Promise(r => setTimeout(r, 1));
+            }
+
+            const quick = Array.from({ length: 4 }, (_, i) => ({ wasm: add, func: 'add', args: [i, i] }));
+            const started = Date.now();
+            await expect(runtime.concurrentWasm(quick)).rejects.toThrow('ERR_OVERLOADED');
+            expect(Date.now() - started).toBeLessThan(100);
+            expect(runtime.runtimeStats().overloadRejections).toBeGreaterThan(0);
+
+            const settled = await flood;
+            expect(settled.results[0].ok).toBe(true);
+            settled.results.slice(1).forEach(r => expect(r.error).toContain('ERR_QUEUE_TIMEOUT'));
+            // The timed-out tasks never ran, so only the first was recorded
+            expect(runtime.metricsSnapshot().find(m => m.tag === 'admission-flood').executions).toBe(1);
+
+            expect(runtime.runtimeStats().queuedExecutions).toBe(0);
+            expect(await runtime.concurrentWasm(quick)).toEqual([0, 2, 4, 6]);
+        } finally {
+            runtime.configureRuntime({ maxQueuedExecutions: 0 });
+        }
+    });
+});
+
+describe.skipIf(!hasRuntime)('fuel top-ups', () => {
+    const wasm = () => Buffer.from(generateFuelModule());
+
+    test('a granting policy carries a guest past the fuel limit', async () => {
+        const requests = [];
+        runtime.setFuelPolicy(request => {
+            requests.push(request);
+            return request.amount;
+        });
+        try {
+            expect(await runtime.execWasm(wasm(), 'run', [16, 100_000_000])).toBeGreaterThan(0);
+            expect(requests.length).toBe(16);
+            expect(requests[0]).toMatchObject({ amount: 100_000_000, granted: 0, taskIndex: 0 });
+            expect(requests[15].granted).toBe(1_500_000_000);
+        } finally {
+            runtime.setFuelPolicy(null);
+        }
+    });
+
+    test('a denying policy leaves the guest to run out of fuel', async () => {
+        let calls = 0;
+        runtime.setFuelPolicy(() => {
+            calls += 1;
+            return 0;
+        });
+        try {
+            await expect(runtime.execWasm(wasm(), 'run', [16, 100_000_000])).rejects.toThrow('ERR_OUT_OF_FUEL');
+            // About 80M fuel per round against the default limit of 1e9
+            expect(calls).toBeGreaterThanOrEqual(10);
+            expect(calls).toBeLessThan(16);
+        } finally {
+            runtime.setFuelPolicy(null);
+        }
+    });
+
+    test('without a policy requests are denied, and unmetered calls read -1', async () => {
+        const remaining = await runtime.execWasm(wasm(), 'remaining', []);
+        expect(remaining).toBeGreaterThan(0);
+        await expect(runtime.execWasm(wasm(), 'run', [16, 100_000_000])).rejects.toThrow('ERR_OUT_OF_FUEL');
+        expect(await runtime.execWasm(wasm(), 'remaining', [], { metering: 'none' })).toBe(-1);
+    });
+});
+
+describe.skipIf(!hasRuntime)('channel message TTL', () => {
+    const sleep = ms => new Promise(r => setTimeout(r, ms));
+
+    test('messages held past the TTL are dropped and counted', async () => {
+        const ch = runtime.channelCreate(8, { ttlMs: 40 });
+        [1, 2, 3].forEach(v => runtime.channelSend(ch, v));
+        await sleep(80);
+        [4, 5].forEach(v => runtime.channelSend(ch, v));
+        expect(runtime.channelReceive(ch)).toBe(4);
+        expect(runtime.channelReceive(ch)).toBe(5);
+        expect(runtime.channelReceive(ch)).toBeNull();
+        const stat = runtime.channelStat(ch);
+        expect(stat.expired).toBe(3);
+        expect(stat.len).toBe(0);
+        runtime.channelClose(ch);
+    });
+
+    test('purging ages out old messages on a channel without a TTL', async () => {
+        const ch = runtime.channelCreate(8, { timestamps: true });
+        [1, 2].forEach(v => runtime.channelSend(ch, v));
+        await sleep(60);
+        runtime.channelSend(ch, 3);
+        expect(runtime.channelPurgeOlderThan(ch, 30)).toBe(2);
+        expect(runtime.channelStat(ch).expired).toBe(2);
+        expect(runtime.channelCloseAndDrain(ch)).toEqual([3]);
+
+        const plain = runtime.channelCreate(4);
+        runtime.channelSend(plain, 1);
+        expect(() => runtime.channelPurgeOlderThan(plain, 0)).toThrow('ERR_INVALID_INPUT');
+        expect(runtime.channelStat(plain).expired).toBe(0);
+        expect(runtime.channelReceive(plain)).toBe(1);
+        runtime.channelClose(plain);
+    });
+});
+
+describe.skipIf(!hasRuntime)('shared data', () => {
+    const wasm = () => Buffer.from(generateSharedDataModule());
+    // Entry i of the sorted table is i * 3 - 100
+    const table = n => {
+        const bytes = Buffer.alloc(n * 8);
+        for (let i = 0; i < n; i++) bytes.writeBigInt64LE(BigInt(i * 3 - 100), i * 8);
+        return bytes;
+    };
+
+    test('a guest binary-searches a host-registered table', async () => {
+        const id = runtime.sharedDataRegister(table(100_000));
+        try {
+            const search = key => runtime.execWasm(wasm(), 'search', [key, 0], { sharedData: [id] });
+            expect(await search(-100)).toBe(0);
+            expect(await search(50_000 * 3 - 100)).toBe(50_000);
+            expect(await search(299_897)).toBe(99_999);
+            expect(await search(-99)).toBe(-1);
+            expect(await runtime.execWasm(wasm(), 'len', [0], { sharedData: [id] })).toBe(800_000);
+        } finally {
+            runtime.sharedDataUnregister(id);
+        }
+    });
+
+    test('out-of-range reads return an error status', async () => {
+        const id = runtime.sharedDataRegister(Buffer.alloc(64, 7));
+        try {
+            const read = args => runtime.execWasm(wasm(), 'read', args, { sharedData: [id] });
+            expect(await read([0, 0, 0, 64])).toBe(0);
+            expect(await read([0, 60, 0, 8])).toBe(-2);
+            expect(await read([0, 0, 65_532, 8])).toBe(-3);
+            expect(await read([1, 0, 0, 8])).toBe(-1);
+            // A call given no segments sees none
+            expect(await runtime.execWasm(wasm(), 'len', [0])).toBe(-1);
+        } finally {
+            runtime.sharedDataUnregister(id);
+        }
+    });
+
+    test('unregistering during a call leaves its reads intact', async () => {
+        const id = runtime.sharedDataRegister(table(1_000));
+        const running = runtime.execWasm(wasm(), 'search', [2_897, 50_000_000], { sharedData: [id] });
+        await new Promise(r => setTimeout(r, 20));
+        expect(runtime.sharedDataUnregister(id)).toBe(true);
+        expect(await running).toBe(999);
+        expect(runtime.sharedDataUnregister(id)).toBe(false);
+        await expect(runtime.execWasm(wasm(), 'len', [0], { sharedData: [id] })).rejects.toThrow('ERR_INVALID_INPUT');
+    });
+});
+
+describe.skipIf(!hasRuntime)('warmup', () => {
+    test('reports each step and finds everything in place the second time', async () => {
+        const first = await runtime.warmup();
+        expect(first.steps.map(s => s.name)).toEqual(
+            ['tokio', 'channels', 'engine.fuel', 'engine.unmetered', 'compile.fuel', 'compile.unmetered']);
+        for (const step of first.steps) {
+            expect(step.ms).toBeGreaterThanOrEqual(0);
+        }
+        expect(first.totalMs).toBeGreaterThanOrEqual(0);
+        const again = await runtime.warmup({ compile: false });
+        expect(again.steps.map(s => s.name)).toEqual(['tokio', 'channels', 'engine.fuel', 'engine.unmetered']);
+        expect(again.steps.every(s => !s.initialized)).toBe(true);
+        expect(() => runtime.configureEngineFeatures({ maxWasmStackBytes: 1 << 20 })).toThrow('before the first module');
+    });
+});
+
+// Only against a runtime built with `--features fault-injection`
+describe.skipIf(!hasRuntime || !runtime.injectFault)('fault injection', () => {
+    afterEach(() => runtime.clearFaults());
+
+    test('each fault fails a call the documented way, and only while injected', async () => {
+        const wasm = generateAddModule();
+        // Cached, so the compile fault fires on a cache hit
+        expect(await runtime.execWasm(wasm, 'add', [1, 2])).toBe(3);
+        const cases = [
+            ['compile', 'compile: injected fault'],
+            ['instantiate', 'WASM instantiation error: injected fault'],
+            ['exec_trap', 'ERR_TRAP'],
+            ['join', 'join error: injected fault'],
+        ];
+        for (const [kind, message] of cases) {
+            runtime.injectFault(kind, 1);
+            await expect(runtime.execWasm(wasm, 'add', [1, 2])).rejects.toThrow(message);
+            runtime.injectFault(kind, 0);
+            expect(await runtime.execWasm(wasm, 'add', [2, 3])).toBe(5);
+        }
+    });
+
+    test('everyN fails only every Nth pass', async () => {
+        const wasm = generateAddModule();
+        runtime.injectFault('exec_trap', 3);
+        const outcomes = [];
+        for (let i = 0; i < 6; i++) {
+            outcomes.push(await runtime.execWasm(wasm, 'add', [i, 1]).then(() => 'ok', () => 'trap'));
+        }
+        expect(outcomes).toEqual(['ok', 'ok', 'trap', 'ok', 'ok', 'trap']);
+    });
+
+    test('a failed send buffers nothing', () => {
+        const ch = runtime.channelCreate(4);
+        const before = runtime.runtimeStats().channelBufferBytes;
+        runtime.injectFault('channel_send', 1);
+        expect(() => runtime.channelSend(ch, 1)).toThrow('ERR_CHANNEL_BUDGET');
+        expect(runtime.runtimeStats().channelBufferBytes).toBe(before);
+        runtime.clearFaults();
+        runtime.channelSend(ch, 2);
+        expect(runtime.channelReceive(ch)).toBe(2);
+        runtime.channelClose(ch);
+    });
+
+    test('resetAllState stops injecting; unknown kinds are rejected', async () => {
+        runtime.injectFault('exec_trap', 1);
+        runtime.resetAllState();
+        expect(await runtime.execWasm(generateAddModule(), 'add', [1, 2])).toBe(3);
+        expect(() => runtime.injectFault('oom', 1)).toThrow('ERR_INVALID_INPUT');
+    });
+});
+
+describe.skipIf(!hasRuntime)('module cache pinning', () => {
+    // The add module with a custom section tagging it, so each tag compiles separately
+    const tagged = (tag) => Buffer.concat([Buffer.from(generateAddModule()), Buffer.from([0x00, 0x05, 0x03, 0x74, 0x61, 0x67, tag])]);
+
+    afterEach(() => {
+        runtime.resetAllState();
+        runtime.configureRuntime({ moduleCacheCapacity: 256, maxPinnedCacheFraction: 0.5 });
+    });
+
+    test('a pinned module survives an eviction storm until unpinned', async () => {
+        runtime.resetAllState();
+        runtime.configureRuntime({ moduleCacheCapacity: 4 });
+        const core = tagged(1);
+        expect(await runtime.prefetchModules([core], true)).toBe(1);
+        expect(await runtime.pinModule({ wasm: core })).toBe(false);
+
+        const before = runtime.runtimeStats().moduleCacheEvictions;
+        expect(await runtime.prefetchModules(Array.from({ length: 12 }, (_, i) => tagged(10 + i)), false)).toBe(12);
+        let stats = runtime.runtimeStats();
+        expect(stats.moduleCacheSize).toBe(4);
+        expect(stats.pinnedModules).toBe(1);
+        expect(stats.moduleCacheEvictions - before).toBe(9);
+        expect(await runtime.prefetchModules([core], false)).toBe(0);
+        expect(await runtime.prefetchModules([tagged(10)], false)).toBe(1);
+
+        expect(runtime.unpinModule({ wasm: core })).toBe(true);
+        expect(runtime.unpinModule({ wasm: core })).toBe(false);
+        await runtime.prefetchModules(Array.from({ length: 4 }, (_, i) => tagged(30 + i)), false);
+        expect(await runtime.prefetchModules([core], false)).toBe(1);
+        expect(runtime.runtimeStats().pinnedModules).toBe(0);
+    });
+
+    test('pins past the configured fraction are refused', async () => {
+        runtime.configureRuntime({ moduleCacheCapacity: 4, maxPinnedCacheFraction: 0.5 });
+        const handle = await runtime.moduleCompile(tagged(40));
+        expect(await runtime.pinModule({ module: handle })).toBe(true);
+        expect(await runtime.pinModule({ wasm: tagged(41) })).toBe(true);
+        await expect(runtime.pinModule({ wasm: tagged(42) })).rejects.toThrow('ERR_PIN_BUDGET');
+        await expect(runtime.prefetchModules([tagged(43)], true)).rejects.toThrow('ERR_PIN_BUDGET');
+        expect(runtime.runtimeStats().pinnedModules).toBe(2);
+        expect(runtime.unpinModule({ module: handle })).toBe(true);
+        expect(await runtime.pinModule({ wasm: tagged(42) })).toBe(true);
+    });
+
+    test('filling the cache with pinned modules shows up as overflows', async () => {
+        runtime.configureRuntime({ moduleCacheCapacity: 2, maxPinnedCacheFraction: 1 });
+        await runtime.prefetchModules([tagged(50), tagged(51)], true);
+        const before = runtime.runtimeStats().moduleCachePinnedOverflows;
+        expect(await runtime.execWasm(tagged(52), 'add', [1, 2])).toBe(3);
+        const stats = runtime.runtimeStats();
+        expect(stats.moduleCachePinnedOverflows - before).toBe(1);
+        expect(stats.moduleCacheSize).toBe(3);
+    });
+});
+
+describe.skipIf(!hasRuntime)('default exec options', () => {
+    const wasm = () => Buffer.from(generateAddModule());
+    const executions = tag => runtime.metricsSnapshot().find(m => m.tag === tag)?.executions ?? 0;
+
+    test('defaults apply when a call passes nothing and per-call fields win', async () => {
+        runtime.setDefaultExecOptions({ tag: 'defaults-a', allowedImports: [], instancePool: { size: 2, resetEvery: 10 } });
+        try {
+            expect(runtime.getDefaultExecOptions()).toEqual({
+                tag: 'defaults-a',
+                allowedImports: [],
+                instancePool: { size: 2, resetEvery: 10 },
+            });
+            const before = executions('defaults-a');
+            expect(await runtime.execWasm(wasm(), 'add', [1, 2])).toBe(3);
+            expect(executions('defaults-a') - before).toBe(1);
+
+            const own = executions('defaults-b');
+            expect(await runtime.execWasm(wasm(), 'add', [2, 2], { tag: 'defaults-b', instancePool: { size: 1 } })).toBe(4);
+            expect(executions('defaults-b') - own).toBe(1);
+            expect(executions('defaults-a') - before).toBe(1);
+
+            // The default import list applies too: the channel module is refused
+            const ch = runtime.channelCreate(1);
+            await expect(runtime.execWasm(Buffer.from(generateSendOnceModule()), 'send_once', [ch, 1]))
+                .rejects.toThrow('ERR_FORBIDDEN_IMPORT');
+        } finally {
+            runtime.setDefaultExecOptions({});
+        }
+        expect(runtime.getDefaultExecOptions()).toEqual({});
+    });
+
+    test('unknown keys are rejected when setting defaults and per call', async () => {
+        expect(() => runtime.setDefaultExecOptions({ timoutMs: 5 })).toThrow("unknown exec option 'timoutMs'");
+        expect(() => runtime.setDefaultExecOptions({ instancePool: { sise: 2 } }))
+            .toThrow("unknown exec option 'instancePool.sise'");
+        expect(() => runtime.setDefaultExecOptions({ metering: 'gas' })).toThrow("unknown metering 'gas'");
+        expect(runtime.getDefaultExecOptions()).toEqual({});
+        // Argument conversion fails before the call's promise exists
+        const exec = async options => runtime.execWasm(wasm(), 'add', [1, 2], options);
+        await expect(exec({ queueTimeoutMS: 5 })).rejects.toThrow("unknown exec option 'queueTimeoutMS'");
+        await expect(exec({ capabilities: { channel: true } })).rejects.toThrow("unknown exec option 'capabilities.channel'");
+    });
+
+    test('a batch keeps the defaults it was submitted with', async () => {
+        runtime.setDefaultExecOptions({ tag: 'defaults-batch-1' });
+        try {
+            const before = [executions('defaults-batch-1'), executions('defaults-batch-2')];
+            const tasks = Array.from({ length: 64 }, (_, i) => ({ wasm: wasm(), func: 'add', args: [i, 1] }));
+            const batch = runtime.concurrentWasm(tasks);
+            runtime.setDefaultExecOptions({ tag: 'defaults-batch-2' });
+            expect((await batch).length).toBe(64);
+            expect(executions('defaults-batch-1') - before[0]).toBe(64);
+            expect(executions('defaults-batch-2') - before[1]).toBe(0);
+        } finally {
+            runtime.setDefaultExecOptions({});
+        }
+    });
+});
+
+describe.skipIf(!hasRuntime)('cancel tokens', () => {
+    test('cancelling a parent stops a guest, a channel wait and a producer', async () => {
+        const parent = runtime.cancelTokenCreate();
+        const child = runtime.cancelTokenChild(parent);
+        const spinning = runtime.execWasm(Buffer.from(generateSpinModule()), 'spin', [0], { cancelToken: child });
+        const idle = runtime.channelCreate(1);
+        const waiting = runtime.channelWaitCount(idle, 1, undefined, child);
+        const ticks = runtime.channelCreate(1000);
+        runtime.spawnChannelProducer(ticks, Array.from({ length: 1000 }, (_, i) => i), 5, child);
+        await new Promise(r => setTimeout(r, 100));
+
+        const started = Date.now();
+        expect(runtime.cancelTokenCancel(parent)).toBe(true);
+        await expect(spinning).rejects.toThrow('ERR_CANCELLED');
+        await expect(waiting).rejects.toThrow('ERR_CANCELLED');
+        expect(Date.now() - started).toBeLessThan(1000);
+        expect(runtime.cancelTokenIsCancelled(child)).toBe(true);
+
+        // The producer sends nothing more once cancelled
+        await new Promise(r => setTimeout(r, 20));
+        const sent = runtime.channelStat(ticks).len;
+        await new Promise(r => setTimeout(r, 50));
+        expect(runtime.channelStat(ticks).len).toBe(sent);
+        expect(sent).toBeLessThan(1000);
+    });
+
+    test('an already-cancelled token fails new work at once', async () => {
+        const token = runtime.cancelTokenCreate();
+        runtime.cancelTokenCancel(token);
+        expect(runtime.cancelTokenCancel(token)).toBe(false);
+        await expect(runtime.execWasm(Buffer.from(generateAddModule()), 'add', [1, 2], { cancelToken: token }))
+            .rejects.toThrow('ERR_CANCELLED');
+        const ch = runtime.channelCreate(1);
+        expect(() => runtime.spawnChannelProducer(ch, [1], 0, token)).toThrow('ERR_CANCELLED');
+        await expect(runtime.channelWaitCount(ch, 1, 50, token)).rejects.toThrow('ERR_CANCELLED');
+        expect(runtime.cancelTokenRelease(token)).toBe(true);
+        expect(() => runtime.cancelTokenIsCancelled(token)).toThrow('no such cancel token');
+    });
+});
+
+describe.skipIf(!hasRuntime)('kernels', () => {
+    test('sort, sum and argsort work on the Float64Array given', () => {
+        const values = new Float64Array([3, -0, NaN, 0, -1.5, 3]);
+        expect(Array.from(runtime.kernelArgsortF64(values))).toEqual([4, 1, 3, 0, 5, 2]);
+        runtime.kernelSortF64(values);
+        expect(Object.is(values[1], -0)).toBe(true);
+        expect(Array.from(values.subarray(0, 5))).toEqual([-1.5, -0, 0, 3, 3]);
+        expect(Number.isNaN(values[5])).toBe(true);
+        expect(runtime.kernelSumF64(new Float64Array([1.5, 2.25, -0.75]))).toBe(3);
+
+        // A subarray is sorted where it lies
+        const whole = new Float64Array([9, 3, 2, 1, 0]);
+        runtime.kernelSortF64(whole.subarray(1, 4));
+        expect(Array.from(whole)).toEqual([9, 1, 2, 3, 0]);
+    });
+
+    test('value counts come back as BigInt arrays', () => {
+        const { keys, counts } = runtime.kernelValueCountsI64(new BigInt64Array([7n, -2n, 7n, 0n, 7n, -2n]));
+        expect(keys).toBeInstanceOf(BigInt64Array);
+        expect(Array.from(keys)).toEqual([-2n, 0n, 7n]);
+        expect(Array.from(counts)).toEqual([2n, 1n, 3n]);
+    });
+
+    test('empty, mistyped and detached arrays', () => {
+        expect(runtime.kernelSumF64(new Float64Array(0))).toBe(0);
+        expect(runtime.kernelArgsortF64(new Float64Array(0)).length).toBe(0);
+        expect(() => runtime.kernelSortF64(new Float32Array(4))).toThrow('expected a Float64Array, got a Float32Array');
+        expect(() => runtime.kernelSortF64([1, 2])).toThrow('ERR_INVALID_INPUT');
+        const values = new Float64Array(8);
+        structuredClone(values.buffer, { transfer: [values.buffer] });
+        expect(() => runtime.kernelSortF64(values)).toThrow('detached');
+    });
+
+    test('async sorts report progress up to 1', async () => {
+        const values = Float64Array.from({ length: 1_000_000 }, (_, i) => (i * 7919) % 1_000_000);
+        const reports = [];
+        await runtime.kernelSortF64Async(values, { progressIntervalMs: 0 }, fraction => reports.push(fraction));
+        for (let i = 1; i < values.length; i++) expect(values[i - 1] <= values[i]).toBe(true);
+        // Progress calls may land just after the promise settles
+        await new Promise(r => setTimeout(r, 20));
+        expect(reports.length).toBeGreaterThan(2);
+        expect(reports.every((f, i) => i === 0 || reports[i - 1] <= f)).toBe(true);
+        expect(reports.at(-1)).toBe(1);
+
+        const ints = new BigInt64Array([5n, -3n, 9n, 0n]);
+        await runtime.kernelSortI64Async(ints);
+        expect(Array.from(ints)).toEqual([-3n, 0n, 5n, 9n]);
+        expect(await runtime.kernelSumF64Async(new Float64Array([1.5, 2.25, -0.75]))).toBe(3);
+        await expect(runtime.kernelSortF64Async(new Float32Array(4))).rejects.toThrow('expected a Float64Array, got a Float32Array');
+    });
+
+    test('cancelling an async sort rejects promptly and keeps every value', async () => {
+        const values = Float64Array.from({ length: 5_000_000 }, () => Math.random());
+        const before = Float64Array.from(values).sort();
+        const token = runtime.cancelTokenCreate();
+        const sorting = runtime.kernelSortF64Async(values, { cancelToken: token });
+        const started = Date.now();
+        runtime.cancelTokenCancel(token);
+        await expect(sorting).rejects.toThrow('ERR_CANCELLED');
+        expect(Date.now() - started).toBeLessThan(500);
+        expect(Float64Array.from(values).sort()).toEqual(before);
+        runtime.cancelTokenRelease(token);
+    });
+});
+
+describe.skipIf(!hasRuntime)('execution recordings', () => {
+    test('a recorded consumer replays without its channel', async () => {
+        const ch = runtime.channelCreate(8);
+        for (const v of [4, 5, 6]) runtime.channelSend(ch, v);
+        const wasm = Buffer.from(generateConsumerModule());
+        const recorded = await runtime.execWasmWithChannels(wasm, 'consumer', [ch, 3], { record: true });
+        expect(recorded.result).toBe(15);
+        expect(recorded.error).toBeUndefined();
+        runtime.channelClose(ch);
+
+        expect(await runtime.replayExecution(recorded.recording, wasm)).toBe(15);
+        const tampered = recorded.recording.replace('"result":5}', '"result":50}');
+        await expect(runtime.replayExecution(tampered, wasm))
+            .rejects.toThrow('ERR_REPLAY_DIVERGED: the recorded call returned 15, the replay returned 60');
+    });
+
+    test('failing calls are recorded rather than rejected', async () => {
+        const wasm = Buffer.from(generateConsumerModule());
+        const recorded = await runtime.execWasmWithChannels(wasm, 'missing', [0, 1], { record: true });
+        expect(recorded.result).toBeUndefined();
+        expect(recorded.error).toContain("function 'missing' not found");
+        await expect(runtime.replayExecution(recorded.recording, wasm)).rejects.toThrow("function 'missing' not found");
+        await expect(runtime.replayExecution(recorded.recording, Buffer.from(generateAddModule())))
+            .rejects.toThrow('ERR_INVALID_INPUT: the recording is of module');
+        await expect(runtime.replayExecution('{}', wasm)).rejects.toThrow("ERR_INVALID_INPUT: recording: missing 'version'");
+    });
+});
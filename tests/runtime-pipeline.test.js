@@ -0,0 +1,102 @@
+import { describe, test, expect } from 'bun:test';
+import { join } from 'path';
+import { existsSync, readdirSync } from 'fs';
+
+// Load the napi-rs addon — returns null if not available
+function tryLoadRuntime() {
+    const searchDirs = [
+        join(__dirname, '..', 'tova_runtime'),
+        join(__dirname, '..', 'tova_runtime', 'target', 'release'),
+    ];
+    for (const dir of searchDirs) {
+        if (!existsSync(dir)) continue;
+        const files = readdirSync(dir).filter(f => f.endsWith('.node'));
+        for (const f of files) {
+            try { return require(join(dir, f)); } catch (e) { continue; }
+        }
+    }
+    return null;
+}
+
+const runtime = tryLoadRuntime();
+const hasRuntime = runtime !== null;
+
+let generateDoubleModule, generateNegateModule;
+if (hasRuntime) {
+    ({ generateDoubleModule, generateNegateModule } = require('./fixtures/gen-test-wasm.js'));
+}
+
+describe.skipIf(!hasRuntime)('pipelines', () => {
+    test('two-stage double-then-negate pipeline over 10k inputs', async () => {
+        const n = 10000;
+        const source = runtime.channelCreate(64);
+        const middle = runtime.channelCreate(64);
+        // Sink holds everything so the test can read after shutdown
+        const sink = runtime.channelCreate(n);
+
+        const id = await runtime.pipelineCreate({
+            stages: [
+                { wasm: Buffer.from(generateDoubleModule()), func: 'double', workers: 4, inputChannel: source, outputChannel: middle },
+                { wasm: Buffer.from(generateNegateModule()), func: 'negate', workers: 2, inputChannel: middle, outputChannel: sink },
+            ],
+        });
+
+        for (let i = 0; i < n; i++) {
+            runtime.channelSend(source, i);
+        }
+
+        const stats = await runtime.pipelineShutdown(id, true);
+        expect(stats.length).toBe(2);
+        expect(stats[0].processed).toBe(n);
+        expect(stats[1].processed).toBe(n);
+        expect(stats[0].liveWorkers).toBe(0);
+        expect(stats[1].liveWorkers).toBe(0);
+        expect(stats[0].errors).toBe(0);
+
+        const values = [];
+        let v;
+        while ((v = runtime.channelReceive(sink)) !== null) {
+            values.push(v);
+        }
+        expect(values.length).toBe(n);
+        values.sort((a, b) => b - a);
+        expect(values).toEqual(Array.from({ length: n }, (_, i) => -2 * i));
+    });
+
+    test('closing the source shuts the pipeline down on its own', async () => {
+        const source = runtime.channelCreate(16);
+        const sink = runtime.channelCreate(16);
+        const id = await runtime.pipelineCreate({
+            stages: [
+                { wasm: Buffer.from(generateDoubleModule()), func: 'double', workers: 2, inputChannel: source, outputChannel: sink },
+            ],
+        });
+        runtime.channelSend(source, 21);
+        runtime.channelClose(source);
+
+        // Workers exit after draining and close the sink behind them
+        const deadline = Date.now() + 5000;
+        while (runtime.pipelineStats(id)[0].liveWorkers > 0 && Date.now() < deadline) {
+            await new Promise(r => setTimeout(r, 5));
+        }
+        expect(runtime.pipelineStats(id)[0].liveWorkers).toBe(0);
+        expect(runtime.channelReceive(sink)).toBe(42);
+        expect(runtime.channelReceive(sink)).toBeNull();
+        await runtime.pipelineShutdown(id, true);
+    });
+
+    test('unknown pipeline ids error', async () => {
+        expect(() => runtime.pipelineStats(999999)).toThrow('no such pipeline');
+        await expect(runtime.pipelineShutdown(999999, false)).rejects.toThrow('no such pipeline');
+    });
+
+    test('a bad stage fails the whole create', async () => {
+        const a = runtime.channelCreate(1);
+        const b = runtime.channelCreate(1);
+        await expect(runtime.pipelineCreate({
+            stages: [
+                { wasm: Buffer.from(generateDoubleModule()), func: 'missing', inputChannel: a, outputChannel: b },
+            ],
+        })).rejects.toThrow('stage 0');
+    });
+});
@@ -0,0 +1,190 @@
+// Runtime benchmarks: exec_wasm with a warm and a cold module cache, a
+// compute-heavy guest under each metering mode, typed against dynamic calls
+// in a reused-instance batch (and a batch mixing functions), and channel
+// throughput between tokio blocking tasks. Same reporting as
+// native/benches/kernels.rs: the median of repeated samples, per operation.
+//
+//   cargo bench --bench runtime
+//   cargo bench --bench runtime -- channel
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use tova_runtime::bench;
+
+const TARGET: Duration = Duration::from_millis(300);
+const MAX_SAMPLES: usize = 50;
+
+// add(i64, i64) -> i64, as produced by tests/fixtures/gen-test-wasm.js
+const ADD_WASM: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x07, 0x01, 0x60, 0x02, 0x7e,
+    0x7e, 0x01, 0x7e, 0x03, 0x02, 0x01, 0x00, 0x07, 0x07, 0x01, 0x03, 0x61, 0x64, 0x64,
+    0x00, 0x00, 0x0a, 0x09, 0x01, 0x07, 0x00, 0x20, 0x00, 0x20, 0x01, 0x7c, 0x0b,
+];
+
+// fib(i64) -> i64, iterative, as produced by tests/fixtures/gen-test-wasm.js
+const FIB_WASM: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x06, 0x01, 0x60, 0x01, 0x7e,
+    0x01, 0x7e, 0x03, 0x02, 0x01, 0x00, 0x07, 0x07, 0x01, 0x03, 0x66, 0x69, 0x62, 0x00,
+    0x00, 0x0a, 0x31, 0x01, 0x2f, 0x03, 0x01, 0x7e, 0x01, 0x7e, 0x01, 0x7e, 0x42, 0x01,
+    0x21, 0x02, 0x02, 0x40, 0x03, 0x40, 0x20, 0x03, 0x20, 0x00, 0x59, 0x0d, 0x01, 0x20,
+    0x01, 0x20, 0x02, 0x7c, 0x20, 0x02, 0x21, 0x01, 0x21, 0x02, 0x20, 0x03, 0x42, 0x01,
+    0x7c, 0x21, 0x03, 0x0c, 0x00, 0x0b, 0x0b, 0x20, 0x01, 0x0b,
+];
+
+// One function per typed batch signature, as in the executor tests:
+//   add(i32, i32) = a + b, sub(i32, i32) = a - b
+//   mad3_i32/mad3_i64(a, b, c) = a * b - c
+//   mad4_i32/mad4_i64(a, b, c, d) = a * b - c * d
+//   sqrt(f64), div(f64, f64), mad3_f64(f64, f64, f64)
+const TYPED_WASM: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x3d, 0x09, 0x60,
+    0x02, 0x7f, 0x7f, 0x01, 0x7f, 0x60, 0x02, 0x7f, 0x7f, 0x01, 0x7f, 0x60,
+    0x03, 0x7f, 0x7f, 0x7f, 0x01, 0x7f, 0x60, 0x03, 0x7e, 0x7e, 0x7e, 0x01,
+    0x7e, 0x60, 0x04, 0x7f, 0x7f, 0x7f, 0x7f, 0x01, 0x7f, 0x60, 0x04, 0x7e,
+    0x7e, 0x7e, 0x7e, 0x01, 0x7e, 0x60, 0x01, 0x7c, 0x01, 0x7c, 0x60, 0x02,
+    0x7c, 0x7c, 0x01, 0x7c, 0x60, 0x03, 0x7c, 0x7c, 0x7c, 0x01, 0x7c, 0x03,
+    0x0a, 0x09, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x07,
+    0x51, 0x09, 0x03, 0x61, 0x64, 0x64, 0x00, 0x00, 0x03, 0x73, 0x75, 0x62,
+    0x00, 0x01, 0x08, 0x6d, 0x61, 0x64, 0x33, 0x5f, 0x69, 0x33, 0x32, 0x00,
+    0x02, 0x08, 0x6d, 0x61, 0x64, 0x33, 0x5f, 0x69, 0x36, 0x34, 0x00, 0x03,
+    0x08, 0x6d, 0x61, 0x64, 0x34, 0x5f, 0x69, 0x33, 0x32, 0x00, 0x04, 0x08,
+    0x6d, 0x61, 0x64, 0x34, 0x5f, 0x69, 0x36, 0x34, 0x00, 0x05, 0x04, 0x73,
+    0x71, 0x72, 0x74, 0x00, 0x06, 0x03, 0x64, 0x69, 0x76, 0x00, 0x07, 0x08,
+    0x6d, 0x61, 0x64, 0x33, 0x5f, 0x66, 0x36, 0x34, 0x00, 0x08, 0x0a, 0x5c,
+    0x09, 0x07, 0x00, 0x20, 0x00, 0x20, 0x01, 0x6a, 0x0b, 0x07, 0x00, 0x20,
+    0x00, 0x20, 0x01, 0x6b, 0x0b, 0x0a, 0x00, 0x20, 0x00, 0x20, 0x01, 0x6c,
+    0x20, 0x02, 0x6b, 0x0b, 0x0a, 0x00, 0x20, 0x00, 0x20, 0x01, 0x7e, 0x20,
+    0x02, 0x7d, 0x0b, 0x0d, 0x00, 0x20, 0x00, 0x20, 0x01, 0x6c, 0x20, 0x02,
+    0x20, 0x03, 0x6c, 0x6b, 0x0b, 0x0d, 0x00, 0x20, 0x00, 0x20, 0x01, 0x7e,
+    0x20, 0x02, 0x20, 0x03, 0x7e, 0x7d, 0x0b, 0x05, 0x00, 0x20, 0x00, 0x9f,
+    0x0b, 0x07, 0x00, 0x20, 0x00, 0x20, 0x01, 0xa3, 0x0b, 0x0a, 0x00, 0x20,
+    0x00, 0x20, 0x01, 0xa2, 0x20, 0x02, 0xa1, 0x0b,
+];
+
+/// ADD_WASM plus a custom section carrying `n`, so every call hashes to a
+/// module the cache has not seen.
+fn cold_add_wasm(n: u64) -> Vec<u8> {
+    let mut wasm = ADD_WASM.to_vec();
+    wasm.extend_from_slice(&[0x00, 14, 5, b'b', b'e', b'n', b'c', b'h']);
+    wasm.extend_from_slice(&n.to_le_bytes());
+    wasm
+}
+
+fn enabled(name: &str) -> bool {
+    // cargo passes `--bench`; anything else is a name filter
+    std::env::args().skip(1).find(|a| !a.starts_with("--")).is_none_or(|f| name.contains(&f))
+}
+
+/// Time `ops` operations per sample and report the median per operation.
+fn run(name: &str, ops: u64, mut sample: impl FnMut()) {
+    if !enabled(name) {
+        return;
+    }
+    let mut samples = Vec::new();
+    let started = Instant::now();
+    while samples.len() < MAX_SAMPLES && (samples.len() < 3 || started.elapsed() < TARGET) {
+        let t = Instant::now();
+        sample();
+        samples.push(t.elapsed());
+    }
+    samples.sort();
+    let median = samples[samples.len() / 2];
+    println!("{:<40} {:>12.1} ns/op", name, median.as_nanos() as f64 / ops as f64);
+}
+
+fn bench_exec() {
+    bench::exec_wasm(ADD_WASM, "add", &[1, 2]).unwrap();
+    run("exec_wasm/cache_hit", 100, || {
+        for i in 0..100 {
+            black_box(bench::exec_wasm(ADD_WASM, "add", &[i, 1]).unwrap());
+        }
+    });
+    let mut n = 0u64;
+    run("exec_wasm/cache_miss", 10, || {
+        for _ in 0..10 {
+            n += 1;
+            black_box(bench::exec_wasm(&cold_add_wasm(n), "add", &[1, 2]).unwrap());
+        }
+    });
+}
+
+/// Fuel instrumentation overhead: the same loop under each metering mode,
+/// per loop iteration.
+fn bench_metering() {
+    const ITERATIONS: i64 = 1_000_000;
+    for metering in ["fuel", "epoch", "none"] {
+        let expected = bench::exec_wasm_metered(FIB_WASM, "fib", &[ITERATIONS], metering).unwrap();
+        run(&format!("exec_wasm/fib/{}", metering), ITERATIONS as u64, || {
+            assert_eq!(black_box(bench::exec_wasm_metered(FIB_WASM, "fib", &[ITERATIONS], metering).unwrap()), expected);
+        });
+    }
+}
+
+/// Per call in a reused-instance batch, for each typed signature with the
+/// typed path on and forced off.
+fn bench_typed_dispatch() {
+    const TASKS: usize = 1_000;
+    let integer = [("add", 2), ("mad3_i32", 3), ("mad3_i64", 3), ("mad4_i32", 4), ("mad4_i64", 4)];
+    for (func, nargs) in integer {
+        let tasks: Vec<(String, Vec<i64>)> = (0..TASKS as i64).map(|i| (func.to_string(), vec![i; nargs])).collect();
+        for (mode, typed) in [("typed", true), ("dynamic", false)] {
+            run(&format!("shared_batch/{}/{}", func, mode), TASKS as u64, || {
+                black_box(bench::shared_batch(TYPED_WASM, tasks.clone(), typed));
+            });
+        }
+    }
+    for (func, nargs) in [("sqrt", 1), ("div", 2), ("mad3_f64", 3)] {
+        let tasks: Vec<(String, Vec<f64>)> = (0..TASKS).map(|i| (func.to_string(), vec![i as f64 + 1.0; nargs])).collect();
+        for (mode, typed) in [("typed", true), ("dynamic", false)] {
+            run(&format!("shared_batch/{}/{}", func, mode), TASKS as u64, || {
+                black_box(bench::shared_batch_f64(TYPED_WASM, tasks.clone(), typed));
+            });
+        }
+    }
+    // Several functions in one batch always take the dynamic path
+    let mixed: Vec<(String, Vec<i64>)> = (0..TASKS as i64)
+        .map(|i| match i % 3 {
+            0 => ("add".to_string(), vec![i; 2]),
+            1 => ("mad3_i64".to_string(), vec![i; 3]),
+            _ => ("mad4_i32".to_string(), vec![i; 4]),
+        })
+        .collect();
+    run("shared_batch/mixed/dynamic", TASKS as u64, || {
+        black_box(bench::shared_batch(TYPED_WASM, mixed.clone(), true));
+    });
+}
+
+fn bench_channels(rt: &tokio::runtime::Runtime) {
+    const VALUES: i64 = 100_000;
+    for capacity in [1u32, 64, 1024] {
+        run(&format!("channel/spsc/capacity={}", capacity), VALUES as u64, || {
+            rt.block_on(async {
+                let ch = bench::channel_create(capacity);
+                let producer = tokio::task::spawn_blocking(move || {
+                    for v in 0..VALUES {
+                        bench::channel_send(ch, v).unwrap();
+                    }
+                    bench::channel_close(ch);
+                });
+                let consumer = tokio::task::spawn_blocking(move || {
+                    let mut sum = 0i64;
+                    while let Some(v) = bench::channel_receive(ch) {
+                        sum += v;
+                    }
+                    sum
+                });
+                producer.await.unwrap();
+                assert_eq!(consumer.await.unwrap(), VALUES * (VALUES - 1) / 2);
+                bench::channel_destroy(ch);
+            });
+        });
+    }
+}
+
+fn main() {
+    let rt = tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+    bench_exec();
+    bench_metering();
+    bench_typed_dispatch();
+    bench_channels(&rt);
+}
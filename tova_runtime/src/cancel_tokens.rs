@@ -0,0 +1,227 @@
+use crate::errors::{self, lock};
+use crate::executor;
+use crossbeam_channel::{bounded, Receiver};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+// Cancellation tokens stop everything a caller started with one: running
+// guests (their stores' epoch callbacks check the token, and cancelling
+// bumps the epoch so they check promptly), channel waits, producers and
+// chunked batches. Each of those registers a hook on the token while it
+// runs; cancelling runs the hooks once, then cancels the token's children,
+// so cancelling the root of a tree of tokens tears down a whole pipeline.
+// A token stays cancelled, and anything started with it afterwards fails
+// with ERR_CANCELLED at once. Tokens live until released or reset.
+
+static TOKENS: Lazy<Mutex<HashMap<u64, Arc<Token>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+static NEXT_TOKEN: AtomicU64 = AtomicU64::new(0);
+
+type HookFn = Box<dyn FnOnce() + Send>;
+
+pub struct Token {
+    cancelled: AtomicBool,
+    state: Mutex<TokenState>,
+}
+
+#[derive(Default)]
+struct TokenState {
+    hooks: HashMap<u64, HookFn>,
+    next_hook: u64,
+    children: Vec<Weak<Token>>,
+}
+
+impl Token {
+    fn new(cancelled: bool) -> Arc<Token> {
+        Arc::new(Token { cancelled: AtomicBool::new(cancelled), state: Mutex::new(TokenState::default()) })
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    /// ERR_CANCELLED once the token is cancelled.
+    pub fn check(&self) -> Result<(), String> {
+        if self.is_cancelled() {
+            return Err(cancelled_error());
+        }
+        Ok(())
+    }
+
+    /// Run `hook` when the token is cancelled, or now if it already is.
+    /// Dropping the returned guard unregisters a hook that has not run.
+    pub fn on_cancel(self: &Arc<Self>, hook: impl FnOnce() + Send + 'static) -> Hook {
+        let mut state = lock(&self.state);
+        if self.is_cancelled() {
+            drop(state);
+            hook();
+            return Hook { token: Weak::new(), id: 0 };
+        }
+        let id = state.next_hook;
+        state.next_hook += 1;
+        state.hooks.insert(id, Box::new(hook));
+        Hook { token: Arc::downgrade(self), id }
+    }
+
+    /// A receiver that disconnects when the token is cancelled, for
+    /// blocking waits to select on alongside what they wait for.
+    pub fn stop_signal(self: &Arc<Self>) -> (Receiver<()>, Hook) {
+        let (stop, stopped) = bounded::<()>(0);
+        (stopped, self.on_cancel(move || drop(stop)))
+    }
+
+    /// Cancel this token and its descendants, running their hooks; false if
+    /// it already was cancelled.
+    fn cancel(&self) -> bool {
+        let mut state = lock(&self.state);
+        if self.cancelled.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+        let hooks = std::mem::take(&mut state.hooks);
+        let children = std::mem::take(&mut state.children);
+        drop(state);
+        for (_, hook) in hooks {
+            hook();
+        }
+        for child in children.iter().filter_map(Weak::upgrade) {
+            child.cancel();
+        }
+        true
+    }
+}
+
+/// Keeps a hook registered until dropped.
+pub struct Hook {
+    token: Weak<Token>,
+    id: u64,
+}
+
+impl Drop for Hook {
+    fn drop(&mut self) {
+        if let Some(token) = self.token.upgrade() {
+            lock(&token.state).hooks.remove(&self.id);
+        }
+    }
+}
+
+pub fn cancelled_error() -> String {
+    errors::coded(errors::ERR_CANCELLED, "cancelled by its cancel token")
+}
+
+fn register(token: Arc<Token>) -> u64 {
+    let id = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+    lock(&TOKENS).insert(id, token);
+    id
+}
+
+pub fn create() -> u64 {
+    register(Token::new(false))
+}
+
+/// A token cancelled along with `parent`; already cancelled if it is.
+pub fn child(parent: u64) -> Result<u64, String> {
+    let parent = get(parent)?;
+    let mut state = lock(&parent.state);
+    let child = Token::new(parent.is_cancelled());
+    state.children.retain(|c| c.strong_count() > 0);
+    state.children.push(Arc::downgrade(&child));
+    drop(state);
+    Ok(register(child))
+}
+
+/// An unregistered token cancelled whenever either parent is, for work
+/// answering to two owners: the call's own token and its scope's.
+pub fn joined(a: &Arc<Token>, b: &Arc<Token>) -> Arc<Token> {
+    let token = Token::new(false);
+    for parent in [a, b] {
+        let mut state = lock(&parent.state);
+        if parent.is_cancelled() {
+            token.cancelled.store(true, Ordering::Release);
+            continue;
+        }
+        state.children.retain(|c| c.strong_count() > 0);
+        state.children.push(Arc::downgrade(&token));
+    }
+    token
+}
+
+pub fn get(id: u64) -> Result<Arc<Token>, String> {
+    lock(&TOKENS)
+        .get(&id)
+        .cloned()
+        .ok_or_else(|| errors::coded(errors::ERR_INVALID_INPUT, format!("no such cancel token: {}", id)))
+}
+
+/// Cancel `id` and its descendants; false if it already was cancelled.
+pub fn cancel(id: u64) -> Result<bool, String> {
+    let cancelled = get(id)?.cancel();
+    if cancelled {
+        // Guests check their token at the next epoch tick
+        executor::interrupt_guests();
+    }
+    Ok(cancelled)
+}
+
+/// Forget `id`. Whatever holds it keeps it, and its children stay linked.
+pub fn release(id: u64) -> bool {
+    lock(&TOKENS).remove(&id).is_some()
+}
+
+/// Cancel every token and forget them. Used by `reset_all_state`.
+pub fn reset() {
+    let tokens: Vec<_> = lock(&TOKENS).drain().map(|(_, token)| token).collect();
+    for token in tokens {
+        token.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn test_cancel_runs_hooks_once_and_reaches_children() {
+        let root = create();
+        let child_id = child(root).unwrap();
+        let grandchild = get(child(child_id).unwrap()).unwrap();
+        let ran = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&ran);
+        let _hook = grandchild.on_cancel(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        let dropped = {
+            let counter = Arc::clone(&ran);
+            grandchild.on_cancel(move || {
+                counter.fetch_add(10, Ordering::SeqCst);
+            })
+        };
+        drop(dropped);
+
+        assert_eq!(cancel(root), Ok(true));
+        assert_eq!(cancel(root), Ok(false));
+        assert!(grandchild.is_cancelled());
+        assert!(grandchild.check().unwrap_err().starts_with(errors::ERR_CANCELLED));
+        assert_eq!(ran.load(Ordering::SeqCst), 1);
+
+        // Children and hooks of a cancelled token start out cancelled
+        assert!(get(child(root).unwrap()).unwrap().is_cancelled());
+        let late = Arc::clone(&ran);
+        let _late = grandchild.on_cancel(move || {
+            late.fetch_add(1, Ordering::SeqCst);
+        });
+        assert_eq!(ran.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_cancelling_a_child_leaves_its_parent() {
+        let parent = create();
+        let child = child(parent).unwrap();
+        assert_eq!(cancel(child), Ok(true));
+        assert!(!get(parent).unwrap().is_cancelled());
+        assert!(release(child));
+        assert!(cancel(child).unwrap_err().starts_with(errors::ERR_INVALID_INPUT));
+    }
+}
@@ -1,12 +1,76 @@
-use crossbeam_channel::{bounded, Sender, Receiver};
-use std::collections::HashMap;
-use std::sync::Mutex;
+use crossbeam_channel::{at, bounded, never, select, Sender, Receiver, RecvTimeoutError, TryRecvError, TrySendError};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Bound;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, RwLock, RwLockReadGuard};
+use std::time::{Duration, Instant};
 use once_cell::sync::Lazy;
+use crate::diagnostics::{self, WaitOp};
+use crate::errors::{self, lock};
+
+// Close semantics: once `close` returns, every send fails, including sends
+// that were blocked on a full buffer when it was called. Values already
+// buffered stay receivable (or are handed back by `close_and_drain`), and
+// receivers see the end of the channel once the buffer is empty.
 
 struct ChannelEntry {
-    sender: Sender<i64>,
-    receiver: Receiver<i64>,
-    closed: bool,
+    /// None once the channel is closed
+    sender: Option<Sender<Message>>,
+    receiver: Receiver<Message>,
+    /// Dropped on close. Never sent on: its disconnection is what wakes
+    /// senders selecting on `close_watch`.
+    close_signal: Option<Sender<()>>,
+    close_watch: Receiver<()>,
+    /// Sends hold a read guard while in flight; close takes the write guard,
+    /// so no value can land in the buffer after close has returned.
+    send_gate: Arc<RwLock<()>>,
+    /// Gated receives hold a read guard while taking from the buffer;
+    /// `hold_still` takes the write guard, after the send gate's
+    recv_gate: Arc<RwLock<()>>,
+    /// Whether receives take `recv_gate`: always on adaptive channels, and on
+    /// the rest only while `hold_still` has them, so that a plain channel's
+    /// receives take no lock
+    recv_gated: bool,
+    /// Cloned by each receive that skipped the gate, for as long as it uses
+    /// the buffer; `hold_still` waits for them to let go
+    ungated_receives: Arc<()>,
+    /// What the channel was created with; `capacity` is the initial one
+    capacity: u32,
+    options: Options,
+    /// Set for channels created fair: blocked senders go in ticket order
+    fair: Option<Arc<FairQueue>>,
+    contention: Arc<Contention>,
+    /// Set for channels that stamp their messages (see the aging section)
+    aging: Option<Arc<Aging>>,
+    /// Set for channels that resize themselves (see the adaptive section)
+    adaptive: Option<Arc<Adaptive>>,
+    /// Dropped when `hold_still` takes the channel (to move the buffer or
+    /// copy it), waking senders and receivers blocked on it so they look it
+    /// up again. None while a hold is under way.
+    move_signal: Option<Sender<()>>,
+    move_watch: Receiver<()>,
+}
+
+/// A buffered value. `sent` is only stamped on channels that age their
+/// messages, so plain channels never read the clock.
+#[derive(Clone, Copy)]
+struct Message {
+    value: i64,
+    sent: Option<Instant>,
+}
+
+/// Sends that found the buffer full, and the longest any of them waited.
+#[derive(Default)]
+struct Contention {
+    blocked_sends: AtomicU64,
+    max_wait_micros: AtomicU64,
+}
+
+impl Contention {
+    fn record(&self, since: Instant) {
+        self.blocked_sends.fetch_add(1, Ordering::Relaxed);
+        self.max_wait_micros.fetch_max(since.elapsed().as_micros() as u64, Ordering::Relaxed);
+    }
 }
 
 static CHANNELS: Lazy<Mutex<HashMap<u64, ChannelEntry>>> =
@@ -14,95 +78,1772 @@ static CHANNELS: Lazy<Mutex<HashMap<u64, ChannelEntry>>> =
 
 static NEXT_ID: Lazy<Mutex<u64>> = Lazy::new(|| Mutex::new(0));
 
+// --- Buffer memory budget ---
+
+/// Bytes accounted for each buffered i64
+const I64_BYTES: u64 = 8;
+
+// Runtime-wide cap on bytes held in channel buffers (u64::MAX = unlimited).
+// A send reserves its bytes before touching the channel and keeps them while
+// it waits on a full buffer; receiving, draining or destroying releases them.
+static BUDGET_BYTES: AtomicU64 = AtomicU64::new(u64::MAX);
+static USED_BYTES: AtomicU64 = AtomicU64::new(0);
+static HIGH_WATER_BYTES: AtomicU64 = AtomicU64::new(0);
+
+pub struct BufferUsage {
+    pub used: u64,
+    pub high_water: u64,
+    pub budget: Option<u64>,
+}
+
+/// Set the cap (None = unlimited). Lowering it below current usage makes
+/// sends fail until enough is received.
+pub fn set_buffer_budget(bytes: Option<u64>) {
+    BUDGET_BYTES.store(bytes.unwrap_or(u64::MAX), Ordering::Relaxed);
+}
+
+pub fn buffer_usage() -> BufferUsage {
+    let budget = BUDGET_BYTES.load(Ordering::Relaxed);
+    BufferUsage {
+        used: USED_BYTES.load(Ordering::Acquire),
+        high_water: HIGH_WATER_BYTES.load(Ordering::Relaxed),
+        budget: if budget == u64::MAX { None } else { Some(budget) },
+    }
+}
+
+fn reserve(bytes: u64) -> bool {
+    let budget = BUDGET_BYTES.load(Ordering::Relaxed);
+    let reserved = USED_BYTES.fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+        used.checked_add(bytes).filter(|&total| total <= budget)
+    });
+    match reserved {
+        Ok(previous) => {
+            HIGH_WATER_BYTES.fetch_max(previous + bytes, Ordering::Relaxed);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+fn release(bytes: u64) {
+    let _ = USED_BYTES.fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| Some(used.saturating_sub(bytes)));
+}
+
+fn budget_error() -> String {
+    errors::coded(errors::ERR_CHANNEL_BUDGET, "channel buffer budget exhausted")
+}
+
+/// What a channel does beyond buffering; the default is a plain channel.
+#[derive(Clone, Copy, Default)]
+pub struct Options {
+    /// Serve blocked senders in turn (see the fairness section below)
+    pub fair: bool,
+    /// Drop messages that sat in the buffer longer than this
+    pub ttl: Option<Duration>,
+    /// Stamp messages on send so `purge_older_than` can age them; implied by `ttl`
+    pub timestamps: bool,
+    /// Grow the buffer under send pressure (see the adaptive section);
+    /// ignored on fair and rendezvous channels
+    pub adaptive: Option<AdaptiveOptions>,
+}
+
 pub fn create(capacity: u32) -> u64 {
-    let cap = if capacity == 0 { 0 } else { capacity as usize };
-    let (sender, receiver) = bounded(cap);
-    let mut id_lock = NEXT_ID.lock().unwrap();
+    create_with(capacity, Options::default())
+}
+
+pub fn create_with(capacity: u32, options: Options) -> u64 {
+    create_sized(capacity, options, capacity as usize, Vec::new())
+}
+
+/// `create_with`, starting an adaptive channel on a buffer of `buffered`
+/// rather than its initial capacity, with `values` (which fit it) already
+/// buffered when the channel appears.
+fn create_sized(capacity: u32, options: Options, buffered: usize, values: Vec<i64>) -> u64 {
+    let cap = capacity as usize;
+    let (sender, receiver) = bounded(buffered);
+    let (close_signal, close_watch) = bounded(0);
+    let mut id_lock = lock(&NEXT_ID);
     let id = *id_lock;
     *id_lock += 1;
     drop(id_lock);
-    let mut channels = CHANNELS.lock().unwrap();
-    channels.insert(id, ChannelEntry { sender, receiver, closed: false });
+    let options = Options { adaptive: options.adaptive.filter(|_| !options.fair && cap > 0), ..options };
+    let adaptive = options.adaptive.map(|adaptive| Arc::new(Adaptive::new(adaptive, cap)));
+    let aging = (options.timestamps || options.ttl.is_some()).then(|| Arc::new(Aging::new(options.ttl)));
+    for value in values {
+        let message = Message { value, sent: aging.as_ref().map(|aging| aging.stamp()) };
+        assert!(sender.try_send(message).is_ok(), "the values fit the buffer");
+    }
+    let (move_signal, move_watch) = bounded(0);
+    let mut channels = lock(&CHANNELS);
+    channels.insert(id, ChannelEntry {
+        sender: Some(sender),
+        receiver,
+        close_signal: Some(close_signal),
+        close_watch,
+        send_gate: Arc::new(RwLock::new(())),
+        recv_gate: Arc::new(RwLock::new(())),
+        recv_gated: adaptive.is_some(),
+        ungated_receives: Arc::new(()),
+        capacity,
+        options,
+        fair: options.fair.then(|| Arc::new(FairQueue::default())),
+        contention: Arc::new(Contention::default()),
+        aging,
+        adaptive,
+        move_signal: Some(move_signal),
+        move_watch,
+    });
     id
 }
 
+fn closed_error() -> String {
+    "Cannot send on closed channel".to_string()
+}
+
+/// Send, blocking while the buffer is full. Fails with an error if the
+/// channel is closed before or while waiting, or with ERR_CHANNEL_BUDGET if
+/// buffering the value would exceed the runtime-wide budget.
 pub fn send(id: u64, value: i64) -> Result<bool, String> {
-    let channels = CHANNELS.lock().unwrap();
-    if let Some(entry) = channels.get(&id) {
-        if entry.closed {
-            return Err("Cannot send on closed channel".to_string());
-        }
-        let sender = entry.sender.clone();
-        drop(channels);
-        Ok(sender.send(value).is_ok())
-    } else {
-        Err("Cannot send on closed channel".to_string())
+    send_or_stop(id, value, &never())
+}
+
+/// `send` that also gives up, returning Ok(false), if `stop` fires (or is
+/// disconnected) while it waits on a full buffer.
+pub fn send_or_stop(id: u64, value: i64, stop: &Receiver<()>) -> Result<bool, String> {
+    loop {
+        match send_once(id, value, stop) {
+            Attempt::Done(result) => return result,
+            // The buffer moved under the send; try again on the new one
+            Attempt::Moved => std::thread::yield_now(),
+        }
+    }
+}
+
+/// How one try at a send went.
+#[derive(Debug, PartialEq)]
+enum Attempt {
+    Done(Result<bool, String>),
+    /// The channel migrated (or this send set it migrating) before the
+    /// value went in
+    Moved,
+}
+
+fn send_once(id: u64, value: i64, stop: &Receiver<()>) -> Attempt {
+    let channels = lock(&CHANNELS);
+    let (sender, close_watch, move_watch, gate, fair, contention, aging, adaptive) = match channels.get(&id) {
+        Some(ChannelEntry { sender: Some(sender), close_watch, move_watch, send_gate, fair, contention, aging, adaptive, .. }) => (
+            sender.clone(),
+            close_watch.clone(),
+            move_watch.clone(),
+            Arc::clone(send_gate),
+            fair.clone(),
+            Arc::clone(contention),
+            aging.clone(),
+            adaptive.clone(),
+        ),
+        _ => return Attempt::Done(Err(closed_error())),
+    };
+    drop(channels);
+
+    let in_flight = gate.read().unwrap_or_else(|e| e.into_inner());
+    // close() may have run between the lookup and taking the gate
+    if let Err(TryRecvError::Disconnected) = close_watch.try_recv() {
+        return Attempt::Done(Err(closed_error()));
+    }
+    // and so may a migration, leaving `sender` on the old buffer
+    if moved(&move_watch) {
+        return Attempt::Moved;
+    }
+    #[cfg(feature = "fault-injection")]
+    if crate::faults::hit(crate::faults::Kind::ChannelSend) {
+        return Attempt::Done(Err(errors::coded(errors::ERR_CHANNEL_BUDGET, crate::faults::INJECTED)));
+    }
+    if !reserve(I64_BYTES) {
+        return Attempt::Done(Err(budget_error()));
+    }
+    let value = Message { value, sent: aging.as_ref().map(|aging| aging.stamp()) };
+    let send = BlockingSend { id, sender: &sender, close_watch: &close_watch, move_watch: &move_watch, stop };
+    let mut grow = false;
+    let attempt = match &fair {
+        Some(queue) => queue.send(&send, value, &contention),
+        None => match sender.try_send(value) {
+            Ok(()) => Attempt::Done(Ok(true)),
+            Err(TrySendError::Disconnected(_)) => Attempt::Done(Ok(false)),
+            Err(TrySendError::Full(_)) if adaptive.as_ref().is_some_and(|adaptive| adaptive.pressed(sender.capacity())) => {
+                grow = true;
+                Attempt::Moved
+            }
+            Err(TrySendError::Full(_)) => {
+                let since = Instant::now();
+                let sent = send.wait(value);
+                contention.record(since);
+                sent
+            }
+        },
+    };
+    if attempt != Attempt::Done(Ok(true)) {
+        release(I64_BYTES);
+        if let (Some(aging), Some(sent)) = (&aging, value.sent) {
+            aging.unstamp(sent);
+        }
+    }
+    // Migrating waits out every send in flight, this one included
+    drop(in_flight);
+    if let Some(adaptive) = &adaptive {
+        if grow {
+            resize(id, adaptive, Resize::Grow);
+        } else if attempt == Attempt::Done(Ok(true)) {
+            adaptive.settle(id, sender.len(), sender.capacity());
+        }
+    }
+    attempt
+}
+
+/// Release a received message's bytes and unwrap it, or None if it aged out.
+fn accept(aging: Option<&Aging>, message: Message) -> Option<i64> {
+    release(I64_BYTES);
+    match (aging, message.sent) {
+        (Some(aging), Some(sent)) if !aging.admit(sent) => None,
+        _ => Some(message.value),
+    }
+}
+
+/// What a receive takes from an entry, cloned out from under the registry
+/// lock.
+struct ReceiveEnd {
+    receiver: Receiver<Message>,
+    move_watch: Receiver<()>,
+    /// Set when the receive must take the gate
+    recv_gate: Option<Arc<RwLock<()>>>,
+    /// Held instead while the receive skips it
+    _ungated: Option<Arc<()>>,
+    aging: Option<Arc<Aging>>,
+    adaptive: Option<Arc<Adaptive>>,
+    closed: bool,
+}
+
+impl ReceiveEnd {
+    fn lookup(id: u64) -> Option<ReceiveEnd> {
+        lock(&CHANNELS).get(&id).map(|entry| ReceiveEnd {
+            receiver: entry.receiver.clone(),
+            move_watch: entry.move_watch.clone(),
+            recv_gate: entry.recv_gated.then(|| Arc::clone(&entry.recv_gate)),
+            _ungated: (!entry.recv_gated).then(|| Arc::clone(&entry.ungated_receives)),
+            aging: entry.aging.clone(),
+            adaptive: entry.adaptive.clone(),
+            closed: entry.sender.is_none(),
+        })
+    }
+
+    /// Hold off `hold_still` while receiving from `receiver`; None if one
+    /// already took the channel, and the entry needs looking up again.
+    fn hold(&self) -> Option<Option<RwLockReadGuard<'_, ()>>> {
+        let held = self.recv_gate.as_ref().map(|gate| gate.read().unwrap_or_else(|e| e.into_inner()));
+        if moved(&self.move_watch) {
+            return None;
+        }
+        Some(held)
+    }
+
+    /// A received value was taken: let an adaptive channel shrink.
+    fn settle(&self, id: u64) {
+        if let Some(adaptive) = &self.adaptive {
+            adaptive.settle(id, self.receiver.len(), self.receiver.capacity());
+        }
     }
 }
 
 pub fn receive(id: u64) -> Option<i64> {
-    let channels = CHANNELS.lock().unwrap();
-    if let Some(entry) = channels.get(&id) {
-        let receiver = entry.receiver.clone();
-        let closed = entry.closed;
-        drop(channels);
-        match receiver.try_recv() {
-            Ok(val) => Some(val),
-            Err(_) => {
-                // If closed and buffer drained, clean up the entry
-                if closed {
-                    let mut channels = CHANNELS.lock().unwrap();
-                    channels.remove(&id);
-                }
-                None
+    loop {
+        let end = ReceiveEnd::lookup(id)?;
+        let Some(held) = end.hold() else {
+            std::thread::yield_now();
+            continue;
+        };
+        while let Ok(message) = end.receiver.try_recv() {
+            if let Some(val) = accept(end.aging.as_deref(), message) {
+                drop(held);
+                end.settle(id);
+                return Some(val);
             }
         }
-    } else {
-        None
+        // If closed and buffer drained, clean up the entry
+        if end.closed {
+            let mut channels = lock(&CHANNELS);
+            channels.remove(&id);
+        }
+        return None;
     }
 }
 
 pub fn receive_blocking(id: u64) -> Option<i64> {
-    let channels = CHANNELS.lock().unwrap();
-    if let Some(entry) = channels.get(&id) {
-        let receiver = entry.receiver.clone();
-        let closed = entry.closed;
-        drop(channels);
-        match receiver.recv() {
-            Ok(val) => Some(val),
+    match receive_inner(id, None, &never(), true) {
+        Received::Value(val) => Some(val),
+        _ => None,
+    }
+}
+
+/// Outcome of `receive_until`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Received {
+    Value(i64),
+    /// Closed and drained, destroyed, or never existed
+    Ended,
+    TimedOut,
+    /// The caller's stop signal fired first
+    Stopped,
+}
+
+/// Receive, blocking until a value arrives, the channel ends, or `deadline`
+/// passes (None waits indefinitely).
+pub fn receive_until(id: u64, deadline: Option<Instant>) -> Received {
+    receive_or_stop(id, deadline, &never())
+}
+
+/// `receive_until` that also gives up, with Received::Stopped, if `stop`
+/// fires (or is disconnected) while it waits.
+pub fn receive_or_stop(id: u64, deadline: Option<Instant>, stop: &Receiver<()>) -> Received {
+    receive_inner(id, deadline, stop, true)
+}
+
+/// The blocking receives. `track_wait` reports time spent blocked to
+/// diagnostics as a waiting receive.
+fn receive_inner(id: u64, deadline: Option<Instant>, stop: &Receiver<()>, track_wait: bool) -> Received {
+    loop {
+        let Some(end) = ReceiveEnd::lookup(id) else {
+            return Received::Ended;
+        };
+        let Some(held) = end.hold() else {
+            std::thread::yield_now();
+            continue;
+        };
+        let received = match end.receiver.try_recv() {
+            Ok(message) => Some(Ok(message)),
             Err(_) => {
-                // If closed and buffer drained, clean up the entry
-                if closed {
-                    let mut channels = CHANNELS.lock().unwrap();
-                    channels.remove(&id);
+                let _waiting = track_wait.then(|| diagnostics::begin_wait(id, WaitOp::Receive));
+                let timer = deadline.map(at).unwrap_or_else(never);
+                select! {
+                    recv(end.receiver) -> message => Some(message.map_err(|_| RecvTimeoutError::Disconnected)),
+                    // Let the migration through, then wait on the new buffer
+                    recv(end.move_watch) -> _ => None,
+                    recv(timer) -> _ => Some(Err(RecvTimeoutError::Timeout)),
+                    recv(stop) -> _ => return Received::Stopped,
+                }
+            }
+        };
+        drop(held);
+        match received {
+            None => continue,
+            Some(Ok(message)) => {
+                if let Some(val) = accept(end.aging.as_deref(), message) {
+                    end.settle(id);
+                    return Received::Value(val);
                 }
-                None
+            }
+            Some(Err(RecvTimeoutError::Timeout)) => return Received::TimedOut,
+            Some(Err(RecvTimeoutError::Disconnected)) => {
+                remove_if_drained(id);
+                return Received::Ended;
             }
         }
-    } else {
-        None
     }
 }
 
+/// Drop a closed channel's entry once its buffer is empty.
+fn remove_if_drained(id: u64) {
+    let mut channels = lock(&CHANNELS);
+    if channels.get(&id).is_some_and(|entry| entry.sender.is_none() && entry.receiver.is_empty()) {
+        channels.remove(&id);
+    }
+}
+
+// --- Fairness ---
+
+// crossbeam wakes blocked senders in no particular order, so under contention
+// one producer can keep winning the freed slot while another starves. A fair
+// channel hands slots out in ticket order instead: a send that finds the
+// buffer full (or other senders already queued) takes the next ticket and
+// waits on a condvar for its turn; only the sender being served blocks in
+// crossbeam, and the turn passes on once its value is in. Senders that give
+// up (close, stop) while queued leave their ticket behind to be skipped.
+
+/// How often queued senders recheck for close and stop between turn changes
+const FAIR_POLL: Duration = Duration::from_millis(10);
+
+#[derive(Default)]
+struct FairQueue {
+    turns: Mutex<Turns>,
+    turn_changed: Condvar,
+}
+
+#[derive(Default)]
+struct Turns {
+    next_ticket: u64,
+    serving: u64,
+    abandoned: BTreeSet<u64>,
+}
+
+impl FairQueue {
+    fn send(&self, send: &BlockingSend, value: Message, contention: &Contention) -> Attempt {
+        let ticket = {
+            let mut turns = lock(&self.turns);
+            // Nobody queued: the fast path may take a free slot directly
+            if turns.serving == turns.next_ticket {
+                match send.sender.try_send(value) {
+                    Ok(()) => return Attempt::Done(Ok(true)),
+                    Err(TrySendError::Disconnected(_)) => return Attempt::Done(Ok(false)),
+                    Err(TrySendError::Full(_)) => {}
+                }
+            }
+            turns.next_ticket += 1;
+            turns.next_ticket - 1
+        };
+        let since = Instant::now();
+        let mut turns = lock(&self.turns);
+        while turns.serving != ticket {
+            // A hold waits for the send gate, so queued senders make way for
+            // it too, queueing again behind whoever is left
+            if send.closed() || send.stopped() || moved(send.move_watch) {
+                turns.abandoned.insert(ticket);
+                drop(turns);
+                contention.record(since);
+                return match () {
+                    _ if send.closed() => Attempt::Done(Err(closed_error())),
+                    _ if send.stopped() => Attempt::Done(Ok(false)),
+                    _ => Attempt::Moved,
+                };
+            }
+            turns = self.turn_changed.wait_timeout(turns, FAIR_POLL).unwrap_or_else(|e| e.into_inner()).0;
+        }
+        drop(turns);
+        let attempt = send.wait(value);
+        contention.record(since);
+        self.advance();
+        attempt
+    }
+
+    /// Pass the turn to the next ticket still waiting.
+    fn advance(&self) {
+        let mut turns = lock(&self.turns);
+        turns.serving += 1;
+        loop {
+            let serving = turns.serving;
+            if !turns.abandoned.remove(&serving) {
+                break;
+            }
+            turns.serving += 1;
+        }
+        drop(turns);
+        self.turn_changed.notify_all();
+    }
+}
+
+/// A send that may block on a full buffer until a slot frees, the channel
+/// closes or migrates, or `stop` fires.
+struct BlockingSend<'a> {
+    id: u64,
+    sender: &'a Sender<Message>,
+    close_watch: &'a Receiver<()>,
+    move_watch: &'a Receiver<()>,
+    stop: &'a Receiver<()>,
+}
+
+impl BlockingSend<'_> {
+    fn wait(&self, value: Message) -> Attempt {
+        match self.sender.try_send(value) {
+            Ok(()) => return Attempt::Done(Ok(true)),
+            Err(TrySendError::Disconnected(_)) => return Attempt::Done(Ok(false)),
+            Err(TrySendError::Full(_)) => {}
+        }
+        let _waiting = diagnostics::begin_wait(self.id, WaitOp::Send);
+        select! {
+            send(self.sender, value) -> res => Attempt::Done(Ok(res.is_ok())),
+            recv(self.close_watch) -> _ => Attempt::Done(Err(closed_error())),
+            recv(self.move_watch) -> _ => Attempt::Moved,
+            recv(self.stop) -> _ => Attempt::Done(Ok(false)),
+        }
+    }
+
+    fn closed(&self) -> bool {
+        self.close_watch.try_recv() == Err(TryRecvError::Disconnected)
+    }
+
+    fn stopped(&self) -> bool {
+        self.stop.try_recv() == Err(TryRecvError::Disconnected)
+    }
+}
+
+pub struct ChannelStat {
+    pub len: usize,
+    /// 0 for a rendezvous channel
+    pub capacity: usize,
+    pub closed: bool,
+    pub fair: bool,
+    /// Sends that found the buffer full (or, when fair, other senders queued)
+    pub blocked_sends: u64,
+    /// Longest such a send waited
+    pub max_send_wait: Duration,
+    /// Messages dropped for age, past the TTL or purged; 0 unless stamped
+    pub expired: u64,
+    /// Times an adaptive channel moved to a larger or smaller buffer
+    pub resizes: u64,
+}
+
+/// Buffer and contention figures for a channel, or None if it is unknown.
+pub fn stat(id: u64) -> Option<ChannelStat> {
+    let channels = lock(&CHANNELS);
+    channels.get(&id).map(|entry| ChannelStat {
+        len: entry.receiver.len(),
+        capacity: entry.receiver.capacity().unwrap_or(0),
+        closed: entry.sender.is_none(),
+        fair: entry.fair.is_some(),
+        blocked_sends: entry.contention.blocked_sends.load(Ordering::Relaxed),
+        max_send_wait: Duration::from_micros(entry.contention.max_wait_micros.load(Ordering::Relaxed)),
+        expired: entry.aging.as_ref().map_or(0, |aging| lock(&aging.ledger).expired),
+        resizes: entry.adaptive.as_ref().map_or(0, |adaptive| adaptive.resizes.load(Ordering::Relaxed)),
+    })
+}
+
+/// Number of values currently buffered, or None for an unknown channel.
+pub fn len(id: u64) -> Option<usize> {
+    let channels = lock(&CHANNELS);
+    channels.get(&id).map(|entry| entry.receiver.len())
+}
+
+/// Close the channel. Blocked and future sends fail; buffered values stay
+/// receivable until drained.
 pub fn close(id: u64) {
-    let mut channels = CHANNELS.lock().unwrap();
-    // Drop the original sender to signal disconnection to receivers
-    if let Some(entry) = channels.remove(&id) {
-        let real_receiver = entry.receiver.clone();
-        drop(entry.sender); // Drop original sender
-        // If buffer is already empty, no need to keep the entry around
-        if real_receiver.is_empty() {
+    close_inner(id, false);
+}
+
+/// Close the channel and return whatever was still buffered, in order, so the
+/// caller can handle undelivered messages. Receivers see the channel as ended.
+pub fn close_and_drain(id: u64) -> Vec<i64> {
+    close_inner(id, true)
+}
+
+fn close_inner(id: u64, drain: bool) -> Vec<i64> {
+    let gate = {
+        let mut channels = lock(&CHANNELS);
+        let entry = match channels.get_mut(&id) {
+            Some(entry) => entry,
+            None => return Vec::new(),
+        };
+        // Wakes senders blocked on a full buffer; they fail and release the gate
+        entry.close_signal = None;
+        if let Some(queue) = &entry.fair {
+            queue.turn_changed.notify_all();
+        }
+        Arc::clone(&entry.send_gate)
+    };
+    // Wait out sends that got past the closed check before the signal dropped
+    let _quiesced = gate.write().unwrap_or_else(|e| e.into_inner());
+
+    let mut channels = lock(&CHANNELS);
+    let entry = match channels.remove(&id) {
+        Some(entry) => entry,
+        None => return Vec::new(),
+    };
+    let drained: Vec<i64> = if drain {
+        entry.receiver.try_iter().filter_map(|message| accept(entry.aging.as_deref(), message)).collect()
+    } else {
+        Vec::new()
+    };
+    // Keep a closed entry only while receivers still have values to take.
+    // Dropping the entry's sender lets blocked receivers see the disconnect.
+    if !entry.receiver.is_empty() {
+        channels.insert(id, ChannelEntry { sender: None, ..entry });
+    }
+    drained
+}
+
+/// Close the channel and discard whatever it still buffers. Blocked senders
+/// fail and blocked receivers see the end exactly as on `close`, and the id
+/// keeps reading as a closed channel, not an unknown one (see `issued`).
+pub fn destroy(id: u64) {
+    close_and_drain(id);
+}
+
+/// Whether `id` was ever handed out. An issued id missing from the registry
+/// belongs to a channel that was closed and drained, or destroyed.
+pub fn issued(id: u64) -> bool {
+    id < *lock(&NEXT_ID)
+}
+
+/// Close and discard every channel, failing blocked senders and ending
+/// blocked receivers. Used by `reset_all_state`.
+pub fn reset() {
+    let ids: Vec<u64> = lock(&CHANNELS).keys().copied().collect();
+    for id in ids {
+        close_and_drain(id);
+        destroy(id);
+    }
+}
+
+/// Create the registry now instead of on first use; false if it existed.
+pub fn warm() -> bool {
+    let created = Lazy::get(&CHANNELS).is_none();
+    Lazy::force(&CHANNELS);
+    Lazy::force(&NEXT_ID);
+    Lazy::force(&SUBSCRIPTIONS);
+    created
+}
+
+// --- Aging ---
+
+// A channel created with a TTL, or with timestamps on, stamps every message
+// with its send time. Receivers drop messages that have outlived the TTL as
+// they reach them, without handing them on, so a consumer that falls behind
+// skips straight to fresh values. crossbeam cannot look at the head of the
+// buffer without taking it, so a purge does not remove anything itself: it
+// raises a cutoff below which receivers drop messages too, and counts the
+// buffered messages it doomed from the stamps the ledger keeps. Dropped
+// messages still hold their buffer slot and bytes until a receiver gets to
+// them. Plain channels skip all of this.
+
+struct Aging {
+    ttl: Option<Duration>,
+    ledger: Mutex<Ledger>,
+}
+
+#[derive(Default)]
+struct Ledger {
+    /// Stamps of buffered messages, and of sends still in flight, with counts
+    buffered: BTreeMap<Instant, u32>,
+    /// Messages stamped before this were purged
+    cutoff: Option<Instant>,
+    /// Purged messages, counted at the purge, and messages found past the
+    /// TTL, counted as they are dropped
+    expired: u64,
+}
+
+impl Ledger {
+    fn forget(&mut self, sent: Instant) {
+        if let Some(count) = self.buffered.get_mut(&sent) {
+            *count -= 1;
+            if *count == 0 {
+                self.buffered.remove(&sent);
+            }
+        }
+    }
+
+    fn purged(&self, sent: Instant) -> bool {
+        self.cutoff.is_some_and(|cutoff| sent < cutoff)
+    }
+}
+
+impl Aging {
+    fn new(ttl: Option<Duration>) -> Self {
+        Aging { ttl, ledger: Mutex::new(Ledger::default()) }
+    }
+
+    fn stamp(&self) -> Instant {
+        let now = Instant::now();
+        *lock(&self.ledger).buffered.entry(now).or_insert(0) += 1;
+        now
+    }
+
+    /// The send stamped `sent` never reached the buffer.
+    fn unstamp(&self, sent: Instant) {
+        let mut ledger = lock(&self.ledger);
+        ledger.forget(sent);
+        if ledger.purged(sent) {
+            ledger.expired -= 1;
+        }
+    }
+
+    /// Whether a buffered message would still be delivered, without
+    /// counting it either way.
+    fn live(&self, sent: Instant) -> bool {
+        let purged = lock(&self.ledger).purged(sent);
+        !(purged || self.ttl.is_some_and(|ttl| sent.elapsed() > ttl))
+    }
+
+    /// Whether a message taken off the buffer is still live.
+    fn admit(&self, sent: Instant) -> bool {
+        let mut ledger = lock(&self.ledger);
+        ledger.forget(sent);
+        if ledger.purged(sent) {
+            return false;
+        }
+        if self.ttl.is_some_and(|ttl| sent.elapsed() > ttl) {
+            ledger.expired += 1;
+            return false;
+        }
+        true
+    }
+
+    fn purge(&self, age: Duration) -> u64 {
+        let cutoff = match Instant::now().checked_sub(age) {
+            Some(cutoff) => cutoff,
+            None => return 0,
+        };
+        let mut ledger = lock(&self.ledger);
+        let from = match ledger.cutoff {
+            Some(previous) if previous >= cutoff => return 0,
+            Some(previous) => Bound::Included(previous),
+            None => Bound::Unbounded,
+        };
+        let purged: u64 = ledger.buffered.range((from, Bound::Excluded(cutoff))).map(|(_, &count)| count as u64).sum();
+        ledger.cutoff = Some(cutoff);
+        ledger.expired += purged;
+        purged
+    }
+}
+
+/// Drop every message buffered on `id` that was sent more than `age` ago,
+/// returning how many. Receivers skip them from then on; the buffer's length
+/// and bytes fall as they do. Fails on a channel that does not stamp its
+/// messages; a channel that is gone has nothing to purge.
+pub fn purge_older_than(id: u64, age: Duration) -> Result<u32, String> {
+    let aging = match lock(&CHANNELS).get(&id) {
+        Some(entry) => entry.aging.clone(),
+        None => return Ok(0),
+    };
+    match aging {
+        Some(aging) => Ok(aging.purge(age).min(u32::MAX as u64) as u32),
+        None => Err(errors::coded(
+            errors::ERR_INVALID_INPUT,
+            format!("channel {} does not timestamp its messages; create it with a ttl or timestamps", id),
+        )),
+    }
+}
+
+// --- Adaptive capacity ---
+
+// An adaptive channel starts at the capacity it was created with and grows,
+// `growth_factor` times over up to `max`, once more than `full_sends` sends
+// within `window` found its buffer full. crossbeam cannot resize a channel,
+// so growing moves the buffer instead: the send that tips the balance drops
+// the entry's move signal, waking every sender and receiver blocked on the
+// old channel so they let go of it, then takes the send gate and the receive
+// gate for writing, drains the old buffer in order into a new bounded channel
+// and swaps that into the entry. Sends and receives that lost the race look
+// the entry up again and carry on against the new buffer, so holders of the
+// id (WASM guests included) never see the move. With `shrink_after` set, a
+// channel that went that long without a full buffer moves back to its
+// initial capacity once what it holds fits. Buffered bytes and aging stamps
+// travel with the messages.
+
+#[derive(Clone, Copy, Debug)]
+pub struct AdaptiveOptions {
+    /// Largest capacity to grow to
+    pub max: usize,
+    /// Capacity multiplier for each step, above 1
+    pub growth_factor: f64,
+    /// Grow once more than this many sends in a window found the buffer full
+    pub full_sends: u32,
+    pub window: Duration,
+    /// Shrink back to the initial capacity after this long without a full
+    /// buffer (None keeps the capacity grown to)
+    pub shrink_after: Option<Duration>,
+}
+
+impl AdaptiveOptions {
+    /// Double up to `max` after more than 4 full sends within a second,
+    /// never shrinking.
+    pub fn up_to(max: usize) -> Self {
+        AdaptiveOptions { max, growth_factor: 2.0, full_sends: 4, window: Duration::from_secs(1), shrink_after: None }
+    }
+}
+
+struct Adaptive {
+    options: AdaptiveOptions,
+    initial: usize,
+    pressure: Mutex<Pressure>,
+    resizes: AtomicU64,
+}
+
+struct Pressure {
+    window_start: Instant,
+    /// Sends that found the buffer full since `window_start`
+    full_sends: u32,
+    /// The last full buffer or move
+    calm_since: Instant,
+}
+
+#[derive(Clone, Copy)]
+enum Resize {
+    Grow,
+    Shrink,
+}
+
+impl Adaptive {
+    fn new(options: AdaptiveOptions, initial: usize) -> Self {
+        let now = Instant::now();
+        Adaptive {
+            options,
+            initial,
+            pressure: Mutex::new(Pressure { window_start: now, full_sends: 0, calm_since: now }),
+            resizes: AtomicU64::new(0),
+        }
+    }
+
+    /// Count a send that found the buffer, of `capacity`, full; whether the
+    /// channel should grow.
+    fn pressed(&self, capacity: Option<usize>) -> bool {
+        let now = Instant::now();
+        let mut pressure = lock(&self.pressure);
+        pressure.calm_since = now;
+        if now.duration_since(pressure.window_start) > self.options.window {
+            pressure.window_start = now;
+            pressure.full_sends = 0;
+        }
+        pressure.full_sends = pressure.full_sends.saturating_add(1);
+        pressure.full_sends > self.options.full_sends && capacity.is_some_and(|capacity| capacity < self.options.max)
+    }
+
+    /// A value went in or out, leaving `buffered` of `capacity`: shrink if
+    /// the channel has been quiet long enough and they fit.
+    fn settle(&self, id: u64, buffered: usize, capacity: Option<usize>) {
+        let Some(after) = self.options.shrink_after else {
             return;
+        };
+        if capacity.is_none_or(|capacity| capacity <= self.initial) || buffered > self.initial {
+            return;
+        }
+        if lock(&self.pressure).calm_since.elapsed() >= after {
+            resize(id, self, Resize::Shrink);
         }
-        channels.insert(id, ChannelEntry {
-            sender: bounded(0).0, // dead sender (no corresponding receiver)
-            receiver: real_receiver,
-            closed: true,
+    }
+
+    /// The capacity a `step` from `capacity` leads to.
+    fn target(&self, capacity: usize, step: Resize) -> usize {
+        match step {
+            Resize::Grow if capacity >= self.options.max => capacity,
+            Resize::Grow => {
+                let grown = (capacity as f64 * self.options.growth_factor).ceil() as usize;
+                grown.clamp(capacity + 1, self.options.max)
+            }
+            Resize::Shrink => self.initial,
+        }
+    }
+}
+
+/// Move `id`'s buffer one `step`, unless the channel closed, another move is
+/// under way, or (shrinking) what it holds no longer fits.
+fn resize(id: u64, adaptive: &Adaptive, step: Resize) {
+    let moved = hold_still(id, |entry| {
+        let capacity = entry.receiver.capacity().unwrap_or(0);
+        let target = adaptive.target(capacity, step);
+        if target == capacity || entry.receiver.len() > target {
+            return false;
+        }
+        let (sender, receiver) = bounded(target);
+        for message in entry.receiver.try_iter() {
+            // Nothing else sends or receives until the gates open again
+            assert!(sender.try_send(message).is_ok(), "the new buffer holds what the old one did");
+        }
+        entry.sender = Some(sender);
+        entry.receiver = receiver;
+        true
+    });
+    if let Held::Done(true) = moved {
+        adaptive.resizes.fetch_add(1, Ordering::Relaxed);
+        let now = Instant::now();
+        *lock(&adaptive.pressure) = Pressure { window_start: now, full_sends: 0, calm_since: now };
+    }
+}
+
+/// How a `hold_still` went.
+enum Held<R> {
+    Done(R),
+    /// Closed, or gone
+    Closed,
+    /// Another hold has the channel
+    Busy,
+}
+
+/// Run `f` on `id`'s entry with every send and receive shut out: gate the
+/// receives, drop the move signal, waking every sender and receiver blocked
+/// on the buffer so they let go of it, wait for receives that skipped the
+/// gate to finish, then take the send gate and the receive gate for
+/// writing. Sends and receives that lost the race look the entry up again
+/// once `f` is done, and carry on against whatever buffer it left.
+fn hold_still<R>(id: u64, f: impl FnOnce(&mut ChannelEntry) -> R) -> Held<R> {
+    let (sends, receives, ungated) = {
+        let mut channels = lock(&CHANNELS);
+        match channels.get_mut(&id) {
+            Some(entry) if entry.sender.is_some() && entry.move_signal.is_some() => {
+                entry.move_signal = None;
+                // Receives looked up from here on take the gate
+                entry.recv_gated = true;
+                (Arc::clone(&entry.send_gate), Arc::clone(&entry.recv_gate), Arc::clone(&entry.ungated_receives))
+            }
+            Some(entry) if entry.sender.is_some() => return Held::Busy,
+            _ => return Held::Closed,
+        }
+    };
+    // The entry's clone and this one; woken receivers drop theirs on their
+    // way to looking the entry up again
+    while Arc::strong_count(&ungated) > 2 {
+        std::thread::yield_now();
+    }
+    // Pairs with the release of their last drop: their receives are done
+    std::sync::atomic::fence(Ordering::Acquire);
+    let _sends = sends.write().unwrap_or_else(|e| e.into_inner());
+    let _receives = receives.write().unwrap_or_else(|e| e.into_inner());
+
+    let mut channels = lock(&CHANNELS);
+    let Some(entry) = channels.get_mut(&id) else {
+        return Held::Closed;
+    };
+    // No receive looks the entry up before `f` is done, as this holds the
+    // registry until then
+    entry.recv_gated = entry.adaptive.is_some();
+    // Whatever `f` does, waiters that wake on the old signal need a live
+    // one to find when they look the entry up again
+    let (signal, watch) = bounded(0);
+    entry.move_signal = Some(signal);
+    entry.move_watch = watch;
+    if entry.sender.is_none() {
+        return Held::Closed;
+    }
+    Held::Done(f(entry))
+}
+
+/// Whether the buffer `watch` was handed out with has moved.
+fn moved(watch: &Receiver<()>) -> bool {
+    watch.try_recv() == Err(TryRecvError::Disconnected)
+}
+
+// --- Snapshots ---
+
+// `dump` copies what a channel buffers, for a restart to `restore` into a
+// new channel, without taking anything out of it: it holds the channel still
+// (see `hold_still`), drains the buffer and sends it straight back in order.
+// Receivers wait out the copy, so no value reaches one and the snapshot
+// both; a send still in flight when the dump starts may land before the
+// copy or after it. Messages already past their TTL or purged are left out,
+// and restored messages are stamped afresh. Closed channels are not dumped:
+// nothing can be sent back into them, and `close_and_drain` hands over what
+// they hold.
+//
+// A dump is little-endian: the magic "TVCH", a u16 version, a u32 header
+// length and the header (initial capacity u32, buffer capacity u32, flags
+// u32, TTL u64 ns, then for adaptive channels max u32, growth factor f64,
+// full sends u32, window u64 ns and shrink-after u64 ns, u64::MAX for
+// never), then a u64 value count and the values as i64s. `dump_all` wraps
+// several: the magic "TVCA", a u16 version, a u32 count, and per channel
+// the caller's label for it as an i64, the dump's length as a u64 and the
+// dump.
+
+const DUMP_MAGIC: &[u8; 4] = b"TVCH";
+const DUMP_ALL_MAGIC: &[u8; 4] = b"TVCA";
+const DUMP_VERSION: u16 = 1;
+
+const DUMP_FAIR: u32 = 1;
+const DUMP_TTL: u32 = 1 << 1;
+const DUMP_TIMESTAMPS: u32 = 1 << 2;
+const DUMP_ADAPTIVE: u32 = 1 << 3;
+
+/// Header bytes before and after the adaptive block
+const DUMP_HEADER_LEN: usize = 20;
+const DUMP_ADAPTIVE_LEN: usize = 32;
+
+fn dump_error(message: &str) -> String {
+    errors::coded(errors::ERR_INVALID_INPUT, format!("channel dump: {}", message))
+}
+
+/// Snapshot of the values `id` buffers and the options it was created with.
+/// Fails for a closed or unknown channel.
+pub fn dump(id: u64) -> Result<Vec<u8>, String> {
+    loop {
+        let held = hold_still(id, |entry| {
+            let messages: Vec<Message> = entry.receiver.try_iter().collect();
+            let sender = entry.sender.as_ref().expect("held channels are open");
+            for &message in &messages {
+                // Nothing else sends or receives until the gates open again
+                assert!(sender.try_send(message).is_ok(), "the buffer takes back what it held");
+            }
+            let live = messages
+                .iter()
+                .filter(|m| match (&entry.aging, m.sent) {
+                    (Some(aging), Some(sent)) => aging.live(sent),
+                    _ => true,
+                })
+                .map(|m| m.value);
+            encode(entry.capacity, entry.receiver.capacity().unwrap_or(0), &entry.options, live)
         });
+        match held {
+            Held::Done(dump) => return Ok(dump),
+            Held::Closed => return Err(format!("channel {} is closed or unknown; closed channels cannot be dumped", id)),
+            // An adaptive channel is moving its buffer; the copy goes next
+            Held::Busy => std::thread::yield_now(),
+        }
     }
 }
 
-pub fn destroy(id: u64) {
-    let mut channels = CHANNELS.lock().unwrap();
-    channels.remove(&id);
+fn encode(capacity: u32, buffered: usize, options: &Options, values: impl Iterator<Item = i64>) -> Vec<u8> {
+    let nanos = |d: Duration| u64::try_from(d.as_nanos()).unwrap_or(u64::MAX - 1);
+    let flags = [
+        (options.fair, DUMP_FAIR),
+        (options.ttl.is_some(), DUMP_TTL),
+        (options.timestamps, DUMP_TIMESTAMPS),
+        (options.adaptive.is_some(), DUMP_ADAPTIVE),
+    ]
+    .iter()
+    .filter(|(set, _)| *set)
+    .fold(0, |flags, (_, bit)| flags | bit);
+    let mut header = Vec::with_capacity(DUMP_HEADER_LEN + DUMP_ADAPTIVE_LEN);
+    header.extend_from_slice(&capacity.to_le_bytes());
+    header.extend_from_slice(&(buffered as u32).to_le_bytes());
+    header.extend_from_slice(&flags.to_le_bytes());
+    header.extend_from_slice(&options.ttl.map_or(0, nanos).to_le_bytes());
+    if let Some(adaptive) = &options.adaptive {
+        header.extend_from_slice(&(adaptive.max as u32).to_le_bytes());
+        header.extend_from_slice(&adaptive.growth_factor.to_le_bytes());
+        header.extend_from_slice(&adaptive.full_sends.to_le_bytes());
+        header.extend_from_slice(&nanos(adaptive.window).to_le_bytes());
+        header.extend_from_slice(&adaptive.shrink_after.map_or(u64::MAX, nanos).to_le_bytes());
+    }
+    let values: Vec<i64> = values.collect();
+    let mut out = Vec::with_capacity(10 + header.len() + 8 + values.len() * 8);
+    out.extend_from_slice(DUMP_MAGIC);
+    out.extend_from_slice(&DUMP_VERSION.to_le_bytes());
+    out.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    out.extend_from_slice(&header);
+    out.extend_from_slice(&(values.len() as u64).to_le_bytes());
+    for value in values {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    out
+}
+
+/// Reads a dump front to back, failing on anything short.
+struct DumpReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> DumpReader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() < n {
+            return Err(dump_error("truncated"));
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn preamble(&mut self, magic: &[u8; 4]) -> Result<(), String> {
+        if self.take(4)? != magic {
+            return Err(dump_error("not a channel dump"));
+        }
+        match self.u16()? {
+            DUMP_VERSION => Ok(()),
+            version => Err(dump_error(&format!("unsupported version {}", version))),
+        }
+    }
+}
+
+/// What `restore` recreates from one dump.
+struct Snapshot {
+    capacity: u32,
+    buffered: usize,
+    options: Options,
+    values: Vec<i64>,
+}
+
+fn decode(bytes: &[u8]) -> Result<Snapshot, String> {
+    let mut reader = DumpReader { bytes };
+    reader.preamble(DUMP_MAGIC)?;
+    let header_len = reader.u32()? as usize;
+    let mut header = DumpReader { bytes: reader.take(header_len)? };
+    let capacity = header.u32()?;
+    let buffered = header.u32()? as usize;
+    let flags = header.u32()?;
+    let ttl = header.u64()?;
+    if flags & !(DUMP_FAIR | DUMP_TTL | DUMP_TIMESTAMPS | DUMP_ADAPTIVE) != 0 {
+        return Err(dump_error("unknown option flags"));
+    }
+    let duration = |nanos: u64| Duration::from_nanos(nanos);
+    let adaptive = if flags & DUMP_ADAPTIVE != 0 {
+        let max = header.u32()? as usize;
+        let growth_factor = f64::from_bits(header.u64()?);
+        let full_sends = header.u32()?;
+        let window = duration(header.u64()?);
+        let shrink_after = match header.u64()? {
+            u64::MAX => None,
+            nanos => Some(duration(nanos)),
+        };
+        if flags & DUMP_FAIR != 0 || capacity == 0 || max < capacity as usize || !(growth_factor > 1.0 && growth_factor.is_finite()) {
+            return Err(dump_error("invalid adaptive options"));
+        }
+        Some(AdaptiveOptions { max, growth_factor, full_sends, window, shrink_after })
+    } else {
+        None
+    };
+    if !header.bytes.is_empty() {
+        return Err(dump_error("header longer than its options"));
+    }
+    let fixed_buffer = if adaptive.is_some() { buffered < capacity as usize } else { buffered != capacity as usize };
+    if fixed_buffer || adaptive.is_some_and(|adaptive| buffered > adaptive.max) {
+        return Err(dump_error("buffer capacity does not match the options"));
+    }
+    let count = reader.u64()?;
+    if count > buffered as u64 || reader.bytes.len() as u64 != count * 8 {
+        return Err(dump_error("value count does not match the buffer or the length"));
+    }
+    let values = reader.bytes.chunks_exact(8).map(|b| i64::from_le_bytes(b.try_into().unwrap())).collect();
+    let options = Options {
+        fair: flags & DUMP_FAIR != 0,
+        ttl: (flags & DUMP_TTL != 0).then(|| duration(ttl)),
+        timestamps: flags & DUMP_TIMESTAMPS != 0,
+        adaptive,
+    };
+    Ok(Snapshot { capacity, buffered, options, values })
+}
+
+/// A new channel with the options and buffered values of `dump`, as taken
+/// by `dump`. Fails with ERR_INVALID_INPUT for a malformed dump and
+/// ERR_CHANNEL_BUDGET if the values do not fit the buffer budget, creating
+/// nothing either way.
+pub fn restore(dump: &[u8]) -> Result<u64, String> {
+    let snapshot = decode(dump)?;
+    let bytes = snapshot.values.len() as u64 * I64_BYTES;
+    if !reserve(bytes) {
+        return Err(budget_error());
+    }
+    // Filled before it is registered, so nothing (a reset included) sees
+    // the channel without its values
+    Ok(create_sized(snapshot.capacity, snapshot.options, snapshot.buffered, snapshot.values))
+}
+
+/// Dumps of the open channels among `ids`, each under the label `label`
+/// gives it, in one buffer for `restore_all`.
+pub fn dump_all(ids: &[u64], label: impl Fn(u64) -> i64) -> Vec<u8> {
+    let dumps: Vec<(i64, Vec<u8>)> = ids.iter().filter_map(|&id| dump(id).ok().map(|dump| (label(id), dump))).collect();
+    let mut out = Vec::new();
+    out.extend_from_slice(DUMP_ALL_MAGIC);
+    out.extend_from_slice(&DUMP_VERSION.to_le_bytes());
+    out.extend_from_slice(&(dumps.len() as u32).to_le_bytes());
+    for (label, dump) in dumps {
+        out.extend_from_slice(&label.to_le_bytes());
+        out.extend_from_slice(&(dump.len() as u64).to_le_bytes());
+        out.extend_from_slice(&dump);
+    }
+    out
+}
+
+/// Restore every channel in a `dump_all` buffer, returning each one's label
+/// and new id in dump order. Checks every dump before creating anything.
+pub fn restore_all(dump: &[u8]) -> Result<Vec<(i64, u64)>, String> {
+    let mut reader = DumpReader { bytes: dump };
+    reader.preamble(DUMP_ALL_MAGIC)?;
+    let count = reader.u32()?;
+    let mut dumps = Vec::new();
+    for _ in 0..count {
+        let label = reader.u64()? as i64;
+        let len = usize::try_from(reader.u64()?).map_err(|_| dump_error("truncated"))?;
+        let bytes = reader.take(len)?;
+        decode(bytes)?;
+        dumps.push((label, bytes));
+    }
+    if !reader.bytes.is_empty() {
+        return Err(dump_error("trailing bytes"));
+    }
+    let mut restored = Vec::with_capacity(dumps.len());
+    for (label, bytes) in dumps {
+        match restore(bytes) {
+            Ok(id) => restored.push((label, id)),
+            Err(e) => {
+                for (_, id) in restored {
+                    destroy(id);
+                }
+                return Err(e);
+            }
+        }
+    }
+    Ok(restored)
+}
+
+/// Every open channel, for a whole-runtime `dump_all`.
+pub fn open_ids() -> Vec<u64> {
+    let mut ids: Vec<u64> = lock(&CHANNELS).iter().filter(|(_, entry)| entry.sender.is_some()).map(|(&id, _)| id).collect();
+    ids.sort_unstable();
+    ids
+}
+
+// --- Subscriptions ---
+
+// A subscription forwards every value arriving on a channel to a callback,
+// from a dedicated thread blocked on the channel's receiver. It competes with
+// other receivers like any consumer, and ends when the channel ends (closed
+// and drained, or destroyed) or when `unsubscribe` drops its stop sender.
+// The forwarding thread does not register a wait, so an idle subscription is
+// not reported as a blocked receive.
+
+static SUBSCRIPTIONS: Lazy<Mutex<HashMap<u64, Sender<()>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+static NEXT_SUBSCRIPTION: AtomicU64 = AtomicU64::new(0);
+
+/// Call `on_value` with every value received on `id`, in order, until the
+/// channel ends or the subscription is dropped. Returns the subscription id.
+pub fn subscribe<F>(id: u64, on_value: F) -> Result<u64, String>
+where
+    F: Fn(i64) + Send + 'static,
+{
+    if !lock(&CHANNELS).contains_key(&id) {
+        return Err(format!("no such channel: {}", id));
+    }
+    let (stop, stopped) = bounded::<()>(0);
+    let subscription = NEXT_SUBSCRIPTION.fetch_add(1, Ordering::Relaxed);
+    lock(&SUBSCRIPTIONS).insert(subscription, stop);
+    let spawned = std::thread::Builder::new()
+        .name(format!("tova-subscribe-{}", id))
+        .spawn(move || {
+            while let Received::Value(value) = receive_inner(id, None, &stopped, false) {
+                on_value(value);
+            }
+            lock(&SUBSCRIPTIONS).remove(&subscription);
+            // A closed channel is removed once drained; this thread drained it
+            remove_if_drained(id);
+        });
+    if let Err(e) = spawned {
+        lock(&SUBSCRIPTIONS).remove(&subscription);
+        return Err(format!("failed to spawn subscriber: {}", e));
+    }
+    Ok(subscription)
+}
+
+/// Stop forwarding; false if the subscription already ended. A value the
+/// thread was delivering when this is called may still reach the callback.
+pub fn unsubscribe(subscription: u64) -> bool {
+    lock(&SUBSCRIPTIONS).remove(&subscription).is_some()
+}
+
+/// Channel tests share the process-wide budget; the budget test changes it,
+/// so every test that sends takes this guard.
+#[cfg(test)]
+pub(crate) fn test_serial() -> std::sync::MutexGuard<'static, ()> {
+    static SERIAL: Mutex<()> = Mutex::new(());
+    lock(&SERIAL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_close_fails_blocked_sender_promptly() {
+        let _serial = test_serial();
+        let id = create(1);
+        assert_eq!(send(id, 1), Ok(true));
+        let producer = thread::spawn(move || {
+            let result = send(id, 2);
+            (result, Instant::now())
+        });
+        thread::sleep(Duration::from_millis(50));
+        assert!(!producer.is_finished(), "send on a full channel should block");
+
+        let closed_at = Instant::now();
+        let undelivered = close_and_drain(id);
+        let (result, failed_at) = producer.join().unwrap();
+        assert_eq!(result, Err(closed_error()));
+        assert!(failed_at.duration_since(closed_at) < Duration::from_millis(100));
+        assert_eq!(undelivered, vec![1]);
+        assert_eq!(receive(id), None);
+        assert_eq!(send(id, 3), Err(closed_error()));
+    }
+
+    #[test]
+    fn test_close_keeps_buffered_values_for_receivers() {
+        let _serial = test_serial();
+        let id = create(4);
+        for v in [10, 20, 30] {
+            assert_eq!(send(id, v), Ok(true));
+        }
+        close(id);
+        assert_eq!(send(id, 40), Err(closed_error()));
+        assert_eq!(len(id), Some(3));
+        assert_eq!(receive_blocking(id), Some(10));
+        assert_eq!(close_and_drain(id), vec![20, 30]);
+        assert_eq!(receive_blocking(id), None);
+    }
+
+    #[test]
+    fn test_destroy_ends_waiters_like_close() {
+        let _serial = test_serial();
+        let full = create(1);
+        assert_eq!(send(full, 1), Ok(true));
+        let sender = thread::spawn(move || send(full, 2));
+        let empty = create(1);
+        let receiver = thread::spawn(move || receive_until(empty, None));
+        thread::sleep(Duration::from_millis(30));
+
+        destroy(full);
+        destroy(empty);
+        assert_eq!(sender.join().unwrap(), Err(closed_error()));
+        assert_eq!(receiver.join().unwrap(), Received::Ended);
+        // Gone from the registry, but still known as a channel that ended
+        assert_eq!(len(full), None);
+        assert!(issued(full) && issued(empty));
+        assert!(!issued(u64::MAX));
+    }
+
+    #[test]
+    fn test_close_wakes_blocked_receiver() {
+        let id = create(1);
+        let consumer = thread::spawn(move || receive_blocking(id));
+        thread::sleep(Duration::from_millis(20));
+        close(id);
+        assert_eq!(consumer.join().unwrap(), None);
+    }
+
+    #[test]
+    fn test_subscription_forwards_every_value_until_the_channel_ends() {
+        let _serial = test_serial();
+        let id = create(2);
+        let (seen_tx, seen) = crossbeam_channel::unbounded();
+        let subscription = subscribe(id, move |v| seen_tx.send(v).unwrap()).unwrap();
+        for v in 0..10 {
+            assert_eq!(send(id, v), Ok(true));
+        }
+        close(id);
+        let forwarded: Vec<i64> = seen.iter().collect();
+        assert_eq!(forwarded, (0..10).collect::<Vec<_>>());
+        // The thread exited (dropping the callback) and cleaned up after itself
+        assert!(!unsubscribe(subscription));
+        assert_eq!(len(id), None);
+        assert!(subscribe(u64::MAX, |_| {}).is_err());
+    }
+
+    #[test]
+    fn test_unsubscribe_stops_forwarding() {
+        let _serial = test_serial();
+        let id = create(4);
+        let (seen_tx, seen) = crossbeam_channel::unbounded();
+        let subscription = subscribe(id, move |v| seen_tx.send(v).unwrap()).unwrap();
+        assert_eq!(send(id, 1), Ok(true));
+        assert_eq!(seen.recv_timeout(Duration::from_secs(1)), Ok(1));
+
+        assert!(unsubscribe(subscription));
+        // The callback is dropped with the thread, disconnecting `seen`
+        assert!(seen.recv_timeout(Duration::from_secs(1)).is_err());
+        assert_eq!(send(id, 2), Ok(true));
+        assert_eq!(receive(id), Some(2));
+        destroy(id);
+    }
+
+    /// Two producers sending their own tag until the channel closes, into a
+    /// capacity-1 channel; returns how many of `n` received values each sent.
+    fn hammer(fair: bool, n: usize) -> [usize; 2] {
+        let id = create_with(1, Options { fair, ..Options::default() });
+        let producers: Vec<_> = (0..2)
+            .map(|tag| thread::spawn(move || while send(id, tag) == Ok(true) {}))
+            .collect();
+        let mut counts = [0; 2];
+        for _ in 0..n {
+            counts[receive_blocking(id).unwrap() as usize] += 1;
+        }
+        close_and_drain(id);
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        destroy(id);
+        counts
+    }
+
+    #[test]
+    fn test_fair_channel_serves_blocked_senders_in_turn() {
+        let _serial = test_serial();
+        let id = create_with(1, Options { fair: true, ..Options::default() });
+        let s = stat(id).unwrap();
+        assert!(s.fair && !s.closed);
+        assert_eq!((s.len, s.capacity, s.blocked_sends), (0, 1, 0));
+        destroy(id);
+
+        let counts = hammer(true, 2000);
+        let (low, high) = (counts[0].min(counts[1]), counts[0].max(counts[1]));
+        assert!((high - low) as f64 <= 0.1 * high as f64, "{:?}", counts);
+    }
+
+    #[test]
+    fn test_default_channel_delivers_everything_under_contention() {
+        let _serial = test_serial();
+        let counts = hammer(false, 2000);
+        assert_eq!(counts[0] + counts[1], 2000);
+
+        let id = create(1);
+        assert_eq!(send(id, 1), Ok(true));
+        let blocked = thread::spawn(move || send(id, 2));
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(receive(id), Some(1));
+        assert_eq!(blocked.join().unwrap(), Ok(true));
+        let s = stat(id).unwrap();
+        assert!(!s.fair);
+        assert_eq!(s.blocked_sends, 1);
+        assert!(s.max_send_wait >= Duration::from_millis(20), "{:?}", s.max_send_wait);
+        destroy(id);
+        assert!(stat(id).is_none());
+    }
+
+    #[test]
+    fn test_budget_caps_total_buffered_bytes() {
+        let _serial = test_serial();
+        let base = buffer_usage().used;
+        // Room for five values across all channels
+        set_buffer_budget(Some(base + 5 * I64_BYTES));
+        let (a, b, c) = (create(10), create(10), create(10));
+        for (id, n) in [(a, 2), (b, 2), (c, 1)] {
+            for v in 0..n {
+                assert_eq!(send(id, v), Ok(true));
+            }
+        }
+        let err = send(c, 9).unwrap_err();
+        assert!(err.starts_with(errors::ERR_CHANNEL_BUDGET), "{}", err);
+        assert_eq!(buffer_usage().used, base + 5 * I64_BYTES);
+        assert!(buffer_usage().high_water >= base + 5 * I64_BYTES);
+
+        assert_eq!(close_and_drain(a), vec![0, 1]);
+        assert_eq!(send(c, 9), Ok(true));
+        assert_eq!(receive(b), Some(0));
+        assert_eq!(send(b, 7), Ok(true));
+        // The fifth value fits; a sixth would not
+        assert_eq!(send(b, 8), Ok(true));
+        let err = send(b, 9).unwrap_err();
+        assert!(err.starts_with(errors::ERR_CHANNEL_BUDGET), "{}", err);
+
+        destroy(b);
+        destroy(c);
+        assert_eq!(buffer_usage().used, base);
+        set_buffer_budget(None);
+        assert_eq!(buffer_usage().budget, None);
+    }
+
+    #[test]
+    fn test_ttl_drops_stale_messages_on_receive() {
+        let _serial = test_serial();
+        let base = buffer_usage().used;
+        let id = create_with(8, Options { ttl: Some(Duration::from_millis(50)), ..Options::default() });
+        for v in [1, 2, 3] {
+            assert_eq!(send(id, v), Ok(true));
+        }
+        thread::sleep(Duration::from_millis(80));
+        for v in [4, 5] {
+            assert_eq!(send(id, v), Ok(true));
+        }
+        assert_eq!(receive(id), Some(4));
+        assert_eq!(receive_until(id, Some(Instant::now() + Duration::from_millis(20))), Received::Value(5));
+        assert_eq!(receive(id), None);
+        assert_eq!(stat(id).unwrap().expired, 3);
+        assert_eq!(buffer_usage().used, base);
+
+        // Fresh messages flow as on a plain channel
+        for v in [6, 7] {
+            assert_eq!(send(id, v), Ok(true));
+        }
+        assert_eq!(close_and_drain(id), vec![6, 7]);
+    }
+
+    #[test]
+    fn test_purge_drops_messages_older_than_the_age() {
+        let _serial = test_serial();
+        let id = create_with(8, Options { timestamps: true, ..Options::default() });
+        for v in [1, 2] {
+            assert_eq!(send(id, v), Ok(true));
+        }
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(send(id, 3), Ok(true));
+
+        assert_eq!(purge_older_than(id, Duration::from_millis(30)), Ok(2));
+        // Already purged, so not counted again
+        assert_eq!(purge_older_than(id, Duration::from_millis(30)), Ok(0));
+        assert_eq!(stat(id).unwrap().expired, 2);
+        assert_eq!(receive_blocking(id), Some(3));
+        assert_eq!(receive(id), None);
+        assert_eq!(stat(id).unwrap().expired, 2);
+        destroy(id);
+
+        let plain = create(4);
+        let err = purge_older_than(plain, Duration::ZERO).unwrap_err();
+        assert!(err.starts_with(errors::ERR_INVALID_INPUT), "{}", err);
+        assert_eq!(stat(plain).unwrap().expired, 0);
+        destroy(plain);
+    }
+
+    #[test]
+    fn test_expired_messages_are_never_delivered() {
+        let _serial = test_serial();
+        let id = create_with(8, Options { ttl: Some(Duration::from_millis(30)), ..Options::default() });
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        for v in [1, 2] {
+            assert_eq!(send(id, v), Ok(true));
+        }
+        thread::sleep(Duration::from_millis(50));
+        let sink = Arc::clone(&seen);
+        subscribe(id, move |value| lock(&sink).push(value)).unwrap();
+        assert_eq!(send(id, 3), Ok(true));
+        close(id);
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while len(id).is_some() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(*lock(&seen), vec![3]);
+    }
+
+    fn adaptive(capacity: u32, options: AdaptiveOptions) -> u64 {
+        create_with(capacity, Options { adaptive: Some(options), ..Options::default() })
+    }
+
+    #[test]
+    fn test_bursty_producer_grows_the_buffer_step_by_step() {
+        let _serial = test_serial();
+        let id = adaptive(2, AdaptiveOptions { full_sends: 0, ..AdaptiveOptions::up_to(20) });
+        let mut capacities = vec![stat(id).unwrap().capacity];
+        for v in 0..20 {
+            assert_eq!(send(id, v), Ok(true));
+            capacities.push(stat(id).unwrap().capacity);
+        }
+        capacities.dedup();
+        assert_eq!(capacities, [2, 4, 8, 16, 20]);
+        assert_eq!(stat(id).unwrap().resizes, 4);
+
+        // At the max, a full buffer blocks as on a fixed channel
+        let blocked = thread::spawn(move || send(id, 20));
+        thread::sleep(Duration::from_millis(30));
+        assert!(!blocked.is_finished());
+        assert_eq!(receive(id), Some(0));
+        assert_eq!(blocked.join().unwrap(), Ok(true));
+        assert_eq!(close_and_drain(id), (1..=20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_migration_keeps_order_for_a_receiver_mid_wait() {
+        let _serial = test_serial();
+        let base = buffer_usage().used;
+        let id = adaptive(1, AdaptiveOptions { full_sends: 0, ..AdaptiveOptions::up_to(1024) });
+        // Blocked on the empty buffer when the first migration starts
+        let consumer = thread::spawn(move || {
+            let mut seen = Vec::new();
+            loop {
+                // Alternate between the two blocking receives
+                let next = match seen.len() % 2 {
+                    0 => receive_blocking(id),
+                    _ => match receive_until(id, None) {
+                        Received::Value(value) => Some(value),
+                        _ => None,
+                    },
+                };
+                match next {
+                    Some(value) => seen.push(value),
+                    None => return seen,
+                }
+            }
+        });
+        thread::sleep(Duration::from_millis(20));
+        for v in 0..20_000 {
+            assert_eq!(send(id, v), Ok(true));
+        }
+        let resizes = stat(id).unwrap().resizes;
+        close(id);
+        assert_eq!(consumer.join().unwrap(), (0..20_000).collect::<Vec<_>>());
+        assert!(resizes > 0);
+        assert_eq!(buffer_usage().used, base);
+    }
+
+    #[test]
+    fn test_adaptive_capacity_never_exceeds_the_max() {
+        let _serial = test_serial();
+        let id = adaptive(1, AdaptiveOptions { growth_factor: 3.0, full_sends: 0, ..AdaptiveOptions::up_to(10) });
+        let producers: Vec<_> = (0..4)
+            .map(|p| thread::spawn(move || (0..500).for_each(|v| assert_eq!(send(id, p * 1000 + v), Ok(true)))))
+            .collect();
+        let mut received = vec![Vec::new(); 4];
+        let mut largest = 0;
+        for _ in 0..2000 {
+            largest = largest.max(stat(id).unwrap().capacity);
+            let value = receive_blocking(id).unwrap();
+            received[(value / 1000) as usize].push(value % 1000);
+            if received.iter().map(Vec::len).sum::<usize>() % 100 == 0 {
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        // 1, 3, 9, then capped at 10
+        assert!(largest <= 10, "{}", largest);
+        assert!(stat(id).unwrap().resizes <= 3);
+        for values in received {
+            assert_eq!(values, (0..500).collect::<Vec<_>>());
+        }
+        destroy(id);
+    }
+
+    #[test]
+    fn test_quiet_adaptive_channel_shrinks_back() {
+        let _serial = test_serial();
+        let id = adaptive(
+            2,
+            AdaptiveOptions { full_sends: 0, shrink_after: Some(Duration::from_millis(30)), ..AdaptiveOptions::up_to(8) },
+        );
+        for v in 0..5 {
+            assert_eq!(send(id, v), Ok(true));
+        }
+        assert_eq!(stat(id).unwrap().capacity, 8);
+        for v in 0..5 {
+            assert_eq!(receive(id), Some(v));
+        }
+        // Busy until a moment ago, so it keeps its capacity for now
+        assert_eq!(stat(id).unwrap().capacity, 8);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(send(id, 9), Ok(true));
+        let s = stat(id).unwrap();
+        assert_eq!((s.capacity, s.resizes), (2, 3));
+        assert_eq!(receive(id), Some(9));
+        destroy(id);
+
+        // Fair channels keep a fixed buffer
+        let fair = create_with(1, Options { fair: true, adaptive: Some(AdaptiveOptions::up_to(8)), ..Options::default() });
+        assert_eq!(send(fair, 1), Ok(true));
+        assert_eq!(stat(fair).unwrap().capacity, 1);
+        destroy(fair);
+    }
+
+    #[test]
+    fn test_dump_and_restore_round_trip_values_and_options() {
+        let _serial = test_serial();
+        let stamped = Options { fair: true, ttl: Some(Duration::from_micros(60_000_001)), timestamps: true, adaptive: None };
+        let grown = Options {
+            adaptive: Some(AdaptiveOptions { full_sends: 0, shrink_after: Some(Duration::from_millis(1500)), ..AdaptiveOptions::up_to(16) }),
+            ..Options::default()
+        };
+        let extremes = vec![i64::MIN, -1, 0, 7, i64::MAX];
+        // The adaptive channel grows to hold all five
+        for (capacity, options, buffered) in [
+            (8, stamped, extremes.clone()),
+            (2, grown, extremes.clone()),
+            (3, Options::default(), vec![4, 5, 6]),
+            (0, Options::default(), vec![]),
+        ] {
+            let id = create_with(capacity, options);
+            for &v in &buffered {
+                assert_eq!(send(id, v), Ok(true));
+            }
+            let dumped = dump(id).unwrap();
+            // Nothing was taken out
+            assert_eq!(len(id), Some(buffered.len()));
+
+            let restored = restore(&dumped).unwrap();
+            assert_ne!(restored, id);
+            assert_eq!(dump(restored).unwrap(), dumped, "capacity {}", capacity);
+            let (a, b) = (stat(id).unwrap(), stat(restored).unwrap());
+            assert_eq!((a.len, a.capacity, a.fair), (b.len, b.capacity, b.fair));
+            for channel in [id, restored] {
+                let received: Vec<i64> = std::iter::from_fn(|| receive(channel)).collect();
+                assert_eq!(received, buffered);
+                destroy(channel);
+            }
+        }
+        let closed = create(1);
+        close(closed);
+        assert!(dump(closed).is_err());
+    }
+
+    #[test]
+    fn test_restore_rejects_corrupted_dumps() {
+        let _serial = test_serial();
+        let id = create_with(4, Options { adaptive: Some(AdaptiveOptions::up_to(8)), ..Options::default() });
+        for v in 0..3 {
+            send(id, v).unwrap();
+        }
+        let good = dump(id).unwrap();
+        destroy(id);
+        let issued_before = *lock(&NEXT_ID);
+
+        let mut bad: Vec<Vec<u8>> = (0..good.len()).map(|n| good[..n].to_vec()).collect();
+        let mut corrupt = |at: usize, byte: u8| {
+            let mut dump = good.clone();
+            dump[at] = byte;
+            bad.push(dump);
+        };
+        corrupt(0, b'X'); // magic
+        corrupt(4, 9); // version
+        corrupt(6, 4); // header length
+        corrupt(21, 0x80); // unknown flag
+        corrupt(30, 0); // adaptive max below the capacity
+        corrupt(10 + DUMP_HEADER_LEN + DUMP_ADAPTIVE_LEN, 9); // count
+        bad.push([good.as_slice(), &[0]].concat());
+        for dump in &bad {
+            let err = restore(dump).unwrap_err();
+            assert!(err.starts_with(errors::ERR_INVALID_INPUT), "{}", err);
+        }
+        assert!(restore_all(&good).is_err());
+        assert_eq!(*lock(&NEXT_ID), issued_before, "nothing was created");
+    }
+
+    #[test]
+    fn test_dump_while_receiving_never_duplicates_a_delivered_value() {
+        let _serial = test_serial();
+        const N: i64 = 20_000;
+        let id = create(N as u32);
+        for v in 0..N {
+            send(id, v).unwrap();
+        }
+        let delivered = Arc::new(AtomicU64::new(0));
+        let consumer = {
+            let delivered = Arc::clone(&delivered);
+            thread::spawn(move || {
+                let mut received = Vec::new();
+                while let Received::Value(v) = receive_until(id, Some(Instant::now() + Duration::from_millis(200))) {
+                    received.push(v);
+                    delivered.store(received.len() as u64, Ordering::Release);
+                }
+                received
+            })
+        };
+        let mut dumps = 0;
+        while delivered.load(Ordering::Acquire) < N as u64 {
+            let before = delivered.load(Ordering::Acquire) as i64;
+            let values = decode(&dump(id).unwrap()).unwrap().values;
+            // Everything undelivered when the copy was taken, and nothing else
+            if let Some(&first) = values.first() {
+                assert!(first >= before, "{} was delivered before the dump", first);
+                assert!(values.iter().copied().eq(first..N));
+            }
+            dumps += 1;
+        }
+        assert_eq!(consumer.join().unwrap(), (0..N).collect::<Vec<_>>());
+        assert!(dumps > 1);
+        destroy(id);
+    }
+
+    #[test]
+    fn test_dump_does_not_wait_for_blocked_senders() {
+        let _serial = test_serial();
+        for fair in [false, true] {
+            let id = create_with(2, Options { fair, ..Options::default() });
+            send(id, 1).unwrap();
+            send(id, 2).unwrap();
+            let blocked: Vec<_> = (3..5).map(|v| thread::spawn(move || send(id, v))).collect();
+            thread::sleep(Duration::from_millis(30));
+            let started = Instant::now();
+            assert_eq!(decode(&dump(id).unwrap()).unwrap().values, vec![1, 2]);
+            assert!(started.elapsed() < Duration::from_millis(500));
+            let mut received: Vec<i64> = (0..4).map(|_| receive_blocking(id).unwrap()).collect();
+            assert_eq!(&received[..2], &[1, 2]);
+            received[2..].sort_unstable();
+            assert_eq!(&received[2..], &[3, 4], "fair {}", fair);
+            for sender in blocked {
+                assert_eq!(sender.join().unwrap(), Ok(true));
+            }
+            destroy(id);
+        }
+    }
 }
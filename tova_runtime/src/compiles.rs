@@ -0,0 +1,234 @@
+use crate::config;
+use crate::errors::lock;
+use once_cell::sync::Lazy;
+use std::sync::{Condvar, Mutex};
+
+// Admission for module compilation. Cranelift compiles a module's functions
+// in parallel across every core, so a burst of new modules would starve the
+// guests already running. Each compilation instead waits for one of
+// `max_concurrent_compilations` slots (default 1), however many are
+// submitted. Within a slot, compilation runs on rayon's global pool, or with
+// `compile_threads` set on a dedicated pool of that many threads at a lowered
+// scheduling priority.
+
+/// Nice value of the dedicated compile threads
+#[cfg(target_os = "linux")]
+const COMPILE_NICE: libc::c_int = 10;
+
+struct Admission {
+    running: usize,
+    queued: usize,
+}
+
+static ADMISSION: Mutex<Admission> = Mutex::new(Admission { running: 0, queued: 0 });
+static SLOT_FREED: Condvar = Condvar::new();
+
+static POOL: Lazy<Option<rayon::ThreadPool>> = Lazy::new(|| {
+    let threads = config::get().compile_threads?;
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .thread_name(|i| format!("tova-compile-{}", i))
+        .start_handler(|_| lower_priority())
+        .build()
+        .ok()
+});
+
+/// Whether the compile pool settings are fixed yet, which happens on the
+/// first compilation.
+pub fn pool_started() -> bool {
+    Lazy::get(&POOL).is_some()
+}
+
+// Test hook: called with a module's hash when its compilation starts (true)
+// and ends (false), both while it holds its slot
+#[cfg(test)]
+type CompileHook = Box<dyn Fn(u64, bool) + Send>;
+
+#[cfg(test)]
+pub(crate) static COMPILE_HOOK: Mutex<Option<CompileHook>> = Mutex::new(None);
+
+/// A held compilation slot, given back on drop.
+struct Slot;
+
+impl Slot {
+    fn acquire() -> Slot {
+        let mut admission = lock(&ADMISSION);
+        admission.queued += 1;
+        while admission.running >= config::get().max_concurrent_compilations {
+            admission = SLOT_FREED.wait(admission).unwrap_or_else(|e| e.into_inner());
+        }
+        admission.queued -= 1;
+        admission.running += 1;
+        Slot
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        lock(&ADMISSION).running -= 1;
+        SLOT_FREED.notify_all();
+    }
+}
+
+/// Run `compile` (of the module hashing to `hash`) once a slot is free, on
+/// the compile pool.
+pub fn admitted<R: Send>(hash: u64, compile: impl FnOnce() -> R + Send) -> R {
+    let _slot = Slot::acquire();
+    compile_hook(hash, true);
+    let result = match &*POOL {
+        Some(pool) => pool.install(compile),
+        None => compile(),
+    };
+    compile_hook(hash, false);
+    result
+}
+
+#[cfg(test)]
+fn compile_hook(hash: u64, started: bool) {
+    if let Some(hook) = &*lock(&COMPILE_HOOK) {
+        hook(hash, started);
+    }
+}
+
+#[cfg(not(test))]
+fn compile_hook(_hash: u64, _started: bool) {}
+
+/// Raising the slot count lets waiting compilations start at once.
+pub fn limit_changed() {
+    SLOT_FREED.notify_all();
+}
+
+pub struct CompileStats {
+    /// Compilations waiting for a slot
+    pub queued: usize,
+    /// Compilations holding one
+    pub running: usize,
+}
+
+pub fn stats() -> CompileStats {
+    let admission = lock(&ADMISSION);
+    CompileStats { queued: admission.queued, running: admission.running }
+}
+
+#[cfg(target_os = "linux")]
+fn lower_priority() {
+    // Per thread on Linux, whatever POSIX says about processes
+    unsafe {
+        libc::setpriority(libc::PRIO_PROCESS, libc::gettid() as libc::id_t, COMPILE_NICE);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn lower_priority() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channels;
+    use crate::executor::{self, Metering, WasmInput};
+    use crate::testsupport as fixtures;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    type Log = Arc<Mutex<Vec<(u64, bool)>>>;
+
+    /// Record the compilation events of `watched` modules, sleeping `pause`
+    /// as each starts.
+    fn watch(watched: Vec<u64>, pause: Duration) -> Log {
+        let log = Log::default();
+        let events = log.clone();
+        *lock(&COMPILE_HOOK) = Some(Box::new(move |hash, started| {
+            if watched.contains(&hash) {
+                lock(&events).push((hash, started));
+                if started {
+                    assert_eq!(stats().running, 1, "a second compilation got a slot");
+                    std::thread::sleep(pause);
+                }
+            }
+        }));
+        log
+    }
+
+    fn hash_of(wasm: &[u8]) -> u64 {
+        match executor::resolve_wasm_with(wasm, Metering::Fuel) {
+            WasmInput::Bytes { hash, .. } => hash,
+            WasmInput::Compiled(_) => panic!("module already compiled"),
+        }
+    }
+
+    #[test]
+    fn test_compilations_take_turns_at_one_slot() {
+        let _serial = channels::test_serial();
+        config::update(|c| c.max_concurrent_compilations = 1);
+        let modules = [fixtures::large_module(60, 1), fixtures::large_module(60, 2)];
+        let hashes: Vec<u64> = modules.iter().map(|m| hash_of(m)).collect();
+        let log = watch(hashes.clone(), Duration::from_millis(50));
+
+        let mut saw_queued = false;
+        std::thread::scope(|s| {
+            let compiling: Vec<_> =
+                modules.iter().map(|m| s.spawn(move || executor::precompile(m, Metering::Fuel).unwrap())).collect();
+            while !compiling.iter().all(|t| t.is_finished()) {
+                saw_queued |= stats().queued > 0;
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        });
+        *lock(&COMPILE_HOOK) = None;
+
+        let log = lock(&log).clone();
+        assert_eq!(log.len(), 4, "{:?}", log);
+        // Each ends before the other starts
+        assert!(log[0].1 && !log[1].1 && log[2].1 && !log[3].1, "{:?}", log);
+        assert_eq!(log[0].0, log[1].0);
+        assert_eq!(log[2].0, log[3].0);
+        assert_ne!(log[0].0, log[2].0);
+        assert!(saw_queued, "the second compilation never queued");
+        assert_eq!(stats().running, 0);
+    }
+
+    #[test]
+    fn test_concurrent_misses_on_one_module_compile_it_once() {
+        let _serial = channels::test_serial();
+        let wasm = fixtures::tagged(fixtures::add_module(), 199);
+        let log = watch(vec![hash_of(&wasm)], Duration::from_millis(100));
+        let deduplicated = executor::cache_stats().deduplicated;
+
+        let handles: Vec<u64> = std::thread::scope(|s| {
+            let threads: Vec<_> = (0..4).map(|_| s.spawn(|| executor::precompile(&wasm, Metering::Fuel).unwrap())).collect();
+            threads.into_iter().map(|t| t.join().unwrap()).collect()
+        });
+        *lock(&COMPILE_HOOK) = None;
+
+        assert_eq!(lock(&log).len(), 2, "compiled more than once");
+        assert_eq!(executor::cache_stats().deduplicated - deduplicated, 3);
+        for handle in handles {
+            let module = executor::precompiled(handle).unwrap();
+            assert_eq!(executor::exec_wasm_sync(&module, "add", &[2, 3]), Ok(5));
+            executor::release_module(handle);
+        }
+    }
+
+    #[test]
+    fn test_guests_stay_responsive_during_a_background_compile() {
+        let _serial = channels::test_serial();
+        let add = executor::resolve_wasm(&fixtures::add_module());
+        assert_eq!(executor::exec_wasm_sync(&add, "add", &[1, 2]), Ok(3));
+        let large = fixtures::large_module(150, 3);
+
+        let mut worst = Duration::ZERO;
+        let mut calls = 0;
+        std::thread::scope(|s| {
+            let compiling = s.spawn(|| executor::precompile(&large, Metering::Fuel).unwrap());
+            while !compiling.is_finished() {
+                let started = Instant::now();
+                assert_eq!(executor::exec_wasm_sync(&add, "add", &[calls, 1]), Ok(calls + 1));
+                worst = worst.max(started.elapsed());
+                calls += 1;
+            }
+            executor::release_module(compiling.join().unwrap());
+        });
+        assert!(calls > 0);
+        // Coarse: a call never waits out the compilation
+        assert!(worst < Duration::from_millis(250), "a call took {:?} during the compile", worst);
+    }
+}
@@ -0,0 +1,153 @@
+use crate::errors;
+use crate::executor::Metering;
+use once_cell::sync::Lazy;
+use std::sync::RwLock;
+use std::time::Duration;
+
+/// Runtime-wide settings, changed through `configure_runtime`.
+#[derive(Clone, Debug)]
+pub struct RuntimeConfig {
+    /// Threads in the dedicated compute pool
+    pub compute_threads: usize,
+    /// CPU sets compute threads are pinned to, assigned round-robin by thread index
+    pub cpu_affinity: Vec<Vec<usize>>,
+    /// Chunks concurrent_wasm_shared splits a batch into (None = one per compute thread)
+    pub shared_chunks: Option<usize>,
+    /// Largest module accepted at the napi boundary, in bytes
+    pub max_module_bytes: usize,
+    /// Most arguments accepted for one call
+    pub max_args: usize,
+    /// Most tasks accepted in one batch
+    pub max_batch_tasks: usize,
+    /// Most executions accepted but not yet started (None = unbounded)
+    pub max_queued_executions: Option<usize>,
+    /// Metering for calls that don't choose one
+    pub default_metering: Metering,
+    /// How long a Metering::Epoch call may run
+    pub epoch_time_limit: Duration,
+    /// Native stack a guest call may use before trapping with
+    /// ERR_STACK_OVERFLOW; fixed once an engine exists
+    pub max_wasm_stack: usize,
+    /// Whether cranelift compiles a module's functions in parallel; fixed
+    /// once an engine exists
+    pub parallel_compilation: bool,
+    /// Threads of the dedicated, lower-priority compile pool (None = rayon's
+    /// global pool); fixed once a module has compiled
+    pub compile_threads: Option<usize>,
+    /// Modules that may compile at once; the rest wait for a slot
+    pub max_concurrent_compilations: usize,
+    /// Compiled modules the module cache holds before evicting the least
+    /// recently used unpinned one
+    pub module_cache_capacity: usize,
+    /// Share of `module_cache_capacity` that pinned modules may take
+    pub max_pinned_fraction: f64,
+    /// Fuel an inline execution may burn on the JS thread
+    pub inline_fuel: u64,
+    /// Whether the runtime watchdog samples for stuck work
+    pub watchdog: bool,
+    /// Age at which the watchdog flags an execution or channel wait
+    pub watchdog_threshold: Duration,
+    /// How often the watchdog samples
+    pub watchdog_interval: Duration,
+}
+
+/// Upper bound on `max_wasm_stack`, leaving host frames room on the
+/// GUEST_THREAD_STACK of every thread that runs guests
+pub const MAX_WASM_STACK_LIMIT: usize = 4 << 20;
+
+/// Native stack size of threads that run guests
+pub const GUEST_THREAD_STACK: usize = 8 << 20;
+
+static CONFIG: Lazy<RwLock<RuntimeConfig>> = Lazy::new(|| {
+    RwLock::new(RuntimeConfig {
+        compute_threads: crate::scheduler::num_cpus(),
+        cpu_affinity: Vec::new(),
+        shared_chunks: None,
+        max_module_bytes: 64 << 20,
+        max_args: 64,
+        max_batch_tasks: 100_000,
+        max_queued_executions: None,
+        default_metering: Metering::Fuel,
+        epoch_time_limit: Duration::from_secs(10),
+        max_wasm_stack: 512 << 10,
+        parallel_compilation: true,
+        compile_threads: None,
+        max_concurrent_compilations: 1,
+        module_cache_capacity: 256,
+        max_pinned_fraction: 0.5,
+        inline_fuel: 1_000_000,
+        watchdog: false,
+        watchdog_threshold: Duration::from_secs(30),
+        watchdog_interval: Duration::from_secs(5),
+    })
+});
+
+pub fn get() -> RuntimeConfig {
+    CONFIG.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+pub fn update(f: impl FnOnce(&mut RuntimeConfig)) {
+    let mut config = CONFIG.write().unwrap_or_else(|e| e.into_inner());
+    f(&mut config);
+}
+
+// Input limits, checked at the napi boundary before any bytes are copied or
+// tasks spawned. `index` names the offending task of a batch.
+impl RuntimeConfig {
+    pub fn check_batch(&self, tasks: usize) -> Result<(), String> {
+        if tasks > self.max_batch_tasks {
+            return Err(invalid(None, format!("batch has {} tasks, over maxTasksPerBatch ({})", tasks, self.max_batch_tasks)));
+        }
+        Ok(())
+    }
+
+    pub fn check_call(&self, index: Option<usize>, module_bytes: Option<usize>, args: usize) -> Result<(), String> {
+        if let Some(bytes) = module_bytes {
+            if bytes > self.max_module_bytes {
+                return Err(invalid(index, format!("module is {} bytes, over maxModuleBytes ({})", bytes, self.max_module_bytes)));
+            }
+        }
+        if args > self.max_args {
+            return Err(invalid(index, format!("{} args, over maxArgsPerTask ({})", args, self.max_args)));
+        }
+        Ok(())
+    }
+}
+
+impl RuntimeConfig {
+    /// Most modules that may be pinned at once
+    pub fn max_pinned_modules(&self) -> usize {
+        (self.module_cache_capacity as f64 * self.max_pinned_fraction) as usize
+    }
+}
+
+fn invalid(index: Option<usize>, msg: String) -> String {
+    match index {
+        Some(i) => errors::coded(errors::ERR_INVALID_INPUT, format!("task {}: {}", i, msg)),
+        None => errors::coded(errors::ERR_INVALID_INPUT, msg),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> RuntimeConfig {
+        RuntimeConfig { max_module_bytes: 100, max_args: 2, max_batch_tasks: 3, ..get() }
+    }
+
+    #[test]
+    fn test_limits_name_the_violation_and_task() {
+        let c = limits();
+        assert_eq!(c.check_call(None, Some(100), 2), Ok(()));
+        assert_eq!(c.check_call(Some(0), None, 0), Ok(()));
+        assert_eq!(c.check_batch(3), Ok(()));
+
+        let err = c.check_call(None, Some(101), 0).unwrap_err();
+        assert!(err.starts_with(errors::ERR_INVALID_INPUT) && err.contains("maxModuleBytes"), "{}", err);
+        let err = c.check_call(Some(7), Some(1), 3).unwrap_err();
+        assert!(err.contains("task 7") && err.contains("maxArgsPerTask"), "{}", err);
+        let err = c.check_batch(4).unwrap_err();
+        assert!(err.starts_with(errors::ERR_INVALID_INPUT) && err.contains("maxTasksPerBatch"), "{}", err);
+    }
+}
@@ -0,0 +1,211 @@
+use crate::channels;
+use crate::errors::lock;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+// A context scopes the channels a guest can reach. Guests executed with a
+// context address channels by the small ids `grant` hands out, and the host
+// imports translate them through the context's table; any other id fails to
+// resolve, so a module can only touch channels it was explicitly given.
+
+struct Context {
+    /// Channels created through the context, closed when it is destroyed
+    owned: Vec<u64>,
+    /// Guest-visible id (the index) -> channel id
+    granted: Vec<u64>,
+}
+
+static CONTEXTS: Lazy<Mutex<HashMap<u64, Context>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+fn no_such_context(ctx: u64) -> String {
+    format!("no such channel context: {}", ctx)
+}
+
+pub fn create() -> u64 {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    lock(&CONTEXTS).insert(id, Context { owned: Vec::new(), granted: Vec::new() });
+    id
+}
+
+pub fn exists(ctx: u64) -> bool {
+    lock(&CONTEXTS).contains_key(&ctx)
+}
+
+/// Create a channel owned by `ctx`. Ownership only ties its lifetime to the
+/// context; guests still need a `grant` to reach it.
+pub fn create_channel(ctx: u64, capacity: u32) -> Result<u64, String> {
+    let mut contexts = lock(&CONTEXTS);
+    let context = contexts.get_mut(&ctx).ok_or_else(|| no_such_context(ctx))?;
+    let id = channels::create(capacity);
+    context.owned.push(id);
+    Ok(id)
+}
+
+/// Make `channel` reachable from guests running in `ctx` and return the id
+/// they should use. Granting the same channel twice returns the same id.
+pub fn grant(ctx: u64, channel: u64) -> Result<i32, String> {
+    if channels::len(channel).is_none() {
+        return Err(format!("no such channel: {}", channel));
+    }
+    let mut contexts = lock(&CONTEXTS);
+    let context = contexts.get_mut(&ctx).ok_or_else(|| no_such_context(ctx))?;
+    if let Some(existing) = context.granted.iter().position(|&c| c == channel) {
+        return Ok(existing as i32);
+    }
+    context.granted.push(channel);
+    Ok((context.granted.len() - 1) as i32)
+}
+
+/// Guest-visible id of the result channel: by convention a guest publishes
+/// intermediate results with `chan_send(0, value)`.
+pub const RESULT_CHANNEL_ID: i32 = 0;
+
+/// Grant `channel` as RESULT_CHANNEL_ID. Fails if that id already belongs to
+/// another channel, so the result channel has to be granted first.
+pub fn grant_result(ctx: u64, channel: u64) -> Result<(), String> {
+    if channels::len(channel).is_none() {
+        return Err(format!("no such channel: {}", channel));
+    }
+    let mut contexts = lock(&CONTEXTS);
+    let context = contexts.get_mut(&ctx).ok_or_else(|| no_such_context(ctx))?;
+    match context.granted.first() {
+        None => context.granted.push(channel),
+        Some(&granted) if granted == channel => {}
+        Some(_) => {
+            return Err(format!(
+                "guest channel id {} of context {} is already granted to another channel",
+                RESULT_CHANNEL_ID, ctx
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Channel behind a guest-visible id, if `ctx` granted one.
+pub fn resolve(ctx: u64, guest_id: i32) -> Option<u64> {
+    let contexts = lock(&CONTEXTS);
+    let context = contexts.get(&ctx)?;
+    usize::try_from(guest_id).ok().and_then(|i| context.granted.get(i).copied())
+}
+
+/// Every channel `ctx` owns or grants, ascending.
+pub fn channels(ctx: u64) -> Result<Vec<u64>, String> {
+    let contexts = lock(&CONTEXTS);
+    let context = contexts.get(&ctx).ok_or_else(|| no_such_context(ctx))?;
+    let mut ids: Vec<u64> = context.owned.iter().chain(&context.granted).copied().collect();
+    ids.sort_unstable();
+    ids.dedup();
+    Ok(ids)
+}
+
+/// Close every channel `ctx` owns or grants, so each guest receiving on one
+/// sees it end. The first step of tearing a context down while consumers
+/// still run in it; the channels stay registered until drained. Returns how
+/// many channels were closed.
+pub fn close_all(ctx: u64) -> Result<usize, String> {
+    let ids = channels(ctx)?;
+    for &id in &ids {
+        channels::close(id);
+    }
+    Ok(ids.len())
+}
+
+/// Drop the context and close every channel it owns. Channels it was only
+/// granted are left alone.
+pub fn destroy(ctx: u64) -> bool {
+    let context = match lock(&CONTEXTS).remove(&ctx) {
+        Some(context) => context,
+        None => return false,
+    };
+    for id in context.owned {
+        channels::close(id);
+    }
+    true
+}
+
+/// Forget every context. Their channels are closed by `channels::reset`.
+pub fn reset() {
+    lock(&CONTEXTS).clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grants_translate_only_granted_channels() {
+        let ctx = create();
+        let owned = create_channel(ctx, 4).unwrap();
+        let outside = channels::create(4);
+        assert_eq!(resolve(ctx, 0), None);
+
+        let guest = grant(ctx, owned).unwrap();
+        assert_eq!(guest, 0);
+        assert_eq!(grant(ctx, owned), Ok(0));
+        assert_eq!(resolve(ctx, guest), Some(owned));
+        assert_eq!(resolve(ctx, 1), None);
+        assert_eq!(resolve(ctx, -1), None);
+        assert!(grant(ctx, u64::MAX).is_err());
+        assert!(grant(u64::MAX, outside).is_err());
+        assert!(destroy(ctx));
+    }
+
+    #[test]
+    fn test_result_channel_takes_guest_id_zero() {
+        let ctx = create();
+        let result = channels::create(4);
+        let other = channels::create(4);
+        assert_eq!(grant_result(ctx, result), Ok(()));
+        assert_eq!(grant_result(ctx, result), Ok(()));
+        assert_eq!(resolve(ctx, RESULT_CHANNEL_ID), Some(result));
+        assert_eq!(grant(ctx, other), Ok(1));
+        assert!(grant_result(ctx, other).is_err());
+
+        let taken = create();
+        grant(taken, other).unwrap();
+        assert!(grant_result(taken, result).is_err());
+        assert!(destroy(ctx) && destroy(taken));
+    }
+
+    #[test]
+    fn test_destroy_closes_owned_channels_only() {
+        let _serial = channels::test_serial();
+        let ctx = create();
+        let owned = create_channel(ctx, 4).unwrap();
+        let shared = channels::create(4);
+        grant(ctx, shared).unwrap();
+
+        assert!(destroy(ctx));
+        assert!(!destroy(ctx));
+        assert!(!exists(ctx));
+        assert!(channels::send(owned, 1).is_err());
+        assert_eq!(channels::send(shared, 1), Ok(true));
+        assert_eq!(resolve(ctx, 0), None);
+        assert!(create_channel(ctx, 1).is_err());
+    }
+
+    #[test]
+    fn test_close_all_closes_owned_and_granted_channels() {
+        let _serial = channels::test_serial();
+        let ctx = create();
+        let owned = create_channel(ctx, 4).unwrap();
+        let shared = channels::create(4);
+        grant(ctx, owned).unwrap();
+        grant(ctx, shared).unwrap();
+        assert_eq!(channels::send(shared, 5), Ok(true));
+
+        assert_eq!(close_all(ctx), Ok(2));
+        assert!(channels::send(owned, 1).is_err());
+        assert!(channels::send(shared, 1).is_err());
+        // Closing keeps what was buffered for the consumers
+        assert_eq!(channels::receive(shared), Some(5));
+        assert!(exists(ctx));
+        assert!(destroy(ctx));
+        assert!(close_all(ctx).is_err());
+    }
+}
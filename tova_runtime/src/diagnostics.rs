@@ -0,0 +1,297 @@
+use crate::errors::lock;
+use crossbeam_channel::{RecvTimeoutError, Sender};
+use once_cell::sync::Lazy;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Wait tracking for channel operations that block. Only the slow path
+// registers (a receive on an empty buffer, a send on a full one), so the
+// table holds exactly what is stuck right now and costs nothing when
+// channels flow freely.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WaitOp {
+    Send,
+    Receive,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TaskKind {
+    /// A host call (napi or Rust)
+    Host,
+    /// A WASM guest inside a channel import
+    Guest,
+}
+
+#[derive(Clone, Debug)]
+pub struct BlockedWait {
+    pub wait_id: u64,
+    pub channel: u64,
+    pub op: WaitOp,
+    pub task: TaskKind,
+    pub waited: Duration,
+}
+
+struct Wait {
+    channel: u64,
+    op: WaitOp,
+    task: TaskKind,
+    since: Instant,
+}
+
+static WAITS: Lazy<Mutex<HashMap<u64, Wait>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Ids for waits and running executions alike, so one id names one entry
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static TASK_KIND: Cell<TaskKind> = const { Cell::new(TaskKind::Host) };
+}
+
+/// Registered wait; dropping it (on completion, error or unwind) clears the entry.
+pub struct WaitGuard(u64);
+
+impl Drop for WaitGuard {
+    fn drop(&mut self) {
+        lock(&WAITS).remove(&self.0);
+    }
+}
+
+pub fn begin_wait(channel: u64, op: WaitOp) -> WaitGuard {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let task = TASK_KIND.with(|kind| kind.get());
+    lock(&WAITS).insert(id, Wait { channel, op, task, since: Instant::now() });
+    WaitGuard(id)
+}
+
+/// Marks waits on this thread as guest waits until dropped.
+pub struct GuestScope(TaskKind);
+
+impl Drop for GuestScope {
+    fn drop(&mut self) {
+        TASK_KIND.with(|kind| kind.set(self.0));
+    }
+}
+
+pub fn guest_scope() -> GuestScope {
+    GuestScope(TASK_KIND.with(|kind| kind.replace(TaskKind::Guest)))
+}
+
+/// Everything currently blocked, longest wait first.
+pub fn blocked() -> Vec<BlockedWait> {
+    let now = Instant::now();
+    let mut waits: Vec<BlockedWait> = lock(&WAITS)
+        .iter()
+        .map(|(&wait_id, w)| BlockedWait {
+            wait_id,
+            channel: w.channel,
+            op: w.op,
+            task: w.task,
+            waited: now.saturating_duration_since(w.since),
+        })
+        .collect();
+    waits.sort_by(|a, b| b.waited.cmp(&a.waited).then(a.wait_id.cmp(&b.wait_id)));
+    waits
+}
+
+// --- Running executions ---
+
+// Guest calls register while they run, in the same way, so a call that never
+// returns shows up next to the waits. Each carries a kill flag that the
+// store's epoch callback on the calling thread checks (see
+// `executor::arm_store`), which is how the watchdog stops one.
+
+#[derive(Clone, Debug)]
+pub struct RunningExecution {
+    pub id: u64,
+    /// The module's cache hash
+    pub module: u64,
+    pub func: String,
+    pub running: Duration,
+}
+
+struct Execution {
+    module: u64,
+    func: String,
+    since: Instant,
+    killed: Arc<AtomicBool>,
+}
+
+static EXECUTIONS: Lazy<Mutex<HashMap<u64, Execution>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+thread_local! {
+    static KILLED: RefCell<Option<Arc<AtomicBool>>> = const { RefCell::new(None) };
+}
+
+/// Registered execution; dropping it clears the entry and restores the
+/// kill flag of any call it ran inside.
+pub struct ExecutionGuard {
+    id: u64,
+    outer: Option<Arc<AtomicBool>>,
+}
+
+impl Drop for ExecutionGuard {
+    fn drop(&mut self) {
+        lock(&EXECUTIONS).remove(&self.id);
+        KILLED.with(|killed| *killed.borrow_mut() = self.outer.take());
+    }
+}
+
+/// Register a call to `func` of `module` running on this thread until the
+/// guard drops.
+pub fn begin_execution(module: u64, func: &str) -> ExecutionGuard {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let killed = Arc::new(AtomicBool::new(false));
+    let execution = Execution { module, func: func.to_string(), since: Instant::now(), killed: Arc::clone(&killed) };
+    lock(&EXECUTIONS).insert(id, execution);
+    ExecutionGuard { id, outer: KILLED.with(|current| current.replace(Some(killed))) }
+}
+
+/// Whether the execution running on this thread has been killed.
+pub fn killed() -> bool {
+    KILLED.with(|killed| killed.borrow().as_ref().is_some_and(|k| k.load(Ordering::Acquire)))
+}
+
+/// Flag execution `id` killed; false if it is not running. The guest stops
+/// at its store's next epoch check.
+pub fn kill_execution(id: u64) -> bool {
+    match lock(&EXECUTIONS).get(&id) {
+        Some(execution) => {
+            execution.killed.store(true, Ordering::Release);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Every registered execution, longest running first.
+pub fn running() -> Vec<RunningExecution> {
+    let now = Instant::now();
+    let mut executions: Vec<RunningExecution> = lock(&EXECUTIONS)
+        .iter()
+        .map(|(&id, e)| RunningExecution {
+            id,
+            module: e.module,
+            func: e.func.clone(),
+            running: now.saturating_duration_since(e.since),
+        })
+        .collect();
+    executions.sort_by(|a, b| b.running.cmp(&a.running).then(a.id.cmp(&b.id)));
+    executions
+}
+
+// --- Stall watchdog ---
+
+/// Dropping the sender stops the watchdog thread.
+static WATCHDOG: Lazy<Mutex<Option<Sender<()>>>> = Lazy::new(|| Mutex::new(None));
+
+/// Start (or replace) the watchdog. It polls the wait table and calls
+/// `on_stall` with a full snapshot whenever some wait newly crosses
+/// `threshold`; a wait that stays stuck is reported once.
+pub fn start_watchdog<F>(threshold: Duration, on_stall: F)
+where
+    F: Fn(Vec<BlockedWait>) + Send + 'static,
+{
+    let (stop, stopped) = crossbeam_channel::bounded::<()>(0);
+    let poll = (threshold / 4).clamp(Duration::from_millis(5), Duration::from_secs(1));
+    thread::Builder::new()
+        .name("tova-stall-watchdog".to_string())
+        .spawn(move || {
+            let mut reported: HashSet<u64> = HashSet::new();
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(poll) {
+                let snapshot = blocked();
+                let stalled: HashSet<u64> = snapshot
+                    .iter()
+                    .filter(|w| w.waited >= threshold)
+                    .map(|w| w.wait_id)
+                    .collect();
+                if stalled.iter().any(|id| !reported.contains(id)) {
+                    on_stall(snapshot);
+                }
+                reported = stalled;
+            }
+        })
+        .expect("failed to spawn stall watchdog");
+    *lock(&WATCHDOG) = Some(stop);
+}
+
+/// Stop the watchdog; false if none was running.
+pub fn stop_watchdog() -> bool {
+    lock(&WATCHDOG).take().is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channels;
+
+    #[test]
+    fn test_snapshot_names_both_channels_of_a_deadlock() {
+        let _serial = channels::test_serial();
+        let a = channels::create(1);
+        let b = channels::create(1);
+        assert_eq!(channels::send(b, 0), Ok(true));
+
+        // Each side waits on the other: one needs a value on `a` before it
+        // drains `b`, the other needs room in `b` before it feeds `a`.
+        let receiver = thread::spawn(move || {
+            let got = channels::receive_blocking(a);
+            channels::receive_blocking(b);
+            got
+        });
+        let sender = thread::spawn(move || {
+            let sent = channels::send(b, 1);
+            let _ = channels::send(a, 2);
+            sent
+        });
+        thread::sleep(Duration::from_millis(60));
+
+        let stuck: Vec<BlockedWait> = blocked().into_iter().filter(|w| w.channel == a || w.channel == b).collect();
+        assert_eq!(stuck.len(), 2, "{:?}", stuck);
+        let on_a = stuck.iter().find(|w| w.channel == a).unwrap();
+        let on_b = stuck.iter().find(|w| w.channel == b).unwrap();
+        assert_eq!((on_a.op, on_b.op), (WaitOp::Receive, WaitOp::Send));
+        assert_eq!(on_a.task, TaskKind::Host);
+        for w in &stuck {
+            assert!(w.waited >= Duration::from_millis(40) && w.waited < Duration::from_secs(10), "{:?}", w);
+        }
+
+        // `b` first: closing `a` first lets the receiver drain `b` and free
+        // the sender before `b` closes
+        channels::close(b);
+        channels::close(a);
+        assert_eq!(receiver.join().unwrap(), None);
+        assert!(sender.join().unwrap().is_err());
+        assert!(blocked().iter().all(|w| w.channel != a && w.channel != b));
+        channels::destroy(b);
+    }
+
+    #[test]
+    fn test_watchdog_reports_a_stall_once() {
+        let _serial = channels::test_serial();
+        let ch = channels::create(1);
+        let (tx, rx) = crossbeam_channel::unbounded();
+        start_watchdog(Duration::from_millis(20), move |snapshot| {
+            if snapshot.iter().any(|w| w.channel == ch) {
+                let _ = tx.send(snapshot.len());
+            }
+        });
+        let waiter = thread::spawn(move || {
+            let _guest = guest_scope();
+            channels::receive_blocking(ch)
+        });
+
+        assert!(rx.recv_timeout(Duration::from_secs(5)).is_ok());
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+        assert!(blocked().iter().any(|w| w.channel == ch && w.task == TaskKind::Guest));
+
+        assert!(stop_watchdog());
+        assert!(!stop_watchdog());
+        channels::close(ch);
+        assert_eq!(waiter.join().unwrap(), None);
+    }
+}
@@ -0,0 +1,99 @@
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Mutex, MutexGuard};
+
+// Error codes are carried as a "CODE: message" prefix on the error string so
+// they survive the String-based executor results and reach JS in `message`.
+
+/// A panic escaped from runtime code (a bug, not a guest failure).
+pub const ERR_INTERNAL: &str = "ERR_INTERNAL";
+
+/// The guest ran out of fuel.
+pub const ERR_OUT_OF_FUEL: &str = "ERR_OUT_OF_FUEL";
+
+/// The guest executed `unreachable`.
+pub const ERR_TRAP_UNREACHABLE: &str = "ERR_TRAP_UNREACHABLE";
+
+/// Any other guest trap (out-of-bounds access, division by zero, ...).
+pub const ERR_TRAP: &str = "ERR_TRAP";
+
+/// A batch's shared fuel budget was spent before the task could start.
+pub const ERR_FUEL_BUDGET: &str = "ERR_FUEL_BUDGET";
+
+/// The module has a start function and the caller disabled running it.
+pub const ERR_START_FUNCTION: &str = "ERR_START_FUNCTION";
+
+/// A channel send would push buffered values past the runtime-wide budget.
+pub const ERR_CHANNEL_BUDGET: &str = "ERR_CHANNEL_BUDGET";
+
+/// Input rejected at the napi boundary before any copying or spawning (a
+/// module, argument list or batch over its configured limit).
+pub const ERR_INVALID_INPUT: &str = "ERR_INVALID_INPUT";
+
+/// A handle (channel, context, module or pipeline id) issued before the last
+/// module reload or `reset_all_state`.
+pub const ERR_STALE_HANDLE: &str = "ERR_STALE_HANDLE";
+
+/// The module imports something outside the call's allowed imports.
+pub const ERR_FORBIDDEN_IMPORT: &str = "ERR_FORBIDDEN_IMPORT";
+
+/// A tag's quota (concurrent tasks or fuel per minute) would be exceeded.
+pub const ERR_QUOTA: &str = "ERR_QUOTA";
+
+/// The execution was interrupted by `reset_all_state`.
+pub const ERR_CANCELLED: &str = "ERR_CANCELLED";
+
+/// An epoch-metered execution ran past the configured epoch time limit.
+pub const ERR_TIME_LIMIT: &str = "ERR_TIME_LIMIT";
+
+/// The guest recursed past the engine's wasm stack limit.
+pub const ERR_STACK_OVERFLOW: &str = "ERR_STACK_OVERFLOW";
+
+/// A channel wait ran out of time before what it waited for arrived.
+pub const ERR_TIMEOUT: &str = "ERR_TIMEOUT";
+
+/// The runtime already has maxQueuedExecutions waiting to start.
+pub const ERR_OVERLOADED: &str = "ERR_OVERLOADED";
+
+/// An execution waited for a worker longer than its queue timeout.
+pub const ERR_QUEUE_TIMEOUT: &str = "ERR_QUEUE_TIMEOUT";
+
+/// Pinning another module would put more than maxPinnedCacheFraction of the
+/// module cache's capacity out of reach of eviction.
+pub const ERR_PIN_BUDGET: &str = "ERR_PIN_BUDGET";
+
+/// A replayed execution made a different host import call, or ended
+/// differently, than its recording.
+pub const ERR_REPLAY_DIVERGED: &str = "ERR_REPLAY_DIVERGED";
+
+/// An inline execution ran past its fuel cap; the async exec path has room.
+pub const ERR_INLINE_BUDGET: &str = "ERR_INLINE_BUDGET";
+
+pub fn coded(code: &str, msg: impl std::fmt::Display) -> String {
+    format!("{}: {}", code, msg)
+}
+
+/// Lock a registry mutex, recovering the guard if a previous holder panicked.
+/// Every registry guarded this way is updated with single insert/remove calls,
+/// so its state is consistent even after a panic mid-critical-section.
+pub fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Run `f`, turning a panic into an ERR_INTERNAL error carrying the panic message.
+pub fn catch_panic<T>(f: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => result,
+        Err(payload) => Err(coded(ERR_INTERNAL, format!("panic: {}", panic_message(&*payload)))),
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "unknown panic payload"
+    }
+}
@@ -0,0 +1,91 @@
+use crate::errors::{self, lock};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+// Exec ids name a long-running call so the host can stop it from outside.
+// The caller creates an id, passes it with the call and cancels it later;
+// the call claims the id while it runs and checks the flag between units of
+// work (the chunks of a chunked batch), so a cancel takes effect at the next
+// boundary rather than interrupting a guest mid-call. A claimed id is
+// released when its call finishes, after which cancelling it reports false.
+
+static EXECS: Lazy<Mutex<HashMap<u64, Arc<AtomicBool>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+static NEXT_EXEC: AtomicU64 = AtomicU64::new(0);
+
+pub fn create() -> u64 {
+    let id = NEXT_EXEC.fetch_add(1, Ordering::Relaxed);
+    lock(&EXECS).insert(id, Arc::new(AtomicBool::new(false)));
+    id
+}
+
+/// Mark `id` cancelled; false if no such id is live.
+pub fn cancel(id: u64) -> bool {
+    match lock(&EXECS).get(&id) {
+        Some(flag) => {
+            flag.store(true, Ordering::Release);
+            true
+        }
+        None => false,
+    }
+}
+
+/// A call's hold on its exec id; dropping it releases the id.
+pub struct Exec {
+    id: u64,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Exec {
+    pub fn cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for Exec {
+    fn drop(&mut self) {
+        lock(&EXECS).remove(&self.id);
+    }
+}
+
+/// Claim `id` for the call about to run. An id cancelled before the call
+/// starts is still claimed; the call sees it cancelled at its first check.
+pub fn claim(id: u64) -> Result<Exec, String> {
+    match lock(&EXECS).get(&id) {
+        Some(flag) => Ok(Exec { id, cancelled: Arc::clone(flag) }),
+        None => Err(errors::coded(errors::ERR_INVALID_INPUT, format!("no such exec id: {}", id))),
+    }
+}
+
+/// Cancel every live exec and forget the ids.
+pub fn reset() {
+    for (_, flag) in lock(&EXECS).drain() {
+        flag.store(true, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claimed_exec_sees_cancel_until_released() {
+        let id = create();
+        let exec = claim(id).unwrap();
+        assert!(!exec.cancelled());
+        assert!(cancel(id));
+        assert!(exec.cancelled());
+        drop(exec);
+        assert!(!cancel(id));
+        assert!(claim(id).err().unwrap().starts_with(errors::ERR_INVALID_INPUT));
+    }
+
+    #[test]
+    fn test_cancel_before_claim_carries_over() {
+        let id = create();
+        assert!(cancel(id));
+        assert!(claim(id).unwrap().cancelled());
+    }
+}
@@ -1,24 +1,259 @@
 use wasmtime::*;
 use once_cell::sync::Lazy;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
-use std::sync::Mutex;
-use crate::host_imports;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use crate::cancel_tokens::{self, Token};
+use crate::compiles;
+use crate::config;
+use crate::diagnostics;
+use crate::errors::{self, lock};
+use crate::host_imports::{self, HostState};
+use crate::recording::{self, Recording, ReplayFailed};
+use crate::shutdown;
+
+/// Fuel given to every execution unless the caller asks for something else.
+pub const DEFAULT_FUEL: u64 = 1_000_000_000;
+
+/// How often the unmetered engine's epoch advances, which is how often its
+/// guests check for a time limit or a reset
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+/// How a guest's execution is bounded. Fuel instrumentation costs every
+/// basic block, and the engine config is global, so unmetered modes run on a
+/// second engine compiled without it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Metering {
+    /// Count fuel: ERR_OUT_OF_FUEL past DEFAULT_FUEL, and fuel use is reported
+    #[default]
+    Fuel,
+    /// No fuel; a call fails with ERR_TIME_LIMIT once it has run for the
+    /// configured epoch time limit
+    Epoch,
+    /// No limits at all, for trusted modules. Reports no fuel use.
+    None,
+}
+
+impl Metering {
+    pub fn parse(metering: &str) -> Result<Metering, String> {
+        match metering {
+            "fuel" => Ok(Metering::Fuel),
+            "epoch" => Ok(Metering::Epoch),
+            "none" => Ok(Metering::None),
+            _ => Err(errors::coded(
+                errors::ERR_INVALID_INPUT,
+                format!("unknown metering '{}' (expected fuel, epoch or none)", metering),
+            )),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Metering::Fuel => "fuel",
+            Metering::Epoch => "epoch",
+            Metering::None => "none",
+        }
+    }
+
+    fn engine(self) -> &'static Engine {
+        match self {
+            Metering::Fuel => &WASM_ENGINE,
+            Metering::Epoch | Metering::None => &UNMETERED_ENGINE,
+        }
+    }
+}
 
 // Global cached Engine — Wasmtime's JIT pipeline initialization is expensive,
 // reuse the engine across all WASM executions.
 static WASM_ENGINE: Lazy<Engine> = Lazy::new(|| {
     let mut config = Config::new();
     config.consume_fuel(true);
+    // Lets `reset` and cancel tokens interrupt running guests; see `arm_store`
+    config.epoch_interruption(true);
     config.wasm_multi_value(true);
+    // Deep recursion traps with ERR_STACK_OVERFLOW well before the thread's
+    // native stack runs out
+    config.max_wasm_stack(crate::config::get().max_wasm_stack);
+    config.parallel_compilation(crate::config::get().parallel_compilation);
     Engine::new(&config).expect("failed to create WASM engine")
 });
 
+// The engine for Metering::Epoch and Metering::None, created on first use.
+// Its epoch ticks every EPOCH_TICK from a background thread; stores decide in
+// their deadline callback whether a tick ends the call (see `arm_store`).
+static UNMETERED_ENGINE: Lazy<Engine> = Lazy::new(|| {
+    let mut config = Config::new();
+    config.epoch_interruption(true);
+    config.wasm_multi_value(true);
+    config.max_wasm_stack(crate::config::get().max_wasm_stack);
+    config.parallel_compilation(crate::config::get().parallel_compilation);
+    let engine = Engine::new(&config).expect("failed to create unmetered WASM engine");
+    let ticking = engine.clone();
+    std::thread::Builder::new()
+        .name("tova-epoch".to_string())
+        .spawn(move || loop {
+            std::thread::sleep(EPOCH_TICK);
+            ticking.increment_epoch();
+        })
+        .expect("failed to spawn epoch thread");
+    engine
+});
+
+/// Whether either engine (or the compile pool) exists yet; engine settings
+/// are fixed after that.
+pub fn engines_started() -> bool {
+    Lazy::get(&WASM_ENGINE).is_some() || Lazy::get(&UNMETERED_ENGINE).is_some() || compiles::pool_started()
+}
+
+/// Create the engine `metering` runs on now instead of on first use; false
+/// if it existed.
+pub fn warm_engine(metering: Metering) -> bool {
+    let engine = match metering {
+        Metering::Fuel => &WASM_ENGINE,
+        Metering::Epoch | Metering::None => &UNMETERED_ENGINE,
+    };
+    let created = Lazy::get(engine).is_none();
+    Lazy::force(engine);
+    created
+}
+
+/// Bumped by `reset`; unmetered stores compare it against the value they
+/// were armed with to notice a reset, since their engine's epoch ticks anyway
+static RESETS: AtomicU64 = AtomicU64::new(0);
+
 // Module cache — avoids recompiling the same WASM bytes on repeated calls.
-// Keyed by a fast hash of the WASM bytes.
-static MODULE_CACHE: Lazy<Mutex<HashMap<u64, Module>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
+// Keyed by metering mode and a fast hash of the WASM bytes: each engine
+// compiles different code. Holds up to `module_cache_capacity` modules,
+// evicting the least recently used; pinned modules are never evicted, and
+// when only pinned ones are left the cache keeps the module just added past
+// its capacity and counts an overflow.
+static MODULE_CACHE: Lazy<Mutex<ModuleCache>> = Lazy::new(|| Mutex::new(ModuleCache::default()));
+
+type CacheKey = (Metering, u64);
+
+struct CacheEntry {
+    module: CompiledModule,
+    /// `ModuleCache::clock` at the last lookup or insert
+    last_used: u64,
+    pinned: bool,
+}
+
+#[derive(Default)]
+struct ModuleCache {
+    entries: HashMap<CacheKey, CacheEntry>,
+    clock: u64,
+    pinned: usize,
+    evictions: u64,
+    overflows: u64,
+}
+
+impl ModuleCache {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn get(&mut self, key: CacheKey) -> Option<CompiledModule> {
+        let now = self.tick();
+        let entry = self.entries.get_mut(&key)?;
+        entry.last_used = now;
+        Some(entry.module.clone())
+    }
+
+    fn insert(&mut self, module: CompiledModule, capacity: usize) {
+        let last_used = self.tick();
+        let key = (module.metering, module.hash);
+        let pinned = self.entries.get(&key).is_some_and(|e| e.pinned);
+        self.entries.insert(key, CacheEntry { module, last_used, pinned });
+        self.evict(capacity, key);
+    }
+
+    /// Drop least recently used unpinned modules other than `newest` until
+    /// the cache is within `capacity`.
+    fn evict(&mut self, capacity: usize, newest: CacheKey) {
+        while self.entries.len() > capacity {
+            let lru = self
+                .entries
+                .iter()
+                .filter(|(k, e)| !e.pinned && **k != newest)
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(k, _)| *k);
+            match lru {
+                Some(k) => {
+                    self.entries.remove(&k);
+                    self.evictions += 1;
+                }
+                None => {
+                    self.overflows += 1;
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Pin `module`, caching it again if it was evicted; false if it already
+    /// was pinned. Refuses with ERR_PIN_BUDGET once `max_pinned` modules are.
+    fn pin(&mut self, module: &CompiledModule, capacity: usize, max_pinned: usize) -> Result<bool, String> {
+        let key = (module.metering, module.hash);
+        if self.entries.get(&key).is_some_and(|e| e.pinned) {
+            return Ok(false);
+        }
+        if self.pinned >= max_pinned {
+            return Err(errors::coded(
+                errors::ERR_PIN_BUDGET,
+                format!("{} modules pinned, the most maxPinnedCacheFraction allows", self.pinned),
+            ));
+        }
+        let last_used = self.tick();
+        let entry = self.entries.entry(key).or_insert_with(|| CacheEntry { module: module.clone(), last_used, pinned: true });
+        entry.pinned = true;
+        entry.last_used = last_used;
+        self.pinned += 1;
+        self.evict(capacity, key);
+        Ok(true)
+    }
+
+    fn unpin(&mut self, key: CacheKey) -> bool {
+        match self.entries.get_mut(&key) {
+            Some(entry) if entry.pinned => {
+                entry.pinned = false;
+                self.pinned -= 1;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+pub struct CacheStats {
+    pub size: usize,
+    pub capacity: usize,
+    pub pinned: usize,
+    /// Unpinned modules dropped to stay within capacity
+    pub evictions: u64,
+    /// Inserts that found every cached module pinned and left the cache
+    /// over capacity
+    pub overflows: u64,
+    /// Cache misses that waited for a compilation of the same module
+    /// already in flight
+    pub deduplicated: u64,
+}
+
+pub fn cache_stats() -> CacheStats {
+    let cache = lock(&MODULE_CACHE);
+    CacheStats {
+        size: cache.entries.len(),
+        capacity: config::get().module_cache_capacity,
+        pinned: cache.pinned,
+        evictions: cache.evictions,
+        overflows: cache.overflows,
+        deduplicated: DEDUPLICATED_COMPILES.load(Ordering::Relaxed),
+    }
+}
 
 fn hash_wasm_bytes(bytes: &[u8]) -> u64 {
     let mut hasher = DefaultHasher::new();
@@ -26,34 +261,847 @@ fn hash_wasm_bytes(bytes: &[u8]) -> u64 {
     hasher.finish()
 }
 
-fn get_or_compile_module(wasm_bytes: &[u8]) -> Result<Module, String> {
-    let hash = hash_wasm_bytes(wasm_bytes);
-    {
-        let cache = MODULE_CACHE.lock().unwrap();
-        if let Some(module) = cache.get(&hash) {
-            return Ok(module.clone());
+// Precompiled module handles returned to JS — exec paths that receive a handle
+// skip hashing and copying the module bytes altogether.
+static MODULE_HANDLES: Lazy<Mutex<HashMap<u64, CompiledModule>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+static NEXT_HANDLE: AtomicU64 = AtomicU64::new(0);
+
+// Test hook: panic while MODULE_CACHE is held during a lookup of this hash
+// (0 = disabled), to exercise poison recovery without disturbing other tests
+#[cfg(test)]
+static PANIC_WHILE_CACHE_LOCKED: AtomicU64 = AtomicU64::new(0);
+
+/// A compiled module plus what wasmtime doesn't expose about it. `Module`
+/// and `Engine` are reference counted, and a blocking task works from its own
+/// clone of this and its store's clone of the engine rather than the statics,
+/// so shutdown order cannot free either under a running guest.
+#[derive(Clone)]
+pub struct CompiledModule {
+    module: Module,
+    /// The module declares a start function, which instantiation runs
+    has_start: bool,
+    /// Hash of the module bytes; keys the instance pools
+    hash: u64,
+    /// Mode (and so engine) the module was compiled for; every store
+    /// instantiating it uses the same one
+    metering: Metering,
+}
+
+/// Whether the (already validated) module bytes contain a start section.
+fn has_start_section(bytes: &[u8]) -> bool {
+    const START_SECTION: u8 = 8;
+    let mut pos = 8; // magic + version
+    while pos < bytes.len() {
+        let id = bytes[pos];
+        pos += 1;
+        let mut size = 0usize;
+        let mut shift = 0;
+        while let Some(&b) = bytes.get(pos) {
+            pos += 1;
+            size |= ((b & 0x7F) as usize) << shift;
+            shift += 7;
+            if b & 0x80 == 0 {
+                break;
+            }
         }
+        if id == START_SECTION {
+            return true;
+        }
+        pos = pos.saturating_add(size);
     }
-    let module = Module::new(&*WASM_ENGINE, wasm_bytes)
-        .map_err(|e| format!("compile: {}", e))?;
+    false
+}
+
+fn cached_module(metering: Metering, hash: u64) -> Option<CompiledModule> {
+    let mut cache = lock(&MODULE_CACHE);
+    #[cfg(test)]
+    if PANIC_WHILE_CACHE_LOCKED
+        .compare_exchange(hash, 0, Ordering::SeqCst, Ordering::SeqCst)
+        .is_ok()
     {
-        let mut cache = MODULE_CACHE.lock().unwrap();
-        cache.insert(hash, module.clone());
+        panic!("injected panic while holding MODULE_CACHE");
+    }
+    cache.get((metering, hash))
+}
+
+// Compilations in flight, so that tasks missing the cache on the same module
+// at once wait for one compilation rather than each queueing their own. The
+// compiling task inserts into the cache before it lands its flight.
+static IN_FLIGHT: Lazy<Mutex<HashMap<CacheKey, Arc<Flight>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Tasks that waited for another's compilation instead of compiling
+static DEDUPLICATED_COMPILES: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Default)]
+struct Flight {
+    result: Mutex<Option<Result<CompiledModule, String>>>,
+    landed: Condvar,
+}
+
+impl Flight {
+    fn wait(&self) -> Result<CompiledModule, String> {
+        let mut result = lock(&self.result);
+        loop {
+            if let Some(result) = &*result {
+                return result.clone();
+            }
+            result = self.landed.wait(result).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+
+/// Held by the compiling task; hands its result (or, if it panicked, an
+/// error) to the waiting ones and retires the flight.
+struct Landing {
+    key: CacheKey,
+    flight: Arc<Flight>,
+}
+
+impl Drop for Landing {
+    fn drop(&mut self) {
+        lock(&self.flight.result)
+            .get_or_insert_with(|| Err(errors::coded(errors::ERR_INTERNAL, "compile: the compiling task panicked")));
+        self.flight.landed.notify_all();
+        lock(&IN_FLIGHT).remove(&self.key);
+    }
+}
+
+fn compile_keyed(hash: u64, wasm_bytes: &[u8], metering: Metering) -> Result<CompiledModule, String> {
+    // Another task may have compiled the same bytes since the input was resolved
+    if let Some(module) = cached_module(metering, hash) {
+        return Ok(module);
+    }
+    let key = (metering, hash);
+    let flight = {
+        let mut in_flight = lock(&IN_FLIGHT);
+        // Again under the lock, or a flight that just landed is missed
+        if let Some(module) = cached_module(metering, hash) {
+            return Ok(module);
+        }
+        if let Some(flight) = in_flight.get(&key) {
+            let flight = flight.clone();
+            drop(in_flight);
+            DEDUPLICATED_COMPILES.fetch_add(1, Ordering::Relaxed);
+            return flight.wait();
+        }
+        let flight = Arc::new(Flight::default());
+        in_flight.insert(key, flight.clone());
+        flight
+    };
+    let landing = Landing { key, flight };
+    let result = compiles::admitted(hash, || Module::new(metering.engine(), wasm_bytes))
+        .map_err(|e| format!("compile: {}", e))
+        .map(|module| {
+            let compiled = CompiledModule { module, has_start: has_start_section(wasm_bytes), hash, metering };
+            let capacity = config::get().module_cache_capacity;
+            lock(&MODULE_CACHE).insert(compiled.clone(), capacity);
+            compiled
+        });
+    *lock(&landing.flight.result) = Some(result.clone());
+    result
+}
+
+/// Module bytes resolved at the napi boundary: either already compiled (cache
+/// hit or precompiled handle, nothing copied) or copied once into an Arc that
+/// every task of a batch shares.
+#[derive(Clone)]
+pub enum WasmInput {
+    Compiled(CompiledModule),
+    Bytes { hash: u64, bytes: Arc<Vec<u8>>, metering: Metering },
+}
+
+impl WasmInput {
+    fn compiled(&self) -> Result<CompiledModule, String> {
+        #[cfg(feature = "fault-injection")]
+        if crate::faults::hit(crate::faults::Kind::Compile) {
+            return Err(format!("compile: {}", crate::faults::INJECTED));
+        }
+        match self {
+            WasmInput::Compiled(compiled) => Ok(compiled.clone()),
+            WasmInput::Bytes { hash, bytes, metering } => compile_keyed(*hash, bytes, *metering),
+        }
+    }
+
+    /// The metering mode every execution of this input runs under
+    pub fn metering(&self) -> Metering {
+        match self {
+            WasmInput::Compiled(compiled) => compiled.metering,
+            WasmInput::Bytes { metering, .. } => *metering,
+        }
+    }
+}
+
+/// Hash the caller's borrowed bytes and copy them only on a module-cache miss.
+pub fn resolve_wasm(wasm_bytes: &[u8]) -> WasmInput {
+    resolve_wasm_with(wasm_bytes, Metering::Fuel)
+}
+
+/// `resolve_wasm` for a module that runs under `metering`.
+pub fn resolve_wasm_with(wasm_bytes: &[u8], metering: Metering) -> WasmInput {
+    let hash = hash_wasm_bytes(wasm_bytes);
+    match cached_module(metering, hash) {
+        Some(module) => WasmInput::Compiled(module),
+        None => WasmInput::Bytes { hash, bytes: Arc::new(wasm_bytes.to_vec()), metering },
+    }
+}
+
+/// Resolves the module bytes of a batch, hashing and copying each distinct
+/// buffer once. Tasks built from the same JS Buffer share the same view, so
+/// identity (address, length) is enough to detect repeats without rehashing.
+#[derive(Default)]
+pub struct WasmResolver {
+    seen: HashMap<(usize, usize), WasmInput>,
+    metering: Metering,
+}
+
+impl WasmResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A resolver whose modules run under `metering`
+    pub fn with_metering(metering: Metering) -> Self {
+        WasmResolver { seen: HashMap::new(), metering }
+    }
+
+    pub fn resolve(&mut self, wasm_bytes: &[u8]) -> WasmInput {
+        let key = (wasm_bytes.as_ptr() as usize, wasm_bytes.len());
+        self.seen
+            .entry(key)
+            .or_insert_with(|| resolve_wasm_with(wasm_bytes, self.metering))
+            .clone()
+    }
+}
+
+/// Compile (or fetch from the cache) and register a module handle that runs
+/// under `metering`. Blocking.
+pub fn precompile(wasm_bytes: &[u8], metering: Metering) -> Result<u64, String> {
+    let module = errors::catch_panic(|| resolve_wasm_with(wasm_bytes, metering).compiled())?;
+    let handle = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+    lock(&MODULE_HANDLES).insert(handle, module);
+    Ok(handle)
+}
+
+pub fn precompiled(handle: u64) -> Result<WasmInput, String> {
+    lock(&MODULE_HANDLES)
+        .get(&handle)
+        .map(|module| WasmInput::Compiled(module.clone()))
+        .ok_or_else(|| format!("no such module handle: {}", handle))
+}
+
+pub fn release_module(handle: u64) -> bool {
+    lock(&MODULE_HANDLES).remove(&handle).is_some()
+}
+
+/// Compile `wasm` if needed and keep it out of reach of cache eviction until
+/// `unpin`; false if it already was pinned. Blocking.
+pub fn pin(wasm: &WasmInput) -> Result<bool, String> {
+    let module = errors::catch_panic(|| wasm.compiled())?;
+    let config = config::get();
+    lock(&MODULE_CACHE).pin(&module, config.module_cache_capacity, config.max_pinned_modules())
+}
+
+/// Compile each module the cache lacks and, with `pin`, pin it. A pin
+/// refused with ERR_PIN_BUDGET fails the call, leaving earlier modules
+/// pinned. Returns how many modules were not cached when resolved. Blocking.
+pub fn prefetch(wasms: &[WasmInput], pin: bool) -> Result<usize, String> {
+    for wasm in wasms {
+        if pin {
+            self::pin(wasm)?;
+        } else {
+            errors::catch_panic(|| wasm.compiled())?;
+        }
+    }
+    Ok(wasms.iter().filter(|w| matches!(w, WasmInput::Bytes { .. })).count())
+}
+
+/// Make a pinned module evictable again; false if it was not pinned.
+pub fn unpin(wasm: &WasmInput) -> bool {
+    let key = match wasm {
+        WasmInput::Compiled(compiled) => (compiled.metering, compiled.hash),
+        WasmInput::Bytes { hash, metering, .. } => (*metering, *hash),
+    };
+    lock(&MODULE_CACHE).unpin(key)
+}
+
+/// Make every running guest check its cancel token now rather than at the
+/// next tick; guests without a cancelled one carry on.
+pub fn interrupt_guests() {
+    for engine in [&WASM_ENGINE, &UNMETERED_ENGINE] {
+        if let Some(engine) = Lazy::get(engine) {
+            engine.increment_epoch();
+        }
     }
-    Ok(module)
 }
 
-pub fn exec_wasm_sync(wasm_bytes: &[u8], func_name: &str, args: &[i64]) -> Result<i64, String> {
-    let engine = &*WASM_ENGINE;
-    let module = get_or_compile_module(wasm_bytes)?;
-    let mut store = Store::new(engine, ());
-    store.set_fuel(1_000_000_000).map_err(|e| format!("fuel error: {}", e))?;
-    let instance = Instance::new(&mut store, &module, &[])
-        .map_err(|e| format!("WASM instantiation error: {}", e))?;
+/// Interrupt every running guest for a shutdown: like `reset`, but compiled
+/// modules stay cached, since the guests unwinding may still be using them.
+pub fn interrupt_for_shutdown() {
+    RESETS.fetch_add(1, Ordering::AcqRel);
+    interrupt_guests();
+}
+
+/// Keep both engines for the rest of the process whatever happens to the
+/// statics, for a shutdown that could not stop every guest in time.
+pub fn leak_engines() {
+    for engine in [&WASM_ENGINE, &UNMETERED_ENGINE] {
+        if let Some(engine) = Lazy::get(engine) {
+            std::mem::forget(engine.clone());
+        }
+    }
+}
+
+/// Interrupt every running guest (its call fails with ERR_CANCELLED) and
+/// forget all compiled modules and handles.
+pub fn reset() {
+    RESETS.fetch_add(1, Ordering::AcqRel);
+    WASM_ENGINE.increment_epoch();
+    if let Some(engine) = Lazy::get(&UNMETERED_ENGINE) {
+        engine.increment_epoch();
+    }
+    let mut cache = lock(&MODULE_CACHE);
+    cache.entries.clear();
+    cache.pinned = 0;
+    drop(cache);
+    lock(&MODULE_HANDLES).clear();
+    let mut pools = lock(&INSTANCE_POOLS);
+    POOL_EPOCH.fetch_add(1, Ordering::AcqRel);
+    pools.clear();
+}
+
+pub struct ModuleInfo {
+    /// "module.name" for each import
+    pub imports: Vec<String>,
+    /// Exported function names
+    pub exports: Vec<String>,
+    pub has_start: bool,
+}
+
+pub fn inspect(wasm: &WasmInput) -> Result<ModuleInfo, String> {
+    errors::catch_panic(|| {
+        let compiled = wasm.compiled()?;
+        Ok(ModuleInfo {
+            imports: compiled
+                .module
+                .imports()
+                .map(|i| format!("{}.{}", i.module(), i.name()))
+                .collect(),
+            exports: compiled
+                .module
+                .exports()
+                .filter(|e| matches!(e.ty(), ExternType::Func(_)))
+                .map(|e| e.name().to_string())
+                .collect(),
+            has_start: compiled.has_start,
+        })
+    })
+}
+
+// --- Instantiation ---
+
+/// Refuse to instantiate a module with a start function when the caller
+/// disabled it: wasmtime always runs the start function while instantiating,
+/// so there is no way to skip it.
+fn check_start(compiled: &CompiledModule, run_start: bool) -> Result<(), String> {
+    if compiled.has_start && !run_start {
+        return Err(errors::coded(
+            errors::ERR_START_FUNCTION,
+            "module has a start function and runStart is false",
+        ));
+    }
+    Ok(())
+}
+
+/// Instantiation error with traps classified. A trap at this point comes from
+/// the start function (or from initializing memory or tables), so say so
+/// rather than reporting a generic instantiation failure.
+fn instantiation_error(e: wasmtime::Error, compiled: &CompiledModule) -> String {
+    let context = if compiled.has_start { "in start function" } else { "during instantiation" };
+    if e.downcast_ref::<TimeLimitExceeded>().is_some() {
+        return time_limit_error();
+    }
+    if e.downcast_ref::<TokenCancelled>().is_some() {
+        return cancel_tokens::cancelled_error();
+    }
+    if e.downcast_ref::<Killed>().is_some() {
+        return killed_error();
+    }
+    if let Some(ReplayFailed(message)) = e.downcast_ref() {
+        return message.clone();
+    }
+    match e.downcast_ref::<Trap>() {
+        Some(Trap::Interrupt) => cancelled_error(),
+        Some(Trap::OutOfFuel) => errors::coded(errors::ERR_OUT_OF_FUEL, format!("{}: {}", context, e)),
+        Some(Trap::StackOverflow) => errors::coded(errors::ERR_STACK_OVERFLOW, format!("{}: {}", context, e)),
+        Some(Trap::UnreachableCodeReached) => {
+            errors::coded(errors::ERR_TRAP_UNREACHABLE, format!("{}: {}", context, e))
+        }
+        Some(_) => errors::coded(errors::ERR_TRAP, format!("{}: {}", context, e)),
+        None => format!("WASM instantiation error: {}", e),
+    }
+}
+
+fn cancelled_error() -> String {
+    if shutdown::shutting_down() {
+        return errors::coded(errors::ERR_CANCELLED, "execution cancelled: the runtime is shutting down");
+    }
+    errors::coded(errors::ERR_CANCELLED, "execution cancelled by a runtime reset")
+}
+
+/// Raised from a store's deadline callback once its cancel token is cancelled
+#[derive(Debug)]
+struct TokenCancelled;
+
+impl std::fmt::Display for TokenCancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("cancelled by its cancel token")
+    }
+}
+
+impl std::error::Error for TokenCancelled {}
+
+/// Raised from a store's deadline callback once its execution was killed
+/// through the watchdog
+#[derive(Debug)]
+struct Killed;
+
+impl std::fmt::Display for Killed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("killed through the watchdog")
+    }
+}
+
+impl std::error::Error for Killed {}
+
+fn killed_error() -> String {
+    errors::coded(errors::ERR_CANCELLED, "execution killed by watchdogKill")
+}
+
+/// Raised from an epoch-metered store's deadline callback
+#[derive(Debug)]
+struct TimeLimitExceeded;
+
+impl std::fmt::Display for TimeLimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("epoch time limit exceeded")
+    }
+}
+
+impl std::error::Error for TimeLimitExceeded {}
+
+fn time_limit_error() -> String {
+    errors::coded(
+        errors::ERR_TIME_LIMIT,
+        format!("execution ran past the epoch time limit ({:?})", config::get().epoch_time_limit),
+    )
+}
+
+/// Error for a failed call, reporting an interrupt from `reset`, the call's
+/// cancel token or the watchdog as ERR_CANCELLED, an epoch timeout as ERR_TIME_LIMIT
+/// and exhausting the wasm stack as ERR_STACK_OVERFLOW. A replay that could
+/// not go on reports its own error.
+fn call_error(what: &str, e: wasmtime::Error) -> String {
+    if e.downcast_ref::<TimeLimitExceeded>().is_some() {
+        return time_limit_error();
+    }
+    if e.downcast_ref::<TokenCancelled>().is_some() {
+        return cancel_tokens::cancelled_error();
+    }
+    if e.downcast_ref::<Killed>().is_some() {
+        return killed_error();
+    }
+    if let Some(ReplayFailed(message)) = e.downcast_ref() {
+        return message.clone();
+    }
+    match e.downcast_ref::<Trap>() {
+        Some(Trap::Interrupt) => cancelled_error(),
+        Some(Trap::OutOfFuel) => errors::coded(errors::ERR_OUT_OF_FUEL, format!("{}: {}", what, e)),
+        Some(Trap::StackOverflow) => errors::coded(errors::ERR_STACK_OVERFLOW, format!("{}: {}", what, e)),
+        _ => format!("{}: {}", what, e),
+    }
+}
+
+thread_local! {
+    static TASK: Cell<Option<HostState>> = const { Cell::new(None) };
+    static CANCEL: RefCell<Option<Arc<Token>>> = const { RefCell::new(None) };
+}
+
+/// Stores armed on this thread while the scope lives stop when `token` is
+/// cancelled, and refuse to arm once it is.
+pub struct CancelScope(Option<Arc<Token>>);
+
+impl Drop for CancelScope {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        CANCEL.with(|cancel| *cancel.borrow_mut() = previous);
+    }
+}
+
+pub fn cancel_scope(token: Option<Arc<Token>>) -> CancelScope {
+    CancelScope(CANCEL.with(|cancel| cancel.replace(token)))
+}
+
+/// The cancel token of the scope this thread runs in, if any.
+pub fn cancel_token() -> Option<Arc<Token>> {
+    CANCEL.with(|cancel| cancel.borrow().clone())
+}
+
+/// Puts calls on this thread at `index` of a `count`-task batch until dropped.
+pub struct TaskScope(Option<HostState>);
+
+impl Drop for TaskScope {
+    fn drop(&mut self) {
+        TASK.with(|task| task.set(self.0));
+    }
+}
+
+/// Calls made on this thread while the scope lives see task_index() ==
+/// `index` and task_count() == `count`. A batch call made inside it numbers
+/// its tasks from `index`, so a chunk of a larger batch reports each task's
+/// position in the whole batch.
+pub fn task_scope(index: usize, count: usize) -> TaskScope {
+    let task = HostState { task_index: index as i64, task_count: count as i64, ..HostState::default() };
+    TaskScope(TASK.with(|current| current.replace(Some(task))))
+}
+
+/// Task 0 of 1 outside any scope.
+fn current_task() -> HostState {
+    TASK.with(Cell::get).unwrap_or_default()
+}
+
+/// Where a batch call's `n` tasks start: the scope's task, or task 0 of `n`
+/// outside one.
+fn batch_start(n: usize) -> HostState {
+    TASK.with(Cell::get).unwrap_or(HostState { task_index: 0, task_count: n as i64, ..HostState::default() })
+}
+
+/// A store on `metering`'s engine, armed for one call (see `arm_store`).
+fn new_store(metering: Metering, fuel: u64) -> Result<Store<HostState>, String> {
+    let mut store = Store::new(metering.engine(), current_task());
+    arm_store(&mut store, metering, fuel)?;
+    Ok(store)
+}
+
+/// Prepare a store for a call. A fuel store gets `fuel`; every store gets an
+/// epoch deadline one tick ahead. `reset` and cancelling a token bump the
+/// epoch, and the unmetered engine's also ticks on its own, so at every tick
+/// a store checks whether a reset happened since it was armed, whether the
+/// scope's cancel token was cancelled, whether the watchdog killed the
+/// execution running on its thread and, for Metering::Epoch, whether the
+/// time limit has passed. Once the runtime is shutting down no store is
+/// armed at all.
+fn arm_store(store: &mut Store<HostState>, metering: Metering, fuel: u64) -> Result<(), String> {
+    store.set_epoch_deadline(1);
+    // After the deadline is set and before the shutdown check, so a shutdown
+    // racing this either fails the check or bumps both RESETS and the epoch
+    // past what the store was armed with
+    let armed_at = RESETS.load(Ordering::Acquire);
+    if shutdown::shutting_down() {
+        return Err(cancelled_error());
+    }
+    // After the deadline is set, so a cancel racing this either fails the
+    // check or bumps the epoch past the deadline
+    let token = cancel_token();
+    if let Some(token) = &token {
+        token.check()?;
+    }
+    store.data_mut().fuel_granted = 0;
+    if metering == Metering::Fuel {
+        store.set_fuel(fuel).map_err(|e| format!("fuel error: {}", e))?;
+    }
+    let deadline = (metering == Metering::Epoch).then(|| Instant::now() + config::get().epoch_time_limit);
+    store.epoch_deadline_callback(move |_| {
+        if RESETS.load(Ordering::Acquire) != armed_at {
+            return Err(Trap::Interrupt.into());
+        }
+        if token.as_ref().is_some_and(|token| token.is_cancelled()) {
+            return Err(TokenCancelled.into());
+        }
+        if diagnostics::killed() {
+            return Err(Killed.into());
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Err(TimeLimitExceeded.into());
+        }
+        Ok(UpdateDeadline::Continue(1))
+    });
+    Ok(())
+}
+
+/// Fuel a call burnt since its store was armed with `fuel`, counting what
+/// request_fuel added on top; zero for unmetered stores.
+fn fuel_burnt(store: &Store<HostState>, fuel: u64) -> u64 {
+    let armed = fuel.saturating_add(store.data().fuel_granted);
+    store.get_fuel().map_or(0, |left| armed.saturating_sub(left))
+}
+
+pub fn exec_wasm_sync(wasm: &WasmInput, func_name: &str, args: &[i64]) -> Result<i64, String> {
+    exec_wasm_sync_with(wasm, func_name, args, true)
+}
+
+/// `exec_wasm_sync`, failing with ERR_START_FUNCTION instead of running the
+/// module's start function when `run_start` is false.
+pub fn exec_wasm_sync_with(wasm: &WasmInput, func_name: &str, args: &[i64], run_start: bool) -> Result<i64, String> {
+    errors::catch_panic(|| exec_wasm_sync_inner(wasm, func_name, args, run_start))
+}
+
+fn exec_wasm_sync_inner(wasm: &WasmInput, func_name: &str, args: &[i64], run_start: bool) -> Result<i64, String> {
+    let mut store = new_store(wasm.metering(), DEFAULT_FUEL)?;
+    exec_in_store(&mut store, wasm, func_name, args, run_start)
+}
+
+/// A task's result and the fuel it burnt, including fuel spent by the start
+/// function and by a call that trapped.
+#[derive(Debug)]
+pub struct Metered {
+    pub result: Result<i64, String>,
+    pub fuel_used: u64,
+}
+
+pub fn exec_wasm_metered(wasm: &WasmInput, func_name: &str, args: &[i64]) -> Metered {
+    exec_wasm_metered_with(wasm, func_name, args, true)
+}
+
+/// `exec_wasm_sync_with`, reporting the fuel burnt.
+pub fn exec_wasm_metered_with(wasm: &WasmInput, func_name: &str, args: &[i64], run_start: bool) -> Metered {
+    let mut fuel_used = 0;
+    let result = errors::catch_panic(|| {
+        let mut store = new_store(wasm.metering(), DEFAULT_FUEL)?;
+        let result = exec_in_store(&mut store, wasm, func_name, args, run_start);
+        fuel_used = fuel_burnt(&store, DEFAULT_FUEL);
+        result
+    });
+    Metered { result, fuel_used }
+}
+
+/// Fuel shared by every task of a batch. A task checks the balance before it
+/// starts and is charged what it burnt once it finishes, so tasks running at
+/// the same time can overshoot the budget by at most their own fuel limits.
+pub struct FuelBudget {
+    remaining: AtomicI64,
+}
+
+impl FuelBudget {
+    pub fn new(total: u64) -> Self {
+        FuelBudget { remaining: AtomicI64::new(total.min(i64::MAX as u64) as i64) }
+    }
+
+    /// Fuel left; negative once running tasks overshot the budget.
+    pub fn remaining(&self) -> i64 {
+        self.remaining.load(Ordering::Acquire)
+    }
+
+    /// Run the task unless the budget is spent, in which case it fails with
+    /// ERR_FUEL_BUDGET without being executed.
+    pub fn run(&self, wasm: &WasmInput, func_name: &str, args: &[i64]) -> Metered {
+        if self.remaining() <= 0 {
+            return Metered {
+                result: Err(errors::coded(errors::ERR_FUEL_BUDGET, "shared fuel budget exhausted")),
+                fuel_used: 0,
+            };
+        }
+        let metered = exec_wasm_metered(wasm, func_name, args);
+        self.remaining.fetch_sub(metered.fuel_used as i64, Ordering::AcqRel);
+        metered
+    }
+}
+
+// --- Instance pools ---
+
+// Ready-to-call instances of hot modules, keyed by module hash. A pooled call
+// checks one out (or instantiates a fresh one on a miss), refills its fuel and
+// hands it back afterwards; instances beyond the pool bound are dropped.
+// Guests keep their globals and memory between pooled calls, so `reset_every`
+// bounds how long state carries over, and a call that fails retires its
+// instance rather than risk reusing half-updated state.
+
+/// How a pooled exec reuses instances.
+#[derive(Clone, Copy, Debug)]
+pub struct PoolOptions {
+    /// Idle instances kept per module
+    pub size: usize,
+    /// Re-instantiate after this many calls; None trusts the guest to be pure
+    pub reset_every: Option<u64>,
+}
+
+struct PooledInstance {
+    store: Store<HostState>,
+    instance: Instance,
+    uses: u64,
+    /// POOL_EPOCH at instantiation; instances checked out across a `reset`
+    /// are dropped instead of returned
+    epoch: u64,
+}
+
+/// Idle instances per module, under the module cache's key
+type InstancePools = HashMap<CacheKey, Vec<PooledInstance>>;
+
+static INSTANCE_POOLS: Lazy<Mutex<InstancePools>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+static POOL_EPOCH: AtomicU64 = AtomicU64::new(0);
+static POOL_HITS: AtomicU64 = AtomicU64::new(0);
+static POOL_MISSES: AtomicU64 = AtomicU64::new(0);
+static POOL_RESETS: AtomicU64 = AtomicU64::new(0);
+
+pub struct PoolStats {
+    /// Pooled calls that reused an idle instance
+    pub hits: u64,
+    /// Pooled calls that had to instantiate
+    pub misses: u64,
+    /// Instances retired by `reset_every`
+    pub resets: u64,
+    /// Idle instances across all pools
+    pub size: usize,
+}
+
+pub fn pool_stats() -> PoolStats {
+    let size = lock(&INSTANCE_POOLS).values().map(Vec::len).sum();
+    PoolStats {
+        hits: POOL_HITS.load(Ordering::Relaxed),
+        misses: POOL_MISSES.load(Ordering::Relaxed),
+        resets: POOL_RESETS.load(Ordering::Relaxed),
+        size,
+    }
+}
+
+/// `exec_wasm_sync_with`, running on a pooled instance of the module and
+/// reporting the fuel the call burnt (instantiating a pooled instance, start
+/// function included, is not charged to any call).
+pub fn exec_pooled_metered(wasm: &WasmInput, func_name: &str, args: &[i64], run_start: bool, pool: PoolOptions) -> Metered {
+    let mut fuel_used = 0;
+    let result = errors::catch_panic(|| exec_pooled_inner(wasm, func_name, args, run_start, pool, DEFAULT_FUEL, &mut fuel_used));
+    Metered { result, fuel_used }
+}
+
+fn exec_pooled_inner(
+    wasm: &WasmInput,
+    func_name: &str,
+    args: &[i64],
+    run_start: bool,
+    pool: PoolOptions,
+    fuel: u64,
+    fuel_used: &mut u64,
+) -> Result<i64, String> {
+    let compiled = wasm.compiled()?;
+    check_start(&compiled, run_start)?;
+    let key = (compiled.metering, compiled.hash);
+    let idle = lock(&INSTANCE_POOLS).get_mut(&key).and_then(Vec::pop);
+    let mut pooled = match idle {
+        Some(pooled) => {
+            POOL_HITS.fetch_add(1, Ordering::Relaxed);
+            pooled
+        }
+        None => {
+            POOL_MISSES.fetch_add(1, Ordering::Relaxed);
+            let epoch = POOL_EPOCH.load(Ordering::Acquire);
+            let mut store = new_store(compiled.metering, fuel)?;
+            let instance = instantiate(&mut store, &compiled)?;
+            PooledInstance { store, instance, uses: 0, epoch }
+        }
+    };
+    // Full fuel and a deadline relative to the current engine epoch, as a
+    // fresh store would have
+    arm_store(&mut pooled.store, compiled.metering, fuel)?;
+    *pooled.store.data_mut() = current_task();
+    let running = diagnostics::begin_execution(compiled.hash, func_name);
+    let result = call_instance(&mut pooled.store, &pooled.instance, func_name, args);
+    drop(running);
+    *fuel_used = fuel_burnt(&pooled.store, fuel);
+    pooled.uses += 1;
+
+    // A call that failed may have left the instance in any state
+    let value = result?;
+    if pool.reset_every.is_some_and(|n| pooled.uses >= n) {
+        POOL_RESETS.fetch_add(1, Ordering::Relaxed);
+        return Ok(value);
+    }
+    let mut pools = lock(&INSTANCE_POOLS);
+    if pooled.epoch == POOL_EPOCH.load(Ordering::Acquire) {
+        let idle = pools.entry(key).or_default();
+        if idle.len() < pool.size {
+            idle.push(pooled);
+        }
+    }
+    Ok(value)
+}
+
+// --- Inline calls ---
+
+// A call made straight on the JS thread, skipping the trip through the
+// blocking pool, for guests so short that the trip would dominate. The event
+// loop waits for the call, so its fuel is a hard cap: request_fuel is denied,
+// and a call that would run longer fails with ERR_INLINE_BUDGET rather than
+// hold up everything else. Compiling would block just the same, so only
+// modules already compiled for the fuel engine qualify.
+
+/// Run one call on this thread with at most `fuel`, on a pooled instance if
+/// `pool` is given. Fails with ERR_INVALID_INPUT for a module that is not
+/// compiled yet or not fuel-metered.
+pub fn exec_inline(wasm: &WasmInput, func_name: &str, args: &[i64], fuel: u64, pool: Option<PoolOptions>) -> Result<i64, String> {
+    let WasmInput::Compiled(compiled) = wasm else {
+        return Err(errors::coded(
+            errors::ERR_INVALID_INPUT,
+            "inline execution needs a compiled module; compile it first or run it once with execWasm",
+        ));
+    };
+    if compiled.metering != Metering::Fuel {
+        return Err(errors::coded(
+            errors::ERR_INVALID_INPUT,
+            format!("inline execution needs a fuel-metered module, not '{}'", compiled.metering.name()),
+        ));
+    }
+    let _task = TaskScope(TASK.with(|task| task.replace(Some(HostState { inline: true, ..current_task() }))));
+    let result = errors::catch_panic(|| match pool {
+        Some(pool) => exec_pooled_inner(wasm, func_name, args, true, pool, fuel, &mut 0),
+        None => {
+            let mut store = new_store(Metering::Fuel, fuel)?;
+            exec_in_store(&mut store, wasm, func_name, args, true)
+        }
+    });
+    result.map_err(|e| match e.strip_prefix(errors::ERR_OUT_OF_FUEL) {
+        Some(_) => errors::coded(
+            errors::ERR_INLINE_BUDGET,
+            format!("'{}' ran past the inline fuel cap ({}); call it with execWasm instead", func_name, fuel),
+        ),
+        None => e,
+    })
+}
+
+/// Instantiate with the imports every guest may bind: the task and fuel
+/// imports.
+fn instantiate(store: &mut Store<HostState>, compiled: &CompiledModule) -> Result<Instance, String> {
+    #[cfg(feature = "fault-injection")]
+    if crate::faults::hit(crate::faults::Kind::Instantiate) {
+        return Err(format!("WASM instantiation error: {}", crate::faults::INJECTED));
+    }
+    let mut linker = Linker::new(compiled.metering.engine());
+    host_imports::add_task_imports(&mut linker)?;
+    host_imports::add_fuel_imports(&mut linker)?;
+    host_imports::add_shared_data_imports(&mut linker)?;
+    linker
+        .instantiate(store, &compiled.module)
+        .map_err(|e| instantiation_error(e, compiled))
+}
+
+fn exec_in_store(store: &mut Store<HostState>, wasm: &WasmInput, func_name: &str, args: &[i64], run_start: bool) -> Result<i64, String> {
+    let compiled = wasm.compiled()?;
+    check_start(&compiled, run_start)?;
+    let _running = diagnostics::begin_execution(compiled.hash, func_name);
+    let instance = instantiate(store, &compiled)?;
+    call_instance(store, &instance, func_name, args)
+}
+
+fn call_instance(store: &mut Store<HostState>, instance: &Instance, func_name: &str, args: &[i64]) -> Result<i64, String> {
+    #[cfg(feature = "fault-injection")]
+    if crate::faults::hit(crate::faults::Kind::ExecTrap) {
+        return Err(errors::coded(errors::ERR_TRAP, format!("WASM execution error: {}", crate::faults::INJECTED)));
+    }
     let func = instance
-        .get_func(&mut store, func_name)
+        .get_func(&mut *store, func_name)
         .ok_or_else(|| format!("function '{}' not found", func_name))?;
-    let func_ty = func.ty(&store);
+    let func_ty = func.ty(&*store);
     let wasm_args: Vec<Val> = args
         .iter()
         .zip(func_ty.params())
@@ -64,8 +1112,8 @@ pub fn exec_wasm_sync(wasm_bytes: &[u8], func_name: &str, args: &[i64]) -> Resul
         })
         .collect();
     let mut results = vec![Val::I64(0)];
-    func.call(&mut store, &wasm_args, &mut results)
-        .map_err(|e| format!("WASM execution error: {}", e))?;
+    func.call(&mut *store, &wasm_args, &mut results)
+        .map_err(|e| call_error("WASM execution error", e))?;
     match results[0] {
         Val::I64(v) => Ok(v),
         Val::I32(v) => Ok(v as i64),
@@ -73,222 +1121,380 @@ pub fn exec_wasm_sync(wasm_bytes: &[u8], func_name: &str, args: &[i64]) -> Resul
     }
 }
 
-pub fn exec_many_shared(
-    wasm_bytes: &[u8],
-    tasks: Vec<(String, Vec<i64>)>,
-) -> Vec<Result<i64, String>> {
-    let engine = &*WASM_ENGINE;
-    let module = match get_or_compile_module(wasm_bytes) {
-        Ok(m) => m,
-        Err(e) => {
-            return tasks.iter().map(|_| Err(e.clone())).collect();
-        }
-    };
-    tasks
-        .into_iter()
-        .map(|(func_name, args)| {
-            let mut store = Store::new(engine, ());
-            store.set_fuel(1_000_000_000).map_err(|e| format!("fuel error: {}", e))?;
-            let instance = Instance::new(&mut store, &module, &[])
-                .map_err(|e| format!("instantiate: {}", e))?;
-            let func = instance
-                .get_func(&mut store, &func_name)
-                .ok_or_else(|| format!("func '{}' not found", func_name))?;
-            let func_ty = func.ty(&store);
-            let wasm_args: Vec<Val> = args
-                .iter()
-                .zip(func_ty.params())
-                .map(|(&v, ty)| match ty {
-                    ValType::I32 => Val::I32(v as i32),
-                    ValType::I64 => Val::I64(v),
-                    _ => Val::I64(v),
-                })
-                .collect();
-            let mut results = vec![Val::I64(0)];
-            func.call(&mut store, &wasm_args, &mut results)
-                .map_err(|e| format!("exec: {}", e))?;
-            match results[0] {
-                Val::I64(v) => Ok(v),
-                Val::I32(v) => Ok(v as i64),
-                _ => Err("unexpected return type".to_string()),
-            }
-        })
-        .collect()
-}
-
 /// Optimized batch execution: reuse a single Store+Instance for all tasks in a chunk.
 /// Uses TypedFunc for known signatures to avoid Val boxing overhead.
 /// Safe for pure WASM functions with no mutable globals or linear memory side effects.
 pub fn exec_many_shared_reuse(
-    wasm_bytes: &[u8],
+    wasm: &WasmInput,
     tasks: Vec<(String, Vec<i64>)>,
 ) -> Vec<Result<i64, String>> {
+    exec_many_shared_reuse_with(wasm, tasks, true)
+}
+
+/// `exec_many_shared_reuse` for functions taking and returning floats.
+pub fn exec_many_shared_reuse_f64(
+    wasm: &WasmInput,
+    tasks: Vec<(String, Vec<f64>)>,
+) -> Vec<Result<f64, String>> {
+    exec_many_shared_reuse_with(wasm, tasks, true)
+}
+
+/// Either of the above; `typed: false` forces the dynamic path, for
+/// benchmarking the typed one against it.
+pub(crate) fn exec_many_shared_reuse_with<V: BatchValue>(
+    wasm: &WasmInput,
+    tasks: Vec<(String, Vec<V>)>,
+    typed: bool,
+) -> Vec<Result<V, String>> {
+    let n = tasks.len();
+    errors::catch_panic(|| Ok(exec_many_shared_reuse_inner(wasm, tasks, typed)))
+        .unwrap_or_else(|e| vec![Err(e); n])
+}
+
+/// A value a reused-instance batch passes in and gets back: i64 for integer
+/// signatures, f64 for float ones.
+pub(crate) trait BatchValue: Copy + Sized {
+    /// How errors name the type
+    const KIND: &'static str;
+    /// Whether a parameter or result of type `ty` converts to and from this
+    fn fits(ty: &ValType) -> bool;
+    fn to_val(self, ty: &ValType) -> Val;
+    fn from_val(val: &Val) -> Option<Self>;
+    /// Run every task through a TypedFunc when the signature is a known one.
+    fn typed_batch(
+        store: &mut Store<HostState>,
+        instance: &Instance,
+        tasks: &[(String, Vec<Self>)],
+        func_name: &str,
+        nargs: usize,
+    ) -> Option<Vec<Result<Self, String>>>;
+}
+
+/// Expands to one TypedFunc attempt per signature, in order, returning the
+/// batch's results from the first that the function has. Each signature is
+/// `nargs: type => [argument indices]`, and takes and returns `type`.
+macro_rules! typed_signatures {
+    (@ty $i:tt $t:ty) => { $t };
+    ($store:ident, $instance:ident, $tasks:ident, $func_name:ident, $nargs:ident, $out:ty;
+     $($n:literal: $t:ty => [$($i:tt)*];)*) => {
+        $(
+            if $nargs == $n {
+                if let Ok(f) = $instance.get_typed_func::<($(typed_signatures!(@ty $i $t),)*), $t>(&mut *$store, $func_name) {
+                    let first = $store.data().task_index;
+                    return Some($tasks
+                        .iter()
+                        .enumerate()
+                        // `_args` goes unused in the no-argument arm
+                        .map(|(i, (_, _args))| {
+                            $store.data_mut().task_index = first + i as i64;
+                            f.call(&mut *$store, ($(_args[$i] as $t,)*))
+                                .map(|v| v as $out)
+                                .map_err(|e| call_error("exec", e))
+                        })
+                        .collect());
+                }
+            }
+        )*
+    };
+}
+
+impl BatchValue for i64 {
+    const KIND: &'static str = "integer";
+
+    fn fits(ty: &ValType) -> bool {
+        matches!(ty, ValType::I32 | ValType::I64)
+    }
+
+    fn to_val(self, ty: &ValType) -> Val {
+        match ty {
+            ValType::I32 => Val::I32(self as i32),
+            _ => Val::I64(self),
+        }
+    }
+
+    fn from_val(val: &Val) -> Option<i64> {
+        match *val {
+            Val::I64(v) => Some(v),
+            Val::I32(v) => Some(v as i64),
+            _ => None,
+        }
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    fn typed_batch(
+        store: &mut Store<HostState>,
+        instance: &Instance,
+        tasks: &[(String, Vec<i64>)],
+        func_name: &str,
+        nargs: usize,
+    ) -> Option<Vec<Result<i64, String>>> {
+        typed_signatures!(store, instance, tasks, func_name, nargs, i64;
+            0: i32 => [];
+            1: i32 => [0];
+            1: i64 => [0];
+            2: i32 => [0 1];
+            2: i64 => [0 1];
+            3: i32 => [0 1 2];
+            3: i64 => [0 1 2];
+            4: i32 => [0 1 2 3];
+            4: i64 => [0 1 2 3];
+        );
+        None
+    }
+}
+
+impl BatchValue for f64 {
+    const KIND: &'static str = "float";
+
+    fn fits(ty: &ValType) -> bool {
+        matches!(ty, ValType::F32 | ValType::F64)
+    }
+
+    fn to_val(self, ty: &ValType) -> Val {
+        match ty {
+            ValType::F32 => Val::F32((self as f32).to_bits()),
+            _ => Val::F64(self.to_bits()),
+        }
+    }
+
+    fn from_val(val: &Val) -> Option<f64> {
+        match *val {
+            Val::F64(bits) => Some(f64::from_bits(bits)),
+            Val::F32(bits) => Some(f32::from_bits(bits) as f64),
+            _ => None,
+        }
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    fn typed_batch(
+        store: &mut Store<HostState>,
+        instance: &Instance,
+        tasks: &[(String, Vec<f64>)],
+        func_name: &str,
+        nargs: usize,
+    ) -> Option<Vec<Result<f64, String>>> {
+        typed_signatures!(store, instance, tasks, func_name, nargs, f64;
+            1: f64 => [0];
+            2: f64 => [0 1];
+            3: f64 => [0 1 2];
+        );
+        None
+    }
+}
+
+fn exec_many_shared_reuse_inner<V: BatchValue>(
+    wasm: &WasmInput,
+    tasks: Vec<(String, Vec<V>)>,
+    typed: bool,
+) -> Vec<Result<V, String>> {
     if tasks.is_empty() {
         return vec![];
     }
 
-    let engine = &*WASM_ENGINE;
-    let module = match get_or_compile_module(wasm_bytes) {
+    let compiled = match wasm.compiled() {
         Ok(m) => m,
         Err(e) => {
             return tasks.iter().map(|_| Err(e.clone())).collect();
         }
     };
 
-    let mut store = Store::new(engine, ());
-    if let Err(e) = store.set_fuel(1_000_000_000) {
-        let err = format!("fuel error: {}", e);
-        return tasks.iter().map(|_| Err(err.clone())).collect();
-    }
-    let instance = match Instance::new(&mut store, &module, &[]) {
+    // The whole batch is one entry, named after its first function
+    let _running = diagnostics::begin_execution(compiled.hash, &tasks[0].0);
+    let mut store = match new_store(compiled.metering, DEFAULT_FUEL) {
+        Ok(store) => store,
+        Err(err) => return tasks.iter().map(|_| Err(err.clone())).collect(),
+    };
+    // The typed and dynamic paths below number their tasks from here
+    *store.data_mut() = batch_start(tasks.len());
+    let instance = match instantiate(&mut store, &compiled) {
         Ok(i) => i,
-        Err(e) => {
-            let err = format!("instantiate: {}", e);
-            return tasks.iter().map(|_| Err(err.clone())).collect();
-        }
+        Err(err) => return tasks.iter().map(|_| Err(err.clone())).collect(),
     };
 
-    // Detect signature from the first task's function to pick the fast typed path
-    let first_func_name = &tasks[0].0;
-    let first_nargs = tasks[0].1.len();
-
-    // Try typed fast paths: (i32,i32)->i32, (i32)->i32, (i64)->i64, ()->i32
-    // These avoid Val allocation/boxing per call.
-    if let Some(results) = try_typed_batch(&mut store, &instance, &tasks, first_func_name, first_nargs) {
-        return results;
+    // The typed fast path calls one function with one arity, so it only
+    // applies when every task shares them; mixed batches go dynamic.
+    let func_name = &tasks[0].0;
+    let nargs = tasks[0].1.len();
+    let uniform = tasks.iter().all(|(f, args)| f == func_name && args.len() == nargs);
+    if typed && uniform {
+        if let Some(results) = V::typed_batch(&mut store, &instance, &tasks, func_name, nargs) {
+            return results;
+        }
     }
 
-    // Fallback: dynamic Val-based path for unknown signatures
-    let mut func_cache: HashMap<String, (Func, Vec<ValType>)> = HashMap::new();
+    // Fallback: dynamic Val-based path for unknown signatures. Each distinct
+    // function is looked up once, with its param types, and tasks refer to
+    // it by index; the argument and result buffers are reused across calls.
+    // A function whose parameters or single result V cannot carry fails
+    // every task calling it with one error, before any call.
+    let mut slots: HashMap<&str, usize> = HashMap::new();
+    let mut funcs: Vec<Result<(Func, Vec<ValType>), String>> = Vec::new();
+    let task_funcs: Vec<usize> = tasks
+        .iter()
+        .map(|(func_name, _)| {
+            *slots.entry(func_name.as_str()).or_insert_with(|| {
+                funcs.push(match instance.get_func(&mut store, func_name) {
+                    Some(f) => {
+                        let ty = f.ty(&store);
+                        let params: Vec<ValType> = ty.params().collect();
+                        let results: Vec<ValType> = ty.results().collect();
+                        if params.iter().all(V::fits) && results.len() == 1 && V::fits(&results[0]) {
+                            Ok((f, params))
+                        } else {
+                            Err(format!("func '{}' does not take and return {} values", func_name, V::KIND))
+                        }
+                    }
+                    None => Err(format!("func '{}' not found", func_name)),
+                });
+                funcs.len() - 1
+            })
+        })
+        .collect();
+    let first = store.data().task_index;
+    let mut wasm_args: Vec<Val> = Vec::new();
+    let mut results = [Val::I64(0)];
 
     tasks
-        .into_iter()
-        .map(|(func_name, args)| {
-            let (func, param_types) = if let Some(cached) = func_cache.get(&func_name) {
-                (cached.0, cached.1.clone())
-            } else {
-                let f = instance
-                    .get_func(&mut store, &func_name)
-                    .ok_or_else(|| format!("func '{}' not found", func_name))?;
-                let param_types: Vec<ValType> = f.ty(&store).params().collect();
-                func_cache.insert(func_name.clone(), (f, param_types.clone()));
-                (f, param_types)
-            };
-
-            let wasm_args: Vec<Val> = args
-                .iter()
-                .zip(param_types.iter())
-                .map(|(&v, ty)| match ty {
-                    ValType::I32 => Val::I32(v as i32),
-                    ValType::I64 => Val::I64(v),
-                    _ => Val::I64(v),
-                })
-                .collect();
+        .iter()
+        .zip(task_funcs)
+        .enumerate()
+        .map(|(i, ((_, args), slot))| {
+            store.data_mut().task_index = first + i as i64;
+            let (func, param_types) = funcs[slot].as_ref().map_err(Clone::clone)?;
 
-            let mut results = vec![Val::I64(0)];
+            wasm_args.clear();
+            wasm_args.extend(args.iter().zip(param_types).map(|(&v, ty)| v.to_val(ty)));
             func.call(&mut store, &wasm_args, &mut results)
-                .map_err(|e| format!("exec: {}", e))?;
+                .map_err(|e| call_error("exec", e))?;
 
-            match results[0] {
-                Val::I64(v) => Ok(v),
-                Val::I32(v) => Ok(v as i64),
-                _ => Err("unexpected return type".to_string()),
-            }
+            V::from_val(&results[0]).ok_or_else(|| "unexpected return type".to_string())
         })
         .collect()
 }
 
-/// Try to use TypedFunc for common WASM signatures.
-/// Returns None if the signature doesn't match any fast path.
-fn try_typed_batch(
-    store: &mut Store<()>,
-    instance: &Instance,
-    tasks: &[(String, Vec<i64>)],
+/// Fail with ERR_FORBIDDEN_IMPORT if the module imports anything `allowed`
+/// does not list, before it is instantiated.
+pub fn check_imports(wasm: &WasmInput, allowed: &[String]) -> Result<(), String> {
+    errors::catch_panic(|| host_imports::check_imports(&wasm.compiled()?.module, allowed))
+}
+
+/// Run with the channel host imports. `context` scopes the channel ids the
+/// guest can use (see contexts.rs); None gives it the global registry. With
+/// `allowed`, the module may import only what it lists, and only those
+/// imports are linked.
+pub fn exec_wasm_with_channels(
+    wasm: &WasmInput,
     func_name: &str,
-    nargs: usize,
-) -> Option<Vec<Result<i64, String>>> {
-    // (i32, i32) -> i32  — e.g. add(a, b)
-    if nargs == 2 {
-        if let Ok(f) = instance.get_typed_func::<(i32, i32), i32>(&mut *store, func_name) {
-            let mut results = Vec::with_capacity(tasks.len());
-            for (_, args) in tasks {
-                results.push(
-                    f.call(&mut *store, (args[0] as i32, args[1] as i32))
-                        .map(|v| v as i64)
-                        .map_err(|e| format!("exec: {}", e))
-                );
-            }
-            return Some(results);
-        }
-        if let Ok(f) = instance.get_typed_func::<(i64, i64), i64>(&mut *store, func_name) {
-            let mut results = Vec::with_capacity(tasks.len());
-            for (_, args) in tasks {
-                results.push(
-                    f.call(&mut *store, (args[0], args[1]))
-                        .map_err(|e| format!("exec: {}", e))
-                );
-            }
-            return Some(results);
-        }
-    }
+    args: &[i64],
+    context: Option<u64>,
+    allowed: Option<&[String]>,
+) -> Result<i64, String> {
+    errors::catch_panic(|| exec_wasm_with_channels_inner(wasm, func_name, args, context, allowed))
+}
 
-    // (i32) -> i32  — e.g. fib(n)
-    if nargs == 1 {
-        if let Ok(f) = instance.get_typed_func::<i32, i32>(&mut *store, func_name) {
-            let mut results = Vec::with_capacity(tasks.len());
-            for (_, args) in tasks {
-                results.push(
-                    f.call(&mut *store, args[0] as i32)
-                        .map(|v| v as i64)
-                        .map_err(|e| format!("exec: {}", e))
-                );
-            }
-            return Some(results);
-        }
-        if let Ok(f) = instance.get_typed_func::<i64, i64>(&mut *store, func_name) {
-            let mut results = Vec::with_capacity(tasks.len());
-            for (_, args) in tasks {
-                results.push(
-                    f.call(&mut *store, args[0])
-                        .map_err(|e| format!("exec: {}", e))
-                );
-            }
-            return Some(results);
-        }
+fn exec_wasm_with_channels_inner(
+    wasm: &WasmInput,
+    func_name: &str,
+    args: &[i64],
+    context: Option<u64>,
+    allowed: Option<&[String]>,
+) -> Result<i64, String> {
+    let compiled = wasm.compiled()?;
+    if let Some(allowed) = allowed {
+        host_imports::check_imports(&compiled.module, allowed)?;
     }
+    let mut store = new_store(compiled.metering, DEFAULT_FUEL)?;
+    call_with_channels(&mut store, &compiled, func_name, args, context, allowed)
+}
 
-    // () -> i32
-    if nargs == 0 {
-        if let Ok(f) = instance.get_typed_func::<(), i32>(&mut *store, func_name) {
-            let mut results = Vec::with_capacity(tasks.len());
-            for _ in tasks {
-                results.push(
-                    f.call(&mut *store, ())
-                        .map(|v| v as i64)
-                        .map_err(|e| format!("exec: {}", e))
-                );
-            }
-            return Some(results);
+/// `exec_wasm_with_channels`, recording every host import call the guest
+/// makes (see recording.rs). The recording names the module by `module`, its
+/// `recording::module_hash`, and carries the call's result or error.
+pub fn exec_wasm_recorded(
+    wasm: &WasmInput,
+    module: u64,
+    func_name: &str,
+    args: &[i64],
+    context: Option<u64>,
+    allowed: Option<&[String]>,
+) -> Recording {
+    let session = recording::record();
+    let mut fuel_used = 0;
+    let outcome = errors::catch_panic(|| {
+        let compiled = wasm.compiled()?;
+        if let Some(allowed) = allowed {
+            host_imports::check_imports(&compiled.module, allowed)?;
         }
+        let mut store = new_store(compiled.metering, DEFAULT_FUEL)?;
+        let result = call_with_channels(&mut store, &compiled, func_name, args, context, allowed);
+        fuel_used = fuel_burnt(&store, DEFAULT_FUEL);
+        result
+    });
+    let (calls, truncated) = session.finish();
+    Recording {
+        module,
+        func: func_name.to_string(),
+        args: args.to_vec(),
+        metering: wasm.metering(),
+        calls,
+        truncated,
+        fuel_used,
+        outcome,
     }
+}
 
-    None
+/// Run `recording`'s call again on `wasm`, whose `recording::module_hash`
+/// is `module`, answering every host import from the recording rather than
+/// touching channels or any other host state. Fails with
+/// ERR_REPLAY_DIVERGED at the first import call, or the end of the call,
+/// that differs from the recording; otherwise ends as the recorded call did.
+pub fn replay(wasm: &WasmInput, module: u64, recording: &Recording) -> Result<i64, String> {
+    if module != recording.module {
+        return Err(errors::coded(
+            errors::ERR_INVALID_INPUT,
+            format!("the recording is of module {:016x}, not {:016x}", recording.module, module),
+        ));
+    }
+    let session = recording::replay(recording);
+    let outcome = errors::catch_panic(|| {
+        let compiled = wasm.compiled()?;
+        let mut store = new_store(compiled.metering, DEFAULT_FUEL)?;
+        // Every import is answered from the recording, so link them all
+        let all: Vec<String> = [host_imports::CHANNEL_IMPORTS, host_imports::NATIVE_IMPORTS]
+            .concat()
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        call_with_channels(&mut store, &compiled, &recording.func, &recording.args, None, Some(&all))
+    });
+    session.finish()?;
+    recording::check_outcome(recording, outcome)
 }
 
-pub fn exec_wasm_with_channels(wasm_bytes: &[u8], func_name: &str, args: &[i64]) -> Result<i64, String> {
-    let engine = &*WASM_ENGINE;
-    let module = get_or_compile_module(wasm_bytes)?;
-    let mut linker = Linker::new(engine);
-    host_imports::add_channel_imports(&mut linker)?;
-    let mut store = Store::new(engine, ());
-    store.set_fuel(1_000_000_000).map_err(|e| format!("fuel error: {}", e))?;
+/// Instantiate `compiled` in `store` with the task, fuel, shared data and
+/// channel imports, and the native ones `allowed` lists, and call `func_name`.
+fn call_with_channels(
+    store: &mut Store<HostState>,
+    compiled: &CompiledModule,
+    func_name: &str,
+    args: &[i64],
+    context: Option<u64>,
+    allowed: Option<&[String]>,
+) -> Result<i64, String> {
+    let _running = diagnostics::begin_execution(compiled.hash, func_name);
+    let mut linker = Linker::new(compiled.metering.engine());
+    host_imports::add_task_imports(&mut linker)?;
+    host_imports::add_fuel_imports(&mut linker)?;
+    host_imports::add_shared_data_imports(&mut linker)?;
+    host_imports::add_channel_imports(&mut linker, context, allowed)?;
+    host_imports::add_native_imports(&mut linker, allowed)?;
     let instance = linker
-        .instantiate(&mut store, &module)
-        .map_err(|e| format!("WASM instantiation error: {}", e))?;
+        .instantiate(&mut *store, &compiled.module)
+        .map_err(|e| instantiation_error(e, compiled))?;
     let func = instance
-        .get_func(&mut store, func_name)
+        .get_func(&mut *store, func_name)
         .ok_or_else(|| format!("function '{}' not found", func_name))?;
-    let func_ty = func.ty(&store);
+    let func_ty = func.ty(&*store);
     let wasm_args: Vec<Val> = args
         .iter()
         .zip(func_ty.params())
@@ -299,11 +1505,524 @@ pub fn exec_wasm_with_channels(wasm_bytes: &[u8], func_name: &str, args: &[i64])
         })
         .collect();
     let mut results = vec![Val::I64(0)];
-    func.call(&mut store, &wasm_args, &mut results)
-        .map_err(|e| format!("WASM exec error: {}", e))?;
+    func.call(&mut *store, &wasm_args, &mut results)
+        .map_err(|e| call_error("WASM exec error", e))?;
     match results[0] {
         Val::I64(v) => Ok(v),
         Val::I32(v) => Ok(v as i64),
         _ => Err("unexpected return type".to_string()),
     }
 }
+
+/// Long-lived worker for a pipeline stage: one Store+Instance reused for every
+/// value the worker processes, with fuel refilled before each call.
+pub struct StageWorker {
+    store: Store<HostState>,
+    func: StageFunc,
+    fuel: u64,
+    /// Module hash and function name, for the execution registry
+    module: u64,
+    func_name: String,
+}
+
+enum StageFunc {
+    I64(TypedFunc<i64, i64>),
+    I32(TypedFunc<i32, i32>),
+    Dynamic(Func, ValType),
+}
+
+impl StageWorker {
+    pub fn new(wasm: &WasmInput, func_name: &str, fuel: u64) -> Result<Self, String> {
+        errors::catch_panic(|| Self::new_inner(wasm, func_name, fuel))
+    }
+
+    fn new_inner(wasm: &WasmInput, func_name: &str, fuel: u64) -> Result<Self, String> {
+        let compiled = wasm.compiled()?;
+        let mut linker = Linker::new(compiled.metering.engine());
+        host_imports::add_task_imports(&mut linker)?;
+        host_imports::add_fuel_imports(&mut linker)?;
+        host_imports::add_shared_data_imports(&mut linker)?;
+        host_imports::add_channel_imports(&mut linker, None, None)?;
+        let mut store = new_store(compiled.metering, fuel)?;
+        let instance = linker
+            .instantiate(&mut store, &compiled.module)
+            .map_err(|e| instantiation_error(e, &compiled))?;
+
+        // Stages are unary by construction, so only the unary typed paths apply
+        let func = if let Ok(f) = instance.get_typed_func::<i64, i64>(&mut store, func_name) {
+            StageFunc::I64(f)
+        } else if let Ok(f) = instance.get_typed_func::<i32, i32>(&mut store, func_name) {
+            StageFunc::I32(f)
+        } else {
+            let f = instance
+                .get_func(&mut store, func_name)
+                .ok_or_else(|| format!("function '{}' not found", func_name))?;
+            let param = f.ty(&store).params().next().unwrap_or(ValType::I64);
+            StageFunc::Dynamic(f, param)
+        };
+
+        Ok(StageWorker { store, func, fuel, module: compiled.hash, func_name: func_name.to_string() })
+    }
+
+    pub fn call(&mut self, value: i64) -> Result<i64, String> {
+        errors::catch_panic(|| self.call_inner(value))
+    }
+
+    fn call_inner(&mut self, value: i64) -> Result<i64, String> {
+        let _running = diagnostics::begin_execution(self.module, &self.func_name);
+        self.store.set_fuel(self.fuel).map_err(|e| format!("fuel error: {}", e))?;
+        self.store.data_mut().fuel_granted = 0;
+        match &self.func {
+            StageFunc::I64(f) => f
+                .call(&mut self.store, value)
+                .map_err(|e| call_error("exec", e)),
+            StageFunc::I32(f) => f
+                .call(&mut self.store, value as i32)
+                .map(|v| v as i64)
+                .map_err(|e| call_error("exec", e)),
+            StageFunc::Dynamic(f, ty) => {
+                let arg = match ty {
+                    ValType::I32 => Val::I32(value as i32),
+                    _ => Val::I64(value),
+                };
+                let mut results = vec![Val::I64(0)];
+                f.call(&mut self.store, &[arg], &mut results)
+                    .map_err(|e| call_error("exec", e))?;
+                match results[0] {
+                    Val::I64(v) => Ok(v),
+                    Val::I32(v) => Ok(v as i64),
+                    _ => Err("unexpected return type".to_string()),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testsupport::{self as fixtures, TrapKind};
+
+    #[test]
+    fn test_panic_while_cache_locked_does_not_brick_executor() {
+        let wasm = fixtures::tagged(fixtures::add_module(), 1);
+        let hash = hash_wasm_bytes(&wasm);
+        // Force the uncompiled path so the exec itself takes the cache lock
+        let input = WasmInput::Bytes { hash, bytes: Arc::new(wasm), metering: Metering::Fuel };
+        PANIC_WHILE_CACHE_LOCKED.store(hash, Ordering::SeqCst);
+        let err = exec_wasm_sync(&input, "add", &[1, 2]).unwrap_err();
+        assert!(err.starts_with(errors::ERR_INTERNAL), "{}", err);
+        assert!(err.contains("injected panic"), "{}", err);
+        assert!(MODULE_CACHE.is_poisoned());
+
+        assert_eq!(exec_wasm_sync(&input, "add", &[1, 2]), Ok(3));
+        let batch = exec_many_shared_reuse(&input, vec![("add".to_string(), vec![2, 3])]);
+        assert_eq!(batch, vec![Ok(5)]);
+    }
+
+    #[test]
+    fn test_resolver_shares_one_copy_per_batch() {
+        let wasm = fixtures::tagged(fixtures::add_module(), 2);
+        let mut resolver = WasmResolver::new();
+        let a = resolver.resolve(&wasm);
+        let b = resolver.resolve(&wasm);
+        match (&a, &b) {
+            (WasmInput::Bytes { bytes: x, .. }, WasmInput::Bytes { bytes: y, .. }) => {
+                assert!(Arc::ptr_eq(x, y));
+                // resolver + two tasks
+                assert_eq!(Arc::strong_count(x), 3);
+            }
+            _ => panic!("expected a cache miss"),
+        }
+        assert_eq!(exec_wasm_sync(&a, "add", &[20, 22]), Ok(42));
+
+        // Once compiled, resolution borrows without copying
+        assert!(matches!(resolve_wasm(&wasm), WasmInput::Compiled(_)));
+    }
+
+    #[test]
+    fn test_precompiled_handle() {
+        let handle = precompile(&fixtures::tagged(fixtures::add_module(), 3), Metering::Fuel).unwrap();
+        let input = precompiled(handle).unwrap();
+        assert_eq!(exec_wasm_sync(&input, "add", &[5, 6]), Ok(11));
+        assert!(release_module(handle));
+        assert!(precompiled(handle).is_err());
+        assert!(!release_module(handle));
+    }
+
+    /// A module compiled outside the global cache, for a private ModuleCache
+    fn uncached(tag: u8) -> CompiledModule {
+        let wasm = fixtures::tagged(fixtures::add_module(), tag);
+        let module = Module::new(Metering::Fuel.engine(), &wasm).unwrap();
+        CompiledModule { module, has_start: false, hash: hash_wasm_bytes(&wasm), metering: Metering::Fuel }
+    }
+
+    #[test]
+    fn test_pinned_module_survives_eviction_until_unpinned() {
+        let mut cache = ModuleCache::default();
+        let core = uncached(200);
+        let key = (core.metering, core.hash);
+        cache.insert(core.clone(), 4);
+        assert_eq!(cache.pin(&core, 4, 2), Ok(true));
+        assert_eq!(cache.pin(&core, 4, 2), Ok(false));
+
+        // A storm of one-shot modules cycles every unpinned slot
+        let peers: Vec<CompiledModule> = (201..213).map(uncached).collect();
+        for peer in &peers {
+            cache.insert(peer.clone(), 4);
+        }
+        assert!(cache.get(key).is_some());
+        assert_eq!((cache.entries.len(), cache.evictions, cache.overflows), (4, 9, 0));
+        assert!(cache.get((Metering::Fuel, peers[0].hash)).is_none());
+
+        // Unpinned, it is the least recently used and goes first
+        assert!(cache.unpin(key));
+        assert!(!cache.unpin(key));
+        cache.get((Metering::Fuel, peers[9].hash));
+        cache.get((Metering::Fuel, peers[10].hash));
+        cache.get((Metering::Fuel, peers[11].hash));
+        cache.insert(uncached(213), 4);
+        assert!(cache.get(key).is_none());
+        assert_eq!(cache.pinned, 0);
+    }
+
+    #[test]
+    fn test_pin_budget_and_full_pinned_cache() {
+        let mut cache = ModuleCache::default();
+        let modules: Vec<CompiledModule> = (220..224).map(uncached).collect();
+        // A pin budget of 2 (half of 4) refuses the third pin
+        assert_eq!(cache.pin(&modules[0], 4, 2), Ok(true));
+        assert_eq!(cache.pin(&modules[1], 4, 2), Ok(true));
+        let err = cache.pin(&modules[2], 4, 2).unwrap_err();
+        assert!(err.starts_with(errors::ERR_PIN_BUDGET), "{}", err);
+        assert_eq!(cache.pinned, 2);
+
+        // With the budget at the whole capacity, inserts past it overflow
+        // instead of evicting a pinned module
+        assert_eq!(cache.pin(&modules[2], 3, 3), Ok(true));
+        cache.insert(modules[3].clone(), 3);
+        assert_eq!((cache.entries.len(), cache.evictions, cache.overflows), (4, 0, 1));
+    }
+
+    #[test]
+    fn test_start_function_trap_is_classified() {
+        let input = resolve_wasm(&fixtures::start_module(Some(TrapKind::Unreachable)));
+        let err = exec_wasm_sync(&input, "run", &[]).unwrap_err();
+        assert!(err.starts_with(errors::ERR_TRAP_UNREACHABLE), "{}", err);
+        assert!(err.contains("in start function"), "{}", err);
+
+        let batch = exec_many_shared_reuse(&input, vec![("run".to_string(), vec![])]);
+        assert!(batch[0].as_ref().unwrap_err().starts_with(errors::ERR_TRAP_UNREACHABLE));
+    }
+
+    #[test]
+    fn test_run_start_false_rejects_start_modules() {
+        let input = resolve_wasm(&fixtures::start_module(None));
+        assert_eq!(exec_wasm_sync(&input, "run", &[]), Ok(1));
+        let err = exec_wasm_sync_with(&input, "run", &[], false).unwrap_err();
+        assert!(err.starts_with(errors::ERR_START_FUNCTION), "{}", err);
+        // Modules without a start function are unaffected
+        assert_eq!(exec_wasm_sync_with(&resolve_wasm(&fixtures::add_module()), "add", &[1, 2], false), Ok(3));
+    }
+
+    #[test]
+    fn test_inspect_reports_start_function() {
+        let info = inspect(&resolve_wasm(&fixtures::start_module(None))).unwrap();
+        assert!(info.has_start);
+        assert_eq!(info.exports, vec!["run".to_string()]);
+        assert!(info.imports.is_empty());
+        assert!(!inspect(&resolve_wasm(&fixtures::tagged(fixtures::add_module(), 4))).unwrap().has_start);
+    }
+
+    fn idle_instances(wasm: &WasmInput) -> usize {
+        let compiled = wasm.compiled().unwrap();
+        lock(&INSTANCE_POOLS).get(&(compiled.metering, compiled.hash)).map_or(0, Vec::len)
+    }
+
+    #[test]
+    fn test_batch_calls_number_tasks_from_their_scope() {
+        let input = resolve_wasm(&fixtures::task_module());
+        let batch = |func: &str| (0..4).map(|_| (func.to_string(), vec![])).collect::<Vec<_>>();
+        assert_eq!(exec_many_shared_reuse(&input, batch("index_i32")), vec![Ok(0), Ok(1), Ok(2), Ok(3)]);
+        assert_eq!(exec_many_shared_reuse(&input, batch("count")), vec![Ok(4); 4]);
+        {
+            let _task = task_scope(100, 1000);
+            assert_eq!(exec_many_shared_reuse(&input, batch("index")), vec![Ok(100), Ok(101), Ok(102), Ok(103)]);
+            assert_eq!(exec_wasm_sync(&input, "count", &[]), Ok(1000));
+            let pool = PoolOptions { size: 1, reset_every: None };
+            assert_eq!(exec_pooled_metered(&input, "index", &[], true, pool).result, Ok(100));
+            let _inner = task_scope(7, 1000);
+            // The pooled instance picks up the new task on reuse
+            assert_eq!(exec_pooled_metered(&input, "index", &[], true, pool).result, Ok(7));
+        }
+        assert_eq!(exec_wasm_sync(&input, "index", &[]), Ok(0));
+        assert_eq!(exec_wasm_sync(&input, "count", &[]), Ok(1));
+    }
+
+    #[test]
+    fn test_pooled_exec_reuses_instances() {
+        let input = resolve_wasm(&fixtures::tagged(fixtures::add_module(), 5));
+        let pool = PoolOptions { size: 4, reset_every: None };
+        let hits = pool_stats().hits;
+        for i in 0..10 {
+            assert_eq!(exec_pooled_metered(&input, "add", &[i, 1], true, pool).result, Ok(i + 1));
+        }
+        // One miss to fill the pool, then every call is a hit
+        assert!(pool_stats().hits - hits >= 9);
+        assert_eq!(idle_instances(&input), 1);
+    }
+
+    #[test]
+    fn test_pool_state_carries_over_only_without_reset() {
+        let input = resolve_wasm(&fixtures::tagged(fixtures::counter_module(), 6));
+        let fresh = PoolOptions { size: 4, reset_every: Some(1) };
+        for _ in 0..5 {
+            assert_eq!(exec_pooled_metered(&input, "next", &[], true, fresh).result, Ok(1));
+        }
+        assert_eq!(idle_instances(&input), 0);
+
+        // Trust-pure mode keeps the instance, globals and all
+        let pure = PoolOptions { size: 4, reset_every: None };
+        let seen: Vec<i64> = (0..3).map(|_| exec_pooled_metered(&input, "next", &[], true, pure).result.unwrap()).collect();
+        assert_eq!(seen, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_pool_never_exceeds_its_bound() {
+        let input = resolve_wasm(&fixtures::tagged(fixtures::add_module(), 7));
+        let pool = PoolOptions { size: 2, reset_every: None };
+        std::thread::scope(|s| {
+            for t in 0..8 {
+                let input = &input;
+                s.spawn(move || {
+                    for i in 0..50 {
+                        assert_eq!(exec_pooled_metered(input, "add", &[t, i], true, pool).result, Ok(t + i));
+                        assert!(idle_instances(input) <= 2);
+                    }
+                });
+            }
+        });
+        assert!(idle_instances(&input) <= 2);
+    }
+
+    #[test]
+    fn test_fuel_budget_stops_starting_tasks_once_spent() {
+        let input = resolve_wasm(&fixtures::add_module());
+        let per_task = exec_wasm_metered(&input, "add", &[1, 2]).fuel_used;
+        assert!(per_task > 0);
+
+        // Covers four tasks fully; the fifth starts with fuel left and overshoots
+        let budget = FuelBudget::new(5 * per_task - 1);
+        let results: Vec<Metered> = (0..10).map(|i| budget.run(&input, "add", &[i, 1])).collect();
+        for (i, m) in results.iter().enumerate().take(5) {
+            assert_eq!(m.result, Ok(i as i64 + 1));
+            assert_eq!(m.fuel_used, per_task);
+        }
+        for m in &results[5..] {
+            assert!(m.result.as_ref().unwrap_err().starts_with(errors::ERR_FUEL_BUDGET));
+            assert_eq!(m.fuel_used, 0);
+        }
+        assert_eq!(budget.remaining(), -1);
+    }
+    #[test]
+    fn test_allowed_imports_gate_linking() {
+        let _serial = crate::channels::test_serial();
+        let input = resolve_wasm(&fixtures::send_once_module());
+        let ch = crate::channels::create(1);
+        let channels = host_imports::Capabilities { channels: true, ..Default::default() }.imports();
+        assert_eq!(exec_wasm_with_channels(&input, "send_once", &[ch as i64, 7], None, Some(&channels)), Ok(0));
+        assert_eq!(crate::channels::receive(ch), Some(7));
+
+        let receive_only = vec!["tova.chan_receive".to_string()];
+        let err = exec_wasm_with_channels(&input, "send_once", &[ch as i64, 8], None, Some(&receive_only)).unwrap_err();
+        assert!(err.starts_with(errors::ERR_FORBIDDEN_IMPORT), "{}", err);
+        assert!(err.contains("tova.chan_send"), "{}", err);
+        assert!(check_imports(&input, &[]).is_err());
+        assert_eq!(check_imports(&resolve_wasm(&fixtures::add_module()), &[]), Ok(()));
+        crate::channels::destroy(ch);
+    }
+
+    #[test]
+    fn test_metering_modes_agree_and_cache_separately() {
+        let wasm = fixtures::tagged(fixtures::add_module(), 9);
+        let fuel = resolve_wasm_with(&wasm, Metering::Fuel);
+        assert_eq!(exec_wasm_sync(&fuel, "add", &[1, 2]), Ok(3));
+        // Compiling for the fuel engine does not satisfy the unmetered one
+        assert!(matches!(resolve_wasm_with(&wasm, Metering::None), WasmInput::Bytes { .. }));
+
+        let mut results = Vec::new();
+        for metering in [Metering::Fuel, Metering::Epoch, Metering::None] {
+            let input = resolve_wasm_with(&fixtures::fib_module(), metering);
+            assert_eq!(input.metering(), metering);
+            let metered = exec_wasm_metered(&input, "fib", &[90]);
+            assert_eq!(metered.fuel_used > 0, metering == Metering::Fuel, "{:?}", metering);
+            let pooled = exec_pooled_metered(&input, "fib", &[90], true, PoolOptions { size: 1, reset_every: None }).result;
+            assert_eq!(pooled, metered.result);
+            results.push(metered.result);
+        }
+        assert_eq!(results, vec![Ok(2_880_067_194_370_816_120); 3]);
+        assert!(Metering::parse("gas").unwrap_err().starts_with(errors::ERR_INVALID_INPUT));
+    }
+
+    #[test]
+    fn test_epoch_metering_enforces_the_time_limit() {
+        let _serial = crate::channels::test_serial();
+        let previous = config::get().epoch_time_limit;
+        config::update(|c| c.epoch_time_limit = Duration::from_millis(50));
+        let started = Instant::now();
+        let input = resolve_wasm_with(&fixtures::infinite_loop_module(), Metering::Epoch);
+        let err = exec_wasm_sync(&input, "spin", &[0]).unwrap_err();
+        config::update(|c| c.epoch_time_limit = previous);
+        assert!(err.starts_with(errors::ERR_TIME_LIMIT), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    /// Timing-sensitive, so not run by default: `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_unmetered_engine_is_faster_on_compute_heavy_guests() {
+        let best_of = |metering: Metering| {
+            let input = resolve_wasm_with(&fixtures::fib_module(), metering);
+            (0..5)
+                .map(|_| {
+                    let started = Instant::now();
+                    exec_wasm_sync(&input, "fib", &[20_000_000]).unwrap();
+                    started.elapsed()
+                })
+                .min()
+                .unwrap()
+        };
+        let fuel = best_of(Metering::Fuel);
+        let none = best_of(Metering::None);
+        assert!(none < fuel, "none {:?} vs fuel {:?}", none, fuel);
+    }
+
+    #[test]
+    fn test_unbounded_recursion_traps_with_stack_overflow() {
+        let input = resolve_wasm(&fixtures::depth_module());
+        let err = exec_wasm_sync(&input, "depth", &[-1]).unwrap_err();
+        assert!(err.starts_with(errors::ERR_STACK_OVERFLOW), "{}", err);
+        // Still alive, and a deep recursion within the limit is fine
+        assert_eq!(exec_wasm_sync(&input, "depth", &[5_000]), Ok(5_000));
+        let pooled = exec_pooled_metered(&input, "depth", &[-1], true, PoolOptions { size: 1, reset_every: None }).result;
+        assert!(pooled.unwrap_err().starts_with(errors::ERR_STACK_OVERFLOW));
+    }
+
+    fn batch(func: &str, args: &[&[i64]]) -> Vec<(String, Vec<i64>)> {
+        args.iter().map(|a| (func.to_string(), a.to_vec())).collect()
+    }
+
+    fn batch_f64(func: &str, args: &[&[f64]]) -> Vec<(String, Vec<f64>)> {
+        args.iter().map(|a| (func.to_string(), a.to_vec())).collect()
+    }
+
+    #[test]
+    fn test_typed_arms_for_three_and_four_integer_args() {
+        let input = resolve_wasm(&fixtures::typed_module());
+        let three: &[&[i64]] = &[&[6, 7, 2], &[-3, 5, 1]];
+        assert_eq!(exec_many_shared_reuse(&input, batch("mad3_i32", three)), vec![Ok(40), Ok(-16)]);
+        assert_eq!(exec_many_shared_reuse(&input, batch("mad3_i64", three)), vec![Ok(40), Ok(-16)]);
+        let four: &[&[i64]] = &[&[6, 7, 2, 3], &[1, 1, 4, 4]];
+        assert_eq!(exec_many_shared_reuse(&input, batch("mad4_i32", four)), vec![Ok(36), Ok(-15)]);
+        assert_eq!(exec_many_shared_reuse(&input, batch("mad4_i64", four)), vec![Ok(36), Ok(-15)]);
+        // i32 arms wrap like the guest does; i64 arms keep the full width
+        let wide: &[&[i64]] = &[&[1 << 20, 1 << 20, 0]];
+        assert_eq!(exec_many_shared_reuse(&input, batch("mad3_i32", wide)), vec![Ok(0)]);
+        assert_eq!(exec_many_shared_reuse(&input, batch("mad3_i64", wide)), vec![Ok(1 << 40)]);
+    }
+
+    #[test]
+    fn test_typed_arms_for_f64_signatures() {
+        let input = resolve_wasm(&fixtures::typed_module());
+        let sqrt = exec_many_shared_reuse_f64(&input, batch_f64("sqrt", &[&[2.0], &[9.0]]));
+        assert_eq!(sqrt, vec![Ok(std::f64::consts::SQRT_2), Ok(3.0)]);
+        let div = exec_many_shared_reuse_f64(&input, batch_f64("div", &[&[1.0, 4.0], &[1.0, 0.0]]));
+        assert_eq!(div, vec![Ok(0.25), Ok(f64::INFINITY)]);
+        let mad = exec_many_shared_reuse_f64(&input, batch_f64("mad3_f64", &[&[1.5, 4.0, 0.25]]));
+        assert_eq!(mad, vec![Ok(5.75)]);
+        // Integer functions are refused before they are called
+        let err = exec_many_shared_reuse_f64(&input, batch_f64("add", &[&[1.0, 2.0], &[3.0, 4.0]]));
+        let refused = Err("func 'add' does not take and return float values".to_string());
+        assert_eq!(err, vec![refused.clone(), refused]);
+        let err = exec_many_shared_reuse(&input, batch("sqrt", &[&[4]]));
+        assert_eq!(err, vec![Err("func 'sqrt' does not take and return integer values".to_string())]);
+    }
+
+    #[test]
+    fn test_mixed_batches_call_each_tasks_own_function() {
+        let input = resolve_wasm(&fixtures::typed_module());
+        // Same signature, different names: the typed path would have run
+        // `add` for both
+        let tasks = vec![
+            ("add".to_string(), vec![5, 3]),
+            ("sub".to_string(), vec![5, 3]),
+            ("mad3_i64".to_string(), vec![2, 3, 4]),
+        ];
+        assert_eq!(exec_many_shared_reuse(&input, tasks), vec![Ok(8), Ok(2), Ok(2)]);
+        let tasks = vec![("sqrt".to_string(), vec![16.0]), ("div".to_string(), vec![3.0, 2.0])];
+        assert_eq!(exec_many_shared_reuse_f64(&input, tasks), vec![Ok(4.0), Ok(1.5)]);
+    }
+
+    #[test]
+    fn test_dynamic_batches_resolve_each_function_once_per_name() {
+        let input = resolve_wasm(&fixtures::typed_module());
+        // Names repeat out of order, with arities the typed path never sees
+        // together, and a missing one fails only its own tasks
+        let tasks: Vec<(String, Vec<i64>)> = (0..300)
+            .map(|i| match i % 3 {
+                0 => ("add".to_string(), vec![i, 1]),
+                1 => ("mad4_i64".to_string(), vec![i, 2, 1, 1]),
+                _ => ("missing".to_string(), vec![i]),
+            })
+            .collect();
+        let results = exec_many_shared_reuse(&input, tasks);
+        for (i, result) in results.iter().enumerate() {
+            let i = i as i64;
+            match i % 3 {
+                0 => assert_eq!(result, &Ok(i + 1)),
+                1 => assert_eq!(result, &Ok(i * 2 - 1)),
+                _ => assert_eq!(result, &Err("func 'missing' not found".to_string())),
+            }
+        }
+    }
+
+    #[test]
+    fn test_guest_linear_memory_round_trips() {
+        // 10,000 i64 slots span two pages
+        let input = resolve_wasm(&fixtures::memory_sum_module(10_000));
+        let expected = |seed: i64| 10_000 * seed + 10_000 * 9_999 / 2;
+        assert_eq!(exec_wasm_sync(&input, "fill_sum", &[5]), Ok(expected(5)));
+        // A reused instance overwrites what the previous task left behind
+        let tasks = batch("fill_sum", &[&[1], &[-3], &[1]]);
+        assert_eq!(exec_many_shared_reuse(&input, tasks), vec![Ok(expected(1)), Ok(expected(-3)), Ok(expected(1))]);
+    }
+
+    #[test]
+    fn test_trap_kinds_fail_the_call_not_the_instance() {
+        for kind in TrapKind::ALL {
+            let input = resolve_wasm(&fixtures::trap_module(kind));
+            let err = exec_wasm_sync(&input, "trap", &[]).unwrap_err();
+            assert!(err.starts_with("WASM execution error"), "{:?}: {}", kind, err);
+            // The shared instance keeps serving tasks after one traps
+            let results = exec_many_shared_reuse(&input, vec![("trap".to_string(), vec![]), ("ok".to_string(), vec![])]);
+            assert!(results[0].is_err(), "{:?}", kind);
+            assert_eq!(results[1], Ok(1), "{:?}", kind);
+
+            // The same trap in a start function is classified
+            let err = exec_wasm_sync(&resolve_wasm(&fixtures::start_module(Some(kind))), "run", &[]).unwrap_err();
+            let code = if kind == TrapKind::Unreachable { errors::ERR_TRAP_UNREACHABLE } else { errors::ERR_TRAP };
+            assert!(err.starts_with(&format!("{}: ", code)), "{:?}: {}", kind, err);
+            assert!(err.contains("in start function"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_multi_value_functions_are_an_error_not_a_panic() {
+        let input = resolve_wasm(&fixtures::multi_value_module());
+        let err = exec_wasm_sync(&input, "divmod", &[7, 2]).unwrap_err();
+        assert!(err.starts_with("WASM execution error"), "{}", err);
+        let batch = exec_many_shared_reuse(&input, vec![("divmod".to_string(), vec![7, 2])]);
+        assert!(batch[0].is_err());
+        assert!(!batch[0].as_ref().unwrap_err().starts_with(errors::ERR_INTERNAL));
+    }
+}
@@ -0,0 +1,115 @@
+use crate::errors;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Fault injection, built only with the "fault-injection" feature: forces the
+// failures that are otherwise hard to provoke, so tests can drive every
+// error path through the public API. Each point fails the way the real
+// failure there does, with INJECTED as the message:
+//
+//   compile       "compile: injected fault", on cache hits as well as misses
+//   instantiate   "WASM instantiation error: injected fault"
+//   exec_trap     ERR_TRAP, before the guest function runs
+//   channel_send  ERR_CHANNEL_BUDGET, before any bytes are reserved
+//   join          "join: injected fault", after the guest finished
+//
+// Without the feature this module and every check against it are compiled out.
+
+pub const INJECTED: &str = "injected fault";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Compile,
+    Instantiate,
+    ExecTrap,
+    ChannelSend,
+    Join,
+}
+
+const KINDS: [Kind; 5] = [Kind::Compile, Kind::Instantiate, Kind::ExecTrap, Kind::ChannelSend, Kind::Join];
+
+impl Kind {
+    pub fn parse(name: &str) -> Result<Kind, String> {
+        match name {
+            "compile" => Ok(Kind::Compile),
+            "instantiate" => Ok(Kind::Instantiate),
+            "exec_trap" => Ok(Kind::ExecTrap),
+            "channel_send" => Ok(Kind::ChannelSend),
+            "join" => Ok(Kind::Join),
+            _ => Err(errors::coded(
+                errors::ERR_INVALID_INPUT,
+                format!("unknown fault '{}' (expected compile, instantiate, exec_trap, channel_send or join)", name),
+            )),
+        }
+    }
+}
+
+/// Fails every `every`th pass through its point (0 = never).
+struct Fault {
+    every: AtomicU64,
+    passes: AtomicU64,
+}
+
+impl Fault {
+    const fn new() -> Self {
+        Fault { every: AtomicU64::new(0), passes: AtomicU64::new(0) }
+    }
+
+    fn set(&self, every: u64) {
+        self.every.store(every, Ordering::SeqCst);
+        self.passes.store(0, Ordering::SeqCst);
+    }
+
+    fn hit(&self) -> bool {
+        match self.every.load(Ordering::SeqCst) {
+            0 => false,
+            every => (self.passes.fetch_add(1, Ordering::SeqCst) + 1).is_multiple_of(every),
+        }
+    }
+}
+
+static FAULTS: [Fault; 5] = [Fault::new(), Fault::new(), Fault::new(), Fault::new(), Fault::new()];
+
+/// Fail every `every_n`th pass through `kind`'s point from now on, counting
+/// from this call: 1 fails them all, 0 stops injecting.
+pub fn inject(kind: Kind, every_n: u64) {
+    FAULTS[kind as usize].set(every_n);
+}
+
+/// Stop injecting every kind. Used by `reset_all_state`.
+pub fn clear() {
+    for kind in KINDS {
+        inject(kind, 0);
+    }
+}
+
+/// Whether this pass through `kind`'s point should fail.
+pub fn hit(kind: Kind) -> bool {
+    FAULTS[kind as usize].hit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The global faults would hit other tests running alongside, so these
+    // check a private one; tests/runtime-foundation.test.js drives the
+    // injection points end to end.
+    #[test]
+    fn test_fault_fires_every_nth_pass_and_restarts_when_set() {
+        let fault = Fault::new();
+        assert!(!(0..10).any(|_| fault.hit()));
+        fault.set(3);
+        let hits: Vec<bool> = (0..7).map(|_| fault.hit()).collect();
+        assert_eq!(hits, [false, false, true, false, false, true, false]);
+        fault.set(3);
+        assert!(!fault.hit());
+        fault.set(1);
+        assert!((0..5).all(|_| fault.hit()));
+        fault.set(0);
+        assert!(!fault.hit());
+
+        assert_eq!(Kind::parse("exec_trap"), Ok(Kind::ExecTrap));
+        let err = Kind::parse("oom").unwrap_err();
+        assert!(err.starts_with(errors::ERR_INVALID_INPUT), "{}", err);
+    }
+}
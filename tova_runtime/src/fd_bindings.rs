@@ -0,0 +1,436 @@
+use crate::channels::{self, Received};
+use crate::errors::{self, lock};
+use crate::scheduler;
+use crossbeam_channel::{bounded, Receiver, Sender};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+// Channel bindings to file descriptors, for processes outside Bun (a Python
+// worker on a pipe or unix socket) to feed or drain a channel directly. A
+// reader binding reads frames of little-endian i64s from the fd and sends
+// them into the channel; a writer binding receives from the channel and
+// writes them out as frames. Either is a task on TOKIO_RT waiting on the fd
+// through AsyncFd, handing channel sends and receives, which block, to a
+// blocking thread. A reader whose channel is full stops reading until the
+// send goes through, so the writing process sees the pipe fill up.
+//
+// A binding owns its fd and closes it when it ends: at EOF or a write the
+// peer no longer takes, on a malformed frame, once its channel is closed
+// (a writer first drains it), on `unbind`, or on `reset`. Like a producer
+// or a subscription it never closes the channel itself. Values a writer
+// took off the channel and could not write are lost.
+
+/// How values are laid out on the fd.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Framing {
+    /// A u32 little-endian byte length, then that many bytes of values
+    LengthPrefixed,
+    /// Values back to back, 8 bytes each
+    Raw,
+}
+
+/// Bytes read from the fd at a time
+const READ_CHUNK: usize = 64 * 1024;
+
+/// Largest length-prefixed frame a reader accepts; a longer length is taken
+/// for a peer speaking some other protocol
+const MAX_FRAME_BYTES: usize = 1 << 20;
+
+/// Values a writer puts in one length-prefixed frame, of those already
+/// buffered when it wakes
+const WRITE_BATCH: usize = 512;
+
+/// How often a reader waiting on its fd checks whether the channel closed
+const CLOSE_POLL: Duration = Duration::from_millis(50);
+
+struct Binding {
+    /// Dropping it wakes the task out of a blocked channel send or receive
+    _stop: Sender<()>,
+    /// Dropping it wakes the task out of a wait on the fd
+    _cancel: oneshot::Sender<()>,
+}
+
+static BINDINGS: Lazy<Mutex<HashMap<u64, Binding>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+static NEXT_BINDING: AtomicU64 = AtomicU64::new(0);
+
+/// Send every value read from `fd` into `channel`. Takes ownership of `fd`,
+/// which must be a pipe, socket or other pollable descriptor; it is made
+/// non-blocking. Returns the binding id.
+pub fn bind_reader(channel: u64, fd: i32, framing: Framing) -> Result<u64, String> {
+    let fd = imp::open(channel, fd)?;
+    let (binding, stop, cancel) = register();
+    scheduler::TOKIO_RT.spawn(async move {
+        imp::pump_in(channel, fd, framing, stop, cancel).await;
+        lock(&BINDINGS).remove(&binding);
+    });
+    Ok(binding)
+}
+
+/// Write every value received on `channel` to `fd`, until the channel is
+/// closed and drained. Takes ownership of `fd` as `bind_reader` does.
+/// Returns the binding id.
+pub fn bind_writer(channel: u64, fd: i32, framing: Framing) -> Result<u64, String> {
+    let fd = imp::open(channel, fd)?;
+    let (binding, stop, cancel) = register();
+    scheduler::TOKIO_RT.spawn(async move {
+        imp::pump_out(channel, fd, framing, stop, cancel).await;
+        lock(&BINDINGS).remove(&binding);
+    });
+    Ok(binding)
+}
+
+fn register() -> (u64, Receiver<()>, oneshot::Receiver<()>) {
+    let (stop, stopped) = bounded::<()>(0);
+    let (cancel, cancelled) = oneshot::channel();
+    let binding = NEXT_BINDING.fetch_add(1, Ordering::Relaxed);
+    lock(&BINDINGS).insert(binding, Binding { _stop: stop, _cancel: cancel });
+    (binding, stopped, cancelled)
+}
+
+/// End a binding and close its fd; false if it already ended.
+pub fn unbind(binding: u64) -> bool {
+    lock(&BINDINGS).remove(&binding).is_some()
+}
+
+/// End every binding. Used by `reset_all_state`.
+pub fn reset() {
+    lock(&BINDINGS).clear();
+}
+
+fn invalid(message: impl std::fmt::Display) -> String {
+    errors::coded(errors::ERR_INVALID_INPUT, message)
+}
+
+/// Reassembles values from reads that split frames anywhere.
+struct Decoder {
+    framing: Framing,
+    pending: Vec<u8>,
+    /// Set at the first length that is not a whole number of i64s or is
+    /// over MAX_FRAME_BYTES; nothing after it is decoded
+    malformed: bool,
+}
+
+impl Decoder {
+    fn new(framing: Framing) -> Self {
+        Decoder { framing, pending: Vec::new(), malformed: false }
+    }
+
+    /// The values completed by `bytes`, up to any malformed frame; the rest
+    /// waits for the next read.
+    fn push(&mut self, bytes: &[u8]) -> Vec<i64> {
+        if self.malformed {
+            return Vec::new();
+        }
+        self.pending.extend_from_slice(bytes);
+        let mut values = Vec::new();
+        let mut at = 0;
+        match self.framing {
+            Framing::Raw => {
+                let whole = self.pending.len() / 8 * 8;
+                values.extend(self.pending[..whole].chunks_exact(8).map(read_i64));
+                at = whole;
+            }
+            Framing::LengthPrefixed => {
+                while let Some(prefix) = self.pending.get(at..at + 4) {
+                    let len = u32::from_le_bytes(prefix.try_into().unwrap()) as usize;
+                    if !len.is_multiple_of(8) || len > MAX_FRAME_BYTES {
+                        self.malformed = true;
+                        break;
+                    }
+                    let Some(frame) = self.pending.get(at + 4..at + 4 + len) else {
+                        break;
+                    };
+                    values.extend(frame.chunks_exact(8).map(read_i64));
+                    at += 4 + len;
+                }
+            }
+        }
+        self.pending.drain(..at);
+        values
+    }
+}
+
+fn read_i64(bytes: &[u8]) -> i64 {
+    i64::from_le_bytes(bytes.try_into().unwrap())
+}
+
+fn encode(framing: Framing, values: &[i64]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(4 + values.len() * 8);
+    if framing == Framing::LengthPrefixed {
+        bytes.extend_from_slice(&((values.len() * 8) as u32).to_le_bytes());
+    }
+    for value in values {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    bytes
+}
+
+/// Wait for a value on `channel`, then take whatever else is buffered up to
+/// a frame's worth. None once the channel ended or the binding was stopped.
+fn take_batch(channel: u64, stop: &Receiver<()>) -> Option<Vec<i64>> {
+    let Received::Value(first) = channels::receive_or_stop(channel, None, stop) else {
+        return None;
+    };
+    let mut values = vec![first];
+    while values.len() < WRITE_BATCH {
+        match channels::receive(channel) {
+            Some(value) => values.push(value),
+            None => break,
+        }
+    }
+    Some(values)
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use super::*;
+    use std::fs::File;
+    use std::io::{Read, Write};
+    use std::os::fd::{FromRawFd, OwnedFd};
+    use tokio::io::unix::AsyncFd;
+
+    /// Check the channel, and take `fd` for the runtime to wait on.
+    pub fn open(channel: u64, fd: i32) -> Result<AsyncFd<File>, String> {
+        if fd < 0 {
+            return Err(invalid(format!("bad file descriptor {}", fd)));
+        }
+        // Handed over even when refused: dropping the File closes it
+        let file = File::from(unsafe { OwnedFd::from_raw_fd(fd) });
+        if channels::len(channel).is_none() {
+            return Err(format!("no such channel: {}", channel));
+        }
+        unsafe {
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            if flags < 0 || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) < 0 {
+                return Err(invalid(format!("file descriptor {}: {}", fd, std::io::Error::last_os_error())));
+            }
+        }
+        let _runtime = scheduler::TOKIO_RT.enter();
+        // Regular files cannot be polled and are refused here
+        AsyncFd::new(file).map_err(|e| invalid(format!("file descriptor {} cannot be polled: {}", fd, e)))
+    }
+
+    pub async fn pump_in(channel: u64, fd: AsyncFd<File>, framing: Framing, stop: Receiver<()>, mut cancel: oneshot::Receiver<()>) {
+        let mut decoder = Decoder::new(framing);
+        let mut buf = vec![0u8; READ_CHUNK];
+        let mut poll = tokio::time::interval(CLOSE_POLL);
+        loop {
+            let read = tokio::select! {
+                _ = &mut cancel => return,
+                _ = poll.tick() => {
+                    if channels::stat(channel).is_none_or(|stat| stat.closed) {
+                        return;
+                    }
+                    continue;
+                }
+                ready = fd.readable() => {
+                    let Ok(mut guard) = ready else { return };
+                    match guard.try_io(|inner| inner.get_ref().read(&mut buf)) {
+                        Ok(read) => read,
+                        Err(_would_block) => continue,
+                    }
+                }
+            };
+            let values = match read {
+                Ok(0) | Err(_) => return,
+                Ok(n) => decoder.push(&buf[..n]),
+            };
+            if !values.is_empty() {
+                // No more reads until the channel takes these
+                let stop = stop.clone();
+                let sent = tokio::task::spawn_blocking(move || {
+                    values.into_iter().all(|value| channels::send_or_stop(channel, value, &stop) == Ok(true))
+                })
+                .await;
+                if sent.ok() != Some(true) {
+                    return;
+                }
+            }
+            if decoder.malformed {
+                return;
+            }
+        }
+    }
+
+    pub async fn pump_out(channel: u64, fd: AsyncFd<File>, framing: Framing, stop: Receiver<()>, mut cancel: oneshot::Receiver<()>) {
+        loop {
+            let stop = stop.clone();
+            let taken = tokio::task::spawn_blocking(move || take_batch(channel, &stop)).await;
+            let Ok(Some(values)) = taken else { return };
+            let bytes = encode(framing, &values);
+            let mut written = 0;
+            while written < bytes.len() {
+                let ready = tokio::select! {
+                    _ = &mut cancel => return,
+                    ready = fd.writable() => ready,
+                };
+                let Ok(mut guard) = ready else { return };
+                match guard.try_io(|inner| inner.get_ref().write(&bytes[written..])) {
+                    Ok(Ok(n)) if n > 0 => written += n,
+                    Ok(_) => return,
+                    Err(_would_block) => continue,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use super::*;
+
+    pub struct Fd;
+
+    pub fn open(_channel: u64, _fd: i32) -> Result<Fd, String> {
+        Err("binding channels to file descriptors is not supported on this platform".to_string())
+    }
+
+    pub async fn pump_in(_channel: u64, _fd: Fd, _framing: Framing, _stop: Receiver<()>, _cancel: oneshot::Receiver<()>) {}
+
+    pub async fn pump_out(_channel: u64, _fd: Fd, _framing: Framing, _stop: Receiver<()>, _cancel: oneshot::Receiver<()>) {}
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::os::fd::{FromRawFd, IntoRawFd};
+    use std::os::unix::net::UnixStream;
+    use std::time::Instant;
+
+    fn frame(values: &[i64]) -> Vec<u8> {
+        encode(Framing::LengthPrefixed, values)
+    }
+
+    fn wait_unbound(binding: u64) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while lock(&BINDINGS).contains_key(&binding) {
+            assert!(Instant::now() < deadline, "binding {} did not end", binding);
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_reader_reassembles_frames_split_across_reads() {
+        let _serial = channels::test_serial();
+        let id = channels::create(1024);
+        let (mut peer, ours) = UnixStream::pair().unwrap();
+        let binding = bind_reader(id, ours.into_raw_fd(), Framing::LengthPrefixed).unwrap();
+
+        let mut bytes = Vec::new();
+        for n in 1..=20 {
+            bytes.extend(frame(&(0..n).map(|v| v * 1000 + n).collect::<Vec<_>>()));
+        }
+        let expected: Vec<i64> = (1..=20).flat_map(|n| (0..n).map(move |v| v * 1000 + n)).collect();
+        let writer = std::thread::spawn(move || {
+            // Pieces of 1 to 13 bytes cut frames and values anywhere
+            let mut at = 0;
+            let mut piece = 1;
+            while at < bytes.len() {
+                let end = (at + piece).min(bytes.len());
+                peer.write_all(&bytes[at..end]).unwrap();
+                at = end;
+                piece = piece % 13 + 1;
+                std::thread::sleep(Duration::from_micros(200));
+            }
+        });
+        let received: Vec<i64> = (0..expected.len()).map(|_| channels::receive_until(id, Some(Instant::now() + Duration::from_secs(5)))).map(|r| match r {
+            Received::Value(v) => v,
+            other => panic!("{:?}", other),
+        }).collect();
+        assert_eq!(received, expected);
+        // Dropping the peer is EOF: the binding ends, leaving the channel open
+        writer.join().unwrap();
+        wait_unbound(binding);
+        assert!(!unbind(binding));
+        assert_eq!(channels::send(id, 1), Ok(true));
+        channels::destroy(id);
+    }
+
+    #[test]
+    fn test_full_channel_pauses_reading_from_a_pipe() {
+        let _serial = channels::test_serial();
+        let id = channels::create(2);
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let binding = bind_reader(id, fds[0], Framing::Raw).unwrap();
+        let mut pipe = unsafe { std::fs::File::from_raw_fd(fds[1]) };
+        let writer = std::thread::spawn(move || {
+            for v in 0..10_000i64 {
+                pipe.write_all(&v.to_le_bytes()).unwrap();
+            }
+        });
+        std::thread::sleep(Duration::from_millis(50));
+        // The channel holds two values; the rest wait in the pipe
+        assert_eq!(channels::len(id), Some(2));
+        let mut received = Vec::new();
+        while received.len() < 10_000 {
+            match channels::receive_until(id, Some(Instant::now() + Duration::from_secs(5))) {
+                Received::Value(v) => received.push(v),
+                other => panic!("{:?} after {} values", other, received.len()),
+            }
+        }
+        writer.join().unwrap();
+        assert_eq!(received, (0..10_000).collect::<Vec<_>>());
+        wait_unbound(binding);
+        channels::destroy(id);
+    }
+
+    #[test]
+    fn test_writer_drains_the_channel_then_closes_the_fd() {
+        let _serial = channels::test_serial();
+        let id = channels::create(8);
+        let (mut peer, ours) = UnixStream::pair().unwrap();
+        let binding = bind_writer(id, ours.into_raw_fd(), Framing::LengthPrefixed).unwrap();
+        let producer = std::thread::spawn(move || {
+            for v in -500..500 {
+                assert_eq!(channels::send(id, v), Ok(true));
+            }
+            channels::close(id);
+        });
+        let mut bytes = Vec::new();
+        peer.read_to_end(&mut bytes).unwrap();
+        producer.join().unwrap();
+        let mut decoder = Decoder::new(Framing::LengthPrefixed);
+        assert_eq!(decoder.push(&bytes), (-500..500).collect::<Vec<_>>());
+        assert!(decoder.pending.is_empty() && !decoder.malformed);
+        wait_unbound(binding);
+    }
+
+    #[test]
+    fn test_unbind_and_bad_frames_end_a_binding() {
+        let _serial = channels::test_serial();
+        let id = channels::create(1);
+        let (mut peer, ours) = UnixStream::pair().unwrap();
+        let binding = bind_reader(id, ours.into_raw_fd(), Framing::Raw).unwrap();
+        // One value fills the channel; the reader blocks sending the second
+        peer.write_all(&[1i64, 2].map(i64::to_le_bytes).concat()).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(unbind(binding));
+        let mut rest = Vec::new();
+        peer.read_to_end(&mut rest).unwrap();
+        assert_eq!(channels::close_and_drain(id), vec![1]);
+
+        let id = channels::create(4);
+        let (mut peer, ours) = UnixStream::pair().unwrap();
+        let binding = bind_reader(id, ours.into_raw_fd(), Framing::LengthPrefixed).unwrap();
+        // Frames before a bad length still arrive
+        peer.write_all(&[frame(&[7]), 5u32.to_le_bytes().to_vec(), frame(&[8])].concat()).unwrap();
+        wait_unbound(binding);
+        assert_eq!(channels::close_and_drain(id), vec![7]);
+
+        let err = bind_reader(u64::MAX, UnixStream::pair().unwrap().0.into_raw_fd(), Framing::Raw).unwrap_err();
+        assert!(err.contains("no such channel"), "{}", err);
+        let file = std::fs::File::open("/proc/self/status").unwrap();
+        let channel = channels::create(1);
+        let err = bind_reader(channel, file.into_raw_fd(), Framing::Raw).unwrap_err();
+        assert!(err.starts_with(errors::ERR_INVALID_INPUT) && err.contains("cannot be polled"), "{}", err);
+        channels::destroy(channel);
+    }
+}
@@ -1,26 +1,870 @@
 use wasmtime::*;
-use crate::channels;
+use crate::channels::{self, Received};
+use crate::{contexts, diagnostics, errors, executor, recording, scheduler, shared_data};
+use std::sync::{Arc, Mutex};
+use tova_native::kernels;
 
 /// Sentinel value returned by chan_receive when channel is closed/empty.
 /// Using i64::MIN avoids collision with legitimate -1 values.
 pub const CHAN_CLOSED_SENTINEL: i64 = i64::MIN; // 0x8000000000000000
 
-pub fn add_channel_imports(linker: &mut Linker<()>) -> Result<(), String> {
+/// chan_send statuses
+pub const CHAN_SEND_OK: i32 = 0;
+pub const CHAN_SEND_CLOSED: i32 = -1;
+/// The id does not resolve in the guest's channel context
+pub const CHAN_NO_SUCH_CHANNEL: i32 = -2;
+/// The runtime-wide channel buffer budget is exhausted
+pub const CHAN_SEND_OVER_BUDGET: i32 = -3;
+
+/// chan_receive_status statuses. A destroyed channel reads as closed; only
+/// an id that never named a channel (or that the guest's context does not
+/// grant) is CHAN_NO_SUCH_CHANNEL.
+pub const CHAN_RECEIVE_OK: i32 = 0;
+pub const CHAN_RECEIVE_CLOSED: i32 = -1;
+
+// Import families, as "module.name". Capabilities expand to these lists; the
+// clock, log and kv families are reserved and have no imports yet. The native
+// family is the only one linked solely when granted (see add_native_imports).
+pub const CHANNEL_IMPORTS: &[&str] = &["tova.chan_send", "tova.chan_receive", "tova.chan_receive_status"];
+pub const NATIVE_IMPORTS: &[&str] = &[
+    "tova_native.native_sort_f64",
+    "tova_native.native_sort_i64",
+    "tova_native.native_sum_f64",
+    "tova_native.native_hash_xxh64",
+];
+/// Linked for every guest and never subject to an allow list: they only
+/// tell a guest where it sits in its batch.
+pub const TASK_IMPORTS: &[&str] = &["tova.task_index", "tova.task_count"];
+/// Also linked for every guest: reading the fuel left is harmless, and
+/// request_fuel only gets what the fuel policy grants.
+pub const FUEL_IMPORTS: &[&str] = &["tova.fuel_remaining", "tova.request_fuel"];
+/// Also linked for every guest: a guest reads only the segments its call
+/// was given.
+pub const SHARED_DATA_IMPORTS: &[&str] = &["tova.shared_data_len", "tova.shared_data_read"];
+pub const CLOCK_IMPORTS: &[&str] = &[];
+pub const LOG_IMPORTS: &[&str] = &[];
+pub const KV_IMPORTS: &[&str] = &[];
+
+/// fuel_remaining and request_fuel result for a call without fuel metering
+pub const FUEL_UNMETERED: i64 = -1;
+
+/// shared_data_read statuses; shared_data_len also returns
+/// SHARED_DATA_NO_SUCH_SEGMENT
+pub const SHARED_DATA_OK: i32 = 0;
+/// The call was given no segment with that index
+pub const SHARED_DATA_NO_SUCH_SEGMENT: i32 = -1;
+/// The source range is negative or runs past the end of the segment
+pub const SHARED_DATA_OUT_OF_RANGE: i32 = -2;
+/// The destination is negative or runs past the end of guest memory, or the
+/// module exports no memory
+pub const SHARED_DATA_BAD_DESTINATION: i32 = -3;
+
+/// Per-store data the host imports read. A batch executor sets the task
+/// fields before each call; a lone call is task 0 of 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HostState {
+    pub task_index: i64,
+    pub task_count: i64,
+    /// Fuel request_fuel added since the store was last armed
+    pub fuel_granted: u64,
+    /// The call runs inline on the JS thread: request_fuel is denied without
+    /// asking the policy, which would wait on this very thread
+    pub inline: bool,
+}
+
+impl Default for HostState {
+    fn default() -> Self {
+        HostState { task_index: 0, task_count: 1, fuel_granted: 0, inline: false }
+    }
+}
+
+/// A guest's request_fuel call, as the fuel policy sees it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FuelRequest {
+    pub amount: u64,
+    /// Fuel the call had left when it asked
+    pub remaining: u64,
+    /// Fuel earlier requests of the same call were granted
+    pub granted: u64,
+    pub task_index: i64,
+}
+
+/// Decides how much fuel a request gets; 0 denies it. It runs on the
+/// guest's worker thread, which waits for the answer.
+pub type FuelPolicy = Arc<dyn Fn(&FuelRequest) -> u64 + Send + Sync>;
+
+static FUEL_POLICY: Mutex<Option<FuelPolicy>> = Mutex::new(None);
+
+/// Install the policy request_fuel consults, or remove it with None, after
+/// which every request is denied.
+pub fn set_fuel_policy(policy: Option<FuelPolicy>) {
+    *errors::lock(&FUEL_POLICY) = policy;
+}
+
+/// Shorthand for the import families a guest may use.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Capabilities {
+    pub channels: bool,
+    pub clock: bool,
+    pub log: bool,
+    pub kv: bool,
+    pub native: bool,
+}
+
+impl Capabilities {
+    pub fn imports(&self) -> Vec<String> {
+        [
+            (self.channels, CHANNEL_IMPORTS),
+            (self.clock, CLOCK_IMPORTS),
+            (self.log, LOG_IMPORTS),
+            (self.kv, KV_IMPORTS),
+            (self.native, NATIVE_IMPORTS),
+        ]
+        .into_iter()
+        .filter(|(granted, _)| *granted)
+        .flat_map(|(_, family)| family.iter().map(|name| name.to_string()))
+        .collect()
+    }
+}
+
+/// Fail with ERR_FORBIDDEN_IMPORT naming the first import of `module` that
+/// `allowed` ("module.name" entries) does not list.
+pub fn check_imports(module: &Module, allowed: &[String]) -> Result<(), String> {
+    for import in module.imports() {
+        let name = format!("{}.{}", import.module(), import.name());
+        let builtin = [TASK_IMPORTS, FUEL_IMPORTS, SHARED_DATA_IMPORTS].iter().any(|family| family.contains(&name.as_str()));
+        if !allowed.contains(&name) && !builtin {
+            return Err(errors::coded(
+                errors::ERR_FORBIDDEN_IMPORT,
+                format!("module imports {}, which is not allowed", name),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Register `task_index() -> i64` and `task_count() -> i64`, which read the
+/// store's HostState.
+pub fn add_task_imports(linker: &mut Linker<HostState>) -> Result<(), String> {
     linker
-        .func_wrap("tova", "chan_send", |ch_id: i32, value: i64| -> i32 {
-            match channels::send(ch_id as u64, value) {
-                Ok(true) => 0,
-                Ok(false) => -1,
-                Err(_) => -1,  // closed channel
-            }
+        .func_wrap("tova", "task_index", |mut caller: Caller<'_, HostState>| -> Result<i64> {
+            traced(&mut caller, "tova.task_index", &[], |caller| Ok(caller.data().task_index))
+        })
+        .map_err(|e| format!("failed to add task_index: {}", e))?;
+    linker
+        .func_wrap("tova", "task_count", |mut caller: Caller<'_, HostState>| -> Result<i64> {
+            traced(&mut caller, "tova.task_count", &[], |caller| Ok(caller.data().task_count))
+        })
+        .map_err(|e| format!("failed to add task_count: {}", e))?;
+    Ok(())
+}
+
+/// Register `fuel_remaining() -> i64`, the fuel the call has left, and
+/// `request_fuel(amount: i64) -> i64`, which asks the fuel policy for a
+/// top-up and returns the fuel left after it, or 0 when denied. Both return
+/// FUEL_UNMETERED outside fuel metering. A long-running guest checks its
+/// budget every so often and either tops up or checkpoints its state (into
+/// a channel or its memory) and returns, rather than trapping with
+/// ERR_OUT_OF_FUEL halfway through a step.
+pub fn add_fuel_imports(linker: &mut Linker<HostState>) -> Result<(), String> {
+    linker
+        .func_wrap("tova", "fuel_remaining", |mut caller: Caller<'_, HostState>| -> Result<i64> {
+            traced(&mut caller, "tova.fuel_remaining", &[], |caller| {
+                Ok(caller.get_fuel().map_or(FUEL_UNMETERED, |left| left.min(i64::MAX as u64) as i64))
+            })
         })
-        .map_err(|e| format!("failed to add chan_send: {}", e))?;
+        .map_err(|e| format!("failed to add fuel_remaining: {}", e))?;
+    linker
+        .func_wrap("tova", "request_fuel", |mut caller: Caller<'_, HostState>, amount: i64| -> Result<i64> {
+            traced(&mut caller, "tova.request_fuel", &[amount], |caller| {
+                let Ok(remaining) = caller.get_fuel() else {
+                    return Ok(FUEL_UNMETERED);
+                };
+                if caller.data().inline {
+                    return Ok(0);
+                }
+                let request = FuelRequest {
+                    amount: amount.max(0) as u64,
+                    remaining,
+                    granted: caller.data().fuel_granted,
+                    task_index: caller.data().task_index,
+                };
+                // Cloned out so a slow policy does not hold up other guests
+                let policy = errors::lock(&FUEL_POLICY).clone();
+                let grant = policy.map_or(0, |policy| policy(&request));
+                if grant == 0 {
+                    return Ok(0);
+                }
+                let total = remaining.saturating_add(grant);
+                caller.set_fuel(total)?;
+                caller.data_mut().fuel_granted = request.granted.saturating_add(grant);
+                Ok(total.min(i64::MAX as u64) as i64)
+            })
+        })
+        .map_err(|e| format!("failed to add request_fuel: {}", e))?;
+    Ok(())
+}
 
+/// Register `shared_data_len(id: i32) -> i64` and
+/// `shared_data_read(id: i32, src: i64, dst: i32, len: i32) -> i32` over the
+/// segments the call was given (see shared_data.rs), `id` being a segment's
+/// position in the call's list. A read copies `len` bytes from offset `src`
+/// of the segment to `dst` in the guest's exported memory, all or nothing,
+/// and returns SHARED_DATA_OK or one of the error statuses above.
+pub fn add_shared_data_imports(linker: &mut Linker<HostState>) -> Result<(), String> {
     linker
-        .func_wrap("tova", "chan_receive", |ch_id: i32| -> i64 {
-            channels::receive_blocking(ch_id as u64).unwrap_or(CHAN_CLOSED_SENTINEL)
+        .func_wrap("tova", "shared_data_len", |mut caller: Caller<'_, HostState>, id: i32| -> Result<i64> {
+            traced(&mut caller, "tova.shared_data_len", &[id as i64], |_| {
+                Ok(shared_data::granted(id).map_or(SHARED_DATA_NO_SUCH_SEGMENT as i64, |segment| segment.len() as i64))
+            })
         })
-        .map_err(|e| format!("failed to add chan_receive: {}", e))?;
+        .map_err(|e| format!("failed to add shared_data_len: {}", e))?;
+    linker
+        .func_wrap(
+            "tova",
+            "shared_data_read",
+            |mut caller: Caller<'_, HostState>, id: i32, src: i64, dst: i32, len: i32| -> Result<i32> {
+                let args = [id as i64, src, dst as i64, len as i64];
+                let status = traced(&mut caller, "tova.shared_data_read", &args, |caller| {
+                    let Some(segment) = shared_data::granted(id) else {
+                        return Ok(SHARED_DATA_NO_SUCH_SEGMENT as i64);
+                    };
+                    let Some(source) = byte_range(src, len as i64, segment.len()) else {
+                        return Ok(SHARED_DATA_OUT_OF_RANGE as i64);
+                    };
+                    let Some(memory) = caller.get_export("memory").and_then(Extern::into_memory) else {
+                        return Ok(SHARED_DATA_BAD_DESTINATION as i64);
+                    };
+                    let data = memory.data_mut(caller);
+                    let Some(destination) = byte_range(dst as i64, len as i64, data.len()) else {
+                        return Ok(SHARED_DATA_BAD_DESTINATION as i64);
+                    };
+                    data[destination].copy_from_slice(&segment[source]);
+                    Ok(SHARED_DATA_OK as i64)
+                })?;
+                Ok(status as i32)
+            },
+        )
+        .map_err(|e| format!("failed to add shared_data_read: {}", e))?;
+    Ok(())
+}
+
+/// `start..start + len` if both are non-negative and it fits in `size` bytes.
+fn byte_range(start: i64, len: i64, size: usize) -> Option<std::ops::Range<usize>> {
+    let start = usize::try_from(start).ok()?;
+    let end = start.checked_add(usize::try_from(len).ok()?)?;
+    (end <= size).then_some(start..end)
+}
+
+/// Register the `tova` channel imports, or with `allowed` only those it
+/// lists, so a guest cannot bind one it was not granted even with a matching
+/// signature. With a `context`, guest channel ids are looked up in that
+/// context's grants instead of the global registry; chan_receive on an
+/// unresolved id behaves like a closed channel.
+///
+/// `chan_receive_status(ch: i32, out: i32) -> i32` is the receive to loop
+/// on: it blocks for a value, stores it as an i64 at `out` in the guest's
+/// exported memory and returns CHAN_RECEIVE_OK, or returns
+/// CHAN_RECEIVE_CLOSED once the channel is closed and drained or destroyed,
+/// or CHAN_NO_SUCH_CHANNEL; it traps if `out` is outside memory.
+pub fn add_channel_imports(linker: &mut Linker<HostState>, context: Option<u64>, allowed: Option<&[String]>) -> Result<(), String> {
+    let resolve = move |ch_id: i32| match context {
+        Some(ctx) => contexts::resolve(ctx, ch_id),
+        None => Some(ch_id as u64),
+    };
+    let permitted = |name: &str| allowed.is_none_or(|allowed| allowed.iter().any(|a| a == name));
+
+    if permitted("tova.chan_send") {
+        linker
+            .func_wrap("tova", "chan_send", move |mut caller: Caller<'_, HostState>, ch_id: i32, value: i64| -> Result<i32> {
+                let status = traced(&mut caller, "tova.chan_send", &[ch_id as i64, value], |_| {
+                    let id = match resolve(ch_id) {
+                        Some(id) => id,
+                        None => return Ok(CHAN_NO_SUCH_CHANNEL as i64),
+                    };
+                    let _guest = diagnostics::guest_scope();
+                    let _turn = scheduler::import_turn();
+                    let status = match channels::send(id, value) {
+                        Ok(true) => CHAN_SEND_OK,
+                        Err(e) if e.starts_with(errors::ERR_CHANNEL_BUDGET) => CHAN_SEND_OVER_BUDGET,
+                        Ok(false) | Err(_) => CHAN_SEND_CLOSED,
+                    };
+                    Ok(status as i64)
+                })?;
+                Ok(status as i32)
+            })
+            .map_err(|e| format!("failed to add chan_send: {}", e))?;
+    }
+
+    if permitted("tova.chan_receive") {
+        linker
+            .func_wrap("tova", "chan_receive", move |mut caller: Caller<'_, HostState>, ch_id: i32| -> Result<i64> {
+                traced(&mut caller, "tova.chan_receive", &[ch_id as i64], |_| {
+                    let _guest = diagnostics::guest_scope();
+                    let _turn = scheduler::import_turn();
+                    Ok(resolve(ch_id)
+                        .and_then(|id| match executor::cancel_token() {
+                            Some(_) => match guest_receive(id) {
+                                Received::Value(value) => Some(value),
+                                _ => None,
+                            },
+                            None => channels::receive_blocking(id),
+                        })
+                        .unwrap_or(CHAN_CLOSED_SENTINEL))
+                })
+            })
+            .map_err(|e| format!("failed to add chan_receive: {}", e))?;
+    }
+
+    if permitted("tova.chan_receive_status") {
+        linker
+            .func_wrap(
+                "tova",
+                "chan_receive_status",
+                move |mut caller: Caller<'_, HostState>, ch_id: i32, out: i32| -> Result<i32> {
+                    let status = traced(&mut caller, "tova.chan_receive_status", &[ch_id as i64, out as i64], |caller| {
+                        let id = match resolve(ch_id) {
+                            Some(id) if channels::issued(id) => id,
+                            _ => return Ok(CHAN_NO_SUCH_CHANNEL as i64),
+                        };
+                        // Check the destination before taking a value that could not be stored
+                        guest_range(caller, "chan_receive_status", out, 1, 8)?;
+                        let received = {
+                            let _guest = diagnostics::guest_scope();
+                            let _turn = scheduler::import_turn();
+                            guest_receive(id)
+                        };
+                        match received {
+                            Received::Value(value) => {
+                                guest_range(caller, "chan_receive_status", out, 1, 8)?.copy_from_slice(&value.to_le_bytes());
+                                Ok(CHAN_RECEIVE_OK as i64)
+                            }
+                            _ => Ok(CHAN_RECEIVE_CLOSED as i64),
+                        }
+                    })?;
+                    Ok(status as i32)
+                },
+            )
+            .map_err(|e| format!("failed to add chan_receive_status: {}", e))?;
+    }
 
     Ok(())
 }
+
+/// Receive for a guest, giving up if the call's cancel token is cancelled
+/// meanwhile; the guest then traps at its next epoch check.
+fn guest_receive(id: u64) -> Received {
+    match executor::cancel_token() {
+        Some(token) => {
+            let (stop, _hook) = token.stop_signal();
+            channels::receive_or_stop(id, None, &stop)
+        }
+        None => channels::receive_until(id, None),
+    }
+}
+
+/// Register the `tova_native` kernel imports that `allowed` lists. Unlike
+/// the channel imports these are never linked without an allow list, so only
+/// guests granted the family can bind them. Each takes a (ptr, len) range of
+/// the caller's exported "memory", len counted in elements, and traps when
+/// the range falls outside it; the sorts work in place and return 0.
+pub fn add_native_imports(linker: &mut Linker<HostState>, allowed: Option<&[String]>) -> Result<(), String> {
+    let permitted = |name: &str| allowed.is_some_and(|allowed| allowed.iter().any(|a| a == name));
+
+    if permitted("tova_native.native_sort_f64") {
+        linker
+            .func_wrap("tova_native", "native_sort_f64", |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> Result<i32> {
+                traced(&mut caller, "tova_native.native_sort_f64", &[ptr as i64, len as i64], |caller| {
+                    let bytes = guest_range(caller, "native_sort_f64", ptr, len, 8)?;
+                    let mut values: Vec<f64> = bytes.chunks_exact(8).map(|b| f64::from_le_bytes(b.try_into().unwrap())).collect();
+                    kernels::sort_f64(&mut values);
+                    for (b, v) in bytes.chunks_exact_mut(8).zip(values) {
+                        b.copy_from_slice(&v.to_le_bytes());
+                    }
+                    Ok(0)
+                })?;
+                Ok(0)
+            })
+            .map_err(|e| format!("failed to add native_sort_f64: {}", e))?;
+    }
+
+    if permitted("tova_native.native_sort_i64") {
+        linker
+            .func_wrap("tova_native", "native_sort_i64", |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> Result<i32> {
+                traced(&mut caller, "tova_native.native_sort_i64", &[ptr as i64, len as i64], |caller| {
+                    let bytes = guest_range(caller, "native_sort_i64", ptr, len, 8)?;
+                    let mut values: Vec<i64> = bytes.chunks_exact(8).map(|b| i64::from_le_bytes(b.try_into().unwrap())).collect();
+                    kernels::sort_i64(&mut values);
+                    for (b, v) in bytes.chunks_exact_mut(8).zip(values) {
+                        b.copy_from_slice(&v.to_le_bytes());
+                    }
+                    Ok(0)
+                })?;
+                Ok(0)
+            })
+            .map_err(|e| format!("failed to add native_sort_i64: {}", e))?;
+    }
+
+    if permitted("tova_native.native_sum_f64") {
+        linker
+            .func_wrap("tova_native", "native_sum_f64", |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> Result<f64> {
+                let bits = traced(&mut caller, "tova_native.native_sum_f64", &[ptr as i64, len as i64], |caller| {
+                    let bytes = guest_range(caller, "native_sum_f64", ptr, len, 8)?;
+                    let values: Vec<f64> = bytes.chunks_exact(8).map(|b| f64::from_le_bytes(b.try_into().unwrap())).collect();
+                    Ok(kernels::sum_f64(&values).to_bits() as i64)
+                })?;
+                Ok(f64::from_bits(bits as u64))
+            })
+            .map_err(|e| format!("failed to add native_sum_f64: {}", e))?;
+    }
+
+    if permitted("tova_native.native_hash_xxh64") {
+        linker
+            .func_wrap(
+                "tova_native",
+                "native_hash_xxh64",
+                |mut caller: Caller<'_, HostState>, ptr: i32, len: i32, seed: i64| -> Result<i64> {
+                    traced(&mut caller, "tova_native.native_hash_xxh64", &[ptr as i64, len as i64, seed], |caller| {
+                        let bytes = guest_range(caller, "native_hash_xxh64", ptr, len, 1)?;
+                        Ok(kernels::hash_xxh64(bytes, seed as u64) as i64)
+                    })
+                },
+            )
+            .map_err(|e| format!("failed to add native_hash_xxh64: {}", e))?;
+    }
+
+    Ok(())
+}
+
+/// Run `import` with `args` under the recording or replay this thread is in,
+/// if any (see recording.rs). `live` performs the call; while recording it is
+/// logged with the guest memory it filled, and in a replay the recorded
+/// result is returned and that memory filled again instead.
+fn traced(
+    caller: &mut Caller<'_, HostState>,
+    import: &str,
+    args: &[i64],
+    live: impl FnOnce(&mut Caller<'_, HostState>) -> Result<i64>,
+) -> Result<i64> {
+    match recording::mode() {
+        recording::Mode::Live => live(caller),
+        recording::Mode::Record => {
+            let result = live(caller);
+            let filled = result.as_ref().ok().and_then(|&value| filled(import, args, value));
+            let fill = filled.and_then(|(ptr, len, width)| {
+                let bytes = guest_range(caller, import, ptr, len, width).ok()?;
+                Some((ptr as u64, &*bytes))
+            });
+            recording::log(import, args, result.as_ref().copied().map_err(|e| e.to_string()), fill);
+            result
+        }
+        recording::Mode::Replay => {
+            let call = recording::answer(import, args).map_err(|e| Error::new(recording::ReplayFailed(e)))?;
+            if let Some(recording::Fill { at, bytes: Some(bytes), .. }) = &call.fill {
+                guest_range(caller, import, *at as i32, bytes.len() as i32, 1)?.copy_from_slice(bytes);
+            }
+            let result = call.result.map_err(Error::msg)?;
+            // The grant changed the fuel the rest of the call runs on
+            if import == "tova.request_fuel" && result > 0 {
+                if let Ok(remaining) = caller.get_fuel() {
+                    caller.set_fuel(result as u64)?;
+                    let granted = caller.data().fuel_granted.saturating_add((result as u64).saturating_sub(remaining));
+                    caller.data_mut().fuel_granted = granted;
+                }
+            }
+            Ok(result)
+        }
+    }
+}
+
+/// The guest memory a call of `import` that returned `result` filled, as
+/// guest_range's (ptr, len, width).
+fn filled(import: &str, args: &[i64], result: i64) -> Option<(i32, i32, usize)> {
+    match import {
+        "tova.chan_receive_status" if result == CHAN_RECEIVE_OK as i64 => Some((args[1] as i32, 1, 8)),
+        "tova.shared_data_read" if result == SHARED_DATA_OK as i64 => Some((args[2] as i32, args[3] as i32, 1)),
+        "tova_native.native_sort_f64" | "tova_native.native_sort_i64" => Some((args[0] as i32, args[1] as i32, 8)),
+        _ => None,
+    }
+}
+
+/// The `len` elements of `width` bytes at `ptr` in the caller's exported
+/// memory, or an error naming `import` that traps the guest. Callers copy
+/// values through little-endian bytes rather than reinterpreting the slice,
+/// since guest pointers carry no alignment guarantee.
+fn guest_range<'a>(caller: &'a mut Caller<'_, HostState>, import: &str, ptr: i32, len: i32, width: usize) -> Result<&'a mut [u8]> {
+    let memory = caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .ok_or_else(|| Error::msg(format!("{}: the module exports no memory", import)))?;
+    let data = memory.data_mut(caller);
+    let range = usize::try_from(ptr)
+        .ok()
+        .zip(usize::try_from(len).ok())
+        .and_then(|(p, l)| Some((p, p.checked_add(l.checked_mul(width)?)?)))
+        .filter(|&(_, end)| end <= data.len());
+    match range {
+        Some((start, end)) => Ok(&mut data[start..end]),
+        None => Err(Error::msg(format!(
+            "{}: range ({}, {}) is outside guest memory of {} bytes",
+            import,
+            ptr,
+            len,
+            data.len()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::{self, resolve_wasm, resolve_wasm_with, Metering, DEFAULT_FUEL};
+    use crate::recording::Recording;
+    use crate::testsupport as fixtures;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    fn echo(input: u64, output: u64, context: Option<u64>) -> Result<i64, String> {
+        let wasm = resolve_wasm(&fixtures::channel_echo_module());
+        executor::exec_wasm_with_channels(&wasm, "echo", &[input as i64, output as i64], context, None)
+    }
+
+    #[test]
+    fn test_echo_forwards_until_its_input_ends() {
+        let _serial = channels::test_serial();
+        let (input, output) = (channels::create(16), channels::create(16));
+        for v in [3, -1, i64::MAX, 0] {
+            assert_eq!(channels::send(input, v), Ok(true));
+        }
+        channels::close(input);
+        assert_eq!(echo(input, output, None), Ok(4));
+        assert_eq!(channels::close_and_drain(output), vec![3, -1, i64::MAX, 0]);
+        channels::destroy(input);
+        channels::destroy(output);
+    }
+
+    #[test]
+    fn test_send_statuses_reach_the_guest() {
+        let _serial = channels::test_serial();
+        let wasm = resolve_wasm(&fixtures::send_once_module());
+        let send_once = |ch: i64, context| executor::exec_wasm_with_channels(&wasm, "send_once", &[ch, 1], context, None);
+        let ch = channels::create(1);
+        assert_eq!(send_once(ch as i64, None), Ok(CHAN_SEND_OK as i64));
+        channels::close(ch);
+        assert_eq!(send_once(ch as i64, None), Ok(CHAN_SEND_CLOSED as i64));
+
+        // In a context, only granted ids resolve
+        let ctx = contexts::create();
+        let open = channels::create(1);
+        let guest_id = contexts::grant(ctx, open).unwrap();
+        assert_eq!(send_once(guest_id as i64 + 1, Some(ctx)), Ok(CHAN_NO_SUCH_CHANNEL as i64));
+        assert_eq!(send_once(guest_id as i64, Some(ctx)), Ok(CHAN_SEND_OK as i64));
+        assert_eq!(channels::receive(open), Some(1));
+        contexts::destroy(ctx);
+        channels::destroy(ch);
+        channels::destroy(open);
+    }
+
+    #[test]
+    fn test_contexts_isolate_receives() {
+        let _serial = channels::test_serial();
+        let ctx = contexts::create();
+        let (input, output) = (channels::create(4), channels::create(4));
+        assert_eq!(channels::send(input, 9), Ok(true));
+        channels::close(input);
+        // Nothing granted: receive reads as ended, so nothing is forwarded
+        assert_eq!(echo(0, 1, Some(ctx)), Ok(0));
+        let guest_in = contexts::grant(ctx, input).unwrap();
+        let guest_out = contexts::grant(ctx, output).unwrap();
+        assert_eq!(echo(guest_in as u64, guest_out as u64, Some(ctx)), Ok(1));
+        assert_eq!(channels::receive(output), Some(9));
+        contexts::destroy(ctx);
+        channels::destroy(input);
+        channels::destroy(output);
+    }
+
+    #[test]
+    fn test_check_imports_names_the_first_forbidden_import() {
+        let wasm = resolve_wasm(&fixtures::channel_echo_module());
+        let send_only = vec!["tova.chan_send".to_string()];
+        let err = executor::check_imports(&wasm, &send_only).unwrap_err();
+        assert!(err.starts_with(errors::ERR_FORBIDDEN_IMPORT), "{}", err);
+        assert!(err.contains("tova.chan_receive"), "{}", err);
+        let all = Capabilities { channels: true, ..Default::default() }.imports();
+        assert_eq!(executor::check_imports(&wasm, &all), Ok(()));
+    }
+
+    #[test]
+    fn test_consumers_return_when_their_input_ends() {
+        let _serial = channels::test_serial();
+        let wasm = resolve_wasm(&fixtures::drain_module());
+        for end in [channels::close as fn(u64), channels::destroy] {
+            let input = channels::create(4);
+            assert_eq!(channels::send(input, 1), Ok(true));
+            let consumers: Vec<_> = (0..3)
+                .map(|_| {
+                    let wasm = wasm.clone();
+                    std::thread::spawn(move || executor::exec_wasm_with_channels(&wasm, "drain", &[input as i64], None, None))
+                })
+                .collect();
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            let ended = std::time::Instant::now();
+            end(input);
+            let counts: Vec<i64> = consumers.into_iter().map(|c| c.join().unwrap().unwrap()).collect();
+            assert!(ended.elapsed() < std::time::Duration::from_secs(2), "{:?}", ended.elapsed());
+            // Between them the consumers took the one value, and each saw the end
+            assert_eq!(counts.iter().sum::<i64>(), 1, "{:?}", counts);
+        }
+    }
+
+    #[test]
+    fn test_receive_status_tells_closed_from_missing() {
+        let _serial = channels::test_serial();
+        let wasm = resolve_wasm(&fixtures::drain_module());
+        let drain = |ch: i64, context| executor::exec_wasm_with_channels(&wasm, "drain", &[ch], context, None);
+        let ch = channels::create(4);
+        for v in [7, 8] {
+            assert_eq!(channels::send(ch, v), Ok(true));
+        }
+        channels::close(ch);
+        assert_eq!(drain(ch as i64, None), Ok(2));
+        // Drained and gone from the registry, yet still closed rather than missing
+        assert_eq!(drain(ch as i64, None), Ok(0));
+        let destroyed = channels::create(4);
+        channels::destroy(destroyed);
+        assert_eq!(drain(destroyed as i64, None), Ok(0));
+        assert_eq!(drain(i32::MAX as i64, None), Ok(CHAN_NO_SUCH_CHANNEL as i64));
+
+        let ctx = contexts::create();
+        assert_eq!(drain(0, Some(ctx)), Ok(CHAN_NO_SUCH_CHANNEL as i64));
+        let shared = channels::create(4);
+        let granted = contexts::grant(ctx, shared).unwrap();
+        assert_eq!(contexts::close_all(ctx), Ok(1));
+        assert_eq!(drain(granted as i64, Some(ctx)), Ok(0));
+        contexts::destroy(ctx);
+        channels::destroy(shared);
+
+        let open = channels::create(1);
+        assert_eq!(channels::send(open, 3), Ok(true));
+        let receive_at = |out: i64| executor::exec_wasm_with_channels(&wasm, "receive_at", &[open as i64, out], None, None);
+        assert!(receive_at(65536 - 4).is_err());
+        // The trap left the value in the channel
+        assert_eq!(receive_at(65536 - 8), Ok(CHAN_RECEIVE_OK as i64));
+        channels::destroy(open);
+    }
+
+    fn native(func: &str, args: &[i64], allowed: Option<&[String]>) -> Result<i64, String> {
+        let wasm = resolve_wasm(&fixtures::native_kernels_module());
+        executor::exec_wasm_with_channels(&wasm, func, args, None, allowed)
+    }
+
+    #[test]
+    fn test_native_sorts_reach_guest_memory() {
+        let granted = Capabilities { native: true, ..Default::default() }.imports();
+        for n in [0, 1, 2, 100, 7000] {
+            assert_eq!(native("sort_f64_check", &[n], Some(&granted)), Ok(1), "n = {}", n);
+            assert_eq!(native("sort_i64_check", &[n], Some(&granted)), Ok(1), "n = {}", n);
+        }
+    }
+
+    #[test]
+    fn test_native_sum_and_hash_match_the_kernels() {
+        let granted = Capabilities { native: true, ..Default::default() }.imports();
+        assert_eq!(native("sum", &[0], Some(&granted)), Ok(0));
+        assert_eq!(native("sum", &[1000], Some(&granted)), Ok(500_500));
+        assert_eq!(native("hash", &[60000, 3, 0], Some(&granted)), Ok(0x44BC_2CF5_AD77_0999));
+        assert_eq!(
+            native("hash", &[60000, 3, 7], Some(&granted)),
+            Ok(kernels::hash_xxh64(b"abc", 7) as i64)
+        );
+        assert_eq!(native("hash", &[0, 0, 0], Some(&granted)), Ok(kernels::hash_xxh64(b"", 0) as i64));
+    }
+
+    #[test]
+    fn test_native_ranges_outside_memory_trap() {
+        let granted = Capabilities { native: true, ..Default::default() }.imports();
+        assert_eq!(native("sort", &[65536 - 8, 1], Some(&granted)), Ok(0));
+        for (ptr, len) in [(65536 - 8, 2), (65536, 1), (-8, 1), (0, -1), (0, i32::MAX as i64)] {
+            assert!(native("sort", &[ptr, len], Some(&granted)).is_err(), "({}, {})", ptr, len);
+        }
+        assert!(native("hash", &[65535, 2, 0], Some(&granted)).is_err());
+        assert!(native("hash", &[65535, 1, 0], Some(&granted)).is_ok());
+    }
+
+    #[test]
+    fn test_native_imports_need_the_capability() {
+        // Without an allow list the family is not linked at all
+        assert!(native("sort_f64_check", &[4], None).is_err());
+        let channels = Capabilities { channels: true, ..Default::default() }.imports();
+        let err = native("sort_f64_check", &[4], Some(&channels)).unwrap_err();
+        assert!(err.starts_with(errors::ERR_FORBIDDEN_IMPORT), "{}", err);
+        assert!(err.contains("tova_native.native_sort_f64"), "{}", err);
+    }
+
+    #[test]
+    fn test_request_fuel_follows_the_policy() {
+        let _serial = channels::test_serial();
+        let wasm = resolve_wasm(&fixtures::fuel_module());
+        let calls = Arc::new(AtomicU64::new(0));
+        let policy = |grant: bool| -> FuelPolicy {
+            let calls = calls.clone();
+            Arc::new(move |request: &FuelRequest| {
+                calls.fetch_add(1, Ordering::Relaxed);
+                if grant { request.amount } else { 0 }
+            })
+        };
+
+        set_fuel_policy(Some(policy(true)));
+        let granted = executor::exec_wasm_metered(&wasm, "run", &[16, 100_000_000]);
+        let granting_calls = calls.swap(0, Ordering::Relaxed);
+        set_fuel_policy(Some(policy(false)));
+        let denied = executor::exec_wasm_sync(&wasm, "run", &[16, 100_000_000]);
+        let denying_calls = calls.swap(0, Ordering::Relaxed);
+        set_fuel_policy(None);
+        let unanswered = executor::exec_wasm_sync(&wasm, "request", &[1000]);
+
+        assert!(granted.result.unwrap() > 0);
+        assert_eq!(granting_calls, 16);
+        // The grants count as burnt fuel, past DEFAULT_FUEL
+        assert!(granted.fuel_used > DEFAULT_FUEL, "{}", granted.fuel_used);
+        assert!(denied.unwrap_err().contains(errors::ERR_OUT_OF_FUEL));
+        assert!((10..16).contains(&denying_calls), "{} requests before running out", denying_calls);
+        assert_eq!(unanswered, Ok(0));
+    }
+
+    #[test]
+    fn test_fuel_remaining_reads_the_store() {
+        let remaining = executor::exec_wasm_sync(&resolve_wasm(&fixtures::fuel_module()), "remaining", &[]).unwrap();
+        assert!(remaining > 0 && remaining < DEFAULT_FUEL as i64, "{}", remaining);
+        let unmetered = resolve_wasm_with(&fixtures::fuel_module(), Metering::None);
+        assert_eq!(executor::exec_wasm_sync(&unmetered, "remaining", &[]), Ok(FUEL_UNMETERED));
+        assert_eq!(executor::exec_wasm_sync(&unmetered, "request", &[10]), Ok(FUEL_UNMETERED));
+    }
+
+    fn sorted_table(n: i64) -> Vec<u8> {
+        (0..n).flat_map(|i| (i * 3 - 100).to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn test_guest_binary_searches_a_shared_table() {
+        let wasm = resolve_wasm(&fixtures::shared_data_module());
+        let table = shared_data::register(sorted_table(100_000));
+        let _data = shared_data::scope(shared_data::resolve(&[table]).unwrap());
+        shared_data::unregister(table);
+        for (key, expected) in [(-100, 0), (-97, 1), (299_897, 99_999), (50_000 * 3 - 100, 50_000), (-99, -1), (300_000, -1)] {
+            assert_eq!(executor::exec_wasm_sync(&wasm, "search", &[key, 0]), Ok(expected), "key {}", key);
+        }
+        assert_eq!(executor::exec_wasm_sync(&wasm, "len", &[0]), Ok(800_000));
+        assert_eq!(executor::exec_wasm_sync(&wasm, "len", &[1]), Ok(SHARED_DATA_NO_SUCH_SEGMENT as i64));
+    }
+
+    #[test]
+    fn test_shared_data_reads_check_both_ranges() {
+        let wasm = resolve_wasm(&fixtures::shared_data_module());
+        let table = shared_data::register(vec![7; 64]);
+        let _data = shared_data::scope(shared_data::resolve(&[table]).unwrap());
+        shared_data::unregister(table);
+        let read = |id: i64, src: i64, dst: i64, len: i64| executor::exec_wasm_sync(&wasm, "read", &[id, src, dst, len]);
+        let status = |s: i32| Ok(s as i64);
+        assert_eq!(read(0, 0, 0, 64), status(SHARED_DATA_OK));
+        assert_eq!(read(0, 60, 100, 0), status(SHARED_DATA_OK));
+        assert_eq!(read(0, 60, 0, 8), status(SHARED_DATA_OUT_OF_RANGE));
+        assert_eq!(read(0, -1, 0, 1), status(SHARED_DATA_OUT_OF_RANGE));
+        assert_eq!(read(0, 0, 0, -1), status(SHARED_DATA_OUT_OF_RANGE));
+        assert_eq!(read(0, 0, 65_536 - 4, 8), status(SHARED_DATA_BAD_DESTINATION));
+        assert_eq!(read(0, 0, -8, 8), status(SHARED_DATA_BAD_DESTINATION));
+        assert_eq!(read(1, 0, 0, 8), status(SHARED_DATA_NO_SUCH_SEGMENT));
+        assert_eq!(read(-1, 0, 0, 8), status(SHARED_DATA_NO_SUCH_SEGMENT));
+    }
+
+    #[test]
+    fn test_unregistering_mid_call_keeps_the_segment_readable() {
+        let wasm = resolve_wasm(&fixtures::shared_data_module());
+        let table = shared_data::register(sorted_table(1_000));
+        let segments = shared_data::resolve(&[table]).unwrap();
+        let running = std::thread::spawn(move || {
+            let _data = shared_data::scope(segments);
+            executor::exec_wasm_sync(&wasm, "search", &[2_897, 20_000_000])
+        });
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        assert!(shared_data::unregister(table));
+        assert_eq!(running.join().unwrap(), Ok(999));
+        // Without a scope the guest sees no segments
+        let wasm = resolve_wasm(&fixtures::shared_data_module());
+        assert_eq!(executor::exec_wasm_sync(&wasm, "search", &[2_897, 0]), Ok(-1));
+    }
+
+    fn record(wasm: &[u8], func: &str, args: &[i64], allowed: Option<&[String]>) -> Recording {
+        executor::exec_wasm_recorded(&resolve_wasm(wasm), recording::module_hash(wasm), func, args, None, allowed)
+    }
+
+    fn replay(wasm: &[u8], recorded: &Recording) -> Result<i64, String> {
+        executor::replay(&resolve_wasm(wasm), recording::module_hash(wasm), recorded)
+    }
+
+    #[test]
+    fn test_recorded_calls_replay_without_their_channels() {
+        let _serial = channels::test_serial();
+        let wasm = fixtures::channel_echo_module();
+        let (input, output) = (channels::create(16), channels::create(16));
+        for v in [3, -1, i64::MAX] {
+            assert_eq!(channels::send(input, v), Ok(true));
+        }
+        channels::close(input);
+        let recorded = record(&wasm, "echo", &[input as i64, output as i64], None);
+        assert_eq!(recorded.outcome, Ok(3));
+        // A receive and a send per value, then the receive that ends
+        assert_eq!(recorded.calls.len(), 7);
+        assert_eq!(recorded.calls[3].import, "tova.chan_send");
+        assert_eq!(recorded.calls[6].result, Ok(CHAN_CLOSED_SENTINEL));
+        assert_eq!(channels::close_and_drain(output), vec![3, -1, i64::MAX]);
+        channels::destroy(input);
+        channels::destroy(output);
+
+        // Live, the destroyed input would end the echo at once
+        let recorded = Recording::from_json(&recorded.to_json()).unwrap();
+        assert_eq!(replay(&wasm, &recorded), Ok(3));
+
+        let mut tampered = recorded.clone();
+        tampered.calls[2].result = Ok(4);
+        let err = replay(&wasm, &tampered).unwrap_err();
+        assert!(err.starts_with(errors::ERR_REPLAY_DIVERGED), "{}", err);
+        let expected = format!("call 3: the guest called tova.chan_send({0}, 4), the recording has tova.chan_send({0}, -1)", output);
+        assert!(err.contains(&expected), "{}", err);
+
+        let mut tampered = recorded.clone();
+        tampered.outcome = Ok(2);
+        let err = replay(&wasm, &tampered).unwrap_err();
+        assert!(err.contains("the recorded call returned 2, the replay returned 3"), "{}", err);
+
+        let err = replay(&fixtures::drain_module(), &recorded).unwrap_err();
+        assert!(err.starts_with(errors::ERR_INVALID_INPUT) && err.contains("the recording is of module"), "{}", err);
+    }
+
+    #[test]
+    fn test_replays_refill_memory_and_repeat_traps() {
+        let _serial = channels::test_serial();
+        let wasm = fixtures::drain_module();
+        let ch = channels::create(4);
+        for v in [7, 8] {
+            assert_eq!(channels::send(ch, v), Ok(true));
+        }
+        let recorded = record(&wasm, "receive_at", &[ch as i64, 64], None);
+        assert_eq!(recorded.calls[0].fill.as_ref().and_then(|f| f.bytes.clone()), Some(7i64.to_le_bytes().to_vec()));
+        assert_eq!(replay(&wasm, &recorded), Ok(CHAN_RECEIVE_OK as i64));
+        let trapped = record(&wasm, "receive_at", &[ch as i64, 65536 - 4], None);
+        assert!(trapped.outcome.is_err());
+        assert_eq!(replay(&wasm, &trapped), trapped.outcome);
+        channels::destroy(ch);
+
+        // A sort's result is only replayable while it is small enough to keep
+        let natives = fixtures::native_kernels_module();
+        let granted = Capabilities { native: true, ..Default::default() }.imports();
+        let small = record(&natives, "sort_f64_check", &[100], Some(&granted));
+        assert_eq!(small.outcome, Ok(1));
+        assert_eq!(replay(&natives, &small), Ok(1));
+        let large = record(&natives, "sort_f64_check", &[7000], Some(&granted));
+        assert_eq!(large.outcome, Ok(1));
+        let err = replay(&natives, &large).unwrap_err();
+        assert!(err.starts_with(errors::ERR_INVALID_INPUT) && err.contains("redacted"), "{}", err);
+    }
+}
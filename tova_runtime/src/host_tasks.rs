@@ -0,0 +1,367 @@
+use crate::cancel_tokens::{Hook, Token};
+use crate::channels::{self, Received};
+use crate::errors::{self, lock};
+use crossbeam_channel::{bounded, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Host tasks: plain Rust-side work on the channel registry, with no guest
+// involved. A producer feeds a fixed list of values into a channel on a
+// schedule from its own thread; collectors and reducers drain a channel on a
+// blocking Tokio thread and resolve with what they took. Waiters do the same
+// until a count or a value arrives, and stop early when their registration
+// is dropped. Producers and waiters given a cancel token also stop when it
+// is cancelled. All of them compete with any other receiver on the channel,
+// like a subscription.
+
+static PRODUCERS: Lazy<Mutex<HashMap<u64, Sender<()>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+static NEXT_PRODUCER: AtomicU64 = AtomicU64::new(0);
+
+static WAITERS: Lazy<Mutex<HashMap<u64, Sender<()>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+static NEXT_WAITER: AtomicU64 = AtomicU64::new(0);
+
+/// Send `values` to `channel` in order, waiting `interval` before each one,
+/// from a dedicated thread. Stops early at the first send that fails (the
+/// channel closed or the buffer budget ran out) or on `cancel_producer` or
+/// cancelling `token`, including while it waits on a full buffer. Returns
+/// the producer id.
+pub fn spawn_producer(channel: u64, values: Vec<i64>, interval: Duration, token: Option<Arc<Token>>) -> Result<u64, String> {
+    if let Some(token) = &token {
+        token.check()?;
+    }
+    let (stop, stopped) = bounded::<()>(0);
+    let producer = NEXT_PRODUCER.fetch_add(1, Ordering::Relaxed);
+    lock(&PRODUCERS).insert(producer, stop);
+    let hook = token.map(|token| {
+        token.on_cancel(move || {
+            cancel_producer(producer);
+        })
+    });
+    let spawned = std::thread::Builder::new()
+        .name(format!("tova-producer-{}", channel))
+        .spawn(move || {
+            let _hook = hook;
+            for value in values {
+                // Cancelling drops `stop`, disconnecting `stopped`
+                let cancelled = if interval.is_zero() {
+                    stopped.try_recv() == Err(TryRecvError::Disconnected)
+                } else {
+                    stopped.recv_timeout(interval) == Err(RecvTimeoutError::Disconnected)
+                };
+                if cancelled {
+                    break;
+                }
+                if channels::send_or_stop(channel, value, &stopped) != Ok(true) {
+                    break;
+                }
+            }
+            lock(&PRODUCERS).remove(&producer);
+        });
+    if let Err(e) = spawned {
+        lock(&PRODUCERS).remove(&producer);
+        return Err(format!("failed to spawn producer: {}", e));
+    }
+    Ok(producer)
+}
+
+/// Stop a producer before its next send; false if it already finished.
+pub fn cancel_producer(producer: u64) -> bool {
+    lock(&PRODUCERS).remove(&producer).is_some()
+}
+
+/// Receive until `max_items` values were taken, the channel ends, or
+/// `timeout` passes (None waits for one of the others). Blocks the caller.
+pub fn collect(channel: u64, max_items: usize, timeout: Option<Duration>) -> Vec<i64> {
+    let deadline = timeout.map(|t| Instant::now() + t);
+    let mut values = Vec::new();
+    while values.len() < max_items {
+        match channels::receive_until(channel, deadline) {
+            Received::Value(value) => values.push(value),
+            Received::Ended | Received::TimedOut | Received::Stopped => break,
+        }
+    }
+    values
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReduceOp {
+    Sum,
+    Min,
+    Max,
+    Count,
+}
+
+impl ReduceOp {
+    pub fn parse(op: &str) -> Result<ReduceOp, String> {
+        match op {
+            "sum" => Ok(ReduceOp::Sum),
+            "min" => Ok(ReduceOp::Min),
+            "max" => Ok(ReduceOp::Max),
+            "count" => Ok(ReduceOp::Count),
+            _ => Err(errors::coded(
+                errors::ERR_INVALID_INPUT,
+                format!("unknown reduce op '{}' (expected sum, min, max or count)", op),
+            )),
+        }
+    }
+}
+
+/// Fold every value received until the channel ends or `timeout` passes.
+/// Sums wrap like guest i64 arithmetic. Sum and count of nothing are 0; min
+/// and max of nothing are an error.
+pub fn reduce(channel: u64, op: ReduceOp, timeout: Option<Duration>) -> Result<i64, String> {
+    let deadline = timeout.map(|t| Instant::now() + t);
+    let mut acc: Option<i64> = None;
+    let mut count = 0i64;
+    while let Received::Value(value) = channels::receive_until(channel, deadline) {
+        count += 1;
+        acc = Some(match (op, acc) {
+            (_, None) => value,
+            (ReduceOp::Sum, Some(a)) => a.wrapping_add(value),
+            (ReduceOp::Min, Some(a)) => a.min(value),
+            (ReduceOp::Max, Some(a)) => a.max(value),
+            (ReduceOp::Count, Some(a)) => a,
+        });
+    }
+    match op {
+        ReduceOp::Count => Ok(count),
+        ReduceOp::Sum => Ok(acc.unwrap_or(0)),
+        ReduceOp::Min | ReduceOp::Max => {
+            acc.ok_or_else(|| format!("channel {}: no values to reduce", channel))
+        }
+    }
+}
+
+/// Keeps a wait registered. Dropping it, as happens when the future
+/// awaiting the wait is dropped, `reset` or cancelling the wait's token
+/// stops the wait at its next receive with ERR_CANCELLED.
+pub struct WaitGuard {
+    waiter: u64,
+    _hook: Option<Hook>,
+}
+
+impl Drop for WaitGuard {
+    fn drop(&mut self) {
+        lock(&WAITERS).remove(&self.waiter);
+    }
+}
+
+/// Register a wait, cancelled along with `token` if given: pass the
+/// receiver to `wait_count` or `wait_value`.
+pub fn register_wait(token: Option<&Arc<Token>>) -> (WaitGuard, Receiver<()>) {
+    let (stop, stopped) = bounded::<()>(0);
+    let waiter = NEXT_WAITER.fetch_add(1, Ordering::Relaxed);
+    lock(&WAITERS).insert(waiter, stop);
+    let hook = token.map(|token| {
+        token.on_cancel(move || {
+            lock(&WAITERS).remove(&waiter);
+        })
+    });
+    (WaitGuard { waiter, _hook: hook }, stopped)
+}
+
+fn timed_out(channel: u64, what: impl std::fmt::Display) -> String {
+    errors::coded(errors::ERR_TIMEOUT, format!("channel {}: timed out waiting for {}", channel, what))
+}
+
+fn wait_cancelled(channel: u64) -> String {
+    errors::coded(errors::ERR_CANCELLED, format!("channel {}: wait cancelled", channel))
+}
+
+/// Take the first `n` values received, or fewer if the channel ends first.
+/// Fails with ERR_TIMEOUT if `timeout` passes first; values taken by then
+/// are consumed and lost.
+pub fn wait_count(channel: u64, n: usize, timeout: Option<Duration>, stop: &Receiver<()>) -> Result<Vec<i64>, String> {
+    let deadline = timeout.map(|t| Instant::now() + t);
+    let mut values = Vec::with_capacity(n.min(1024));
+    while values.len() < n {
+        match channels::receive_or_stop(channel, deadline, stop) {
+            Received::Value(value) => values.push(value),
+            Received::Ended => break,
+            Received::TimedOut => {
+                return Err(timed_out(channel, format!("{} values (got {})", n, values.len())))
+            }
+            Received::Stopped => return Err(wait_cancelled(channel)),
+        }
+    }
+    Ok(values)
+}
+
+/// Receive until `value` arrives: true once it does, false if the channel
+/// ends first. Every other value received meanwhile is discarded, not
+/// re-queued. Fails with ERR_TIMEOUT if `timeout` passes first.
+pub fn wait_value(channel: u64, value: i64, timeout: Option<Duration>, stop: &Receiver<()>) -> Result<bool, String> {
+    let deadline = timeout.map(|t| Instant::now() + t);
+    loop {
+        match channels::receive_or_stop(channel, deadline, stop) {
+            Received::Value(v) if v == value => return Ok(true),
+            Received::Value(_) => {}
+            Received::Ended => return Ok(false),
+            Received::TimedOut => return Err(timed_out(channel, format!("value {}", value))),
+            Received::Stopped => return Err(wait_cancelled(channel)),
+        }
+    }
+}
+
+/// Cancel every producer and waiter. Used by `reset_all_state`.
+pub fn reset() {
+    lock(&PRODUCERS).clear();
+    lock(&WAITERS).clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cancel_tokens;
+    use std::thread;
+
+    #[test]
+    fn test_producer_feeds_a_collector_in_order() {
+        let _serial = channels::test_serial();
+        let id = channels::create(2);
+        spawn_producer(id, (0..20).collect(), Duration::ZERO, None).unwrap();
+        assert_eq!(collect(id, 20, Some(Duration::from_secs(5))), (0..20).collect::<Vec<_>>());
+        // Nothing more arrives; the timeout ends the next collection
+        let started = Instant::now();
+        assert_eq!(collect(id, 5, Some(Duration::from_millis(50))), Vec::<i64>::new());
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        channels::destroy(id);
+    }
+
+    #[test]
+    fn test_cancel_stops_a_producer_blocked_on_a_full_channel() {
+        let _serial = channels::test_serial();
+        let id = channels::create(1);
+        let producer = spawn_producer(id, vec![1, 2, 3], Duration::ZERO, None).unwrap();
+        thread::sleep(Duration::from_millis(50));
+        assert!(cancel_producer(producer));
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(channels::receive(id), Some(1));
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(channels::receive(id), None);
+        assert!(!cancel_producer(producer));
+        channels::destroy(id);
+    }
+
+    #[test]
+    fn test_cancelling_a_parent_token_stops_timers_and_waits() {
+        let _serial = channels::test_serial();
+        let ticks = channels::create(64);
+        let idle = channels::create(4);
+        let parent = cancel_tokens::create();
+        let child = cancel_tokens::get(cancel_tokens::child(parent).unwrap()).unwrap();
+        let producer = spawn_producer(ticks, (0..1000).collect(), Duration::from_millis(5), Some(Arc::clone(&child))).unwrap();
+        let (guard, stop) = register_wait(Some(&child));
+        let waiter = thread::spawn(move || {
+            let _guard = guard;
+            wait_count(idle, 1, None, &stop)
+        });
+        thread::sleep(Duration::from_millis(30));
+
+        let started = Instant::now();
+        assert_eq!(cancel_tokens::cancel(parent), Ok(true));
+        let err = waiter.join().unwrap().unwrap_err();
+        assert!(err.starts_with(errors::ERR_CANCELLED), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(!cancel_producer(producer));
+        let sent = collect(ticks, 1000, Some(Duration::from_millis(50))).len();
+        assert!(sent > 0 && sent < 1000, "{}", sent);
+
+        // Nothing new starts under a cancelled token
+        let err = spawn_producer(ticks, vec![1], Duration::ZERO, Some(child)).unwrap_err();
+        assert!(err.starts_with(errors::ERR_CANCELLED), "{}", err);
+        channels::destroy(ticks);
+        channels::destroy(idle);
+    }
+
+    #[test]
+    fn test_reducers_stop_at_close() {
+        let _serial = channels::test_serial();
+        for (op, expected) in [(ReduceOp::Sum, Ok(10)), (ReduceOp::Min, Ok(-3)), (ReduceOp::Max, Ok(7)), (ReduceOp::Count, Ok(4))] {
+            let id = channels::create(8);
+            for v in [2, -3, 7, 4] {
+                assert_eq!(channels::send(id, v), Ok(true));
+            }
+            channels::close(id);
+            assert_eq!(reduce(id, op, None), expected, "{:?}", op);
+        }
+        let id = channels::create(1);
+        channels::close(id);
+        assert!(reduce(id, ReduceOp::Max, None).is_err());
+        assert_eq!(reduce(id, ReduceOp::Count, None), Ok(0));
+        assert!(ReduceOp::parse("avg").unwrap_err().starts_with(errors::ERR_INVALID_INPUT));
+    }
+
+    #[test]
+    fn test_wait_count_takes_one_token_per_worker() {
+        let _serial = channels::test_serial();
+        // Room for every token, so no send waits on the receiver
+        let id = channels::create(8);
+        let workers: Vec<_> = (0..8)
+            .map(|w| thread::spawn(move || assert_eq!(channels::send(id, w), Ok(true))))
+            .collect();
+        let (_guard, stop) = register_wait(None);
+        let mut tokens = wait_count(id, 8, Some(Duration::from_secs(5)), &stop).unwrap();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        tokens.sort();
+        assert_eq!(tokens, (0..8).collect::<Vec<_>>());
+        assert_eq!(channels::len(id), Some(0));
+
+        // One token short: the wait times out
+        for w in 0..7 {
+            assert_eq!(channels::send(id, w), Ok(true));
+        }
+        let err = wait_count(id, 8, Some(Duration::from_millis(50)), &stop).unwrap_err();
+        assert!(err.starts_with(errors::ERR_TIMEOUT), "{}", err);
+        assert!(err.contains("got 7"), "{}", err);
+
+        // Closed early: resolves with what arrived
+        assert_eq!(channels::send(id, 42), Ok(true));
+        channels::close(id);
+        assert_eq!(wait_count(id, 8, None, &stop), Ok(vec![42]));
+        channels::destroy(id);
+    }
+
+    #[test]
+    fn test_wait_value_discards_until_the_sentinel() {
+        let _serial = channels::test_serial();
+        let id = channels::create(8);
+        for v in [1, 2, -1, 3] {
+            assert_eq!(channels::send(id, v), Ok(true));
+        }
+        let (_guard, stop) = register_wait(None);
+        assert_eq!(wait_value(id, -1, Some(Duration::from_secs(5)), &stop), Ok(true));
+        // Values before the sentinel are gone; values after it remain
+        assert_eq!(channels::receive(id), Some(3));
+        let err = wait_value(id, -1, Some(Duration::from_millis(20)), &stop).unwrap_err();
+        assert!(err.starts_with(errors::ERR_TIMEOUT), "{}", err);
+        channels::close(id);
+        assert_eq!(wait_value(id, -1, None, &stop), Ok(false));
+        channels::destroy(id);
+    }
+
+    #[test]
+    fn test_dropping_the_guard_cancels_the_wait() {
+        let _serial = channels::test_serial();
+        let id = channels::create(4);
+        let (guard, stop) = register_wait(None);
+        let waiter = thread::spawn(move || wait_count(id, 1, None, &stop));
+        thread::sleep(Duration::from_millis(50));
+        drop(guard);
+        let err = waiter.join().unwrap().unwrap_err();
+        assert!(err.starts_with(errors::ERR_CANCELLED), "{}", err);
+        // The cancelled wait took nothing
+        assert_eq!(channels::send(id, 5), Ok(true));
+        assert_eq!(channels::receive(id), Some(5));
+        channels::destroy(id);
+    }
+}
@@ -1,10 +1,103 @@
 mod scheduler;
+mod config;
 mod executor;
+mod cancel_tokens;
 mod channels;
+mod compiles;
+mod contexts;
+mod diagnostics;
+mod errors;
+mod execs;
+#[cfg(feature = "fault-injection")]
+mod faults;
+mod fd_bindings;
 mod host_imports;
+mod host_tasks;
+mod lifecycle;
+mod metrics;
+mod pipeline;
+mod quotas;
+mod readers;
+mod recording;
+mod scopes;
+mod shared_data;
+mod shutdown;
+mod typed_views;
+mod warmup;
+mod watchdog;
+#[cfg(test)]
+mod testsupport;
+
+/// Engine entry points for benches/runtime.rs and tests/teardown.rs, which
+/// link the rlib and cannot reach the private modules. Not a stable API.
+#[doc(hidden)]
+pub mod bench {
+    pub fn exec_wasm(wasm: &[u8], func: &str, args: &[i64]) -> Result<i64, String> {
+        crate::executor::exec_wasm_sync(&crate::executor::resolve_wasm(wasm), func, args)
+    }
+
+    /// `exec_wasm` under "fuel", "epoch" or "none" metering
+    pub fn exec_wasm_metered(wasm: &[u8], func: &str, args: &[i64], metering: &str) -> Result<i64, String> {
+        let metering = crate::executor::Metering::parse(metering)?;
+        crate::executor::exec_wasm_sync(&crate::executor::resolve_wasm_with(wasm, metering), func, args)
+    }
+
+    /// One reused-instance chunk of integer tasks; `typed: false` forces the
+    /// dynamic Val path
+    pub fn shared_batch(wasm: &[u8], tasks: Vec<(String, Vec<i64>)>, typed: bool) -> Vec<Result<i64, String>> {
+        crate::executor::exec_many_shared_reuse_with(&crate::executor::resolve_wasm(wasm), tasks, typed)
+    }
+
+    /// `shared_batch` for float tasks
+    pub fn shared_batch_f64(wasm: &[u8], tasks: Vec<(String, Vec<f64>)>, typed: bool) -> Vec<Result<f64, String>> {
+        crate::executor::exec_many_shared_reuse_with(&crate::executor::resolve_wasm(wasm), tasks, typed)
+    }
+
+    pub fn channel_create(capacity: u32) -> u64 {
+        crate::channels::create(capacity)
+    }
+
+    pub fn channel_send(id: u64, value: i64) -> Result<bool, String> {
+        crate::channels::send(id, value)
+    }
+
+    pub fn channel_receive(id: u64) -> Option<i64> {
+        crate::channels::receive_blocking(id)
+    }
+
+    pub fn channel_close(id: u64) {
+        crate::channels::close(id)
+    }
+
+    pub fn channel_destroy(id: u64) {
+        crate::channels::destroy(id)
+    }
+
+    /// Start `exec_wasm_metered` as a guest execution nobody waits for, like
+    /// a call still running when its environment goes away
+    pub fn spawn_detached(wasm: &[u8], func: &str, args: &[i64], metering: &str) -> Result<(), String> {
+        let input = crate::executor::resolve_wasm_with(wasm, crate::executor::Metering::parse(metering)?);
+        let (func, args) = (func.to_string(), args.to_vec());
+        drop(crate::scheduler::spawn_guest(move || crate::executor::exec_wasm_sync(&input, &func, &args)));
+        Ok(())
+    }
+
+    /// What loading the addon into an environment does (see `register_shutdown_hook`)
+    pub fn environment_started() {
+        crate::shutdown::environment_started()
+    }
+
+    /// What the environment cleanup hook does: Some((executions interrupted,
+    /// whether they all finished in time)) when it shut the runtime down
+    pub fn environment_finished() -> Option<(usize, bool)> {
+        crate::shutdown::environment_finished(crate::shutdown::GRACE).map(|r| (r.interrupted, r.drained))
+    }
+}
 
 use napi::bindgen_prelude::*;
 use napi_derive::napi;
+use napi::sys;
+use napi::threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode};
 use std::sync::Arc;
 
 #[napi]
@@ -12,6 +105,478 @@ pub fn health_check() -> String {
     "tova_runtime ok".to_string()
 }
 
+// --- Lifecycle ---
+
+#[napi_derive::module_init]
+fn init() {
+    lifecycle::begin_generation();
+    if warmup::eager_requested() {
+        warmup::cheap();
+    }
+}
+
+/// Runs for every JS environment the addon is loaded into (the main thread
+/// and each Worker) and has the environment's teardown shut the runtime down
+/// once no environment is left, before statics are torn down at exit.
+#[napi(module_exports)]
+pub fn register_shutdown_hook(_exports: Object, env: Env) -> Result<()> {
+    shutdown::environment_started();
+    env.add_env_cleanup_hook((), |_| {
+        shutdown::environment_finished(shutdown::GRACE);
+    })?;
+    Ok(())
+}
+
+/// JS id -> registry id, rejecting ids from an earlier generation.
+fn untag(id: i64, kind: &str) -> Result<u64> {
+    lifecycle::untag(id, kind).map_err(Error::from_reason)
+}
+
+/// Incremented every time the module is loaded (and by `resetAllState`).
+/// A value different from the one seen at startup means this module was
+/// reloaded, and every id obtained before is now stale.
+#[napi]
+pub fn runtime_generation() -> i64 {
+    lifecycle::generation() as i64
+}
+
+/// Clean dev-mode restart: stop pipelines, cancel every cancel token, close
+/// every channel (failing or ending whoever is blocked on one), drop
+/// contexts, compiled modules and handles, and interrupt running guests with
+/// ERR_CANCELLED. Starts a new
+/// generation, so ids issued before fail with ERR_STALE_HANDLE. Returns it.
+#[napi]
+pub fn reset_all_state() -> i64 {
+    let generation = lifecycle::begin_generation();
+    pipeline::reset();
+    scopes::reset();
+    cancel_tokens::reset();
+    host_tasks::reset();
+    execs::reset();
+    readers::reset();
+    fd_bindings::reset();
+    channels::reset();
+    contexts::reset();
+    shared_data::reset();
+    executor::reset();
+    #[cfg(feature = "fault-injection")]
+    faults::clear();
+    generation as i64
+}
+
+// --- Fault injection (feature "fault-injection") ---
+
+/// Make every `everyN`th pass through a failure point fail from now on, the
+/// way a real failure there would: "compile", "instantiate", "exec_trap"
+/// (ERR_TRAP), "channel_send" (ERR_CHANNEL_BUDGET) or "join". 1 fails every
+/// pass, 0 stops. Only in builds with the fault-injection feature.
+#[cfg(feature = "fault-injection")]
+#[napi]
+pub fn inject_fault(kind: String, every_n: u32) -> Result<()> {
+    let kind = faults::Kind::parse(&kind).map_err(Error::from_reason)?;
+    faults::inject(kind, every_n as u64);
+    Ok(())
+}
+
+/// Stop injecting every kind of fault.
+#[cfg(feature = "fault-injection")]
+#[napi]
+pub fn clear_faults() {
+    faults::clear();
+}
+
+// --- Warm start ---
+
+#[napi(object)]
+pub struct WarmupOptions {
+    /// Also compile and run a built-in module on each engine, so the first
+    /// real compile doesn't pay for JIT setup (default true)
+    pub compile: Option<bool>,
+}
+
+#[napi(object)]
+pub struct WarmupStep {
+    /// "tokio", "channels", "engine.fuel", "engine.unmetered",
+    /// "compile.fuel" or "compile.unmetered"
+    pub name: String,
+    /// False if an earlier call or request had already done it
+    pub initialized: bool,
+    pub ms: f64,
+}
+
+#[napi(object)]
+pub struct WarmupReport {
+    pub steps: Vec<WarmupStep>,
+    pub total_ms: f64,
+}
+
+/// Create the Tokio runtime, the channel registry and both engines now, so
+/// the first request doesn't pay for them; call during startup, before
+/// accepting traffic. Creating the engines fixes `configureEngineFeatures`,
+/// so call that first. Setting TOVA_EAGER_INIT does the runtime and
+/// registry (not the engines) when the module loads.
+#[napi]
+pub async fn warmup(options: Option<WarmupOptions>) -> Result<WarmupReport> {
+    let compile = options.and_then(|o| o.compile).unwrap_or(true);
+    let started = std::time::Instant::now();
+    let mut steps = warmup::cheap();
+    let engines = scheduler::TOKIO_RT
+        .spawn_blocking(move || warmup::engines(compile))
+        .await
+        .map_err(|e| Error::from_reason(format!("join: {}", e)))?
+        .map_err(Error::from_reason)?;
+    steps.extend(engines);
+    Ok(WarmupReport {
+        steps: steps
+            .into_iter()
+            .map(|step| WarmupStep { name: step.name.to_string(), initialized: step.initialized, ms: millis(step.elapsed) })
+            .collect(),
+        total_ms: millis(started.elapsed()),
+    })
+}
+
+// --- Runtime configuration ---
+
+#[napi(object)]
+pub struct RuntimeOptions {
+    /// Threads in the dedicated compute pool (default: available parallelism)
+    pub compute_threads: Option<u32>,
+    /// CPU sets compute threads are pinned to, assigned round-robin, e.g. [[0, 1], [2, 3]]
+    pub cpu_affinity: Option<Vec<Vec<u32>>>,
+    /// Default chunk count for concurrentWasmShared (default: one per compute thread)
+    pub shared_chunks: Option<u32>,
+    /// Cap on bytes buffered across all channels (8 per i64 value); 0 = unlimited
+    pub channel_buffer_budget_bytes: Option<i64>,
+    /// Largest module accepted, in bytes (default 64 MiB)
+    pub max_module_bytes: Option<i64>,
+    /// Most arguments one call may pass (default 64)
+    pub max_args_per_task: Option<u32>,
+    /// Most tasks one batch may hold (default 100000)
+    pub max_tasks_per_batch: Option<u32>,
+    /// Most guest executions accepted but not yet started; calls that would
+    /// queue more fail at once with ERR_OVERLOADED (0 = unbounded, the default)
+    pub max_queued_executions: Option<u32>,
+    /// Metering for exec calls and batches that don't pass `metering`:
+    /// "fuel" (default), "epoch" or "none"
+    pub default_metering: Option<String>,
+    /// How long an "epoch"-metered call may run before failing with
+    /// ERR_TIME_LIMIT (default 10000)
+    pub epoch_time_limit_ms: Option<u32>,
+    /// Debug mode: run every guest execution one at a time on a single
+    /// thread, in an order drawn from `dispatchSeed`, and serialize channel
+    /// imports, so a batch replays identically. Pipeline stages keep their
+    /// threads. Guests cannot wait on each other in this mode.
+    pub deterministic_dispatch: Option<bool>,
+    /// Seed for the dispatch order, read with `deterministicDispatch: true`
+    /// (default 0). Turning the mode on again restarts the order.
+    pub dispatch_seed: Option<i64>,
+    /// Compiled modules kept before the least recently used unpinned one is
+    /// evicted (default 256)
+    pub module_cache_capacity: Option<u32>,
+    /// Share of `moduleCacheCapacity` that pinned modules may take, 0 to 1;
+    /// pins past it fail with ERR_PIN_BUDGET (default 0.5)
+    pub max_pinned_cache_fraction: Option<f64>,
+    /// Fuel an execWasmInline call may burn before failing with
+    /// ERR_INLINE_BUDGET (default 1000000)
+    pub inline_fuel: Option<i64>,
+    /// Run the runtime watchdog, which samples running executions and
+    /// blocked channel operations and reports stuck ones to the
+    /// `setWatchdogCallback` callback (default false)
+    pub watchdog: Option<bool>,
+    /// Age at which the watchdog flags an entry (default 30000)
+    pub watchdog_threshold_ms: Option<u32>,
+    /// How often the watchdog samples (default 5000)
+    pub watchdog_interval_ms: Option<u32>,
+    /// Modules that may compile at once, however many are submitted; the
+    /// rest wait their turn (default 1)
+    pub max_concurrent_compilations: Option<u32>,
+}
+
+/// Update runtime-wide settings. Fields left out keep their current value.
+/// Pool sizing and pinning only take effect before the compute pool starts.
+#[napi]
+pub fn configure_runtime(options: RuntimeOptions) -> Result<()> {
+    if (options.compute_threads.is_some() || options.cpu_affinity.is_some())
+        && scheduler::compute_pool_started()
+    {
+        return Err(Error::from_reason(
+            "computeThreads and cpuAffinity must be set before the compute pool starts".to_string(),
+        ));
+    }
+    let default_metering = options
+        .default_metering
+        .as_deref()
+        .map(executor::Metering::parse)
+        .transpose()
+        .map_err(Error::from_reason)?;
+    config::update(|c| {
+        if let Some(n) = options.compute_threads {
+            c.compute_threads = (n as usize).max(1);
+        }
+        if let Some(sets) = options.cpu_affinity {
+            c.cpu_affinity = sets
+                .into_iter()
+                .map(|set| set.into_iter().map(|cpu| cpu as usize).collect())
+                .collect();
+        }
+        if let Some(n) = options.shared_chunks {
+            c.shared_chunks = if n == 0 { None } else { Some(n as usize) };
+        }
+        if let Some(bytes) = options.max_module_bytes {
+            c.max_module_bytes = bytes.max(0) as usize;
+        }
+        if let Some(n) = options.max_args_per_task {
+            c.max_args = n as usize;
+        }
+        if let Some(n) = options.max_tasks_per_batch {
+            c.max_batch_tasks = n as usize;
+        }
+        if let Some(n) = options.max_queued_executions {
+            c.max_queued_executions = if n == 0 { None } else { Some(n as usize) };
+        }
+        if let Some(metering) = default_metering {
+            c.default_metering = metering;
+        }
+        if let Some(ms) = options.epoch_time_limit_ms {
+            c.epoch_time_limit = std::time::Duration::from_millis(ms.max(1) as u64);
+        }
+        if let Some(n) = options.module_cache_capacity {
+            c.module_cache_capacity = (n as usize).max(1);
+        }
+        if let Some(fraction) = options.max_pinned_cache_fraction {
+            c.max_pinned_fraction = if fraction.is_nan() { 0.0 } else { fraction.clamp(0.0, 1.0) };
+        }
+        if let Some(fuel) = options.inline_fuel {
+            c.inline_fuel = fuel.max(1) as u64;
+        }
+        if let Some(on) = options.watchdog {
+            c.watchdog = on;
+        }
+        if let Some(ms) = options.watchdog_threshold_ms {
+            c.watchdog_threshold = std::time::Duration::from_millis(ms as u64);
+        }
+        if let Some(ms) = options.watchdog_interval_ms {
+            c.watchdog_interval = std::time::Duration::from_millis(ms.max(1) as u64);
+        }
+        if let Some(n) = options.max_concurrent_compilations {
+            c.max_concurrent_compilations = (n as usize).max(1);
+        }
+    });
+    if options.max_concurrent_compilations.is_some() {
+        compiles::limit_changed();
+    }
+    if options.watchdog.is_some() || options.watchdog_threshold_ms.is_some() || options.watchdog_interval_ms.is_some() {
+        watchdog::restart();
+    }
+    if let Some(bytes) = options.channel_buffer_budget_bytes {
+        channels::set_buffer_budget(if bytes <= 0 { None } else { Some(bytes as u64) });
+    }
+    if let Some(on) = options.deterministic_dispatch {
+        let seed = options.dispatch_seed.unwrap_or(0) as u64;
+        scheduler::set_deterministic_dispatch(on.then_some(seed));
+    }
+    Ok(())
+}
+
+#[napi(object)]
+pub struct EngineFeatures {
+    /// Native stack a guest call may use; deeper recursion fails with
+    /// ERR_STACK_OVERFLOW (default 512 KiB, at most 4 MiB)
+    pub max_wasm_stack_bytes: Option<u32>,
+    /// Compile each module's functions in parallel (default true); false
+    /// compiles on the one thread that asked
+    pub parallel_compilation: Option<bool>,
+    /// Run parallel compilation on a dedicated pool of this many threads at
+    /// a lowered scheduling priority, rather than on every core
+    pub compile_threads: Option<u32>,
+}
+
+/// Set engine-level options. They are compiled into the engines, so this
+/// only works before the first module is compiled or run.
+#[napi]
+pub fn configure_engine_features(features: EngineFeatures) -> Result<()> {
+    if executor::engines_started() {
+        return Err(Error::from_reason(
+            "engine features must be set before the first module is compiled".to_string(),
+        ));
+    }
+    if let Some(bytes) = features.max_wasm_stack_bytes {
+        let bytes = bytes as usize;
+        if bytes == 0 || bytes > config::MAX_WASM_STACK_LIMIT {
+            return Err(Error::from_reason(errors::coded(
+                errors::ERR_INVALID_INPUT,
+                format!("maxWasmStackBytes must be between 1 and {}", config::MAX_WASM_STACK_LIMIT),
+            )));
+        }
+        config::update(|c| c.max_wasm_stack = bytes);
+    }
+    if features.compile_threads == Some(0) {
+        return Err(Error::from_reason(errors::coded(errors::ERR_INVALID_INPUT, "compileThreads must be at least 1")));
+    }
+    config::update(|c| {
+        if let Some(on) = features.parallel_compilation {
+            c.parallel_compilation = on;
+        }
+        if let Some(n) = features.compile_threads {
+            c.compile_threads = Some(n as usize);
+        }
+    });
+    Ok(())
+}
+
+#[napi(object)]
+pub struct RuntimeStats {
+    /// Bytes currently held in channel buffers
+    pub channel_buffer_bytes: i64,
+    /// Highest `channelBufferBytes` seen since startup
+    pub channel_buffer_high_water: i64,
+    /// Configured budget, if any
+    pub channel_buffer_budget_bytes: Option<i64>,
+    pub max_module_bytes: i64,
+    pub max_args_per_task: u32,
+    pub max_tasks_per_batch: u32,
+    /// Pooled calls that reused a warm instance
+    pub instance_pool_hits: i64,
+    /// Pooled calls that had to instantiate
+    pub instance_pool_misses: i64,
+    /// Pooled instances retired by `resetEvery`
+    pub instance_pool_resets: i64,
+    /// Idle pooled instances across all modules
+    pub instance_pool_size: u32,
+    /// Batch tasks logged for exceeding `slowTaskThresholdMs`
+    pub slow_tasks_logged: i64,
+    /// Whether guests run through the deterministic dispatcher
+    pub deterministic_dispatch: bool,
+    /// Its seed, while it is on
+    pub dispatch_seed: Option<i64>,
+    /// Guest executions accepted and waiting to start
+    pub queued_executions: u32,
+    /// Configured cap, if any
+    pub max_queued_executions: Option<u32>,
+    /// Calls refused with ERR_OVERLOADED since startup
+    pub overload_rejections: i64,
+    /// Executions failed with ERR_QUEUE_TIMEOUT since startup
+    pub queue_timeouts: i64,
+    /// Compiled modules in the module cache, pinned ones included
+    pub module_cache_size: u32,
+    pub module_cache_capacity: u32,
+    pub pinned_modules: u32,
+    /// Unpinned modules evicted to stay within capacity since startup
+    pub module_cache_evictions: i64,
+    /// Modules added while every other cached module was pinned, leaving the
+    /// cache over capacity; a steady climb means too much is pinned
+    pub module_cache_pinned_overflows: i64,
+    /// Cache misses that waited for a compilation of the same module already
+    /// in flight instead of compiling it again
+    pub module_cache_deduplicated_compiles: i64,
+    /// Compilations waiting for a slot
+    pub queued_compilations: u32,
+    /// Compilations holding one
+    pub running_compilations: u32,
+    pub max_concurrent_compilations: u32,
+}
+
+#[napi]
+pub fn runtime_stats() -> RuntimeStats {
+    let usage = channels::buffer_usage();
+    let config = config::get();
+    let pools = executor::pool_stats();
+    let queue = scheduler::queue_stats();
+    let cache = executor::cache_stats();
+    let compiles = compiles::stats();
+    RuntimeStats {
+        channel_buffer_bytes: usage.used as i64,
+        channel_buffer_high_water: usage.high_water as i64,
+        channel_buffer_budget_bytes: usage.budget.map(|b| b as i64),
+        max_module_bytes: config.max_module_bytes as i64,
+        max_args_per_task: config.max_args.min(u32::MAX as usize) as u32,
+        max_tasks_per_batch: config.max_batch_tasks.min(u32::MAX as usize) as u32,
+        instance_pool_hits: pools.hits as i64,
+        instance_pool_misses: pools.misses as i64,
+        instance_pool_resets: pools.resets as i64,
+        instance_pool_size: pools.size as u32,
+        slow_tasks_logged: metrics::slow_tasks_logged() as i64,
+        deterministic_dispatch: scheduler::deterministic_dispatch().is_some(),
+        dispatch_seed: scheduler::deterministic_dispatch().map(|seed| seed as i64),
+        queued_executions: queue.queued.min(u32::MAX as usize) as u32,
+        max_queued_executions: config.max_queued_executions.map(|n| n.min(u32::MAX as usize) as u32),
+        overload_rejections: queue.rejections as i64,
+        queue_timeouts: queue.timeouts as i64,
+        module_cache_size: cache.size.min(u32::MAX as usize) as u32,
+        module_cache_capacity: cache.capacity.min(u32::MAX as usize) as u32,
+        pinned_modules: cache.pinned.min(u32::MAX as usize) as u32,
+        module_cache_evictions: cache.evictions as i64,
+        module_cache_pinned_overflows: cache.overflows as i64,
+        module_cache_deduplicated_compiles: cache.deduplicated as i64,
+        queued_compilations: compiles.queued.min(u32::MAX as usize) as u32,
+        running_compilations: compiles.running.min(u32::MAX as usize) as u32,
+        max_concurrent_compilations: config.max_concurrent_compilations.min(u32::MAX as usize) as u32,
+    }
+}
+
+// --- Tags: metrics and quotas ---
+
+#[napi(object)]
+pub struct TagQuota {
+    /// Tasks that may run under the tag at once
+    pub max_concurrent: Option<u32>,
+    /// Fuel the tag may burn per minute, as a token bucket refilled every second
+    pub max_fuel_per_minute: Option<i64>,
+}
+
+/// Limit what tasks tagged `tag` may use; tasks over a limit fail with
+/// ERR_QUOTA before running. A quota with neither field removes it.
+#[napi]
+pub fn set_tag_quota(tag: String, quota: TagQuota) {
+    quotas::set(
+        &tag,
+        quotas::Quota {
+            max_concurrent: quota.max_concurrent,
+            max_fuel_per_minute: quota.max_fuel_per_minute.map(|f| f.max(0) as u64),
+        },
+    );
+}
+
+#[napi(object)]
+pub struct TagMetrics {
+    /// The caller's tag, "default" for untagged executions, or "other" for
+    /// tags beyond the first 64 seen
+    pub tag: String,
+    pub executions: i64,
+    pub errors: i64,
+    pub fuel_used: i64,
+    pub wall_time_ms: f64,
+    /// Latency quantiles, as the upper bound of a power-of-two bucket
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+fn millis(d: std::time::Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+/// Execution metrics per tag since startup. Counts every exec and batch
+/// task except those of concurrentWasmShared and the channel variants.
+#[napi]
+pub fn metrics_snapshot() -> Vec<TagMetrics> {
+    metrics::snapshot()
+        .into_iter()
+        .map(|(tag, m)| TagMetrics {
+            tag,
+            executions: m.executions as i64,
+            errors: m.errors as i64,
+            fuel_used: m.fuel_used.min(i64::MAX as u64) as i64,
+            wall_time_ms: millis(m.wall_time),
+            p50_ms: millis(m.latency.quantile(0.5)),
+            p90_ms: millis(m.latency.quantile(0.9)),
+            p99_ms: millis(m.latency.quantile(0.99)),
+            max_ms: millis(m.latency.max()),
+        })
+        .collect()
+}
+
 #[napi]
 pub async fn spawn_task(value: i64) -> Result<i64> {
     let result = scheduler::TOKIO_RT
@@ -35,141 +600,2045 @@ pub async fn concurrent_all(values: Vec<i64>) -> Result<Vec<i64>> {
     Ok(results)
 }
 
+// --- Fuel top-ups ---
+
+/// One guest `tova.request_fuel` call, as `setFuelPolicy`'s callback sees it.
+#[napi(object)]
+pub struct FuelRequestInfo {
+    /// Fuel the guest asked for
+    pub amount: i64,
+    /// Fuel its call had left when it asked
+    pub remaining: i64,
+    /// Fuel earlier requests of the same call were granted
+    pub granted: i64,
+    pub task_index: i64,
+}
+
+/// Decide guests' `tova.request_fuel` top-ups: `policy` gets a
+/// FuelRequestInfo and returns the fuel to grant synchronously (0 or less
+/// denies). The guest's worker waits for the answer for up to `timeoutMs`
+/// (default 1000) and is denied without one, as happens while the JS thread
+/// is itself blocked. Pass null to remove the policy; without one every
+/// request is denied.
+#[napi]
+pub fn set_fuel_policy(
+    policy: Option<ThreadsafeFunction<FuelRequestInfo, i64, FuelRequestInfo, Status, false>>,
+    timeout_ms: Option<u32>,
+) {
+    let timeout = std::time::Duration::from_millis(timeout_ms.unwrap_or(1000) as u64);
+    let policy = policy.map(|callback| -> host_imports::FuelPolicy {
+        Arc::new(move |request: &host_imports::FuelRequest| {
+            let info = FuelRequestInfo {
+                amount: request.amount.min(i64::MAX as u64) as i64,
+                remaining: request.remaining.min(i64::MAX as u64) as i64,
+                granted: request.granted.min(i64::MAX as u64) as i64,
+                task_index: request.task_index,
+            };
+            let (answer, answered) = crossbeam_channel::bounded(1);
+            let status = callback.call_with_return_value(info, ThreadsafeFunctionCallMode::NonBlocking, move |grant: Result<i64>, _env| {
+                // A throwing or non-numeric policy denies
+                let _ = answer.send(grant.map_or(0, |g| g.max(0) as u64));
+                Ok(())
+            });
+            if status != Status::Ok {
+                return 0;
+            }
+            answered.recv_timeout(timeout).unwrap_or(0)
+        })
+    });
+    host_imports::set_fuel_policy(policy);
+}
+
+// --- Shared data ---
+
+/// Keep a copy of `data` host-side for guests to read with
+/// tova.shared_data_read. Pass the returned id in an exec's `sharedData`.
+#[napi]
+pub fn shared_data_register(data: Buffer) -> i64 {
+    lifecycle::tag(shared_data::register(data.to_vec()))
+}
+
+/// Forget a segment; its bytes are freed once calls already given it have
+/// finished. Returns false if it was not registered.
+#[napi]
+pub fn shared_data_unregister(id: i64) -> Result<bool> {
+    Ok(shared_data::unregister(untag(id, "shared data")?))
+}
+
+// --- Cancel tokens ---
+
+/// A token for stopping work started with it: execs, chunked batches,
+/// producers and channel waits given it as `cancelToken` stop with
+/// ERR_CANCELLED when it is cancelled, and fail at once if it already is.
+/// Created in `scope`, it is cancelled when the scope's work is, and
+/// released when the scope closes.
+#[napi]
+pub fn cancel_token_create(scope: Option<i64>) -> Result<i64> {
+    let Some(scope) = scope else {
+        return Ok(lifecycle::tag(cancel_tokens::create()));
+    };
+    let scope = untag(scope, "scope")?;
+    let id = scopes::token_id(scope).and_then(cancel_tokens::child).map_err(Error::from_reason)?;
+    adopt(Some(scope), scopes::Resource::CancelToken(id))?;
+    Ok(lifecycle::tag(id))
+}
+
+/// A token cancelled whenever `parent` is, so cancelling the root of a tree
+/// tears down everything started under it. Cancelling the child leaves the
+/// parent alone.
+#[napi]
+pub fn cancel_token_child(parent: i64) -> Result<i64> {
+    cancel_tokens::child(untag(parent, "cancel token")?)
+        .map(lifecycle::tag)
+        .map_err(Error::from_reason)
+}
+
+/// Cancel a token and its children; false if it already was cancelled.
+#[napi]
+pub fn cancel_token_cancel(id: i64) -> Result<bool> {
+    cancel_tokens::cancel(untag(id, "cancel token")?).map_err(Error::from_reason)
+}
+
+#[napi]
+pub fn cancel_token_is_cancelled(id: i64) -> Result<bool> {
+    cancel_tokens::get(untag(id, "cancel token")?)
+        .map(|token| token.is_cancelled())
+        .map_err(Error::from_reason)
+}
+
+/// Forget a token; work already started with it can still be cancelled
+/// through its parent. Returns false if it was not registered.
+#[napi]
+pub fn cancel_token_release(id: i64) -> Result<bool> {
+    Ok(cancel_tokens::release(untag(id, "cancel token")?))
+}
+
+/// The token `id` names, failing with ERR_CANCELLED if it is already
+/// cancelled so that nothing starts under it.
+fn live_cancel_token(id: Option<i64>) -> Result<Option<Arc<cancel_tokens::Token>>> {
+    let Some(id) = id else {
+        return Ok(None);
+    };
+    let token = cancel_tokens::get(untag(id, "cancel token")?).map_err(Error::from_reason)?;
+    token.check().map_err(Error::from_reason)?;
+    Ok(Some(token))
+}
+
+// --- Scopes ---
+
+/// A scope to create resources and run work in, nested in `parent` if
+/// given. Channels, module handles, cancel tokens and producers created with
+/// `scope`, and execs run with it, belong to it until `scopeClose`.
+#[napi]
+pub fn scope_create(parent: Option<i64>) -> Result<i64> {
+    let parent = parent.map(|parent| untag(parent, "scope")).transpose()?;
+    scopes::create(parent).map(lifecycle::tag).map_err(Error::from_reason)
+}
+
+#[napi(object)]
+pub struct ScopeCloseOptions {
+    /// Cancel the scope's running execs and producers at once (default false)
+    pub cancel: Option<bool>,
+    /// Without `cancel`, how long running execs get to finish before they
+    /// are cancelled (default: as long as they take)
+    pub grace_ms: Option<u32>,
+}
+
+#[napi(object)]
+pub struct ScopeReport {
+    /// Nested scopes closed along with this one; the counts below include
+    /// what they cleaned up
+    pub scopes: u32,
+    pub channels: u32,
+    pub modules: u32,
+    pub cancel_tokens: u32,
+    pub producers: u32,
+    /// Execs that settled on their own, successfully or not
+    pub jobs_completed: u32,
+    /// Execs stopped with ERR_CANCELLED
+    pub jobs_cancelled: u32,
+}
+
+/// Close a scope: close its nested scopes first, wait for (or cancel) its
+/// execs, then destroy everything created in it. Resolves with what was
+/// cleaned up; resources already destroyed by hand are not counted.
+#[napi]
+pub async fn scope_close(id: i64, options: Option<ScopeCloseOptions>) -> Result<ScopeReport> {
+    let scope = untag(id, "scope")?;
+    let options = scopes::CloseOptions {
+        cancel: options.as_ref().and_then(|o| o.cancel).unwrap_or(false),
+        grace: timeout_from_ms(options.and_then(|o| o.grace_ms)),
+    };
+    let report = scheduler::TOKIO_RT
+        .spawn_blocking(move || scopes::close(scope, options))
+        .await
+        .map_err(|e| Error::from_reason(format!("join: {}", e)))?
+        .map_err(Error::from_reason)?;
+    Ok(ScopeReport {
+        scopes: report.scopes,
+        channels: report.channels,
+        modules: report.modules,
+        cancel_tokens: report.cancel_tokens,
+        producers: report.producers,
+        jobs_completed: report.jobs_completed,
+        jobs_cancelled: report.jobs_cancelled,
+    })
+}
+
+/// Hand a resource just created to `scope`, if given; it is destroyed
+/// again if the scope has closed meanwhile.
+fn adopt(scope: Option<u64>, resource: scopes::Resource) -> Result<()> {
+    match scope {
+        Some(scope) => scopes::adopt(scope, resource).map_err(Error::from_reason),
+        None => Ok(()),
+    }
+}
+
+/// Start a job in `scope`, if given. Returns it with the token the work
+/// runs under: `token` joined with the scope's.
+fn scoped_job(
+    scope: Option<i64>,
+    token: Option<Arc<cancel_tokens::Token>>,
+) -> Result<(Option<scopes::Job>, Option<Arc<cancel_tokens::Token>>)> {
+    let Some(scope) = scope else {
+        return Ok((None, token));
+    };
+    let (job, token) = scopes::begin_job(untag(scope, "scope")?, token.as_ref()).map_err(Error::from_reason)?;
+    Ok((Some(job), Some(token)))
+}
+
 // --- Channels ---
 
+#[napi(object)]
+pub struct ChannelOptions {
+    /// Serve senders blocked on a full buffer in arrival order, at some
+    /// throughput cost (default false)
+    pub fair: Option<bool>,
+    /// Drop messages that waited in the buffer longer than this instead of
+    /// delivering them; counted in `expired`
+    pub ttl_ms: Option<u32>,
+    /// Stamp messages on send so `channelPurgeOlderThan` can age them, for
+    /// channels without a standing TTL (implied by `ttlMs`; default false)
+    pub timestamps: Option<bool>,
+    /// Grow the buffer while producers keep finding it full; not for fair
+    /// or rendezvous channels
+    pub adaptive: Option<ChannelAdaptive>,
+    /// Scope from `scopeCreate` that destroys the channel when it closes
+    pub scope: Option<i64>,
+}
+
+#[napi(object)]
+pub struct ChannelAdaptive {
+    /// Largest capacity to grow to, at least the initial one
+    pub max: u32,
+    /// Capacity multiplier for each step, above 1 (default 2)
+    pub growth_factor: Option<f64>,
+    /// Grow once more than this many sends within `windowMs` found the
+    /// buffer full (default 4)
+    pub full_sends: Option<u32>,
+    /// Default 1000
+    pub window_ms: Option<u32>,
+    /// Shrink back to the initial capacity after this long without a full
+    /// buffer (default never)
+    pub shrink_after_ms: Option<u32>,
+}
+
+fn adaptive_options(capacity: u32, fair: bool, adaptive: ChannelAdaptive) -> Result<channels::AdaptiveOptions> {
+    let invalid = |message: &str| Err(Error::from_reason(errors::coded(errors::ERR_INVALID_INPUT, format!("adaptive: {}", message))));
+    if fair || capacity == 0 {
+        return invalid("fair and rendezvous channels cannot be adaptive");
+    }
+    if adaptive.max < capacity {
+        return invalid("max must be at least the initial capacity");
+    }
+    let defaults = channels::AdaptiveOptions::up_to(adaptive.max as usize);
+    let growth_factor = adaptive.growth_factor.unwrap_or(defaults.growth_factor);
+    if !(growth_factor > 1.0 && growth_factor.is_finite()) {
+        return invalid("growthFactor must be a finite number above 1");
+    }
+    let ms = |ms: u32| std::time::Duration::from_millis(ms as u64);
+    Ok(channels::AdaptiveOptions {
+        growth_factor,
+        full_sends: adaptive.full_sends.unwrap_or(defaults.full_sends),
+        window: adaptive.window_ms.map_or(defaults.window, ms),
+        shrink_after: adaptive.shrink_after_ms.map(ms),
+        ..defaults
+    })
+}
+
 #[napi]
-pub fn channel_create(capacity: u32) -> i64 {
-    channels::create(capacity) as i64
+pub fn channel_create(capacity: u32, options: Option<ChannelOptions>) -> Result<i64> {
+    let scope = options.as_ref().and_then(|o| o.scope).map(|scope| untag(scope, "scope")).transpose()?;
+    let options = match options {
+        Some(o) => {
+            let fair = o.fair.unwrap_or(false);
+            channels::Options {
+                fair,
+                ttl: o.ttl_ms.map(|ms| std::time::Duration::from_millis(ms as u64)),
+                timestamps: o.timestamps.unwrap_or(false),
+                adaptive: o.adaptive.map(|adaptive| adaptive_options(capacity, fair, adaptive)).transpose()?,
+            }
+        }
+        None => channels::Options::default(),
+    };
+    let id = channels::create_with(capacity, options);
+    adopt(scope, scopes::Resource::Channel(id))?;
+    Ok(lifecycle::tag(id))
 }
 
 #[napi]
 pub fn channel_send(id: i64, value: i64) -> Result<bool> {
-    match channels::send(id as u64, value) {
+    match channels::send(untag(id, "channel")?, value) {
         Ok(sent) => Ok(sent),
         Err(e) => Err(Error::from_reason(e)),
     }
 }
 
 #[napi]
-pub fn channel_receive(id: i64) -> Option<i64> {
-    channels::receive(id as u64)
+pub fn channel_receive(id: i64) -> Result<Option<i64>> {
+    Ok(channels::receive(untag(id, "channel")?))
+}
+
+#[napi(object)]
+pub struct ChannelStat {
+    /// Values currently buffered
+    pub len: u32,
+    pub capacity: u32,
+    pub closed: bool,
+    pub fair: bool,
+    /// Sends that had to wait for a free slot; a high count on an unfair
+    /// channel with several producers is the sign to try `fair`
+    pub blocked_sends: i64,
+    /// Longest any of those sends waited
+    pub max_send_wait_ms: f64,
+    /// Messages dropped for age: past the TTL, or purged
+    pub expired: i64,
+    /// Times an adaptive channel moved to a new buffer; `capacity` is the
+    /// current one
+    pub resizes: i64,
+}
+
+/// Buffer and contention figures for a channel; null once it is gone.
+#[napi]
+pub fn channel_stat(id: i64) -> Result<Option<ChannelStat>> {
+    Ok(channels::stat(untag(id, "channel")?).map(|s| ChannelStat {
+        len: s.len as u32,
+        capacity: s.capacity as u32,
+        closed: s.closed,
+        fair: s.fair,
+        blocked_sends: s.blocked_sends as i64,
+        max_send_wait_ms: s.max_send_wait.as_secs_f64() * 1000.0,
+        expired: s.expired as i64,
+        resizes: s.resizes as i64,
+    }))
+}
+
+/// Drop every buffered message sent more than `ageMs` ago; receivers skip
+/// them from then on. Returns how many were dropped. The channel must have
+/// been created with `ttlMs` or `timestamps`.
+#[napi]
+pub fn channel_purge_older_than(id: i64, age_ms: u32) -> Result<u32> {
+    channels::purge_older_than(untag(id, "channel")?, std::time::Duration::from_millis(age_ms as u64)).map_err(Error::from_reason)
+}
+
+/// Close a channel. Sends fail from then on, including sends blocked on a
+/// full buffer; values already buffered can still be received.
+#[napi]
+pub fn channel_close(id: i64) -> Result<()> {
+    channels::close(untag(id, "channel")?);
+    Ok(())
+}
+
+/// Close a channel and return the values it still buffered, for callers that
+/// need to handle undelivered messages.
+#[napi]
+pub fn channel_close_and_drain(id: i64) -> Result<Vec<i64>> {
+    Ok(channels::close_and_drain(untag(id, "channel")?))
+}
+
+/// Snapshot of a channel's buffered, undelivered values and the options it
+/// was created with, for `channelRestore` after a restart. Nothing is taken
+/// out of the channel: sends and receives pause for the copy, and a send in
+/// flight when the dump starts may or may not make it in. Values already
+/// past their TTL are left out. Fails for a closed channel; use
+/// `channelCloseAndDrain` for those.
+#[napi]
+pub fn channel_dump(id: i64) -> Result<Buffer> {
+    channels::dump(untag(id, "channel")?).map(Buffer::from).map_err(Error::from_reason)
+}
+
+/// A new channel with the capacity, options and buffered values of a
+/// `channelDump`. Restored messages count their TTL from now. Fails with
+/// ERR_INVALID_INPUT for a corrupted or foreign buffer and
+/// ERR_CHANNEL_BUDGET when the values would not fit the buffer budget.
+#[napi]
+pub fn channel_restore(dump: Buffer) -> Result<i64> {
+    channels::restore(&dump).map(lifecycle::tag).map_err(Error::from_reason)
+}
+
+/// `channelDump` of every open channel in a context (owned or granted), or
+/// in the whole runtime without one, in one buffer for `channelsRestoreAll`.
+#[napi]
+pub fn channels_dump_all(ctx: Option<i64>) -> Result<Buffer> {
+    let ids = match ctx {
+        Some(ctx) => contexts::channels(untag(ctx, "channel context")?).map_err(Error::from_reason)?,
+        None => channels::open_ids(),
+    };
+    Ok(channels::dump_all(&ids, lifecycle::tag).into())
+}
+
+#[napi(object)]
+pub struct RestoredChannel {
+    /// The channel's id when it was dumped
+    pub dumped_id: i64,
+    pub id: i64,
+}
+
+/// Restore every channel of a `channelsDumpAll`, in dump order. Creates
+/// nothing unless every one restores.
+#[napi]
+pub fn channels_restore_all(dump: Buffer) -> Result<Vec<RestoredChannel>> {
+    let restored = channels::restore_all(&dump).map_err(Error::from_reason)?;
+    Ok(restored.into_iter().map(|(dumped_id, id)| RestoredChannel { dumped_id, id: lifecycle::tag(id) }).collect())
+}
+
+// --- Channel contexts ---
+
+/// Create a channel context: a capability table that scopes which channels a
+/// guest run with `channelContext` can reach.
+#[napi]
+pub fn context_create() -> i64 {
+    lifecycle::tag(contexts::create())
+}
+
+/// Create a channel owned by a context; it is closed when the context is destroyed.
+#[napi]
+pub fn channel_create_in(ctx: i64, capacity: u32) -> Result<i64> {
+    contexts::create_channel(untag(ctx, "channel context")?, capacity)
+        .map(lifecycle::tag)
+        .map_err(Error::from_reason)
+}
+
+/// Let guests in `ctx` use `channelId`. Returns the id those guests pass to
+/// the channel imports; any id not granted this way fails with "no such channel".
+#[napi]
+pub fn context_grant(ctx: i64, channel_id: i64) -> Result<i32> {
+    contexts::grant(untag(ctx, "channel context")?, untag(channel_id, "channel")?).map_err(Error::from_reason)
+}
+
+/// Destroy a context and close every channel it owns.
+#[napi]
+pub fn context_destroy(ctx: i64) -> Result<bool> {
+    Ok(contexts::destroy(untag(ctx, "channel context")?))
+}
+
+/// Close every channel a context owns or grants, so guests looping on
+/// `chan_receive_status` in it get CHAN_RECEIVE_CLOSED and return. Call it
+/// before `contextDestroy` to shut consumers down. Returns how many channels
+/// it closed.
+#[napi]
+pub fn channel_close_all_in_context(ctx: i64) -> Result<u32> {
+    contexts::close_all(untag(ctx, "channel context")?)
+        .map(|n| n as u32)
+        .map_err(Error::from_reason)
+}
+
+/// Call `callback` with every value arriving on the channel, in order, from
+/// a dedicated forwarding thread, until the channel ends or
+/// `channelUnsubscribe`. The subscriber consumes the values: it competes with
+/// any other receiver. Returns the subscription id.
+#[napi]
+pub fn channel_subscribe(id: i64, callback: ThreadsafeFunction<i64, (), i64, Status, false>) -> Result<i64> {
+    channels::subscribe(untag(id, "channel")?, move |value| {
+        callback.call(value, ThreadsafeFunctionCallMode::NonBlocking);
+    })
+    .map(lifecycle::tag)
+    .map_err(Error::from_reason)
+}
+
+/// Stop a subscription; false if it already ended.
+#[napi]
+pub fn channel_unsubscribe(subscription: i64) -> Result<bool> {
+    Ok(channels::unsubscribe(untag(subscription, "subscription")?))
+}
+
+/// Start a reader on the channel for pull-based, batched consumption (an
+/// async iterator on the JS side): each `channelReaderNext` resolves with up
+/// to `batch` values. Like a subscription, it competes with any other
+/// receiver. Returns the reader id.
+#[napi]
+pub fn channel_reader_create(channel_id: i64, batch: u32) -> Result<i64> {
+    readers::create(untag(channel_id, "channel")?, batch as usize)
+        .map(lifecycle::tag)
+        .map_err(Error::from_reason)
+}
+
+/// Wait until at least one value is available and resolve with the values
+/// buffered at that point, up to the reader's batch size, in channel order;
+/// null once the channel is closed and drained. The reader is gone after
+/// reporting null. Only one call may be pending per reader.
+#[napi]
+pub async fn channel_reader_next(reader_id: i64) -> Result<Option<Vec<i64>>> {
+    let answer = readers::next(untag(reader_id, "channel reader")?).map_err(Error::from_reason)?;
+    answer.await.map_err(|_| Error::from_reason("channel reader was closed"))
+}
+
+/// Stop a reader; a pending `channelReaderNext` rejects. False if it already ended.
+#[napi]
+pub fn channel_reader_close(reader_id: i64) -> Result<bool> {
+    Ok(readers::close(untag(reader_id, "channel reader")?))
+}
+
+// --- Channel fd bindings ---
+
+#[napi(object)]
+pub struct FdBindOptions {
+    /// Frames are bare 8-byte little-endian i64s, instead of a u32
+    /// little-endian byte length followed by that many bytes of them
+    /// (default false)
+    pub raw: Option<bool>,
+}
+
+fn fd_framing(options: Option<FdBindOptions>) -> fd_bindings::Framing {
+    match options.and_then(|o| o.raw) {
+        Some(true) => fd_bindings::Framing::Raw,
+        _ => fd_bindings::Framing::LengthPrefixed,
+    }
+}
+
+/// Send every i64 read from file descriptor `fd` (a pipe or unix socket)
+/// into the channel, so a process outside Bun can feed it. While the
+/// channel is full the fd is not read, so the writing process blocks. The
+/// binding takes `fd` over and closes it when it ends: at EOF, on a
+/// malformed frame, once the channel is closed, or on `channelUnbind`. It
+/// does not close the channel. Linux only. Returns the binding id.
+#[napi]
+pub fn channel_bind_reader_fd(id: i64, fd: i32, options: Option<FdBindOptions>) -> Result<i64> {
+    fd_bindings::bind_reader(untag(id, "channel")?, fd, fd_framing(options))
+        .map(lifecycle::tag)
+        .map_err(Error::from_reason)
+}
+
+/// Write every value received on the channel to file descriptor `fd`, in
+/// the same frames `channelBindReaderFd` reads. The writer competes with
+/// any other receiver, and ends, closing `fd`, once the channel is closed
+/// and drained, when the peer stops taking writes, or on `channelUnbind`;
+/// values it had taken by then are lost. Linux only. Returns the binding id.
+#[napi]
+pub fn channel_bind_writer_fd(id: i64, fd: i32, options: Option<FdBindOptions>) -> Result<i64> {
+    fd_bindings::bind_writer(untag(id, "channel")?, fd, fd_framing(options))
+        .map(lifecycle::tag)
+        .map_err(Error::from_reason)
+}
+
+/// End a reader or writer binding and close its fd; false if it already ended.
+#[napi]
+pub fn channel_unbind(binding_id: i64) -> Result<bool> {
+    Ok(fd_bindings::unbind(untag(binding_id, "channel binding")?))
+}
+
+// --- Host tasks ---
+
+fn timeout_from_ms(timeout_ms: Option<u32>) -> Option<std::time::Duration> {
+    timeout_ms.map(|ms| std::time::Duration::from_millis(ms as u64))
+}
+
+/// Feed `values` into a channel from a Rust-side task, waiting `intervalMs`
+/// before each send. Stops after the last value, at the first failed send,
+/// or on `channelProducerCancel` or cancelling `cancelToken`. Does not close
+/// the channel. Started in `scope`, it is stopped when the scope closes.
+/// Returns the producer id.
+#[napi]
+pub fn spawn_channel_producer(
+    channel_id: i64,
+    values: Vec<i64>,
+    interval_ms: u32,
+    cancel_token: Option<i64>,
+    scope: Option<i64>,
+) -> Result<i64> {
+    let mut token = live_cancel_token(cancel_token)?;
+    let scope = scope.map(|scope| untag(scope, "scope")).transpose()?;
+    if let Some(scope) = scope {
+        token = Some(scopes::token(scope, token.as_ref()).map_err(Error::from_reason)?);
+    }
+    let producer =
+        host_tasks::spawn_producer(untag(channel_id, "channel")?, values, std::time::Duration::from_millis(interval_ms as u64), token)
+            .map_err(Error::from_reason)?;
+    adopt(scope, scopes::Resource::Producer(producer))?;
+    Ok(lifecycle::tag(producer))
+}
+
+/// Stop a producer, even one blocked on a full channel; false if it already finished.
+#[napi]
+pub fn channel_producer_cancel(producer: i64) -> Result<bool> {
+    Ok(host_tasks::cancel_producer(untag(producer, "producer")?))
+}
+
+/// Receive up to `maxItems` values, resolving early when the channel ends or
+/// `timeoutMs` passes (omitted: no timeout).
+#[napi]
+pub async fn spawn_channel_collector(channel_id: i64, max_items: u32, timeout_ms: Option<u32>) -> Result<Vec<i64>> {
+    let id = untag(channel_id, "channel")?;
+    let timeout = timeout_from_ms(timeout_ms);
+    scheduler::TOKIO_RT
+        .spawn_blocking(move || host_tasks::collect(id, max_items as usize, timeout))
+        .await
+        .map_err(|e| Error::from_reason(format!("join: {}", e)))
+}
+
+/// Fold every value received until the channel ends or `timeoutMs` passes
+/// with `op`: "sum" (wrapping), "min", "max" or "count". Min and max of no
+/// values reject.
+#[napi]
+pub async fn spawn_channel_reducer(channel_id: i64, op: String, timeout_ms: Option<u32>) -> Result<i64> {
+    let id = untag(channel_id, "channel")?;
+    let op = host_tasks::ReduceOp::parse(&op).map_err(Error::from_reason)?;
+    let timeout = timeout_from_ms(timeout_ms);
+    scheduler::TOKIO_RT
+        .spawn_blocking(move || host_tasks::reduce(id, op, timeout))
+        .await
+        .map_err(|e| Error::from_reason(format!("join: {}", e)))?
+        .map_err(Error::from_reason)
+}
+
+/// Resolve with the first `n` values received, consuming them, or with
+/// fewer if the channel ends first. Rejects with ERR_TIMEOUT once
+/// `timeoutMs` passes (omitted: no timeout), and with ERR_CANCELLED once
+/// `cancelToken` is cancelled; values taken by then are lost.
+#[napi]
+pub async fn channel_wait_count(id: i64, n: u32, timeout_ms: Option<u32>, cancel_token: Option<i64>) -> Result<Vec<i64>> {
+    let id = untag(id, "channel")?;
+    let timeout = timeout_from_ms(timeout_ms);
+    let token = live_cancel_token(cancel_token)?;
+    let (_wait, stop) = host_tasks::register_wait(token.as_ref());
+    scheduler::TOKIO_RT
+        .spawn_blocking(move || host_tasks::wait_count(id, n as usize, timeout, &stop))
+        .await
+        .map_err(|e| Error::from_reason(format!("join: {}", e)))?
+        .map_err(Error::from_reason)
+}
+
+/// Consume values until `value` arrives: true once it does, false if the
+/// channel ends first. Values received before it are discarded. Rejects
+/// with ERR_TIMEOUT once `timeoutMs` passes and with ERR_CANCELLED once
+/// `cancelToken` is cancelled.
+#[napi]
+pub async fn channel_wait_value(id: i64, value: i64, timeout_ms: Option<u32>, cancel_token: Option<i64>) -> Result<bool> {
+    let id = untag(id, "channel")?;
+    let timeout = timeout_from_ms(timeout_ms);
+    let token = live_cancel_token(cancel_token)?;
+    let (_wait, stop) = host_tasks::register_wait(token.as_ref());
+    scheduler::TOKIO_RT
+        .spawn_blocking(move || host_tasks::wait_value(id, value, timeout, &stop))
+        .await
+        .map_err(|e| Error::from_reason(format!("join: {}", e)))?
+        .map_err(Error::from_reason)
+}
+
+// --- Diagnostics ---
+
+/// A channel operation that is currently blocked.
+#[napi(object)]
+pub struct BlockedOp {
+    pub channel_id: i64,
+    /// "send" (buffer full) or "receive" (buffer empty)
+    pub op: String,
+    /// "host" for napi/Rust callers, "guest" for WASM channel imports
+    pub task: String,
+    pub waited_ms: f64,
+}
+
+fn wait_op_name(op: diagnostics::WaitOp) -> String {
+    match op {
+        diagnostics::WaitOp::Send => "send",
+        diagnostics::WaitOp::Receive => "receive",
+    }
+    .to_string()
+}
+
+fn task_kind_name(task: diagnostics::TaskKind) -> String {
+    match task {
+        diagnostics::TaskKind::Host => "host",
+        diagnostics::TaskKind::Guest => "guest",
+    }
+    .to_string()
+}
+
+impl From<diagnostics::BlockedWait> for BlockedOp {
+    fn from(w: diagnostics::BlockedWait) -> Self {
+        BlockedOp {
+            channel_id: lifecycle::tag(w.channel),
+            op: wait_op_name(w.op),
+            task: task_kind_name(w.task),
+            waited_ms: w.waited.as_secs_f64() * 1000.0,
+        }
+    }
+}
+
+/// Channel operations blocked right now, longest wait first. When every
+/// entry is waiting on another one's channel, the workload is deadlocked.
+#[napi]
+pub fn diagnostics_blocked() -> Vec<BlockedOp> {
+    diagnostics::blocked().into_iter().map(BlockedOp::from).collect()
+}
+
+/// Call `callback` with the blocked-op snapshot whenever a channel wait
+/// exceeds `thresholdMs`. Each stuck wait is reported once; enabling again
+/// replaces the previous watchdog.
+#[napi]
+pub fn enable_stall_watchdog(
+    threshold_ms: u32,
+    callback: ThreadsafeFunction<Vec<BlockedOp>, (), Vec<BlockedOp>, Status, false>,
+) -> Result<()> {
+    if threshold_ms == 0 {
+        return Err(Error::from_reason("thresholdMs must be greater than 0"));
+    }
+    diagnostics::start_watchdog(std::time::Duration::from_millis(threshold_ms as u64), move |snapshot| {
+        let ops = snapshot.into_iter().map(BlockedOp::from).collect();
+        callback.call(ops, ThreadsafeFunctionCallMode::NonBlocking);
+    });
+    Ok(())
+}
+
+#[napi]
+pub fn disable_stall_watchdog() -> bool {
+    diagnostics::stop_watchdog()
+}
+
+/// Something the runtime watchdog found running for longer than its
+/// threshold.
+#[napi(object)]
+pub struct WatchdogEntry {
+    /// Id to pass to `watchdogKill`
+    pub entry_id: i64,
+    /// "exec" for a guest call, "send" or "receive" for a blocked channel
+    /// operation
+    pub kind: String,
+    pub age_ms: f64,
+    /// Hash of the module being run, as hex ("exec" only)
+    pub module_hash: Option<String>,
+    /// Function being called ("exec" only)
+    pub func: Option<String>,
+    /// Channel waited on ("send" and "receive" only)
+    pub channel_id: Option<i64>,
+    /// "host" or "guest" side of a channel wait
+    pub task: Option<String>,
+    /// Whether `watchdogKill` can stop it
+    pub killable: bool,
+}
+
+impl From<watchdog::Entry> for WatchdogEntry {
+    fn from(e: watchdog::Entry) -> Self {
+        let mut entry = WatchdogEntry {
+            entry_id: e.id as i64,
+            kind: "exec".to_string(),
+            age_ms: e.age.as_secs_f64() * 1000.0,
+            module_hash: None,
+            func: None,
+            channel_id: None,
+            task: None,
+            killable: e.killable(),
+        };
+        match e.subject {
+            watchdog::Subject::Exec { module, func } => {
+                entry.module_hash = Some(format!("{:016x}", module));
+                entry.func = Some(func);
+            }
+            watchdog::Subject::Wait { channel, op, task } => {
+                entry.kind = wait_op_name(op);
+                entry.channel_id = Some(lifecycle::tag(channel));
+                entry.task = Some(task_kind_name(task));
+            }
+        }
+        entry
+    }
+}
+
+/// Executions and channel operations older than the watchdog threshold
+/// (`watchdogThresholdMs`) right now, oldest first. Works whether or not
+/// the sampling watchdog runs.
+#[napi]
+pub fn watchdog_report() -> Vec<WatchdogEntry> {
+    watchdog::report(config::get().watchdog_threshold).into_iter().map(WatchdogEntry::from).collect()
+}
+
+/// Call `callback` with the watchdog's report whenever a sampled entry
+/// newly crosses the threshold; each stuck entry is reported once. Takes
+/// effect while the watchdog runs (`configureRuntime({ watchdog: true })`);
+/// null removes the callback.
+#[napi]
+pub fn set_watchdog_callback(
+    callback: Option<ThreadsafeFunction<Vec<WatchdogEntry>, (), Vec<WatchdogEntry>, Status, false>>,
+) {
+    watchdog::set_callback(callback.map(|callback| -> Arc<dyn Fn(Vec<watchdog::Entry>) + Send + Sync> {
+        Arc::new(move |entries: Vec<watchdog::Entry>| {
+            let entries = entries.into_iter().map(WatchdogEntry::from).collect();
+            callback.call(entries, ThreadsafeFunctionCallMode::NonBlocking);
+        })
+    }));
+}
+
+/// Force-cancel a running execution from the watchdog report: the guest
+/// stops at its next epoch check and its call fails with ERR_CANCELLED.
+/// False if `entryId` is not a running execution (it finished, or is a
+/// channel wait, which cannot be killed).
+#[napi]
+pub fn watchdog_kill(entry_id: i64) -> bool {
+    entry_id >= 0 && watchdog::kill(entry_id as u64)
+}
+
+// --- WASM execution ---
+
+#[napi(object)]
+pub struct WasmTask {
+    /// Module bytes; tasks sharing one Buffer share one copy
+    pub wasm: Option<Buffer>,
+    /// Precompiled module handle from `moduleCompile`, used instead of `wasm`
+    pub module: Option<i64>,
+    pub func: String,
+    pub args: Vec<i64>,
+    /// Float arguments, used instead of `args` for functions over f64.
+    /// Only concurrentWasmShared and concurrentWasmSharedF64 take them, and
+    /// a batch is either all-integer or all-float.
+    pub f64_args: Option<Vec<f64>>,
+    /// Who the task runs for: its metrics are attributed to the tag and it
+    /// counts against the tag's quota (see `setTagQuota`). Not applied by
+    /// concurrentWasmShared or the channel variants.
+    pub tag: Option<String>,
+}
+
+/// Run one task metered under its tag's quota and metrics.
+fn run_task(wasm: &executor::WasmInput, func: &str, args: &[i64], tag: Option<&str>) -> std::result::Result<i64, String> {
+    quotas::run_tagged(tag, || executor::exec_wasm_metered(wasm, func, args)).result
+}
+
+/// Accept `executions` guest executions, or fail with ERR_OVERLOADED; see
+/// scheduler::enqueue.
+fn enqueue(executions: usize, queue_timeout_ms: Option<u32>) -> Result<Vec<scheduler::QueueSlot<'static>>> {
+    let timeout = queue_timeout_ms.map(|ms| std::time::Duration::from_millis(ms as u64));
+    scheduler::enqueue(executions, timeout).map_err(Error::from_reason)
+}
+
+/// Reject a single call over the configured limits, before anything is copied.
+fn check_call(module_bytes: Option<usize>, args: usize) -> Result<()> {
+    config::get().check_call(None, module_bytes, args).map_err(Error::from_reason)
+}
+
+/// Reject a batch, or any task in it, over the configured limits before any
+/// task is resolved or spawned. Only shared batches take `f64Args`.
+fn check_tasks(tasks: &[WasmTask]) -> Result<()> {
+    check_task_limits(tasks)?;
+    match tasks.iter().position(|t| t.f64_args.is_some()) {
+        Some(i) => Err(Error::from_reason(errors::coded(
+            errors::ERR_INVALID_INPUT,
+            format!("task {}: f64Args is only supported by concurrentWasmShared", i),
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn check_task_limits(tasks: &[WasmTask]) -> Result<()> {
+    let config = config::get();
+    config.check_batch(tasks.len()).map_err(Error::from_reason)?;
+    for (i, task) in tasks.iter().enumerate() {
+        let module_bytes = if task.module.is_some() { None } else { task.wasm.as_ref().map(|w| w.len()) };
+        let nargs = task.args.len() + task.f64_args.as_ref().map_or(0, Vec::len);
+        config.check_call(Some(i), module_bytes, nargs).map_err(Error::from_reason)?;
+    }
+    Ok(())
+}
+
+fn resolve_task(resolver: &mut executor::WasmResolver, task: &WasmTask) -> Result<executor::WasmInput> {
+    match (task.module, &task.wasm) {
+        (Some(handle), _) => executor::precompiled(untag(handle, "module handle")?).map_err(Error::from_reason),
+        (None, Some(wasm)) => Ok(resolver.resolve(wasm)),
+        (None, None) => Err(Error::from_reason("task needs either `wasm` or `module`".to_string())),
+    }
+}
+
+/// How a call's guest execution is bounded: "fuel", "epoch" or "none", or
+/// the configured `defaultMetering` when not given.
+fn metering(requested: &Option<String>) -> Result<executor::Metering> {
+    match requested {
+        Some(metering) => executor::Metering::parse(metering).map_err(Error::from_reason),
+        None => Ok(config::get().default_metering),
+    }
+}
+
+#[napi(object)]
+pub struct CompileOptions {
+    /// Metering every exec of the handle runs under (default: `defaultMetering`)
+    pub metering: Option<String>,
+    /// Scope from `scopeCreate` that releases the handle when it closes
+    pub scope: Option<i64>,
+}
+
+/// Compile a module once and return a handle that exec calls can use in
+/// place of the bytes. The module stays alive until `moduleRelease`.
+#[napi]
+pub async fn module_compile(wasm: Buffer, options: Option<CompileOptions>) -> Result<i64> {
+    check_call(Some(wasm.len()), 0)?;
+    let scope = options.as_ref().and_then(|o| o.scope).map(|scope| untag(scope, "scope")).transpose()?;
+    let metering = metering(&options.and_then(|o| o.metering))?;
+    let wasm_bytes = wasm.to_vec();
+    let handle = scheduler::TOKIO_RT
+        .spawn_blocking(move || executor::precompile(&wasm_bytes, metering))
+        .await
+        .map_err(|e| Error::from_reason(format!("join: {}", e)))?
+        .map_err(Error::from_reason)?;
+    adopt(scope, scopes::Resource::Module(handle))?;
+    Ok(lifecycle::tag(handle))
+}
+
+#[napi]
+pub fn module_release(handle: i64) -> Result<bool> {
+    Ok(executor::release_module(untag(handle, "module handle")?))
+}
+
+#[napi(object)]
+pub struct ModuleRef {
+    /// Module bytes
+    pub wasm: Option<Buffer>,
+    /// Module handle from `moduleCompile`, used instead of `wasm`
+    pub module: Option<i64>,
+    /// Metering the bytes run under (default: `defaultMetering`); a handle
+    /// has its own
+    pub metering: Option<String>,
+}
+
+fn resolve_ref(target: &ModuleRef) -> Result<executor::WasmInput> {
+    match (target.module, &target.wasm) {
+        (Some(handle), _) => executor::precompiled(untag(handle, "module handle")?).map_err(Error::from_reason),
+        (None, Some(wasm)) => {
+            check_call(Some(wasm.len()), 0)?;
+            Ok(executor::resolve_wasm_with(wasm, metering(&target.metering)?))
+        }
+        (None, None) => Err(Error::from_reason("module ref needs either `wasm` or `module`".to_string())),
+    }
+}
+
+/// Keep a module in the module cache whatever the eviction pressure,
+/// compiling it first if needed. Returns false if it already was pinned.
+/// Fails with ERR_PIN_BUDGET when pinned modules already take
+/// `maxPinnedCacheFraction` of `moduleCacheCapacity`.
+#[napi]
+pub async fn pin_module(target: ModuleRef) -> Result<bool> {
+    let wasm = resolve_ref(&target)?;
+    scheduler::TOKIO_RT
+        .spawn_blocking(move || executor::pin(&wasm))
+        .await
+        .map_err(|e| Error::from_reason(format!("join: {}", e)))?
+        .map_err(Error::from_reason)
+}
+
+/// Make a pinned module evictable again. Returns false if it was not pinned.
+#[napi]
+pub fn unpin_module(target: ModuleRef) -> Result<bool> {
+    Ok(executor::unpin(&resolve_ref(&target)?))
+}
+
+/// Deploy-time setup: compile every module the cache lacks and, with `pin`,
+/// pin each one, so first requests find them warm. A refused pin fails the
+/// call with ERR_PIN_BUDGET and leaves the earlier modules pinned. Returns
+/// how many modules had to be compiled.
+#[napi]
+pub async fn prefetch_modules(wasms: Vec<Buffer>, pin: bool, options: Option<CompileOptions>) -> Result<u32> {
+    let metering = metering(&options.and_then(|o| o.metering))?;
+    let mut resolver = executor::WasmResolver::with_metering(metering);
+    let mut inputs = Vec::with_capacity(wasms.len());
+    for wasm in &wasms {
+        check_call(Some(wasm.len()), 0)?;
+        inputs.push(resolver.resolve(wasm));
+    }
+    let compiled = scheduler::TOKIO_RT
+        .spawn_blocking(move || executor::prefetch(&inputs, pin))
+        .await
+        .map_err(|e| Error::from_reason(format!("join: {}", e)))?
+        .map_err(Error::from_reason)?;
+    Ok(compiled as u32)
+}
+
+#[napi(object)]
+pub struct WasmInfo {
+    /// "module.name" for each import
+    pub imports: Vec<String>,
+    /// Exported function names
+    pub exports: Vec<String>,
+    /// The module runs a start function when instantiated
+    pub has_start: bool,
+}
+
+/// Compile (or fetch from the cache) and describe a module without running it.
+#[napi]
+pub async fn inspect_wasm(wasm: Buffer) -> Result<WasmInfo> {
+    check_call(Some(wasm.len()), 0)?;
+    let wasm = executor::resolve_wasm(&wasm);
+    let info = scheduler::TOKIO_RT
+        .spawn_blocking(move || executor::inspect(&wasm))
+        .await
+        .map_err(|e| Error::from_reason(format!("join: {}", e)))?
+        .map_err(Error::from_reason)?;
+    Ok(WasmInfo { imports: info.imports, exports: info.exports, has_start: info.has_start })
+}
+
+#[napi(object)]
+#[derive(Clone, Default)]
+pub struct ExecOptions {
+    /// Run the module's start function while instantiating (default true).
+    /// With false, a module that has one is rejected with ERR_START_FUNCTION
+    /// rather than run; wasmtime cannot instantiate without running it.
+    pub run_start: Option<bool>,
+    /// Run on a warm instance of the module instead of instantiating per call
+    pub instance_pool: Option<InstancePoolOptions>,
+    /// Attribute the call to this tag's metrics and quota
+    pub tag: Option<String>,
+    /// Imports the module may have, as "module.name"; any other fails with
+    /// ERR_FORBIDDEN_IMPORT before instantiation (default: unchecked)
+    pub allowed_imports: Option<Vec<String>>,
+    /// Import families to allow, added to `allowedImports`
+    pub capabilities: Option<Capabilities>,
+    /// "fuel" counts fuel (ERR_OUT_OF_FUEL past the limit), "epoch" only
+    /// enforces the epoch time limit, "none" runs trusted code unbounded
+    /// without paying for fuel instrumentation (default: `defaultMetering`).
+    /// Module handles keep the metering they were compiled with.
+    pub metering: Option<String>,
+    /// Fail with ERR_QUEUE_TIMEOUT, without running, if the call waits this
+    /// long for a worker (default: waits as long as it takes)
+    pub queue_timeout_ms: Option<u32>,
+    /// Shared data segments the guest may read, as ids from
+    /// `sharedDataRegister`; the guest names them by position in this list.
+    /// The call holds them from when it starts, so unregistering one
+    /// mid-call does not affect it.
+    pub shared_data: Option<Vec<i64>>,
+    /// Token from `cancelTokenCreate`; cancelling it stops the guest with
+    /// ERR_CANCELLED, including while it waits on a channel, and a call
+    /// given a cancelled one fails without running
+    pub cancel_token: Option<i64>,
+    /// Scope from `scopeCreate` to run the call in: closing the scope waits
+    /// for it, or cancels it
+    pub scope: Option<i64>,
+}
+
+#[napi(object)]
+#[derive(Clone, Default)]
+pub struct Capabilities {
+    /// tova.chan_send and tova.chan_receive
+    pub channels: Option<bool>,
+    /// Reserved: no clock imports exist yet
+    pub clock: Option<bool>,
+    /// Reserved: no log imports exist yet
+    pub log: Option<bool>,
+    /// Reserved: no kv imports exist yet
+    pub kv: Option<bool>,
+    /// The tova_native kernels over guest memory: native_sort_f64,
+    /// native_sort_i64, native_sum_f64 and native_hash_xxh64
+    pub native: Option<bool>,
+}
+
+/// The allowed import list from `allowedImports` and `capabilities`, or
+/// None when neither is given.
+fn allowed_imports(allowed: &Option<Vec<String>>, capabilities: &Option<Capabilities>) -> Option<Vec<String>> {
+    if allowed.is_none() && capabilities.is_none() {
+        return None;
+    }
+    let mut imports = allowed.clone().unwrap_or_default();
+    if let Some(c) = capabilities {
+        let granted = host_imports::Capabilities {
+            channels: c.channels.unwrap_or(false),
+            clock: c.clock.unwrap_or(false),
+            log: c.log.unwrap_or(false),
+            kv: c.kv.unwrap_or(false),
+            native: c.native.unwrap_or(false),
+        };
+        imports.extend(granted.imports());
+    }
+    Some(imports)
+}
+
+#[napi(object)]
+#[derive(Clone, Default)]
+pub struct InstancePoolOptions {
+    /// Idle instances kept for the module (default: one per compute thread)
+    pub size: Option<u32>,
+    /// Re-instantiate after this many calls (default: never, for pure guests
+    /// whose globals and memory may carry over between calls)
+    pub reset_every: Option<u32>,
+}
+
+fn run_start(options: &Option<ExecOptions>) -> bool {
+    options.as_ref().and_then(|o| o.run_start).unwrap_or(true)
+}
+
+fn instance_pool(options: &Option<ExecOptions>) -> Option<executor::PoolOptions> {
+    options.as_ref()?.instance_pool.as_ref().map(pool_options)
+}
+
+fn pool_options(pool: &InstancePoolOptions) -> executor::PoolOptions {
+    executor::PoolOptions {
+        size: pool.size.map(|n| n as usize).unwrap_or_else(|| config::get().compute_threads),
+        reset_every: pool.reset_every.filter(|&n| n > 0).map(|n| n as u64),
+    }
+}
+
+/// The segments `sharedData` names, held for the call from here on.
+fn shared_segments(options: &Option<ExecOptions>) -> Result<Vec<shared_data::Segment>> {
+    let Some(ids) = options.as_ref().and_then(|o| o.shared_data.as_ref()) else {
+        return Ok(Vec::new());
+    };
+    let ids = ids.iter().map(|&id| untag(id, "shared data")).collect::<Result<Vec<u64>>>()?;
+    shared_data::resolve(&ids).map_err(Error::from_reason)
+}
+
+fn exec_with(
+    wasm: &executor::WasmInput,
+    func: &str,
+    args: &[i64],
+    run_start: bool,
+    pool: Option<executor::PoolOptions>,
+    tag: Option<&str>,
+    allowed: Option<&[String]>,
+) -> std::result::Result<i64, String> {
+    if let Some(allowed) = allowed {
+        executor::check_imports(wasm, allowed)?;
+    }
+    quotas::run_tagged(tag, || match pool {
+        Some(pool) => executor::exec_pooled_metered(wasm, func, args, run_start, pool),
+        None => executor::exec_wasm_metered_with(wasm, func, args, run_start),
+    })
+    .result
+}
+
+// --- Default exec options ---
+
+/// The keys ExecOptions takes, each with the keys of its nested object
+const EXEC_OPTION_KEYS: &[(&str, &[&str])] = &[
+    ("runStart", &[]),
+    ("instancePool", &["size", "resetEvery"]),
+    ("tag", &[]),
+    ("allowedImports", &[]),
+    ("capabilities", &["channels", "clock", "log", "kv", "native"]),
+    ("metering", &[]),
+    ("queueTimeoutMs", &[]),
+    ("sharedData", &[]),
+    ("cancelToken", &[]),
+    ("scope", &[]),
+];
+
+/// Options that must not carry keys their struct lacks. napi drops unknown
+/// keys silently, so a misspelt `queueTimeoutMs` would run the call without
+/// its timeout; these fail with ERR_INVALID_INPUT naming the key instead.
+pub struct Strict<T>(pub T);
+
+/// `object`'s enumerable property names.
+unsafe fn property_names(env: sys::napi_env, object: sys::napi_value) -> Result<Vec<String>> {
+    let mut names = std::ptr::null_mut();
+    napi::check_status!(sys::napi_get_property_names(env, object, &mut names))?;
+    let mut len = 0u32;
+    napi::check_status!(sys::napi_get_array_length(env, names, &mut len))?;
+    (0..len)
+        .map(|i| {
+            let mut name = std::ptr::null_mut();
+            napi::check_status!(sys::napi_get_element(env, names, i, &mut name))?;
+            String::from_napi_value(env, name)
+        })
+        .collect()
+}
+
+/// The error for a key `keys` does not list, as `prefix` + the key.
+fn unknown_key(prefix: &str, names: &[String], keys: &[&str]) -> Option<Error> {
+    let name = names.iter().find(|name| !keys.contains(&name.as_str()))?;
+    Some(Error::from_reason(errors::coded(
+        errors::ERR_INVALID_INPUT,
+        format!("unknown exec option '{}{}'", prefix, name),
+    )))
+}
+
+impl FromNapiValue for Strict<ExecOptions> {
+    unsafe fn from_napi_value(env: sys::napi_env, value: sys::napi_value) -> Result<Self> {
+        let mut kind = 0;
+        napi::check_status!(sys::napi_typeof(env, value, &mut kind))?;
+        if kind == sys::ValueType::napi_object {
+            let names = property_names(env, value)?;
+            let top: Vec<&str> = EXEC_OPTION_KEYS.iter().map(|(key, _)| *key).collect();
+            if let Some(e) = unknown_key("", &names, &top) {
+                return Err(e);
+            }
+            for (key, nested) in EXEC_OPTION_KEYS.iter().filter(|(_, nested)| !nested.is_empty()) {
+                if !names.iter().any(|name| name.as_str() == *key) {
+                    continue;
+                }
+                let c_key = std::ffi::CString::new(*key).expect("option keys have no NUL");
+                let mut inner = std::ptr::null_mut();
+                napi::check_status!(sys::napi_get_named_property(env, value, c_key.as_ptr(), &mut inner))?;
+                let mut inner_kind = 0;
+                napi::check_status!(sys::napi_typeof(env, inner, &mut inner_kind))?;
+                if inner_kind != sys::ValueType::napi_object {
+                    continue;
+                }
+                if let Some(e) = unknown_key(&format!("{}.", key), &property_names(env, inner)?, nested) {
+                    return Err(e);
+                }
+            }
+        }
+        ExecOptions::from_napi_value(env, value).map(Strict)
+    }
+}
+
+impl TypeName for Strict<ExecOptions> {
+    fn type_name() -> &'static str {
+        "ExecOptions"
+    }
+
+    fn value_type() -> ValueType {
+        ValueType::Object
+    }
+}
+
+impl ValidateNapiValue for Strict<ExecOptions> {}
+
+/// Defaults every call merges its ExecOptions over (see `setDefaultExecOptions`)
+static EXEC_DEFAULTS: once_cell::sync::Lazy<std::sync::Mutex<ExecOptions>> =
+    once_cell::sync::Lazy::new(|| std::sync::Mutex::new(ExecOptions::default()));
+
+/// A copy of the current defaults. Batches take one when submitted, so a
+/// change mid-batch reaches none of its tasks.
+fn exec_defaults() -> ExecOptions {
+    errors::lock(&EXEC_DEFAULTS).clone()
+}
+
+/// `call` with each field it leaves unset taken from `defaults`. Nested
+/// objects merge field by field too; lists replace rather than append.
+fn merge_exec_options(call: ExecOptions, defaults: &ExecOptions) -> ExecOptions {
+    let defaults = defaults.clone();
+    ExecOptions {
+        run_start: call.run_start.or(defaults.run_start),
+        instance_pool: match (call.instance_pool, defaults.instance_pool) {
+            (Some(call), Some(defaults)) => Some(InstancePoolOptions {
+                size: call.size.or(defaults.size),
+                reset_every: call.reset_every.or(defaults.reset_every),
+            }),
+            (call, defaults) => call.or(defaults),
+        },
+        tag: call.tag.or(defaults.tag),
+        allowed_imports: call.allowed_imports.or(defaults.allowed_imports),
+        capabilities: match (call.capabilities, defaults.capabilities) {
+            (Some(call), Some(defaults)) => Some(Capabilities {
+                channels: call.channels.or(defaults.channels),
+                clock: call.clock.or(defaults.clock),
+                log: call.log.or(defaults.log),
+                kv: call.kv.or(defaults.kv),
+                native: call.native.or(defaults.native),
+            }),
+            (call, defaults) => call.or(defaults),
+        },
+        metering: call.metering.or(defaults.metering),
+        queue_timeout_ms: call.queue_timeout_ms.or(defaults.queue_timeout_ms),
+        shared_data: call.shared_data.or(defaults.shared_data),
+        cancel_token: call.cancel_token.or(defaults.cancel_token),
+        scope: call.scope.or(defaults.scope),
+    }
+}
+
+/// A call's options merged over the current defaults.
+fn with_defaults(options: Option<Strict<ExecOptions>>) -> Option<ExecOptions> {
+    Some(merge_exec_options(options.map(|o| o.0).unwrap_or_default(), &exec_defaults()))
+}
+
+/// Give untagged tasks the default tag.
+fn tag_tasks(tasks: &mut [WasmTask], defaults: &ExecOptions) {
+    if let Some(tag) = &defaults.tag {
+        for task in tasks.iter_mut().filter(|t| t.tag.is_none()) {
+            task.tag = Some(tag.clone());
+        }
+    }
+}
+
+/// Batch options with `metering` and `queueTimeoutMs` taken from the
+/// defaults where the batch leaves them unset; None when neither has any.
+fn batch_with_defaults(options: Option<BatchOptions>, defaults: &ExecOptions) -> Option<BatchOptions> {
+    if options.is_none() && defaults.metering.is_none() && defaults.queue_timeout_ms.is_none() {
+        return None;
+    }
+    let options = options.unwrap_or_default();
+    Some(BatchOptions {
+        metering: options.metering.or_else(|| defaults.metering.clone()),
+        queue_timeout_ms: options.queue_timeout_ms.or(defaults.queue_timeout_ms),
+        ..options
+    })
+}
+
+/// Set the options every exec and batch call starts from; a call's own
+/// options override them field by field, nested objects included. Batches
+/// take `tag` for untagged tasks and `metering` and `queueTimeoutMs` where
+/// they take those at all. Unknown keys and invalid values are rejected
+/// here, naming the field; none or `{}` clears the defaults.
+#[napi]
+pub fn set_default_exec_options(options: Option<Strict<ExecOptions>>) -> Result<()> {
+    let options = options.map(|o| o.0).unwrap_or_default();
+    if let Some(requested) = &options.metering {
+        executor::Metering::parse(requested).map_err(Error::from_reason)?;
+    }
+    for &id in options.shared_data.iter().flatten() {
+        untag(id, "shared data")?;
+    }
+    if let Some(id) = options.cancel_token {
+        untag(id, "cancel token")?;
+    }
+    if let Some(id) = options.scope {
+        untag(id, "scope")?;
+    }
+    *errors::lock(&EXEC_DEFAULTS) = options;
+    Ok(())
+}
+
+/// The defaults `setDefaultExecOptions` last set, unset fields omitted.
+#[napi]
+pub fn get_default_exec_options() -> ExecOptions {
+    exec_defaults()
+}
+
+#[napi]
+pub async fn exec_wasm(wasm: Buffer, func: String, args: Vec<i64>, options: Option<Strict<ExecOptions>>) -> Result<i64> {
+    check_call(Some(wasm.len()), args.len())?;
+    let options = with_defaults(options);
+    let run_start = run_start(&options);
+    let pool = instance_pool(&options);
+    let allowed = options.as_ref().and_then(|o| allowed_imports(&o.allowed_imports, &o.capabilities));
+    let metering = metering(&options.as_ref().and_then(|o| o.metering.clone()))?;
+    let segments = shared_segments(&options)?;
+    let cancel = live_cancel_token(options.as_ref().and_then(|o| o.cancel_token))?;
+    let queue_timeout_ms = options.as_ref().and_then(|o| o.queue_timeout_ms);
+    let scope = options.as_ref().and_then(|o| o.scope);
+    let tag = options.and_then(|o| o.tag);
+    let wasm = executor::resolve_wasm_with(&wasm, metering);
+    let (job, cancel) = scoped_job(scope, cancel)?;
+    let slot = enqueue(1, queue_timeout_ms)?.remove(0);
+    let result = scheduler::spawn_guest(move || {
+        slot.start()?;
+        let _data = shared_data::scope(segments);
+        let _cancel = executor::cancel_scope(cancel);
+        exec_with(&wasm, &func, &args, run_start, pool, tag.as_deref(), allowed.as_deref())
+    })
+    .await
+    .map_err(|e| Error::from_reason(format!("task join error: {}", e)))?;
+    if let Some(job) = job {
+        job.finish(&result);
+    }
+    result.map_err(Error::from_reason)
+}
+
+#[napi]
+pub async fn exec_module(handle: i64, func: String, args: Vec<i64>, options: Option<Strict<ExecOptions>>) -> Result<i64> {
+    check_call(None, args.len())?;
+    let options = with_defaults(options);
+    let run_start = run_start(&options);
+    let pool = instance_pool(&options);
+    let allowed = options.as_ref().and_then(|o| allowed_imports(&o.allowed_imports, &o.capabilities));
+    let requested = options.as_ref().and_then(|o| o.metering.clone());
+    let segments = shared_segments(&options)?;
+    let cancel = live_cancel_token(options.as_ref().and_then(|o| o.cancel_token))?;
+    let queue_timeout_ms = options.as_ref().and_then(|o| o.queue_timeout_ms);
+    let scope = options.as_ref().and_then(|o| o.scope);
+    let tag = options.and_then(|o| o.tag);
+    let wasm = executor::precompiled(untag(handle, "module handle")?).map_err(Error::from_reason)?;
+    if let Some(requested) = requested {
+        let requested = executor::Metering::parse(&requested).map_err(Error::from_reason)?;
+        if requested != wasm.metering() {
+            return Err(Error::from_reason(errors::coded(
+                errors::ERR_INVALID_INPUT,
+                format!("module handle was compiled for '{}' metering, not '{}'", wasm.metering().name(), requested.name()),
+            )));
+        }
+    }
+    let (job, cancel) = scoped_job(scope, cancel)?;
+    let slot = enqueue(1, queue_timeout_ms)?.remove(0);
+    let result = scheduler::spawn_guest(move || {
+        slot.start()?;
+        let _data = shared_data::scope(segments);
+        let _cancel = executor::cancel_scope(cancel);
+        exec_with(&wasm, &func, &args, run_start, pool, tag.as_deref(), allowed.as_deref())
+    })
+    .await
+    .map_err(|e| Error::from_reason(format!("task join error: {}", e)))?;
+    if let Some(job) = job {
+        job.finish(&result);
+    }
+    result.map_err(Error::from_reason)
+}
+
+#[napi(object)]
+#[derive(Default)]
+pub struct InlineExecOptions {
+    /// Run on a warm instance of the module instead of instantiating per call
+    pub instance_pool: Option<InstancePoolOptions>,
+}
+
+/// Run a tiny guest call synchronously on the JS thread, skipping the
+/// thread pool round trip that dominates sub-microsecond calls. `wasm` is
+/// module bytes already in the module cache, or a module handle, compiled
+/// for fuel metering. The call gets at most `inlineFuel` (see
+/// configureRuntime) and no top-ups; running past it fails with
+/// ERR_INLINE_BUDGET, and the call belongs on execWasm. The event loop is
+/// blocked while the guest runs, and its stack is the JS thread's.
+#[napi]
+pub fn exec_wasm_inline(wasm: Either<Buffer, i64>, func: String, args: Vec<i64>, options: Option<InlineExecOptions>) -> Result<i64> {
+    let wasm = match wasm {
+        Either::A(bytes) => {
+            check_call(Some(bytes.len()), args.len())?;
+            executor::resolve_wasm_with(&bytes, executor::Metering::Fuel)
+        }
+        Either::B(handle) => {
+            check_call(None, args.len())?;
+            executor::precompiled(untag(handle, "module handle")?).map_err(Error::from_reason)?
+        }
+    };
+    let pool = options.and_then(|o| o.instance_pool).as_ref().map(pool_options);
+    executor::exec_inline(&wasm, &func, &args, config::get().inline_fuel, pool).map_err(Error::from_reason)
+}
+
+#[napi(object)]
+#[derive(Default)]
+pub struct BatchOptions {
+    /// Total fuel the whole batch may burn. Each task is checked before it
+    /// starts and charged after it finishes; once the budget is spent the
+    /// remaining tasks fail with ERR_FUEL_BUDGET without running. Tasks
+    /// already running can overshoot by up to their own fuel limit each.
+    pub shared_fuel_budget: Option<BigInt>,
+    /// Tasks run at once (default: unbounded, or one per compute thread when
+    /// a fuel budget is set, which bounds the overshoot)
+    pub max_concurrency: Option<u32>,
+    /// Log every task whose execution takes longer than this to stderr, with
+    /// its index, function, fuel used and duration
+    pub slow_task_threshold_ms: Option<u32>,
+    /// Report a latency summary with the results (concurrentWasmSettled)
+    pub latency_summary: Option<bool>,
+    /// Metering for tasks given as bytes, as in ExecOptions (default:
+    /// `defaultMetering`). A shared fuel budget needs "fuel".
+    pub metering: Option<String>,
+    /// "ordered" (default) collects results in task order, so a slow early
+    /// task holds back everything after it; "unordered" takes them as they
+    /// finish, placing each by index, and fails on the first error to
+    /// finish rather than the first by index.
+    pub completion: Option<String>,
+    /// Fail a task with ERR_QUEUE_TIMEOUT, without running it, if it waits
+    /// this long to start, for a worker or a `maxConcurrency` permit
+    pub queue_timeout_ms: Option<u32>,
+}
+
+/// The order a batch collects its results in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Completion {
+    Ordered,
+    Unordered,
+}
+
+fn completion(options: &Option<BatchOptions>) -> Result<Completion> {
+    match options.as_ref().and_then(|o| o.completion.as_deref()) {
+        None | Some("ordered") => Ok(Completion::Ordered),
+        Some("unordered") => Ok(Completion::Unordered),
+        Some(other) => Err(Error::from_reason(errors::coded(
+            errors::ERR_INVALID_INPUT,
+            format!("completion must be 'ordered' or 'unordered', not '{}'", other),
+        ))),
+    }
+}
+
+fn fuel_budget(options: &Option<BatchOptions>) -> Result<Option<Arc<executor::FuelBudget>>> {
+    let budget = match options.as_ref().and_then(|o| o.shared_fuel_budget.as_ref()) {
+        Some(budget) => budget,
+        None => return Ok(None),
+    };
+    let (negative, total, lossless) = budget.get_u64();
+    if negative || !lossless {
+        return Err(Error::from_reason("sharedFuelBudget must be a non-negative 64-bit integer"));
+    }
+    Ok(Some(Arc::new(executor::FuelBudget::new(total))))
+}
+
+/// Run every task, metered, honouring the batch options. Results keep task
+/// order and come with how long each task took to execute. `on_done` sees
+/// each result as it is collected, in the batch's completion order; an
+/// error from it fails the batch without waiting for the rest.
+async fn run_metered_batch<F>(
+    tasks: Vec<WasmTask>,
+    options: &Option<BatchOptions>,
+    mut on_done: F,
+) -> Result<Vec<(executor::Metered, std::time::Duration)>>
+where
+    F: FnMut(usize, &executor::Metered) -> Result<()> + Send,
+{
+    check_tasks(&tasks)?;
+    let completion = completion(options)?;
+    let budget = fuel_budget(options)?;
+    let metering = metering(&options.as_ref().and_then(|o| o.metering.clone()))?;
+    if budget.is_some() && metering != executor::Metering::Fuel {
+        return Err(Error::from_reason(errors::coded(
+            errors::ERR_INVALID_INPUT,
+            format!("sharedFuelBudget needs 'fuel' metering, not '{}'", metering.name()),
+        )));
+    }
+    let slow_threshold = options
+        .as_ref()
+        .and_then(|o| o.slow_task_threshold_ms)
+        .map(|ms| std::time::Duration::from_millis(ms as u64));
+    let concurrency = options.as_ref().and_then(|o| o.max_concurrency).map(|n| n.max(1) as usize);
+    let concurrency = match (concurrency, &budget) {
+        (Some(n), _) => Some(n),
+        (None, Some(_)) => Some(config::get().compute_threads),
+        (None, None) => None,
+    };
+    let permits = concurrency.map(|n| Arc::new(tokio::sync::Semaphore::new(n)));
+
+    let mut resolver = executor::WasmResolver::with_metering(metering);
+    let mut batch = scheduler::GuestBatch::new();
+    let count = tasks.len();
+    let slots = enqueue(count, options.as_ref().and_then(|o| o.queue_timeout_ms))?;
+    let mut handles = Vec::with_capacity(count);
+    for ((index, task), slot) in tasks.into_iter().enumerate().zip(slots) {
+        let wasm = resolve_task(&mut resolver, &task)?;
+        let func = task.func;
+        let args = task.args;
+        let tag = task.tag;
+        let budget = budget.clone();
+        let run = move || {
+            if let Err(e) = slot.start() {
+                return (executor::Metered { result: Err(e), fuel_used: 0 }, std::time::Duration::ZERO);
+            }
+            let _task = executor::task_scope(index, count);
+            let started = std::time::Instant::now();
+            let metered = quotas::run_tagged(tag.as_deref(), || match budget {
+                Some(budget) => budget.run(&wasm, &func, &args),
+                None => executor::exec_wasm_metered(&wasm, &func, &args),
+            });
+            let elapsed = started.elapsed();
+            if slow_threshold.is_some_and(|threshold| elapsed > threshold) {
+                metrics::log_slow_task(&metrics::SlowTask { index, func, fuel_used: metered.fuel_used, elapsed });
+            }
+            (metered, elapsed)
+        };
+        if batch.is_dispatched() {
+            // One task runs at a time, so there is no concurrency to bound
+            let handle = batch.spawn(run);
+            handles.push(scheduler::TOKIO_RT.spawn(async move { handle.await.map_err(|e| format!("join: {}", e)) }));
+            continue;
+        }
+        let permits = permits.clone();
+        handles.push(scheduler::TOKIO_RT.spawn(async move {
+            // Permits are handed out in request order, so tasks start in batch order
+            let _permit = match permits {
+                Some(permits) => Some(permits.acquire_owned().await.map_err(|e| format!("permit: {}", e))?),
+                None => None,
+            };
+            scheduler::spawn_guest(run).await.map_err(|e| format!("join: {}", e))
+        }));
+    }
+    batch.submit();
+
+    type Timed = (executor::Metered, std::time::Duration);
+    let mut results: Vec<Option<Timed>> = Vec::with_capacity(handles.len());
+    results.resize_with(handles.len(), || None);
+    let mut gather = |index: usize, joined: std::result::Result<std::result::Result<Timed, String>, tokio::task::JoinError>| -> Result<()> {
+        let timed = joined
+            .map_err(|e| Error::from_reason(format!("join: {}", e)))?
+            .map_err(Error::from_reason)?;
+        on_done(index, &timed.0)?;
+        results[index] = Some(timed);
+        Ok(())
+    };
+    match completion {
+        Completion::Ordered => {
+            for (index, handle) in handles.into_iter().enumerate() {
+                gather(index, handle.await)?;
+            }
+        }
+        Completion::Unordered => {
+            let mut finished: futures::stream::FuturesUnordered<_> = handles
+                .into_iter()
+                .enumerate()
+                .map(|(index, handle)| async move { (index, handle.await) })
+                .collect();
+            while let Some((index, joined)) = futures::StreamExt::next(&mut finished).await {
+                gather(index, joined)?;
+            }
+        }
+    }
+    Ok(results.into_iter().map(|timed| timed.expect("every task is collected")).collect())
+}
+
+#[napi]
+pub async fn concurrent_wasm(mut tasks: Vec<WasmTask>, options: Option<BatchOptions>) -> Result<Vec<i64>> {
+    let defaults = exec_defaults();
+    tag_tasks(&mut tasks, &defaults);
+    let options = batch_with_defaults(options, &defaults);
+    if options.is_some() {
+        let timed = run_metered_batch(tasks, &options, |_, metered| match &metered.result {
+            Ok(_) => Ok(()),
+            Err(e) => Err(Error::from_reason(e.clone())),
+        })
+        .await?;
+        return timed
+            .into_iter()
+            .map(|(m, _)| m.result.map_err(Error::from_reason))
+            .collect();
+    }
+    check_tasks(&tasks)?;
+
+    let mut handles = Vec::with_capacity(tasks.len());
+
+    let mut resolver = executor::WasmResolver::with_metering(config::get().default_metering);
+    let mut batch = scheduler::GuestBatch::new();
+    let count = tasks.len();
+    let slots = enqueue(count, None)?;
+    for ((index, task), slot) in tasks.into_iter().enumerate().zip(slots) {
+        let wasm = resolve_task(&mut resolver, &task)?;
+        let func = task.func;
+        let args = task.args;
+        let tag = task.tag;
+        handles.push(batch.spawn(move || {
+            slot.start()?;
+            let _task = executor::task_scope(index, count);
+            run_task(&wasm, &func, &args, tag.as_deref())
+        }));
+    }
+    batch.submit();
+
+    let mut results = Vec::with_capacity(handles.len());
+    for handle in handles {
+        let r = handle
+            .await
+            .map_err(|e| Error::from_reason(format!("join: {}", e)))?
+            .map_err(|e| Error::from_reason(e))?;
+        results.push(r);
+    }
+    Ok(results)
+}
+
+#[napi(object)]
+pub struct SettledTask {
+    pub ok: bool,
+    pub value: Option<i64>,
+    pub error: Option<String>,
+    /// Fuel the task burnt (0 if it never ran)
+    pub fuel_used: i64,
+}
+
+/// Task execution times across a batch, in milliseconds. Quantiles are
+/// bucket upper bounds, as in `metricsSnapshot`.
+#[napi(object)]
+pub struct LatencySummary {
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+    /// Index of the slowest task
+    pub max_index: Option<u32>,
+}
+
+impl From<&metrics::BatchLatency> for LatencySummary {
+    fn from(latency: &metrics::BatchLatency) -> Self {
+        let histogram = latency.histogram();
+        LatencySummary {
+            p50: millis(histogram.quantile(0.5)),
+            p90: millis(histogram.quantile(0.9)),
+            p99: millis(histogram.quantile(0.99)),
+            max: millis(histogram.max()),
+            max_index: latency.max_index().map(|i| i as u32),
+        }
+    }
+}
+
+#[napi(object)]
+pub struct SettledBatch {
+    /// One entry per task, in task order
+    pub results: Vec<SettledTask>,
+    pub total_fuel_used: i64,
+    /// Present when the batch asked for `latencySummary`
+    pub latency: Option<LatencySummary>,
+}
+
+impl From<&executor::Metered> for SettledTask {
+    fn from(metered: &executor::Metered) -> Self {
+        let fuel_used = metered.fuel_used as i64;
+        match &metered.result {
+            Ok(v) => SettledTask { ok: true, value: Some(*v), error: None, fuel_used },
+            Err(e) => SettledTask { ok: false, value: None, error: Some(e.clone()), fuel_used },
+        }
+    }
+}
+
+fn settle(timed: Vec<(executor::Metered, std::time::Duration)>, want_latency: bool) -> SettledBatch {
+    let total_fuel_used = timed.iter().map(|(m, _)| m.fuel_used as i64).sum();
+    let latency = want_latency.then(|| {
+        let mut latency = metrics::BatchLatency::default();
+        for (index, (_, elapsed)) in timed.iter().enumerate() {
+            latency.record(index, *elapsed);
+        }
+        LatencySummary::from(&latency)
+    });
+    let results = timed.iter().map(|(m, _)| SettledTask::from(m)).collect();
+    SettledBatch { results, total_fuel_used, latency }
+}
+
+/// Run every task to completion and report each outcome, success or
+/// failure, with the fuel it used.
+#[napi]
+pub async fn concurrent_wasm_settled(mut tasks: Vec<WasmTask>, options: Option<BatchOptions>) -> Result<SettledBatch> {
+    let defaults = exec_defaults();
+    tag_tasks(&mut tasks, &defaults);
+    let options = batch_with_defaults(options, &defaults);
+    let want_latency = options.as_ref().and_then(|o| o.latency_summary).unwrap_or(false);
+    let timed = run_metered_batch(tasks, &options, |_, _| Ok(())).await?;
+    Ok(settle(timed, want_latency))
+}
+
+/// One task's outcome as `concurrentWasmStream` reports it
+#[napi(object)]
+pub struct CompletedTask {
+    pub index: u32,
+    pub ok: bool,
+    pub value: Option<i64>,
+    pub error: Option<String>,
+    pub fuel_used: i64,
 }
 
+/// `concurrentWasmSettled` that also calls `onResult` with each task's
+/// outcome as it is collected: in task order by default, or as tasks finish
+/// with `completion: "unordered"`.
 #[napi]
-pub fn channel_close(id: i64) {
-    channels::close(id as u64)
+pub async fn concurrent_wasm_stream(
+    mut tasks: Vec<WasmTask>,
+    on_result: ThreadsafeFunction<CompletedTask, (), CompletedTask, Status, false>,
+    options: Option<BatchOptions>,
+) -> Result<SettledBatch> {
+    let defaults = exec_defaults();
+    tag_tasks(&mut tasks, &defaults);
+    let options = batch_with_defaults(options, &defaults);
+    let want_latency = options.as_ref().and_then(|o| o.latency_summary).unwrap_or(false);
+    let timed = run_metered_batch(tasks, &options, |index, metered| {
+        let settled = SettledTask::from(metered);
+        let completed = CompletedTask {
+            index: index as u32,
+            ok: settled.ok,
+            value: settled.value,
+            error: settled.error,
+            fuel_used: settled.fuel_used,
+        };
+        on_result.call(completed, ThreadsafeFunctionCallMode::NonBlocking);
+        Ok(())
+    })
+    .await?;
+    Ok(settle(timed, want_latency))
 }
 
-// --- WASM execution ---
-
 #[napi(object)]
-pub struct WasmTask {
-    pub wasm: Buffer,
-    pub func: String,
-    pub args: Vec<i64>,
+pub struct SharedBatchOptions {
+    /// Number of chunks to split the batch into (default: one per compute thread)
+    pub chunks: Option<u32>,
 }
 
-#[napi]
-pub async fn exec_wasm(wasm: Buffer, func: String, args: Vec<i64>) -> Result<i64> {
-    let wasm_bytes = wasm.to_vec();
-    let result = scheduler::TOKIO_RT
-        .spawn_blocking(move || {
-            executor::exec_wasm_sync(&wasm_bytes, &func, &args)
-        })
-        .await
-        .map_err(|e| Error::from_reason(format!("task join error: {}", e)))?
-        .map_err(|e| Error::from_reason(e))?;
-    Ok(result)
+/// Where one chunk of a shared batch ran.
+#[napi(object)]
+pub struct ChunkPlacement {
+    pub chunk: u32,
+    pub tasks: u32,
+    /// Index of the compute-pool thread that ran the chunk
+    pub worker: u32,
+    pub os_thread_id: Option<i64>,
+    /// CPU set the worker is pinned to, if any
+    pub cpus: Option<Vec<u32>>,
 }
 
-#[napi]
-pub async fn concurrent_wasm(tasks: Vec<WasmTask>) -> Result<Vec<i64>> {
-    let mut handles = Vec::with_capacity(tasks.len());
+#[napi(object)]
+pub struct SharedBatchReport {
+    /// Results of an integer batch; empty for a float one
+    pub results: Vec<i64>,
+    /// Results of a float batch (tasks with `f64Args`)
+    pub f64_results: Option<Vec<f64>>,
+    pub chunks: Vec<ChunkPlacement>,
+}
 
-    for task in tasks {
-        let wasm_bytes = task.wasm.to_vec();
-        let func = task.func;
-        let args = task.args;
-        handles.push(scheduler::TOKIO_RT.spawn_blocking(move || {
-            executor::exec_wasm_sync(&wasm_bytes, &func, &args)
-        }));
+/// Validate a shared batch that must be all-float (`float`) or all-integer.
+fn check_shared_tasks(tasks: &[WasmTask], float: bool) -> Result<()> {
+    check_task_limits(tasks)?;
+    if let Some(i) = tasks.iter().position(|t| t.f64_args.is_some() != float) {
+        let msg = if float {
+            format!("task {}: a float batch needs f64Args on every task", i)
+        } else {
+            format!("task {}: f64Args in an integer batch", i)
+        };
+        return Err(Error::from_reason(errors::coded(errors::ERR_INVALID_INPUT, msg)));
     }
+    Ok(())
+}
 
-    let mut results = Vec::with_capacity(handles.len());
-    for handle in handles {
-        let r = handle
-            .await
-            .map_err(|e| Error::from_reason(format!("join: {}", e)))?
-            .map_err(|e| Error::from_reason(e))?;
-        results.push(r);
+async fn run_shared(tasks: Vec<WasmTask>, options: Option<SharedBatchOptions>) -> Result<SharedBatchReport> {
+    let float = tasks.first().is_some_and(|t| t.f64_args.is_some());
+    check_shared_tasks(&tasks, float)?;
+    if float {
+        let (results, chunks) = run_shared_chunks(
+            tasks,
+            options,
+            |t| (t.func, t.f64_args.unwrap_or_default()),
+            executor::exec_many_shared_reuse_f64,
+        )
+        .await?;
+        Ok(SharedBatchReport { results: vec![], f64_results: Some(results), chunks })
+    } else {
+        let (results, chunks) =
+            run_shared_chunks(tasks, options, |t| (t.func, t.args), executor::exec_many_shared_reuse).await?;
+        Ok(SharedBatchReport { results, f64_results: None, chunks })
     }
-    Ok(results)
 }
 
-#[napi]
-pub async fn concurrent_wasm_shared(tasks: Vec<WasmTask>) -> Result<Vec<i64>> {
+type SharedTask<V> = (String, Vec<V>);
+
+/// Runs one chunk of shared-instance tasks, a result per task
+type SharedExec<V> = fn(&executor::WasmInput, Vec<SharedTask<V>>) -> Vec<std::result::Result<V, String>>;
+
+async fn run_shared_chunks<V: Clone + Send + 'static>(
+    tasks: Vec<WasmTask>,
+    options: Option<SharedBatchOptions>,
+    task_args: fn(WasmTask) -> SharedTask<V>,
+    exec: SharedExec<V>,
+) -> Result<(Vec<V>, Vec<ChunkPlacement>)> {
     if tasks.is_empty() {
-        return Ok(vec![]);
+        return Ok((vec![], vec![]));
     }
 
-    let wasm_bytes = tasks[0].wasm.to_vec();
-    let chunk_size = (tasks.len() + 7) / 8;
-    let task_data: Vec<(String, Vec<i64>)> = tasks
-        .into_iter()
-        .map(|t| (t.func, t.args))
-        .collect();
+    let pool = &*scheduler::COMPUTE_POOL;
+    let chunk_count = options
+        .and_then(|o| o.chunks)
+        .map(|c| c as usize)
+        .or(config::get().shared_chunks)
+        .unwrap_or(pool.threads())
+        .max(1);
+
+    let wasm = resolve_task(&mut executor::WasmResolver::new(), &tasks[0])?;
+    let chunk_size = tasks.len().div_ceil(chunk_count);
+    let task_data: Vec<SharedTask<V>> = tasks.into_iter().map(task_args).collect();
 
-    let chunks: Vec<Vec<(String, Vec<i64>)>> = task_data
+    let chunks: Vec<Vec<SharedTask<V>>> = task_data
         .chunks(chunk_size.max(1))
         .map(|c| c.to_vec())
         .collect();
 
-    let wasm_arc = Arc::new(wasm_bytes);
     let mut handles = Vec::new();
 
-    for chunk in chunks {
-        let wasm = Arc::clone(&wasm_arc);
-        handles.push(scheduler::TOKIO_RT.spawn_blocking(move || {
-            executor::exec_many_shared_reuse(&wasm, chunk)
+    let total = task_data.len();
+    let mut batch = scheduler::GuestBatch::new();
+    for (i, chunk) in chunks.into_iter().enumerate() {
+        let wasm = wasm.clone();
+        handles.push(batch.spawn_on(pool, move |worker| {
+            let _task = executor::task_scope(i * chunk_size, total);
+            let tasks = chunk.len();
+            (exec(&wasm, chunk), worker.clone(), tasks)
         }));
     }
+    batch.submit();
 
     let mut all_results = Vec::new();
-    for handle in handles {
-        let chunk_results = handle
+    let mut placements = Vec::with_capacity(handles.len());
+    for (i, handle) in handles.into_iter().enumerate() {
+        let (chunk_results, worker, tasks) = handle
             .await
-            .map_err(|e| Error::from_reason(format!("join: {}", e)))?;
+            .map_err(|_| Error::from_reason("join: compute worker dropped the chunk".to_string()))?;
         for r in chunk_results {
             all_results.push(r.map_err(|e| Error::from_reason(e))?);
         }
+        placements.push(ChunkPlacement {
+            chunk: i as u32,
+            tasks: tasks as u32,
+            worker: worker.index as u32,
+            os_thread_id: worker.os_thread_id.map(|t| t as i64),
+            cpus: worker.cpus.map(|set| set.into_iter().map(|c| c as u32).collect()),
+        });
+    }
+    Ok((all_results, placements))
+}
+
+/// Run a batch that shares one module across chunks on the compute pool,
+/// reusing one instance per chunk.
+#[napi]
+pub async fn concurrent_wasm_shared(tasks: Vec<WasmTask>, options: Option<SharedBatchOptions>) -> Result<Vec<i64>> {
+    check_shared_tasks(&tasks, false)?;
+    Ok(run_shared(tasks, options).await?.results)
+}
+
+/// `concurrentWasmShared` for functions over f64: every task passes
+/// `f64Args`, and each function returns an f64 (or f32).
+#[napi]
+pub async fn concurrent_wasm_shared_f64(tasks: Vec<WasmTask>, options: Option<SharedBatchOptions>) -> Result<Vec<f64>> {
+    check_shared_tasks(&tasks, true)?;
+    Ok(run_shared(tasks, options).await?.f64_results.unwrap_or_default())
+}
+
+/// `concurrentWasmShared` plus the placement of every chunk, for verifying
+/// thread pinning. Runs a float batch when the tasks carry `f64Args`.
+#[napi]
+pub async fn concurrent_wasm_shared_stats(tasks: Vec<WasmTask>, options: Option<SharedBatchOptions>) -> Result<SharedBatchReport> {
+    run_shared(tasks, options).await
+}
+
+// --- Chunked batches ---
+
+/// Tasks per sub-batch of `concurrentWasmChunked` unless `chunkSize` says otherwise
+const DEFAULT_CHUNK_TASKS: u32 = 64 << 10;
+
+#[napi(object)]
+pub struct ChunkedBatchOptions {
+    /// Tasks per sub-batch (default 65536, at most `maxTasksPerBatch`).
+    /// Memory beyond the argument and result buffers is proportional to it.
+    pub chunk_size: Option<u32>,
+    /// Id from `execIdCreate`; `execCancel` on it stops the batch with
+    /// ERR_CANCELLED before its next chunk starts
+    pub exec_id: Option<i64>,
+    /// Token from `cancelTokenCreate`; cancelling it stops the running chunk
+    /// and the batch with ERR_CANCELLED
+    pub cancel_token: Option<i64>,
+    /// Metering, as in ExecOptions (default: `defaultMetering`)
+    pub metering: Option<String>,
+}
+
+#[napi(object)]
+pub struct ChunkProgress {
+    pub completed_chunks: u32,
+    pub total_chunks: u32,
+}
+
+/// An id for naming a cancellable call, such as a chunked batch's `execId`.
+/// The call releases it when it finishes.
+#[napi]
+pub fn exec_id_create() -> i64 {
+    lifecycle::tag(execs::create())
+}
+
+/// Cancel the call running under `execId`; false if it has finished or the
+/// id is unknown.
+#[napi]
+pub fn exec_cancel(exec_id: i64) -> Result<bool> {
+    Ok(execs::cancel(untag(exec_id, "exec id")?))
+}
+
+/// Call `func` once for every `arity` consecutive arguments in `argsFlat`
+/// (little-endian i64s, such as a BigInt64Array's bytes) without building a
+/// task object per call. Tasks run in sub-batches of `chunkSize`, one after
+/// another, each split across the compute pool with one reused instance per
+/// piece; results land in the returned Buffer, one little-endian i64 per
+/// task, as each chunk completes, and `onProgress` hears after every chunk.
+/// The first failing task fails the batch.
+#[napi]
+pub async fn concurrent_wasm_chunked(
+    wasm: Buffer,
+    func: String,
+    args_flat: Buffer,
+    arity: u32,
+    options: Option<ChunkedBatchOptions>,
+    on_progress: Option<ThreadsafeFunction<ChunkProgress, (), ChunkProgress, Status, false>>,
+) -> Result<Buffer> {
+    check_call(Some(wasm.len()), arity as usize)?;
+    let arity = arity as usize;
+    if arity == 0 || !args_flat.len().is_multiple_of(arity * 8) {
+        return Err(Error::from_reason(errors::coded(
+            errors::ERR_INVALID_INPUT,
+            format!("argsFlat is {} bytes, not a whole number of {}-argument tasks", args_flat.len(), arity),
+        )));
+    }
+    let chunk_size = options.as_ref().and_then(|o| o.chunk_size).unwrap_or(DEFAULT_CHUNK_TASKS).max(1) as usize;
+    config::get().check_batch(chunk_size).map_err(Error::from_reason)?;
+    let exec = match options.as_ref().and_then(|o| o.exec_id) {
+        Some(id) => Some(execs::claim(untag(id, "exec id")?).map_err(Error::from_reason)?),
+        None => None,
+    };
+    let cancel = live_cancel_token(options.as_ref().and_then(|o| o.cancel_token))?;
+    let metering = metering(&options.as_ref().and_then(|o| o.metering.clone()).or(exec_defaults().metering))?;
+    let wasm = executor::resolve_wasm_with(&wasm, metering);
+
+    let args_flat: &[u8] = &args_flat;
+    let tasks = args_flat.len() / (arity * 8);
+    let total_chunks = tasks.div_ceil(chunk_size);
+    let pool = &*scheduler::COMPUTE_POOL;
+    let mut results = vec![0u8; tasks * 8];
+    for chunk in 0..total_chunks {
+        if exec.as_ref().is_some_and(|exec| exec.cancelled()) || cancel.as_ref().is_some_and(|token| token.is_cancelled()) {
+            return Err(Error::from_reason(errors::coded(
+                errors::ERR_CANCELLED,
+                format!("chunked batch cancelled after {} of {} chunks", chunk, total_chunks),
+            )));
+        }
+        let first = chunk * chunk_size;
+        let count = chunk_size.min(tasks - first);
+        let piece_size = count.div_ceil(pool.threads());
+        let mut batch = scheduler::GuestBatch::new();
+        let mut handles = Vec::new();
+        for start in (first..first + count).step_by(piece_size) {
+            let piece: Vec<(String, Vec<i64>)> = (start..(start + piece_size).min(first + count))
+                .map(|task| {
+                    let bytes = &args_flat[task * arity * 8..(task + 1) * arity * 8];
+                    let args = bytes.chunks_exact(8).map(|b| i64::from_le_bytes(b.try_into().unwrap())).collect();
+                    (func.clone(), args)
+                })
+                .collect();
+            let wasm = wasm.clone();
+            let cancel = cancel.clone();
+            handles.push(batch.spawn_on(pool, move |_| {
+                let _task = executor::task_scope(start, tasks);
+                let _cancel = executor::cancel_scope(cancel);
+                executor::exec_many_shared_reuse(&wasm, piece)
+            }));
+        }
+        batch.submit();
+
+        let mut at = first * 8;
+        for handle in handles {
+            let piece = handle.await.map_err(|e| Error::from_reason(format!("join: {}", e)))?;
+            for result in piece {
+                let value = result.map_err(Error::from_reason)?;
+                results[at..at + 8].copy_from_slice(&value.to_le_bytes());
+                at += 8;
+            }
+        }
+        if let Some(on_progress) = &on_progress {
+            let progress = ChunkProgress { completed_chunks: chunk as u32 + 1, total_chunks: total_chunks as u32 };
+            on_progress.call(progress, ThreadsafeFunctionCallMode::NonBlocking);
+        }
     }
-    Ok(all_results)
+    Ok(Buffer::from(results))
 }
 
 // --- Block mode variants for concurrent WASM ---
 
 /// Race mode: return the first successful result, cancel others
 #[napi]
-pub async fn concurrent_wasm_first(tasks: Vec<WasmTask>) -> Result<i64> {
+pub async fn concurrent_wasm_first(mut tasks: Vec<WasmTask>) -> Result<i64> {
+    tag_tasks(&mut tasks, &exec_defaults());
     use tokio::sync::oneshot;
 
     if tasks.is_empty() {
         return Err(Error::from_reason("no tasks provided".to_string()));
     }
+    check_tasks(&tasks)?;
 
     let (tx, rx) = oneshot::channel::<std::result::Result<i64, String>>();
     let tx = Arc::new(tokio::sync::Mutex::new(Some(tx)));
 
     let mut handles = Vec::with_capacity(tasks.len());
-    for task in tasks {
-        let wasm_bytes = task.wasm.to_vec();
+    let mut resolver = executor::WasmResolver::new();
+    let mut batch = scheduler::GuestBatch::new();
+    let count = tasks.len();
+    let slots = enqueue(count, None)?;
+    for ((index, task), slot) in tasks.into_iter().enumerate().zip(slots) {
+        let wasm = resolve_task(&mut resolver, &task)?;
         let func = task.func;
         let args = task.args;
+        let tag = task.tag;
         let tx = Arc::clone(&tx);
+        let guest = batch.spawn(move || {
+            slot.start()?;
+            let _task = executor::task_scope(index, count);
+            run_task(&wasm, &func, &args, tag.as_deref())
+        });
         handles.push(scheduler::TOKIO_RT.spawn(async move {
-            let result = tokio::task::spawn_blocking(move || {
-                executor::exec_wasm_sync(&wasm_bytes, &func, &args)
-            }).await.unwrap_or_else(|e| Err(format!("join: {}", e)));
+            let result = guest.await.unwrap_or_else(|e| Err(format!("join: {}", e)));
             if let Ok(v) = &result {
                 if let Some(sender) = tx.lock().await.take() {
                     let _ = sender.send(Ok(*v));
@@ -178,6 +2647,7 @@ pub async fn concurrent_wasm_first(tasks: Vec<WasmTask>) -> Result<i64> {
             result
         }));
     }
+    batch.submit();
 
     // Wait for first Ok, or collect all errors
     match rx.await {
@@ -203,18 +2673,28 @@ pub async fn concurrent_wasm_first(tasks: Vec<WasmTask>) -> Result<i64> {
 
 /// Timeout mode: cancel all tasks after deadline
 #[napi]
-pub async fn concurrent_wasm_timeout(tasks: Vec<WasmTask>, timeout_ms: u32) -> Result<Vec<i64>> {
+pub async fn concurrent_wasm_timeout(mut tasks: Vec<WasmTask>, timeout_ms: u32) -> Result<Vec<i64>> {
+    tag_tasks(&mut tasks, &exec_defaults());
+    check_tasks(&tasks)?;
     let duration = std::time::Duration::from_millis(timeout_ms as u64);
 
     let mut handles = Vec::with_capacity(tasks.len());
-    for task in tasks {
-        let wasm_bytes = task.wasm.to_vec();
+    let mut resolver = executor::WasmResolver::new();
+    let mut batch = scheduler::GuestBatch::new();
+    let count = tasks.len();
+    let slots = enqueue(count, None)?;
+    for ((index, task), slot) in tasks.into_iter().enumerate().zip(slots) {
+        let wasm = resolve_task(&mut resolver, &task)?;
         let func = task.func;
         let args = task.args;
-        handles.push(scheduler::TOKIO_RT.spawn_blocking(move || {
-            executor::exec_wasm_sync(&wasm_bytes, &func, &args)
+        let tag = task.tag;
+        handles.push(batch.spawn(move || {
+            slot.start()?;
+            let _task = executor::task_scope(index, count);
+            run_task(&wasm, &func, &args, tag.as_deref())
         }));
     }
+    batch.submit();
 
     match tokio::time::timeout(duration, async {
         let mut results = Vec::with_capacity(handles.len());
@@ -240,16 +2720,27 @@ pub async fn concurrent_wasm_timeout(tasks: Vec<WasmTask>, timeout_ms: u32) -> R
 /// Uses try_join_all to poll all tasks concurrently — detects the first error
 /// immediately rather than waiting sequentially for earlier tasks to complete.
 #[napi]
-pub async fn concurrent_wasm_cancel_on_error(tasks: Vec<WasmTask>) -> Result<Vec<i64>> {
+pub async fn concurrent_wasm_cancel_on_error(mut tasks: Vec<WasmTask>) -> Result<Vec<i64>> {
+    tag_tasks(&mut tasks, &exec_defaults());
+    check_tasks(&tasks)?;
     // Spawn all tasks on the blocking thread pool
-    let handles: Vec<_> = tasks.into_iter().map(|task| {
-        let wasm_bytes = task.wasm.to_vec();
+    let mut resolver = executor::WasmResolver::new();
+    let mut batch = scheduler::GuestBatch::new();
+    let mut handles = Vec::with_capacity(tasks.len());
+    let count = tasks.len();
+    let slots = enqueue(count, None)?;
+    for ((index, task), slot) in tasks.into_iter().enumerate().zip(slots) {
+        let wasm = resolve_task(&mut resolver, &task)?;
         let func = task.func;
         let args = task.args;
-        scheduler::TOKIO_RT.spawn_blocking(move || {
-            executor::exec_wasm_sync(&wasm_bytes, &func, &args)
-        })
-    }).collect();
+        let tag = task.tag;
+        handles.push(batch.spawn(move || {
+            slot.start()?;
+            let _task = executor::task_scope(index, count);
+            run_task(&wasm, &func, &args, tag.as_deref())
+        }));
+    }
+    batch.submit();
 
     // Wrap each handle in a future that flattens the nested Results
     let futures: Vec<_> = handles.into_iter().map(|h| {
@@ -268,31 +2759,168 @@ pub async fn concurrent_wasm_cancel_on_error(tasks: Vec<WasmTask>) -> Result<Vec
 
 // --- WASM with channel host imports ---
 
+#[napi(object)]
+pub struct ChannelExecOptions {
+    /// Context from `contextCreate`; guest channel ids then resolve through its grants
+    pub channel_context: Option<i64>,
+    /// Channel the guest publishes intermediate results on, granted to it as
+    /// guest id 0: `chan_send(0, value)` streams a value out while the guest
+    /// keeps running (see `channelSubscribe`). Without `channelContext` the
+    /// guest gets a context of its own holding only this channel.
+    pub result_channel: Option<i64>,
+    /// Imports the module may have, as "module.name"; any other fails with
+    /// ERR_FORBIDDEN_IMPORT, and only these are linked (default: all)
+    pub allowed_imports: Option<Vec<String>>,
+    /// Import families to allow, added to `allowedImports`
+    pub capabilities: Option<Capabilities>,
+    /// Record every host import call the guest makes, for `replayExecution`.
+    /// The call then resolves with a RecordedExec, carrying the guest's
+    /// error rather than rejecting with it, so a failing call is recorded too.
+    pub record: Option<bool>,
+}
+
+/// A recorded `execWasmWithChannels` call.
+#[napi(object)]
+pub struct RecordedExec {
+    pub result: Option<i64>,
+    pub error: Option<String>,
+    /// JSON holding the module's hash, the function and args, every host
+    /// import call with its args, result and the guest memory it filled
+    /// (redacted past 4 KiB a call or 1 MiB in all), the fuel used and the
+    /// outcome; integers are exact, so pass it on as the string rather than
+    /// through JSON.parse
+    pub recording: String,
+}
+
+/// Context a channel exec runs in. One created just for a result channel is
+/// destroyed with the guard (which leaves the granted channel open).
+struct ExecContext {
+    id: Option<u64>,
+    ephemeral: bool,
+}
+
+impl Drop for ExecContext {
+    fn drop(&mut self) {
+        if let (true, Some(id)) = (self.ephemeral, self.id) {
+            contexts::destroy(id);
+        }
+    }
+}
+
+fn exec_context(options: &Option<ChannelExecOptions>) -> Result<ExecContext> {
+    let context = channel_context(options)?;
+    let result_channel = match options.as_ref().and_then(|o| o.result_channel) {
+        Some(id) => untag(id, "channel")?,
+        None => return Ok(ExecContext { id: context, ephemeral: false }),
+    };
+    let exec = match context {
+        Some(id) => ExecContext { id: Some(id), ephemeral: false },
+        None => ExecContext { id: Some(contexts::create()), ephemeral: true },
+    };
+    if let Some(id) = exec.id {
+        contexts::grant_result(id, result_channel).map_err(Error::from_reason)?;
+    }
+    Ok(exec)
+}
+
+fn channel_context(options: &Option<ChannelExecOptions>) -> Result<Option<u64>> {
+    let ctx = match options.as_ref().and_then(|o| o.channel_context) {
+        Some(ctx) => ctx,
+        None => return Ok(None),
+    };
+    let id = untag(ctx, "channel context")?;
+    if !contexts::exists(id) {
+        return Err(Error::from_reason(format!("no such channel context: {}", ctx)));
+    }
+    Ok(Some(id))
+}
+
 #[napi]
-pub async fn exec_wasm_with_channels(wasm: Buffer, func: String, args: Vec<i64>) -> Result<i64> {
-    let wasm_bytes = wasm.to_vec();
-    let result = scheduler::TOKIO_RT
-        .spawn_blocking(move || {
-            executor::exec_wasm_with_channels(&wasm_bytes, &func, &args)
+pub async fn exec_wasm_with_channels(
+    wasm: Buffer,
+    func: String,
+    args: Vec<i64>,
+    options: Option<ChannelExecOptions>,
+) -> Result<Either<i64, RecordedExec>> {
+    check_call(Some(wasm.len()), args.len())?;
+    let exec = exec_context(&options)?;
+    let context = exec.id;
+    let allowed = options.as_ref().and_then(|o| allowed_imports(&o.allowed_imports, &o.capabilities));
+    let record = options.as_ref().and_then(|o| o.record).unwrap_or(false);
+    let module = record.then(|| recording::module_hash(&wasm));
+    let wasm = executor::resolve_wasm(&wasm);
+    let slot = enqueue(1, None)?.remove(0);
+    if let Some(module) = module {
+        let recording = scheduler::spawn_guest(move || {
+            slot.start()?;
+            Ok(executor::exec_wasm_recorded(&wasm, module, &func, &args, context, allowed.as_deref()))
         })
         .await
         .map_err(|e| Error::from_reason(format!("join: {}", e)))?
-        .map_err(|e| Error::from_reason(e))?;
+        .map_err(|e: String| Error::from_reason(e))?;
+        return Ok(Either::B(RecordedExec {
+            result: recording.outcome.as_ref().ok().copied(),
+            error: recording.outcome.as_ref().err().cloned(),
+            recording: recording.to_json(),
+        }));
+    }
+    let result = scheduler::spawn_guest(move || {
+        slot.start()?;
+        executor::exec_wasm_with_channels(&wasm, &func, &args, context, allowed.as_deref())
+    })
+    .await
+    .map_err(|e| Error::from_reason(format!("join: {}", e)))?
+    .map_err(Error::from_reason)?;
+    Ok(Either::A(result))
+}
+
+/// Run a call `execWasmWithChannels` recorded again on `wasm`, the same
+/// module, answering each host import from the recording instead of
+/// touching channels. Resolves or rejects as the recorded call did; fails
+/// with ERR_REPLAY_DIVERGED naming the first import call (by index) or the
+/// outcome that differs, and with ERR_INVALID_INPUT for another module or a
+/// recording that lacks a call the replay needs (truncated or redacted).
+#[napi]
+pub async fn replay_execution(recording: String, wasm: Buffer) -> Result<i64> {
+    check_call(Some(wasm.len()), 0)?;
+    let recording = recording::Recording::from_json(&recording).map_err(Error::from_reason)?;
+    let module = recording::module_hash(&wasm);
+    let wasm = executor::resolve_wasm_with(&wasm, recording.metering);
+    let slot = enqueue(1, None)?.remove(0);
+    let result = scheduler::spawn_guest(move || {
+        slot.start()?;
+        executor::replay(&wasm, module, &recording)
+    })
+    .await
+    .map_err(|e| Error::from_reason(format!("join: {}", e)))?
+    .map_err(Error::from_reason)?;
     Ok(result)
 }
 
 #[napi]
-pub async fn concurrent_wasm_with_channels(tasks: Vec<WasmTask>) -> Result<Vec<i64>> {
+pub async fn concurrent_wasm_with_channels(tasks: Vec<WasmTask>, options: Option<ChannelExecOptions>) -> Result<Vec<i64>> {
+    check_tasks(&tasks)?;
+    let exec = exec_context(&options)?;
+    let context = exec.id;
+    let allowed = options.as_ref().and_then(|o| allowed_imports(&o.allowed_imports, &o.capabilities));
     let mut handles = Vec::with_capacity(tasks.len());
 
-    for task in tasks {
-        let wasm_bytes = task.wasm.to_vec();
+    let mut resolver = executor::WasmResolver::new();
+    let mut batch = scheduler::GuestBatch::new();
+    let count = tasks.len();
+    let slots = enqueue(count, None)?;
+    for ((index, task), slot) in tasks.into_iter().enumerate().zip(slots) {
+        let wasm = resolve_task(&mut resolver, &task)?;
         let func = task.func;
         let args = task.args;
-        handles.push(scheduler::TOKIO_RT.spawn_blocking(move || {
-            executor::exec_wasm_with_channels(&wasm_bytes, &func, &args)
+        let allowed = allowed.clone();
+        handles.push(batch.spawn(move || {
+            slot.start()?;
+            let _task = executor::task_scope(index, count);
+            executor::exec_wasm_with_channels(&wasm, &func, &args, context, allowed.as_deref())
         }));
     }
+    batch.submit();
 
     let mut results = Vec::with_capacity(handles.len());
     for handle in handles {
@@ -304,3 +2932,795 @@ pub async fn concurrent_wasm_with_channels(tasks: Vec<WasmTask>) -> Result<Vec<i
     }
     Ok(results)
 }
+
+// --- Pipelines ---
+
+#[napi(object)]
+pub struct PipelineStageConfig {
+    pub wasm: Option<Buffer>,
+    pub module: Option<i64>,
+    pub func: String,
+    pub workers: Option<u32>,
+    pub input_channel: i64,
+    pub output_channel: i64,
+    pub fuel: Option<i64>,
+}
+
+#[napi(object)]
+pub struct PipelineConfig {
+    pub stages: Vec<PipelineStageConfig>,
+}
+
+#[napi(object)]
+pub struct PipelineStageStats {
+    pub func: String,
+    pub workers: u32,
+    pub live_workers: u32,
+    pub processed: i64,
+    pub errors: i64,
+    /// Values processed per second since the pipeline started
+    pub throughput: f64,
+    /// Values waiting in the stage's input channel
+    pub queue_depth: u32,
+    pub last_error: Option<String>,
+}
+
+impl From<pipeline::StageStats> for PipelineStageStats {
+    fn from(s: pipeline::StageStats) -> Self {
+        PipelineStageStats {
+            func: s.func,
+            workers: s.workers as u32,
+            live_workers: s.live_workers as u32,
+            processed: s.processed as i64,
+            errors: s.errors as i64,
+            throughput: s.throughput,
+            queue_depth: s.queue_depth as u32,
+            last_error: s.last_error,
+        }
+    }
+}
+
+/// Wire channels and WASM stages into a running pipeline. Each stage spawns
+/// `workers` loops that receive from `inputChannel`, call `func` with the
+/// value, and send the result to `outputChannel`. Returns the pipeline id.
+#[napi]
+pub async fn pipeline_create(config: PipelineConfig) -> Result<i64> {
+    for s in &config.stages {
+        if s.module.is_none() {
+            check_call(s.wasm.as_ref().map(|w| w.len()), 1)?;
+        }
+    }
+    let mut resolver = executor::WasmResolver::new();
+    let mut specs = Vec::with_capacity(config.stages.len());
+    for s in config.stages {
+        let wasm = match (s.module, &s.wasm) {
+            (Some(handle), _) => executor::precompiled(untag(handle, "module handle")?).map_err(Error::from_reason)?,
+            (None, Some(wasm)) => resolver.resolve(wasm),
+            (None, None) => return Err(Error::from_reason("stage needs either `wasm` or `module`".to_string())),
+        };
+        specs.push(pipeline::StageSpec {
+            wasm,
+            func: s.func,
+            workers: s.workers.unwrap_or(1) as usize,
+            input: untag(s.input_channel, "channel")?,
+            output: untag(s.output_channel, "channel")?,
+            fuel: s.fuel.map(|f| f.max(0) as u64).unwrap_or(executor::DEFAULT_FUEL),
+        });
+    }
+    let id = scheduler::TOKIO_RT
+        .spawn_blocking(move || pipeline::create(specs))
+        .await
+        .map_err(|e| Error::from_reason(format!("join: {}", e)))?
+        .map_err(Error::from_reason)?;
+    Ok(lifecycle::tag(id))
+}
+
+#[napi]
+pub fn pipeline_stats(id: i64) -> Result<Vec<PipelineStageStats>> {
+    pipeline::stats(untag(id, "pipeline")?)
+        .map(|stages| stages.into_iter().map(PipelineStageStats::from).collect())
+        .map_err(Error::from_reason)
+}
+
+/// Stop a pipeline. With `drain`, the source channel is closed and every
+/// buffered value is processed before the workers exit; otherwise workers
+/// stop after their current value. Resolves with the final stage stats.
+#[napi]
+pub async fn pipeline_shutdown(id: i64, drain: bool) -> Result<Vec<PipelineStageStats>> {
+    let stages = pipeline::shutdown(untag(id, "pipeline")?, drain)
+        .await
+        .map_err(Error::from_reason)?;
+    Ok(stages.into_iter().map(PipelineStageStats::from).collect())
+}
+
+// --- Kernels ---
+// The tova_native kernels over TypedArrays, for callers already on the napi
+// module; the library's bun:ffi exports remain for those who want no napi
+// overhead. Arrays are used in place, and a wrong kind or a detached buffer
+// fails with ERR_INVALID_INPUT.
+
+/// Sort a Float64Array in place in `f64::total_cmp` order: -0.0 before
+/// +0.0, NaNs at the end (negative-signed NaNs at the front).
+#[napi]
+pub fn kernel_sort_f64(arr: typed_views::TypedView) -> Result<()> {
+    let data = unsafe { arr.elements::<f64>("kernelSortF64") }.map_err(Error::from_reason)?;
+    tova_native::kernels::sort_f64(data);
+    Ok(())
+}
+
+/// Kahan-compensated sum of a Float64Array (0 when empty).
+#[napi]
+pub fn kernel_sum_f64(arr: typed_views::TypedView) -> Result<f64> {
+    let data = unsafe { arr.elements::<f64>("kernelSumF64") }.map_err(Error::from_reason)?;
+    Ok(tova_native::kernels::sum_f64(data))
+}
+
+/// Indices that would sort a Float64Array, in `kernelSortF64` order with
+/// ties in index order. The array itself is left as it is.
+#[napi]
+pub fn kernel_argsort_f64(arr: typed_views::TypedView) -> Result<Uint32Array> {
+    let data = unsafe { arr.elements::<f64>("kernelArgsortF64") }.map_err(Error::from_reason)?;
+    if u32::try_from(data.len()).is_err() {
+        return Err(Error::from_reason(errors::coded(
+            errors::ERR_INVALID_INPUT,
+            format!("kernelArgsortF64: {} values is past the 2^32 a Uint32Array of indices can hold", data.len()),
+        )));
+    }
+    let mut indices: Vec<u32> = (0..data.len() as u32).collect();
+    tova_native::kernels::sort_f64_by_key(&mut indices, |&i| data[i as usize]);
+    Ok(Uint32Array::new(indices))
+}
+
+/// Each distinct value of a BigInt64Array, ascending, with how often it
+/// occurs.
+#[napi(object)]
+pub struct ValueCounts {
+    pub keys: BigInt64Array,
+    pub counts: BigUint64Array,
+}
+
+/// Count the distinct values of a BigInt64Array. Sorts a copy; the array
+/// itself is left as it is.
+#[napi]
+pub fn kernel_value_counts_i64(arr: typed_views::TypedView) -> Result<ValueCounts> {
+    let data = unsafe { arr.elements::<i64>("kernelValueCountsI64") }.map_err(Error::from_reason)?;
+    let mut keys = data.to_vec();
+    tova_native::kernels::sort_i64(&mut keys);
+    let mut counts = vec![0u64; keys.len()];
+    let distinct = tova_native::kernels::unique_counts_sorted_i64(&mut keys, &mut counts);
+    keys.truncate(distinct);
+    counts.truncate(distinct);
+    Ok(ValueCounts { keys: BigInt64Array::new(keys), counts: BigUint64Array::new(counts) })
+}
+
+/// Default least time between a kernel's progress calls, in ms
+const DEFAULT_KERNEL_PROGRESS_MS: u32 = 100;
+
+#[napi(object)]
+pub struct KernelOptions {
+    /// Stop with ERR_CANCELLED soon after this token is cancelled. A
+    /// cancelled sort leaves the array holding its values in some order.
+    pub cancel_token: Option<i64>,
+    /// Least time between calls to `onProgress` before the last (default 100)
+    pub progress_interval_ms: Option<u32>,
+}
+
+/// A kernel's progress callback: false, stopping it, once `cancel` is
+/// cancelled; `report` hears the fraction done at most every `interval`,
+/// and 1 when the kernel finishes.
+fn kernel_progress(
+    cancel: Option<Arc<cancel_tokens::Token>>,
+    interval: std::time::Duration,
+    report: impl Fn(f64),
+) -> impl Fn(u64) -> bool {
+    let last = std::cell::Cell::new(None::<std::time::Instant>);
+    move |done| {
+        let now = std::time::Instant::now();
+        if done == tova_native::kernels::PROGRESS_DONE || last.get().is_none_or(|at| now - at >= interval) {
+            last.set(Some(now));
+            report(done as f64 / tova_native::kernels::PROGRESS_DONE as f64);
+        }
+        !cancel.as_ref().is_some_and(|token| token.is_cancelled())
+    }
+}
+
+/// One of tova_native's `*_with_progress` kernels.
+type ProgressKernel<T, R> = fn(&mut [T], &dyn Fn(u64) -> bool) -> std::result::Result<R, tova_native::kernels::Cancelled>;
+
+/// Run `kernel` over `arr` on the compute pool, checking `options`' cancel
+/// token and reporting to `on_progress` between chunks of its work.
+async fn run_kernel<A: typed_views::HeldArray, R: Send + 'static>(
+    what: &'static str,
+    arr: typed_views::Held<A>,
+    options: Option<KernelOptions>,
+    on_progress: Option<ThreadsafeFunction<f64, (), f64, Status, false>>,
+    kernel: ProgressKernel<A::Element, R>,
+) -> Result<R> {
+    let mut array = arr.take(what).map_err(Error::from_reason)?;
+    let cancel = live_cancel_token(options.as_ref().and_then(|o| o.cancel_token))?;
+    let interval_ms = options.as_ref().and_then(|o| o.progress_interval_ms).unwrap_or(DEFAULT_KERNEL_PROGRESS_MS);
+    let interval = std::time::Duration::from_millis(interval_ms as u64);
+    let result = scheduler::COMPUTE_POOL
+        .spawn(move |_| {
+            let progress = kernel_progress(cancel, interval, |fraction| {
+                if let Some(on_progress) = &on_progress {
+                    on_progress.call(fraction, ThreadsafeFunctionCallMode::NonBlocking);
+                }
+            });
+            kernel(unsafe { array.elements() }, &progress)
+        })
+        .await
+        .map_err(|e| Error::from_reason(format!("join: {}", e)))?;
+    result.map_err(|_| Error::from_reason(cancel_tokens::cancelled_error()))
+}
+
+/// `kernelSortF64` on the compute pool, cancellable and reporting progress
+/// (the fraction done, 0 to 1) as it goes. The array must not be written or
+/// detached until the promise settles.
+#[napi]
+pub async fn kernel_sort_f64_async(
+    arr: typed_views::Held<Float64Array>,
+    options: Option<KernelOptions>,
+    on_progress: Option<ThreadsafeFunction<f64, (), f64, Status, false>>,
+) -> Result<()> {
+    run_kernel("kernelSortF64Async", arr, options, on_progress, tova_native::kernels::sort_f64_with_progress).await
+}
+
+/// Sort a BigInt64Array in place on the compute pool, as `kernelSortF64Async`.
+#[napi]
+pub async fn kernel_sort_i64_async(
+    arr: typed_views::Held<BigInt64Array>,
+    options: Option<KernelOptions>,
+    on_progress: Option<ThreadsafeFunction<f64, (), f64, Status, false>>,
+) -> Result<()> {
+    run_kernel("kernelSortI64Async", arr, options, on_progress, tova_native::kernels::sort_i64_with_progress).await
+}
+
+/// `kernelSumF64` on the compute pool, as `kernelSortF64Async`.
+#[napi]
+pub async fn kernel_sum_f64_async(
+    arr: typed_views::Held<Float64Array>,
+    options: Option<KernelOptions>,
+    on_progress: Option<ThreadsafeFunction<f64, (), f64, Status, false>>,
+) -> Result<f64> {
+    run_kernel("kernelSumF64Async", arr, options, on_progress, |data, progress| {
+        tova_native::kernels::sum_f64_with_progress(data, progress)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testsupport::{self as fixtures, TrapKind};
+
+    // spin(n) counts down from n; wasmtime compiles the text format directly
+    const SPIN_WAT: &str = r#"(module
+        (func (export "spin") (param $n i64) (result i64)
+            (block $done
+                (loop $next
+                    (br_if $done (i64.le_s (local.get $n) (i64.const 0)))
+                    (local.set $n (i64.sub (local.get $n) (i64.const 1)))
+                    (br $next)))
+            (local.get $n)))"#;
+
+    #[test]
+    fn test_settled_batch_reports_the_slow_task() {
+        let _serial = channels::test_serial();
+        // Compile up front, so the tasks' times are their runs alone
+        assert_eq!(executor::exec_wasm_sync(&executor::resolve_wasm(SPIN_WAT.as_bytes()), "spin", &[1]), Ok(0));
+        let task = |n: i64| WasmTask {
+            wasm: Some(Buffer::from(SPIN_WAT.as_bytes().to_vec())),
+            module: None,
+            func: "spin".to_string(),
+            args: vec![n],
+            f64_args: None,
+            tag: None,
+        };
+        let tasks = (0..8).map(|i| task(if i == 5 { 100_000_000 } else { 10 })).collect();
+        let options = BatchOptions {
+            shared_fuel_budget: None,
+            max_concurrency: None,
+            slow_task_threshold_ms: Some(25),
+            latency_summary: Some(true),
+            metering: None,
+            completion: None,
+            queue_timeout_ms: None,
+        };
+        let logged = metrics::slow_tasks_logged();
+        let batch = scheduler::TOKIO_RT.block_on(concurrent_wasm_settled(tasks, Some(options))).unwrap();
+        assert!(batch.results.iter().all(|r| r.ok));
+        assert_eq!(batch.latency.unwrap().max_index, Some(5));
+        assert_eq!(metrics::slow_tasks_logged() - logged, 1);
+    }
+
+    #[test]
+    fn test_exec_wasm_marshals_results_and_errors() {
+        assert_eq!(fixtures::exec(&fixtures::add_module(), "add", &[2, 3]), Ok(5));
+        assert_eq!(fixtures::exec(&fixtures::fib_module(), "fib", &[90]), Ok(2_880_067_194_370_816_120));
+        assert_eq!(fixtures::exec(&fixtures::memory_sum_module(4), "fill_sum", &[10]), Ok(46));
+
+        let err = fixtures::exec(&fixtures::add_module(), "nope", &[]).unwrap_err();
+        assert!(err.contains("'nope' not found"), "{}", err);
+        let err = fixtures::exec(&fixtures::depth_module(), "depth", &[-1]).unwrap_err();
+        assert!(err.starts_with(errors::ERR_STACK_OVERFLOW), "{}", err);
+        let err = fixtures::exec(&fixtures::start_module(Some(TrapKind::Unreachable)), "run", &[]).unwrap_err();
+        assert!(err.starts_with(errors::ERR_TRAP_UNREACHABLE), "{}", err);
+    }
+
+    #[test]
+    fn test_batches_marshal_every_task() {
+        let add = fixtures::add_module();
+        let fib = fixtures::fib_module();
+        let tasks = vec![
+            fixtures::task(&add, "add", &[20, 22]),
+            fixtures::task(&fib, "fib", &[10]),
+            fixtures::task(&add, "add", &[-1, 1]),
+        ];
+        assert_eq!(fixtures::concurrent(tasks), Ok(vec![42, 55, 0]));
+
+        let typed = fixtures::typed_module();
+        let tasks = (0..6).map(|i| fixtures::task(&typed, "mad4_i64", &[i, i, 1, 1])).collect();
+        assert_eq!(fixtures::shared(tasks), Ok(vec![-1, 0, 3, 8, 15, 24]));
+
+        let mut float = fixtures::task(&typed, "div", &[]);
+        float.f64_args = Some(vec![1.0, 8.0]);
+        let err = fixtures::concurrent(vec![float]).unwrap_err();
+        assert!(err.starts_with(errors::ERR_INVALID_INPUT), "{}", err);
+    }
+
+    #[test]
+    fn test_guests_see_their_task_index() {
+        let wasm = fixtures::task_module();
+        let tasks = |func: &str| (0..20).map(|_| fixtures::task(&wasm, func, &[])).collect::<Vec<_>>();
+        let indices: Vec<i64> = (0..20).collect();
+        let three = || Some(SharedBatchOptions { chunks: Some(3) });
+        assert_eq!(fixtures::shared(tasks("index")), Ok(indices.clone()));
+        assert_eq!(fixtures::block_on(concurrent_wasm_shared(tasks("index"), three())).map_err(|e| e.reason), Ok(indices.clone()));
+        assert_eq!(fixtures::block_on(concurrent_wasm_shared(tasks("index_i32"), three())).map_err(|e| e.reason), Ok(indices.clone()));
+        assert_eq!(fixtures::block_on(concurrent_wasm_shared(tasks("count"), three())).map_err(|e| e.reason), Ok(vec![20; 20]));
+        assert_eq!(fixtures::concurrent(tasks("index")), Ok(indices));
+        assert_eq!(fixtures::exec(&wasm, "index", &[]), Ok(0));
+        assert_eq!(fixtures::exec(&wasm, "count", &[]), Ok(1));
+    }
+
+    #[test]
+    fn test_exec_with_channels_reaches_the_registry() {
+        let _serial = channels::test_serial();
+        let ch = channels::create(2);
+        assert_eq!(fixtures::exec_with_channels(&fixtures::send_once_module(), "send_once", &[ch as i64, 7]), Ok(0));
+        assert_eq!(channels::receive(ch), Some(7));
+        // Plain execWasm does not link the channel imports
+        assert!(fixtures::exec(&fixtures::send_once_module(), "send_once", &[ch as i64, 8]).is_err());
+        channels::destroy(ch);
+    }
+
+    fn completion_options(completion: &str) -> Option<BatchOptions> {
+        Some(BatchOptions {
+            shared_fuel_budget: None,
+            max_concurrency: None,
+            slow_task_threshold_ms: None,
+            latency_summary: None,
+            metering: None,
+            completion: Some(completion.to_string()),
+            queue_timeout_ms: None,
+        })
+    }
+
+    /// One slow fib followed by 99 quick adds
+    fn slow_first_batch() -> Vec<WasmTask> {
+        let fib = fixtures::fib_module();
+        let add = fixtures::add_module();
+        std::iter::once(fixtures::task(&fib, "fib", &[30_000_000]))
+            .chain((1..100).map(|i| fixtures::task(&add, "add", &[i, i])))
+            .collect()
+    }
+
+    fn run_recording_order(tasks: Vec<WasmTask>, completion: &str) -> (Vec<usize>, Vec<std::result::Result<i64, String>>) {
+        let mut order = Vec::new();
+        let timed = fixtures::block_on(run_metered_batch(tasks, &completion_options(completion), |index, _| {
+            order.push(index);
+            Ok(())
+        }))
+        .unwrap();
+        (order, timed.into_iter().map(|(m, _)| m.result).collect())
+    }
+
+    #[test]
+    fn test_tasks_past_their_queue_timeout_never_run() {
+        let _serial = channels::test_serial();
+        let fib = fixtures::fib_module();
+        // Tens of milliseconds each, well inside DEFAULT_FUEL
+        let tasks = (0..6).map(|_| fixtures::task(&fib, "fib", &[30_000_000])).collect();
+        // One at a time, so every task after the first waits out the slow one
+        let options = BatchOptions { max_concurrency: Some(1), queue_timeout_ms: Some(10), ..completion_options("ordered").unwrap() };
+        let timeouts = scheduler::queue_stats().timeouts;
+        let settled = fixtures::block_on(concurrent_wasm_settled(tasks, Some(options))).unwrap();
+        assert!(settled.results[0].ok);
+        assert!(settled.results[0].fuel_used > 0);
+        // The rest never ran, so they burnt no fuel
+        for task in &settled.results[1..] {
+            assert!(task.error.as_deref().is_some_and(|e| e.starts_with(errors::ERR_QUEUE_TIMEOUT)), "{:?}", task.error);
+            assert_eq!(task.fuel_used, 0);
+        }
+        assert_eq!(settled.total_fuel_used, settled.results[0].fuel_used);
+        assert!(scheduler::queue_stats().timeouts >= timeouts + 5);
+    }
+
+    #[test]
+    fn test_unordered_completion_reports_fast_tasks_before_a_slow_one() {
+        let (order, unordered) = run_recording_order(slow_first_batch(), "unordered");
+        assert_eq!(order.len(), 100);
+        assert_eq!(order[99], 0, "the slow task should come last: {:?}", order);
+
+        let (order, ordered) = run_recording_order(slow_first_batch(), "ordered");
+        assert_eq!(order, (0..100).collect::<Vec<_>>());
+        // Results land by index either way
+        assert_eq!(unordered, ordered);
+        assert_eq!(ordered[5], Ok(10));
+    }
+
+    #[test]
+    fn test_unordered_completion_fails_on_the_first_error_to_finish() {
+        let tasks = vec![
+            fixtures::task(&fixtures::fib_module(), "fib", &[30_000_000]),
+            fixtures::task(&fixtures::add_module(), "nope", &[]),
+        ];
+        let mut seen = Vec::new();
+        let err = fixtures::block_on(run_metered_batch(tasks, &completion_options("unordered"), |index, metered| {
+            seen.push(index);
+            metered.result.clone().map(|_| ()).map_err(Error::from_reason)
+        }))
+        .unwrap_err();
+        assert!(err.reason.contains("'nope' not found"), "{}", err.reason);
+        assert_eq!(seen, vec![1]);
+
+        let err = fixtures::block_on(run_metered_batch(vec![], &completion_options("sorted"), |_, _| Ok(())))
+            .unwrap_err();
+        assert!(err.reason.starts_with(errors::ERR_INVALID_INPUT), "{}", err.reason);
+    }
+
+    fn chunked(wasm: &[u8], func: &str, args: &[i64], arity: u32, options: Option<ChunkedBatchOptions>) -> std::result::Result<Vec<i64>, String> {
+        let flat: Vec<u8> = args.iter().flat_map(|v| v.to_le_bytes()).collect();
+        let out = fixtures::block_on(concurrent_wasm_chunked(
+            Buffer::from(wasm.to_vec()),
+            func.to_string(),
+            Buffer::from(flat),
+            arity,
+            options,
+            None,
+        ))
+        .map_err(|e| e.reason)?;
+        Ok(out.chunks_exact(8).map(|b| i64::from_le_bytes(b.try_into().unwrap())).collect())
+    }
+
+    fn chunk_options(chunk_size: u32, exec_id: Option<i64>) -> Option<ChunkedBatchOptions> {
+        Some(ChunkedBatchOptions { chunk_size: Some(chunk_size), exec_id, metering: None, cancel_token: None })
+    }
+
+    #[test]
+    fn test_chunked_batch_covers_a_million_tasks() {
+        let n = 1_000_000;
+        let args: Vec<i64> = (0..n).flat_map(|i| [i, 2 * i]).collect();
+        let results = chunked(&fixtures::add_module(), "add", &args, 2, chunk_options(65_536, None)).unwrap();
+        assert_eq!(results.len(), n as usize);
+        assert!(results.iter().enumerate().all(|(i, &r)| r == 3 * i as i64));
+        // A short last chunk, and the default chunk size
+        assert_eq!(chunked(&fixtures::add_module(), "add", &[1, 2, 3, 4, 5, 6], 2, chunk_options(2, None)), Ok(vec![3, 7, 11]));
+        assert_eq!(chunked(&fixtures::fib_module(), "fib", &[10, 20], 1, None), Ok(vec![55, 6765]));
+        assert_eq!(chunked(&fixtures::add_module(), "add", &[], 2, None), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_chunked_batch_rejects_bad_input_and_fails_on_errors() {
+        let add = fixtures::add_module();
+        for (args, arity) in [(vec![1, 2, 3], 2), (vec![1], 0)] {
+            let err = chunked(&add, "add", &args, arity, None).unwrap_err();
+            assert!(err.starts_with(errors::ERR_INVALID_INPUT), "{}", err);
+        }
+        let err = chunked(&add, "add", &[1, 2], 2, chunk_options(u32::MAX, None)).unwrap_err();
+        assert!(err.contains("maxTasksPerBatch"), "{}", err);
+        let err = chunked(&add, "nope", &[1, 2], 2, None).unwrap_err();
+        assert!(err.contains("'nope' not found"), "{}", err);
+    }
+
+    #[test]
+    fn test_cancelled_exec_id_stops_a_chunked_batch() {
+        // Exec ids carry the generation, which the lifecycle tests move on
+        let _serial = channels::test_serial();
+        let id = exec_id_create();
+        assert_eq!(exec_cancel(id).map_err(|e| e.reason), Ok(true));
+        let err = chunked(&fixtures::add_module(), "add", &[1, 2, 3, 4], 2, chunk_options(1, Some(id))).unwrap_err();
+        assert!(err.starts_with(errors::ERR_CANCELLED) && err.contains("after 0 of 2"), "{}", err);
+        // The batch released the id when it stopped
+        assert_eq!(exec_cancel(id).map_err(|e| e.reason), Ok(false));
+    }
+
+    #[test]
+    fn test_cancelling_a_parent_token_stops_a_running_exec() {
+        // Token ids carry the generation, which the lifecycle tests move on
+        let _serial = channels::test_serial();
+        let parent = cancel_token_create(None).map_err(|e| e.reason).unwrap();
+        let child = cancel_token_child(parent).map_err(|e| e.reason).unwrap();
+        let options = |token| {
+            Some(Strict(ExecOptions { metering: Some("none".into()), cancel_token: Some(token), ..Default::default() }))
+        };
+        let wasm = Buffer::from(fixtures::infinite_loop_module());
+        let spinning = std::thread::spawn(move || {
+            fixtures::block_on(exec_wasm(wasm, "spin".into(), vec![0], options(child))).map_err(|e| e.reason)
+        });
+        std::thread::sleep(std::time::Duration::from_millis(50));
+
+        let started = std::time::Instant::now();
+        assert_eq!(cancel_token_cancel(parent).map_err(|e| e.reason), Ok(true));
+        let err = spinning.join().unwrap().unwrap_err();
+        assert!(err.starts_with(errors::ERR_CANCELLED), "{}", err);
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+        assert_eq!(cancel_token_is_cancelled(child).map_err(|e| e.reason), Ok(true));
+
+        // A call given a cancelled token fails without running
+        let add = Buffer::from(fixtures::add_module());
+        let err = fixtures::block_on(exec_wasm(add, "add".into(), vec![1, 2], options(child))).unwrap_err().reason;
+        assert!(err.starts_with(errors::ERR_CANCELLED), "{}", err);
+    }
+
+    #[test]
+    fn test_closing_a_scope_cancels_its_execs_and_destroys_its_resources() {
+        let _serial = channels::test_serial();
+        let scope = scope_create(None).map_err(|e| e.reason).unwrap();
+        let in_scope = |capacity| {
+            let options = ChannelOptions { fair: None, ttl_ms: None, timestamps: None, adaptive: None, scope: Some(scope) };
+            channel_create(capacity, Some(options)).map_err(|e| e.reason).unwrap()
+        };
+        let (a, b) = (in_scope(4), in_scope(8));
+        let producer = spawn_channel_producer(a, vec![1, 2], 3_600_000, None, Some(scope)).map_err(|e| e.reason).unwrap();
+        let token = cancel_token_create(Some(scope)).map_err(|e| e.reason).unwrap();
+        let options = Some(Strict(ExecOptions { metering: Some("none".into()), scope: Some(scope), ..Default::default() }));
+        let wasm = Buffer::from(fixtures::infinite_loop_module());
+        let spinning = std::thread::spawn(move || fixtures::block_on(exec_wasm(wasm, "spin".into(), vec![0], options)).map_err(|e| e.reason));
+        std::thread::sleep(std::time::Duration::from_millis(50));
+
+        let options = ScopeCloseOptions { cancel: Some(true), grace_ms: None };
+        let report = fixtures::block_on(scope_close(scope, Some(options))).map_err(|e| e.reason).unwrap();
+        let err = spinning.join().unwrap().unwrap_err();
+        assert!(err.starts_with(errors::ERR_CANCELLED), "{}", err);
+        assert_eq!((report.channels, report.producers, report.cancel_tokens), (2, 1, 1));
+        assert_eq!((report.jobs_cancelled, report.jobs_completed, report.scopes), (1, 0, 0));
+        for channel in [a, b] {
+            assert!(channel_stat(channel).map_err(|e| e.reason).unwrap().is_none());
+        }
+        assert_eq!(channel_producer_cancel(producer).map_err(|e| e.reason), Ok(false));
+        assert!(cancel_token_is_cancelled(token).is_err());
+
+        // The scope is gone, so nothing more can be created in it
+        let options = ChannelOptions { fair: None, ttl_ms: None, timestamps: None, adaptive: None, scope: Some(scope) };
+        let err = channel_create(1, Some(options)).unwrap_err().reason;
+        assert!(err.starts_with(errors::ERR_INVALID_INPUT) && err.contains("no such scope"), "{}", err);
+    }
+
+    #[test]
+    fn test_watchdog_reports_and_kills_a_wedged_guest() {
+        let _serial = channels::test_serial();
+        let previous = config::get().watchdog_threshold;
+        config::update(|c| c.watchdog_threshold = std::time::Duration::from_millis(50));
+        // Unmetered, then under an epoch limit too long to end it first
+        for metering in ["none", "epoch"] {
+            let options = Some(Strict(ExecOptions { metering: Some(metering.into()), ..Default::default() }));
+            let wasm = Buffer::from(fixtures::infinite_loop_module());
+            let spinning =
+                std::thread::spawn(move || fixtures::block_on(exec_wasm(wasm, "spin".into(), vec![0], options)).map_err(|e| e.reason));
+            let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+            let entry = loop {
+                if let Some(entry) = watchdog_report().into_iter().find(|e| e.func.as_deref() == Some("spin")) {
+                    break entry;
+                }
+                assert!(std::time::Instant::now() < deadline, "{} guest never reported", metering);
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            };
+            assert_eq!((entry.kind.as_str(), entry.killable, entry.channel_id), ("exec", true, None));
+            assert!(entry.age_ms >= 50.0, "{}", entry.age_ms);
+            assert_eq!(entry.module_hash.map(|h| h.len()), Some(16));
+
+            assert!(watchdog_kill(entry.entry_id));
+            let err = spinning.join().unwrap().unwrap_err();
+            assert!(err.starts_with(errors::ERR_CANCELLED) && err.contains("watchdogKill"), "{}: {}", metering, err);
+            assert!(watchdog_report().iter().all(|e| e.entry_id != entry.entry_id));
+            assert!(!watchdog_kill(entry.entry_id));
+        }
+
+        // Channel waits are reported but cannot be killed
+        let ch = channels::create(1);
+        let waiter = std::thread::spawn(move || channels::receive_blocking(ch));
+        std::thread::sleep(std::time::Duration::from_millis(80));
+        let wait = watchdog_report().into_iter().find(|e| e.channel_id == Some(lifecycle::tag(ch))).unwrap();
+        assert_eq!((wait.kind.as_str(), wait.task.as_deref(), wait.killable), ("receive", Some("host"), false));
+        assert!(!watchdog_kill(wait.entry_id));
+        channels::close(ch);
+        assert_eq!(waiter.join().unwrap(), None);
+        channels::destroy(ch);
+        config::update(|c| c.watchdog_threshold = previous);
+    }
+
+    #[test]
+    fn test_inline_execs_match_the_async_path_within_their_fuel_cap() {
+        let fib = || Buffer::from(fixtures::fib_module());
+        let inline = |wasm: Either<Buffer, i64>, n: i64| exec_wasm_inline(wasm, "fib".into(), vec![n], None).map_err(|e| e.reason);
+        // Bytes must already be compiled; the first async call does that
+        let err = inline(Either::A(Buffer::from(fixtures::tagged(fixtures::fib_module(), 41))), 10).unwrap_err();
+        assert!(err.starts_with(errors::ERR_INVALID_INPUT) && err.contains("compiled module"), "{}", err);
+        let expected = fixtures::block_on(exec_wasm(fib(), "fib".into(), vec![90], None)).map_err(|e| e.reason);
+        assert_eq!(inline(Either::A(fib()), 90), expected);
+
+        // A loop past the cap fails inline and is fine on the async path
+        let err = inline(Either::A(fib()), 2_000_000).unwrap_err();
+        assert!(err.starts_with(errors::ERR_INLINE_BUDGET) && err.contains("execWasm"), "{}", err);
+        assert!(fixtures::block_on(exec_wasm(fib(), "fib".into(), vec![2_000_000], None)).is_ok());
+
+        let compile = |metering: &str| {
+            let options = CompileOptions { metering: Some(metering.into()), scope: None };
+            fixtures::block_on(module_compile(fib(), Some(options))).map_err(|e| e.reason).unwrap()
+        };
+        let handle = compile("fuel");
+        let pooled = Some(InlineExecOptions { instance_pool: Some(InstancePoolOptions { size: Some(1), reset_every: None }) });
+        assert_eq!(exec_wasm_inline(Either::B(handle), "fib".into(), vec![90], pooled).map_err(|e| e.reason), expected);
+        let unmetered = compile("none");
+        let err = inline(Either::B(unmetered), 90).unwrap_err();
+        assert!(err.starts_with(errors::ERR_INVALID_INPUT) && err.contains("fuel-metered"), "{}", err);
+        for handle in [handle, unmetered] {
+            module_release(handle).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_inline_execs_get_no_fuel_top_ups() {
+        let _serial = channels::test_serial();
+        host_imports::set_fuel_policy(Some(Arc::new(|request: &host_imports::FuelRequest| request.amount)));
+        let wasm = || Buffer::from(fixtures::fuel_module());
+        let granted = fixtures::block_on(exec_wasm(wasm(), "request".into(), vec![1000], None)).map_err(|e| e.reason);
+        let inline = exec_wasm_inline(Either::A(wasm()), "request".into(), vec![1000], None).map_err(|e| e.reason);
+        host_imports::set_fuel_policy(None);
+        assert!(granted.unwrap() > executor::DEFAULT_FUEL as i64);
+        assert_eq!(inline, Ok(0));
+    }
+
+    #[test]
+    fn test_inline_execs_skip_the_pool_round_trip() {
+        let add = || Buffer::from(fixtures::add_module());
+        let pool = || Some(InstancePoolOptions { size: Some(1), reset_every: None });
+        let calls = 200;
+        fixtures::block_on(exec_wasm(add(), "add".into(), vec![1, 2], None)).unwrap();
+
+        let started = std::time::Instant::now();
+        for i in 0..calls {
+            let options = Some(InlineExecOptions { instance_pool: pool() });
+            assert_eq!(exec_wasm_inline(Either::A(add()), "add".into(), vec![i, 1], options).map_err(|e| e.reason), Ok(i + 1));
+        }
+        let inline = started.elapsed();
+        let started = std::time::Instant::now();
+        for i in 0..calls {
+            let options = Some(Strict(ExecOptions { instance_pool: pool(), ..Default::default() }));
+            assert_eq!(fixtures::block_on(exec_wasm(add(), "add".into(), vec![i, 1], options)).map_err(|e| e.reason), Ok(i + 1));
+        }
+        let pooled = started.elapsed();
+        assert!(inline < pooled, "{} inline calls took {:?}, through the pool {:?}", calls, inline, pooled);
+    }
+
+    #[test]
+    fn test_kernels_work_on_typed_arrays_in_place() {
+        use napi::bindgen_prelude::TypedArrayType;
+        use typed_views::TypedView;
+        let mut floats = vec![3.0, -0.0, f64::NAN, 0.0, -1.5, 3.0];
+        let mut view = || TypedView::over(TypedArrayType::Float64, &mut floats);
+        assert!(kernel_sum_f64(view()).unwrap().is_nan());
+        let order = kernel_argsort_f64(view()).unwrap();
+        assert_eq!(&order[..], [4, 1, 3, 0, 5, 2]);
+        kernel_sort_f64(view()).unwrap();
+        assert_eq!(floats[..5], [-1.5, -0.0, 0.0, 3.0, 3.0]);
+        assert!(floats[1].is_sign_negative() && floats[5].is_nan());
+
+        let mut ints = vec![7i64, -2, 7, 0, 7, -2];
+        let counts = kernel_value_counts_i64(TypedView::over(TypedArrayType::BigInt64, &mut ints)).unwrap();
+        assert_eq!((&counts.keys[..], &counts.counts[..]), (&[-2, 0, 7][..], &[2, 1, 3][..]));
+        // Counting left the array alone
+        assert_eq!(ints, [7, -2, 7, 0, 7, -2]);
+
+        let mut finite = vec![1.5, 2.25, -0.75];
+        assert_eq!(kernel_sum_f64(TypedView::over(TypedArrayType::Float64, &mut finite)).map_err(|e| e.reason), Ok(3.0));
+        let mut empty: Vec<f64> = Vec::new();
+        assert_eq!(kernel_sum_f64(TypedView::over(TypedArrayType::Float64, &mut empty)).map_err(|e| e.reason), Ok(0.0));
+        assert!(kernel_argsort_f64(TypedView::over(TypedArrayType::Float64, &mut empty)).unwrap().is_empty());
+        let err = kernel_sort_f64(TypedView::over(TypedArrayType::BigInt64, &mut ints)).unwrap_err().reason;
+        assert!(err.starts_with(errors::ERR_INVALID_INPUT) && err.contains("got a BigInt64Array"), "{}", err);
+    }
+
+    #[test]
+    fn test_kernel_progress_reaches_one_and_stops_on_cancel() {
+        let values: Vec<f64> = (0..300_000u64).map(|i| ((i * 7919) % 300_000) as f64).collect();
+        let reports = std::cell::RefCell::new(Vec::new());
+        let progress = kernel_progress(None, std::time::Duration::ZERO, |fraction| reports.borrow_mut().push(fraction));
+        let mut sorted = values.clone();
+        assert_eq!(tova_native::kernels::sort_f64_with_progress(&mut sorted, &progress), Ok(()));
+        assert!(sorted.windows(2).all(|w| w[0] <= w[1]));
+        let reports = reports.take();
+        assert!(reports.len() > 2 && reports.windows(2).all(|w| w[0] <= w[1]), "{:?}", reports);
+        assert_eq!((reports[0], reports.last()), (0.0, Some(&1.0)));
+
+        // Throttled to the first report and the last
+        let reports = std::cell::RefCell::new(Vec::new());
+        let progress = kernel_progress(None, std::time::Duration::from_secs(60), |fraction| reports.borrow_mut().push(fraction));
+        assert!(tova_native::kernels::sum_f64_with_progress(&values, &progress).is_ok());
+        assert_eq!(reports.take(), [0.0, 1.0]);
+
+        // Cancelling part way leaves the values in some order
+        let _serial = channels::test_serial();
+        let id = cancel_tokens::create();
+        let calls = std::cell::Cell::new(0);
+        let cancelling = kernel_progress(Some(cancel_tokens::get(id).unwrap()), std::time::Duration::ZERO, |_| {
+            calls.set(calls.get() + 1);
+            if calls.get() == 3 {
+                assert_eq!(cancel_tokens::cancel(id), Ok(true));
+            }
+        });
+        let mut partial = values.clone();
+        assert!(tova_native::kernels::sort_f64_with_progress(&mut partial, &cancelling).is_err());
+        assert_eq!(calls.get(), 3);
+        partial.sort_by(f64::total_cmp);
+        assert_eq!(partial, sorted);
+        assert!(cancel_tokens::release(id));
+    }
+
+    #[test]
+    fn test_exec_options_merge_field_by_field_over_defaults() {
+        let defaults = ExecOptions {
+            instance_pool: Some(InstancePoolOptions { size: Some(4), reset_every: Some(100) }),
+            capabilities: Some(Capabilities { channels: Some(true), ..Default::default() }),
+            metering: Some("epoch".into()),
+            queue_timeout_ms: Some(50),
+            allowed_imports: Some(vec!["env.a".into(), "env.b".into()]),
+            ..Default::default()
+        };
+        // Nothing given: the defaults as they are
+        let merged = merge_exec_options(ExecOptions::default(), &defaults);
+        assert_eq!(merged.metering.as_deref(), Some("epoch"));
+        assert_eq!(merged.queue_timeout_ms, Some(50));
+        assert_eq!(merged.instance_pool.as_ref().and_then(|p| p.size), Some(4));
+
+        let call = ExecOptions {
+            instance_pool: Some(InstancePoolOptions { size: Some(1), reset_every: None }),
+            capabilities: Some(Capabilities { native: Some(true), ..Default::default() }),
+            queue_timeout_ms: Some(5),
+            allowed_imports: Some(vec!["env.c".into()]),
+            tag: Some("call".into()),
+            ..Default::default()
+        };
+        let merged = merge_exec_options(call, &defaults);
+        assert_eq!(merged.queue_timeout_ms, Some(5));
+        assert_eq!(merged.metering.as_deref(), Some("epoch"));
+        assert_eq!(merged.tag.as_deref(), Some("call"));
+        let pool = merged.instance_pool.unwrap();
+        assert_eq!((pool.size, pool.reset_every), (Some(1), Some(100)));
+        let capabilities = merged.capabilities.unwrap();
+        assert_eq!((capabilities.channels, capabilities.native), (Some(true), Some(true)));
+        // Lists replace the default rather than extend it
+        assert_eq!(merged.allowed_imports, Some(vec!["env.c".to_string()]));
+    }
+
+    #[test]
+    fn test_batches_take_a_snapshot_of_the_defaults() {
+        let defaults = ExecOptions { tag: Some("team".into()), queue_timeout_ms: Some(20), ..Default::default() };
+        assert!(batch_with_defaults(None, &ExecOptions::default()).is_none());
+        let options = batch_with_defaults(None, &defaults).unwrap();
+        assert_eq!((options.queue_timeout_ms, options.metering), (Some(20), None));
+        let given = BatchOptions { queue_timeout_ms: Some(1), max_concurrency: Some(2), ..Default::default() };
+        let options = batch_with_defaults(Some(given), &defaults).unwrap();
+        assert_eq!((options.queue_timeout_ms, options.max_concurrency), (Some(1), Some(2)));
+
+        let mut tasks = vec![
+            WasmTask { wasm: None, module: Some(1), func: "f".into(), args: vec![], f64_args: None, tag: None },
+            WasmTask { wasm: None, module: Some(1), func: "f".into(), args: vec![], f64_args: None, tag: Some("own".into()) },
+        ];
+        tag_tasks(&mut tasks, &defaults);
+        assert_eq!(tasks[0].tag.as_deref(), Some("team"));
+        assert_eq!(tasks[1].tag.as_deref(), Some("own"));
+    }
+}
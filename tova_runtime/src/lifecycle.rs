@@ -0,0 +1,113 @@
+use crate::errors;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Generations make a module reload (Bun dev-mode hot reload) or a
+// `reset_all_state` observable. Every id handed to JS carries the generation
+// it was issued in, in bits 40..52, and every id coming back is checked
+// against the current one, so an id kept across a reload fails with
+// ERR_STALE_HANDLE instead of aliasing whatever now has the same raw id.
+//
+// A reload may or may not re-initialise this library's statics, so the
+// counter is also mirrored into the process environment, which survives
+// either way; generation numbers never repeat within a process (modulo the
+// 12-bit tag).
+
+const GEN_SHIFT: u32 = 40;
+/// 12 tag bits keep tagged ids below 2^52, exact as JS numbers
+const GEN_BITS: u32 = 12;
+const GEN_MASK: u64 = (1 << GEN_BITS) - 1;
+const RAW_MASK: u64 = (1 << GEN_SHIFT) - 1;
+
+const GENERATION_ENV: &str = "TOVA_RUNTIME_GENERATION";
+
+/// Raw id for JS ids no generation issued (tag 0, e.g. hand-written numbers).
+/// Registries never allocate it, so lookups report "no such ...".
+pub const UNISSUED: u64 = u64::MAX;
+
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+pub fn generation() -> u64 {
+    GENERATION.load(Ordering::Acquire)
+}
+
+/// Start a new generation; called on module init and by `reset_all_state`.
+pub fn begin_generation() -> u64 {
+    let inherited = std::env::var(GENERATION_ENV).ok().and_then(|g| g.parse::<u64>().ok()).unwrap_or(0);
+    let next = GENERATION
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |g| Some(g.max(inherited) + 1))
+        .map(|previous| previous.max(inherited) + 1)
+        .unwrap_or(1);
+    std::env::set_var(GENERATION_ENV, next.to_string());
+    next
+}
+
+/// Tag bits for a generation; never 0, so every issued id has a nonzero tag.
+fn tag_bits(generation: u64) -> u64 {
+    (generation - 1) % GEN_MASK + 1
+}
+
+/// The id JS sees for a registry id issued now.
+pub fn tag(raw: u64) -> i64 {
+    ((tag_bits(generation().max(1)) << GEN_SHIFT) | (raw & RAW_MASK)) as i64
+}
+
+/// The registry id behind a JS id, or ERR_STALE_HANDLE if an earlier
+/// generation issued it. `kind` names the handle in the error.
+pub fn untag(id: i64, kind: &str) -> Result<u64, String> {
+    let bits = id as u64;
+    let tag = bits >> GEN_SHIFT;
+    if tag == 0 {
+        return Ok(UNISSUED);
+    }
+    if tag != tag_bits(generation().max(1)) {
+        return Err(errors::coded(
+            errors::ERR_STALE_HANDLE,
+            format!("{} {} was issued before the runtime was reloaded or reset", kind, id),
+        ));
+    }
+    Ok(bits & RAW_MASK)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channels;
+
+    #[test]
+    fn test_ids_from_an_earlier_generation_are_rejected() {
+        let _serial = channels::test_serial();
+        // Two init cycles: ids from the first must not resolve in the second
+        let first = begin_generation();
+        let old = tag(7);
+        assert_eq!(untag(old, "channel"), Ok(7));
+
+        let second = begin_generation();
+        assert!(second > first);
+        assert_eq!(generation(), second);
+        let err = untag(old, "channel").unwrap_err();
+        assert!(err.starts_with(errors::ERR_STALE_HANDLE), "{}", err);
+        assert!(err.contains("channel"), "{}", err);
+
+        let new = tag(7);
+        assert_ne!(new, old);
+        assert_eq!(untag(new, "channel"), Ok(7));
+        assert!(new < 1 << 53);
+        assert_eq!(untag(7, "channel"), Ok(UNISSUED));
+    }
+
+    #[test]
+    fn test_generation_survives_a_reset_of_the_statics() {
+        let _serial = channels::test_serial();
+        let current = begin_generation();
+        // A reload that re-initialises statics starts the counter from 0 again
+        GENERATION.store(0, Ordering::Release);
+        assert_eq!(begin_generation(), current + 1);
+    }
+
+    #[test]
+    fn test_tag_wraps_without_reaching_zero() {
+        assert_eq!(tag_bits(1), 1);
+        assert_eq!(tag_bits(GEN_MASK), GEN_MASK);
+        assert_eq!(tag_bits(GEN_MASK + 1), 1);
+    }
+}
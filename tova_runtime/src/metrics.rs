@@ -0,0 +1,236 @@
+use crate::errors::lock;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Execution metrics per tag: counts, fuel, wall time and a latency histogram,
+// for attributing usage to whoever a module runs on behalf of. Tags come from
+// callers, so the registry holds at most MAX_TAGS of them and folds any
+// further ones into OTHER_TAG; untagged executions count under UNTAGGED.
+// Neither of those two labels counts against the cap.
+
+pub const MAX_TAGS: usize = 64;
+pub const OTHER_TAG: &str = "other";
+pub const UNTAGGED: &str = "default";
+
+/// Latency histogram with fixed power-of-two buckets: bucket i counts
+/// durations in [2^(i-1), 2^i) microseconds (the last one everything longer), so it
+/// costs the same for ten samples or ten million. Quantiles are reported as
+/// the upper bound of the bucket they fall in.
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    buckets: [u64; Histogram::BUCKETS],
+    count: u64,
+    max: Duration,
+}
+
+impl Histogram {
+    /// 2^39 µs is over six days
+    pub const BUCKETS: usize = 40;
+
+    pub fn new() -> Self {
+        Histogram { buckets: [0; Self::BUCKETS], count: 0, max: Duration::ZERO }
+    }
+
+    pub fn record(&mut self, elapsed: Duration) {
+        let micros = elapsed.as_micros().min(u64::MAX as u128) as u64;
+        let bucket = (u64::BITS - micros.leading_zeros()) as usize;
+        self.buckets[bucket.min(Self::BUCKETS - 1)] += 1;
+        self.count += 1;
+        self.max = self.max.max(elapsed);
+    }
+
+    #[cfg(test)]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// Upper bound of the bucket holding the `q` quantile (0..=1), capped at
+    /// the largest duration recorded; zero when empty.
+    pub fn quantile(&self, q: f64) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, &n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return Duration::from_micros(1u64 << i).min(self.max);
+            }
+        }
+        self.max
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TagMetrics {
+    pub executions: u64,
+    pub errors: u64,
+    pub fuel_used: u64,
+    pub wall_time: Duration,
+    pub latency: Histogram,
+}
+
+static REGISTRY: Lazy<Mutex<HashMap<String, TagMetrics>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Record one finished execution under `tag`.
+pub fn record(tag: Option<&str>, ok: bool, fuel_used: u64, elapsed: Duration) {
+    record_in(&mut lock(&REGISTRY), tag, ok, fuel_used, elapsed);
+}
+
+fn record_in(registry: &mut HashMap<String, TagMetrics>, tag: Option<&str>, ok: bool, fuel_used: u64, elapsed: Duration) {
+    let label = match tag {
+        None => UNTAGGED,
+        Some(tag) if registry.contains_key(tag) => tag,
+        Some(tag) => {
+            let reserved = [UNTAGGED, OTHER_TAG].iter().filter(|label| registry.contains_key(**label)).count();
+            if registry.len() - reserved < MAX_TAGS { tag } else { OTHER_TAG }
+        }
+    };
+    let metrics = registry.entry(label.to_string()).or_default();
+    metrics.executions += 1;
+    if !ok {
+        metrics.errors += 1;
+    }
+    metrics.fuel_used = metrics.fuel_used.saturating_add(fuel_used);
+    metrics.wall_time += elapsed;
+    metrics.latency.record(elapsed);
+}
+
+/// Every tag's metrics so far, sorted by tag.
+pub fn snapshot() -> Vec<(String, TagMetrics)> {
+    let mut tags: Vec<_> = lock(&REGISTRY).iter().map(|(tag, m)| (tag.clone(), m.clone())).collect();
+    tags.sort_by(|a, b| a.0.cmp(&b.0));
+    tags
+}
+
+/// Latency of one batch's tasks, in the same fixed buckets as the tag
+/// metrics so a ten-thousand-task batch stores no more than a ten-task one,
+/// plus the index of the slowest task.
+#[derive(Clone, Debug, Default)]
+pub struct BatchLatency {
+    histogram: Histogram,
+    max_index: Option<usize>,
+}
+
+impl BatchLatency {
+    pub fn record(&mut self, index: usize, elapsed: Duration) {
+        if self.max_index.is_none() || elapsed > self.histogram.max() {
+            self.max_index = Some(index);
+        }
+        self.histogram.record(elapsed);
+    }
+
+    pub fn histogram(&self) -> &Histogram {
+        &self.histogram
+    }
+
+    /// The first task to take the longest; None for an empty batch
+    pub fn max_index(&self) -> Option<usize> {
+        self.max_index
+    }
+}
+
+/// A batch task that ran longer than its batch's slow-task threshold.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SlowTask {
+    pub index: usize,
+    pub func: String,
+    pub fuel_used: u64,
+    pub elapsed: Duration,
+}
+
+static SLOW_TASKS: AtomicU64 = AtomicU64::new(0);
+
+/// Log a slow task to stderr, like the runtime's other warnings, and count it.
+pub fn log_slow_task(task: &SlowTask) {
+    SLOW_TASKS.fetch_add(1, Ordering::Relaxed);
+    eprintln!(
+        "tova_runtime: slow task {} ({}): {:.1}ms, {} fuel",
+        task.index,
+        task.func,
+        task.elapsed.as_secs_f64() * 1000.0,
+        task.fuel_used
+    );
+}
+
+/// Slow tasks logged since startup.
+pub fn slow_tasks_logged() -> u64 {
+    SLOW_TASKS.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_quantiles_use_bucket_bounds() {
+        let mut h = Histogram::new();
+        assert_eq!(h.quantile(0.5), Duration::ZERO);
+        for _ in 0..98 {
+            h.record(Duration::from_micros(100));
+        }
+        h.record(Duration::from_millis(50));
+        h.record(Duration::from_secs(2));
+        assert_eq!(h.count(), 100);
+        // 100µs lands in [64, 128)
+        assert_eq!(h.quantile(0.5), Duration::from_micros(128));
+        assert_eq!(h.quantile(0.98), Duration::from_micros(128));
+        assert_eq!(h.quantile(0.99), Duration::from_micros(1 << 16));
+        assert_eq!(h.quantile(1.0), Duration::from_secs(2));
+        assert_eq!(h.max(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_tags_past_the_cap_fold_into_other() {
+        // A local registry, so the cap is not spent for the rest of the suite
+        let mut registry = HashMap::new();
+        record_in(&mut registry, None, false, 7, Duration::ZERO);
+        for i in 0..MAX_TAGS + 10 {
+            record_in(&mut registry, Some(&format!("tag-{}", i)), true, 1, Duration::from_micros(5));
+        }
+        assert_eq!(registry.len(), MAX_TAGS + 2);
+        assert_eq!(registry[OTHER_TAG].executions, 10);
+        assert!(registry.contains_key(&format!("tag-{}", MAX_TAGS - 1)));
+        assert!(!registry.contains_key(&format!("tag-{}", MAX_TAGS)));
+        // Known tags keep counting under their own name
+        record_in(&mut registry, Some("tag-0"), true, 1, Duration::ZERO);
+        assert_eq!(registry["tag-0"].executions, 2);
+        assert_eq!(registry[UNTAGGED].errors, 1);
+    }
+
+    #[test]
+    fn test_batch_latency_tracks_the_slowest_index() {
+        let mut latency = BatchLatency::default();
+        assert_eq!(latency.max_index(), None);
+        for (i, micros) in [40, 300, 90, 300, 10].into_iter().enumerate() {
+            latency.record(i, Duration::from_micros(micros));
+        }
+        // Ties keep the first task to reach the maximum
+        assert_eq!(latency.max_index(), Some(1));
+        assert_eq!(latency.histogram().count(), 5);
+        assert_eq!(latency.histogram().quantile(1.0), Duration::from_micros(300));
+    }
+
+    #[test]
+    fn test_slow_tasks_are_counted_once_each() {
+        let _serial = crate::channels::test_serial();
+        let before = slow_tasks_logged();
+        log_slow_task(&SlowTask { index: 3, func: "spin".into(), fuel_used: 10, elapsed: Duration::from_millis(30) });
+        assert_eq!(slow_tasks_logged() - before, 1);
+    }
+}
@@ -0,0 +1,212 @@
+use crate::errors::lock;
+use crate::{channels, executor, scheduler};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::task::JoinHandle;
+
+// Pipeline topology: each stage runs N blocking workers that loop
+// receive(input) -> exec -> send(output). When the last worker of a stage
+// exits it closes the stage's output channel, so closing the source channel
+// cascades through every stage once the buffered values have drained.
+
+pub struct StageSpec {
+    pub wasm: executor::WasmInput,
+    pub func: String,
+    pub workers: usize,
+    pub input: u64,
+    pub output: u64,
+    pub fuel: u64,
+}
+
+pub struct StageStats {
+    pub func: String,
+    pub workers: usize,
+    pub live_workers: usize,
+    pub processed: u64,
+    pub errors: u64,
+    pub throughput: f64,
+    pub queue_depth: usize,
+    pub last_error: Option<String>,
+}
+
+struct StageState {
+    func: String,
+    input: u64,
+    output: u64,
+    workers: usize,
+    live_workers: AtomicUsize,
+    processed: AtomicU64,
+    errors: AtomicU64,
+    last_error: Mutex<Option<String>>,
+}
+
+impl StageState {
+    fn record_error(&self, err: String) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        *lock(&self.last_error) = Some(err);
+    }
+
+    fn stats(&self, elapsed_secs: f64) -> StageStats {
+        let processed = self.processed.load(Ordering::Relaxed);
+        StageStats {
+            func: self.func.clone(),
+            workers: self.workers,
+            live_workers: self.live_workers.load(Ordering::Acquire),
+            processed,
+            errors: self.errors.load(Ordering::Relaxed),
+            throughput: if elapsed_secs > 0.0 { processed as f64 / elapsed_secs } else { 0.0 },
+            queue_depth: channels::len(self.input).unwrap_or(0),
+            last_error: lock(&self.last_error).clone(),
+        }
+    }
+}
+
+struct Pipeline {
+    stages: Vec<Arc<StageState>>,
+    handles: Vec<JoinHandle<()>>,
+    stop: Arc<AtomicBool>,
+    started: Instant,
+}
+
+impl Pipeline {
+    fn stats(&self) -> Vec<StageStats> {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        self.stages.iter().map(|s| s.stats(elapsed)).collect()
+    }
+}
+
+static PIPELINES: Lazy<Mutex<HashMap<u64, Pipeline>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Instantiate every worker up front (so a bad module fails the whole create
+/// instead of leaving half a pipeline running), then start the worker loops.
+/// Blocking: compiles modules on the calling thread.
+pub fn create(specs: Vec<StageSpec>) -> Result<u64, String> {
+    if specs.is_empty() {
+        return Err("pipeline needs at least one stage".to_string());
+    }
+
+    let mut prepared = Vec::with_capacity(specs.len());
+    for (i, spec) in specs.into_iter().enumerate() {
+        let count = spec.workers.max(1);
+        let mut workers = Vec::with_capacity(count);
+        for _ in 0..count {
+            let worker = executor::StageWorker::new(&spec.wasm, &spec.func, spec.fuel)
+                .map_err(|e| format!("stage {}: {}", i, e))?;
+            workers.push(worker);
+        }
+        let state = Arc::new(StageState {
+            func: spec.func,
+            input: spec.input,
+            output: spec.output,
+            workers: count,
+            live_workers: AtomicUsize::new(count),
+            processed: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            last_error: Mutex::new(None),
+        });
+        prepared.push((state, workers));
+    }
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut stages = Vec::with_capacity(prepared.len());
+    let mut handles = Vec::new();
+    for (state, workers) in prepared {
+        for worker in workers {
+            let state = Arc::clone(&state);
+            let stop = Arc::clone(&stop);
+            handles.push(scheduler::TOKIO_RT.spawn_blocking(move || {
+                run_worker(worker, state, stop)
+            }));
+        }
+        stages.push(state);
+    }
+
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    lock(&PIPELINES).insert(id, Pipeline {
+        stages,
+        handles,
+        stop,
+        started: Instant::now(),
+    });
+    Ok(id)
+}
+
+fn run_worker(mut worker: executor::StageWorker, stage: Arc<StageState>, stop: Arc<AtomicBool>) {
+    while !stop.load(Ordering::Acquire) {
+        // None once the input is closed and drained (or was never created)
+        let value = match channels::receive_blocking(stage.input) {
+            Some(v) => v,
+            None => break,
+        };
+        if stop.load(Ordering::Acquire) {
+            break;
+        }
+        // StageWorker::call already converts guest-side panics into errors
+        match worker.call(value) {
+            Ok(out) => match channels::send(stage.output, out) {
+                Ok(true) => {
+                    stage.processed.fetch_add(1, Ordering::Relaxed);
+                }
+                _ => {
+                    stage.record_error("downstream channel closed".to_string());
+                    break;
+                }
+            },
+            Err(e) => stage.record_error(e),
+        }
+    }
+    // Last worker out closes the downstream channel so the next stage drains and exits
+    if stage.live_workers.fetch_sub(1, Ordering::AcqRel) == 1 {
+        channels::close(stage.output);
+    }
+}
+
+pub fn stats(id: u64) -> Result<Vec<StageStats>, String> {
+    let pipelines = lock(&PIPELINES);
+    pipelines
+        .get(&id)
+        .map(|p| p.stats())
+        .ok_or_else(|| format!("no such pipeline: {}", id))
+}
+
+/// Stop every pipeline without waiting for its workers; they exit once their
+/// current value is done (or interrupted). Used by `reset_all_state`.
+pub fn reset() {
+    let pipelines: Vec<Pipeline> = lock(&PIPELINES).drain().map(|(_, p)| p).collect();
+    for pipeline in pipelines {
+        pipeline.stop.store(true, Ordering::Release);
+        for stage in &pipeline.stages {
+            channels::close(stage.input);
+        }
+    }
+}
+
+/// Stop a pipeline and wait for its workers, returning the final stats.
+/// With `drain`, only the source channel is closed and every buffered value
+/// flows through to the sink. Without it, workers stop after their current
+/// value and all stage inputs are closed to wake idle workers.
+pub async fn shutdown(id: u64, drain: bool) -> Result<Vec<StageStats>, String> {
+    let mut pipeline = lock(&PIPELINES)
+        .remove(&id)
+        .ok_or_else(|| format!("no such pipeline: {}", id))?;
+
+    if drain {
+        channels::close(pipeline.stages[0].input);
+    } else {
+        pipeline.stop.store(true, Ordering::Release);
+        for stage in &pipeline.stages {
+            channels::close(stage.input);
+        }
+    }
+
+    for handle in pipeline.handles.drain(..) {
+        handle.await.map_err(|e| format!("join: {}", e))?;
+    }
+    Ok(pipeline.stats())
+}
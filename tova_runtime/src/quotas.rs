@@ -0,0 +1,175 @@
+use crate::errors::{self, lock};
+use crate::executor::Metered;
+use crate::{metrics, scheduler};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI64, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, Once};
+use std::time::{Duration, Instant};
+
+// Per-tag quotas, checked when a tagged task is about to run. A concurrency
+// cap counts tasks running under the tag; a fuel cap is a token bucket holding
+// up to one minute's allowance, refilled in small steps by a timer on
+// TOKIO_RT. A task may start while the bucket is positive and is charged what
+// it burnt once it finishes, so running tasks can overdraw it by up to their
+// own fuel limit each, like a batch's shared fuel budget.
+
+/// How often the fuel buckets are topped up (by 1/60th of the per-minute cap)
+const REFILL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Quota {
+    pub max_concurrent: Option<u32>,
+    pub max_fuel_per_minute: Option<u64>,
+}
+
+struct TagQuota {
+    quota: Mutex<Quota>,
+    running: AtomicU32,
+    fuel_tokens: AtomicI64,
+}
+
+static QUOTAS: Lazy<Mutex<HashMap<String, Arc<TagQuota>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+static REFILL: Once = Once::new();
+
+/// Set (or with an empty quota, remove) the quota for `tag`. The fuel bucket
+/// starts full; tasks already running under the tag keep counting against a
+/// replaced quota, but not against one set after a removal.
+pub fn set(tag: &str, quota: Quota) {
+    let mut quotas = lock(&QUOTAS);
+    if quota == Quota::default() {
+        quotas.remove(tag);
+        return;
+    }
+    let fuel = fuel_cap(&quota);
+    match quotas.get(tag) {
+        Some(existing) => {
+            *lock(&existing.quota) = quota;
+            existing.fuel_tokens.store(fuel, Ordering::Release);
+        }
+        None => {
+            let entry = TagQuota { quota: Mutex::new(quota), running: AtomicU32::new(0), fuel_tokens: AtomicI64::new(fuel) };
+            quotas.insert(tag.to_string(), Arc::new(entry));
+        }
+    }
+    drop(quotas);
+    if quota.max_fuel_per_minute.is_some() {
+        REFILL.call_once(|| {
+            scheduler::TOKIO_RT.spawn(async {
+                let mut ticks = tokio::time::interval(REFILL_INTERVAL);
+                loop {
+                    ticks.tick().await;
+                    refill();
+                }
+            });
+        });
+    }
+}
+
+fn fuel_cap(quota: &Quota) -> i64 {
+    quota.max_fuel_per_minute.unwrap_or(0).min(i64::MAX as u64) as i64
+}
+
+fn refill() {
+    let quotas: Vec<Arc<TagQuota>> = lock(&QUOTAS).values().cloned().collect();
+    for entry in quotas {
+        let quota = *lock(&entry.quota);
+        if quota.max_fuel_per_minute.is_some() {
+            let cap = fuel_cap(&quota);
+            let step = (cap / 60).max(1);
+            let _ = entry.fuel_tokens.fetch_update(Ordering::AcqRel, Ordering::Acquire, |tokens| {
+                Some(tokens.saturating_add(step).min(cap))
+            });
+        }
+    }
+}
+
+/// Permission for one task to run under a quota; dropping it ends the task.
+pub struct Admission(Arc<TagQuota>);
+
+impl Admission {
+    fn charge(&self, fuel_used: u64) {
+        if lock(&self.0.quota).max_fuel_per_minute.is_some() {
+            self.0.fuel_tokens.fetch_sub(fuel_used.min(i64::MAX as u64) as i64, Ordering::AcqRel);
+        }
+    }
+}
+
+impl Drop for Admission {
+    fn drop(&mut self) {
+        self.0.running.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Admit a task under `tag`'s quota, or fail with ERR_QUOTA naming the limit
+/// it would exceed. Tags without a quota are always admitted.
+pub fn admit(tag: &str) -> Result<Option<Admission>, String> {
+    let entry = match lock(&QUOTAS).get(tag) {
+        Some(entry) => Arc::clone(entry),
+        None => return Ok(None),
+    };
+    let quota = *lock(&entry.quota);
+    if quota.max_fuel_per_minute.is_some() && entry.fuel_tokens.load(Ordering::Acquire) <= 0 {
+        return Err(errors::coded(errors::ERR_QUOTA, format!("tag '{}' spent its fuel for this minute", tag)));
+    }
+    let max = quota.max_concurrent.unwrap_or(u32::MAX);
+    if entry.running.fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| (n < max).then_some(n + 1)).is_err() {
+        return Err(errors::coded(
+            errors::ERR_QUOTA,
+            format!("tag '{}' already runs {} tasks (maxConcurrent)", tag, max),
+        ));
+    }
+    Ok(Some(Admission(entry)))
+}
+
+/// Run a task under `tag`: admit it against the tag's quota, run it, charge
+/// the fuel it burnt and record it in the tag's metrics. A rejected task
+/// does not run and reports ERR_QUOTA with no fuel used.
+pub fn run_tagged(tag: Option<&str>, run: impl FnOnce() -> Metered) -> Metered {
+    let admission = match tag.map(admit).transpose() {
+        Ok(admission) => admission.flatten(),
+        Err(e) => return Metered { result: Err(e), fuel_used: 0 },
+    };
+    let started = Instant::now();
+    let metered = run();
+    if let Some(admission) = &admission {
+        admission.charge(metered.fuel_used);
+    }
+    metrics::record(tag, metered.result.is_ok(), metered.fuel_used, started.elapsed());
+    metered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_concurrency_quota_rejects_then_recovers() {
+        set("quota-test-concurrent", Quota { max_concurrent: Some(2), max_fuel_per_minute: None });
+        let first = admit("quota-test-concurrent").unwrap();
+        let second = admit("quota-test-concurrent").unwrap();
+        let err = admit("quota-test-concurrent").err().unwrap();
+        assert!(err.starts_with(errors::ERR_QUOTA), "{}", err);
+        drop(first);
+        assert!(admit("quota-test-concurrent").unwrap().is_some());
+        drop(second);
+        assert!(admit("quota-test-unlimited").unwrap().is_none());
+        set("quota-test-concurrent", Quota::default());
+        assert!(admit("quota-test-concurrent").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_fuel_quota_is_charged_after_the_run() {
+        set("quota-test-fuel", Quota { max_concurrent: None, max_fuel_per_minute: Some(1000) });
+        let run = || Metered { result: Ok(1), fuel_used: 600 };
+        assert_eq!(run_tagged(Some("quota-test-fuel"), run).result, Ok(1));
+        // 400 left: the next task may start, and overdraws the bucket
+        assert_eq!(run_tagged(Some("quota-test-fuel"), run).result, Ok(1));
+        let rejected = run_tagged(Some("quota-test-fuel"), run);
+        assert!(rejected.result.unwrap_err().starts_with(errors::ERR_QUOTA));
+        assert_eq!(rejected.fuel_used, 0);
+        set("quota-test-fuel", Quota::default());
+    }
+}
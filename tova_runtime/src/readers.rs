@@ -0,0 +1,159 @@
+use crate::channels::{self, Received};
+use crate::errors::lock;
+use crossbeam_channel::{bounded, unbounded, Sender};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+
+// Channel readers: pull-based, batched delivery of a channel's values for JS
+// async iteration. Each reader has a forwarding thread that sleeps until a
+// `next` request arrives, then parks on the channel until at least one value
+// is there and answers with everything buffered, up to the reader's batch
+// size, or with None once the channel has ended. A reader takes one request
+// at a time, so batches come back in channel order. Like a subscription, it
+// competes with any other receiver on the channel.
+
+/// A batch of values, or None once the channel ended
+pub type Batch = Option<Vec<i64>>;
+
+struct Reader {
+    requests: Sender<oneshot::Sender<Batch>>,
+    /// Dropping it wakes the forwarding thread out of a blocked receive
+    _stop: Sender<()>,
+    /// A `next` is outstanding
+    pending: Arc<AtomicBool>,
+}
+
+static READERS: Lazy<Mutex<HashMap<u64, Reader>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+static NEXT_READER: AtomicU64 = AtomicU64::new(0);
+
+/// Start a reader on `channel` that answers each `next` with up to `batch`
+/// values. Returns the reader id.
+pub fn create(channel: u64, batch: usize) -> Result<u64, String> {
+    if channels::len(channel).is_none() {
+        return Err(format!("no such channel: {}", channel));
+    }
+    let batch = batch.max(1);
+    let (requests, incoming) = unbounded::<oneshot::Sender<Batch>>();
+    let (stop, stopped) = bounded::<()>(0);
+    let pending = Arc::new(AtomicBool::new(false));
+    let reader = NEXT_READER.fetch_add(1, Ordering::Relaxed);
+    lock(&READERS).insert(reader, Reader { requests, _stop: stop, pending: Arc::clone(&pending) });
+    let spawned = std::thread::Builder::new()
+        .name(format!("tova-reader-{}", channel))
+        .spawn(move || {
+            for reply in incoming.iter() {
+                let values = match channels::receive_or_stop(channel, None, &stopped) {
+                    Received::Value(first) => {
+                        let mut values = Vec::with_capacity(batch);
+                        values.push(first);
+                        while values.len() < batch {
+                            match channels::receive(channel) {
+                                Some(value) => values.push(value),
+                                None => break,
+                            }
+                        }
+                        Some(values)
+                    }
+                    Received::Ended => None,
+                    // Closed by `close` or `reset`: drop the reply unanswered
+                    Received::TimedOut | Received::Stopped => break,
+                };
+                let ended = values.is_none();
+                if ended {
+                    // Gone before the end is reported, so it is reported once
+                    lock(&READERS).remove(&reader);
+                }
+                pending.store(false, Ordering::Release);
+                let _ = reply.send(values);
+                if ended {
+                    return;
+                }
+            }
+            lock(&READERS).remove(&reader);
+        });
+    if let Err(e) = spawned {
+        lock(&READERS).remove(&reader);
+        return Err(format!("failed to spawn reader: {}", e));
+    }
+    Ok(reader)
+}
+
+/// Ask for the next batch. Fails if the reader is gone (closed, or it
+/// already reported the end of its channel) or a previous `next` has not
+/// been answered yet.
+pub fn next(reader: u64) -> Result<oneshot::Receiver<Batch>, String> {
+    let readers = lock(&READERS);
+    let entry = readers.get(&reader).ok_or_else(|| format!("no such channel reader: {}", reader))?;
+    if entry.pending.swap(true, Ordering::AcqRel) {
+        return Err(format!("channel reader {} already has a next() pending", reader));
+    }
+    let (reply, answer) = oneshot::channel();
+    if entry.requests.send(reply).is_err() {
+        entry.pending.store(false, Ordering::Release);
+        return Err(format!("channel reader {} has ended", reader));
+    }
+    Ok(answer)
+}
+
+/// Stop a reader; a pending `next` fails. False if it already ended.
+pub fn close(reader: u64) -> bool {
+    lock(&READERS).remove(&reader).is_some()
+}
+
+/// Close every reader. Used by `reset_all_state`.
+pub fn reset() {
+    lock(&READERS).clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wait(answer: oneshot::Receiver<Batch>) -> Batch {
+        answer.blocking_recv().unwrap()
+    }
+
+    #[test]
+    fn test_batches_keep_order_and_end_once() {
+        let _serial = channels::test_serial();
+        let id = channels::create(64);
+        let producer = std::thread::spawn(move || {
+            for v in 0..10_000 {
+                assert_eq!(channels::send(id, v), Ok(true));
+            }
+            channels::close(id);
+        });
+        let reader = create(id, 256).unwrap();
+        let mut seen = Vec::new();
+        while let Some(values) = wait(next(reader).unwrap()) {
+            assert!(!values.is_empty() && values.len() <= 256);
+            seen.extend(values);
+        }
+        producer.join().unwrap();
+        assert_eq!(seen, (0..10_000).collect::<Vec<_>>());
+        // The end is reported once; the reader is gone after it
+        assert!(next(reader).unwrap_err().contains("no such channel reader"));
+    }
+
+    #[test]
+    fn test_one_pending_next_at_a_time() {
+        let _serial = channels::test_serial();
+        let id = channels::create(4);
+        let reader = create(id, 8).unwrap();
+        let first = next(reader).unwrap();
+        assert!(next(reader).unwrap_err().contains("already has a next() pending"));
+        assert_eq!(channels::send(id, 7), Ok(true));
+        assert_eq!(wait(first), Some(vec![7]));
+
+        let pending = next(reader).unwrap();
+        assert!(close(reader));
+        assert!(pending.blocking_recv().is_err());
+        assert!(!close(reader));
+        channels::destroy(id);
+    }
+}
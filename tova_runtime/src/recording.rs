@@ -0,0 +1,745 @@
+use crate::errors;
+use crate::executor::Metering;
+use std::cell::RefCell;
+use tova_native::kernels;
+
+// Execution recordings, for reproducing a misbehaving guest away from the
+// channels and other state it ran against. While a call records, every host
+// import it makes is logged with its arguments, its result and the guest
+// memory it filled. A replay runs the same module and answers each import
+// from the log instead of performing it, failing with ERR_REPLAY_DIVERGED at
+// the first call that differs from the recorded one.
+//
+// Logs stop at MAX_RECORDED_CALLS, and memory an import filled is kept as its
+// length only when it is over REDACT_OVER bytes or would take the recording
+// past MAX_PAYLOAD_BYTES; a replay that needs a call the log lacks fails
+// rather than guessing.
+
+/// Calls a recording logs; later ones are dropped and the recording marked
+/// truncated
+pub const MAX_RECORDED_CALLS: usize = 100_000;
+
+/// Largest memory fill a recording keeps the bytes of
+pub const REDACT_OVER: usize = 4096;
+
+/// Memory fill bytes a recording keeps in total
+pub const MAX_PAYLOAD_BYTES: usize = 1 << 20;
+
+/// Version of the JSON form `to_json` writes
+const FORMAT_VERSION: u64 = 1;
+
+/// Guest memory an import filled.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fill {
+    pub at: u64,
+    pub len: u64,
+    /// None once redacted
+    pub bytes: Option<Vec<u8>>,
+}
+
+/// One host import call.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Call {
+    /// As "module.name"
+    pub import: String,
+    pub args: Vec<i64>,
+    /// The import's result, f64 results as their bits, or the trap it raised
+    pub result: Result<i64, String>,
+    pub fill: Option<Fill>,
+}
+
+/// Everything needed to run a call again without its host state.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Recording {
+    /// `module_hash` of the module's bytes
+    pub module: u64,
+    pub func: String,
+    pub args: Vec<i64>,
+    pub metering: Metering,
+    pub calls: Vec<Call>,
+    /// Calls past MAX_RECORDED_CALLS were not logged
+    pub truncated: bool,
+    pub fuel_used: u64,
+    pub outcome: Result<i64, String>,
+}
+
+/// The hash a recording names its module by. Unlike the module cache's key
+/// it is the same in every build, so a recording replays anywhere.
+pub fn module_hash(wasm: &[u8]) -> u64 {
+    kernels::hash_xxh64(wasm, 0)
+}
+
+/// Raised from an import to stop a replay that cannot go on; the message
+/// carries its error code.
+#[derive(Debug)]
+pub struct ReplayFailed(pub String);
+
+impl std::fmt::Display for ReplayFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ReplayFailed {}
+
+fn diverged(msg: impl std::fmt::Display) -> String {
+    errors::coded(errors::ERR_REPLAY_DIVERGED, msg)
+}
+
+fn describe(import: &str, args: &[i64]) -> String {
+    let args: Vec<String> = args.iter().map(i64::to_string).collect();
+    format!("{}({})", import, args.join(", "))
+}
+
+fn describe_outcome(outcome: &Result<i64, String>) -> String {
+    match outcome {
+        Ok(value) => format!("returned {}", value),
+        Err(e) => format!("failed with '{}'", e),
+    }
+}
+
+enum Session {
+    Recording { calls: Vec<Call>, payload: usize, truncated: bool },
+    Replaying { calls: Vec<Call>, next: usize, truncated: bool, failure: Option<String> },
+}
+
+thread_local! {
+    static SESSION: RefCell<Option<Session>> = const { RefCell::new(None) };
+}
+
+/// What host imports on this thread do with their calls.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Live,
+    Record,
+    Replay,
+}
+
+pub fn mode() -> Mode {
+    SESSION.with(|session| match &*session.borrow() {
+        None => Mode::Live,
+        Some(Session::Recording { .. }) => Mode::Record,
+        Some(Session::Replaying { .. }) => Mode::Replay,
+    })
+}
+
+/// Puts back the session a scope replaced.
+struct Restore(Option<Session>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        SESSION.with(|session| *session.borrow_mut() = previous);
+    }
+}
+
+fn enter(session: Session) -> Restore {
+    Restore(SESSION.with(|current| current.replace(Some(session))))
+}
+
+fn take() -> Option<Session> {
+    SESSION.with(|session| session.borrow_mut().take())
+}
+
+/// Logs the host imports calls on this thread make until finished.
+pub struct RecordScope {
+    _restore: Restore,
+}
+
+pub fn record() -> RecordScope {
+    RecordScope { _restore: enter(Session::Recording { calls: Vec::new(), payload: 0, truncated: false }) }
+}
+
+impl RecordScope {
+    /// The calls logged, and whether any were dropped.
+    pub fn finish(self) -> (Vec<Call>, bool) {
+        match take() {
+            Some(Session::Recording { calls, truncated, .. }) => (calls, truncated),
+            _ => (Vec::new(), false),
+        }
+    }
+}
+
+/// Answers the host imports calls on this thread make from `recording`'s
+/// log until finished.
+pub struct ReplayScope {
+    _restore: Restore,
+}
+
+pub fn replay(recording: &Recording) -> ReplayScope {
+    ReplayScope {
+        _restore: enter(Session::Replaying {
+            calls: recording.calls.clone(),
+            next: 0,
+            truncated: recording.truncated,
+            failure: None,
+        }),
+    }
+}
+
+impl ReplayScope {
+    /// The first call the log could not answer, else ERR_REPLAY_DIVERGED if
+    /// the guest stopped short of the calls it holds.
+    pub fn finish(self) -> Result<(), String> {
+        match take() {
+            Some(Session::Replaying { failure: Some(failure), .. }) => Err(failure),
+            Some(Session::Replaying { calls, next, .. }) if next < calls.len() => Err(diverged(format!(
+                "the guest stopped after {} calls, the recording has {}",
+                next,
+                calls.len()
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Log a call made live, if this thread is recording. `fill` is the guest
+/// memory the call filled, at the offset given.
+pub fn log(import: &str, args: &[i64], result: Result<i64, String>, fill: Option<(u64, &[u8])>) {
+    SESSION.with(|session| {
+        let Some(Session::Recording { calls, payload, truncated }) = &mut *session.borrow_mut() else {
+            return;
+        };
+        if calls.len() >= MAX_RECORDED_CALLS {
+            *truncated = true;
+            return;
+        }
+        let fill = fill.map(|(at, bytes)| {
+            let keep = bytes.len() <= REDACT_OVER && *payload + bytes.len() <= MAX_PAYLOAD_BYTES;
+            if keep {
+                *payload += bytes.len();
+            }
+            Fill { at, len: bytes.len() as u64, bytes: keep.then(|| bytes.to_vec()) }
+        });
+        calls.push(Call { import: import.to_string(), args: args.to_vec(), result, fill });
+    })
+}
+
+/// The recorded answer to this thread's next call, if it is `import` with
+/// `args`; the error also fails the replay's `finish`.
+pub fn answer(import: &str, args: &[i64]) -> Result<Call, String> {
+    SESSION.with(|session| {
+        let Some(Session::Replaying { calls, next, truncated, failure }) = &mut *session.borrow_mut() else {
+            return Err(errors::coded(errors::ERR_INTERNAL, "no replay is running"));
+        };
+        if let Some(failure) = failure {
+            return Err(failure.clone());
+        }
+        let index = *next;
+        let answer = match calls.get(index) {
+            Some(call) if call.import == import && call.args == args => match &call.fill {
+                Some(Fill { len, bytes: None, .. }) => Err(errors::coded(
+                    errors::ERR_INVALID_INPUT,
+                    format!("call {}: the {} bytes {} filled were redacted from the recording", index, len, import),
+                )),
+                _ => Ok(call.clone()),
+            },
+            Some(call) => Err(diverged(format!(
+                "call {}: the guest called {}, the recording has {}",
+                index,
+                describe(import, args),
+                describe(&call.import, &call.args)
+            ))),
+            None if *truncated => Err(errors::coded(
+                errors::ERR_INVALID_INPUT,
+                format!("call {}: the recording was truncated after {} calls", index, calls.len()),
+            )),
+            None => Err(diverged(format!(
+                "call {}: the guest called {}, the recording ends after {} calls",
+                index,
+                describe(import, args),
+                calls.len()
+            ))),
+        };
+        match &answer {
+            Ok(_) => *next += 1,
+            Err(e) => *failure = Some(e.clone()),
+        }
+        answer
+    })
+}
+
+/// `outcome` if it is what `recording` ended with, else ERR_REPLAY_DIVERGED.
+pub fn check_outcome(recording: &Recording, outcome: Result<i64, String>) -> Result<i64, String> {
+    if outcome != recording.outcome {
+        return Err(diverged(format!(
+            "the recorded call {}, the replay {}",
+            describe_outcome(&recording.outcome),
+            describe_outcome(&outcome)
+        )));
+    }
+    outcome
+}
+
+// --- JSON ---
+//
+// Numbers are written and read as their digits, so i64 payloads survive the
+// trip even though JS would round them if it parsed the recording itself.
+
+impl Recording {
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        out.push_str(&format!("{{\"version\":{},\"module\":\"{:016x}\",\"func\":", FORMAT_VERSION, self.module));
+        push_string(&mut out, &self.func);
+        out.push_str(&format!(
+            ",\"args\":{},\"metering\":\"{}\",\"fuelUsed\":{},\"truncated\":{},",
+            numbers(&self.args),
+            self.metering.name(),
+            self.fuel_used,
+            self.truncated
+        ));
+        push_outcome(&mut out, "result", "error", &self.outcome);
+        out.push_str(",\"calls\":[");
+        for (i, call) in self.calls.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str("{\"import\":");
+            push_string(&mut out, &call.import);
+            out.push_str(&format!(",\"args\":{},", numbers(&call.args)));
+            push_outcome(&mut out, "result", "trap", &call.result);
+            if let Some(fill) = &call.fill {
+                out.push_str(&format!(",\"fill\":{{\"at\":{},\"len\":{},", fill.at, fill.len));
+                match &fill.bytes {
+                    Some(bytes) => {
+                        let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                        out.push_str(&format!("\"bytes\":\"{}\"}}", hex));
+                    }
+                    None => out.push_str("\"redacted\":true}"),
+                }
+            }
+            out.push('}');
+        }
+        out.push_str("]}");
+        out
+    }
+
+    /// Parse what `to_json` wrote; ERR_INVALID_INPUT naming the problem otherwise.
+    pub fn from_json(text: &str) -> Result<Recording, String> {
+        let invalid = |msg: String| errors::coded(errors::ERR_INVALID_INPUT, format!("recording: {}", msg));
+        let root = Parser::parse(text).map_err(invalid)?;
+        read_recording(&root).map_err(invalid)
+    }
+}
+
+fn numbers(values: &[i64]) -> String {
+    let values: Vec<String> = values.iter().map(i64::to_string).collect();
+    format!("[{}]", values.join(","))
+}
+
+fn push_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn push_outcome(out: &mut String, ok: &str, err: &str, outcome: &Result<i64, String>) {
+    match outcome {
+        Ok(value) => out.push_str(&format!("\"{}\":{}", ok, value)),
+        Err(e) => {
+            out.push_str(&format!("\"{}\":", err));
+            push_string(out, e);
+        }
+    }
+}
+
+enum Json {
+    Null,
+    Bool(bool),
+    /// The number's text
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn field(&self, name: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(key, _)| key == name).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+}
+
+fn field<'a>(object: &'a Json, name: &str) -> Result<&'a Json, String> {
+    object.field(name).ok_or_else(|| format!("missing '{}'", name))
+}
+
+fn int<T: std::str::FromStr>(value: &Json, name: &str) -> Result<T, String> {
+    match value {
+        Json::Number(text) => text.parse().map_err(|_| format!("'{}' is out of range: {}", name, text)),
+        _ => Err(format!("'{}' must be an integer", name)),
+    }
+}
+
+fn string<'a>(value: &'a Json, name: &str) -> Result<&'a str, String> {
+    match value {
+        Json::String(s) => Ok(s),
+        _ => Err(format!("'{}' must be a string", name)),
+    }
+}
+
+fn ints(value: &Json, name: &str) -> Result<Vec<i64>, String> {
+    match value {
+        Json::Array(items) => items.iter().map(|item| int(item, name)).collect(),
+        _ => Err(format!("'{}' must be an array", name)),
+    }
+}
+
+fn outcome(object: &Json, ok: &str, err: &str) -> Result<Result<i64, String>, String> {
+    match (object.field(ok), object.field(err)) {
+        (Some(value), None) => Ok(Ok(int(value, ok)?)),
+        (None, Some(e)) => Ok(Err(string(e, err)?.to_string())),
+        _ => Err(format!("needs exactly one of '{}' and '{}'", ok, err)),
+    }
+}
+
+fn read_recording(root: &Json) -> Result<Recording, String> {
+    let version: u64 = int(field(root, "version")?, "version")?;
+    if version != FORMAT_VERSION {
+        return Err(format!("unsupported version {}", version));
+    }
+    let module = string(field(root, "module")?, "module")?;
+    let module = u64::from_str_radix(module, 16).map_err(|_| format!("'module' is not a hex hash: {}", module))?;
+    let metering = Metering::parse(string(field(root, "metering")?, "metering")?)?;
+    let truncated = match field(root, "truncated")? {
+        Json::Bool(b) => *b,
+        _ => return Err("'truncated' must be a boolean".to_string()),
+    };
+    let calls = match field(root, "calls")? {
+        Json::Array(calls) => calls
+            .iter()
+            .enumerate()
+            .map(|(i, call)| read_call(call).map_err(|e| format!("call {}: {}", i, e)))
+            .collect::<Result<Vec<Call>, String>>()?,
+        _ => return Err("'calls' must be an array".to_string()),
+    };
+    Ok(Recording {
+        module,
+        func: string(field(root, "func")?, "func")?.to_string(),
+        args: ints(field(root, "args")?, "args")?,
+        metering,
+        calls,
+        truncated,
+        fuel_used: int(field(root, "fuelUsed")?, "fuelUsed")?,
+        outcome: outcome(root, "result", "error")?,
+    })
+}
+
+fn read_call(call: &Json) -> Result<Call, String> {
+    let fill = match call.field("fill") {
+        None | Some(Json::Null) => None,
+        Some(fill) => {
+            let len: u64 = int(field(fill, "len")?, "len")?;
+            let bytes = match fill.field("bytes") {
+                Some(hex) => Some(unhex(string(hex, "bytes")?).filter(|b| b.len() as u64 == len).ok_or("'bytes' must be 'len' bytes of hex")?),
+                None => None,
+            };
+            Some(Fill { at: int(field(fill, "at")?, "at")?, len, bytes })
+        }
+    };
+    Ok(Call {
+        import: string(field(call, "import")?, "import")?.to_string(),
+        args: ints(field(call, "args")?, "args")?,
+        result: outcome(call, "result", "trap")?,
+        fill,
+    })
+}
+
+fn unhex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+/// Just enough JSON for recordings, numbers kept as text.
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn parse(text: &'a str) -> Result<Json, String> {
+        let mut parser = Parser { text, pos: 0 };
+        let value = parser.value()?;
+        parser.skip_space();
+        if parser.pos != text.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        Ok(value)
+    }
+
+    fn error(&self, msg: &str) -> String {
+        format!("invalid JSON at offset {}: {}", self.pos, msg)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_space(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        self.skip_space();
+        if self.peek() != Some(c) {
+            return Err(self.error(&format!("expected '{}'", c as char)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_space();
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'-' | b'0'..=b'9') => {
+                let start = self.pos;
+                while matches!(self.peek(), Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')) {
+                    self.pos += 1;
+                }
+                Ok(Json::Number(self.text[start..self.pos].to_string()))
+            }
+            _ => {
+                for (word, value) in [("true", Json::Bool(true)), ("false", Json::Bool(false)), ("null", Json::Null)] {
+                    if self.text[self.pos..].starts_with(word) {
+                        self.pos += word.len();
+                        return Ok(value);
+                    }
+                }
+                Err(self.error("expected a value"))
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect(b'{')?;
+        let mut fields = Vec::new();
+        self.skip_space();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.skip_space();
+            let key = self.string()?;
+            self.expect(b':')?;
+            fields.push((key, self.value()?));
+            self.skip_space();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(fields));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        self.skip_space();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_space();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if self.peek() != Some(b'"') {
+            return Err(self.error("expected a string"));
+        }
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            let rest = &self.text[self.pos..];
+            let Some(c) = rest.chars().next() else {
+                return Err(self.error("unterminated string"));
+            };
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let escape = self.peek().ok_or_else(|| self.error("unterminated escape"))?;
+                    self.pos += 1;
+                    match escape {
+                        b'"' => out.push('"'),
+                        b'\\' => out.push('\\'),
+                        b'/' => out.push('/'),
+                        b'b' => out.push('\u{8}'),
+                        b'f' => out.push('\u{c}'),
+                        b'n' => out.push('\n'),
+                        b'r' => out.push('\r'),
+                        b't' => out.push('\t'),
+                        b'u' => out.push(self.unicode_escape()?),
+                        _ => return Err(self.error("unknown escape")),
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+    }
+
+    /// The character of a \u escape, after the "\u", joining surrogate pairs.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        if !(0xD800..0xDC00).contains(&high) {
+            return char::from_u32(high).ok_or_else(|| self.error("lone surrogate"));
+        }
+        if !self.text[self.pos..].starts_with("\\u") {
+            return Err(self.error("lone surrogate"));
+        }
+        self.pos += 2;
+        let low = self.hex4()?;
+        if !(0xDC00..0xE000).contains(&low) {
+            return Err(self.error("lone surrogate"));
+        }
+        char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)).ok_or_else(|| self.error("bad surrogate pair"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self.text.get(self.pos..self.pos + 4).ok_or_else(|| self.error("short \\u escape"))?;
+        let value = u32::from_str_radix(digits, 16).map_err(|_| self.error("bad \\u escape"))?;
+        self.pos += 4;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Recording {
+        Recording {
+            module: 0x0123_4567_89ab_cdef,
+            func: "echo \"quoted\"\n\u{1}é𝄞".to_string(),
+            args: vec![i64::MIN, -1, i64::MAX],
+            metering: Metering::Epoch,
+            calls: vec![
+                Call { import: "tova.chan_receive".to_string(), args: vec![0], result: Ok(i64::MAX), fill: None },
+                Call {
+                    import: "tova.chan_receive_status".to_string(),
+                    args: vec![0, 64],
+                    result: Ok(0),
+                    fill: Some(Fill { at: 64, len: 8, bytes: Some(vec![0, 1, 0xfe, 0xff, 9, 8, 7, 6]) }),
+                },
+                Call {
+                    import: "tova.shared_data_read".to_string(),
+                    args: vec![0, 0, 0, 9000],
+                    result: Ok(0),
+                    fill: Some(Fill { at: 0, len: 9000, bytes: None }),
+                },
+                Call { import: "tova.chan_receive_status".to_string(), args: vec![0, 70000], result: Err("range is outside".to_string()), fill: None },
+            ],
+            truncated: true,
+            fuel_used: u64::MAX,
+            outcome: Err("ERR_TRAP: \\ boom".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_recordings_round_trip_through_json() {
+        let recording = sample();
+        assert_eq!(Recording::from_json(&recording.to_json()), Ok(recording));
+        let ok = Recording { outcome: Ok(-5), calls: Vec::new(), ..sample() };
+        assert_eq!(Recording::from_json(&ok.to_json()), Ok(ok));
+        // Whitespace and escapes JS's JSON.stringify may produce
+        let spaced = sample().to_json().replace(",\"", ", \"").replace("é", "\\u00e9").replace("𝄞", "\\ud834\\udd1e");
+        assert_eq!(Recording::from_json(&spaced), Ok(sample()));
+    }
+
+    #[test]
+    fn test_malformed_recordings_name_the_problem() {
+        let json = sample().to_json();
+        for (text, expected) in [
+            ("", "expected a value"),
+            ("{\"version\":2}", "unsupported version 2"),
+            (&json[..json.len() - 1], "expected ',' or '}'"),
+            (&json.replace("\"metering\":\"epoch\"", "\"metering\":\"fast\""), "fast"),
+            (&json.replace("\"len\":8", "\"len\":7"), "call 1: 'bytes' must be 'len' bytes of hex"),
+            (&json.replace("\"result\":0,", ""), "call 1: needs exactly one of 'result' and 'trap'"),
+            (&json.replace("-9223372036854775808", "-9223372036854775809"), "'args' is out of range"),
+        ] {
+            let err = Recording::from_json(text).unwrap_err();
+            assert!(err.starts_with(errors::ERR_INVALID_INPUT), "{}", err);
+            assert!(err.contains(expected), "{} lacks {}", err, expected);
+        }
+    }
+
+    #[test]
+    fn test_logs_redact_large_fills_and_stop_at_the_cap() {
+        let session = record();
+        assert_eq!(mode(), Mode::Record);
+        let small = [7u8; 8];
+        let large = vec![1u8; REDACT_OVER + 1];
+        log("tova.a", &[1], Ok(0), Some((16, &small)));
+        log("tova.b", &[2], Ok(0), Some((0, &large)));
+        for _ in 2..MAX_RECORDED_CALLS + 5 {
+            log("tova.task_index", &[], Ok(0), None);
+        }
+        let (calls, truncated) = session.finish();
+        assert_eq!(mode(), Mode::Live);
+        assert!(truncated);
+        assert_eq!(calls.len(), MAX_RECORDED_CALLS);
+        assert_eq!(calls[0].fill, Some(Fill { at: 16, len: 8, bytes: Some(small.to_vec()) }));
+        assert_eq!(calls[1].fill, Some(Fill { at: 0, len: large.len() as u64, bytes: None }));
+        // Outside a recording, calls are not logged anywhere
+        log("tova.a", &[1], Ok(0), None);
+    }
+
+    #[test]
+    fn test_replays_answer_in_order_and_report_the_first_divergence() {
+        let recording = Recording { calls: sample().calls[..2].to_vec(), truncated: false, ..sample() };
+        let session = replay(&recording);
+        assert_eq!(mode(), Mode::Replay);
+        assert_eq!(answer("tova.chan_receive", &[0]), Ok(recording.calls[0].clone()));
+        let err = answer("tova.chan_receive_status", &[0, 72]).unwrap_err();
+        assert!(err.starts_with(errors::ERR_REPLAY_DIVERGED), "{}", err);
+        assert!(err.contains("call 1: the guest called tova.chan_receive_status(0, 72), the recording has tova.chan_receive_status(0, 64)"), "{}", err);
+        // The replay stays failed
+        assert_eq!(answer("tova.chan_receive_status", &[0, 64]), Err(err.clone()));
+        assert_eq!(session.finish(), Err(err));
+
+        let session = replay(&recording);
+        answer("tova.chan_receive", &[0]).unwrap();
+        let err = session.finish().unwrap_err();
+        assert!(err.contains("the guest stopped after 1 calls, the recording has 2"), "{}", err);
+
+        let truncated = Recording { truncated: true, ..recording.clone() };
+        let session = replay(&truncated);
+        answer("tova.chan_receive", &[0]).unwrap();
+        answer("tova.chan_receive_status", &[0, 64]).unwrap();
+        let err = answer("tova.task_index", &[]).unwrap_err();
+        assert!(err.starts_with(errors::ERR_INVALID_INPUT) && err.contains("truncated after 2 calls"), "{}", err);
+        drop(session);
+        assert_eq!(mode(), Mode::Live);
+    }
+}
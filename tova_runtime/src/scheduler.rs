@@ -1,17 +1,652 @@
+use crossbeam_channel::{Receiver, Sender};
 use once_cell::sync::Lazy;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 use tokio::runtime::Runtime;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use crate::config;
+use crate::errors::{self, lock};
 
 // Global Tokio runtime — multi-threaded, work-stealing scheduler
 pub static TOKIO_RT: Lazy<Runtime> = Lazy::new(|| {
     tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .worker_threads(num_cpus())
+        // Guests run on blocking threads; see config::MAX_WASM_STACK_LIMIT
+        .thread_stack_size(config::GUEST_THREAD_STACK)
         .build()
         .expect("Failed to create Tokio runtime")
 });
 
-fn num_cpus() -> usize {
+// Dedicated compute pool — a fixed set of long-lived threads for chunked batch
+// work, optionally pinned to CPU sets so memory-hungry guests stay on one
+// socket. Sized and pinned from the runtime config when first used.
+pub static COMPUTE_POOL: Lazy<ComputePool> = Lazy::new(|| {
+    let cfg = config::get();
+    ComputePool::new(cfg.compute_threads, &cfg.cpu_affinity)
+});
+
+pub fn compute_pool_started() -> bool {
+    Lazy::get(&COMPUTE_POOL).is_some()
+}
+
+pub fn num_cpus() -> usize {
     std::thread::available_parallelism()
         .map(|n| n.get())
         .unwrap_or(4)
 }
+
+/// Identity of the compute thread running a job, for placement reporting.
+#[derive(Clone, Debug)]
+pub struct WorkerInfo {
+    pub index: usize,
+    pub os_thread_id: Option<u64>,
+    /// CPU set the thread is pinned to, if pinning was requested and succeeded
+    pub cpus: Option<Vec<usize>>,
+}
+
+type Job = Box<dyn FnOnce(&WorkerInfo) + Send>;
+
+pub struct ComputePool {
+    sender: Sender<Job>,
+    threads: usize,
+}
+
+impl ComputePool {
+    pub fn new(threads: usize, affinity: &[Vec<usize>]) -> Self {
+        let threads = threads.max(1);
+        let (sender, receiver) = crossbeam_channel::unbounded::<Job>();
+        for index in 0..threads {
+            let receiver = receiver.clone();
+            let requested = if affinity.is_empty() {
+                None
+            } else {
+                Some(affinity[index % affinity.len()].clone())
+            };
+            std::thread::Builder::new()
+                .name(format!("tova-compute-{}", index))
+                .stack_size(config::GUEST_THREAD_STACK)
+                .spawn(move || {
+                    // Pinning is best effort: an unsupported platform or an
+                    // invalid set leaves the thread unpinned with a warning
+                    let cpus = requested.and_then(|set| match pin_current_thread(&set) {
+                        Ok(()) => Some(set),
+                        Err(e) => {
+                            eprintln!(
+                                "tova_runtime: warning: could not pin compute thread {} to CPUs {:?}: {}",
+                                index, set, e
+                            );
+                            None
+                        }
+                    });
+                    let info = WorkerInfo { index, os_thread_id: current_os_thread_id(), cpus };
+                    for job in receiver.iter() {
+                        // A panicking job drops its result sender; keep the thread alive
+                        let _ = panic::catch_unwind(AssertUnwindSafe(|| job(&info)));
+                    }
+                })
+                .expect("failed to spawn compute thread");
+        }
+        ComputePool { sender, threads }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Run `f` on a pool thread. The receiver resolves with its result, or
+    /// errors if the job panicked.
+    pub fn spawn<T, F>(&self, f: F) -> oneshot::Receiver<T>
+    where
+        F: FnOnce(&WorkerInfo) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let job: Job = Box::new(move |info| {
+            let _ = tx.send(f(info));
+        });
+        // Pool threads never exit while the pool is alive, so the send can't fail
+        let _ = self.sender.send(job);
+        rx
+    }
+}
+
+// --- Deterministic dispatch ---
+
+// Debug mode for interleaving bugs: every guest execution goes to a single
+// dispatcher thread, which runs queued jobs one at a time in an order drawn
+// from a seeded PRNG. A batch queues all of its tasks in one message, so the
+// same seed and inputs give the same order, and with it the same sequence of
+// channel operations, on every run. Pipeline stages keep their own threads;
+// their channel imports are serialized through `import_turn` instead.
+//
+// Guests never overlap in this mode, so one that waits on another guest
+// through a channel waits forever. Only the host may feed a waiting guest.
+
+static DISPATCHER: Lazy<Dispatcher> = Lazy::new(|| Dispatcher::new(0));
+static DISPATCHING: AtomicBool = AtomicBool::new(false);
+static DISPATCH_SEED: AtomicU64 = AtomicU64::new(0);
+static IMPORT_LOCK: Mutex<()> = Mutex::new(());
+
+/// Route guest executions through the dispatcher, restarting its order from
+/// `seed`, or with None go back to the thread pools. Jobs already queued
+/// still run on the dispatcher.
+pub fn set_deterministic_dispatch(seed: Option<u64>) {
+    match seed {
+        Some(seed) => {
+            DISPATCHER.reseed(seed);
+            DISPATCH_SEED.store(seed, Ordering::Relaxed);
+            DISPATCHING.store(true, Ordering::Relaxed);
+            eprintln!(
+                "tova_runtime: warning: deterministic dispatch is on (seed {}): guests run one at a time on one thread",
+                seed
+            );
+        }
+        None => DISPATCHING.store(false, Ordering::Relaxed),
+    }
+}
+
+/// The dispatch seed while deterministic dispatch is on.
+pub fn deterministic_dispatch() -> Option<u64> {
+    DISPATCHING.load(Ordering::Relaxed).then(|| DISPATCH_SEED.load(Ordering::Relaxed))
+}
+
+/// Held by a channel host import for the whole operation while
+/// deterministic dispatch is on; None otherwise.
+pub fn import_turn() -> Option<MutexGuard<'static, ()>> {
+    deterministic_dispatch().map(|_| lock(&IMPORT_LOCK))
+}
+
+enum Dispatch {
+    Jobs(Vec<Job>),
+    Reseed(u64),
+}
+
+/// A single thread running queued jobs in seeded pseudo-random order. The
+/// thread exits once every handle is dropped and the queue is empty.
+#[derive(Clone)]
+pub struct Dispatcher {
+    sender: Sender<Dispatch>,
+}
+
+impl Dispatcher {
+    pub fn new(seed: u64) -> Self {
+        let (sender, receiver) = crossbeam_channel::unbounded::<Dispatch>();
+        std::thread::Builder::new()
+            .name("tova-dispatch".to_string())
+            .stack_size(config::GUEST_THREAD_STACK)
+            .spawn(move || dispatch_loop(receiver, seed))
+            .expect("failed to spawn dispatcher thread");
+        Dispatcher { sender }
+    }
+
+    /// Restart the order from `seed`, for jobs queued from now on.
+    pub fn reseed(&self, seed: u64) {
+        let _ = self.sender.send(Dispatch::Reseed(seed));
+    }
+
+    fn submit(&self, jobs: Vec<Job>) {
+        let _ = self.sender.send(Dispatch::Jobs(jobs));
+    }
+}
+
+fn dispatch_loop(receiver: Receiver<Dispatch>, seed: u64) {
+    let info = WorkerInfo { index: 0, os_thread_id: current_os_thread_id(), cpus: None };
+    let mut rng = SplitMix64(seed);
+    let mut queue: Vec<Job> = Vec::new();
+    loop {
+        let waiting = if queue.is_empty() {
+            match receiver.recv() {
+                Ok(message) => Some(message),
+                Err(_) => return,
+            }
+        } else {
+            None
+        };
+        for message in waiting.into_iter().chain(receiver.try_iter()) {
+            match message {
+                Dispatch::Jobs(jobs) => queue.extend(jobs),
+                Dispatch::Reseed(seed) => rng = SplitMix64(seed),
+            }
+        }
+        if queue.is_empty() {
+            continue;
+        }
+        let pick = (rng.next() % queue.len() as u64) as usize;
+        let job = queue.swap_remove(pick);
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| job(&info)));
+    }
+}
+
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+/// A guest execution started by `GuestBatch`. Resolves with its result, or
+/// an error if the job panicked or was dropped.
+pub enum GuestHandle<T> {
+    Blocking(JoinHandle<T>),
+    Queued(oneshot::Receiver<T>),
+}
+
+impl<T> GuestHandle<T> {
+    /// Abort a job on the blocking pool that has not started yet; queued
+    /// jobs always run.
+    pub fn abort(&self) {
+        if let GuestHandle::Blocking(handle) = self {
+            handle.abort();
+        }
+    }
+}
+
+impl<T> Future for GuestHandle<T> {
+    type Output = Result<T, String>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let joined = match self.get_mut() {
+            GuestHandle::Blocking(handle) => Pin::new(handle).poll(cx).map_err(|e| e.to_string()),
+            GuestHandle::Queued(receiver) => {
+                Pin::new(receiver).poll(cx).map_err(|_| "the worker dropped the task".to_string())
+            }
+        };
+        #[cfg(feature = "fault-injection")]
+        if joined.is_ready() && crate::faults::hit(crate::faults::Kind::Join) {
+            return Poll::Ready(Err(crate::faults::INJECTED.to_string()));
+        }
+        joined
+    }
+}
+
+/// The guest executions of one call. Normally each starts as soon as it is
+/// spawned; under deterministic dispatch they are held until `submit` (or
+/// drop) and reach the dispatcher together.
+pub struct GuestBatch {
+    dispatcher: Option<Dispatcher>,
+    jobs: Vec<Job>,
+}
+
+impl GuestBatch {
+    pub fn new() -> Self {
+        GuestBatch { dispatcher: deterministic_dispatch().map(|_| DISPATCHER.clone()), jobs: Vec::new() }
+    }
+
+    /// A batch for `dispatcher`, whatever the runtime's mode
+    #[cfg(test)]
+    pub fn on(dispatcher: &Dispatcher) -> Self {
+        GuestBatch { dispatcher: Some(dispatcher.clone()), jobs: Vec::new() }
+    }
+
+    pub fn is_dispatched(&self) -> bool {
+        self.dispatcher.is_some()
+    }
+
+    /// Run `f` on the blocking pool, or queue it for the dispatcher
+    pub fn spawn<T, F>(&mut self, f: F) -> GuestHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        match self.dispatcher {
+            None => GuestHandle::Blocking(TOKIO_RT.spawn_blocking(f)),
+            Some(_) => self.queue(move |_| f()),
+        }
+    }
+
+    /// Run `f` on `pool`, or queue it for the dispatcher
+    pub fn spawn_on<T, F>(&mut self, pool: &ComputePool, f: F) -> GuestHandle<T>
+    where
+        F: FnOnce(&WorkerInfo) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        match self.dispatcher {
+            None => GuestHandle::Queued(pool.spawn(f)),
+            Some(_) => self.queue(f),
+        }
+    }
+
+    fn queue<T, F>(&mut self, f: F) -> GuestHandle<T>
+    where
+        F: FnOnce(&WorkerInfo) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        self.jobs.push(Box::new(move |info| {
+            let _ = tx.send(f(info));
+        }));
+        GuestHandle::Queued(rx)
+    }
+
+    /// Hand the queued jobs to the dispatcher. Must come before awaiting
+    /// any of the batch's handles.
+    pub fn submit(self) {}
+}
+
+impl Default for GuestBatch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for GuestBatch {
+    fn drop(&mut self) {
+        if let Some(dispatcher) = &self.dispatcher {
+            if !self.jobs.is_empty() {
+                dispatcher.submit(std::mem::take(&mut self.jobs));
+            }
+        }
+    }
+}
+
+/// Start one guest execution.
+pub fn spawn_guest<T, F>(f: F) -> GuestHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let mut batch = GuestBatch::new();
+    let handle = batch.spawn(f);
+    batch.submit();
+    handle
+}
+
+// --- Admission control ---
+
+// A guest execution counts as queued from the moment its call is accepted
+// until it starts running: while it waits for a blocking thread, a batch's
+// concurrency permit or the dispatcher. With maxQueuedExecutions set, a call
+// whose executions would push the count past it is refused whole with
+// ERR_OVERLOADED before anything is spawned, so an overloaded runtime sheds
+// load at once instead of growing latency. An execution given a queue
+// timeout that waits longer than that fails with ERR_QUEUE_TIMEOUT when it
+// reaches a worker, without running.
+
+/// Counts of executions waiting to start; the runtime has one, QUEUE.
+pub struct Queue {
+    queued: AtomicUsize,
+    rejections: AtomicU64,
+    timeouts: AtomicU64,
+}
+
+static QUEUE: Queue = Queue::new();
+
+/// One accepted execution's place in the queue; it leaves the queue when
+/// started or dropped.
+pub struct QueueSlot<'a> {
+    queue: &'a Queue,
+    queued_at: Instant,
+    timeout: Option<Duration>,
+}
+
+impl QueueSlot<'_> {
+    /// Leave the queue as the execution starts. Fails with ERR_QUEUE_TIMEOUT,
+    /// and the execution must not run, if it waited past its timeout.
+    pub fn start(self) -> Result<(), String> {
+        let waited = self.queued_at.elapsed();
+        match self.timeout {
+            Some(timeout) if waited > timeout => {
+                self.queue.timeouts.fetch_add(1, Ordering::Relaxed);
+                Err(errors::coded(
+                    errors::ERR_QUEUE_TIMEOUT,
+                    format!("waited {}ms for a worker, over queueTimeoutMs ({})", waited.as_millis(), timeout.as_millis()),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for QueueSlot<'_> {
+    fn drop(&mut self) {
+        self.queue.queued.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+pub struct QueueStats {
+    /// Executions accepted and not yet started
+    pub queued: usize,
+    /// Calls refused with ERR_OVERLOADED
+    pub rejections: u64,
+    /// Executions failed with ERR_QUEUE_TIMEOUT
+    pub timeouts: u64,
+}
+
+impl Queue {
+    pub const fn new() -> Self {
+        Queue { queued: AtomicUsize::new(0), rejections: AtomicU64::new(0), timeouts: AtomicU64::new(0) }
+    }
+
+    /// Accept `executions` new executions, one slot each, or refuse them all
+    /// with ERR_OVERLOADED if that would queue more than `cap`.
+    pub fn enqueue(&self, executions: usize, timeout: Option<Duration>, cap: Option<usize>) -> Result<Vec<QueueSlot<'_>>, String> {
+        let admitted = self.queued.fetch_update(Ordering::AcqRel, Ordering::Acquire, |queued| match cap {
+            Some(cap) if queued + executions > cap => None,
+            _ => Some(queued + executions),
+        });
+        if let Err(queued) = admitted {
+            self.rejections.fetch_add(1, Ordering::Relaxed);
+            return Err(errors::coded(
+                errors::ERR_OVERLOADED,
+                format!(
+                    "{} executions queued, {} more would exceed maxQueuedExecutions ({})",
+                    queued,
+                    executions,
+                    cap.unwrap_or(0)
+                ),
+            ));
+        }
+        let queued_at = Instant::now();
+        Ok((0..executions).map(|_| QueueSlot { queue: self, queued_at, timeout }).collect())
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        QueueStats {
+            queued: self.queued.load(Ordering::Acquire),
+            rejections: self.rejections.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for Queue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Accept executions into the runtime's queue, capped at maxQueuedExecutions.
+pub fn enqueue(executions: usize, timeout: Option<Duration>) -> Result<Vec<QueueSlot<'static>>, String> {
+    QUEUE.enqueue(executions, timeout, config::get().max_queued_executions)
+}
+
+pub fn queue_stats() -> QueueStats {
+    QUEUE.stats()
+}
+
+#[cfg(target_os = "linux")]
+fn pin_current_thread(cpus: &[usize]) -> Result<(), String> {
+    if cpus.is_empty() {
+        return Err("empty CPU set".to_string());
+    }
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_ZERO(&mut set);
+        for &cpu in cpus {
+            if cpu >= libc::CPU_SETSIZE as usize {
+                return Err(format!("CPU {} out of range", cpu));
+            }
+            libc::CPU_SET(cpu, &mut set);
+        }
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(std::io::Error::last_os_error().to_string());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_current_thread(_cpus: &[usize]) -> Result<(), String> {
+    Err("CPU affinity is not supported on this platform".to_string())
+}
+
+#[cfg(target_os = "linux")]
+fn current_os_thread_id() -> Option<u64> {
+    Some(unsafe { libc::gettid() } as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn current_os_thread_id() -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::{self, resolve_wasm};
+    use crate::testsupport as fixtures;
+    use crate::channels;
+
+    #[cfg(target_os = "linux")]
+    fn cpus_allowed_list(tid: u64) -> String {
+        let status = std::fs::read_to_string(format!("/proc/self/task/{}/status", tid)).unwrap();
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix("Cpus_allowed_list:"))
+            .map(|v| v.trim().to_string())
+            .unwrap()
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_compute_threads_report_requested_affinity() {
+        let pool = ComputePool::new(2, &[vec![0]]);
+        for _ in 0..4 {
+            let rx = pool.spawn(|info| (info.os_thread_id, info.cpus.clone()));
+            let (tid, cpus) = futures::executor::block_on(rx).unwrap();
+            assert_eq!(cpus, Some(vec![0]));
+            assert_eq!(cpus_allowed_list(tid.unwrap()), "0");
+        }
+    }
+
+    #[test]
+    fn test_panicking_job_keeps_thread_alive() {
+        let pool = ComputePool::new(1, &[]);
+        let rx = pool.spawn(|_| -> i32 { panic!("job failed") });
+        assert!(futures::executor::block_on(rx).is_err());
+        let rx = pool.spawn(|info| info.index);
+        assert_eq!(futures::executor::block_on(rx).unwrap(), 0);
+    }
+
+    /// Sixteen guests each send their index to one channel, dispatched
+    /// with `seed`; the channel's contents are the order they ran in.
+    fn dispatch_order(seed: u64) -> Vec<i64> {
+        let dispatcher = Dispatcher::new(seed);
+        let wasm = resolve_wasm(&fixtures::send_once_module());
+        let ch = channels::create(16);
+        let mut batch = GuestBatch::on(&dispatcher);
+        let handles: Vec<_> = (0..16)
+            .map(|i| {
+                let wasm = wasm.clone();
+                batch.spawn(move || executor::exec_wasm_with_channels(&wasm, "send_once", &[ch as i64, i], None, None))
+            })
+            .collect();
+        batch.submit();
+        for handle in handles {
+            assert_eq!(futures::executor::block_on(handle), Ok(Ok(0)));
+        }
+        let order = channels::close_and_drain(ch);
+        channels::destroy(ch);
+        order
+    }
+
+    #[test]
+    fn test_dispatch_order_replays_from_its_seed() {
+        let _serial = channels::test_serial();
+        let first = dispatch_order(7);
+        assert_eq!(dispatch_order(7), first);
+        let mut sorted = first.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..16).collect::<Vec<i64>>());
+
+        let other = dispatch_order(8);
+        assert_ne!(other, first);
+        assert_eq!(dispatch_order(8), other);
+    }
+
+    #[test]
+    fn test_reseeding_restarts_the_order() {
+        let dispatcher = Dispatcher::new(1);
+        let run = |dispatcher: &Dispatcher| {
+            let order = std::sync::Arc::new(Mutex::new(Vec::new()));
+            let mut batch = GuestBatch::on(dispatcher);
+            let handles: Vec<_> = (0..8)
+                .map(|i| {
+                    let order = order.clone();
+                    batch.spawn(move || lock(&order).push(i))
+                })
+                .collect();
+            batch.submit();
+            for handle in handles {
+                futures::executor::block_on(handle).unwrap();
+            }
+            let order = lock(&order).clone();
+            order
+        };
+        let first = run(&dispatcher);
+        dispatcher.reseed(1);
+        assert_eq!(run(&dispatcher), first);
+    }
+
+    #[test]
+    fn test_panicking_dispatched_job_fails_only_its_handle() {
+        let dispatcher = Dispatcher::new(3);
+        let mut batch = GuestBatch::on(&dispatcher);
+        let failed = batch.spawn(|| -> i32 { panic!("job failed") });
+        let ok = batch.spawn(|| 5);
+        batch.submit();
+        assert!(futures::executor::block_on(failed).is_err());
+        assert_eq!(futures::executor::block_on(ok), Ok(5));
+    }
+
+    #[test]
+    fn test_queue_refuses_past_its_cap_until_slots_leave() {
+        let queue = Queue::new();
+        let mut held = queue.enqueue(3, None, Some(4)).unwrap().into_iter();
+        let err = queue.enqueue(2, None, Some(4)).err().unwrap();
+        assert!(err.starts_with(errors::ERR_OVERLOADED) && err.contains("3 executions queued"), "{}", err);
+        assert_eq!(queue.stats().rejections, 1);
+
+        // Starting a slot and dropping one unstarted both free their places
+        assert_eq!(held.next().unwrap().start(), Ok(()));
+        drop(held.next());
+        assert_eq!(queue.stats().queued, 1);
+        assert_eq!(queue.enqueue(3, None, Some(4)).map(|slots| slots.len()), Ok(3));
+        assert_eq!(queue.stats().queued, 1);
+        assert!(queue.enqueue(100, None, None).is_ok());
+    }
+
+    #[test]
+    fn test_slot_that_waited_too_long_fails_to_start() {
+        let queue = Queue::new();
+        let mut slots = queue.enqueue(2, Some(Duration::from_millis(10)), None).unwrap();
+        assert_eq!(slots.pop().unwrap().start(), Ok(()));
+        std::thread::sleep(Duration::from_millis(20));
+        let err = slots.pop().unwrap().start().unwrap_err();
+        assert!(err.starts_with(errors::ERR_QUEUE_TIMEOUT), "{}", err);
+        assert_eq!(queue.stats().timeouts, 1);
+        assert_eq!(queue.stats().queued, 0);
+    }
+}
@@ -0,0 +1,351 @@
+use crate::cancel_tokens::{self, Token};
+use crate::errors::{self, lock};
+use crate::{channels, executor, host_tasks};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+// A scope ties resources and work to one lifetime, so a caller (an HTTP
+// handler, say) can tear down everything it started in one call however it
+// exits. Resources created with a scope are registered in it and destroyed
+// when it closes; jobs (execs) started with one count as running until they
+// settle, and run under the scope's cancel token, so closing with `cancel`
+// stops them with ERR_CANCELLED. Closing first closes the scope's children,
+// then stops its producers, waits for its jobs, destroys the rest of its
+// resources, and reports what it cleaned up. A closed scope is gone:
+// registering anything in it fails, and whatever was created for it is
+// destroyed on the spot.
+
+/// Something a scope destroys when it closes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resource {
+    Channel(u64),
+    Module(u64),
+    CancelToken(u64),
+    Producer(u64),
+}
+
+impl Resource {
+    /// Destroy it; false if it was already gone.
+    fn destroy(self) -> bool {
+        match self {
+            Resource::Channel(id) => {
+                let live = channels::stat(id).is_some();
+                channels::destroy(id);
+                live
+            }
+            Resource::Module(handle) => executor::release_module(handle),
+            Resource::CancelToken(id) => cancel_tokens::release(id),
+            Resource::Producer(id) => host_tasks::cancel_producer(id),
+        }
+    }
+}
+
+struct Scope {
+    parent: Option<u64>,
+    children: Vec<u64>,
+    /// Registered like any cancel token, and released on close
+    token_id: u64,
+    token: Arc<Token>,
+    resources: Vec<Resource>,
+    jobs: Arc<Jobs>,
+}
+
+#[derive(Default)]
+struct Jobs {
+    counts: Mutex<JobCounts>,
+    settled: Condvar,
+}
+
+#[derive(Default)]
+struct JobCounts {
+    running: usize,
+    completed: u32,
+    cancelled: u32,
+}
+
+impl Jobs {
+    /// Wait until no job is running or `deadline` passes; whether they all settled.
+    fn wait(&self, deadline: Option<Instant>) -> bool {
+        let mut counts = lock(&self.counts);
+        while counts.running > 0 {
+            counts = match deadline {
+                None => self.settled.wait(counts).unwrap_or_else(|e| e.into_inner()),
+                Some(deadline) => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        return false;
+                    }
+                    self.settled.wait_timeout(counts, left).unwrap_or_else(|e| e.into_inner()).0
+                }
+            };
+        }
+        true
+    }
+}
+
+/// A job running in a scope; the scope counts it as running until dropped.
+pub struct Job {
+    jobs: Arc<Jobs>,
+    cancelled: bool,
+}
+
+impl Job {
+    /// Settle the job with its `result`, counting it as cancelled if it
+    /// failed with ERR_CANCELLED.
+    pub fn finish<T>(mut self, result: &Result<T, String>) {
+        self.cancelled = result.as_ref().is_err_and(|e| e.starts_with(errors::ERR_CANCELLED));
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        let mut counts = lock(&self.jobs.counts);
+        counts.running -= 1;
+        if self.cancelled {
+            counts.cancelled += 1;
+        } else {
+            counts.completed += 1;
+        }
+        drop(counts);
+        self.jobs.settled.notify_all();
+    }
+}
+
+static SCOPES: Lazy<Mutex<HashMap<u64, Scope>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+static NEXT_SCOPE: AtomicU64 = AtomicU64::new(0);
+
+fn no_such_scope(scope: u64) -> String {
+    errors::coded(errors::ERR_INVALID_INPUT, format!("no such scope: {} (closed, or never created)", scope))
+}
+
+/// A new scope, nested in `parent` if given: closing the parent closes it
+/// first, and cancelling the parent's token cancels its token too.
+pub fn create(parent: Option<u64>) -> Result<u64, String> {
+    let mut scopes = lock(&SCOPES);
+    let token_id = match parent {
+        Some(parent) => cancel_tokens::child(scopes.get(&parent).ok_or_else(|| no_such_scope(parent))?.token_id)?,
+        None => cancel_tokens::create(),
+    };
+    let token = cancel_tokens::get(token_id)?;
+    let id = NEXT_SCOPE.fetch_add(1, Ordering::Relaxed);
+    if let Some(parent) = parent.and_then(|parent| scopes.get_mut(&parent)) {
+        parent.children.push(id);
+    }
+    scopes.insert(id, Scope { parent, children: Vec::new(), token_id, token, resources: Vec::new(), jobs: Arc::default() });
+    Ok(id)
+}
+
+/// The id of `scope`'s cancel token, for tokens created under it.
+pub fn token_id(scope: u64) -> Result<u64, String> {
+    lock(&SCOPES).get(&scope).map(|s| s.token_id).ok_or_else(|| no_such_scope(scope))
+}
+
+/// The token work in `scope` runs under: the scope's own, joined with
+/// `own` (the call's token) if given.
+pub fn token(scope: u64, own: Option<&Arc<Token>>) -> Result<Arc<Token>, String> {
+    let token = lock(&SCOPES).get(&scope).map(|s| Arc::clone(&s.token)).ok_or_else(|| no_such_scope(scope))?;
+    Ok(joined(token, own))
+}
+
+fn joined(token: Arc<Token>, own: Option<&Arc<Token>>) -> Arc<Token> {
+    match own {
+        Some(own) => cancel_tokens::joined(&token, own),
+        None => token,
+    }
+}
+
+/// Hand `resource` to `scope`. If the scope is gone, the resource is
+/// destroyed instead and the error returned.
+pub fn adopt(scope: u64, resource: Resource) -> Result<(), String> {
+    if let Some(s) = lock(&SCOPES).get_mut(&scope) {
+        s.resources.push(resource);
+        return Ok(());
+    }
+    resource.destroy();
+    Err(no_such_scope(scope))
+}
+
+/// Start a job in `scope`, running under the token `token` would give it.
+pub fn begin_job(scope: u64, own: Option<&Arc<Token>>) -> Result<(Job, Arc<Token>), String> {
+    let (token, jobs) = lock(&SCOPES)
+        .get(&scope)
+        .map(|s| (Arc::clone(&s.token), Arc::clone(&s.jobs)))
+        .ok_or_else(|| no_such_scope(scope))?;
+    lock(&jobs.counts).running += 1;
+    Ok((Job { jobs, cancelled: false }, joined(token, own)))
+}
+
+/// How `close` treats jobs still running.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CloseOptions {
+    /// Cancel them at once
+    pub cancel: bool,
+    /// Otherwise, how long they get to finish before being cancelled (None
+    /// waits for them)
+    pub grace: Option<Duration>,
+}
+
+/// What closing a scope cleaned up, its nested scopes included.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    /// Nested scopes closed first
+    pub scopes: u32,
+    pub channels: u32,
+    pub modules: u32,
+    pub cancel_tokens: u32,
+    pub producers: u32,
+    /// Jobs that settled any other way
+    pub jobs_completed: u32,
+    /// Jobs that ended with ERR_CANCELLED
+    pub jobs_cancelled: u32,
+}
+
+impl Report {
+    /// Destroy `resources`, counting those that were still there.
+    fn count(&mut self, resources: Vec<Resource>) {
+        for resource in resources.into_iter().filter(|resource| resource.destroy()) {
+            match resource {
+                Resource::Channel(_) => self.channels += 1,
+                Resource::Module(_) => self.modules += 1,
+                Resource::CancelToken(_) => self.cancel_tokens += 1,
+                Resource::Producer(_) => self.producers += 1,
+            }
+        }
+    }
+
+    fn add(&mut self, other: Report) {
+        self.scopes += other.scopes;
+        self.channels += other.channels;
+        self.modules += other.modules;
+        self.cancel_tokens += other.cancel_tokens;
+        self.producers += other.producers;
+        self.jobs_completed += other.jobs_completed;
+        self.jobs_cancelled += other.jobs_cancelled;
+    }
+}
+
+/// Close `scope` and everything in it, blocking until its jobs settle.
+pub fn close(scope: u64, options: CloseOptions) -> Result<Report, String> {
+    let deadline = options.grace.filter(|_| !options.cancel).map(|grace| Instant::now() + grace);
+    let mut scopes = lock(&SCOPES);
+    let s = scopes.remove(&scope).ok_or_else(|| no_such_scope(scope))?;
+    if let Some(parent) = s.parent.and_then(|parent| scopes.get_mut(&parent)) {
+        parent.children.retain(|&child| child != scope);
+    }
+    drop(scopes);
+    Ok(close_removed(s, options.cancel, deadline))
+}
+
+/// Close a scope already taken out of the registry, children first.
+fn close_removed(s: Scope, cancel: bool, deadline: Option<Instant>) -> Report {
+    let mut report = Report::default();
+    for child in s.children {
+        if let Some(child) = lock(&SCOPES).remove(&child) {
+            report.add(close_removed(child, cancel, deadline));
+            report.scopes += 1;
+        }
+    }
+    // Producers go first, like timers: nothing fires once closing starts
+    let (producers, resources): (Vec<Resource>, Vec<Resource>) =
+        s.resources.into_iter().partition(|resource| matches!(resource, Resource::Producer(_)));
+    report.count(producers);
+    if cancel || !s.jobs.wait(deadline) {
+        let _ = cancel_tokens::cancel(s.token_id);
+    }
+    s.jobs.wait(None);
+    report.count(resources);
+    cancel_tokens::release(s.token_id);
+    let counts = lock(&s.jobs.counts);
+    report.jobs_completed += counts.completed;
+    report.jobs_cancelled += counts.cancelled;
+    report
+}
+
+/// Forget every scope. Used by `reset_all_state`, after the registries the
+/// scopes point into were reset themselves.
+pub fn reset() {
+    lock(&SCOPES).clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    /// A job that runs until its token is cancelled, like a long exec.
+    fn spin(scope: u64) -> thread::JoinHandle<Result<(), String>> {
+        let (job, token) = begin_job(scope, None).unwrap();
+        thread::spawn(move || {
+            let (stopped, _hook) = token.stop_signal();
+            let _ = stopped.recv();
+            let result = token.check();
+            job.finish(&result);
+            result
+        })
+    }
+
+    #[test]
+    fn test_close_with_cancel_tears_down_children_first() {
+        let _serial = channels::test_serial();
+        let scope = create(None).unwrap();
+        let child = create(Some(scope)).unwrap();
+        let channel = channels::create(4);
+        let child_channel = channels::create(4);
+        adopt(scope, Resource::Channel(channel)).unwrap();
+        adopt(child, Resource::Channel(child_channel)).unwrap();
+        // A slow producer stands in for a timer: it waits an hour per value
+        let producer = host_tasks::spawn_producer(channel, vec![1], Duration::from_secs(3600), Some(token(scope, None).unwrap())).unwrap();
+        adopt(scope, Resource::Producer(producer)).unwrap();
+        let token = cancel_tokens::child(token_id(child).unwrap()).unwrap();
+        adopt(child, Resource::CancelToken(token)).unwrap();
+        let job = spin(scope);
+        let child_job = spin(child);
+
+        let report = close(scope, CloseOptions { cancel: true, grace: None }).unwrap();
+        let cancelled = job.join().unwrap().unwrap_err();
+        assert!(cancelled.starts_with(errors::ERR_CANCELLED), "{}", cancelled);
+        assert!(child_job.join().unwrap().is_err());
+        assert_eq!(
+            report,
+            Report { scopes: 1, channels: 2, modules: 0, cancel_tokens: 1, producers: 1, jobs_completed: 0, jobs_cancelled: 2 }
+        );
+        assert!(channels::stat(channel).is_none() && channels::stat(child_channel).is_none());
+        assert!(!host_tasks::cancel_producer(producer));
+        assert!(cancel_tokens::get(token).is_err());
+        assert!(lock(&SCOPES).get(&scope).is_none() && lock(&SCOPES).get(&child).is_none());
+
+        // Closed means gone: nothing more can join it
+        assert!(close(scope, CloseOptions::default()).is_err());
+        assert!(begin_job(child, None).is_err());
+        let late = channels::create(1);
+        assert!(adopt(scope, Resource::Channel(late)).is_err());
+        assert!(channels::stat(late).is_none());
+    }
+
+    #[test]
+    fn test_close_waits_for_jobs_then_cancels_after_the_grace() {
+        let _serial = channels::test_serial();
+        let scope = create(None).unwrap();
+        let (quick, _) = begin_job(scope, None).unwrap();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            quick.finish(&Ok::<_, String>(()));
+        });
+        let slow = spin(scope);
+        let started = Instant::now();
+        let report = close(scope, CloseOptions { cancel: false, grace: Some(Duration::from_millis(100)) }).unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert!(slow.join().unwrap().is_err());
+        assert_eq!((report.jobs_completed, report.jobs_cancelled), (1, 1));
+
+        // Closing a child leaves its parent open
+        let parent = create(None).unwrap();
+        let child = create(Some(parent)).unwrap();
+        assert_eq!(close(child, CloseOptions::default()).unwrap(), Report::default());
+        assert_eq!(close(parent, CloseOptions::default()).unwrap().scopes, 0);
+    }
+}
@@ -0,0 +1,107 @@
+use crate::errors::{self, lock};
+use once_cell::sync::Lazy;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+// Shared data segments: read-only bytes the host registers once and guests
+// copy ranges out of with tova.shared_data_len and tova.shared_data_read, so
+// a large lookup table is neither embedded in every module nor streamed in
+// over a channel. A call lists the segments it may read in its options and
+// the guest names them by position in that list. The call holds each one for
+// as long as it runs, so unregistering a segment only frees the bytes once
+// every call still reading them has finished.
+
+pub type Segment = Arc<[u8]>;
+
+static SEGMENTS: Lazy<Mutex<HashMap<u64, Segment>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+static NEXT_SEGMENT: AtomicU64 = AtomicU64::new(0);
+
+pub fn register(data: Vec<u8>) -> u64 {
+    let id = NEXT_SEGMENT.fetch_add(1, Ordering::Relaxed);
+    lock(&SEGMENTS).insert(id, data.into());
+    id
+}
+
+/// Drop the registry's hold on `id`; false if no such segment is registered.
+pub fn unregister(id: u64) -> bool {
+    lock(&SEGMENTS).remove(&id).is_some()
+}
+
+/// The segments `ids` name, in order, or ERR_INVALID_INPUT naming the first
+/// one not registered.
+pub fn resolve(ids: &[u64]) -> Result<Vec<Segment>, String> {
+    let segments = lock(&SEGMENTS);
+    ids.iter()
+        .map(|id| {
+            segments.get(id).cloned().ok_or_else(|| {
+                errors::coded(errors::ERR_INVALID_INPUT, format!("no such shared data segment: {}", id))
+            })
+        })
+        .collect()
+}
+
+/// Unregister every segment. Used by `reset_all_state`; calls already
+/// running keep reading theirs.
+pub fn reset() {
+    lock(&SEGMENTS).clear();
+}
+
+thread_local! {
+    static GRANTED: RefCell<Vec<Segment>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Lets calls on this thread read `segments` until dropped.
+pub struct DataScope(Vec<Segment>);
+
+impl Drop for DataScope {
+    fn drop(&mut self) {
+        GRANTED.with(|granted| *granted.borrow_mut() = std::mem::take(&mut self.0));
+    }
+}
+
+/// Calls made on this thread while the scope lives see `segments` as
+/// shared data 0, 1, ...; the scope keeps them alive until then.
+pub fn scope(segments: Vec<Segment>) -> DataScope {
+    DataScope(GRANTED.with(|granted| granted.replace(segments)))
+}
+
+/// The segment a guest on this thread calls `index`, if it was granted one.
+pub fn granted(index: i32) -> Option<Segment> {
+    let index = usize::try_from(index).ok()?;
+    GRANTED.with(|granted| granted.borrow().get(index).cloned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scopes_nest_and_outlive_unregistering() {
+        let (table, other) = (register(vec![1, 2, 3]), register(vec![9]));
+        let segments = resolve(&[table]).unwrap();
+        let weak = Arc::downgrade(&segments[0]);
+        {
+            let _outer = scope(segments);
+            assert!(unregister(table));
+            assert!(!unregister(table));
+            assert_eq!(granted(0).as_deref(), Some(&[1u8, 2, 3][..]));
+            {
+                let _inner = scope(resolve(&[other]).unwrap());
+                assert_eq!(granted(0).as_deref(), Some(&[9u8][..]));
+                assert!(granted(1).is_none());
+            }
+            assert_eq!(granted(0).as_deref(), Some(&[1u8, 2, 3][..]));
+            assert!(granted(-1).is_none());
+        }
+        assert!(granted(0).is_none());
+        // The scope held the last reference
+        assert!(weak.upgrade().is_none());
+
+        let err = resolve(&[other, table]).unwrap_err();
+        assert!(err.starts_with(errors::ERR_INVALID_INPUT), "{}", err);
+        assert!(unregister(other));
+    }
+}
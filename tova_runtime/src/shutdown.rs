@@ -0,0 +1,82 @@
+use crate::{cancel_tokens, channels, diagnostics, executor, host_tasks, pipeline};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+// Orderly teardown when the last JS environment using the addon goes away,
+// which for the main thread is process exit. Guests run on detached blocking
+// threads that nothing joins, so without this a guest can still be executing
+// while the process tears down around it. Shutting down stops new guest
+// calls, interrupts running ones through the engines' epochs, unblocks
+// channel and host-task waits, and gives the executions a short grace period
+// to unwind. Stores and CompiledModules hold their own clones of the Engine
+// and Module, so nothing a finishing guest uses is freed under it; if some
+// still have not finished when the grace period ends, both engines are
+// leaked so that they are never destroyed at all.
+
+/// How long the cleanup hook waits for running executions to unwind.
+pub const GRACE: Duration = Duration::from_millis(250);
+
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// JS environments (the main thread and each Worker) with the addon loaded
+static ENVIRONMENTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether the runtime is shutting down; no new guest call starts after this.
+pub fn shutting_down() -> bool {
+    SHUTTING_DOWN.load(Ordering::Acquire)
+}
+
+/// What `shut_down` found.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    /// Executions running when shutdown began
+    pub interrupted: usize,
+    /// Whether they had all finished within the grace period
+    pub drained: bool,
+}
+
+/// An environment loaded the addon.
+pub fn environment_started() {
+    ENVIRONMENTS.fetch_add(1, Ordering::AcqRel);
+}
+
+/// An environment is being torn down; the last one shuts the runtime down.
+/// A Worker exiting leaves the others running. The count stops at zero, so
+/// a hook without a matching `environment_started` still shuts down rather
+/// than wrapping the count around.
+pub fn environment_finished(grace: Duration) -> Option<Report> {
+    let previous = ENVIRONMENTS
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| Some(n.saturating_sub(1)))
+        .unwrap_or_else(|n| n);
+    (previous <= 1).then(|| shut_down(grace))
+}
+
+/// Stop accepting guest calls, interrupt the running ones and wait up to
+/// `grace` for them to finish, leaking the engines if they do not.
+/// Idempotent.
+pub fn shut_down(grace: Duration) -> Report {
+    SHUTTING_DOWN.store(true, Ordering::Release);
+    let interrupted = diagnostics::running().len();
+    executor::interrupt_for_shutdown();
+    // Guests parked in a channel or host-task import wait outside wasm,
+    // where no epoch reaches them
+    pipeline::reset();
+    cancel_tokens::reset();
+    host_tasks::reset();
+    channels::reset();
+
+    let deadline = Instant::now() + grace;
+    let drained = loop {
+        if diagnostics::running().is_empty() {
+            break true;
+        }
+        if Instant::now() >= deadline {
+            break false;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    };
+    if !drained {
+        executor::leak_engines();
+    }
+    Report { interrupted, drained }
+}
@@ -0,0 +1,446 @@
+use napi::bindgen_prelude::{Buffer, Either};
+use std::future::Future;
+
+// Guest modules for the runtime's tests, written in WAT and compiled with the
+// `wat` crate, plus helpers that run them through the napi-layer functions so
+// argument and error marshaling is covered too. A new scenario is one more
+// builder here: a function returning `wasm(r#"(module ...)"#)`.
+//
+// tests/fixtures/gen-*.js builds the same guests by hand for the JS suites.
+
+/// Compile WAT to module bytes, panicking with the parse error.
+pub fn wasm(wat: &str) -> Vec<u8> {
+    wat::parse_str(wat).unwrap_or_else(|e| panic!("bad fixture: {}", e))
+}
+
+/// `wasm` plus a one-byte custom section, so a test gets a module nobody else
+/// has compiled yet (and a cold module cache and instance pool).
+pub fn tagged(mut wasm: Vec<u8>, tag: u8) -> Vec<u8> {
+    wasm.extend_from_slice(&[0x00, 0x05, 0x03, b't', b'a', b'g', tag]);
+    wasm
+}
+
+/// `functions` exported functions of straight-line arithmetic, slow enough
+/// to compile that overlapping compilations show; `seed` varies the
+/// constants so each seed is a module of its own.
+pub fn large_module(functions: usize, seed: u8) -> Vec<u8> {
+    let mut wat = String::from("(module");
+    for f in 0..functions {
+        wat.push_str(&format!("\n(func (export \"f{}\") (param $x i64) (result i64) (local.get $x)", f));
+        for op in 0..60 {
+            let k = (f * 61 + op) as i64 * 2654435761 + seed as i64;
+            wat.push_str(&format!(" (i64.const {}) (i64.{})", k, ["add", "mul", "xor", "rotl"][op % 4]));
+        }
+        wat.push(')');
+    }
+    wat.push(')');
+    wasm(&wat)
+}
+
+/// `add(i64, i64) -> i64`
+pub fn add_module() -> Vec<u8> {
+    wasm(r#"(module
+        (func (export "add") (param i64 i64) (result i64)
+            (i64.add (local.get 0) (local.get 1))))"#)
+}
+
+/// `fib(n: i64) -> i64`, iterative, with fib(0) = 0
+pub fn fib_module() -> Vec<u8> {
+    wasm(r#"(module
+        (func (export "fib") (param $n i64) (result i64)
+            (local $a i64) (local $b i64) (local $t i64) (local $i i64)
+            (local.set $b (i64.const 1))
+            (block $done
+                (loop $next
+                    (br_if $done (i64.ge_s (local.get $i) (local.get $n)))
+                    (local.set $t (i64.add (local.get $a) (local.get $b)))
+                    (local.set $a (local.get $b))
+                    (local.set $b (local.get $t))
+                    (local.set $i (i64.add (local.get $i) (i64.const 1)))
+                    (br $next)))
+            (local.get $a)))"#)
+}
+
+/// `spin(i64) -> i64` loops forever; only fuel, a time limit or a reset
+/// stops it.
+pub fn infinite_loop_module() -> Vec<u8> {
+    wasm(r#"(module
+        (func (export "spin") (param i64) (result i64)
+            (loop $forever (br $forever))
+            (i64.const 0)))"#)
+}
+
+/// `depth(n: i64) -> i64` recurses n frames and returns n; a negative n
+/// recurses until the stack runs out.
+pub fn depth_module() -> Vec<u8> {
+    wasm(r#"(module
+        (func $depth (export "depth") (param $n i64) (result i64)
+            (if (result i64) (i64.eqz (local.get $n))
+                (then (i64.const 0))
+                (else (i64.add
+                    (call $depth (i64.sub (local.get $n) (i64.const 1)))
+                    (i64.const 1))))))"#)
+}
+
+/// `next() -> i64` increments a mutable global: 1, 2, 3, ... from one
+/// instance.
+pub fn counter_module() -> Vec<u8> {
+    wasm(r#"(module
+        (global $count (mut i64) (i64.const 0))
+        (func (export "next") (result i64)
+            (global.set $count (i64.add (global.get $count) (i64.const 1)))
+            (global.get $count)))"#)
+}
+
+/// `run() -> i64` returning 1, with a start function that traps with `trap`
+/// or, given None, does nothing.
+pub fn start_module(trap: Option<TrapKind>) -> Vec<u8> {
+    let body = trap.map_or(String::new(), |kind| format!("(drop {})", kind.wat()));
+    wasm(&format!(
+        r#"(module
+            (memory 1)
+            (func $init {})
+            (start $init)
+            (func (export "run") (result i64) (i64.const 1)))"#,
+        body
+    ))
+}
+
+/// `send_once(ch: i32, value: i64) -> i32`, forwarding to tova.chan_send
+pub fn send_once_module() -> Vec<u8> {
+    wasm(r#"(module
+        (import "tova" "chan_send" (func $send (param i32 i64) (result i32)))
+        (func (export "send_once") (param i32 i64) (result i32)
+            (call $send (local.get 0) (local.get 1))))"#)
+}
+
+/// `echo(in: i32, out: i32) -> i64` receives from `in` until it ends,
+/// sends every value on to `out`, and returns how many it forwarded.
+pub fn channel_echo_module() -> Vec<u8> {
+    wasm(r#"(module
+        (import "tova" "chan_send" (func $send (param i32 i64) (result i32)))
+        (import "tova" "chan_receive" (func $receive (param i32) (result i64)))
+        (func (export "echo") (param $in i32) (param $out i32) (result i64)
+            (local $value i64) (local $count i64)
+            (block $done
+                (loop $next
+                    (local.set $value (call $receive (local.get $in)))
+                    (br_if $done (i64.eq (local.get $value) (i64.const 0x8000000000000000)))
+                    (drop (call $send (local.get $out) (local.get $value)))
+                    (local.set $count (i64.add (local.get $count) (i64.const 1)))
+                    (br $next)))
+            (local.get $count)))"#)
+}
+
+/// `index() -> i64` and `count() -> i64` return tova.task_index and
+/// tova.task_count; `index_i32() -> i32` is `index` on the typed batch path.
+pub fn task_module() -> Vec<u8> {
+    wasm(r#"(module
+        (import "tova" "task_index" (func $index (result i64)))
+        (import "tova" "task_count" (func $count (result i64)))
+        (func (export "index") (result i64) (call $index))
+        (func (export "count") (result i64) (call $count))
+        (func (export "index_i32") (result i32) (i32.wrap_i64 (call $index))))"#)
+}
+
+/// `run(rounds: i64, amount: i64) -> i64` calls tova.request_fuel(amount)
+/// before each round of about 80M fuel of busy work, then returns
+/// tova.fuel_remaining(); 13 rounds outrun DEFAULT_FUEL. `remaining() -> i64`
+/// and `request(amount: i64) -> i64` call the imports once.
+pub fn fuel_module() -> Vec<u8> {
+    wasm(r#"(module
+        (import "tova" "fuel_remaining" (func $remaining (result i64)))
+        (import "tova" "request_fuel" (func $request (param i64) (result i64)))
+        (func (export "remaining") (result i64) (call $remaining))
+        (func (export "request") (param i64) (result i64) (call $request (local.get 0)))
+        (func (export "run") (param $rounds i64) (param $amount i64) (result i64)
+            (local $i i64)
+            (block $done
+                (loop $round
+                    (br_if $done (i64.le_s (local.get $rounds) (i64.const 0)))
+                    (drop (call $request (local.get $amount)))
+                    (local.set $i (i64.const 10000000))
+                    (loop $burn
+                        (local.set $i (i64.sub (local.get $i) (i64.const 1)))
+                        (br_if $burn (i64.gt_s (local.get $i) (i64.const 0))))
+                    (local.set $rounds (i64.sub (local.get $rounds) (i64.const 1)))
+                    (br $round)))
+            (call $remaining)))"#)
+}
+
+/// Guest of the shared data imports, with i64 params throughout:
+/// `search(key, spins) -> i64` busy-waits `spins` iterations, then
+/// binary-searches shared data 0 as a sorted table of little-endian i64s,
+/// reading one entry per probe, and returns the key's index or -1.
+/// `len(id)` and `read(id, src, dst, len)` call the imports once.
+pub fn shared_data_module() -> Vec<u8> {
+    wasm(r#"(module
+        (import "tova" "shared_data_len" (func $len (param i32) (result i64)))
+        (import "tova" "shared_data_read" (func $read (param i32 i64 i32 i32) (result i32)))
+        (memory (export "memory") 1)
+        (func (export "len") (param i64) (result i64) (call $len (i32.wrap_i64 (local.get 0))))
+        (func (export "read") (param i64 i64 i64 i64) (result i64)
+            (i64.extend_i32_s (call $read
+                (i32.wrap_i64 (local.get 0)) (local.get 1) (i32.wrap_i64 (local.get 2)) (i32.wrap_i64 (local.get 3)))))
+        (func (export "search") (param $key i64) (param $spins i64) (result i64)
+            (local $lo i64) (local $hi i64) (local $mid i64) (local $v i64)
+            (loop $spin
+                (if (i64.gt_s (local.get $spins) (i64.const 0))
+                    (then
+                        (local.set $spins (i64.sub (local.get $spins) (i64.const 1)))
+                        (br $spin))))
+            (local.set $hi (i64.div_s (call $len (i32.const 0)) (i64.const 8)))
+            (block $missing
+                (loop $probe
+                    (br_if $missing (i64.ge_s (local.get $lo) (local.get $hi)))
+                    (local.set $mid (i64.shr_u (i64.add (local.get $lo) (local.get $hi)) (i64.const 1)))
+                    (if (call $read (i32.const 0) (i64.mul (local.get $mid) (i64.const 8)) (i32.const 0) (i32.const 8))
+                        (then (unreachable)))
+                    (local.set $v (i64.load (i32.const 0)))
+                    (if (i64.eq (local.get $v) (local.get $key)) (then (return (local.get $mid))))
+                    (if (i64.lt_s (local.get $v) (local.get $key))
+                        (then (local.set $lo (i64.add (local.get $mid) (i64.const 1))))
+                        (else (local.set $hi (local.get $mid))))
+                    (br $probe)))
+            (i64.const -1)))"#)
+}
+
+/// `drain(ch: i32) -> i64` loops on tova.chan_receive_status until the
+/// channel stops yielding values, then returns how many it took if the
+/// channel ended (CHAN_RECEIVE_CLOSED), or the status otherwise.
+/// `receive_at(ch: i32, out: i32) -> i64` is one raw receive into `out`.
+pub fn drain_module() -> Vec<u8> {
+    wasm(r#"(module
+        (import "tova" "chan_receive_status" (func $receive (param i32 i32) (result i32)))
+        (memory (export "memory") 1)
+        (func (export "drain") (param $ch i32) (result i64)
+            (local $status i32) (local $count i64)
+            (loop $next
+                (local.set $status (call $receive (local.get $ch) (i32.const 0)))
+                (if (i32.eqz (local.get $status))
+                    (then
+                        (local.set $count (i64.add (local.get $count) (i64.const 1)))
+                        (br $next))))
+            (if (result i64) (i32.eq (local.get $status) (i32.const -1))
+                (then (local.get $count))
+                (else (i64.extend_i32_s (local.get $status)))))
+        (func (export "receive_at") (param i32 i32) (result i64)
+            (i64.extend_i32_s (call $receive (local.get 0) (local.get 1)))))"#)
+}
+
+/// `fill_sum(seed: i64) -> i64` writes seed, seed + 1, ... into `size` i64
+/// slots of linear memory, then reads them back and returns their sum.
+pub fn memory_sum_module(size: u32) -> Vec<u8> {
+    let pages = (size as u64 * 8).div_ceil(65536).max(1);
+    wasm(&format!(
+        r#"(module
+            (memory (export "memory") {pages})
+            (func (export "fill_sum") (param $seed i64) (result i64)
+                (local $i i32) (local $sum i64)
+                (block $filled
+                    (loop $fill
+                        (br_if $filled (i32.ge_u (local.get $i) (i32.const {size})))
+                        (i64.store (i32.shl (local.get $i) (i32.const 3))
+                            (i64.add (local.get $seed) (i64.extend_i32_u (local.get $i))))
+                        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                        (br $fill)))
+                (local.set $i (i32.const 0))
+                (block $summed
+                    (loop $sum
+                        (br_if $summed (i32.ge_u (local.get $i) (i32.const {size})))
+                        (local.set $sum (i64.add (local.get $sum)
+                            (i64.load (i32.shl (local.get $i) (i32.const 3)))))
+                        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                        (br $sum)))
+                (local.get $sum)))"#
+    ))
+}
+
+/// Ways a guest can trap
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrapKind {
+    Unreachable,
+    DivideByZero,
+    IntegerOverflow,
+    OutOfBounds,
+}
+
+impl TrapKind {
+    pub const ALL: [TrapKind; 4] =
+        [TrapKind::Unreachable, TrapKind::DivideByZero, TrapKind::IntegerOverflow, TrapKind::OutOfBounds];
+
+    /// An expression of type i64 that traps this way (needs a memory)
+    fn wat(self) -> &'static str {
+        match self {
+            TrapKind::Unreachable => "(unreachable)",
+            TrapKind::DivideByZero => "(i64.div_s (i64.const 1) (i64.const 0))",
+            TrapKind::IntegerOverflow => "(i64.div_s (i64.const 0x8000000000000000) (i64.const -1))",
+            TrapKind::OutOfBounds => "(i64.load (i32.const 65536))",
+        }
+    }
+}
+
+/// `trap() -> i64` traps with `kind`; `ok() -> i64` returns 1 from the
+/// same instance.
+pub fn trap_module(kind: TrapKind) -> Vec<u8> {
+    wasm(&format!(
+        r#"(module
+            (memory 1)
+            (func (export "trap") (result i64) {})
+            (func (export "ok") (result i64) (i64.const 1)))"#,
+        kind.wat()
+    ))
+}
+
+/// `divmod(a: i64, b: i64) -> (i64, i64)`, a multi-value function the
+/// single-result call paths cannot return
+pub fn multi_value_module() -> Vec<u8> {
+    wasm(r#"(module
+        (func (export "divmod") (param $a i64) (param $b i64) (result i64 i64)
+            (i64.div_s (local.get $a) (local.get $b))
+            (i64.rem_s (local.get $a) (local.get $b))))"#)
+}
+
+/// One function per typed batch signature, plus a second (i32, i32) -> i32
+/// for mixed batches:
+///   add(i32, i32) = a + b            sub(i32, i32) = a - b
+///   mad3_i32/mad3_i64(a, b, c) = a * b - c
+///   mad4_i32/mad4_i64(a, b, c, d) = a * b - c * d
+///   sqrt(f64) = f64.sqrt a           div(f64, f64) = a / b
+///   mad3_f64(a, b, c) = a * b - c
+pub fn typed_module() -> Vec<u8> {
+    wasm(r#"(module
+        (func (export "add") (param i32 i32) (result i32) (i32.add (local.get 0) (local.get 1)))
+        (func (export "sub") (param i32 i32) (result i32) (i32.sub (local.get 0) (local.get 1)))
+        (func (export "mad3_i32") (param i32 i32 i32) (result i32)
+            (i32.sub (i32.mul (local.get 0) (local.get 1)) (local.get 2)))
+        (func (export "mad3_i64") (param i64 i64 i64) (result i64)
+            (i64.sub (i64.mul (local.get 0) (local.get 1)) (local.get 2)))
+        (func (export "mad4_i32") (param i32 i32 i32 i32) (result i32)
+            (i32.sub (i32.mul (local.get 0) (local.get 1)) (i32.mul (local.get 2) (local.get 3))))
+        (func (export "mad4_i64") (param i64 i64 i64 i64) (result i64)
+            (i64.sub (i64.mul (local.get 0) (local.get 1)) (i64.mul (local.get 2) (local.get 3))))
+        (func (export "sqrt") (param f64) (result f64) (f64.sqrt (local.get 0)))
+        (func (export "div") (param f64 f64) (result f64) (f64.div (local.get 0) (local.get 1)))
+        (func (export "mad3_f64") (param f64 f64 f64) (result f64)
+            (f64.sub (f64.mul (local.get 0) (local.get 1)) (local.get 2))))"#)
+}
+
+/// Guests of the tova_native kernel imports, over one exported page:
+///   sort_f64_check(n: i32) -> i64   writes n, n - 1, ..., 1 as f64 at 0,
+///                                   sorts with native_sort_f64 and returns 1
+///                                   if it then reads 1, 2, ..., n, else 0
+///   sort_i64_check(n: i32) -> i64   the same with i64 and native_sort_i64
+///   sort(ptr: i32, len: i32) -> i64 native_sort_f64 on any range
+///   sum(n: i32) -> i64              writes n, ..., 1 as f64 and returns
+///                                   their native_sum_f64, truncated
+///   hash(ptr: i32, len: i32, seed: i64) -> i64
+/// "abc" sits at byte 60000, clear of what the checks write for n <= 7000.
+pub fn native_kernels_module() -> Vec<u8> {
+    wasm(r#"(module
+        (import "tova_native" "native_sort_f64" (func $sort_f64 (param i32 i32) (result i32)))
+        (import "tova_native" "native_sort_i64" (func $sort_i64 (param i32 i32) (result i32)))
+        (import "tova_native" "native_sum_f64" (func $sum_f64 (param i32 i32) (result f64)))
+        (import "tova_native" "native_hash_xxh64" (func $hash (param i32 i32 i64) (result i64)))
+        (memory (export "memory") 1)
+        (data (i32.const 60000) "abc")
+        (func (export "sort_f64_check") (param $n i32) (result i64)
+            (local $i i32)
+            (block $filled
+                (loop $fill
+                    (br_if $filled (i32.ge_u (local.get $i) (local.get $n)))
+                    (f64.store (i32.shl (local.get $i) (i32.const 3))
+                        (f64.convert_i32_u (i32.sub (local.get $n) (local.get $i))))
+                    (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                    (br $fill)))
+            (drop (call $sort_f64 (i32.const 0) (local.get $n)))
+            (local.set $i (i32.const 0))
+            (block $checked
+                (loop $check
+                    (br_if $checked (i32.ge_u (local.get $i) (local.get $n)))
+                    (if (f64.ne (f64.load (i32.shl (local.get $i) (i32.const 3)))
+                                (f64.convert_i32_u (i32.add (local.get $i) (i32.const 1))))
+                        (then (return (i64.const 0))))
+                    (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                    (br $check)))
+            (i64.const 1))
+        (func (export "sort_i64_check") (param $n i32) (result i64)
+            (local $i i32)
+            (block $filled
+                (loop $fill
+                    (br_if $filled (i32.ge_u (local.get $i) (local.get $n)))
+                    (i64.store (i32.shl (local.get $i) (i32.const 3))
+                        (i64.extend_i32_u (i32.sub (local.get $n) (local.get $i))))
+                    (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                    (br $fill)))
+            (drop (call $sort_i64 (i32.const 0) (local.get $n)))
+            (local.set $i (i32.const 0))
+            (block $checked
+                (loop $check
+                    (br_if $checked (i32.ge_u (local.get $i) (local.get $n)))
+                    (if (i64.ne (i64.load (i32.shl (local.get $i) (i32.const 3)))
+                                (i64.extend_i32_u (i32.add (local.get $i) (i32.const 1))))
+                        (then (return (i64.const 0))))
+                    (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                    (br $check)))
+            (i64.const 1))
+        (func (export "sort") (param i32 i32) (result i64)
+            (i64.extend_i32_s (call $sort_f64 (local.get 0) (local.get 1))))
+        (func (export "sum") (param $n i32) (result i64)
+            (local $i i32)
+            (block $filled
+                (loop $fill
+                    (br_if $filled (i32.ge_u (local.get $i) (local.get $n)))
+                    (f64.store (i32.shl (local.get $i) (i32.const 3))
+                        (f64.convert_i32_u (i32.sub (local.get $n) (local.get $i))))
+                    (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                    (br $fill)))
+            (i64.trunc_f64_s (call $sum_f64 (i32.const 0) (local.get $n))))
+        (func (export "hash") (param i32 i32 i64) (result i64)
+            (call $hash (local.get 0) (local.get 1) (local.get 2))))"#)
+}
+
+// --- Through the napi layer ---
+
+/// Drive one of the async napi functions to completion. Their blocking work
+/// runs on the runtime's own pools, so any executor will do.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    futures::executor::block_on(future)
+}
+
+/// A batch task calling `func` on `wasm`
+pub fn task(wasm: &[u8], func: &str, args: &[i64]) -> crate::WasmTask {
+    crate::WasmTask {
+        wasm: Some(Buffer::from(wasm.to_vec())),
+        module: None,
+        func: func.to_string(),
+        args: args.to_vec(),
+        f64_args: None,
+        tag: None,
+    }
+}
+
+/// `execWasm` with default options, its error as the message JS would see
+pub fn exec(wasm: &[u8], func: &str, args: &[i64]) -> Result<i64, String> {
+    block_on(crate::exec_wasm(Buffer::from(wasm.to_vec()), func.to_string(), args.to_vec(), None))
+        .map_err(|e| e.reason)
+}
+
+/// `concurrentWasm` with default options
+pub fn concurrent(tasks: Vec<crate::WasmTask>) -> Result<Vec<i64>, String> {
+    block_on(crate::concurrent_wasm(tasks, None)).map_err(|e| e.reason)
+}
+
+/// `concurrentWasmShared` with default options
+pub fn shared(tasks: Vec<crate::WasmTask>) -> Result<Vec<i64>, String> {
+    block_on(crate::concurrent_wasm_shared(tasks, None)).map_err(|e| e.reason)
+}
+
+/// `execWasmWithChannels` against the global channel registry
+pub fn exec_with_channels(wasm: &[u8], func: &str, args: &[i64]) -> Result<i64, String> {
+    match block_on(crate::exec_wasm_with_channels(Buffer::from(wasm.to_vec()), func.to_string(), args.to_vec(), None)) {
+        Ok(Either::A(result)) => Ok(result),
+        Ok(Either::B(_)) => Err("unexpected recording".to_string()),
+        Err(e) => Err(e.reason),
+    }
+}
@@ -0,0 +1,209 @@
+use crate::errors;
+use napi::bindgen_prelude::*;
+use napi::sys;
+
+// TypedArray arguments for the kernel exports, borrowed in place. napi's own
+// typed array types reject a wrong kind with an uncoded message and do not
+// report detached buffers, so kernels take a TypedView and check it here,
+// failing with ERR_INVALID_INPUT like every other bad argument.
+
+/// A TypedArray argument as napi describes it: its kind, where its elements
+/// start and how many there are. Only valid during the call that received it.
+pub struct TypedView {
+    kind: TypedArrayType,
+    data: *mut u8,
+    len: usize,
+    detached: bool,
+}
+
+/// Element types a TypedView can be read as.
+pub trait Element: Copy {
+    const KIND: TypedArrayType;
+}
+
+impl Element for f64 {
+    const KIND: TypedArrayType = TypedArrayType::Float64;
+}
+
+impl Element for i64 {
+    const KIND: TypedArrayType = TypedArrayType::BigInt64;
+}
+
+impl Element for u32 {
+    const KIND: TypedArrayType = TypedArrayType::Uint32;
+}
+
+fn array_name(kind: TypedArrayType) -> String {
+    format!("{}Array", kind.as_ref())
+}
+
+fn invalid(what: &str, message: impl std::fmt::Display) -> String {
+    errors::coded(errors::ERR_INVALID_INPUT, format!("{}: {}", what, message))
+}
+
+/// Whether an array of `kind` can be read as `T`s by `what`.
+fn check<T: Element>(kind: TypedArrayType, detached: bool, what: &str) -> std::result::Result<(), String> {
+    if kind != T::KIND {
+        return Err(invalid(what, format!("expected a {}, got a {}", array_name(T::KIND), array_name(kind))));
+    }
+    if detached {
+        return Err(invalid(what, format!("the {}'s buffer is detached", array_name(kind))));
+    }
+    Ok(())
+}
+
+impl TypedView {
+    /// The elements, for `what` (the export's name) to work on in place.
+    /// Fails if the view is not of `T`'s kind or its buffer is detached.
+    ///
+    /// # Safety
+    /// The array must stay alive, and no other slice of it may be in use,
+    /// for as long as the returned slice is.
+    pub unsafe fn elements<'a, T: Element>(&self, what: &str) -> std::result::Result<&'a mut [T], String> {
+        check::<T>(self.kind, self.detached, what)?;
+        if self.len == 0 || self.data.is_null() {
+            return Ok(&mut []);
+        }
+        Ok(std::slice::from_raw_parts_mut(self.data as *mut T, self.len))
+    }
+}
+
+impl FromNapiValue for TypedView {
+    unsafe fn from_napi_value(env: sys::napi_env, value: sys::napi_value) -> Result<Self> {
+        let mut is_typed_array = false;
+        napi::check_status!(sys::napi_is_typedarray(env, value, &mut is_typed_array))?;
+        if !is_typed_array {
+            return Err(Error::from_reason(errors::coded(errors::ERR_INVALID_INPUT, "expected a TypedArray")));
+        }
+        let mut kind = 0;
+        let mut len = 0;
+        let mut data = std::ptr::null_mut();
+        let mut buffer = std::ptr::null_mut();
+        let mut byte_offset = 0;
+        napi::check_status!(sys::napi_get_typedarray_info(env, value, &mut kind, &mut len, &mut data, &mut buffer, &mut byte_offset))?;
+        let mut detached = false;
+        napi::check_status!(sys::napi_is_detached_arraybuffer(env, buffer, &mut detached))?;
+        Ok(TypedView { kind: kind.into(), data: data as *mut u8, len, detached })
+    }
+}
+
+impl TypeName for TypedView {
+    fn type_name() -> &'static str {
+        "TypedArray"
+    }
+
+    fn value_type() -> ValueType {
+        ValueType::Object
+    }
+}
+
+impl ValidateNapiValue for TypedView {}
+
+/// napi's typed arrays a `Held` argument can keep.
+pub trait HeldArray: FromNapiValue + Send + 'static {
+    type Element: Element + Send;
+
+    /// # Safety
+    /// As `TypedView::elements`.
+    unsafe fn elements(&mut self) -> &mut [Self::Element];
+}
+
+impl HeldArray for Float64Array {
+    type Element = f64;
+
+    unsafe fn elements(&mut self) -> &mut [f64] {
+        self.as_mut()
+    }
+}
+
+impl HeldArray for BigInt64Array {
+    type Element = i64;
+
+    unsafe fn elements(&mut self) -> &mut [i64] {
+        self.as_mut()
+    }
+}
+
+/// A TypedArray argument for kernels that keep working on it after the call
+/// returns, on the compute pool. Checked as a TypedView is, then referenced
+/// until dropped so the array outlives the work; napi releases the reference
+/// on the JS thread wherever the drop happens.
+pub struct Held<A> {
+    kind: TypedArrayType,
+    detached: bool,
+    /// Only taken when the kind matches, since napi's own check is uncoded
+    array: Option<A>,
+}
+
+impl<A: HeldArray> Held<A> {
+    /// The array, for `what` to work on. Fails as `TypedView::elements` does.
+    pub fn take(self, what: &str) -> std::result::Result<A, String> {
+        check::<A::Element>(self.kind, self.detached, what)?;
+        Ok(self.array.expect("kind matched"))
+    }
+}
+
+impl<A: HeldArray> FromNapiValue for Held<A> {
+    unsafe fn from_napi_value(env: sys::napi_env, value: sys::napi_value) -> Result<Self> {
+        let view = TypedView::from_napi_value(env, value)?;
+        let array = if view.kind == A::Element::KIND { Some(A::from_napi_value(env, value)?) } else { None };
+        Ok(Held { kind: view.kind, detached: view.detached, array })
+    }
+}
+
+impl<A> TypeName for Held<A> {
+    fn type_name() -> &'static str {
+        "TypedArray"
+    }
+
+    fn value_type() -> ValueType {
+        ValueType::Object
+    }
+}
+
+impl<A: HeldArray> ValidateNapiValue for Held<A> {}
+
+#[cfg(test)]
+impl TypedView {
+    /// A view of `values` as napi would describe a TypedArray of `kind` over them.
+    pub fn over<T>(kind: TypedArrayType, values: &mut [T]) -> TypedView {
+        TypedView { kind, data: values.as_mut_ptr() as *mut u8, len: values.len(), detached: false }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_views_of_the_right_kind_are_borrowed_in_place() {
+        let mut values = vec![3.0, 1.0, 2.0];
+        let view = TypedView::over(TypedArrayType::Float64, &mut values);
+        let elements = unsafe { view.elements::<f64>("test") }.unwrap();
+        elements[0] = 9.0;
+        assert_eq!(values, [9.0, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn test_wrong_kinds_and_detached_buffers_are_rejected() {
+        let mut bytes = vec![0u8; 16];
+        let view = TypedView::over(TypedArrayType::Uint8, &mut bytes);
+        let err = unsafe { view.elements::<f64>("kernelSortF64") }.unwrap_err();
+        assert!(err.starts_with(errors::ERR_INVALID_INPUT), "{}", err);
+        assert!(err.contains("kernelSortF64: expected a Float64Array, got a Uint8Array"), "{}", err);
+        let mut floats = vec![1.0f64];
+        let view = TypedView::over(TypedArrayType::Float64, &mut floats);
+        assert!(unsafe { view.elements::<i64>("test") }.unwrap_err().contains("expected a BigInt64Array"));
+
+        // A detached buffer reports no data and no length
+        let detached = TypedView { kind: TypedArrayType::Float64, data: std::ptr::null_mut(), len: 0, detached: true };
+        let err = unsafe { detached.elements::<f64>("test") }.unwrap_err();
+        assert!(err.starts_with(errors::ERR_INVALID_INPUT) && err.contains("detached"), "{}", err);
+    }
+
+    #[test]
+    fn test_zero_length_views_need_no_data() {
+        let empty = TypedView { kind: TypedArrayType::BigInt64, data: std::ptr::null_mut(), len: 0, detached: false };
+        assert_eq!(unsafe { empty.elements::<i64>("test") }, Ok(&mut [][..]));
+    }
+}
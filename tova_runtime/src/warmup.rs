@@ -0,0 +1,105 @@
+use crate::channels;
+use crate::executor::{self, Metering, WasmInput};
+use crate::scheduler;
+use once_cell::sync::Lazy;
+use std::time::{Duration, Instant};
+
+// Warm start: build the runtime's lazily created pieces before the first
+// request needs them, so no single call pays for the Tokio runtime, both
+// engines and the first JIT compile at once. `warmup` runs every step and
+// reports each; TOVA_EAGER_INIT runs the cheap ones when the module loads.
+
+/// Set (to anything but "", "0" or "false") to run `cheap` at module load.
+pub const EAGER_INIT_ENV: &str = "TOVA_EAGER_INIT";
+
+// add(i64, i64) -> i64, as produced by tests/fixtures/gen-test-wasm.js
+const WARMUP_WASM: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x07, 0x01, 0x60, 0x02, 0x7e,
+    0x7e, 0x01, 0x7e, 0x03, 0x02, 0x01, 0x00, 0x07, 0x07, 0x01, 0x03, 0x61, 0x64, 0x64,
+    0x00, 0x00, 0x0a, 0x09, 0x01, 0x07, 0x00, 0x20, 0x00, 0x20, 0x01, 0x7c, 0x0b,
+];
+
+/// One piece of warmup: whether it created anything (false when an earlier
+/// call or request already had) and how long it took.
+pub struct Step {
+    pub name: &'static str,
+    pub initialized: bool,
+    pub elapsed: Duration,
+}
+
+fn timed(name: &'static str, f: impl FnOnce() -> bool) -> Step {
+    let started = Instant::now();
+    let initialized = f();
+    Step { name, initialized, elapsed: started.elapsed() }
+}
+
+pub fn eager_requested() -> bool {
+    std::env::var(EAGER_INIT_ENV).is_ok_and(|v| !matches!(v.trim(), "" | "0" | "false"))
+}
+
+/// The Tokio runtime and the channel registry. They fix no settings, so
+/// they are safe to create at module load.
+pub fn cheap() -> Vec<Step> {
+    vec![
+        timed("tokio", || {
+            let created = Lazy::get(&scheduler::TOKIO_RT).is_none();
+            Lazy::force(&scheduler::TOKIO_RT);
+            created
+        }),
+        timed("channels", channels::warm),
+    ]
+}
+
+const ENGINES: [(&str, &str, Metering); 2] = [
+    ("engine.fuel", "compile.fuel", Metering::Fuel),
+    ("engine.unmetered", "compile.unmetered", Metering::Epoch),
+];
+
+/// Both engines, which fixes their `configureEngineFeatures` settings, and
+/// with `compile` one compile and call of a built-in module on each.
+pub fn engines(compile: bool) -> Result<Vec<Step>, String> {
+    let mut steps: Vec<Step> = ENGINES.iter().map(|&(name, _, metering)| timed(name, || executor::warm_engine(metering))).collect();
+    if compile {
+        for (_, name, metering) in ENGINES {
+            let started = Instant::now();
+            let wasm = executor::resolve_wasm_with(WARMUP_WASM, metering);
+            let initialized = !matches!(wasm, WasmInput::Compiled(_));
+            executor::exec_wasm_sync(&wasm, "add", &[1, 2])?;
+            steps.push(Step { name, initialized, elapsed: started.elapsed() });
+        }
+    }
+    Ok(steps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exec_after_warmup_is_far_faster_than_a_cold_start() {
+        // A cold start on a private engine, since another test in this
+        // binary may already have created the shared ones
+        let started = Instant::now();
+        let engine = wasmtime::Engine::new(&wasmtime::Config::new()).unwrap();
+        wasmtime::Module::new(&engine, WARMUP_WASM).unwrap();
+        let cold = started.elapsed();
+
+        let mut steps = cheap();
+        steps.extend(engines(true).unwrap());
+        let names: Vec<&str> = steps.iter().map(|s| s.name).collect();
+        assert_eq!(names, ["tokio", "channels", "engine.fuel", "engine.unmetered", "compile.fuel", "compile.unmetered"]);
+        // Everything is in place now
+        assert!(cheap().iter().chain(&engines(true).unwrap()).all(|s| !s.initialized));
+
+        let warm = (0..5)
+            .map(|_| {
+                let started = Instant::now();
+                let wasm = executor::resolve_wasm(WARMUP_WASM);
+                assert_eq!(executor::exec_wasm_sync(&wasm, "add", &[2, 3]), Ok(5));
+                started.elapsed()
+            })
+            .min()
+            .unwrap();
+        assert!(warm * 4 < cold, "warm {:?}, cold {:?}", warm, cold);
+    }
+}
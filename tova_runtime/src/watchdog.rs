@@ -0,0 +1,155 @@
+use crate::diagnostics::{self, TaskKind, WaitOp};
+use crate::errors::lock;
+use crate::{config, executor, scheduler};
+use once_cell::sync::Lazy;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// The runtime watchdog looks for work that has stopped making progress: a
+// guest call that never returns, or a channel operation that never
+// completes, each holding a blocking thread until the pool runs dry. A task
+// on TOKIO_RT samples the execution registry and the wait table, and hands
+// anything older than the configured threshold to the registered callback;
+// an entry that stays stuck is reported once. The same snapshot is
+// available on demand, and running executions can be killed through their
+// store's epoch check. Blocked waits are reported but cannot be killed here.
+
+/// What a flagged entry is doing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Subject {
+    /// A guest call to `func` of the module with cache hash `module`
+    Exec { module: u64, func: String },
+    /// A blocked channel operation
+    Wait { channel: u64, op: WaitOp, task: TaskKind },
+}
+
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub id: u64,
+    pub subject: Subject,
+    pub age: Duration,
+}
+
+impl Entry {
+    pub fn killable(&self) -> bool {
+        matches!(self.subject, Subject::Exec { .. })
+    }
+}
+
+type Callback = Arc<dyn Fn(Vec<Entry>) + Send + Sync>;
+
+static CALLBACK: Lazy<Mutex<Option<Callback>>> = Lazy::new(|| Mutex::new(None));
+
+/// The sampling task; aborted on restart.
+static TASK: Lazy<Mutex<Option<tokio::task::JoinHandle<()>>>> = Lazy::new(|| Mutex::new(None));
+
+/// Executions and waits at least `threshold` old, oldest first.
+pub fn report(threshold: Duration) -> Vec<Entry> {
+    let executions = diagnostics::running().into_iter().map(|e| Entry {
+        id: e.id,
+        subject: Subject::Exec { module: e.module, func: e.func },
+        age: e.running,
+    });
+    let waits = diagnostics::blocked().into_iter().map(|w| Entry {
+        id: w.wait_id,
+        subject: Subject::Wait { channel: w.channel, op: w.op, task: w.task },
+        age: w.waited,
+    });
+    let mut entries: Vec<Entry> = executions.chain(waits).filter(|e| e.age >= threshold).collect();
+    entries.sort_by(|a, b| b.age.cmp(&a.age).then(a.id.cmp(&b.id)));
+    entries
+}
+
+/// Kill running execution `id`: the guest stops at its next epoch check and
+/// the call fails with ERR_CANCELLED. False if no such execution is running.
+pub fn kill(id: u64) -> bool {
+    if !diagnostics::kill_execution(id) {
+        return false;
+    }
+    // Fuel-metered guests only check at an epoch bump
+    executor::interrupt_guests();
+    true
+}
+
+/// Call `callback` with the sampled entries whenever one newly crosses the
+/// threshold; None stops reporting.
+pub fn set_callback(callback: Option<Callback>) {
+    *lock(&CALLBACK) = callback;
+}
+
+/// Start, restart or stop the sampling task to match the configuration.
+pub fn restart() {
+    let mut task = lock(&TASK);
+    if let Some(previous) = task.take() {
+        previous.abort();
+    }
+    let c = config::get();
+    if !c.watchdog {
+        return;
+    }
+    let (threshold, interval) = (c.watchdog_threshold, c.watchdog_interval);
+    *task = Some(scheduler::TOKIO_RT.spawn(async move {
+        let mut reported: HashSet<u64> = HashSet::new();
+        loop {
+            tokio::time::sleep(interval).await;
+            let flagged = report(threshold);
+            let ids: HashSet<u64> = flagged.iter().map(|e| e.id).collect();
+            if ids.iter().any(|id| !reported.contains(id)) {
+                let callback = lock(&CALLBACK).clone();
+                if let Some(callback) = callback {
+                    callback(flagged);
+                }
+            }
+            reported = ids;
+        }
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channels;
+    use crate::executor::{resolve_wasm_with, Metering};
+    use crate::testsupport as fixtures;
+    use std::time::Instant;
+
+    #[test]
+    fn test_sampling_reports_a_wedged_guest_once_until_killed() {
+        let _serial = channels::test_serial();
+        let previous = config::get();
+        config::update(|c| {
+            c.watchdog = true;
+            c.watchdog_threshold = Duration::from_millis(40);
+            c.watchdog_interval = Duration::from_millis(10);
+        });
+        let (tx, rx) = crossbeam_channel::unbounded();
+        set_callback(Some(Arc::new(move |entries: Vec<Entry>| {
+            let spinning: Vec<Entry> =
+                entries.into_iter().filter(|e| matches!(&e.subject, Subject::Exec { func, .. } if func == "spin")).collect();
+            if !spinning.is_empty() {
+                let _ = tx.send(spinning);
+            }
+        })));
+        restart();
+
+        let input = resolve_wasm_with(&fixtures::infinite_loop_module(), Metering::None);
+        let spinning = std::thread::spawn(move || executor::exec_wasm_sync(&input, "spin", &[0]));
+        let flagged = rx.recv_timeout(Duration::from_secs(5)).expect("the wedged guest is reported");
+        assert_eq!(flagged.len(), 1, "{:?}", flagged);
+        assert!(flagged[0].killable() && flagged[0].age >= Duration::from_millis(40), "{:?}", flagged[0]);
+        // Still stuck, but already reported
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+
+        let started = Instant::now();
+        assert!(kill(flagged[0].id));
+        let err = spinning.join().unwrap().unwrap_err();
+        assert!(err.starts_with(crate::errors::ERR_CANCELLED) && err.contains("watchdogKill"), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(!kill(flagged[0].id));
+
+        config::update(|c| *c = previous);
+        restart();
+        set_callback(None);
+    }
+}
@@ -0,0 +1,68 @@
+// Process teardown with guests still running: each iteration re-runs this
+// binary as a child that loads the runtime, spawns a burst of detached guest
+// executions (some spinning forever) under every metering mode, runs the
+// environment cleanup hook the way Node and Bun do at exit, and exits. A
+// child that crashes instead of exiting cleanly fails the test.
+//
+//   cargo test --test teardown
+
+use std::process::Command;
+use std::time::Duration;
+use tova_runtime::bench;
+
+const ITERATIONS: usize = 200;
+const TASKS: usize = 48;
+const CHILD: &str = "TOVA_TEARDOWN_CHILD";
+
+const GUESTS: &str = r#"(module
+  (func (export "spin") (param i64) (result i64)
+    (loop $forever (br $forever))
+    (i64.const 0))
+  (func (export "add") (param i64 i64) (result i64)
+    (i64.add (local.get 0) (local.get 1))))"#;
+
+#[test]
+fn test_exit_with_running_guests_never_crashes() {
+    let exe = std::env::current_exe().unwrap();
+    for iteration in 0..ITERATIONS {
+        let output = Command::new(&exe)
+            .args(["--exact", "child_spawns_guests_and_exits", "--nocapture", "--test-threads=1"])
+            .env(CHILD, iteration.to_string())
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "iteration {}: {:?}\n{}",
+            iteration,
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
+
+/// The child's side; does nothing when run as an ordinary test.
+#[test]
+fn child_spawns_guests_and_exits() {
+    let Some(iteration) = std::env::var(CHILD).ok().and_then(|v| v.parse::<u64>().ok()) else {
+        return;
+    };
+    let wasm = wat::parse_str(GUESTS).unwrap();
+    bench::environment_started();
+    // Compiled up front, so that the guests get going before the hook runs
+    for metering in ["fuel", "epoch", "none"] {
+        assert_eq!(bench::exec_wasm_metered(&wasm, "add", &[1, 2], metering), Ok(3));
+    }
+    for task in 0..TASKS {
+        let metering = ["fuel", "epoch", "none"][task % 3];
+        let (func, args): (&str, &[i64]) = if task % 2 == 0 { ("spin", &[0]) } else { ("add", &[1, 2]) };
+        bench::spawn_detached(&wasm, func, args, metering).unwrap();
+    }
+    // From still queued to all running, depending on the iteration
+    std::thread::sleep(Duration::from_millis(iteration % 8));
+    let (_, drained) = bench::environment_finished().expect("the last environment shuts the runtime down");
+    assert!(drained, "running guests did not unwind within the grace period");
+    // Later calls are refused rather than run
+    let err = bench::exec_wasm_metered(&wasm, "add", &[1, 2], "none").unwrap_err();
+    assert!(err.contains("shutting down"), "{}", err);
+    std::process::exit(0);
+}